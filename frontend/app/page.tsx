@@ -18,6 +18,7 @@ import {
   deleteNode,
   fetchDiagram,
   isLocalMode,
+  quickAddNode,
   updateLayout,
   updateNodeImage,
   updateSource,
@@ -28,6 +29,7 @@ import {
   searchCodebase,
 } from "../lib/api";
 import {
+  BorderStyle,
   DiagramData,
   EdgeArrowDirection,
   EdgeKind,
@@ -65,6 +67,7 @@ const DEFAULT_NODE_COLORS: Record<NodeData["shape"], string> = {
   trapezoid: "#FCE7F3",
   "trapezoid-alt": "#FCD5CE",
   asymmetric: "#F5D0FE",
+  junction: "#2D3748",
 };
 
 const DEFAULT_EDGE_COLOR = "#2d3748";
@@ -75,6 +78,14 @@ const LINE_STYLE_OPTIONS: Array<{ value: EdgeKind; label: string }> = [
   { value: "dashed", label: "Dashed" },
 ];
 
+const BORDER_STYLE_OPTIONS: Array<{ value: BorderStyle; label: string }> = [
+  { value: "solid", label: "Solid" },
+  { value: "dashed", label: "Dashed" },
+  { value: "dotted", label: "Dotted" },
+];
+
+const DEFAULT_NODE_BORDER_WIDTH = 2;
+
 const ARROW_DIRECTION_OPTIONS: Array<{ value: EdgeArrowDirection; label: string }> = [
   { value: "forward", label: "Forward" },
   { value: "backward", label: "Backward" },
@@ -675,6 +686,45 @@ const handleNodeFillChange = useCallback(
   [selectedNode, submitStyleUpdate]
 );
 
+const handleNodeBorderStyleChange = useCallback(
+  (value: BorderStyle) => {
+    if (!selectedNode || (selectedNode.borderStyle ?? "solid") === value) {
+      return;
+    }
+    void submitStyleUpdate({
+      nodeStyles: {
+        [selectedNode.id]: {
+          borderStyle: value === "solid" ? null : value,
+        },
+      },
+    });
+  },
+  [selectedNode, submitStyleUpdate]
+);
+
+const handleNodeBorderWidthChange = useCallback(
+  (value: string) => {
+    if (!selectedNode) {
+      return;
+    }
+    const width = Number.parseFloat(value);
+    if (!Number.isFinite(width) || width < 0) {
+      return;
+    }
+    if ((selectedNode.strokeWidth ?? DEFAULT_NODE_BORDER_WIDTH) === width) {
+      return;
+    }
+    void submitStyleUpdate({
+      nodeStyles: {
+        [selectedNode.id]: {
+          strokeWidth: width === DEFAULT_NODE_BORDER_WIDTH ? null : width,
+        },
+      },
+    });
+  },
+  [selectedNode, submitStyleUpdate]
+);
+
 const handleNodeStrokeChange = useCallback(
   (value: string) => {
     if (!selectedNode) {
@@ -923,6 +973,13 @@ const handleNodeStyleReset = useCallback(() => {
   });
 }, [selectedNode, submitStyleUpdate]);
 
+const handleNodePinToggle = useCallback(() => {
+  if (!selectedNode) {
+    return;
+  }
+  void applyUpdate({ pinned: { [selectedNode.id]: !selectedNode.pinned } });
+}, [applyUpdate, selectedNode]);
+
 const handleEdgeColorChange = useCallback(
   (value: string) => {
     if (!selectedEdge) {
@@ -983,6 +1040,26 @@ const handleEdgeArrowChange = useCallback(
   [selectedEdge, submitStyleUpdate]
 );
 
+const handleEdgeLabelFillChange = useCallback(
+  (value: string | null) => {
+    if (!selectedEdge) {
+      return;
+    }
+    const normalized = value === null ? "none" : normalizeColorInput(value);
+    if (selectedEdge.labelFillColor === normalized) {
+      return;
+    }
+    void submitStyleUpdate({
+      edgeStyles: {
+        [selectedEdge.id]: {
+          labelFill: normalized,
+        },
+      },
+    });
+  },
+  [selectedEdge, submitStyleUpdate]
+);
+
 const handleEdgeStyleReset = useCallback(() => {
   if (!selectedEdge) {
     return;
@@ -1306,6 +1383,24 @@ const handleDeleteSelection = useCallback(async () => {
   }
 }, [deleteTarget, selectedEdgeId, selectedNodeId]);
 
+const handleQuickAdd = useCallback(async () => {
+  if (saving || sourceSaving) {
+    return;
+  }
+  try {
+    setSaving(true);
+    setError(null);
+    const id = await quickAddNode("", selectedNodeId);
+    await loadDiagram({ silent: true });
+    setSelectedNodeId(id);
+    setSelectedEdgeId(null);
+  } catch (err) {
+    setError((err as Error).message);
+  } finally {
+    setSaving(false);
+  }
+}, [loadDiagram, saving, selectedNodeId, sourceSaving]);
+
 const handleDeleteNodeDirect = useCallback(
   async (id: string) => {
     await deleteTarget({ type: "node", id });
@@ -1329,7 +1424,7 @@ const handleResetOverrides = useCallback(() => {
   const edgesUpdate: Record<string, { points?: Point[] | null }> = {};
 
   for (const node of diagram.nodes) {
-    if (node.overridePosition) {
+    if (node.overridePosition && !node.pinned) {
       nodesUpdate[node.id] = null;
     }
   }
@@ -1478,8 +1573,15 @@ const sourceStatus = useMemo(() => {
   if (sourceDraft !== source) {
     return { label: "Pending changes…", variant: "pending" as const };
   }
+  const firstDiagnostic = diagram?.diagnostics?.[0];
+  if (firstDiagnostic) {
+    return {
+      label: `Line ${firstDiagnostic.line} skipped: ${firstDiagnostic.message}`,
+      variant: "error" as const,
+    };
+  }
   return { label: "Synced", variant: "synced" as const };
-}, [sourceError, sourceSaving, sourceDraft, source]);
+}, [diagram, sourceError, sourceSaving, sourceDraft, source]);
 
 const selectionLabel = useMemo(() => {
   if (selectedNodeId) {
@@ -1575,6 +1677,14 @@ const edgeColorValue = useMemo(() => {
   return resolveColor(selectedEdge.color, DEFAULT_EDGE_COLOR);
 }, [selectedEdge]);
 
+const edgeLabelBoxHidden = selectedEdge?.labelFillColor === "none";
+const edgeLabelFillValue = useMemo(() => {
+  if (!selectedEdge || edgeLabelBoxHidden) {
+    return "#ffffff";
+  }
+  return resolveColor(selectedEdge.labelFillColor, "#ffffff");
+}, [selectedEdge, edgeLabelBoxHidden]);
+
 const edgeLineValue = selectedEdge?.kind ?? "solid";
 const edgeArrowValue = selectedEdge?.arrowDirection ?? "forward";
 
@@ -1583,7 +1693,9 @@ const edgeControlsDisabled = !selectedEdge || saving;
 
 useEffect(() => {
   const handleKeyDown = (event: KeyboardEvent) => {
-    if (event.key !== "Delete" && event.key !== "Backspace") {
+    const isQuickAdd =
+      event.key === "n" && !event.ctrlKey && !event.metaKey && !event.altKey;
+    if (event.key !== "Delete" && event.key !== "Backspace" && !isQuickAdd) {
       return;
     }
     const active = document.activeElement as HTMLElement | null;
@@ -1593,6 +1705,12 @@ useEffect(() => {
     ) {
       return;
     }
+    if (isQuickAdd) {
+      // Adds a node connected from the current selection, like sketching on a whiteboard.
+      event.preventDefault();
+      void handleQuickAdd();
+      return;
+    }
     if (!selectedNodeId && !selectedEdgeId) {
       return;
     }
@@ -1602,7 +1720,7 @@ useEffect(() => {
 
   window.addEventListener("keydown", handleKeyDown);
   return () => window.removeEventListener("keydown", handleKeyDown);
-}, [handleDeleteSelection, selectedEdgeId, selectedNodeId]);
+}, [handleDeleteSelection, handleQuickAdd, selectedEdgeId, selectedNodeId]);
 
 const handleLineClick = useCallback((line: number) => {
   if (!codeMapMapping || !selectedFile) return;
@@ -1845,6 +1963,34 @@ return (
                         </>
                       ) : null}
                     </div>
+                    <div className="style-color-row">
+                      <label className="style-control">
+                        <span>Border</span>
+                        <select
+                          value={selectedNode?.borderStyle ?? "solid"}
+                          onChange={(event) => handleNodeBorderStyleChange(event.target.value as BorderStyle)}
+                          disabled={nodeControlsDisabled}
+                        >
+                          {BORDER_STYLE_OPTIONS.map((option) => (
+                            <option key={option.value} value={option.value}>
+                              {option.label}
+                            </option>
+                          ))}
+                        </select>
+                      </label>
+                      <label className="style-control">
+                        <span>Border width</span>
+                        <input
+                          type="number"
+                          min={0}
+                          max={12}
+                          step={0.5}
+                          value={selectedNode?.strokeWidth ?? DEFAULT_NODE_BORDER_WIDTH}
+                          onChange={(event) => handleNodeBorderWidthChange(event.target.value)}
+                          disabled={nodeControlsDisabled}
+                        />
+                      </label>
+                    </div>
                     <div className="style-control image-control">
                       <span>Image</span>
                       <div className="image-control-actions">
@@ -1905,6 +2051,14 @@ return (
                   >
                     Reset node style
                   </button>
+                  <button
+                    type="button"
+                    className="style-reset"
+                    onClick={handleNodePinToggle}
+                    disabled={nodeControlsDisabled}
+                  >
+                    {selectedNode?.pinned ? "Unpin node" : "Pin node"}
+                  </button>
                   {codeMapMode && selectedNode && codeMapMapping?.nodes[selectedNode.id] && (
                     <div className="editor-link-actions" style={{ marginTop: "0.5rem" }}>
                       <button
@@ -1978,6 +2132,28 @@ return (
                         ))}
                       </select>
                     </label>
+                    <label className="style-control">
+                      <span>Label box</span>
+                      <select
+                        value={edgeLabelBoxHidden ? "halo" : "box"}
+                        onChange={(event) =>
+                          handleEdgeLabelFillChange(event.target.value === "halo" ? null : edgeLabelFillValue)
+                        }
+                        disabled={edgeControlsDisabled || !selectedEdge?.label}
+                      >
+                        <option value="box">Box</option>
+                        <option value="halo">Text halo</option>
+                      </select>
+                    </label>
+                    <label className="style-control">
+                      <span>Label fill</span>
+                      <input
+                        type="color"
+                        value={edgeLabelFillValue}
+                        onChange={(event) => handleEdgeLabelFillChange(event.target.value)}
+                        disabled={edgeControlsDisabled || !selectedEdge?.label || edgeLabelBoxHidden}
+                      />
+                    </label>
                   </div>
                   <button
                     type="button"
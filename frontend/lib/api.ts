@@ -1,11 +1,16 @@
 import {
   DiagramData,
+  DryRunPreview,
   EdgeStyleUpdate,
   LayoutUpdate,
   NodeStyleUpdate,
+  ParseDiagnostic,
   StyleUpdate,
   CodeMapMapping,
   SearchResult,
+  SourceChangeSet,
+  MinimapRender,
+  ViewportRender,
 } from "./types";
 import { createWasmEditor, type WasmEditorCore } from "./wasmEditor";
 
@@ -176,11 +181,13 @@ function localDiagramFromCore(core: WasmEditorCore): DiagramData {
     background: (vm.background as string) ?? "white",
     autoSize: (vm.autoSize as { width: number; height: number }) ?? { width: 0, height: 0 },
     renderSize: (vm.renderSize as { width: number; height: number }) ?? { width: 0, height: 0 },
+    coordinateShift: (vm.coordinateShift as { x: number; y: number }) ?? { x: 0, y: 0 },
     nodes: (vm.nodes as DiagramData["nodes"]) ?? [],
     edges: (vm.edges as DiagramData["edges"]) ?? [],
     subgraphs: (vm.subgraphs as DiagramData["subgraphs"]) ?? [],
     gantt: (vm.gantt as DiagramData["gantt"]) ?? undefined,
     source: (vm.source as string) ?? "",
+    layoutDiagnostics: (vm.layoutDiagnostics as DiagramData["layoutDiagnostics"]) ?? [],
   };
 }
 
@@ -192,6 +199,9 @@ function toLocalLayoutPayload(update: LayoutUpdate): Record<string, unknown> {
   if (update.edges && Object.keys(update.edges).length > 0) {
     payload.edges = update.edges;
   }
+  if (update.pinned && Object.keys(update.pinned).length > 0) {
+    payload.pinned = update.pinned;
+  }
   if (update.ganttTasks && Object.keys(update.ganttTasks).length > 0) {
     const entries: Array<[string, { start_day?: number; end_day?: number } | null]> = [];
     for (const [taskId, value] of Object.entries(update.ganttTasks)) {
@@ -216,7 +226,7 @@ function toLocalLayoutPayload(update: LayoutUpdate): Record<string, unknown> {
 function toLocalStylePayload(update: StyleUpdate): Record<string, unknown> {
   const payload: Record<string, unknown> = {};
 
-  const nodeEntries: Array<[string, Record<string, string | null> | null]> = [];
+  const nodeEntries: Array<[string, Record<string, string | number | null> | null]> = [];
   for (const [key, value] of Object.entries(update.nodeStyles ?? {})) {
     const normalized = normalizeNodeStyle(value);
     if (normalized !== undefined) {
@@ -330,6 +340,9 @@ export async function updateLayout(update: LayoutUpdate): Promise<void> {
   if (update.edges && Object.keys(update.edges).length > 0) {
     payload.edges = update.edges;
   }
+  if (update.pinned && Object.keys(update.pinned).length > 0) {
+    payload.pinned = update.pinned;
+  }
   if (update.ganttTasks && Object.keys(update.ganttTasks).length > 0) {
     const entries: Array<[string, { start_day?: number; end_day?: number } | null]> = [];
     for (const [taskId, value] of Object.entries(update.ganttTasks)) {
@@ -374,12 +387,12 @@ export async function updateLayout(update: LayoutUpdate): Promise<void> {
   }
 }
 
-export async function updateSource(source: string): Promise<void> {
+export async function updateSource(source: string): Promise<SourceChangeSet> {
   if (MODE === "local") {
     const core = await ensureLocalCore();
-    core.setSource(source);
+    const changes = core.setSource(source) as SourceChangeSet;
     persistLocalCore(core, source);
-    return;
+    return changes;
   }
 
   const response = await fetch(`${API_BASE}/api/diagram/source`, {
@@ -392,14 +405,66 @@ export async function updateSource(source: string): Promise<void> {
 
   if (!response.ok) {
     const message = await response.text();
+    if (response.status === 422) {
+      let diagnostic: ParseDiagnostic | null = null;
+      try {
+        diagnostic = JSON.parse(message) as ParseDiagnostic;
+      } catch {
+        diagnostic = null;
+      }
+      if (diagnostic) {
+        throw new SourceParseError(diagnostic);
+      }
+    }
     throw new Error(message || `Failed to update source: ${response.status}`);
   }
+
+  return (await response.json()) as SourceChangeSet;
+}
+
+export async function previewSource(source: string): Promise<DryRunPreview> {
+  if (MODE === "local") {
+    throw new Error("Previewing source edits requires the oxdraw server");
+  }
+
+  const response = await fetch(`${API_BASE}/api/diagram/source?dry_run=true`, {
+    method: "PUT",
+    headers: {
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({ source }),
+  });
+
+  if (!response.ok) {
+    const message = await response.text();
+    if (response.status === 422) {
+      let diagnostic: ParseDiagnostic | null = null;
+      try {
+        diagnostic = JSON.parse(message) as ParseDiagnostic;
+      } catch {
+        diagnostic = null;
+      }
+      if (diagnostic) {
+        throw new SourceParseError(diagnostic);
+      }
+    }
+    throw new Error(message || `Failed to preview source: ${response.status}`);
+  }
+
+  return (await response.json()) as DryRunPreview;
+}
+
+export class SourceParseError extends Error {
+  constructor(public readonly diagnostic: ParseDiagnostic) {
+    super(`Line ${diagnostic.line}, column ${diagnostic.column}: ${diagnostic.message}`);
+    this.name = "SourceParseError";
+  }
 }
 
 export async function updateStyle(update: StyleUpdate): Promise<void> {
   const payload: Record<string, unknown> = {};
 
-  const nodeEntries: Array<[string, Record<string, string | null> | null]> = [];
+  const nodeEntries: Array<[string, Record<string, string | number | null> | null]> = [];
   for (const [key, value] of Object.entries(update.nodeStyles ?? {})) {
     const normalized = normalizeNodeStyle(value);
     if (normalized !== undefined) {
@@ -507,7 +572,7 @@ export async function updateNodeImage(
 
 function normalizeNodeStyle(
   style: NodeStyleUpdate | null | undefined
-): Record<string, string | null> | null | undefined {
+): Record<string, string | number | null> | null | undefined {
   if (style === null) {
     return null;
   }
@@ -515,7 +580,7 @@ function normalizeNodeStyle(
     return undefined;
   }
 
-  const patch: Record<string, string | null> = {};
+  const patch: Record<string, string | number | null> = {};
   if (style.fill !== undefined) {
     patch.fill = style.fill;
   }
@@ -531,6 +596,12 @@ function normalizeNodeStyle(
   if (style.imageFill !== undefined) {
     patch["image_fill"] = style.imageFill;
   }
+  if (style.strokeWidth !== undefined) {
+    patch["stroke_width"] = style.strokeWidth;
+  }
+  if (style.borderStyle !== undefined) {
+    patch["border_style"] = style.borderStyle;
+  }
 
   return Object.keys(patch).length > 0 ? patch : undefined;
 }
@@ -555,10 +626,46 @@ function normalizeEdgeStyle(
   if (style.arrow !== undefined) {
     patch.arrow = style.arrow;
   }
+  if (style.labelFill !== undefined) {
+    patch["label_fill"] = style.labelFill;
+  }
+  if (style.labelStroke !== undefined) {
+    patch["label_stroke"] = style.labelStroke;
+  }
 
   return Object.keys(patch).length > 0 ? patch : undefined;
 }
 
+export async function quickAddNode(label: string, from?: string | null): Promise<string> {
+  if (MODE === "local") {
+    const core = await ensureLocalCore();
+    if (!core.quickAddNode) {
+      throw new Error(
+        "This build of the in-browser editor cannot add nodes; rebuild the wasm bundle."
+      );
+    }
+    const id = core.quickAddNode(label, from ?? null);
+    persistLocalCore(core);
+    return id;
+  }
+
+  const response = await fetch(`${API_BASE}/api/diagram/nodes`, {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({ label, from: from ?? null }),
+  });
+
+  if (!response.ok) {
+    const message = await response.text();
+    throw new Error(message || `Failed to add node: ${response.status}`);
+  }
+
+  const payload = (await response.json()) as { id: string; edgeId?: string };
+  return payload.id;
+}
+
 export async function deleteNode(nodeId: string): Promise<void> {
   if (MODE === "local") {
     const core = await ensureLocalCore();
@@ -607,6 +714,61 @@ export async function deleteEdge(edgeId: string): Promise<void> {
   }
 }
 
+export async function renderViewportPng(viewport: ViewportRender): Promise<Blob> {
+  if (MODE === "local") {
+    throw new Error("Viewport export requires the oxdraw server");
+  }
+
+  const response = await fetch(`${API_BASE}/api/diagram/png`, {
+    method: "POST",
+    headers: {
+      "Content-Type": "application/json",
+    },
+    body: JSON.stringify({
+      x: viewport.x,
+      y: viewport.y,
+      width: viewport.width,
+      height: viewport.height,
+      zoom: viewport.zoom ?? 1,
+      selected_nodes: viewport.selectedNodes ?? [],
+      selected_edges: viewport.selectedEdges ?? [],
+      selection_color: viewport.selectionColor ?? null,
+    }),
+  });
+
+  if (!response.ok) {
+    const message = await response.text();
+    throw new Error(message || `Failed to render viewport: ${response.status}`);
+  }
+
+  return response.blob();
+}
+
+export async function fetchMinimapSvg(minimap: MinimapRender = {}): Promise<string> {
+  if (MODE === "local") {
+    throw new Error("Minimap rendering requires the oxdraw server");
+  }
+
+  const params = new URLSearchParams();
+  if (minimap.size !== undefined) {
+    params.set("size", String(minimap.size));
+  }
+  if (minimap.viewport) {
+    params.set("x", String(minimap.viewport.x));
+    params.set("y", String(minimap.viewport.y));
+    params.set("width", String(minimap.viewport.width));
+    params.set("height", String(minimap.viewport.height));
+  }
+
+  const response = await fetch(`${API_BASE}/api/diagram/minimap?${params.toString()}`);
+  if (!response.ok) {
+    const message = await response.text();
+    throw new Error(message || `Failed to render minimap: ${response.status}`);
+  }
+
+  return response.text();
+}
+
 export async function fetchCodeMapMapping(): Promise<CodeMapMapping> {
   if (MODE === "local") {
     throw new Error("Code map is not available in local mode.");
@@ -11,8 +11,10 @@ export type NodeShape =
   | "parallelogram-alt"
   | "trapezoid"
   | "trapezoid-alt"
-  | "asymmetric";
+  | "asymmetric"
+  | "junction";
 export type EdgeKind = "solid" | "dashed";
+export type BorderStyle = "solid" | "dashed" | "dotted";
 export type EdgeArrowDirection = "forward" | "backward" | "both" | "none";
 
 export interface Point {
@@ -37,7 +39,10 @@ export interface NodeData {
   textColor?: string;
   labelFillColor?: string;
   imageFillColor?: string;
+  strokeWidth?: number;
+  borderStyle?: BorderStyle;
   membership?: string[];
+  pinned?: boolean;
   image?: NodeImageData;
   width: number;
   height: number;
@@ -62,6 +67,9 @@ export interface EdgeData {
   overridePoints?: Point[];
   color?: string;
   arrowDirection?: EdgeArrowDirection;
+  labelFillColor?: string;
+  labelStrokeColor?: string;
+  animated?: boolean;
 }
 
 export interface SubgraphData {
@@ -117,20 +125,28 @@ export interface GanttData {
 export interface DiagramData {
   sourcePath: string;
   kind: "flowchart" | "gantt";
+  /** Heading drawn above the diagram, from the frontmatter `title` or `%% oxdraw-title`. */
+  title?: string;
   background: string;
   autoSize: Size;
   renderSize: Size;
+  /** Render space (SVG, subgraphs, renderSize) = diagram space (nodes, edges) + coordinateShift. */
+  coordinateShift: Point;
   nodes: NodeData[];
   edges: EdgeData[];
   subgraphs?: SubgraphData[];
   gantt?: GanttData;
   source: string;
+  diagnostics?: ParseDiagnostic[];
+  layoutDiagnostics?: LayoutDiagnostic[];
+  debug?: DiagramDebug;
 }
 
 export interface LayoutUpdate {
   nodes?: Record<string, Point | null>;
   edges?: Record<string, { points?: Point[] | null }>;
   ganttTasks?: Record<string, { startDay?: number; endDay?: number } | null>;
+  pinned?: Record<string, boolean>;
 }
 
 export interface NodeStyleUpdate {
@@ -139,12 +155,17 @@ export interface NodeStyleUpdate {
   text?: string | null;
   labelFill?: string | null;
   imageFill?: string | null;
+  strokeWidth?: number | null;
+  borderStyle?: BorderStyle | null;
 }
 
 export interface EdgeStyleUpdate {
   line?: EdgeKind | null;
   color?: string | null;
   arrow?: EdgeArrowDirection | null;
+  labelFill?: string | null;
+  labelStroke?: string | null;
+  animated?: boolean | null;
 }
 
 export interface StyleUpdate {
@@ -160,6 +181,49 @@ export interface StyleUpdate {
   };
 }
 
+export interface ViewportRender {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  zoom?: number;
+  selectedNodes?: string[];
+  selectedEdges?: string[];
+  selectionColor?: string;
+}
+
+export interface MinimapRender {
+  size?: number;
+  viewport?: {
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+  };
+}
+
+export interface ChangeList {
+  added: string[];
+  removed: string[];
+  modified: string[];
+}
+
+export interface SourceChangeSet {
+  nodes: ChangeList;
+  edges: ChangeList;
+  subgraphs: ChangeList;
+  prunedOverrides: {
+    nodes: string[];
+    edges: string[];
+  };
+  destructive: boolean;
+}
+
+export interface DryRunPreview {
+  source: string;
+  changes: SourceChangeSet;
+}
+
 export interface SearchResult {
   file: string;
   line: number;
@@ -176,3 +240,27 @@ export interface CodeLocation {
 export interface CodeMapMapping {
   nodes: Record<string, CodeLocation>;
 }
+
+export interface PhaseTiming {
+  phase: string;
+  millis: number;
+}
+
+export interface DiagramDebug {
+  timings: PhaseTiming[];
+}
+
+export interface LayoutDiagnostic {
+  edgeId: string;
+  kind: "node-overlap" | "label-overlap" | "edge-crossing";
+  nodes: string[];
+  otherEdgeId?: string;
+}
+
+export interface ParseDiagnostic {
+  code: string;
+  message: string;
+  line: number;
+  column: number;
+  endColumn: number;
+}
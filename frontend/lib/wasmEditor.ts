@@ -18,7 +18,9 @@ export interface WasmEditorCore {
   source(): string;
   applyLayoutUpdate(update: unknown): void;
   applyStyleUpdate(update: unknown): void;
-  setSource(source: string): void;
+  setSource(source: string): unknown;
+  // Missing from wasm bundles built before quick-add; frontend/public is regenerated on deploy.
+  quickAddNode?(label: string, from?: string | null): string;
   deleteNode(id: string): boolean;
   deleteEdge(id: string): boolean;
 }
@@ -0,0 +1,467 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+
+use crate::diagram::{intern_node, parse_edge_statement};
+use crate::*;
+
+const BLOCK_GAP: f32 = 40.0;
+
+#[derive(Debug, Clone)]
+pub struct BlockDiagram {
+    /// `columns N` of the outermost grid; `None` keeps every block on one row.
+    pub columns: Option<usize>,
+    pub items: Vec<BlockItem>,
+    /// Grid cell centers, used in place of the layered layout.
+    pub positions: HashMap<String, Point>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockItem {
+    Block { id: String, span: usize },
+    Space { span: usize },
+    Group(BlockGroup),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockGroup {
+    pub id: String,
+    pub label: String,
+    pub span: usize,
+    pub columns: Option<usize>,
+    pub items: Vec<BlockItem>,
+}
+
+impl BlockItem {
+    fn span(&self) -> usize {
+        match self {
+            BlockItem::Block { span, .. } | BlockItem::Space { span } => *span,
+            BlockItem::Group(group) => group.span,
+        }
+    }
+}
+
+/// Splits a row such as `a["Two words"]:2 space b` at whitespace outside quotes and brackets.
+fn split_row(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0_i32;
+    let mut in_quotes = false;
+    let mut start = None;
+    for (idx, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            '[' | '(' | '{' if !in_quotes => depth += 1,
+            ']' | ')' | '}' if !in_quotes => depth -= 1,
+            _ => {}
+        }
+        if ch.is_whitespace() && !in_quotes && depth <= 0 {
+            if let Some(begin) = start.take() {
+                tokens.push(&line[begin..idx]);
+            }
+        } else if start.is_none() {
+            start = Some(idx);
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push(&line[begin..]);
+    }
+    tokens
+}
+
+/// Strips a trailing `:N` column span.
+fn split_span(token: &str) -> Result<(&str, usize)> {
+    match token.rsplit_once(':') {
+        Some((head, span))
+            if !head.is_empty() && !span.is_empty() && span.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            match span.parse::<usize>() {
+                Ok(span) if span > 0 => Ok((head, span)),
+                _ => bail!("block '{head}' must span at least one column"),
+            }
+        }
+        _ => Ok((token, 1)),
+    }
+}
+
+fn parse_columns(value: &str) -> Result<Option<usize>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(None);
+    }
+    match value.parse::<usize>() {
+        Ok(columns) if columns > 0 => Ok(Some(columns)),
+        _ => bail!("expected a positive column count but found '{value}'"),
+    }
+}
+
+struct BlockBuilder {
+    nodes: HashMap<String, Node>,
+    order: Vec<String>,
+    node_membership: HashMap<String, Vec<String>>,
+    /// Open `block ... end` groups; the outermost grid is not on the stack.
+    stack: Vec<BlockGroup>,
+    root: Vec<BlockItem>,
+    root_columns: Option<usize>,
+    anonymous_groups: usize,
+}
+
+impl BlockBuilder {
+    fn push(&mut self, item: BlockItem) {
+        if let BlockItem::Block { id, .. } = &item
+            && !self.stack.is_empty()
+        {
+            self.node_membership.insert(
+                id.clone(),
+                self.stack.iter().map(|group| group.id.clone()).collect(),
+            );
+        }
+        match self.stack.last_mut() {
+            Some(group) => group.items.push(item),
+            None => self.root.push(item),
+        }
+    }
+
+    fn set_columns(&mut self, columns: Option<usize>) {
+        match self.stack.last_mut() {
+            Some(group) => group.columns = columns,
+            None => self.root_columns = columns,
+        }
+    }
+
+    /// Parses `block`, `block:id`, `block:id:2` or `block:id["Label"]:2`.
+    fn open_group(&mut self, head: &str) -> Result<()> {
+        let (spec, span) = split_span(head.strip_prefix(':').unwrap_or(""))?;
+        let spec = spec.trim();
+        let (id, label) = match spec.split_once('[') {
+            Some((id, label)) => {
+                let Some(label) = label.strip_suffix(']') else {
+                    bail!("block group label missing closing ']' in '{spec}'");
+                };
+                (
+                    id.trim().to_string(),
+                    decode_label(label.trim().trim_matches('"')),
+                )
+            }
+            None => (spec.to_string(), String::new()),
+        };
+        let id = if id.is_empty() {
+            self.anonymous_groups += 1;
+            format!("block{}", self.anonymous_groups)
+        } else {
+            id
+        };
+        if self.nodes.contains_key(&id) || self.group_declared(&id) {
+            bail!("block '{id}' is declared more than once");
+        }
+        self.stack.push(BlockGroup {
+            id,
+            label,
+            span,
+            columns: None,
+            items: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn group_declared(&self, id: &str) -> bool {
+        fn declared(items: &[BlockItem], id: &str) -> bool {
+            items.iter().any(|item| match item {
+                BlockItem::Group(group) => group.id == id || declared(&group.items, id),
+                _ => false,
+            })
+        }
+        declared(&self.root, id)
+            || self
+                .stack
+                .iter()
+                .any(|group| group.id == id || declared(&group.items, id))
+    }
+
+    fn add_block(&mut self, token: &str) -> Result<()> {
+        let (spec, span) = split_span(token)?;
+        if spec == "space" {
+            self.push(BlockItem::Space { span });
+            return Ok(());
+        }
+        let (id, inserted) = intern_node(spec, &mut self.nodes, &mut self.order)?;
+        if !inserted || self.group_declared(&id) {
+            bail!("block '{id}' is declared more than once");
+        }
+        self.push(BlockItem::Block { id, span });
+        Ok(())
+    }
+}
+
+/// Parses `columns`, block rows, `space`, nested `block ... end` groups and edges.
+pub(crate) fn parse_block_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut builder = BlockBuilder {
+        nodes: HashMap::new(),
+        order: Vec::new(),
+        node_membership: HashMap::new(),
+        stack: Vec::new(),
+        root: Vec::new(),
+        root_columns: None,
+        anonymous_groups: 0,
+    };
+    let mut edges = Vec::new();
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if ["classdef ", "class ", "style ", "acctitle", "accdescr"]
+            .iter()
+            .any(|prefix| lower.starts_with(prefix))
+        {
+            continue;
+        }
+        if line == "end" {
+            let Some(group) = builder.stack.pop() else {
+                bail!("unexpected 'end' without an open block");
+            };
+            builder.push(BlockItem::Group(group));
+            continue;
+        }
+        if let Some(columns) = lower.strip_prefix("columns ") {
+            builder.set_columns(parse_columns(columns)?);
+            continue;
+        }
+        if lower == "block" || lower.starts_with("block:") {
+            builder.open_group(&line["block".len()..])?;
+            continue;
+        }
+
+        let known = builder.order.len();
+        if let Some(edge) = parse_edge_statement(line, &mut builder.nodes, &mut builder.order)? {
+            // Endpoints that were not declared yet take the next free cells.
+            let added = builder.order[known..].to_vec();
+            for id in added {
+                builder.push(BlockItem::Block { id, span: 1 });
+            }
+            push_edge(&mut edges, edge);
+            continue;
+        }
+
+        for token in split_row(line) {
+            builder.add_block(token)?;
+        }
+    }
+
+    if let Some(group) = builder.stack.last() {
+        bail!("block '{}' is missing its closing 'end'", group.id);
+    }
+    if builder.nodes.is_empty() {
+        bail!("block diagram does not declare any blocks");
+    }
+
+    let mut block = BlockDiagram {
+        columns: builder.root_columns,
+        items: builder.root,
+        positions: HashMap::new(),
+        original_source: original_source.to_string(),
+    };
+    let mut nodes = builder.nodes;
+    block.place(&mut nodes);
+
+    let mut subgraphs = Vec::new();
+    collect_subgraphs(&block.items, &mut subgraphs, &mut 0);
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Block(block),
+        direction: Direction::TopDown,
+        nodes,
+        order: builder.order,
+        edges,
+        subgraphs,
+        node_membership: builder.node_membership,
+    })
+}
+
+fn collect_subgraphs(items: &[BlockItem], out: &mut Vec<Subgraph>, next_order: &mut usize) {
+    for item in items {
+        let BlockItem::Group(group) = item else {
+            continue;
+        };
+        let order = *next_order;
+        *next_order += 1;
+        let mut children = Vec::new();
+        collect_subgraphs(&group.items, &mut children, next_order);
+        out.push(Subgraph {
+            id: group.id.clone(),
+            label: group.label.clone(),
+            nodes: group
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    BlockItem::Block { id, .. } => Some(id.clone()),
+                    _ => None,
+                })
+                .collect(),
+            children,
+            order,
+        });
+    }
+}
+
+/// Rows of `items` once they wrap at `columns`; spans wider than the grid are clamped.
+fn grid_rows(
+    items: &[BlockItem],
+    columns: Option<usize>,
+) -> (usize, Vec<Vec<(usize, &BlockItem)>>) {
+    let columns = columns
+        .unwrap_or_else(|| items.iter().map(BlockItem::span).sum())
+        .max(1);
+    let mut rows: Vec<Vec<(usize, &BlockItem)>> = vec![Vec::new()];
+    let mut column = 0;
+    for item in items {
+        let span = item.span().min(columns);
+        if column + span > columns {
+            rows.push(Vec::new());
+            column = 0;
+        }
+        rows.last_mut()
+            .expect("at least one row")
+            .push((column, item));
+        column += span;
+    }
+    (columns, rows)
+}
+
+fn span_width(span: usize, column_width: f32) -> f32 {
+    span as f32 * column_width + (span as f32 - 1.0) * BLOCK_GAP
+}
+
+fn is_round(shape: NodeShape) -> bool {
+    matches!(shape, NodeShape::Circle | NodeShape::DoubleCircle)
+}
+
+impl BlockDiagram {
+    /// Assigns every block a cell on a uniform grid and stretches it to fill its span.
+    fn place(&mut self, nodes: &mut HashMap<String, Node>) {
+        // Circles keep their own size so they do not stretch every row.
+        let cell_height = nodes
+            .values()
+            .filter(|node| !is_round(node.shape))
+            .map(|node| node.height)
+            .fold(NODE_HEIGHT, f32::max);
+        let column_width = min_column_width(&self.items, self.columns, nodes);
+        let (columns, _) = grid_rows(&self.items, self.columns);
+        let width = span_width(columns, column_width);
+        let mut positions = HashMap::new();
+        place_items(
+            &self.items,
+            self.columns,
+            Point {
+                x: START_OFFSET,
+                y: START_OFFSET,
+            },
+            width,
+            cell_height,
+            nodes,
+            &mut positions,
+        );
+        self.positions = positions;
+    }
+}
+
+/// Narrowest column that still fits every block and group in the grid.
+fn min_column_width(
+    items: &[BlockItem],
+    columns: Option<usize>,
+    nodes: &HashMap<String, Node>,
+) -> f32 {
+    let (columns, rows) = grid_rows(items, columns);
+    rows.iter()
+        .flatten()
+        .map(|(_, item)| {
+            let span = item.span().min(columns);
+            let needed = match item {
+                BlockItem::Block { id, .. } => nodes.get(id).map_or(0.0, |node| node.width),
+                BlockItem::Space { .. } => 0.0,
+                BlockItem::Group(group) => {
+                    let (inner_columns, _) = grid_rows(&group.items, group.columns);
+                    let inner = min_column_width(&group.items, group.columns, nodes);
+                    span_width(inner_columns, inner) + SUBGRAPH_PADDING * 2.0
+                }
+            };
+            (needed - (span as f32 - 1.0) * BLOCK_GAP) / span as f32
+        })
+        .fold(NODE_WIDTH, f32::max)
+}
+
+fn item_height(item: &BlockItem, nodes: &HashMap<String, Node>, cell_height: f32) -> f32 {
+    match item {
+        BlockItem::Block { id, .. } => nodes
+            .get(id)
+            .filter(|node| is_round(node.shape))
+            .map_or(cell_height, |node| node.height.max(cell_height)),
+        BlockItem::Group(group) => {
+            let (_, rows) = grid_rows(&group.items, group.columns);
+            let inner: f32 = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|(_, item)| item_height(item, nodes, cell_height))
+                        .fold(0.0, f32::max)
+                })
+                .sum::<f32>()
+                + (rows.len() as f32 - 1.0) * BLOCK_GAP;
+            inner + SUBGRAPH_PADDING * 2.0 + SUBGRAPH_LABEL_AREA
+        }
+        _ => cell_height,
+    }
+}
+
+fn place_items(
+    items: &[BlockItem],
+    columns: Option<usize>,
+    origin: Point,
+    width: f32,
+    cell_height: f32,
+    nodes: &mut HashMap<String, Node>,
+    positions: &mut HashMap<String, Point>,
+) {
+    let (columns, rows) = grid_rows(items, columns);
+    let column_width = (width - (columns as f32 - 1.0) * BLOCK_GAP) / columns as f32;
+    let mut top = origin.y;
+    for row in rows {
+        let row_height = row
+            .iter()
+            .map(|(_, item)| item_height(item, nodes, cell_height))
+            .fold(0.0, f32::max);
+        for (column, item) in row {
+            let left = origin.x + column as f32 * (column_width + BLOCK_GAP);
+            let span = span_width(item.span().min(columns), column_width);
+            match item {
+                BlockItem::Block { id, .. } => {
+                    if let Some(node) = nodes.get_mut(id)
+                        && !is_round(node.shape)
+                    {
+                        node.width = span;
+                        node.height = cell_height;
+                    }
+                    positions.insert(
+                        id.clone(),
+                        Point {
+                            x: left + span / 2.0,
+                            y: top + row_height / 2.0,
+                        },
+                    );
+                }
+                BlockItem::Space { .. } => {}
+                BlockItem::Group(group) => place_items(
+                    &group.items,
+                    group.columns,
+                    Point {
+                        x: left + SUBGRAPH_PADDING,
+                        y: top + SUBGRAPH_PADDING + SUBGRAPH_LABEL_AREA,
+                    },
+                    span - SUBGRAPH_PADDING * 2.0,
+                    cell_height,
+                    nodes,
+                    positions,
+                ),
+            }
+        }
+        top += row_height + BLOCK_GAP;
+    }
+}
@@ -0,0 +1,614 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+const C4_ELEMENT_WIDTH: f32 = 220.0;
+const C4_PADDING: f32 = 14.0;
+const C4_NAME_HEIGHT: f32 = 20.0;
+const C4_TYPE_HEIGHT: f32 = 16.0;
+const C4_DESCRIPTION_GAP: f32 = 6.0;
+const C4_PERSON_HEAD_RADIUS: f32 = 16.0;
+const C4_DB_CAP: f32 = 10.0;
+/// Approximate advance of the 12px description text.
+const C4_SMALL_CHAR_WIDTH: f32 = 6.6;
+const C4_LEGEND_HEIGHT: f32 = 56.0;
+const C4_LEGEND_SWATCH: f32 = 14.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum C4ElementKind {
+    Person,
+    System,
+    Container,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum C4ElementShape {
+    Box,
+    Database,
+    Queue,
+}
+
+/// `C4Context` and `C4Container` sources are translated into the flowchart model so elements
+/// reuse the layered layout and editor overrides; boundaries become subgraphs and the element
+/// boxes and legend are drawn from this data.
+#[derive(Debug, Clone)]
+pub struct C4DiagramData {
+    pub title: Option<String>,
+    pub elements: HashMap<String, C4Element>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct C4Element {
+    pub kind: C4ElementKind,
+    pub shape: C4ElementShape,
+    pub external: bool,
+    pub technology: Option<String>,
+    pub description: Option<String>,
+}
+
+impl C4ElementKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Person => "Person",
+            Self::System => "Software System",
+            Self::Container => "Container",
+        }
+    }
+}
+
+impl C4Element {
+    /// Standard C4 fill and stroke for this kind of element.
+    pub(crate) fn colors(&self) -> (&'static str, &'static str) {
+        match (self.kind, self.external) {
+            (C4ElementKind::Person, false) => ("#08427b", "#073b6f"),
+            (C4ElementKind::Person, true) => ("#686868", "#5a5a5a"),
+            (C4ElementKind::System, false) => ("#1168bd", "#0e5aa7"),
+            (C4ElementKind::System, true) => ("#999999", "#8a8a8a"),
+            (C4ElementKind::Container, false) => ("#438dd5", "#3c7fc0"),
+            (C4ElementKind::Container, true) => ("#b3b3b3", "#a6a6a6"),
+        }
+    }
+
+    fn type_line(&self) -> String {
+        let name = if self.external {
+            format!("External {}", self.kind.name())
+        } else {
+            self.kind.name().to_string()
+        };
+        match &self.technology {
+            Some(technology) => format!("[{name}: {technology}]"),
+            None => format!("[{name}]"),
+        }
+    }
+
+    fn description_lines(&self) -> Vec<String> {
+        let max_chars = ((C4_ELEMENT_WIDTH - C4_PADDING * 2.0) / C4_SMALL_CHAR_WIDTH) as usize;
+        let mut lines: Vec<String> = Vec::new();
+        let Some(description) = &self.description else {
+            return lines;
+        };
+        let mut current = String::new();
+        for word in description.split_whitespace() {
+            if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars
+            {
+                lines.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        if !current.is_empty() {
+            lines.push(current);
+        }
+        lines
+    }
+
+    /// Vertical space above the text block: the person's head or the database's top cap.
+    fn top_inset(&self) -> f32 {
+        match (self.kind, self.shape) {
+            (_, C4ElementShape::Database) => C4_DB_CAP * 2.0,
+            (C4ElementKind::Person, _) => C4_PERSON_HEAD_RADIUS * 1.5,
+            _ => 0.0,
+        }
+    }
+
+    fn height(&self) -> f32 {
+        let description = self.description_lines();
+        let description_height = if description.is_empty() {
+            0.0
+        } else {
+            C4_DESCRIPTION_GAP + NODE_TEXT_LINE_HEIGHT * description.len() as f32
+        };
+        self.top_inset() + C4_PADDING * 2.0 + C4_NAME_HEIGHT + C4_TYPE_HEIGHT + description_height
+    }
+}
+
+impl C4DiagramData {
+    /// Draws an element box with its name, `[type: technology]` line and wrapped description.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn render_element(
+        &self,
+        svg: &mut String,
+        id: &str,
+        label: &str,
+        position: Point,
+        width: f32,
+        height: f32,
+        fill: &str,
+        stroke: &str,
+        text: &str,
+    ) -> Result<bool> {
+        let Some(element) = self.elements.get(id) else {
+            return Ok(false);
+        };
+        let left = position.x - width / 2.0;
+        let top = position.y - height / 2.0;
+        let (fill, stroke) = (escape_xml(fill), escape_xml(stroke));
+
+        match (element.kind, element.shape) {
+            (_, C4ElementShape::Database) => {
+                let rx = width / 2.0;
+                writeln!(
+                    svg,
+                    "  <path d=\"M{:.1},{:.1} A{:.1},{:.1} 0 0 1 {:.1},{:.1} V{:.1} A{:.1},{:.1} 0 0 1 {:.1},{:.1} Z\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />\n  <path d=\"M{:.1},{:.1} A{:.1},{:.1} 0 0 0 {:.1},{:.1}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\" />",
+                    left,
+                    top + C4_DB_CAP,
+                    rx,
+                    C4_DB_CAP,
+                    left + width,
+                    top + C4_DB_CAP,
+                    top + height - C4_DB_CAP,
+                    rx,
+                    C4_DB_CAP,
+                    left,
+                    top + height - C4_DB_CAP,
+                    fill,
+                    stroke,
+                    left,
+                    top + C4_DB_CAP,
+                    rx,
+                    C4_DB_CAP,
+                    left + width,
+                    top + C4_DB_CAP,
+                    stroke
+                )?;
+            }
+            (kind, shape) => {
+                let body_top = top + element.top_inset();
+                let body_height = height - element.top_inset();
+                let radius = match (kind, shape) {
+                    (_, C4ElementShape::Queue) => body_height / 2.0,
+                    (C4ElementKind::Person, _) => 24.0,
+                    _ => 8.0,
+                };
+                writeln!(
+                    svg,
+                    "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />",
+                    left, body_top, width, body_height, radius, radius, fill, stroke
+                )?;
+                if kind == C4ElementKind::Person {
+                    writeln!(
+                        svg,
+                        "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />",
+                        position.x,
+                        top + C4_PERSON_HEAD_RADIUS,
+                        C4_PERSON_HEAD_RADIUS,
+                        fill,
+                        stroke
+                    )?;
+                }
+            }
+        }
+
+        let text = escape_xml(text);
+        let mut y = top + element.top_inset() + C4_PADDING + C4_NAME_HEIGHT / 2.0;
+        writeln!(
+            svg,
+            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"15\" font-weight=\"700\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+            position.x,
+            y,
+            text,
+            escape_xml(label)
+        )?;
+        y += (C4_NAME_HEIGHT + C4_TYPE_HEIGHT) / 2.0;
+        writeln!(
+            svg,
+            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"11\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+            position.x,
+            y,
+            text,
+            escape_xml(&element.type_line())
+        )?;
+        y += C4_TYPE_HEIGHT / 2.0 + C4_DESCRIPTION_GAP + NODE_TEXT_LINE_HEIGHT / 2.0;
+        for line in element.description_lines() {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                position.x,
+                y,
+                text,
+                escape_xml(&line)
+            )?;
+            y += NODE_TEXT_LINE_HEIGHT;
+        }
+        Ok(true)
+    }
+
+    /// Adds a legend row under the diagram for each kind of element it uses, growing the canvas
+    /// from `width`×`height` to fit.
+    pub(crate) fn append_legend(
+        &self,
+        svg: &mut String,
+        width: f32,
+        height: f32,
+        has_boundaries: bool,
+    ) -> Result<()> {
+        let mut entries: Vec<(C4ElementKind, bool)> = self
+            .elements
+            .values()
+            .map(|element| (element.kind, element.external))
+            .collect();
+        entries.sort();
+        entries.dedup();
+
+        let mut legend = String::new();
+        let mut x = LAYOUT_MARGIN;
+        let y = height + C4_LEGEND_HEIGHT / 2.0 - LAYOUT_MARGIN / 2.0;
+        legend.push_str("  <g class=\"c4-legend\">\n");
+        for (kind, external) in entries {
+            let element = C4Element {
+                kind,
+                shape: C4ElementShape::Box,
+                external,
+                technology: None,
+                description: None,
+            };
+            let (fill, stroke) = element.colors();
+            let label = element.type_line();
+            let label = label.trim_start_matches('[').trim_end_matches(']');
+            writeln!(
+                legend,
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"3\" ry=\"3\" fill=\"{}\" stroke=\"{}\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"12\" dominant-baseline=\"middle\">{}</text>",
+                x,
+                y - C4_LEGEND_SWATCH / 2.0,
+                C4_LEGEND_SWATCH,
+                C4_LEGEND_SWATCH,
+                fill,
+                stroke,
+                x + C4_LEGEND_SWATCH + 6.0,
+                y,
+                escape_xml(label)
+            )?;
+            x += C4_LEGEND_SWATCH + 24.0 + C4_SMALL_CHAR_WIDTH * display_width(label) as f32;
+        }
+        if has_boundaries {
+            writeln!(
+                legend,
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#718096\" stroke-dasharray=\"4 3\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"12\" dominant-baseline=\"middle\">Boundary</text>",
+                x,
+                y - C4_LEGEND_SWATCH / 2.0,
+                C4_LEGEND_SWATCH,
+                C4_LEGEND_SWATCH,
+                x + C4_LEGEND_SWATCH + 6.0,
+                y
+            )?;
+            x += C4_LEGEND_SWATCH + 24.0 + C4_SMALL_CHAR_WIDTH * 8.0;
+        }
+        legend.push_str("  </g>\n");
+
+        let new_width = width.max(x + LAYOUT_MARGIN);
+        let new_height = height + C4_LEGEND_HEIGHT;
+        let root = format!(
+            "width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\"",
+            width, height, width, height
+        );
+        let Some(start) = svg.find(&root) else {
+            bail!("generated SVG is missing its root dimensions");
+        };
+        svg.replace_range(
+            start..start + root.len(),
+            &format!(
+                "width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\"",
+                new_width, new_height, new_width, new_height
+            ),
+        );
+        let Some(end) = svg.rfind("</svg>") else {
+            bail!("generated SVG is missing its root element");
+        };
+        svg.insert_str(end, &legend);
+        Ok(())
+    }
+}
+
+fn call_regex() -> &'static Regex {
+    static CALL: OnceLock<Regex> = OnceLock::new();
+    CALL.get_or_init(|| {
+        Regex::new(r"^(\w+)\s*\((.*)\)\s*(\{)?$").expect("valid C4 statement regex")
+    })
+}
+
+/// Splits `alias, "Label, with comma", $tags="x"` on top-level commas, dropping quotes and
+/// `$name=value` keyword arguments. Returns `None` when a quote is left open.
+fn split_arguments(arguments: &str) -> Option<Vec<String>> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for ch in arguments.chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ',' if !quoted => parts.push(std::mem::take(&mut current)),
+            _ => current.push(ch),
+        }
+    }
+    if quoted {
+        return None;
+    }
+    parts.push(current);
+    Some(
+        parts
+            .into_iter()
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.starts_with('$'))
+            .collect(),
+    )
+}
+
+fn element_kind(function: &str) -> Option<(C4ElementKind, C4ElementShape, bool)> {
+    let (base, external) = match function.strip_suffix("_Ext") {
+        Some(base) => (base, true),
+        None => (function, false),
+    };
+    let (kind, shape) = match base {
+        "Person" => (C4ElementKind::Person, C4ElementShape::Box),
+        "System" => (C4ElementKind::System, C4ElementShape::Box),
+        "SystemDb" => (C4ElementKind::System, C4ElementShape::Database),
+        "SystemQueue" => (C4ElementKind::System, C4ElementShape::Queue),
+        "Container" => (C4ElementKind::Container, C4ElementShape::Box),
+        "ContainerDb" => (C4ElementKind::Container, C4ElementShape::Database),
+        "ContainerQueue" => (C4ElementKind::Container, C4ElementShape::Queue),
+        _ => return None,
+    };
+    Some((kind, shape, external))
+}
+
+struct C4Builder {
+    nodes: HashMap<String, Node>,
+    order: Vec<String>,
+    elements: HashMap<String, C4Element>,
+    boundaries: Vec<(Subgraph, Option<usize>)>,
+    stack: Vec<usize>,
+    node_membership: HashMap<String, Vec<String>>,
+}
+
+impl C4Builder {
+    fn is_declared(&self, id: &str) -> bool {
+        self.nodes.contains_key(id) || self.boundaries.iter().any(|(b, _)| b.id == id)
+    }
+
+    fn into_subgraphs(self) -> Vec<Subgraph> {
+        let mut boundaries: Vec<Option<(Subgraph, Option<usize>)>> =
+            self.boundaries.into_iter().map(Some).collect();
+        // Children always come after their parent, so attach from the back.
+        for idx in (0..boundaries.len()).rev() {
+            let Some(parent) = boundaries[idx].as_ref().and_then(|(_, parent)| *parent) else {
+                continue;
+            };
+            let (child, _) = boundaries[idx].take().expect("child visited once");
+            if let Some((parent, _)) = boundaries[parent].as_mut() {
+                parent.children.insert(0, child);
+            }
+        }
+        boundaries
+            .into_iter()
+            .flatten()
+            .map(|(subgraph, _)| subgraph)
+            .filter(|subgraph| !subgraph.nodes.is_empty() || !subgraph.children.is_empty())
+            .collect()
+    }
+}
+
+/// Parses `Person`, `System`, `Container` (with `Db`, `Queue` and `_Ext` variants), nested
+/// `*Boundary(alias, label) { ... }` blocks and `Rel`/`BiRel`/`Rel_Back` statements.
+pub(crate) fn parse_c4_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut builder = C4Builder {
+        nodes: HashMap::new(),
+        order: Vec::new(),
+        elements: HashMap::new(),
+        boundaries: Vec::new(),
+        stack: Vec::new(),
+        node_membership: HashMap::new(),
+    };
+    let mut title = None;
+    let mut edges = Vec::new();
+
+    for line in lines {
+        let line = line.trim();
+        if line == "}" {
+            if builder.stack.pop().is_none() {
+                bail!("unexpected '}}' without an open boundary");
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("title ") {
+            title = Some(decode_label(rest.trim()));
+            continue;
+        }
+        let Some(caps) = call_regex().captures(line) else {
+            bail!("unrecognized C4 statement '{line}'");
+        };
+        let function = &caps[1];
+        let Some(arguments) = split_arguments(&caps[2]) else {
+            bail!("unterminated quote in '{line}'");
+        };
+        let opens_block = caps.get(3).is_some();
+        let argument = |idx: usize| {
+            arguments
+                .get(idx)
+                .map(|value| decode_label(value))
+                .filter(|value| !value.is_empty())
+        };
+
+        let alias = arguments.first().filter(|alias| !alias.is_empty());
+        if let Some((kind, shape, external)) = element_kind(function) {
+            let (Some(id), Some(label)) = (alias, argument(1)) else {
+                bail!("'{function}' needs an alias and a label in '{line}'");
+            };
+            if opens_block {
+                bail!("'{function}' cannot contain other elements");
+            }
+            if builder.is_declared(id) {
+                bail!("C4 element '{id}' is declared more than once");
+            }
+            let (technology, description) = if kind == C4ElementKind::Container {
+                (argument(2), argument(3))
+            } else {
+                (None, argument(2))
+            };
+            let element = C4Element {
+                kind,
+                shape,
+                external,
+                technology,
+                description,
+            };
+            builder.nodes.insert(
+                id.clone(),
+                Node {
+                    label,
+                    shape: match shape {
+                        C4ElementShape::Box => NodeShape::Rectangle,
+                        C4ElementShape::Database => NodeShape::Cylinder,
+                        C4ElementShape::Queue => NodeShape::Stadium,
+                    },
+                    image: None,
+                    width: C4_ELEMENT_WIDTH,
+                    height: element.height(),
+                },
+            );
+            builder.elements.insert(id.clone(), element);
+            builder.order.push(id.clone());
+            if let Some(&innermost) = builder.stack.last() {
+                builder.boundaries[innermost].0.nodes.push(id.clone());
+                builder.node_membership.insert(
+                    id.clone(),
+                    builder
+                        .stack
+                        .iter()
+                        .map(|&idx| builder.boundaries[idx].0.id.clone())
+                        .collect(),
+                );
+            }
+            continue;
+        }
+
+        match function {
+            "Boundary" | "Enterprise_Boundary" | "System_Boundary" | "Container_Boundary" => {
+                let (Some(id), Some(label)) = (alias, argument(1)) else {
+                    bail!("'{function}' needs an alias and a label in '{line}'");
+                };
+                if !opens_block {
+                    bail!("boundary '{id}' must open a '{{' block");
+                }
+                if builder.is_declared(id) {
+                    bail!("C4 element '{id}' is declared more than once");
+                }
+                let idx = builder.boundaries.len();
+                builder.boundaries.push((
+                    Subgraph {
+                        id: id.clone(),
+                        label,
+                        nodes: Vec::new(),
+                        children: Vec::new(),
+                        order: idx,
+                    },
+                    builder.stack.last().copied(),
+                ));
+                builder.stack.push(idx);
+            }
+            "Rel" | "Rel_Back" | "BiRel" | "Rel_U" | "Rel_Up" | "Rel_D" | "Rel_Down" | "Rel_L"
+            | "Rel_Left" | "Rel_R" | "Rel_Right" => {
+                let to = arguments.get(1).filter(|to| !to.is_empty());
+                let (Some(from), Some(to)) = (alias, to) else {
+                    bail!("'{function}' needs a source and a target in '{line}'");
+                };
+                let (from, to) = if function == "Rel_Back" {
+                    (to.clone(), from.clone())
+                } else {
+                    (from.clone(), to.clone())
+                };
+                let label = match (argument(2), argument(3)) {
+                    (Some(label), Some(technology)) => Some(format!("{label} [{technology}]")),
+                    (label, None) => label,
+                    (None, Some(technology)) => Some(format!("[{technology}]")),
+                };
+                push_edge(
+                    &mut edges,
+                    Edge {
+                        from,
+                        to,
+                        label,
+                        kind: EdgeKind::Solid,
+                        arrow: if function == "BiRel" {
+                            EdgeArrowDirection::Both
+                        } else {
+                            EdgeArrowDirection::Forward
+                        },
+                        head: EdgeHead::Arrow,
+                        occurrence: 0,
+                    },
+                );
+            }
+            // Styling and layout hints have no equivalent here.
+            "UpdateElementStyle"
+            | "UpdateRelStyle"
+            | "UpdateBoundaryStyle"
+            | "UpdateLayoutConfig"
+            | "AddElementTag"
+            | "AddRelTag"
+            | "AddBoundaryTag" => {}
+            _ => bail!("unsupported C4 statement '{function}'"),
+        }
+    }
+
+    if let Some(&idx) = builder.stack.last() {
+        bail!(
+            "boundary '{}' is missing its closing '}}'",
+            builder.boundaries[idx].0.id
+        );
+    }
+    if builder.nodes.is_empty() {
+        bail!("C4 diagram does not declare any elements");
+    }
+    for edge in &edges {
+        for endpoint in [&edge.from, &edge.to] {
+            if !builder.nodes.contains_key(endpoint) {
+                bail!("relationship refers to unknown element '{endpoint}'");
+            }
+        }
+    }
+
+    let nodes = std::mem::take(&mut builder.nodes);
+    let order = std::mem::take(&mut builder.order);
+    let elements = std::mem::take(&mut builder.elements);
+    let node_membership = std::mem::take(&mut builder.node_membership);
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::C4(C4DiagramData {
+            title,
+            elements,
+            original_source: original_source.to_string(),
+        }),
+        direction: Direction::TopDown,
+        nodes,
+        order,
+        edges,
+        subgraphs: builder.into_subgraphs(),
+        node_membership,
+    })
+}
@@ -0,0 +1,587 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+const CLASS_ROW_HEIGHT: f32 = 18.0;
+const CLASS_SECTION_PADDING: f32 = 6.0;
+const CLASS_EMPTY_SECTION_HEIGHT: f32 = 12.0;
+const CLASS_HORIZONTAL_PADDING: f32 = 24.0;
+
+pub(crate) const CLASS_MARKER_DEFS: &str = r##"        <marker id="class-inheritance" markerWidth="20" markerHeight="20" refX="18" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M1,1 L18,9 L1,17 z" fill="#ffffff" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="class-composition" markerWidth="20" markerHeight="20" refX="19" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M1,9 L10,4 L19,9 L10,14 z" fill="context-stroke" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="class-aggregation" markerWidth="20" markerHeight="20" refX="19" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M1,9 L10,4 L19,9 L10,14 z" fill="#ffffff" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="class-arrow" markerWidth="20" markerHeight="20" refX="17" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M3,3 L17,9 L3,15" fill="none" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+"##;
+
+/// `classDiagram` sources are translated into the flowchart model so classes reuse the layered
+/// layout and editor overrides; the compartments and UML markers are drawn from this data.
+#[derive(Debug, Clone)]
+pub struct ClassDiagramData {
+    pub classes: HashMap<String, ClassBox>,
+    /// Relation markers keyed by [`edge_identifier`].
+    pub relations: HashMap<String, ClassRelation>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClassBox {
+    /// Stereotype such as `interface` from `<<interface>>`.
+    pub annotation: Option<String>,
+    pub attributes: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClassRelation {
+    pub start: Option<ClassMarker>,
+    pub end: Option<ClassMarker>,
+    pub start_cardinality: Option<String>,
+    pub end_cardinality: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassMarker {
+    Inheritance,
+    Composition,
+    Aggregation,
+    Arrow,
+}
+
+impl ClassMarker {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "<|" | "|>" => Some(Self::Inheritance),
+            "*" => Some(Self::Composition),
+            "o" => Some(Self::Aggregation),
+            "<" | ">" => Some(Self::Arrow),
+            _ => None,
+        }
+    }
+
+    fn marker_id(self) -> &'static str {
+        match self {
+            Self::Inheritance => "class-inheritance",
+            Self::Composition => "class-composition",
+            Self::Aggregation => "class-aggregation",
+            Self::Arrow => "class-arrow",
+        }
+    }
+}
+
+impl ClassRelation {
+    pub(crate) fn marker_attrs(&self) -> (String, String) {
+        let attr = |name: &str, marker: Option<ClassMarker>| {
+            marker
+                .map(|marker| format!(" {name}=\"url(#{})\"", marker.marker_id()))
+                .unwrap_or_default()
+        };
+        (
+            attr("marker-start", self.start),
+            attr("marker-end", self.end),
+        )
+    }
+}
+
+impl ClassBox {
+    fn header_lines(&self, name: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(annotation) = &self.annotation {
+            lines.push(format!("\u{ab}{annotation}\u{bb}"));
+        }
+        lines.push(name.to_string());
+        lines
+    }
+
+    fn section_height(rows: usize) -> f32 {
+        if rows == 0 {
+            CLASS_EMPTY_SECTION_HEIGHT
+        } else {
+            CLASS_ROW_HEIGHT * rows as f32 + CLASS_SECTION_PADDING * 2.0
+        }
+    }
+
+    fn dimensions(&self, name: &str) -> (f32, f32) {
+        let header = self.header_lines(name);
+        let max_chars = header
+            .iter()
+            .chain(&self.attributes)
+            .chain(&self.methods)
+            .map(|line| display_width(line))
+            .max()
+            .unwrap_or(1);
+        let width =
+            (NODE_TEXT_CHAR_WIDTH * max_chars as f32 + CLASS_HORIZONTAL_PADDING).max(NODE_WIDTH);
+        let height = Self::section_height(header.len())
+            + Self::section_height(self.attributes.len())
+            + Self::section_height(self.methods.len());
+        (width, height)
+    }
+}
+
+impl ClassDiagramData {
+    /// Draws the three-compartment box for a class node. `label` is the display name.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn render_class_box(
+        &self,
+        svg: &mut String,
+        id: &str,
+        label: &str,
+        position: Point,
+        width: f32,
+        height: f32,
+        fill: &str,
+        stroke: &str,
+        text: &str,
+    ) -> Result<bool> {
+        let Some(class) = self.classes.get(id) else {
+            return Ok(false);
+        };
+        let left = position.x - width / 2.0;
+        let top = position.y - height / 2.0;
+        writeln!(
+            svg,
+            "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />",
+            left,
+            top,
+            width,
+            height,
+            escape_xml(fill),
+            escape_xml(stroke)
+        )?;
+
+        let header = class.header_lines(label);
+        let mut y = top + CLASS_SECTION_PADDING + CLASS_ROW_HEIGHT / 2.0;
+        for (idx, line) in header.iter().enumerate() {
+            let weight = if idx + 1 == header.len() {
+                " font-weight=\"600\""
+            } else {
+                ""
+            };
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\"{} text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                position.x,
+                y,
+                escape_xml(text),
+                weight,
+                escape_xml(line)
+            )?;
+            y += CLASS_ROW_HEIGHT;
+        }
+
+        let mut divider = top + ClassBox::section_height(header.len());
+        for members in [&class.attributes, &class.methods] {
+            writeln!(
+                svg,
+                "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"1.5\" />",
+                left,
+                divider,
+                left + width,
+                divider,
+                escape_xml(stroke)
+            )?;
+            let mut y = divider + CLASS_SECTION_PADDING + CLASS_ROW_HEIGHT / 2.0;
+            for member in members {
+                let (member, style) = match strip_classifier(member, '$') {
+                    Some(member) => (member, " text-decoration=\"underline\""),
+                    None => match strip_classifier(member, '*') {
+                        Some(member) => (member, " font-style=\"italic\""),
+                        None => (member.clone(), ""),
+                    },
+                };
+                writeln!(
+                    svg,
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"13\"{} text-anchor=\"start\" dominant-baseline=\"middle\" xml:space=\"preserve\">{}</text>",
+                    left + CLASS_HORIZONTAL_PADDING / 2.0,
+                    y,
+                    escape_xml(text),
+                    style,
+                    escape_xml(&member)
+                )?;
+                y += CLASS_ROW_HEIGHT;
+            }
+            divider += ClassBox::section_height(members.len());
+        }
+        Ok(true)
+    }
+
+    /// Writes the `"1"`/`"*"` multiplicities next to each end of a relation.
+    pub(crate) fn render_cardinalities(
+        &self,
+        svg: &mut String,
+        edge_id: &str,
+        route: &[Point],
+        color: &str,
+    ) -> Result<()> {
+        let Some(relation) = self.relations.get(edge_id) else {
+            return Ok(());
+        };
+        if route.len() < 2 {
+            return Ok(());
+        }
+        let ends = [
+            (relation.start_cardinality.as_ref(), route[0], route[1]),
+            (
+                relation.end_cardinality.as_ref(),
+                route[route.len() - 1],
+                route[route.len() - 2],
+            ),
+        ];
+        for (label, end, towards) in ends {
+            let Some(label) = label else {
+                continue;
+            };
+            let dx = towards.x - end.x;
+            let dy = towards.y - end.y;
+            let length = (dx * dx + dy * dy).sqrt().max(f32::EPSILON);
+            let (ux, uy) = (dx / length, dy / length);
+            let x = end.x + ux * 20.0 - uy * 12.0;
+            let y = end.y + uy * 20.0 + ux * 12.0;
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                x,
+                y,
+                escape_xml(color),
+                escape_xml(label)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Mermaid marks static members with `$` and abstract ones with `*`, either at the end of the
+/// member or right after a method's parameter list (`count()$ int`).
+fn strip_classifier(member: &str, marker: char) -> Option<String> {
+    if let Some(stripped) = member.strip_suffix(marker) {
+        return Some(stripped.to_string());
+    }
+    let needle = format!("){marker}");
+    member
+        .find(&needle)
+        .map(|idx| format!("{}{}", &member[..=idx], &member[idx + needle.len()..]))
+}
+
+fn relation_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r#"^([\w~]+)\s*(?:"([^"]*)"\s*)?(<\||\*|o|<)?(--|\.\.)(\|>|\*|o\b|>)?\s*(?:"([^"]*)"\s*)?([\w~]+)\s*(?::\s*(.*))?$"#,
+        )
+        .expect("valid class relation regex")
+    })
+}
+
+/// Splits `Name~T~` into the id `Name` and the display name `Name<T>`.
+fn split_class_name(token: &str) -> Result<(String, String)> {
+    let token = token.trim();
+    let (id, generic) = match token.split_once('~') {
+        Some((id, rest)) => (id, Some(rest.trim_end_matches('~'))),
+        None => (token, None),
+    };
+    if id.is_empty() || !id.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        bail!("invalid class name '{token}'");
+    }
+    let display = match generic {
+        Some(generic) => format!("{id}<{}>", display_generics(generic)),
+        None => id.to_string(),
+    };
+    Ok((id.to_string(), display))
+}
+
+fn display_generics(text: &str) -> String {
+    // `List~int~` becomes `List<int>`; tildes alternate between opening and closing.
+    let mut open = true;
+    text.chars()
+        .map(|ch| {
+            if ch == '~' {
+                let mapped = if open { '<' } else { '>' };
+                open = !open;
+                mapped
+            } else {
+                ch
+            }
+        })
+        .collect()
+}
+
+struct ClassBuilder {
+    nodes: HashMap<String, Node>,
+    order: Vec<String>,
+    classes: HashMap<String, ClassBox>,
+    labels: HashMap<String, String>,
+    namespaces: Vec<Subgraph>,
+    namespace: Option<usize>,
+    node_membership: HashMap<String, Vec<String>>,
+}
+
+impl ClassBuilder {
+    fn class(&mut self, token: &str) -> Result<String> {
+        let (id, display) = split_class_name(token)?;
+        if !self.classes.contains_key(&id) {
+            self.classes.insert(id.clone(), ClassBox::default());
+            self.labels.insert(id.clone(), display);
+            self.order.push(id.clone());
+            if let Some(idx) = self.namespace {
+                let namespace = &mut self.namespaces[idx];
+                namespace.nodes.push(id.clone());
+                self.node_membership
+                    .insert(id.clone(), vec![namespace.id.clone()]);
+            }
+        } else if display != id {
+            self.labels.insert(id.clone(), display);
+        }
+        Ok(id)
+    }
+
+    fn add_member(&mut self, id: &str, member: &str) {
+        let member = display_generics(member.trim());
+        if member.is_empty() {
+            return;
+        }
+        if let Some(annotation) = member
+            .strip_prefix("<<")
+            .and_then(|rest| rest.strip_suffix(">>"))
+        {
+            self.set_annotation(id, annotation);
+            return;
+        }
+        let Some(class) = self.classes.get_mut(id) else {
+            return;
+        };
+        if member.contains('(') {
+            class.methods.push(member);
+        } else {
+            class.attributes.push(member);
+        }
+    }
+
+    fn set_annotation(&mut self, id: &str, annotation: &str) {
+        if let Some(class) = self.classes.get_mut(id) {
+            class.annotation = Some(annotation.trim().to_string());
+        }
+    }
+
+    fn finish_nodes(&mut self) {
+        for id in &self.order {
+            let class = &self.classes[id];
+            let label = self.labels[id].clone();
+            let (width, height) = class.dimensions(&label);
+            self.nodes.insert(
+                id.clone(),
+                Node {
+                    label,
+                    shape: NodeShape::Rectangle,
+                    image: None,
+                    width,
+                    height,
+                },
+            );
+        }
+    }
+}
+
+pub(crate) fn parse_class_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut builder = ClassBuilder {
+        nodes: HashMap::new(),
+        order: Vec::new(),
+        classes: HashMap::new(),
+        labels: HashMap::new(),
+        namespaces: Vec::new(),
+        namespace: None,
+        node_membership: HashMap::new(),
+    };
+    let mut edges = Vec::new();
+    let mut relations = HashMap::new();
+    let mut direction = Direction::TopDown;
+    let mut open_class: Option<String> = None;
+
+    for line in lines {
+        let line = line.trim();
+        if let Some(id) = &open_class {
+            if line == "}" {
+                open_class = None;
+            } else {
+                let id = id.clone();
+                builder.add_member(&id, line);
+            }
+            continue;
+        }
+
+        let lower = line.to_ascii_lowercase();
+        if [
+            "note ",
+            "note\"",
+            "classdef ",
+            "cssclass ",
+            "style ",
+            "click ",
+            "link ",
+            "callback ",
+        ]
+        .iter()
+        .any(|prefix| lower.starts_with(prefix))
+        {
+            continue;
+        }
+        if let Some(rest) = lower.strip_prefix("direction ") {
+            direction = parse_graph_header(&format!("graph {}", rest.trim()))?;
+            continue;
+        }
+        if line == "}" {
+            if builder.namespace.take().is_none() {
+                bail!("'}}' without a matching class or namespace");
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("namespace ") {
+            let Some(name) = rest.trim().strip_suffix('{') else {
+                bail!("namespace '{}' must open a block with '{{'", rest.trim());
+            };
+            if builder.namespace.is_some() {
+                bail!("nested namespaces are not supported");
+            }
+            let name = name.trim();
+            builder.namespace = Some(builder.namespaces.len());
+            builder.namespaces.push(Subgraph {
+                id: name.to_string(),
+                label: name.to_string(),
+                nodes: Vec::new(),
+                children: Vec::new(),
+                order: builder.namespaces.len(),
+            });
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("class ") {
+            let (rest, opens_body) = match rest.trim().strip_suffix('{') {
+                Some(rest) => (rest.trim(), true),
+                None => (rest.trim(), false),
+            };
+            let (rest, annotation) = match rest.split_once("<<") {
+                Some((rest, annotation)) => {
+                    (rest.trim(), Some(annotation.trim_end_matches(">>").trim()))
+                }
+                None => (rest, None),
+            };
+            let rest = rest.split(":::").next().unwrap_or_default();
+            let (name, label) = match rest.split_once('[') {
+                Some((name, label)) => (
+                    name,
+                    Some(decode_label(
+                        label.trim_end_matches(']').trim_matches(['"', '`']),
+                    )),
+                ),
+                None => (rest, None),
+            };
+            let id = builder.class(name)?;
+            if let Some(label) = label {
+                builder.labels.insert(id.clone(), label);
+            }
+            if let Some(annotation) = annotation {
+                builder.set_annotation(&id, annotation);
+            }
+            if opens_body {
+                open_class = Some(id);
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("<<") {
+            let Some((annotation, name)) = rest.split_once(">>") else {
+                bail!("annotation '{line}' is missing its closing '>>'");
+            };
+            let id = builder.class(name)?;
+            builder.set_annotation(&id, annotation);
+            continue;
+        }
+        if let Some(captures) = relation_regex().captures(line) {
+            let from = builder.class(&captures[1])?;
+            let to = builder.class(&captures[7])?;
+            let kind = if &captures[4] == ".." {
+                EdgeKind::Dashed
+            } else {
+                EdgeKind::Solid
+            };
+            let edge = Edge {
+                from,
+                to,
+                label: captures
+                    .get(8)
+                    .map(|label| decode_label(label.as_str().trim()))
+                    .filter(|label| !label.is_empty()),
+                kind,
+                arrow: EdgeArrowDirection::None,
+                head: EdgeHead::Arrow,
+                occurrence: 0,
+            };
+            let cardinality = |idx: usize| {
+                captures
+                    .get(idx)
+                    .map(|value| value.as_str().trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            relations.insert(
+                push_edge(&mut edges, edge),
+                ClassRelation {
+                    start: captures
+                        .get(3)
+                        .and_then(|token| ClassMarker::from_token(token.as_str())),
+                    end: captures
+                        .get(5)
+                        .and_then(|token| ClassMarker::from_token(token.as_str())),
+                    start_cardinality: cardinality(2),
+                    end_cardinality: cardinality(6),
+                },
+            );
+            continue;
+        }
+        if let Some((name, member)) = line.split_once(':') {
+            let id = builder.class(name)?;
+            builder.add_member(&id, member);
+            continue;
+        }
+        bail!("unrecognized class diagram statement '{line}'");
+    }
+
+    if let Some(id) = open_class {
+        bail!("class '{id}' is missing its closing '}}'");
+    }
+    if builder.namespace.is_some() {
+        bail!("namespace is missing its closing '}}'");
+    }
+    if builder.order.is_empty() {
+        bail!("class diagram does not declare any classes");
+    }
+
+    builder.finish_nodes();
+    let subgraphs = builder
+        .namespaces
+        .into_iter()
+        .filter(|namespace| !namespace.nodes.is_empty())
+        .collect();
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Class(ClassDiagramData {
+            classes: builder.classes,
+            relations,
+            original_source: original_source.to_string(),
+        }),
+        direction,
+        nodes: builder.nodes,
+        order: builder.order,
+        edges,
+        subgraphs,
+        node_membership: builder.node_membership,
+    })
+}
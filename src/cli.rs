@@ -1,16 +1,44 @@
 use anyhow::{Context, Result, anyhow, bail};
 use clap::{ArgAction, Parser, ValueEnum};
 use dialoguer::Select;
+use globset::GlobBuilder;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "server")]
 use oxdraw::serve::{ServeArgs, run_serve};
-use oxdraw::utils::split_source_and_overrides;
-use oxdraw::{Diagram, LayoutOverrides};
+use oxdraw::utils::{embed_source_metadata, extract_source_metadata, split_source_and_overrides};
+use oxdraw::{
+    DEFAULT_MINIMAP_SIZE, DefinitionFormat, Diagram, DiagramKind, EdgeGrouping, GridStyle,
+    LayoutEngine, LayoutOptions, LayoutOverrides, LintFinding, MinimapRender, Palette, ParseError,
+    PhaseTiming, Point, RasterFormat, RasterSize, RenderOptions, Severity, Theme, UiMessage,
+    Watermark, WatermarkCorner, dot_to_mermaid, is_dot_source, lint, minify_svg,
+    rasterize_svg_sized, status, to_d2, to_plantuml, zoom_svg,
+};
 
 const DEFAULT_NEW_DIAGRAM_NAME: &str = "diagram.mmd";
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+/// Raster scale used by `--scale` and by `oxdraw-exports.yaml` entries that omit `scale`.
+const DEFAULT_RASTER_SCALE: f32 = 10.0;
+/// How long `--watch` waits after a change for an editor to finish writing the file.
+const WATCH_SETTLE: Duration = Duration::from_millis(100);
+
+/// A failure that has already been written to stderr in the requested format; `main` exits
+/// non-zero without printing it again.
+#[derive(Debug)]
+pub struct AlreadyReported;
+
+impl std::fmt::Display for AlreadyReported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("the error was already reported")
+    }
+}
+
+impl std::error::Error for AlreadyReported {}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum InputSource {
@@ -24,17 +52,19 @@ enum OutputDestination {
     File(PathBuf),
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(
     name = "oxdraw",
     about = "Render simple diagrams directly to SVG without relying on Mermaid."
 )]
 pub struct RenderArgs {
-    /// Path to the input diagram file. Use '-' to read from stdin.
+    /// Path to the input diagram file. Use '-' to read from stdin. A directory or a glob such as
+    /// 'docs/**/*.mmd' renders every matching diagram.
     #[arg(short = 'i', long = "input")]
     input: Option<String>,
 
-    /// Path to the output file. Use '-' to write to stdout.
+    /// Path to the output file. Use '-' to write to stdout. With several inputs, the directory
+    /// to write them to, keeping their paths relative to the input.
     #[arg(short = 'o', long = "output")]
     output: Option<String>,
 
@@ -46,10 +76,31 @@ pub struct RenderArgs {
     #[arg(long = "png", action = ArgAction::SetTrue, conflicts_with = "output_format")]
     png: bool,
 
-    /// Scale factor when rasterizing PNG output.
-    #[arg(long = "scale", default_value_t = 10.0)]
+    /// Scale factor when rasterizing PNG, JPEG or WebP output.
+    #[arg(long = "scale", default_value_t = DEFAULT_RASTER_SCALE)]
     scale: f32,
 
+    /// Exact width in pixels of PNG, JPEG or WebP output; the height keeps the aspect ratio.
+    #[arg(long = "width", conflicts_with_all = ["scale", "height"])]
+    width: Option<u32>,
+
+    /// Exact height in pixels of PNG, JPEG or WebP output; the width keeps the aspect ratio.
+    #[arg(long = "height", conflicts_with = "scale")]
+    height: Option<u32>,
+
+    /// Pixels per inch of PNG, JPEG or WebP output (96 is one pixel per SVG unit), also
+    /// recorded in the PNG and JPEG file. Sizes the image unless --width or --height does.
+    #[arg(long = "dpi", conflicts_with = "scale")]
+    dpi: Option<f32>,
+
+    /// JPEG quality from 1 to 100; WebP output is always lossless.
+    #[arg(
+        long = "quality",
+        default_value_t = DEFAULT_JPEG_QUALITY,
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    quality: u8,
+
     /// Launch the interactive editor instead of rendering once.
     #[arg(
         long = "edit",
@@ -76,14 +127,179 @@ pub struct RenderArgs {
     #[arg(long = "serve-port")]
     serve_port: Option<u16>,
 
-    /// Background color for the rendered diagram (svg only at the moment).
-    #[arg(short = 'b', long = "background-color", default_value = "white")]
-    background_color: String,
+    /// Commit the diagram with git after every change saved from --edit or --new.
+    #[arg(long = "git-commit", action = ArgAction::SetTrue)]
+    git_commit: bool,
+
+    /// Commit message template for --git-commit; `{summary}`, `{change}` and `{file}` are substituted.
+    #[arg(long = "git-commit-message", requires = "git_commit")]
+    git_commit_message: Option<String>,
+
+    /// Group edges by source node or subgraph when --edit or --new rewrites the definition.
+    #[arg(long = "group-edges", value_enum)]
+    group_edges: Option<EdgeGrouping>,
+
+    /// Keep nodes in place when the source changes during --edit or --new, placing only new
+    /// nodes.
+    #[arg(long = "stable-layout", action = ArgAction::SetTrue)]
+    stable_layout: bool,
+
+    /// Background color for the rendered diagram; `transparent` leaves SVG and PNG pages
+    /// unpainted. Defaults to the theme's background.
+    #[arg(short = 'b', long = "background-color")]
+    background_color: Option<String>,
+
+    /// Built-in theme (default, dark, neutral, forest) or a .toml/.json theme file; overrides
+    /// the frontmatter `config.theme`.
+    #[arg(long = "theme")]
+    theme: Option<String>,
+
+    /// Built-in color set for node fills, pie slices and mindmap branches; overrides the
+    /// frontmatter `config.palette`.
+    #[arg(long = "palette", value_enum)]
+    palette: Option<Palette>,
+
+    /// Warn about node text that falls below the WCAG AA contrast ratio against its fill.
+    #[arg(long = "check-contrast", action = ArgAction::SetTrue)]
+    check_contrast: bool,
+
+    /// Render in black and white, telling node shapes apart with hatch patterns.
+    #[arg(long = "monochrome", action = ArgAction::SetTrue)]
+    monochrome: bool,
+
+    /// Merge edges converging on a busy node into a shared trunk; same as the frontmatter
+    /// `edgeBundling: true`.
+    #[arg(long = "bundle-edges", action = ArgAction::SetTrue)]
+    bundle_edges: bool,
+
+    /// CSS file embedded in a `<style>` block of the SVG; replaces the frontmatter `themeCSS`.
+    #[arg(long = "css")]
+    css: Option<PathBuf>,
+
+    /// URL of an external stylesheet the SVG imports, for theming diagrams on a docs site.
+    #[arg(long = "stylesheet")]
+    stylesheet: Option<String>,
+
+    /// Embed the label font in the SVG, cut down to the glyphs in use; same as the frontmatter
+    /// `embedFont: true`.
+    #[arg(long = "embed-font", action = ArgAction::SetTrue)]
+    embed_font: bool,
+
+    /// Animate dashes along every edge in SVG output; same as the frontmatter
+    /// `animateEdges: true`. Raster output stays still.
+    #[arg(long = "animate-edges", action = ArgAction::SetTrue)]
+    animate_edges: bool,
+
+    /// Shrink SVG output for embedding: strip whitespace and the XML prolog, round
+    /// coordinates to this many decimals (default 1) and move repeated styles into classes.
+    #[arg(long = "minify", num_args = 0..=1, default_missing_value = "1", value_name = "DECIMALS")]
+    minify: Option<usize>,
+
+    /// Font stack for labels, put ahead of the theme's; overrides the frontmatter `fontFamily`.
+    #[arg(long = "font-family")]
+    font_family: Option<String>,
+
+    /// TrueType or OpenType file to draw raster output with, for fonts that aren't installed.
+    /// Its family becomes the label font unless `--font-family` names another.
+    #[arg(long = "font-file")]
+    font_file: Option<PathBuf>,
+
+    /// Directory of `<name>.svg` icons for `fa:fa-<name>` in labels, used ahead of the
+    /// built-in set.
+    #[arg(long = "icon-dir")]
+    icon_dir: Option<PathBuf>,
+
+    /// Draw a faint grid behind the diagram: `dots` (the default) or `lines`. Overrides the
+    /// frontmatter `grid`.
+    #[arg(long = "grid", value_enum, num_args = 0..=1, default_missing_value = "dots")]
+    grid: Option<GridStyle>,
+
+    /// Small text stamped in a corner of the output, such as a classification label.
+    #[arg(long = "watermark")]
+    watermark: Option<String>,
+
+    /// PNG stamped in a corner of the output, before any `--watermark` text.
+    #[arg(long = "watermark-image")]
+    watermark_image: Option<PathBuf>,
+
+    /// Corner the watermark goes in.
+    #[arg(long = "watermark-corner", value_enum, default_value_t = WatermarkCorner::BottomRight)]
+    watermark_corner: WatermarkCorner,
+
+    /// Gap between neighbouring nodes in a layer; overrides the frontmatter `nodeSpacing`.
+    #[arg(long = "node-spacing")]
+    node_spacing: Option<f32>,
+
+    /// Gap between layers; overrides the frontmatter `rankSpacing`.
+    #[arg(long = "rank-spacing")]
+    rank_spacing: Option<f32>,
+
+    /// Blank border around the diagram; overrides the frontmatter `diagramPadding`.
+    #[arg(long = "margin")]
+    margin: Option<f32>,
+
+    /// Widest a flowchart node grows before its label wraps; overrides the frontmatter
+    /// `wrappingWidth`.
+    #[arg(long = "wrap-width")]
+    wrap_width: Option<f32>,
+
+    /// Widest a flowchart node label line is drawn before it ends in `…`; overrides the
+    /// frontmatter `maxLabelWidth`.
+    #[arg(long = "max-label-width")]
+    max_label_width: Option<f32>,
+
+    /// Layout engine; overrides the frontmatter `config.layout`.
+    #[arg(long = "layout", value_enum)]
+    layout: Option<LayoutEngine>,
+
+    /// Render a named view from the layout block, hiding its elements and applying its
+    /// theme and zoom.
+    #[arg(long = "view")]
+    view: Option<String>,
+
+    /// Also write a text-free thumbnail SVG of the whole diagram to this path.
+    #[arg(long = "minimap")]
+    minimap: Option<PathBuf>,
+
+    /// Length of the minimap's longer side in pixels.
+    #[arg(long = "minimap-size", default_value_t = DEFAULT_MINIMAP_SIZE)]
+    minimap_size: f32,
+
+    /// Visible region to outline on the minimap, in diagram coordinates.
+    #[arg(
+        long = "minimap-viewport",
+        value_name = "X,Y,WIDTH,HEIGHT",
+        value_delimiter = ',',
+        allow_negative_numbers = true
+    )]
+    minimap_viewport: Option<Vec<f32>>,
 
     /// Suppress informational output.
     #[arg(short = 'q', long = "quiet", action = ArgAction::SetTrue)]
     quiet: bool,
 
+    /// Report how long parsing, each layout phase and rendering took.
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        action = ArgAction::SetTrue,
+        conflicts_with = "quiet"
+    )]
+    verbose: bool,
+
+    /// How parse errors are reported; `json` prints a structured diagnostic to stderr.
+    #[arg(long = "error-format", value_enum, default_value_t = ErrorFormat::Human)]
+    error_format: ErrorFormat,
+
+    /// Re-render whenever the input file changes, printing the render time or the parse error.
+    #[arg(long = "watch", action = ArgAction::SetTrue)]
+    watch: bool,
+
+    /// How many diagrams to render at once for a directory or glob input (defaults to the
+    /// number of CPUs).
+    #[arg(short = 'j', long = "jobs", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
     /// Generate a code map from the given codebase path.
     #[arg(long = "code-map", conflicts_with = "input")]
     pub code_map: Option<String>,
@@ -150,6 +366,18 @@ pub struct RenderArgs {
 enum OutputFormat {
     Svg,
     Png,
+    #[value(alias = "jpg")]
+    Jpeg,
+    Webp,
+    #[value(name = "plantuml", alias = "puml")]
+    PlantUml,
+    D2,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+enum ErrorFormat {
+    Human,
+    Json,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
@@ -168,6 +396,10 @@ impl OutputFormat {
         {
             Some(ext) if ext == "svg" => Some(OutputFormat::Svg),
             Some(ext) if ext == "png" => Some(OutputFormat::Png),
+            Some(ext) if ext == "jpg" || ext == "jpeg" => Some(OutputFormat::Jpeg),
+            Some(ext) if ext == "webp" => Some(OutputFormat::Webp),
+            Some(ext) if ext == "puml" || ext == "plantuml" => Some(OutputFormat::PlantUml),
+            Some(ext) if ext == "d2" => Some(OutputFormat::D2),
             _ => None,
         }
     }
@@ -176,6 +408,29 @@ impl OutputFormat {
         match self {
             OutputFormat::Svg => "svg",
             OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::PlantUml => "puml",
+            OutputFormat::D2 => "d2",
+        }
+    }
+
+    /// The raster encoding, or `None` for SVG and the text formats.
+    fn raster(self, quality: u8) -> Option<RasterFormat> {
+        match self {
+            OutputFormat::Svg | OutputFormat::PlantUml | OutputFormat::D2 => None,
+            OutputFormat::Png => Some(RasterFormat::Png),
+            OutputFormat::Jpeg => Some(RasterFormat::Jpeg { quality }),
+            OutputFormat::Webp => Some(RasterFormat::Webp),
+        }
+    }
+
+    /// The diagram translated to another tool's language, or `None` for the drawn formats.
+    fn export(self, diagram: &Diagram) -> Option<Result<String>> {
+        match self {
+            OutputFormat::PlantUml => Some(to_plantuml(diagram)),
+            OutputFormat::D2 => Some(to_d2(diagram)),
+            _ => None,
         }
     }
 }
@@ -183,18 +438,23 @@ impl OutputFormat {
 #[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
 enum GraphType {
     Flowchart,
+    Journey,
 }
 
 impl GraphType {
     fn label(self) -> &'static str {
         match self {
             GraphType::Flowchart => "flowchart",
+            GraphType::Journey => "user journey",
         }
     }
 
     fn initial_contents(self) -> &'static str {
         match self {
             GraphType::Flowchart => "graph TD\nHello World!",
+            GraphType::Journey => {
+                "journey\n  title My working day\n  section Go to work\n    Make tea: 5: Me\n    Go upstairs: 3: Me\n    Do work: 1: Me, Cat\n  section Go home\n    Go downstairs: 5: Me\n    Sit down: 5: Me\n"
+            }
         }
     }
 }
@@ -325,8 +585,19 @@ pub async fn run_render_or_edit(cli: RenderArgs) -> Result<()> {
         }
     }
 
-    run_render(cli)?;
-    Ok(())
+    #[cfg(feature = "server")]
+    return run_blocking(move || run_render(cli)).await;
+    #[cfg(not(feature = "server"))]
+    run_render(cli)
+}
+
+/// Runs a synchronous command on tokio's blocking pool, so the blocking image downloads of
+/// rendering never occupy an async worker.
+#[cfg(feature = "server")]
+async fn run_blocking(command: impl FnOnce() -> Result<()> + Send + 'static) -> Result<()> {
+    tokio::task::spawn_blocking(command)
+        .await
+        .context("the command panicked")?
 }
 
 #[cfg(not(feature = "server"))]
@@ -350,6 +621,7 @@ async fn run_edit(cli: RenderArgs) -> Result<()> {
     let canonical_input = input_path
         .canonicalize()
         .with_context(|| format!("failed to canonicalize '{}'", input_path.display()))?;
+    let canonical_input = import_dot_for_editing(canonical_input)?;
 
     // Try to extract code mappings from the file content
     let content = fs::read_to_string(&canonical_input)?;
@@ -413,55 +685,52 @@ async fn run_edit(cli: RenderArgs) -> Result<()> {
     let port = cli.serve_port.unwrap_or(5151);
 
     let serve_args = ServeArgs {
-        input: canonical_input.clone(),
+        input: Some(canonical_input.clone()),
+        store: None,
         host: host.clone(),
         port,
         background_color: cli.background_color.clone(),
+        theme: cli.theme.clone(),
+        webhooks: Vec::new(),
+        webhook_secret: None,
+        git_commit: cli.git_commit,
+        git_commit_message: cli.git_commit_message.clone(),
+        group_edges: cli.group_edges.unwrap_or_default(),
+        stable_layout: cli.stable_layout,
+        untrusted_limits: false,
         code_map_root,
         code_map_mapping: mapping,
         code_map_warning: None,
     };
 
-    println!("Launching editor for {}", canonical_input.display());
-    println!("Loaded web UI from {}", ui_root.display());
-    println!(
-        "Visit http://{}:{} in your browser to begin editing",
-        host, port
-    );
+    status(UiMessage::LaunchingEditor(&canonical_input));
+    status(UiMessage::LoadedWebUi(&ui_root));
+    status(UiMessage::VisitToEdit { host: &host, port });
 
     run_serve(serve_args, Some(ui_root)).await
 }
 
 #[cfg(feature = "server")]
 async fn run_new(cli: RenderArgs) -> Result<()> {
-    let RenderArgs {
-        input,
-        output,
-        output_format,
-        png,
-        scale,
-        serve_host,
-        serve_port,
-        background_color,
-        quiet,
-        ..
-    } = cli;
-
-    if output.is_some() {
+    if cli.output.is_some() {
         bail!("--new does not support specifying an output file");
     }
 
-    if output_format.is_some() {
+    if cli.output_format.is_some() {
         bail!("--new does not support selecting an output format");
     }
 
-    if png {
+    if cli.png {
         bail!("--new does not support the --png flag");
     }
 
+    if let Some(flag) = render_only_flag(&cli) {
+        bail!("--new does not support {flag}, which only applies when rendering to a file");
+    }
+
     let graph_type = select_graph_type()?;
 
-    let mut target_path = match input {
+    let mut target_path = match cli.input.as_deref() {
         Some(path_str) => {
             if path_str == "-" {
                 bail!("--new requires a file path, not stdin");
@@ -516,42 +785,69 @@ async fn run_new(cli: RenderArgs) -> Result<()> {
         .and_then(|name| name.to_str())
         .unwrap_or(DEFAULT_NEW_DIAGRAM_NAME);
 
-    if !quiet {
-        println!("Creating Mermaid graph '{display_name}'.");
-        println!("Location: {}", canonical_path.display());
-        println!("Graph type: {}", graph_type.label());
+    if !cli.quiet {
+        status(UiMessage::CreatingGraph(display_name));
+        status(UiMessage::Location(&canonical_path));
+        status(UiMessage::GraphType(graph_type.label()));
     }
 
+    // Everything else on the command line configures the editor, as with --edit.
     let edit_args = RenderArgs {
         input: Some(canonical_path.to_string_lossy().into_owned()),
-        output: None,
-        output_format: None,
-        png: false,
-        scale,
         edit: true,
         new: false,
-        serve_host,
-        serve_port,
-        background_color,
-        quiet,
-        code_map: None,
-        api_key: None,
-        model: None,
-        api_url: None,
-        regen: false,
-        prompt: None,
-        no_ai: false,
-        max_nodes: 20,
-        gemini: None,
-        codedown: None,
-        augment_markdown: None,
-        repo_path: None,
-        codedown_style: None,
+        ..cli
     };
 
     run_edit(edit_args).await
 }
 
+/// The first flag in `cli` that only affects rendering to a file, which the editor opened by
+/// `--new` would silently drop.
+#[cfg(feature = "server")]
+fn render_only_flag(cli: &RenderArgs) -> Option<&'static str> {
+    [
+        (cli.scale != DEFAULT_RASTER_SCALE, "--scale"),
+        (cli.width.is_some(), "--width"),
+        (cli.height.is_some(), "--height"),
+        (cli.dpi.is_some(), "--dpi"),
+        (cli.quality != DEFAULT_JPEG_QUALITY, "--quality"),
+        (cli.palette.is_some(), "--palette"),
+        (cli.check_contrast, "--check-contrast"),
+        (cli.monochrome, "--monochrome"),
+        (cli.bundle_edges, "--bundle-edges"),
+        (cli.css.is_some(), "--css"),
+        (cli.stylesheet.is_some(), "--stylesheet"),
+        (cli.embed_font, "--embed-font"),
+        (cli.animate_edges, "--animate-edges"),
+        (cli.minify.is_some(), "--minify"),
+        (cli.font_family.is_some(), "--font-family"),
+        (cli.font_file.is_some(), "--font-file"),
+        (cli.icon_dir.is_some(), "--icon-dir"),
+        (cli.grid.is_some(), "--grid"),
+        (cli.watermark.is_some(), "--watermark"),
+        (cli.watermark_image.is_some(), "--watermark-image"),
+        (
+            cli.watermark_corner != WatermarkCorner::BottomRight,
+            "--watermark-corner",
+        ),
+        (cli.node_spacing.is_some(), "--node-spacing"),
+        (cli.rank_spacing.is_some(), "--rank-spacing"),
+        (cli.margin.is_some(), "--margin"),
+        (cli.wrap_width.is_some(), "--wrap-width"),
+        (cli.max_label_width.is_some(), "--max-label-width"),
+        (cli.layout.is_some(), "--layout"),
+        (cli.view.is_some(), "--view"),
+        (cli.minimap.is_some(), "--minimap"),
+        (cli.minimap_size != DEFAULT_MINIMAP_SIZE, "--minimap-size"),
+        (cli.minimap_viewport.is_some(), "--minimap-viewport"),
+        (cli.watch, "--watch"),
+        (cli.jobs.is_some(), "--jobs"),
+    ]
+    .into_iter()
+    .find_map(|(set, flag)| set.then_some(flag))
+}
+
 #[cfg(all(feature = "server", not(target_arch = "wasm32")))]
 async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
     let path = PathBuf::from(&code_map_path);
@@ -600,7 +896,7 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
                     }
                     if !warnings.is_empty() {
                         let msg = warnings.join("; ");
-                        println!("Warning: {}", msg);
+                        status(UiMessage::Warning(&msg));
                         warning = Some(msg);
                     }
                 }
@@ -612,10 +908,19 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
         let port = cli.serve_port.unwrap_or(5151);
 
         let serve_args = ServeArgs {
-            input: path.canonicalize()?,
+            input: Some(path.canonicalize()?),
+            store: None,
             host: host.clone(),
             port,
             background_color: cli.background_color,
+            theme: cli.theme,
+            webhooks: Vec::new(),
+            webhook_secret: None,
+            git_commit: false,
+            git_commit_message: None,
+            group_edges: EdgeGrouping::None,
+            stable_layout: false,
+            untrusted_limits: false,
             code_map_root: if let Some(path_str) = &metadata.path {
                 // Try to resolve the path again for the server state
                 let mut source_path = PathBuf::from(path_str);
@@ -636,8 +941,8 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
             code_map_warning: warning,
         };
 
-        println!("Launching code map viewer for existing map...");
-        println!("Visit http://{}:{} in your browser", host, port);
+        status(UiMessage::LaunchingCodeMapViewer { existing: true });
+        status(UiMessage::Visit { host: &host, port });
 
         return run_serve(serve_args, Some(ui_root)).await;
     }
@@ -682,38 +987,39 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
         }
 
         let output_path = PathBuf::from(&output_path_str);
-        let extension = output_path
-            .extension()
-            .and_then(|s| s.to_str())
-            .unwrap_or("")
-            .to_lowercase();
 
-        if extension == "svg" || extension == "png" {
+        if let Some(format) = OutputFormat::from_path(&output_path) {
             // Save .mmd as well
             let mut mmd_path = output_path.clone();
             mmd_path.set_extension("mmd");
 
             fs::write(&mmd_path, &full_content)?;
-            println!("Code map saved to {}", mmd_path.display());
+            status(UiMessage::CodeMapSaved(&mmd_path));
 
             // Render
             let diagram = Diagram::parse(&full_content)?;
-            let output_bytes = if extension == "png" {
-                if cli.scale <= 0.0 {
-                    bail!("--scale must be greater than zero for PNG output");
+            let background = diagram
+                .config
+                .page_background(cli.background_color.as_deref());
+            let output_bytes = match (format.export(&diagram), format.raster(cli.quality)) {
+                (Some(exported), _) => exported?.into_bytes(),
+                (None, Some(raster)) => {
+                    if cli.scale <= 0.0 {
+                        bail!(
+                            "--scale must be greater than zero for {} output",
+                            raster.name()
+                        );
+                    }
+                    diagram.render_raster(&background, None, cli.scale, raster)?
                 }
-                diagram.render_png(&cli.background_color, None, cli.scale)?
-            } else {
-                diagram
-                    .render_svg(&cli.background_color, None)?
-                    .into_bytes()
+                (None, None) => diagram.render_svg(&background, None)?.into_bytes(),
             };
 
             fs::write(&output_path, output_bytes)?;
-            println!("Rendered diagram saved to {}", output_path.display());
+            status(UiMessage::RenderedDiagramSaved(&output_path));
         } else {
             fs::write(&output_path, full_content)?;
-            println!("Code map saved to {}", output_path.display());
+            status(UiMessage::CodeMapSaved(&output_path));
         }
 
         return Ok(());
@@ -730,7 +1036,7 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
     } else {
         let diagram_path = ensure_unique_path(PathBuf::from("codemap.mmd"));
         fs::write(&diagram_path, &full_content)?;
-        println!("Saved codemap to {}", diagram_path.display());
+        status(UiMessage::CodeMapSaved(&diagram_path));
         diagram_path
     };
 
@@ -739,10 +1045,19 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
     let port = cli.serve_port.unwrap_or(5151);
 
     let serve_args = ServeArgs {
-        input: diagram_path,
+        input: Some(diagram_path),
+        store: None,
         host: host.clone(),
         port,
         background_color: cli.background_color,
+        theme: cli.theme,
+        webhooks: Vec::new(),
+        webhook_secret: None,
+        git_commit: false,
+        git_commit_message: None,
+        group_edges: EdgeGrouping::None,
+        stable_layout: false,
+        untrusted_limits: false,
         code_map_root: if root_path.is_file() {
             root_path.parent().map(|p| p.to_path_buf())
         } else {
@@ -752,8 +1067,8 @@ async fn run_code_map(cli: RenderArgs, code_map_path: String) -> Result<()> {
         code_map_warning: None,
     };
 
-    println!("Launching code map viewer...");
-    println!("Visit http://{}:{} in your browser", host, port);
+    status(UiMessage::LaunchingCodeMapViewer { existing: false });
+    status(UiMessage::Visit { host: &host, port });
 
     run_serve(serve_args, Some(ui_root)).await
 }
@@ -778,10 +1093,19 @@ async fn run_codedown(cli: RenderArgs, codedown_path: String) -> Result<()> {
             let port = cli.serve_port.unwrap_or(5151);
 
             let serve_args = ServeArgs {
-                input: path.canonicalize()?,
+                input: Some(path.canonicalize()?),
+                store: None,
                 host: host.clone(),
                 port,
                 background_color: cli.background_color,
+                theme: cli.theme,
+                webhooks: Vec::new(),
+                webhook_secret: None,
+                git_commit: false,
+                git_commit_message: None,
+                group_edges: EdgeGrouping::None,
+                stable_layout: false,
+                untrusted_limits: false,
                 code_map_root: if let Some(path_str) = &metadata.path {
                     let meta_path = PathBuf::from(path_str);
                     if meta_path.is_absolute() && meta_path.exists() {
@@ -811,8 +1135,8 @@ async fn run_codedown(cli: RenderArgs, codedown_path: String) -> Result<()> {
                 code_map_warning: None,
             };
 
-            println!("Launching codedown viewer for existing file...");
-            println!("Visit http://{}:{} in your browser", host, port);
+            status(UiMessage::LaunchingCodedownViewer { existing: true });
+            status(UiMessage::Visit { host: &host, port });
 
             return run_serve(serve_args, Some(ui_root)).await;
         }
@@ -864,24 +1188,33 @@ async fn run_codedown(cli: RenderArgs, codedown_path: String) -> Result<()> {
 
         let output_path = PathBuf::from(&output_path_str);
         fs::write(&output_path, full_content)?;
-        println!("Codedown saved to {}", output_path.display());
+        status(UiMessage::CodedownSaved(&output_path));
         return Ok(());
     }
 
     // Persist AI-generated codedowns by default so the user can reopen later without regenerating.
     let codedown_file_path = ensure_unique_path(PathBuf::from("codedown.md"));
     fs::write(&codedown_file_path, full_content)?;
-    println!("Codedown saved to {}", codedown_file_path.display());
+    status(UiMessage::CodedownSaved(&codedown_file_path));
 
     let ui_root = locate_ui_dist()?;
     let host = cli.serve_host.unwrap_or_else(|| "127.0.0.1".to_string());
     let port = cli.serve_port.unwrap_or(5151);
 
     let serve_args = ServeArgs {
-        input: codedown_file_path,
+        input: Some(codedown_file_path),
+        store: None,
         host: host.clone(),
         port,
         background_color: cli.background_color,
+        theme: cli.theme,
+        webhooks: Vec::new(),
+        webhook_secret: None,
+        git_commit: false,
+        git_commit_message: None,
+        group_edges: EdgeGrouping::None,
+        stable_layout: false,
+        untrusted_limits: false,
         code_map_root: if root_path.is_file() {
             root_path.parent().map(|p| p.to_path_buf())
         } else {
@@ -891,8 +1224,8 @@ async fn run_codedown(cli: RenderArgs, codedown_path: String) -> Result<()> {
         code_map_warning: None,
     };
 
-    println!("Launching codedown viewer...");
-    println!("Visit http://{}:{} in your browser", host, port);
+    status(UiMessage::LaunchingCodedownViewer { existing: false });
+    status(UiMessage::Visit { host: &host, port });
 
     run_serve(serve_args, Some(ui_root)).await
 }
@@ -951,7 +1284,7 @@ async fn run_augment_markdown(cli: RenderArgs, markdown_path: String) -> Result<
 
         let output_path = PathBuf::from(&output_path_str);
         fs::write(&output_path, full_content)?;
-        println!("Augmented markdown saved to {}", output_path.display());
+        status(UiMessage::AugmentedMarkdownSaved(&output_path));
     } else {
         // Default: save as <original>-mapped.md
         let mut output_path = markdown_file.clone();
@@ -962,16 +1295,310 @@ async fn run_augment_markdown(cli: RenderArgs, markdown_path: String) -> Result<
         output_path.set_file_name(format!("{}-mapped.md", stem));
 
         fs::write(&output_path, full_content)?;
-        println!("Augmented markdown saved to {}", output_path.display());
+        status(UiMessage::AugmentedMarkdownSaved(&output_path));
     }
 
     Ok(())
 }
 
 fn run_render(cli: RenderArgs) -> Result<()> {
+    if cli.input.as_deref().is_some_and(is_batch_input) {
+        return run_batch(cli);
+    }
+    if cli.watch {
+        return run_watch(cli);
+    }
+    if let Err(err) = render_once(&cli) {
+        if cli.error_format == ErrorFormat::Json
+            && let Some(parse_error) = err.downcast_ref::<ParseError>()
+        {
+            eprintln!("{}", serde_json::to_string(parse_error)?);
+            return Err(AlreadyReported.into());
+        }
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Whether `input` names several diagrams: a directory, or a path with glob wildcards that
+/// isn't an existing file, so `diagram[1].mmd` still renders on its own.
+fn is_batch_input(input: &str) -> bool {
+    let path = Path::new(input);
+    !path.is_file() && (path.is_dir() || input.contains(['*', '?', '[']))
+}
+
+/// Renders every diagram matched by a directory or glob input on `--jobs` worker threads,
+/// then reports how many succeeded. Failures don't stop the other renders.
+fn run_batch(cli: RenderArgs) -> Result<()> {
+    let input = cli.input.clone().unwrap_or_default();
+    if cli.watch {
+        bail!("--watch takes a single input file, not '{input}'");
+    }
+    if cli.minimap.is_some() {
+        bail!("--minimap takes a single input file, not '{input}'");
+    }
+    let out_dir = match cli.output.as_deref() {
+        Some("-") => bail!("several diagrams can't be written to stdout; pass an output directory"),
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            if dir.exists() && !dir.is_dir() {
+                bail!(
+                    "'{}' is not a directory; rendering several diagrams needs an output directory",
+                    dir.display()
+                );
+            }
+            Some(dir)
+        }
+        None => None,
+    };
+    let format = if cli.png {
+        OutputFormat::Png
+    } else {
+        cli.output_format.unwrap_or(OutputFormat::Svg)
+    };
+
+    let jobs: Vec<(PathBuf, PathBuf)> = batch_inputs(&input)?
+        .into_iter()
+        .map(|(source, relative)| {
+            let output = match &out_dir {
+                Some(dir) => dir.join(relative).with_extension(format.extension()),
+                None => {
+                    let mut name = source.file_name().unwrap_or_default().to_os_string();
+                    name.push(format!(".{}", format.extension()));
+                    source.with_file_name(name)
+                }
+            };
+            (source, output)
+        })
+        .collect();
+    let workers = cli
+        .jobs
+        .map(usize::from)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from))
+        .min(jobs.len());
+
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some((source, output)) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(err) = render_batch_item(&cli, source, output) {
+                        failures
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .push((source, err));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failures = failures
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    failures.sort_by_key(|(source, _)| *source);
+    for (source, err) in &failures {
+        eprintln!(
+            "\u{001b}[31m{}\u{001b}[0m {}: {err:#}",
+            UiMessage::Error,
+            source.display()
+        );
+    }
+    if !cli.quiet {
+        status(UiMessage::BatchRendered {
+            rendered: jobs.len() - failures.len(),
+            total: jobs.len(),
+        });
+    }
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} diagrams failed to render",
+            failures.len(),
+            jobs.len()
+        );
+    }
+    Ok(())
+}
+
+/// Renders one diagram of a batch to `output`, creating its directory first.
+fn render_batch_item(cli: &RenderArgs, source: &Path, output: &Path) -> Result<()> {
+    if let Some(parent) = output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create '{}'", parent.display()))?;
+    }
+    let mut item = cli.clone();
+    item.input = Some(source.to_string_lossy().into_owned());
+    item.output = Some(output.to_string_lossy().into_owned());
+    item.quiet = true;
+    item.verbose = false;
+    render_once(&item)
+}
+
+/// Diagram files matched by a directory (every `.mmd` and `.mermaid` file below it) or a glob,
+/// sorted and paired with their path relative to the directory, or to the glob's leading
+/// wildcard-free components. `*` and `?` stay within one path component; `**` spans several.
+fn batch_inputs(input: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let (root, pattern) = if Path::new(input).is_dir() {
+        (PathBuf::from(input), "**/*.{mmd,mermaid}".to_string())
+    } else {
+        let normalized = input.replace('\\', "/");
+        let parts: Vec<&str> = normalized.split('/').collect();
+        let literal = parts
+            .iter()
+            .position(|part| part.contains(['*', '?', '[']))
+            .unwrap_or(parts.len());
+        let root = match parts[..literal].join("/") {
+            root if !root.is_empty() => PathBuf::from(root),
+            _ if normalized.starts_with('/') => PathBuf::from("/"),
+            _ => PathBuf::from("."),
+        };
+        (root, parts[literal..].join("/"))
+    };
+    let matcher = GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("invalid glob '{input}'"))?
+        .compile_matcher();
+    if !root.is_dir() {
+        bail!("input directory '{}' does not exist", root.display());
+    }
+
+    let mut matches = Vec::new();
+    for entry in walkdir::WalkDir::new(&root) {
+        let entry = entry.with_context(|| format!("failed to read '{}'", root.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(&root) else {
+            continue;
+        };
+        if matcher.is_match(relative) {
+            matches.push((entry.path().to_path_buf(), relative.to_path_buf()));
+        }
+    }
+    if matches.is_empty() {
+        bail!("no diagrams match '{input}'");
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Renders the input, then renders it again each time it's saved until interrupted. Failed
+/// renders are reported and the watch carries on.
+fn run_watch(mut cli: RenderArgs) -> Result<()> {
+    use notify::{RecursiveMode, Watcher};
+
+    cli.watch = false;
+    cli.quiet = true;
+    let Some(input) = cli.input.as_deref().filter(|input| *input != "-") else {
+        bail!("--watch needs an input file");
+    };
+    if cli.output.as_deref() == Some("-") {
+        bail!("--watch can't write to stdout; pass an output file");
+    }
+    let input = PathBuf::from(input);
+    if !input.is_file() {
+        bail!("input file '{}' does not exist", input.display());
+    }
+    if input.file_name().is_none() {
+        bail!("input file '{}' has no file name", input.display());
+    }
+    // Watch the directory rather than the file: many editors save by renaming a temporary file
+    // over the original, which ends a watch on the file itself.
+    let dir = match input.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (sender, changes) = std::sync::mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).context("failed to start watching for file changes")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to watch '{}'", dir.display()))?;
+    status(UiMessage::Watching(&input));
+
+    render_watched(&cli, &input);
+    while let Ok(change) = changes.recv() {
+        match change {
+            Ok(event) => {
+                rerender_on_change(&cli, &input, &event, &changes);
+            }
+            Err(err) => status(UiMessage::Warning(&format!("file watch error: {err}"))),
+        }
+    }
+    Ok(())
+}
+
+/// Renders `input` again when `event` saved it, once the burst of events a single save produces
+/// has settled. Returns whether it rendered successfully; a failed render keeps the previous
+/// output.
+fn rerender_on_change(
+    cli: &RenderArgs,
+    input: &Path,
+    event: &notify::Event,
+    changes: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+) -> bool {
+    use notify::EventKind;
+
+    let saved = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == input.file_name());
+    if !saved {
+        return false;
+    }
+    std::thread::sleep(WATCH_SETTLE);
+    while changes.try_recv().is_ok() {}
+    input.is_file() && render_watched(cli, input)
+}
+
+/// One `--watch` render, reporting how long it took or why it failed.
+fn render_watched(cli: &RenderArgs, input: &Path) -> bool {
+    let started = Instant::now();
+    let Err(err) = render_once(cli) else {
+        status(UiMessage::WatchRendered {
+            path: input,
+            millis: started.elapsed().as_secs_f64() * 1000.0,
+        });
+        return true;
+    };
+    match err.downcast_ref::<ParseError>() {
+        Some(parse_error) if cli.error_format == ErrorFormat::Json => {
+            if let Ok(json) = serde_json::to_string(parse_error) {
+                eprintln!("{json}");
+            }
+        }
+        _ => eprintln!("\u{001b}[31m{}\u{001b}[0m {err:?}", UiMessage::Error),
+    }
+    false
+}
+
+fn render_once(cli: &RenderArgs) -> Result<()> {
     if cli.serve_host.is_some() || cli.serve_port.is_some() {
         bail!("--serve-host/--serve-port require --edit or --new");
     }
+    if cli.git_commit {
+        bail!("--git-commit requires --edit or --new");
+    }
+    if cli.stable_layout {
+        bail!("--stable-layout requires --edit or --new");
+    }
+    if cli.group_edges.is_some() {
+        bail!("--group-edges requires --edit or --new; use `oxdraw fmt` to reformat a file");
+    }
+    if let Some(viewport) = &cli.minimap_viewport {
+        if cli.minimap.is_none() {
+            bail!("--minimap-viewport requires --minimap");
+        }
+        if viewport.len() != 4 {
+            bail!("--minimap-viewport expects X,Y,WIDTH,HEIGHT");
+        }
+    }
 
     let input_source = parse_input(cli.input.as_deref())?;
     let format_preference = if cli.png {
@@ -983,35 +1610,181 @@ fn run_render(cli: RenderArgs) -> Result<()> {
     let output_dest = parse_output(cli.output.as_deref(), &input_source, format_preference)?;
     let format = determine_format(format_preference, &output_dest)?;
 
-    if format == OutputFormat::Png && cli.scale <= 0.0 {
-        bail!("--scale must be greater than zero for PNG output");
+    if let Some(raster) = format.raster(cli.quality)
+        && cli.scale <= 0.0
+    {
+        bail!(
+            "--scale must be greater than zero for {} output",
+            raster.name()
+        );
+    }
+    for (flag, value) in [
+        ("--node-spacing", cli.node_spacing),
+        ("--rank-spacing", cli.rank_spacing),
+        ("--margin", cli.margin),
+        ("--wrap-width", cli.wrap_width),
+        ("--max-label-width", cli.max_label_width),
+    ] {
+        if value.is_some_and(|value| !value.is_finite() || value < 0.0) {
+            bail!("{flag} must be a finite number that is not negative");
+        }
     }
 
     let definition_raw = load_definition(&input_source)?;
     let (definition_body, overrides) = match &input_source {
         InputSource::File(path) => read_definition_and_overrides(path)?,
+        InputSource::Stdin
+            if is_svg_document(&definition_raw) || is_dot_source(&definition_raw) =>
+        {
+            split_input(&definition_raw)?
+        }
         InputSource::Stdin => (definition_raw.clone(), LayoutOverrides::default()),
     };
 
-    let diagram = Diagram::parse(&definition_body)?;
+    let parse_started = Instant::now();
+    let mut diagram = Diagram::parse(&definition_body)?;
+    if let Some(exported) = format.export(&diagram) {
+        return write_output(output_dest, exported?.as_bytes(), cli.quiet);
+    }
+    let mut timings = vec![elapsed_timing("parse", parse_started)];
+    if let Some(theme) = &cli.theme {
+        diagram.config.custom_theme = Some(Theme::resolve(theme)?);
+    }
+    if let Some(palette) = cli.palette {
+        diagram.config.palette = palette;
+    }
+    if cli.monochrome {
+        diagram.config.monochrome = true;
+    }
+    if cli.bundle_edges {
+        diagram.config.edge_bundling = true;
+    }
+    if let Some(path) = &cli.css {
+        let css = fs::read_to_string(path)
+            .with_context(|| format!("failed to read stylesheet '{}'", path.display()))?;
+        diagram.config.theme_css = Some(css);
+    }
+    if let Some(href) = &cli.stylesheet {
+        diagram.config.stylesheet = Some(href.clone());
+    }
+    if cli.embed_font {
+        diagram.config.embed_font = true;
+    }
+    if cli.animate_edges {
+        diagram.config.animate_edges = true;
+    }
+    diagram.apply_render_options(&RenderOptions {
+        font_family: cli.font_family.clone(),
+        font_file: cli.font_file.clone(),
+        icon_dir: cli.icon_dir.clone(),
+        grid: cli.grid,
+        watermark: (cli.watermark.is_some() || cli.watermark_image.is_some()).then(|| Watermark {
+            text: cli.watermark.clone(),
+            image: cli.watermark_image.clone(),
+            corner: cli.watermark_corner,
+        }),
+    })?;
+    let image_base = match &input_source {
+        InputSource::File(path) => path.parent(),
+        InputSource::Stdin => None,
+    };
+    diagram.load_image_sources(image_base)?;
+    if let Some(raster) = format.raster(cli.quality)
+        && let Some(family) = diagram.missing_font_family()?
+    {
+        status(UiMessage::Warning(&format!(
+            "font '{family}' is not installed, so {} output falls back to another font; pass --font-file to use it",
+            raster.name()
+        )));
+    }
+    diagram.apply_layout_options(&LayoutOptions {
+        node_spacing: cli.node_spacing,
+        rank_spacing: cli.rank_spacing,
+        margin: cli.margin,
+        layout: cli.layout,
+        wrap_width: cli.wrap_width,
+        max_label_width: cli.max_label_width,
+    });
+    diagram.apply_collapsed(&overrides.collapsed);
+    let mut zoom = 1.0;
+    if let Some(name) = &cli.view {
+        let view = overrides.view(name)?;
+        diagram.apply_view(view);
+        zoom = view.zoom.unwrap_or(1.0);
+    }
     let override_ref = if overrides.is_empty() {
         None
     } else {
         Some(&overrides)
     };
 
-    let output_bytes = match format {
-        OutputFormat::Svg => diagram
-            .render_svg(&cli.background_color, override_ref)?
-            .into_bytes(),
-        OutputFormat::Png => diagram.render_png(&cli.background_color, override_ref, cli.scale)?,
+    if cli.check_contrast {
+        for warning in diagram.contrast_warnings(override_ref) {
+            status(UiMessage::LowContrast(&warning));
+        }
+    }
+
+    let background = diagram
+        .config
+        .page_background(cli.background_color.as_deref());
+    let (svg, render_timings) = diagram.render_svg_timed(&background, override_ref)?;
+    timings.extend(render_timings);
+    let output_bytes = match format.raster(cli.quality) {
+        None => {
+            let svg = if zoom == 1.0 {
+                svg
+            } else {
+                zoom_svg(&svg, zoom)?
+            };
+            let svg = embed_source_metadata(&svg, &definition_body, &overrides)?;
+            match cli.minify {
+                Some(decimals) => minify_svg(&svg, decimals),
+                None => svg,
+            }
+            .into_bytes()
+        }
+        Some(raster) => {
+            let rasterize_started = Instant::now();
+            let size = RasterSize {
+                scale: cli.scale * zoom,
+                width: cli.width,
+                height: cli.height,
+                dpi: cli.dpi,
+            };
+            let image = rasterize_svg_sized(&svg, &size, raster, &diagram.config.font_files)?;
+            timings.push(elapsed_timing("rasterize", rasterize_started));
+            image
+        }
     };
 
     write_output(output_dest, &output_bytes, cli.quiet)?;
+    if cli.verbose {
+        for timing in &timings {
+            status(UiMessage::PhaseTiming(timing));
+        }
+    }
+
+    if let Some(path) = &cli.minimap {
+        let viewport = cli.minimap_viewport.as_deref().unwrap_or_default();
+        let minimap = MinimapRender {
+            size: cli.minimap_size,
+            x: viewport.first().copied(),
+            y: viewport.get(1).copied(),
+            width: viewport.get(2).copied(),
+            height: viewport.get(3).copied(),
+        };
+        let svg = diagram.render_minimap_svg(&background, override_ref, &minimap)?;
+        write_output(
+            OutputDestination::File(path.clone()),
+            svg.as_bytes(),
+            cli.quiet,
+        )?;
+    }
 
     Ok(())
 }
 
+#[cfg(feature = "server")]
 pub async fn dispatch() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
     match args.get(1).map(|s| s.as_str()) {
@@ -1036,6 +1809,30 @@ pub async fn dispatch() -> Result<()> {
             );
             run_render_or_edit(render_args).await
         }
+        Some("sync-exports") => {
+            let sync_args = SyncExportsArgs::parse_from(
+                std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+            );
+            run_blocking(move || run_sync_exports(sync_args)).await
+        }
+        Some("add-node") => run_add_node(AddNodeArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("fmt") => run_fmt(FmtArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("lint") => run_lint(LintArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("diff") => run_diff(DiffArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("layout") => {
+            let layout_args = LayoutArgs::parse_from(
+                std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+            );
+            run_blocking(move || run_layout(layout_args)).await
+        }
         _ => {
             let render_args = RenderArgs::parse_from(args);
             run_render_or_edit(render_args).await
@@ -1053,6 +1850,24 @@ pub fn dispatch_sync() -> Result<()> {
             );
             run_render_or_edit_sync(render_args)
         }
+        Some("sync-exports") => run_sync_exports(SyncExportsArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("add-node") => run_add_node(AddNodeArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("fmt") => run_fmt(FmtArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("lint") => run_lint(LintArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("diff") => run_diff(DiffArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
+        Some("layout") => run_layout(LayoutArgs::parse_from(
+            std::iter::once(args[0].clone()).chain(args.iter().skip(2).cloned()),
+        )),
         _ => {
             let render_args = RenderArgs::parse_from(args);
             run_render_or_edit_sync(render_args)
@@ -1294,10 +2109,611 @@ fn load_definition(source: &InputSource) -> Result<String> {
     }
 }
 
+const DEFAULT_EXPORTS_CONFIG: &str = "oxdraw-exports.yaml";
+const EXPORTS_LOCK_FILE: &str = "oxdraw-exports.lock.json";
+
+/// Arguments for `oxdraw sync-exports`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw sync-exports",
+    about = "Re-render stale SVG/PNG exports declared in an exports config; exits non-zero when anything changed."
+)]
+pub struct SyncExportsArgs {
+    /// Path to the exports config.
+    #[arg(short = 'c', long = "config", default_value = DEFAULT_EXPORTS_CONFIG)]
+    config: PathBuf,
+
+    /// Only report stale exports without rewriting them.
+    #[arg(long = "check", action = ArgAction::SetTrue)]
+    check: bool,
+
+    /// Suppress informational output.
+    #[arg(short = 'q', long = "quiet", action = ArgAction::SetTrue)]
+    quiet: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ExportsConfig {
+    exports: Vec<ExportEntry>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ExportEntry {
+    source: PathBuf,
+    outputs: Vec<PathBuf>,
+    #[serde(default = "default_export_background")]
+    background: String,
+    #[serde(default = "default_export_scale")]
+    scale: f32,
+    /// JPEG quality; ignored by other formats.
+    #[serde(default = "default_export_quality")]
+    quality: u8,
+}
+
+fn default_export_background() -> String {
+    "white".to_string()
+}
+
+fn default_export_scale() -> f32 {
+    DEFAULT_RASTER_SCALE
+}
+
+fn default_export_quality() -> u8 {
+    DEFAULT_JPEG_QUALITY
+}
+
+fn run_sync_exports(args: SyncExportsArgs) -> Result<()> {
+    use sha2::{Digest, Sha256};
+    use std::collections::BTreeMap;
+
+    let config_text = fs::read_to_string(&args.config)
+        .with_context(|| format!("failed to read '{}'", args.config.display()))?;
+    let config: ExportsConfig = serde_yaml::from_str(&config_text)
+        .with_context(|| format!("invalid exports config '{}'", args.config.display()))?;
+    let root = args.config.parent().unwrap_or(Path::new("")).to_path_buf();
+    let lock_path = root.join(EXPORTS_LOCK_FILE);
+    // Output path (relative to the config) -> hash of the source and render options.
+    let mut lock: BTreeMap<String, String> = match fs::read_to_string(&lock_path) {
+        Ok(text) => serde_json::from_str(&text)
+            .with_context(|| format!("invalid lock file '{}'", lock_path.display()))?,
+        Err(_) => BTreeMap::new(),
+    };
+
+    let mut stale = Vec::new();
+    for entry in &config.exports {
+        let source_path = root.join(&entry.source);
+        let source = fs::read_to_string(&source_path)
+            .with_context(|| format!("failed to read '{}'", source_path.display()))?;
+        let source_modified = fs::metadata(&source_path).and_then(|m| m.modified()).ok();
+
+        for output in &entry.outputs {
+            let output_path = root.join(output);
+            let format = OutputFormat::from_path(output).ok_or_else(|| {
+                anyhow!(
+                    "unable to determine export format from '{}'",
+                    output.display()
+                )
+            })?;
+            let mut hasher = Sha256::new();
+            hasher.update(source.as_bytes());
+            hasher.update(format!(
+                "\0{}\0{}\0{}",
+                format.extension(),
+                entry.background,
+                entry.scale
+            ));
+            if format == OutputFormat::Jpeg {
+                hasher.update(format!("\0{}", entry.quality));
+            }
+            let hash: String = hasher
+                .finalize()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+
+            let key = output.to_string_lossy().replace('\\', "/");
+            let output_modified = fs::metadata(&output_path).and_then(|m| m.modified()).ok();
+            let reason = match (output_modified, source_modified) {
+                (None, _) => Some("missing"),
+                _ if lock.get(&key) != Some(&hash) => Some("source or options changed"),
+                (Some(output_time), Some(source_time)) if source_time > output_time => {
+                    Some("source is newer")
+                }
+                _ => None,
+            };
+            let Some(reason) = reason else {
+                continue;
+            };
+
+            if !args.check {
+                let (definition, overrides) = split_source_and_overrides(&source)?;
+                let mut diagram = Diagram::parse(&definition)
+                    .with_context(|| format!("failed to parse '{}'", source_path.display()))?;
+                diagram.load_image_sources(source_path.parent())?;
+                let override_ref = if overrides.is_empty() {
+                    None
+                } else {
+                    Some(&overrides)
+                };
+                let bytes = match (format.export(&diagram), format.raster(entry.quality)) {
+                    (Some(exported), _) => exported?.into_bytes(),
+                    (None, None) => embed_source_metadata(
+                        &diagram.render_svg(&entry.background, override_ref)?,
+                        &definition,
+                        &overrides,
+                    )?
+                    .into_bytes(),
+                    (None, Some(raster)) => diagram.render_raster(
+                        &entry.background,
+                        override_ref,
+                        entry.scale,
+                        raster,
+                    )?,
+                };
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&output_path, bytes)
+                    .with_context(|| format!("failed to write '{}'", output_path.display()))?;
+                lock.insert(key, hash);
+            }
+            stale.push((output_path, reason));
+        }
+    }
+
+    if stale.is_empty() {
+        if !args.quiet {
+            status(UiMessage::ExportsUpToDate);
+        }
+        return Ok(());
+    }
+
+    if !args.check {
+        let mut lock_text = serde_json::to_string_pretty(&lock)?;
+        lock_text.push('\n');
+        fs::write(&lock_path, lock_text)
+            .with_context(|| format!("failed to write '{}'", lock_path.display()))?;
+    }
+    if !args.quiet {
+        for (path, reason) in &stale {
+            status(if args.check {
+                UiMessage::ExportStale { path, reason }
+            } else {
+                UiMessage::ExportRegenerated { path, reason }
+            });
+        }
+    }
+    bail!(
+        "{} export(s) {}",
+        stale.len(),
+        if args.check {
+            "are out of date; run `oxdraw sync-exports`"
+        } else {
+            "were regenerated; review and stage the updated files"
+        }
+    )
+}
+
+/// Arguments for `oxdraw add-node`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw add-node",
+    about = "Append a node with a generated id (n1, n2, ...) to a flowchart and print the id."
+)]
+pub struct AddNodeArgs {
+    /// Path to the diagram to edit in place.
+    #[arg(short = 'i', long = "input")]
+    input: PathBuf,
+
+    /// Node label; defaults to the generated id.
+    #[arg(short = 'l', long = "label", default_value = "")]
+    label: String,
+
+    /// Existing node to connect the new node from.
+    #[arg(long = "from")]
+    from: Option<String>,
+
+    /// Only print the id the next node would get without editing the file.
+    #[arg(long = "dry-run", action = ArgAction::SetTrue)]
+    dry_run: bool,
+}
+
+fn run_add_node(args: AddNodeArgs) -> Result<()> {
+    let contents = fs::read_to_string(&args.input)
+        .with_context(|| format!("failed to read '{}'", args.input.display()))?;
+    let (definition, _) = split_source_and_overrides(&contents)?;
+    let mut diagram = Diagram::parse(&definition)?;
+    if args.dry_run {
+        println!("{}", diagram.next_node_id());
+        return Ok(());
+    }
+
+    let (id, _) = diagram.quick_add_node(&args.label, args.from.as_deref())?;
+    let updated = with_layout_block(diagram.to_definition(), &contents);
+    fs::write(&args.input, updated)
+        .with_context(|| format!("failed to write '{}'", args.input.display()))?;
+    println!("{id}");
+    Ok(())
+}
+
+/// Re-attaches the saved layout block from `contents` to a regenerated definition byte-for-byte.
+fn with_layout_block(mut definition: String, contents: &str) -> String {
+    if let Some(start) = contents.find(oxdraw::LAYOUT_BLOCK_START) {
+        definition.push('\n');
+        definition.push_str(&contents[start..]);
+    }
+    definition
+}
+
+/// Arguments for `oxdraw fmt`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw fmt",
+    about = "Rewrite flowchart files in canonical form; exits non-zero with --check when any would change."
+)]
+pub struct FmtArgs {
+    /// Diagram files to format in place.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// How to group edges in the rewritten definition.
+    #[arg(long = "group-edges", value_enum, default_value_t)]
+    group_edges: EdgeGrouping,
+
+    /// Only report files that are not formatted.
+    #[arg(long = "check", action = ArgAction::SetTrue)]
+    check: bool,
+}
+
+fn run_fmt(args: FmtArgs) -> Result<()> {
+    let format = DefinitionFormat {
+        edge_grouping: args.group_edges,
+    };
+    let mut unformatted = Vec::new();
+    for path in &args.inputs {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let (definition, _) = split_source_and_overrides(&contents)?;
+        let diagram = Diagram::parse(&definition)
+            .with_context(|| format!("failed to parse '{}'", path.display()))?;
+        let formatted = with_layout_block(diagram.to_definition_with(&format), &contents);
+        if formatted == contents {
+            continue;
+        }
+        if !args.check {
+            fs::write(path, &formatted)
+                .with_context(|| format!("failed to write '{}'", path.display()))?;
+        }
+        println!("{}", path.display());
+        unformatted.push(path);
+    }
+
+    if args.check && !unformatted.is_empty() {
+        bail!("{} file(s) are not formatted", unformatted.len());
+    }
+    Ok(())
+}
+
+/// Arguments for `oxdraw lint`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw lint",
+    about = "Report problems in diagram files; exits non-zero when any reaches --fail-on."
+)]
+pub struct LintArgs {
+    /// Diagram files to check.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// `json` prints every finding as one JSON array on stdout.
+    #[arg(long = "format", value_enum, default_value_t = ErrorFormat::Human)]
+    format: ErrorFormat,
+
+    /// Lowest severity that makes the command fail.
+    #[arg(long = "fail-on", value_enum, default_value_t = Severity::Error)]
+    fail_on: Severity,
+}
+
+/// A [`LintFinding`] with the file it was found in, as printed by `oxdraw lint --format json`.
+#[derive(serde::Serialize)]
+struct FileLintFinding<'a> {
+    path: &'a Path,
+    #[serde(flatten)]
+    finding: &'a LintFinding,
+}
+
+fn run_lint(args: LintArgs) -> Result<()> {
+    let mut findings = Vec::new();
+    for path in &args.inputs {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        let (definition, _) = split_input(&contents)?;
+        let found =
+            lint(&definition).with_context(|| format!("failed to lint '{}'", path.display()))?;
+        findings.extend(found.into_iter().map(|finding| (path.as_path(), finding)));
+    }
+
+    match args.format {
+        ErrorFormat::Json => {
+            let report: Vec<_> = findings
+                .iter()
+                .map(|(path, finding)| FileLintFinding { path, finding })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        ErrorFormat::Human => {
+            for (path, finding) in &findings {
+                let location = match (finding.line, finding.column) {
+                    (Some(line), Some(column)) => format!(":{line}:{column}"),
+                    (Some(line), None) => format!(":{line}"),
+                    _ => String::new(),
+                };
+                println!(
+                    "{}{location}: {}[{}]: {}",
+                    path.display(),
+                    finding.severity.name(),
+                    finding.rule.name(),
+                    finding.message
+                );
+            }
+        }
+    }
+
+    let failing = findings
+        .iter()
+        .filter(|(_, finding)| finding.severity >= args.fail_on)
+        .count();
+    if failing > 0 {
+        bail!(
+            "{failing} problem(s) at {} severity or above",
+            args.fail_on.name()
+        );
+    }
+    Ok(())
+}
+
+/// Arguments for `oxdraw diff`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw diff",
+    about = "Compare two versions of a diagram by its nodes and edges rather than its lines."
+)]
+pub struct DiffArgs {
+    /// The old version, or the only file when comparing git revisions.
+    old: PathBuf,
+
+    /// The new version; defaults to OLD, read at --to or from the working tree.
+    new: Option<PathBuf>,
+
+    /// Read the old version from this git revision.
+    #[arg(long = "from")]
+    from: Option<String>,
+
+    /// Read the new version from this git revision instead of the working tree.
+    #[arg(long = "to", requires = "from")]
+    to: Option<String>,
+
+    /// Also write an SVG of the new version with additions in green and removals in red.
+    #[arg(long = "svg")]
+    svg: Option<PathBuf>,
+
+    /// Print the changes as one JSON object instead of one line per change.
+    #[arg(long = "json")]
+    json: bool,
+}
+
+fn run_diff(args: DiffArgs) -> Result<()> {
+    if args.new.is_none() && args.from.is_none() {
+        bail!("pass a second file to compare with, or --from <REV>");
+    }
+    let new_path = args.new.as_deref().unwrap_or(&args.old);
+    let read_version = |path: &Path, revision: Option<&str>| -> Result<Diagram> {
+        let contents = match revision {
+            Some(revision) => read_git_revision(path, revision)?,
+            None => fs::read_to_string(path)
+                .with_context(|| format!("failed to read '{}'", path.display()))?,
+        };
+        let (definition, _) = split_input(&contents)?;
+        Diagram::parse(&definition).with_context(|| match revision {
+            Some(revision) => format!("failed to parse '{}' at {revision}", path.display()),
+            None => format!("failed to parse '{}'", path.display()),
+        })
+    };
+    let old = read_version(&args.old, args.from.as_deref())?;
+    let new = read_version(new_path, args.to.as_deref())?;
+    let diff = old.diff(&new);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        for (kind, changes, renames) in [
+            ("node", &diff.nodes, diff.renamed_nodes.as_slice()),
+            ("edge", &diff.edges, diff.renamed_edges.as_slice()),
+            ("subgraph", &diff.subgraphs, &[]),
+        ] {
+            for id in &changes.added {
+                println!("+ {kind} {id}");
+            }
+            for id in &changes.removed {
+                println!("- {kind} {id}");
+            }
+            for id in &changes.modified {
+                println!("~ {kind} {id}");
+            }
+            for rename in renames {
+                println!("~ {kind} {} renamed to {}", rename.from, rename.to);
+            }
+        }
+    }
+
+    if let Some(svg_path) = &args.svg {
+        if !matches!(new.kind, DiagramKind::Flowchart) {
+            bail!("--svg only highlights changes in flowcharts");
+        }
+        let (merged, overrides) = old.diff_overlay(&new, &diff);
+        let background = merged.config.page_background(None);
+        let svg = merged.render_svg(&background, Some(&overrides))?;
+        fs::write(svg_path, svg)
+            .with_context(|| format!("failed to write '{}'", svg_path.display()))?;
+        status(UiMessage::GeneratedDiagram(svg_path));
+    }
+    Ok(())
+}
+
+/// Contents of `path` as committed at `revision`, read with `git show` from the file's
+/// directory.
+fn read_git_revision(path: &Path, revision: &str) -> Result<String> {
+    let Some(name) = path.file_name() else {
+        bail!("'{}' is not a file", path.display());
+    };
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    // A leading '-' would make git read the revision as an option such as `--output`.
+    if revision.starts_with('-') {
+        bail!("'{revision}' is not a git revision");
+    }
+    let output = std::process::Command::new("git")
+        .arg("show")
+        .arg("--end-of-options")
+        .arg(format!("{revision}:./{}", name.to_string_lossy()))
+        .current_dir(dir)
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "failed to read '{}' at {revision}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("'{}' at {revision} is not UTF-8", path.display()))
+}
+
+/// Arguments for `oxdraw layout`.
+#[derive(Debug, Parser)]
+#[command(
+    name = "oxdraw layout",
+    about = "Lay a diagram out and print where its nodes, edges and subgraphs land in the SVG."
+)]
+pub struct LayoutArgs {
+    /// Diagram file to lay out; '-' reads stdin.
+    input: String,
+
+    /// Print the geometry as one JSON object instead of one line per element.
+    #[arg(long = "json")]
+    json: bool,
+}
+
+fn run_layout(args: LayoutArgs) -> Result<()> {
+    let input_source = parse_input(Some(&args.input))?;
+    let (definition, overrides) = split_input(&load_definition(&input_source)?)?;
+    let mut diagram = Diagram::parse(&definition)?;
+    let image_base = match &input_source {
+        InputSource::File(path) => path.parent(),
+        InputSource::Stdin => None,
+    };
+    diagram.load_image_sources(image_base)?;
+    diagram.apply_collapsed(&overrides.collapsed);
+    let override_ref = if overrides.is_empty() {
+        None
+    } else {
+        Some(&overrides)
+    };
+    let geometry = diagram.layout_geometry(override_ref)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&geometry)?);
+        return Ok(());
+    }
+    let points = |points: &[Point]| {
+        points
+            .iter()
+            .map(|point| format!("{:.1},{:.1}", point.x, point.y))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    println!(
+        "canvas {:.1}x{:.1}",
+        geometry.canvas.width, geometry.canvas.height
+    );
+    for node in &geometry.nodes {
+        println!(
+            "node {} {:.1},{:.1} {:.1}x{:.1}",
+            node.id, node.x, node.y, node.width, node.height
+        );
+    }
+    for edge in &geometry.edges {
+        println!("edge {} {}", edge.id, points(&edge.points));
+    }
+    for subgraph in &geometry.subgraphs {
+        println!(
+            "subgraph {} {:.1},{:.1} {:.1}x{:.1}",
+            subgraph.id, subgraph.x, subgraph.y, subgraph.width, subgraph.height
+        );
+    }
+    Ok(())
+}
+
 fn read_definition_and_overrides(path: &Path) -> Result<(String, LayoutOverrides)> {
     let contents =
         fs::read_to_string(path).with_context(|| format!("failed to read '{}'", path.display()))?;
-    split_source_and_overrides(&contents)
+    split_input(&contents)
+}
+
+/// Splits a definition file, recovers the definition from an SVG that oxdraw exported, or
+/// converts a Graphviz DOT graph.
+fn split_input(contents: &str) -> Result<(String, LayoutOverrides)> {
+    if is_svg_document(contents) {
+        return extract_source_metadata(contents)?
+            .ok_or_else(|| anyhow!("the SVG input has no embedded oxdraw source to re-render"));
+    }
+    if is_dot_source(contents) {
+        return Ok((dot_to_mermaid(contents)?, LayoutOverrides::default()));
+    }
+    split_source_and_overrides(contents)
+}
+
+/// The editor saves Mermaid, so a DOT input is converted to a `.mmd` file beside it, which is
+/// edited instead.
+#[cfg(feature = "server")]
+fn import_dot_for_editing(path: PathBuf) -> Result<PathBuf> {
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read '{}'", path.display()))?;
+    if !is_dot_source(&contents) {
+        return Ok(path);
+    }
+    let target = path.with_extension("mmd");
+    if target.exists() {
+        bail!(
+            "'{}' already exists; edit it instead of re-importing '{}'",
+            target.display(),
+            path.display()
+        );
+    }
+    let diagram = oxdraw::parse_dot(&contents)
+        .with_context(|| format!("failed to import '{}'", path.display()))?;
+    fs::write(&target, diagram.to_definition())
+        .with_context(|| format!("failed to write '{}'", target.display()))?;
+    status(UiMessage::ImportedDot {
+        from: &path,
+        to: &target,
+    });
+    Ok(target)
+}
+
+fn is_svg_document(contents: &str) -> bool {
+    let head = contents.trim_start();
+    head.starts_with("<?xml") || head.starts_with("<svg")
+}
+
+fn elapsed_timing(phase: &'static str, started: Instant) -> PhaseTiming {
+    PhaseTiming {
+        phase,
+        millis: started.elapsed().as_secs_f64() * 1000.0,
+    }
 }
 
 fn write_output(dest: OutputDestination, bytes: &[u8], quiet: bool) -> Result<()> {
@@ -1310,7 +2726,7 @@ fn write_output(dest: OutputDestination, bytes: &[u8], quiet: bool) -> Result<()
         OutputDestination::File(path) => {
             fs::write(&path, bytes)?;
             if !quiet {
-                println!("Generated diagram -> {}", path.display());
+                status(UiMessage::GeneratedDiagram(&path));
             }
         }
     }
@@ -1322,3 +2738,100 @@ fn markdown_has_oxdraw_mappings(path: &Path) -> bool {
         .map(|source| source.contains("<!-- OXDRAW MAPPING"))
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, EventKind, ModifyKind};
+    use std::sync::mpsc;
+
+    fn watch_args(input: &Path, output: &Path) -> RenderArgs {
+        let mut cli = RenderArgs::parse_from([
+            "oxdraw",
+            "--input",
+            input.to_str().unwrap(),
+            "--output",
+            output.to_str().unwrap(),
+        ]);
+        cli.quiet = true;
+        cli
+    }
+
+    fn saved(path: &Path) -> notify::Event {
+        notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.to_path_buf())
+    }
+
+    #[test]
+    fn git_revisions_cannot_pass_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("flow.mmd");
+        fs::write(&input, "graph TD\n  A --> B\n").unwrap();
+        let leaked = dir.path().join("leaked.txt");
+
+        let err = read_git_revision(&input, &format!("--output={}", leaked.display()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("is not a git revision"), "{err}");
+        assert!(!leaked.exists());
+
+        // Outside a repository git itself refuses, and the error names the revision.
+        let err = read_git_revision(&input, "HEAD~1").unwrap_err().to_string();
+        assert!(err.contains("at HEAD~1"), "{err}");
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_spacing() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("flow.mmd");
+        let output = dir.path().join("flow.svg");
+        fs::write(&input, "graph TD\n  A --> B\n").unwrap();
+
+        for (flag, value) in [
+            ("--margin", "-5"),
+            ("--node-spacing", "NaN"),
+            ("--rank-spacing", "inf"),
+        ] {
+            let mut cli = watch_args(&input, &output);
+            cli.update_from(["oxdraw".to_string(), format!("{flag}={value}")]);
+            let err = render_once(&cli).unwrap_err().to_string();
+            assert!(err.contains(flag), "{err}");
+            assert!(!output.exists());
+        }
+
+        let mut cli = watch_args(&input, &output);
+        cli.update_from(["oxdraw", "--rank-spacing=0", "--margin=0"]);
+        render_once(&cli).unwrap();
+    }
+
+    #[test]
+    fn watch_rerenders_saved_input_and_keeps_output_on_parse_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("flow.mmd");
+        let output = dir.path().join("flow.svg");
+        fs::write(&input, "graph TD\n  Alpha --> Beta\n").unwrap();
+        let cli = watch_args(&input, &output);
+        let (sender, changes) = mpsc::channel();
+
+        assert!(render_watched(&cli, &input));
+        assert!(fs::read_to_string(&output).unwrap().contains("Beta"));
+
+        // A queued duplicate event from the same save is drained rather than rendered twice.
+        fs::write(&input, "graph TD\n  Alpha --> Gamma\n").unwrap();
+        sender.send(Ok(saved(&input))).unwrap();
+        assert!(rerender_on_change(&cli, &input, &saved(&input), &changes));
+        assert!(changes.try_recv().is_err());
+        let rendered = fs::read_to_string(&output).unwrap();
+        assert!(rendered.contains("Gamma"));
+
+        // Changes to other files in the watched directory are ignored.
+        let other = dir.path().join("notes.txt");
+        fs::write(&other, "unrelated").unwrap();
+        let created = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(other);
+        assert!(!rerender_on_change(&cli, &input, &created, &changes));
+
+        // A save that no longer parses leaves the last good render in place.
+        fs::write(&input, "graph TD\n  Alpha -->|unclosed\n").unwrap();
+        assert!(!rerender_on_change(&cli, &input, &saved(&input), &changes));
+        assert_eq!(fs::read_to_string(&output).unwrap(), rendered);
+    }
+}
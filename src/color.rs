@@ -0,0 +1,341 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::NodeShape;
+
+/// Minimum WCAG 2 AA contrast ratio for normal-sized text.
+pub const WCAG_AA_CONTRAST: f32 = 4.5;
+const DARK_TEXT: &str = "#1a202c";
+const LIGHT_TEXT: &str = "#ffffff";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Parses `#rgb`, `#rrggbb`, `rgb(r, g, b)` and a few common color names.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        if let Some(hex) = value.strip_prefix('#') {
+            let digits: Vec<u8> = hex
+                .chars()
+                .map(|ch| ch.to_digit(16).map(|digit| digit as u8))
+                .collect::<Option<_>>()?;
+            return match digits.as_slice() {
+                [r, g, b] => Some(Rgb {
+                    r: r * 17,
+                    g: g * 17,
+                    b: b * 17,
+                }),
+                [r1, r2, g1, g2, b1, b2] => Some(Rgb {
+                    r: r1 * 16 + r2,
+                    g: g1 * 16 + g2,
+                    b: b1 * 16 + b2,
+                }),
+                _ => None,
+            };
+        }
+        if let Some(args) = value
+            .strip_prefix("rgb(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let channels: Vec<u8> = args
+                .split(',')
+                .map(|channel| channel.trim().parse().ok())
+                .collect::<Option<_>>()?;
+            return match channels.as_slice() {
+                [r, g, b] => Some(Rgb {
+                    r: *r,
+                    g: *g,
+                    b: *b,
+                }),
+                _ => None,
+            };
+        }
+        let hex = match value.as_str() {
+            "white" => "#ffffff",
+            "black" => "#000000",
+            "red" => "#ff0000",
+            "green" => "#008000",
+            "blue" => "#0000ff",
+            "yellow" => "#ffff00",
+            "orange" => "#ffa500",
+            "purple" => "#800080",
+            "gray" | "grey" => "#808080",
+            "silver" => "#c0c0c0",
+            "navy" => "#000080",
+            "teal" => "#008080",
+            _ => return None,
+        };
+        Rgb::parse(hex)
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// WCAG 2 relative luminance.
+    pub fn luminance(self) -> f32 {
+        let channel = |value: u8| {
+            let value = value as f32 / 255.0;
+            if value <= 0.03928 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    pub fn contrast_ratio(self, other: Rgb) -> f32 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// The grey with the same relative luminance, so contrast against any other color is
+    /// unchanged.
+    pub fn grayscale(self) -> Rgb {
+        let linear = self.luminance();
+        let encoded = if linear <= 0.0031308 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        let level = (encoded * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgb {
+            r: level,
+            g: level,
+            b: level,
+        }
+    }
+
+    /// This color moved `amount` (0 to 1) of the way towards `other`.
+    pub fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let blend =
+            |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+        Rgb {
+            r: blend(self.r, other.r),
+            g: blend(self.g, other.g),
+            b: blend(self.b, other.b),
+        }
+    }
+}
+
+/// Whether a background color leaves the page unpainted: `transparent` or `none`.
+pub fn is_transparent(color: &str) -> bool {
+    matches!(
+        color.trim().to_ascii_lowercase().as_str(),
+        "transparent" | "none"
+    )
+}
+
+/// Fill for a page background rect; `none` for transparent backgrounds, which not every SVG
+/// reader understands as a keyword.
+pub fn page_fill(background: &str) -> &str {
+    if is_transparent(background) {
+        "none"
+    } else {
+        background
+    }
+}
+
+/// White or dark text, whichever reads better on `fill`.
+pub fn readable_text_color(fill: &str) -> &'static str {
+    let Some(fill) = Rgb::parse(fill) else {
+        return DARK_TEXT;
+    };
+    let light = Rgb::parse(LIGHT_TEXT).expect("valid light text color");
+    let dark = Rgb::parse(DARK_TEXT).expect("valid dark text color");
+    if fill.contrast_ratio(light) >= fill.contrast_ratio(dark) {
+        LIGHT_TEXT
+    } else {
+        DARK_TEXT
+    }
+}
+
+/// The closest shade of `text` that reaches `target` contrast on `fill`, moving towards black or
+/// white depending on which side of the fill has more room.
+pub fn suggest_text_color(text: Rgb, fill: Rgb, target: f32) -> Rgb {
+    let black = Rgb { r: 0, g: 0, b: 0 };
+    let white = Rgb {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+    let towards = if fill.contrast_ratio(black) >= fill.contrast_ratio(white) {
+        black
+    } else {
+        white
+    };
+    (1..=20)
+        .map(|step| text.mix(towards, step as f32 / 20.0))
+        .find(|candidate| candidate.contrast_ratio(fill) >= target)
+        .unwrap_or(towards)
+}
+
+/// Ink used for strokes and text when rendering with `--monochrome`.
+pub const MONOCHROME_INK: &str = "#000000";
+
+/// Paper behind `--monochrome` output: the page, subgraphs and label backgrounds.
+pub const MONOCHROME_PAPER: &str = "#ffffff";
+
+/// Hatch patterns referenced by [`monochrome_fill`]; light enough to keep labels readable.
+pub const MONOCHROME_PATTERN_DEFS: &str = r##"        <pattern id="oxdraw-mono-diagonal" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M-2,2 L2,-2 M0,8 L8,0 M6,10 L10,6" stroke="#7f7f7f" stroke-width="1" />
+        </pattern>
+        <pattern id="oxdraw-mono-back-diagonal" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M-2,6 L2,10 M0,0 L8,8 M6,-2 L10,2" stroke="#7f7f7f" stroke-width="1" />
+        </pattern>
+        <pattern id="oxdraw-mono-cross" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M0,0 L8,8 M0,8 L8,0" stroke="#7f7f7f" stroke-width="0.8" />
+        </pattern>
+        <pattern id="oxdraw-mono-horizontal" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M0,4 L8,4" stroke="#7f7f7f" stroke-width="1" />
+        </pattern>
+        <pattern id="oxdraw-mono-vertical" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M4,0 L4,8" stroke="#7f7f7f" stroke-width="1" />
+        </pattern>
+        <pattern id="oxdraw-mono-grid" width="8" height="8" patternUnits="userSpaceOnUse">
+            <rect width="8" height="8" fill="#ffffff" />
+            <path d="M4,0 L4,8 M0,4 L8,4" stroke="#7f7f7f" stroke-width="0.8" />
+        </pattern>
+        <pattern id="oxdraw-mono-dots" width="6" height="6" patternUnits="userSpaceOnUse">
+            <rect width="6" height="6" fill="#ffffff" />
+            <circle cx="3" cy="3" r="1" fill="#7f7f7f" />
+        </pattern>
+"##;
+
+/// `svg` with every hex fill, stroke and stop color swapped for its [`Rgb::grayscale`] grey,
+/// for diagram kinds without their own `--monochrome` styling.
+pub fn grayscale_colors(svg: &str) -> String {
+    static PAINT: OnceLock<Regex> = OnceLock::new();
+    let paint = PAINT.get_or_init(|| {
+        Regex::new(
+            r#"((?:fill|stroke|stop-color|color)(?:="|:\s*))(#[0-9a-fA-F]{6}|#[0-9a-fA-F]{3})\b"#,
+        )
+        .expect("valid paint regex")
+    });
+    paint
+        .replace_all(svg, |caps: &regex::Captures| match Rgb::parse(&caps[2]) {
+            Some(color) => format!("{}{}", &caps[1], color.grayscale().to_hex()),
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// Print-friendly fill for `shape`: plain white for rectangles, a distinct hatch otherwise.
+pub fn monochrome_fill(shape: NodeShape) -> &'static str {
+    match shape {
+        NodeShape::Rectangle => "#ffffff",
+        NodeShape::Stadium => "url(#oxdraw-mono-horizontal)",
+        NodeShape::Circle | NodeShape::DoubleCircle => "url(#oxdraw-mono-dots)",
+        NodeShape::Diamond | NodeShape::Parallelogram => "url(#oxdraw-mono-diagonal)",
+        NodeShape::Subroutine | NodeShape::Asymmetric => "url(#oxdraw-mono-vertical)",
+        NodeShape::Cylinder => "url(#oxdraw-mono-grid)",
+        NodeShape::Hexagon => "url(#oxdraw-mono-cross)",
+        NodeShape::ParallelogramAlt | NodeShape::Trapezoid | NodeShape::TrapezoidAlt => {
+            "url(#oxdraw-mono-back-diagonal)"
+        }
+        NodeShape::Junction => MONOCHROME_INK,
+    }
+}
+
+/// Built-in color sets; `okabe-ito` and `tol` stay distinguishable under common color blindness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Tints of the Okabe-Ito palette.
+    OkabeIto,
+    /// Paul Tol's light and bright schemes.
+    Tol,
+    /// Greys for print, each light or dark enough for black or white text at 4.5:1.
+    Grayscale,
+}
+
+impl Palette {
+    pub fn parse(value: &str) -> Option<Self> {
+        <Palette as clap::ValueEnum>::from_str(value, true).ok()
+    }
+
+    pub fn node_fill(self, shape: NodeShape) -> &'static str {
+        match self {
+            Palette::Default => match shape {
+                NodeShape::Rectangle => "#fde68a",
+                NodeShape::Stadium => "#c4f1f9",
+                NodeShape::Circle => "#e9d8fd",
+                NodeShape::DoubleCircle => "#bfdbfe",
+                NodeShape::Diamond => "#fbcfe8",
+                NodeShape::Subroutine => "#fed7aa",
+                NodeShape::Cylinder => "#bbf7d0",
+                NodeShape::Hexagon => "#fca5a5",
+                NodeShape::Parallelogram => "#c7d2fe",
+                NodeShape::ParallelogramAlt => "#a5f3fc",
+                NodeShape::Trapezoid => "#fce7f3",
+                NodeShape::TrapezoidAlt => "#fcd5ce",
+                NodeShape::Asymmetric => "#f5d0fe",
+                NodeShape::Junction => "#2d3748",
+            },
+            Palette::OkabeIto => match shape {
+                NodeShape::Rectangle | NodeShape::TrapezoidAlt => "#f5d999",
+                NodeShape::Stadium | NodeShape::Parallelogram => "#bbe1f6",
+                NodeShape::Circle | NodeShape::Trapezoid => "#ebc9dc",
+                NodeShape::DoubleCircle => "#99c7e0",
+                NodeShape::Diamond => "#eebf99",
+                NodeShape::Subroutine | NodeShape::ParallelogramAlt => "#99d8c7",
+                NodeShape::Cylinder | NodeShape::Asymmetric => "#f9f4b3",
+                NodeShape::Hexagon => "#d6d6d6",
+                NodeShape::Junction => "#2d3748",
+            },
+            Palette::Tol => match shape {
+                NodeShape::Rectangle | NodeShape::TrapezoidAlt => "#eedd88",
+                NodeShape::Stadium | NodeShape::Parallelogram => "#99ddff",
+                NodeShape::Circle | NodeShape::Trapezoid => "#ffaabb",
+                NodeShape::DoubleCircle => "#77aadd",
+                NodeShape::Diamond => "#ee8866",
+                NodeShape::Subroutine => "#bbcc33",
+                NodeShape::Cylinder | NodeShape::ParallelogramAlt => "#44bb99",
+                NodeShape::Hexagon => "#dddddd",
+                NodeShape::Asymmetric => "#aaaa00",
+                NodeShape::Junction => "#2d3748",
+            },
+            Palette::Grayscale => match shape {
+                NodeShape::Rectangle | NodeShape::TrapezoidAlt => "#f2f2f2",
+                NodeShape::Stadium | NodeShape::Parallelogram => "#e0e0e0",
+                NodeShape::Circle | NodeShape::Trapezoid => "#d4d4d4",
+                NodeShape::DoubleCircle | NodeShape::Asymmetric => "#bdbdbd",
+                NodeShape::Diamond | NodeShape::ParallelogramAlt => "#e8e8e8",
+                NodeShape::Subroutine | NodeShape::Hexagon => "#c8c8c8",
+                NodeShape::Cylinder => "#d9d9d9",
+                NodeShape::Junction => "#333333",
+            },
+        }
+    }
+
+    /// Series colors for pie slices and mindmap branches; `None` keeps each diagram's own set.
+    pub fn categorical(self) -> Option<&'static [&'static str]> {
+        match self {
+            Palette::Default => None,
+            Palette::OkabeIto => Some(&[
+                "#e69f00", "#56b4e9", "#009e73", "#f0e442", "#0072b2", "#d55e00", "#cc79a7",
+                "#999999",
+            ]),
+            Palette::Tol => Some(&[
+                "#4477aa", "#ee6677", "#228833", "#ccbb44", "#66ccee", "#aa3377", "#bbbbbb",
+            ]),
+            Palette::Grayscale => Some(&[
+                "#d9d9d9", "#595959", "#a6a6a6", "#303030", "#f0f0f0", "#8c8c8c", "#bfbfbf",
+            ]),
+        }
+    }
+}
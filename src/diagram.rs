@@ -1,25 +1,39 @@
+use anyhow::Context;
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::convert::TryInto;
 use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use tiny_skia::{Pixmap, Transform};
 
 use crate::*;
 
+const EDGE_LABEL_TEXT: &str = "#2d3748";
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LayoutOverrides {
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub nodes: HashMap<String, Point>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub edges: HashMap<String, EdgeOverride>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub node_styles: HashMap<String, NodeStyleOverride>,
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub edge_styles: HashMap<String, EdgeStyleOverride>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub nodes: BTreeMap<String, Point>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub edges: BTreeMap<String, EdgeOverride>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_styles: BTreeMap<String, NodeStyleOverride>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub edge_styles: BTreeMap<String, EdgeStyleOverride>,
     #[serde(default, skip_serializing_if = "GanttOverrides::is_empty")]
     pub gantt: GanttOverrides,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub pinned: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub views: BTreeMap<String, LayoutView>,
+    /// Subgraphs drawn as a single summary node; see [`Diagram::apply_collapsed`].
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub collapsed: BTreeSet<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -70,6 +84,18 @@ impl SubgraphBuilder {
 pub enum DiagramKind {
     Flowchart,
     Gantt(GanttData),
+    Sequence(SequenceDiagram),
+    State(StateDiagramData),
+    Class(ClassDiagramData),
+    Er(ErDiagramData),
+    Pie(PieChart),
+    Mindmap(MindmapData),
+    Journey(JourneyDiagram),
+    Timeline(TimelineDiagram),
+    GitGraph(GitGraph),
+    Quadrant(QuadrantChart),
+    C4(C4DiagramData),
+    Block(BlockDiagram),
 }
 
 #[derive(Debug, Clone)]
@@ -91,9 +117,507 @@ pub struct GanttTask {
     pub milestone: bool,
 }
 
+/// How edges are arranged when a flowchart definition is regenerated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeGrouping {
+    /// One block in parse order.
+    #[default]
+    None,
+    /// One block per source node, in node declaration order.
+    Source,
+    /// One block per innermost subgraph of the source node, top-level edges first.
+    Subgraph,
+}
+
+/// Formatting options for [`Diagram::to_definition_with`], shared by `oxdraw fmt` and the editor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefinitionFormat {
+    pub edge_grouping: EdgeGrouping,
+}
+
+/// Per-diagram spacing overrides from CLI flags or the serve API, layered over the frontmatter
+/// by [`Diagram::apply_layout_options`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutOptions {
+    /// Gap between neighbouring nodes in a layer.
+    #[serde(default)]
+    pub node_spacing: Option<f32>,
+    /// Gap between layers.
+    #[serde(default)]
+    pub rank_spacing: Option<f32>,
+    /// Blank border around the rendered diagram.
+    #[serde(default)]
+    pub margin: Option<f32>,
+    /// Replaces the frontmatter `config.layout` engine.
+    #[serde(default)]
+    pub layout: Option<LayoutEngine>,
+    /// Widest a flowchart node grows before its label wraps.
+    #[serde(default)]
+    pub wrap_width: Option<f32>,
+    /// Widest a flowchart node label line is drawn before it's cut short with `…`.
+    #[serde(default)]
+    pub max_label_width: Option<f32>,
+}
+
+/// Font, icon, grid and watermark choices from CLI flags or library callers, applied by
+/// [`Diagram::apply_render_options`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderOptions {
+    /// Font stack put ahead of the theme's in the SVG root `font-family`.
+    #[serde(default)]
+    pub font_family: Option<String>,
+    /// TrueType or OpenType file registered for PNG, JPEG and WebP output. Its family is used
+    /// when `font_family` is unset.
+    #[serde(default)]
+    pub font_file: Option<PathBuf>,
+    /// Directory of `<name>.svg` files used for `fa:fa-<name>` label icons, ahead of the
+    /// built-in set.
+    #[serde(default)]
+    pub icon_dir: Option<PathBuf>,
+    /// Grid drawn behind the diagram, overriding the frontmatter `grid`.
+    #[serde(default)]
+    pub grid: Option<GridStyle>,
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+}
+
+/// Corner of the canvas a [`Watermark`] is stamped in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatermarkCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// Small text and/or PNG stamped in a corner of every export, such as a classification label.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Watermark {
+    #[serde(default)]
+    pub text: Option<String>,
+    /// PNG drawn before the text.
+    #[serde(default)]
+    pub image: Option<PathBuf>,
+    #[serde(default)]
+    pub corner: WatermarkCorner,
+}
+
+/// A [`Watermark`] with its image read, ready to stamp onto rendered SVGs.
+#[derive(Debug, Clone)]
+pub struct WatermarkStamp {
+    pub text: Option<String>,
+    pub image: Option<NodeImage>,
+    pub corner: WatermarkCorner,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiagramConfig {
+    pub title: Option<String>,
+    pub theme: Option<String>,
+    pub palette: Palette,
+    /// Black ink with hatch-pattern fills instead of color, for printing.
+    pub monochrome: bool,
+    /// `themeVariables.edgeLabelBackground`; `none` or `transparent` turns off label boxes.
+    pub edge_label_fill: Option<String>,
+    /// `themeVariables.edgeLabelBorder`.
+    pub edge_label_stroke: Option<String>,
+    pub node_spacing: Option<f32>,
+    pub rank_spacing: Option<f32>,
+    /// `diagramPadding`; replaces [`LAYOUT_MARGIN`] around the rendered diagram.
+    pub margin: Option<f32>,
+    /// `wrappingWidth`; flowchart node labels wrap onto more lines rather than grow a node
+    /// past this width.
+    pub wrap_width: Option<f32>,
+    /// `maxLabelWidth`; flowchart node label lines longer than this end in `…`, with the full
+    /// text left in the node's `<title>`.
+    pub max_label_width: Option<f32>,
+    pub frontmatter: Option<String>,
+    /// `%% OXDRAW RANK` groups of node ids that share a layer in the automatic layout.
+    pub rank_groups: Vec<Vec<String>>,
+    /// `click` links, keyed by node id; rendered nodes are wrapped in `<a>` elements.
+    pub links: BTreeMap<String, NodeLink>,
+    /// `%% OXDRAW IMAGE` references to image files or URLs, keyed by node id; see
+    /// [`Diagram::load_image_sources`].
+    pub image_sources: BTreeMap<String, NodeImageSource>,
+    pub layout: LayoutEngine,
+    /// `edgeBundling`; merges edges converging on a busy node into a shared trunk.
+    pub edge_bundling: bool,
+    /// `animateEdges`; marches dashes along every edge from source to target in SVG output.
+    pub animate_edges: bool,
+    /// `grid`; a dot or line grid behind the diagram.
+    pub grid: Option<GridStyle>,
+    /// Theme loaded from a file with `--theme`; replaces the named `theme`.
+    pub custom_theme: Option<Theme>,
+    /// `themeCSS`; rules embedded in a `<style>` block, where they win over the inline colors.
+    pub theme_css: Option<String>,
+    /// External stylesheet the SVG imports ahead of `theme_css`.
+    pub stylesheet: Option<String>,
+    /// `embedFont`; embeds the label font, cut down to the glyphs in use, so the SVG looks
+    /// the same on machines without it.
+    pub embed_font: bool,
+    /// `fontFamily`; font stack put ahead of the theme's in the SVG root `font-family`.
+    pub font_family: Option<String>,
+    /// Font files registered with the rasterizer, for families that aren't installed.
+    pub font_files: Vec<PathBuf>,
+    /// SVG icons loaded from an icon directory, keyed by name; they win over the built-in
+    /// `fa:` icons.
+    pub icons: BTreeMap<String, String>,
+    /// `accTitle`; the accessible name of the SVG, falling back to `title`.
+    pub acc_title: Option<String>,
+    /// `accDescr`; the SVG's `<desc>`, read out by screen readers.
+    pub acc_descr: Option<String>,
+    /// Stamped in a corner of the rendered SVG, and so of raster exports too.
+    pub watermark: Option<WatermarkStamp>,
+}
+
+impl DiagramConfig {
+    pub fn canvas_margin(&self) -> f32 {
+        self.margin.unwrap_or(LAYOUT_MARGIN)
+    }
+
+    /// The custom theme, else the built-in one `theme` names; unknown names fall back to
+    /// the default theme.
+    pub fn resolved_theme(&self) -> Theme {
+        self.custom_theme
+            .clone()
+            .or_else(|| self.theme.as_deref().and_then(Theme::built_in))
+            .unwrap_or_default()
+    }
+
+    /// `requested` when given, else the theme's page color.
+    pub fn page_background(&self, requested: Option<&str>) -> String {
+        requested.map_or_else(|| self.resolved_theme().background, str::to_string)
+    }
+
+    /// `<style>` element carrying the stylesheet import and `theme_css`; empty when neither
+    /// is set. Rules target the `node`, `edge` and `subgraph` groups and their
+    /// `node-<shape>`/`edge-<kind>` classes.
+    fn style_block(&self) -> String {
+        let mut css = String::new();
+        if let Some(href) = &self.stylesheet {
+            css.push_str(&format!("@import url(\"{}\");\n", href.replace('"', "%22")));
+        }
+        if let Some(rules) = &self.theme_css {
+            css.push_str(rules.trim());
+            css.push('\n');
+        }
+        if css.is_empty() {
+            return String::new();
+        }
+        // CDATA keeps selectors like `a > b` intact; only its terminator needs splitting.
+        format!(
+            "  <style><![CDATA[\n{}]]></style>\n",
+            css.replace("]]>", "]]]]><![CDATA[>")
+        )
+    }
+}
+
+impl LayoutPass for IncrementalLayout {
+    fn adjust(&self, diagram: &Diagram, positions: &mut HashMap<String, Point>) -> Result<()> {
+        diagram.seed_from_previous(positions, &self.previous);
+        Ok(())
+    }
+}
+
+/// Engine placing nodes in the automatic layout, chosen with frontmatter `config.layout`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutEngine {
+    #[default]
+    Auto,
+    /// Graphviz `dot`, run as an external program; needs the `graphviz` feature.
+    Dot,
+    /// Top-level subgraphs become lanes spanning the whole diagram across the flow, each
+    /// holding its members.
+    Swimlanes,
+    /// Compact tidy tree for diagrams whose edges form a forest; other graphs fall back to
+    /// the layered layout.
+    Tree,
+    /// Like [`LayoutEngine::Tree`] with each level on a ring around the root.
+    Radial,
+}
+
+/// Pattern drawn behind the diagram, chosen with frontmatter `config.grid` or `--grid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GridStyle {
+    Dots,
+    Lines,
+}
+
+/// Machine-readable category of a [`ParseError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticCode {
+    MissingHeader,
+    InvalidHeader,
+    InvalidFrontmatter,
+    InvalidComment,
+    InvalidSubgraph,
+    DuplicateSubgraph,
+    UnmatchedEnd,
+    UnclosedSubgraph,
+    InvalidEdge,
+    InvalidNode,
+    UnknownNode,
+    EmptyDiagram,
+    InvalidGantt,
+    InvalidSequence,
+    InvalidState,
+    InvalidClass,
+    InvalidEr,
+    InvalidPie,
+    InvalidMindmap,
+    InvalidJourney,
+    InvalidTimeline,
+    InvalidGitGraph,
+    InvalidQuadrant,
+    InvalidC4,
+    InvalidBlock,
+    LimitExceeded,
+}
+
+/// Error returned by [`Diagram::parse`] pointing at the offending statement.
+///
+/// Lines and columns are 1-based and count characters; `end_column` is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, thiserror::Error)]
+#[serde(rename_all = "camelCase")]
+#[error("line {line}, column {column}: {message}")]
+pub struct ParseError {
+    pub code: DiagnosticCode,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_column: usize,
+}
+
+/// A subgraph frame or a loose node being packed by `Diagram::pack_cluster`.
+struct ClusterItem {
+    rect: Rect,
+    /// Extent of the member nodes along the layer axis; frames that only meet through their
+    /// padding are stacked later by `separate_subgraphs`.
+    span: (f32, f32),
+    /// Nodes that move along with the item.
+    members: Vec<String>,
+    is_frame: bool,
+}
+
+struct EdgeLabelColors {
+    /// `None` draws the label without a box, outlined by `halo`.
+    fill: Option<String>,
+    stroke: String,
+    text: &'static str,
+    halo: String,
+}
+
+/// Records the time since the previous mark under each phase name. The default timer is
+/// idle and never reads the clock, which wasm builds cannot do.
+#[derive(Default)]
+struct PhaseTimer {
+    last: Option<std::time::Instant>,
+    timings: Vec<PhaseTiming>,
+}
+
+impl PhaseTimer {
+    fn started() -> Self {
+        Self {
+            last: Some(std::time::Instant::now()),
+            timings: Vec::new(),
+        }
+    }
+
+    fn mark(&mut self, phase: &'static str) {
+        let Some(last) = self.last else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        self.timings.push(PhaseTiming {
+            phase,
+            millis: (now - last).as_secs_f64() * 1000.0,
+        });
+        self.last = Some(now);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LineSpan {
+    line: usize,
+    column: usize,
+    end_column: usize,
+}
+
+impl LineSpan {
+    fn new(line: usize, raw: &str, content: &str) -> Self {
+        let leading = raw.len() - raw.trim_start().len();
+        let column = raw[..leading].chars().count() + 1;
+        Self {
+            line,
+            column,
+            end_column: column + content.chars().count(),
+        }
+    }
+
+    fn error(self, code: DiagnosticCode, message: impl std::fmt::Display) -> anyhow::Error {
+        ParseError {
+            code,
+            message: message.to_string(),
+            line: self.line,
+            column: self.column,
+            end_column: self.end_column,
+        }
+        .into()
+    }
+}
+
+/// Upper bounds enforced by [`Diagram::parse_with_limits`] for untrusted input.
+/// `None` leaves a dimension unlimited; [`ParseLimits::default`] is unlimited everywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseLimits {
+    pub max_source_bytes: Option<usize>,
+    pub max_nodes: Option<usize>,
+    pub max_edges: Option<usize>,
+    /// Maximum length of a node, edge or subgraph label, in characters.
+    pub max_label_len: Option<usize>,
+    /// Maximum subgraph nesting depth.
+    pub max_depth: Option<usize>,
+}
+
+impl ParseLimits {
+    /// Conservative preset for rendering diagrams submitted by anonymous users.
+    pub fn untrusted() -> Self {
+        Self {
+            max_source_bytes: Some(256 * 1024),
+            max_nodes: Some(500),
+            max_edges: Some(1_000),
+            max_label_len: Some(1_000),
+            max_depth: Some(16),
+        }
+    }
+
+    fn check(limit: Option<usize>, actual: usize, what: &str, span: LineSpan) -> Result<()> {
+        match limit {
+            Some(limit) if actual > limit => Err(span.error(
+                DiagnosticCode::LimitExceeded,
+                format!("{what} exceeds the limit of {limit}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the items of a non-flowchart diagram: graph-based kinds by their nodes and edges,
+    /// the others by what they draw in place of nodes (participants, commits, slices, tasks...)
+    /// and, for sequence diagrams, by their events in place of edges.
+    fn check_items(&self, diagram: &Diagram, span: LineSpan) -> Result<()> {
+        let (items, connections) = match &diagram.kind {
+            DiagramKind::Sequence(sequence) => (sequence.participants.len(), sequence.events.len()),
+            DiagramKind::Gantt(gantt) => (gantt.tasks.len(), 0),
+            DiagramKind::Pie(pie) => (pie.slices.len(), 0),
+            DiagramKind::Mindmap(mindmap) => (mindmap.nodes.len(), 0),
+            DiagramKind::Journey(journey) => (journey.tasks.len(), 0),
+            DiagramKind::Timeline(timeline) => (
+                timeline.periods.len()
+                    + timeline
+                        .periods
+                        .iter()
+                        .map(|period| period.events.len())
+                        .sum::<usize>(),
+                0,
+            ),
+            DiagramKind::GitGraph(gitgraph) => (
+                gitgraph.commits.len() + gitgraph.branches.len(),
+                gitgraph.commits.len(),
+            ),
+            DiagramKind::Quadrant(quadrant) => (quadrant.points.len(), 0),
+            DiagramKind::Flowchart
+            | DiagramKind::State(_)
+            | DiagramKind::Class(_)
+            | DiagramKind::Er(_)
+            | DiagramKind::C4(_)
+            | DiagramKind::Block(_) => (0, 0),
+        };
+        ParseLimits::check(
+            self.max_nodes,
+            diagram.nodes.len().max(items),
+            "node count",
+            span,
+        )?;
+        ParseLimits::check(
+            self.max_edges,
+            diagram.edges.len().max(connections),
+            "edge count",
+            span,
+        )
+    }
+
+    fn check_labels(&self, diagram: &Diagram, span: LineSpan) -> Result<()> {
+        let Some(limit) = self.max_label_len else {
+            return Ok(());
+        };
+        let mut labels: Vec<(String, &str)> = diagram
+            .order
+            .iter()
+            .filter_map(|id| {
+                Some((
+                    format!("node '{id}'"),
+                    diagram.nodes.get(id)?.label.as_str(),
+                ))
+            })
+            .collect();
+        for edge in &diagram.edges {
+            if let Some(label) = &edge.label {
+                labels.push((format!("edge '{}'", edge_identifier(edge)), label));
+            }
+        }
+        let mut pending: Vec<&Subgraph> = diagram.subgraphs.iter().collect();
+        while let Some(subgraph) = pending.pop() {
+            labels.push((format!("subgraph '{}'", subgraph.id), &subgraph.label));
+            pending.extend(subgraph.children.iter());
+        }
+        for (owner, label) in labels {
+            ParseLimits::check(
+                Some(limit),
+                label.chars().count(),
+                &format!("label length of {owner}"),
+                span,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Records `err` when recovering (lenient parsing) and propagates it otherwise.
+fn recover(diagnostics: &mut Option<&mut Vec<ParseError>>, err: anyhow::Error) -> Result<()> {
+    match (diagnostics.as_deref_mut(), err.downcast::<ParseError>()) {
+        (Some(diagnostics), Ok(parse_error)) => {
+            diagnostics.push(parse_error);
+            Ok(())
+        }
+        (_, Ok(parse_error)) => Err(parse_error.into()),
+        (_, Err(err)) => Err(err),
+    }
+}
+
+fn close_subgraph(stack: &mut Vec<SubgraphBuilder>, top_level: &mut Vec<SubgraphBuilder>) {
+    let Some(builder) = stack.pop() else {
+        return;
+    };
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(builder);
+    } else {
+        top_level.push(builder);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Diagram {
     pub kind: DiagramKind,
+    pub config: DiagramConfig,
     pub direction: Direction,
     pub nodes: HashMap<String, Node>,
     pub order: Vec<String>,
@@ -109,56 +633,247 @@ impl LayoutOverrides {
             && self.node_styles.is_empty()
             && self.edge_styles.is_empty()
             && self.gantt.is_empty()
+            && self.pinned.is_empty()
+            && self.views.is_empty()
+            && self.collapsed.is_empty()
     }
 
-    pub fn prune(&mut self, nodes: &HashSet<String>, edges: &HashSet<String>) {
+    /// Drops overrides for ids missing from `nodes`/`edges` and reports which ids lost one.
+    pub fn prune(&mut self, nodes: &HashSet<String>, edges: &HashSet<String>) -> PrunedOverrides {
+        let node_ids = self
+            .nodes
+            .keys()
+            .chain(self.node_styles.keys())
+            .chain(self.gantt.tasks.keys())
+            .chain(self.pinned.iter())
+            .chain(
+                self.views
+                    .values()
+                    .flat_map(|view| view.hidden_nodes.iter()),
+            );
+        let edge_ids = self.edges.keys().chain(self.edge_styles.keys()).chain(
+            self.views
+                .values()
+                .flat_map(|view| view.hidden_edges.iter()),
+        );
+        let pruned = PrunedOverrides {
+            nodes: node_ids
+                .filter(|id| !nodes.contains(*id))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            edges: edge_ids
+                .filter(|id| !edges.contains(*id))
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        };
+
         self.nodes.retain(|id, _| nodes.contains(id));
         self.edges.retain(|id, _| edges.contains(id));
         self.node_styles.retain(|id, _| nodes.contains(id));
         self.edge_styles.retain(|id, _| edges.contains(id));
         self.gantt.tasks.retain(|id, _| nodes.contains(id));
         self.gantt.tasks.retain(|_, task| !task.is_empty());
+        self.pinned.retain(|id| nodes.contains(id));
+        for view in self.views.values_mut() {
+            view.hidden_nodes.retain(|id| nodes.contains(id));
+            view.hidden_edges.retain(|id| edges.contains(id));
+        }
+        pruned
+    }
+
+    pub fn view(&self, name: &str) -> Result<&LayoutView> {
+        self.views.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.views.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow!("unknown view '{name}'; the layout block does not define any views")
+            } else {
+                anyhow!(
+                    "unknown view '{name}'; expected one of {}",
+                    known.join(", ")
+                )
+            }
+        })
+    }
+
+    pub fn pin(&mut self, id: &str, position: Point) -> bool {
+        let inserted = self.pinned.insert(id.to_string());
+        self.nodes.entry(id.to_string()).or_insert(position);
+        inserted
+    }
+
+    pub fn unpin(&mut self, id: &str) -> bool {
+        self.pinned.remove(id)
+    }
+
+    /// Marks subgraph `id` collapsed or expanded; returns whether that changed anything.
+    pub fn set_collapsed(&mut self, id: &str, collapsed: bool) -> bool {
+        if collapsed {
+            self.collapsed.insert(id.to_string())
+        } else {
+            self.collapsed.remove(id)
+        }
+    }
+
+    pub fn clear_unpinned_positions(&mut self) -> bool {
+        let before = (self.nodes.len(), self.edges.len());
+        let pinned = &self.pinned;
+        self.nodes.retain(|id, _| pinned.contains(id));
+        self.edges.clear();
+        before != (self.nodes.len(), self.edges.len())
     }
 }
 
 impl Diagram {
     pub fn parse(definition: &str) -> Result<Self> {
+        Self::parse_with_recovery(definition, None, &ParseLimits::default())
+    }
+
+    /// Parses untrusted input, failing with [`DiagnosticCode::LimitExceeded`] as soon as any
+    /// of `limits` is exceeded.
+    pub fn parse_with_limits(definition: &str, limits: &ParseLimits) -> Result<Self> {
+        Self::parse_with_recovery(definition, None, limits)
+    }
+
+    /// Like [`Diagram::parse`], but skips malformed statements and returns their diagnostics
+    /// alongside a best-effort diagram. Only a missing or invalid header, an unparseable
+    /// gantt chart, or a diagram without any valid node is still fatal.
+    pub fn parse_lenient(definition: &str) -> Result<(Self, Vec<ParseError>)> {
+        let mut diagnostics = Vec::new();
+        let diagram =
+            Self::parse_with_recovery(definition, Some(&mut diagnostics), &ParseLimits::default())?;
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        Ok((diagram, diagnostics))
+    }
+
+    fn parse_with_recovery(
+        definition: &str,
+        mut diagnostics: Option<&mut Vec<ParseError>>,
+        limits: &ParseLimits,
+    ) -> Result<Self> {
+        let whole_source = LineSpan {
+            line: 1,
+            column: 1,
+            end_column: 1,
+        };
+        ParseLimits::check(
+            limits.max_source_bytes,
+            definition.len(),
+            "source size in bytes",
+            whole_source,
+        )?;
         let definition = extract_mermaid_diagram_source(definition);
-        let mut image_comments: HashMap<String, NodeImage> = HashMap::new();
-        let mut content_lines: Vec<String> = Vec::new();
+        let mut image_comments: HashMap<String, (ImageComment, LineSpan)> = HashMap::new();
+        let mut rank_comments: Vec<(Vec<String>, LineSpan)> = Vec::new();
+        let mut content_lines: Vec<(LineSpan, String)> = Vec::new();
         let mut in_frontmatter = false;
         let mut seen_content = false;
-
-        for raw_line in definition.lines() {
+        let mut frontmatter_lines: Vec<&str> = Vec::new();
+        let mut frontmatter_span: Option<LineSpan> = None;
+        let mut acc_title: Option<String> = None;
+        let mut comment_title: Option<String> = None;
+        let mut acc_descr: Option<String> = None;
+        // Lines of an `accDescr {` block that has not been closed yet.
+        let mut acc_descr_block: Option<Vec<String>> = None;
+
+        for (index, raw_line) in definition.lines().enumerate() {
             let trimmed = raw_line.trim();
             if trimmed.is_empty() {
                 continue;
             }
+            let span = LineSpan::new(index + 1, raw_line, trimmed);
 
             if trimmed == "---" && !seen_content {
                 in_frontmatter = !in_frontmatter;
+                frontmatter_span.get_or_insert(span);
                 continue;
             }
 
             if in_frontmatter {
+                frontmatter_lines.push(raw_line);
+                continue;
+            }
+
+            if let Some(block) = &mut acc_descr_block {
+                match trimmed.strip_suffix('}') {
+                    Some(last) => {
+                        block.push(last.trim_end().to_string());
+                        acc_descr = Some(block.join("\n").trim().to_string());
+                        acc_descr_block = None;
+                    }
+                    None => block.push(trimmed.to_string()),
+                }
+                continue;
+            }
+            if let Some(text) = accessibility_value(trimmed, "accTitle") {
+                acc_title = Some(text.trim().to_string());
+                continue;
+            }
+            if let Some(text) = accessibility_value(trimmed, "accDescr") {
+                acc_descr = Some(text.trim().to_string());
+                continue;
+            }
+            if let Some(body) = trimmed
+                .strip_prefix("accDescr")
+                .and_then(|rest| rest.trim_start().strip_prefix('{'))
+            {
+                match body.strip_suffix('}') {
+                    Some(body) => acc_descr = Some(body.trim().to_string()),
+                    None => acc_descr_block = Some(vec![body.trim().to_string()]),
+                }
                 continue;
             }
 
+            if let Some(title) = trimmed.strip_prefix(TITLE_COMMENT_PREFIX) {
+                let title = title.trim();
+                if !title.is_empty() {
+                    comment_title = Some(title.to_string());
+                }
+                continue;
+            }
             if trimmed.starts_with("%%") {
-                if let Some((node_id, image)) = parse_image_comment(trimmed)? {
-                    image_comments.insert(node_id, image);
+                match parse_image_comment(trimmed) {
+                    Ok(Some((node_id, image))) => {
+                        image_comments.insert(node_id, (image, span));
+                    }
+                    Ok(None) => {}
+                    Err(err) => recover(
+                        &mut diagnostics,
+                        span.error(DiagnosticCode::InvalidComment, format!("{err:#}")),
+                    )?,
+                }
+                match parse_rank_comment(trimmed) {
+                    Ok(Some(group)) => rank_comments.push((group, span)),
+                    Ok(None) => {}
+                    Err(err) => recover(
+                        &mut diagnostics,
+                        span.error(DiagnosticCode::InvalidComment, format!("{err:#}")),
+                    )?,
                 }
                 continue;
             }
 
-            content_lines.push(trimmed.to_string());
+            let trimmed = match find_unquoted(trimmed, "%%") {
+                Some(idx) => trimmed[..idx].trim_end(),
+                None => trimmed,
+            };
+            content_lines.push((
+                LineSpan::new(index + 1, raw_line, trimmed),
+                trimmed.to_string(),
+            ));
             seen_content = true;
         }
 
         let mut lines = content_lines.into_iter();
 
-        let header = lines.next().ok_or_else(|| {
-            anyhow!("diagram definition must start with a 'graph' or 'gantt' declaration")
+        let (header_span, header) = lines.next().ok_or_else(|| {
+            whole_source.error(
+                DiagnosticCode::MissingHeader,
+                "diagram definition must start with a 'graph', 'gantt', 'sequenceDiagram', 'stateDiagram-v2', 'classDiagram', 'erDiagram', 'pie', 'mindmap', 'journey', 'timeline', 'gitGraph', 'quadrantChart', 'C4Context'/'C4Container' or 'block-beta' declaration",
+            )
         })?;
 
         let keyword = header
@@ -167,11 +882,158 @@ impl Diagram {
             .unwrap_or_default()
             .to_ascii_lowercase();
 
-        if keyword == "gantt" {
-            return parse_gantt_diagram(lines.collect(), &definition);
+        let mut config = match parse_frontmatter(&frontmatter_lines) {
+            Ok(config) => config,
+            Err(err) => {
+                recover(
+                    &mut diagnostics,
+                    frontmatter_span
+                        .unwrap_or(header_span)
+                        .error(DiagnosticCode::InvalidFrontmatter, format!("{err:#}")),
+                )?;
+                DiagramConfig::default()
+            }
+        };
+        config.acc_title = acc_title;
+        config.acc_descr = acc_descr;
+        if config.title.is_none() {
+            config.title = comment_title;
+        }
+
+        let special = match keyword.as_str() {
+            "gantt" => Some(
+                parse_gantt_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidGantt, format!("{err:#}"))
+                    })?,
+            ),
+            "statediagram" | "statediagram-v2" => Some(
+                parse_state_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidState, format!("{err:#}"))
+                    })?,
+            ),
+            "classdiagram" | "classdiagram-v2" => Some(
+                parse_class_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidClass, format!("{err:#}"))
+                    })?,
+            ),
+            "erdiagram" => Some(
+                parse_er_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidEr, format!("{err:#}"))
+                    })?,
+            ),
+            "pie" => Some(
+                parse_pie_chart(
+                    &header,
+                    lines.by_ref().map(|(_, line)| line).collect(),
+                    &definition,
+                )
+                .map_err(|err| header_span.error(DiagnosticCode::InvalidPie, format!("{err:#}")))?,
+            ),
+            "timeline" => Some(
+                parse_timeline(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidTimeline, format!("{err:#}"))
+                    })?,
+            ),
+            "journey" => Some(
+                parse_journey_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidJourney, format!("{err:#}"))
+                    })?,
+            ),
+            "mindmap" => Some(
+                parse_mindmap(
+                    lines
+                        .by_ref()
+                        .map(|(span, line)| (span.column - 1, line))
+                        .collect(),
+                    &definition,
+                )
+                .map_err(|err| {
+                    header_span.error(DiagnosticCode::InvalidMindmap, format!("{err:#}"))
+                })?,
+            ),
+            "gitgraph" | "gitgraph:" => Some(
+                parse_git_graph(
+                    &header,
+                    lines.by_ref().map(|(_, line)| line).collect(),
+                    &definition,
+                )
+                .map_err(|err| {
+                    header_span.error(DiagnosticCode::InvalidGitGraph, format!("{err:#}"))
+                })?,
+            ),
+            "quadrantchart" => Some(
+                parse_quadrant_chart(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                    header_span.error(DiagnosticCode::InvalidQuadrant, format!("{err:#}"))
+                })?,
+            ),
+            "c4context" | "c4container" => Some(
+                parse_c4_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidC4, format!("{err:#}"))
+                    })?,
+            ),
+            "block-beta" | "block" => Some(
+                parse_block_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidBlock, format!("{err:#}"))
+                    })?,
+            ),
+            "sequencediagram" => Some(
+                parse_sequence_diagram(lines.by_ref().map(|(_, line)| line).collect(), &definition)
+                    .map_err(|err| {
+                        header_span.error(DiagnosticCode::InvalidSequence, format!("{err:#}"))
+                    })?,
+            ),
+            _ => None,
+        };
+        if let Some(mut diagram) = special {
+            match &mut diagram.kind {
+                DiagramKind::Gantt(gantt) if gantt.title.is_none() => {
+                    gantt.title = config.title.clone();
+                }
+                DiagramKind::Sequence(sequence) if sequence.title.is_none() => {
+                    sequence.title = config.title.clone();
+                }
+                DiagramKind::Pie(pie) if pie.title.is_none() => {
+                    pie.title = config.title.clone();
+                }
+                DiagramKind::Journey(journey) if journey.title.is_none() => {
+                    journey.title = config.title.clone();
+                }
+                DiagramKind::Timeline(timeline) if timeline.title.is_none() => {
+                    timeline.title = config.title.clone();
+                }
+                DiagramKind::GitGraph(gitgraph) if gitgraph.title.is_none() => {
+                    gitgraph.title = config.title.clone();
+                }
+                DiagramKind::Quadrant(quadrant) if quadrant.title.is_none() => {
+                    quadrant.title = config.title.clone();
+                }
+                _ => {}
+            }
+            diagram.config = config;
+            diagram.config.rank_groups =
+                resolve_rank_groups(rank_comments, &diagram.nodes, &mut diagnostics)?;
+            // C4 elements go through the flowchart renderer, which draws the config title.
+            if let DiagramKind::C4(c4) = &diagram.kind
+                && diagram.config.title.is_none()
+            {
+                diagram.config.title = c4.title.clone();
+            }
+            limits.check_items(&diagram, header_span)?;
+            limits.check_labels(&diagram, header_span)?;
+            return Ok(diagram);
         }
 
-        let direction = parse_graph_header(&header)?;
+        let direction = parse_graph_header(&header)
+            .map_err(|err| header_span.error(DiagnosticCode::InvalidHeader, format!("{err:#}")))?;
 
         let mut nodes = HashMap::new();
         let mut order = Vec::new();
@@ -181,76 +1043,180 @@ impl Diagram {
         let mut top_subgraphs: Vec<SubgraphBuilder> = Vec::new();
         let mut seen_subgraph_ids: HashSet<String> = HashSet::new();
         let mut subgraph_counter = 0_usize;
+        let mut subgraph_spans: Vec<LineSpan> = Vec::new();
+        // One entry per open `subgraph` line; `false` marks headers skipped during recovery so
+        // their `end` is consumed without closing a real subgraph.
+        let mut open_blocks: Vec<bool> = Vec::new();
+        let mut click_statements: Vec<(String, NodeLink, LineSpan)> = Vec::new();
 
-        for raw_line in lines {
+        for (span, raw_line) in lines {
             let mut line = raw_line.as_str();
             line = line.trim();
             line = line.trim_end_matches(';').trim();
             if line.is_empty() {
                 continue;
             }
-            if let Some(rest) = line.strip_prefix("subgraph") {
-                let (id, label) = parse_subgraph_header(rest)?;
-                if !seen_subgraph_ids.insert(id.clone()) {
-                    bail!("duplicate subgraph identifier '{id}'");
+            if let Some(rest) = line
+                .strip_prefix("subgraph")
+                .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            {
+                let header = match parse_subgraph_header(rest) {
+                    Ok((id, _)) if !seen_subgraph_ids.insert(id.clone()) => Err(span.error(
+                        DiagnosticCode::DuplicateSubgraph,
+                        format!("duplicate subgraph identifier '{id}'"),
+                    )),
+                    Ok(header) => Ok(header),
+                    Err(err) => {
+                        Err(span.error(DiagnosticCode::InvalidSubgraph, format!("{err:#}")))
+                    }
+                };
+                match header {
+                    Ok((id, label)) => {
+                        let builder = SubgraphBuilder::new(id, label, subgraph_counter);
+                        subgraph_counter += 1;
+                        subgraph_stack.push(builder);
+                        ParseLimits::check(
+                            limits.max_depth,
+                            subgraph_stack.len(),
+                            "subgraph nesting depth",
+                            span,
+                        )?;
+                        subgraph_spans.push(span);
+                        open_blocks.push(true);
+                    }
+                    Err(err) => {
+                        recover(&mut diagnostics, err)?;
+                        open_blocks.push(false);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(rest) = line
+                .strip_prefix("click")
+                .filter(|rest| rest.starts_with(char::is_whitespace))
+            {
+                match parse_click_statement(rest) {
+                    Ok(Some((id, link))) => click_statements.push((id, link, span)),
+                    Ok(None) => {}
+                    Err(err) => recover(
+                        &mut diagnostics,
+                        span.error(DiagnosticCode::InvalidNode, format!("{err:#}")),
+                    )?,
                 }
-                let builder = SubgraphBuilder::new(id, label, subgraph_counter);
-                subgraph_counter += 1;
-                subgraph_stack.push(builder);
                 continue;
             }
 
             if line.eq_ignore_ascii_case("end") {
-                let builder = subgraph_stack
-                    .pop()
-                    .ok_or_else(|| anyhow!("encountered 'end' without matching 'subgraph'"))?;
-                if let Some(parent) = subgraph_stack.last_mut() {
-                    parent.children.push(builder);
-                } else {
-                    top_subgraphs.push(builder);
+                match open_blocks.pop() {
+                    Some(true) => {
+                        close_subgraph(&mut subgraph_stack, &mut top_subgraphs);
+                        subgraph_spans.pop();
+                    }
+                    Some(false) => {}
+                    None => recover(
+                        &mut diagnostics,
+                        span.error(
+                            DiagnosticCode::UnmatchedEnd,
+                            "encountered 'end' without matching 'subgraph'",
+                        ),
+                    )?,
                 }
                 continue;
             }
 
-            if let Some(edge) = parse_edge_line(
+            match parse_edge_line(
                 line,
                 &mut nodes,
                 &mut order,
                 &mut node_membership,
                 &mut subgraph_stack,
-            )? {
-                edges.push(edge);
-                continue;
+            ) {
+                Ok(Some(edge)) => {
+                    push_edge(&mut edges, edge);
+                    ParseLimits::check(limits.max_edges, edges.len(), "edge count", span)?;
+                    ParseLimits::check(limits.max_nodes, nodes.len(), "node count", span)?;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    recover(
+                        &mut diagnostics,
+                        span.error(DiagnosticCode::InvalidEdge, format!("{err:#}")),
+                    )?;
+                    continue;
+                }
             }
 
-            if parse_node_line(
+            if let Err(err) = parse_node_line(
                 line,
                 &mut nodes,
                 &mut order,
                 &mut node_membership,
                 &mut subgraph_stack,
-            )? {
-                continue;
+            ) {
+                recover(
+                    &mut diagnostics,
+                    span.error(DiagnosticCode::InvalidNode, format!("{err:#}")),
+                )?;
             }
+            ParseLimits::check(limits.max_nodes, nodes.len(), "node count", span)?;
         }
 
-        if let Some(unclosed) = subgraph_stack.last() {
-            bail!("subgraph '{}' missing closing 'end'", unclosed.id);
+        while let (Some(unclosed), Some(span)) = (subgraph_stack.last(), subgraph_spans.pop()) {
+            recover(
+                &mut diagnostics,
+                span.error(
+                    DiagnosticCode::UnclosedSubgraph,
+                    format!("subgraph '{}' missing closing 'end'", unclosed.id),
+                ),
+            )?;
+            close_subgraph(&mut subgraph_stack, &mut top_subgraphs);
         }
 
-        for (node_id, image) in image_comments {
+        for (node_id, (image, span)) in image_comments {
             let Some(node) = nodes.get_mut(&node_id) else {
-                bail!("image comment references unknown node '{node_id}'");
+                recover(
+                    &mut diagnostics,
+                    span.error(
+                        DiagnosticCode::UnknownNode,
+                        format!("image comment references unknown node '{node_id}'"),
+                    ),
+                )?;
+                continue;
             };
-            apply_image_to_node(node, image);
+            match image {
+                ImageComment::Inline(image) => apply_image_to_node(node, image),
+                ImageComment::Source(source) => {
+                    config.image_sources.insert(node_id, source);
+                }
+            }
+        }
+        config.rank_groups = resolve_rank_groups(rank_comments, &nodes, &mut diagnostics)?;
+        for (node_id, link, span) in click_statements {
+            if !nodes.contains_key(&node_id) {
+                recover(
+                    &mut diagnostics,
+                    span.error(
+                        DiagnosticCode::UnknownNode,
+                        format!("click statement references unknown node '{node_id}'"),
+                    ),
+                )?;
+                continue;
+            }
+            config.links.insert(node_id, link);
         }
 
         if nodes.is_empty() {
-            bail!("diagram does not declare any nodes");
+            return Err(header_span.error(
+                DiagnosticCode::EmptyDiagram,
+                "diagram does not declare any nodes",
+            ));
         }
 
-        Ok(Self {
+        let mut diagram = Self {
             kind: DiagramKind::Flowchart,
+            config,
             direction,
             nodes,
             order,
@@ -260,7 +1226,10 @@ impl Diagram {
                 .map(SubgraphBuilder::into_subgraph)
                 .collect(),
             node_membership,
-        })
+        };
+        limits.check_labels(&diagram, header_span)?;
+        diagram.apply_label_fitting();
+        Ok(diagram)
     }
 
     pub fn render_svg(
@@ -268,21 +1237,179 @@ impl Diagram {
         background: &str,
         overrides: Option<&LayoutOverrides>,
     ) -> Result<String> {
-        if let DiagramKind::Gantt(gantt) = &self.kind {
-            return self.render_gantt_svg(gantt, background, overrides);
-        }
+        self.render_svg_with(background, overrides, &[])
+    }
 
-        let layout = self.layout(overrides)?;
-        let geometry = align_geometry(
-            &layout.final_positions,
-            &layout.final_routes,
-            &self.edges,
-            &self.subgraphs,
-            &self.nodes,
-        )?;
+    /// Like [`Diagram::render_svg`], laying graph diagrams out with [`Diagram::layout_with`].
+    pub fn render_svg_with(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+    ) -> Result<String> {
+        self.render_svg_timed_with(background, overrides, passes, &mut PhaseTimer::default())
+    }
 
-        let mut clip_defs = String::new();
-        for id in &self.order {
+    /// Like [`Diagram::render_svg`], also returning how long each layout phase and the final
+    /// SVG assembly took.
+    pub fn render_svg_timed(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+    ) -> Result<(String, Vec<PhaseTiming>)> {
+        let mut timer = PhaseTimer::started();
+        let svg = self.render_svg_timed_with(background, overrides, &[], &mut timer)?;
+        timer.mark("render");
+        Ok((svg, timer.timings))
+    }
+
+    fn render_svg_timed_with(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+        timer: &mut PhaseTimer,
+    ) -> Result<String> {
+        let mut svg = self.render_kind_svg(background, overrides, passes, timer)?;
+        if self.config.monochrome {
+            svg = grayscale_colors(&svg);
+        }
+        let ink = self.ink_color();
+        if let Some(grid) = self.config.grid {
+            svg = draw_grid(svg, grid, &ink);
+        }
+        if let Some(stamp) = &self.config.watermark {
+            svg = stamp.apply(svg, &ink)?;
+        }
+        if self.config.embed_font {
+            svg = embed_font(svg)?;
+        }
+        if let Some(family) = &self.config.font_family {
+            svg = prepend_root_font_family(svg, family);
+        }
+        Ok(self.describe_svg(svg))
+    }
+
+    /// Color of the title, grid and watermark: the theme's text color, or black ink when
+    /// printing in monochrome.
+    fn ink_color(&self) -> String {
+        if self.config.monochrome {
+            MONOCHROME_INK.to_string()
+        } else {
+            self.config.resolved_theme().text
+        }
+    }
+
+    /// Whether `id` gets the marching-dash animation, per its style override or `animateEdges`.
+    fn edge_animated(&self, id: &str, overrides: Option<&LayoutOverrides>) -> bool {
+        overrides
+            .and_then(|overrides| overrides.edge_styles.get(id))
+            .and_then(|style| style.animated)
+            .unwrap_or(self.config.animate_edges)
+    }
+
+    /// Marks the root element as an image and adds the `<title>` and `<desc>` screen readers
+    /// announce for it.
+    fn describe_svg(&self, svg: String) -> String {
+        let Some(root) = svg.find("<svg") else {
+            return svg;
+        };
+        let root_end = root + svg[root..].find('>').unwrap_or(0);
+        let title = self
+            .config
+            .acc_title
+            .as_ref()
+            .or(self.config.title.as_ref());
+        let mut described = svg[..root_end].to_string();
+        described.push_str(" role=\"img\"");
+        if title.is_some() {
+            described.push_str(" aria-labelledby=\"oxdraw-title\"");
+        }
+        if self.config.acc_descr.is_some() {
+            described.push_str(" aria-describedby=\"oxdraw-desc\"");
+        }
+        described.push('>');
+        if let Some(title) = title {
+            described.push_str(&format!(
+                "\n  <title id=\"oxdraw-title\">{}</title>",
+                escape_xml(title)
+            ));
+        }
+        if let Some(descr) = &self.config.acc_descr {
+            described.push_str(&format!(
+                "\n  <desc id=\"oxdraw-desc\">{}</desc>",
+                escape_xml(descr)
+            ));
+        }
+        described.push_str(&svg[root_end + 1..]);
+        described
+    }
+
+    fn render_kind_svg(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+        timer: &mut PhaseTimer,
+    ) -> Result<String> {
+        // Print always lands on white paper, where black ink keeps its contrast.
+        let background = if self.config.monochrome && !is_transparent(background) {
+            MONOCHROME_PAPER
+        } else {
+            background
+        };
+        let palette = if self.config.monochrome {
+            Palette::Grayscale
+        } else {
+            self.config.palette
+        };
+        match &self.kind {
+            DiagramKind::Gantt(gantt) => {
+                return self.render_gantt_svg(gantt, background, overrides);
+            }
+            DiagramKind::Sequence(sequence) => return sequence.render_svg(background),
+            DiagramKind::Pie(pie) => return pie.render_svg(background, palette),
+            DiagramKind::Mindmap(mindmap) => {
+                return mindmap.render_svg(background, palette);
+            }
+            DiagramKind::Journey(journey) => {
+                return journey.render_svg(background, palette);
+            }
+            DiagramKind::Timeline(timeline) => {
+                return timeline.render_svg(background, palette);
+            }
+            DiagramKind::GitGraph(gitgraph) => {
+                return gitgraph.render_svg(background, palette);
+            }
+            DiagramKind::Quadrant(quadrant) => {
+                return quadrant.render_svg(background, palette);
+            }
+            DiagramKind::Flowchart
+            | DiagramKind::State(_)
+            | DiagramKind::Class(_)
+            | DiagramKind::Er(_)
+            | DiagramKind::C4(_)
+            | DiagramKind::Block(_) => {}
+        }
+        let class_data = match &self.kind {
+            DiagramKind::Class(class) => Some(class),
+            _ => None,
+        };
+        let er_data = match &self.kind {
+            DiagramKind::Er(er) => Some(er),
+            _ => None,
+        };
+        let c4_data = match &self.kind {
+            DiagramKind::C4(c4) => Some(c4),
+            _ => None,
+        };
+        let theme = self.config.resolved_theme();
+
+        let layout = self.layout_timed_with(overrides, passes, timer)?;
+        let geometry = self.geometry(&layout)?;
+
+        let mut clip_defs = String::new();
+        for id in &self.order {
             let Some(node) = self.nodes.get(id) else {
                 continue;
             };
@@ -305,7 +1432,7 @@ impl Diagram {
         write!(
             svg,
             r##"<?xml version="1.0" encoding="UTF-8"?>
-<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="{}">
   <defs>
         <marker id="arrow-end" markerWidth="8" markerHeight="8" refX="6" refY="4" orient="auto" markerUnits="strokeWidth">
             <path d="M1,1 L6,4 L1,7 z" fill="context-stroke" />
@@ -314,33 +1441,84 @@ impl Diagram {
             <path d="M7,1 L2,4 L7,7 z" fill="context-stroke" />
         </marker>
 "##,
-            geometry.width, geometry.height, geometry.width, geometry.height,
+            geometry.width,
+            geometry.height,
+            geometry.width,
+            geometry.height,
+            escape_xml(&theme.font_family),
         )?;
+        if class_data.is_some() {
+            svg.push_str(CLASS_MARKER_DEFS);
+        }
+        if self.edges.iter().any(|edge| edge.head != EdgeHead::Arrow) {
+            svg.push_str(EDGE_HEAD_MARKER_DEFS);
+        }
+        if er_data.is_some() {
+            svg.push_str(ER_MARKER_DEFS);
+        }
+        if self.config.monochrome {
+            svg.push_str(MONOCHROME_PATTERN_DEFS);
+        }
         svg.push_str(&clip_defs);
         write!(
             svg,
-            "  </defs>\n  <rect width=\"100%\" height=\"100%\" fill=\"{}\" />\n",
-            escape_xml(background)
+            "  </defs>\n{}  <rect width=\"100%\" height=\"100%\" fill=\"{}\" />\n",
+            self.config.style_block(),
+            escape_xml(page_fill(background))
         )?;
+        if self.edges.iter().any(|edge| {
+            edge.kind != EdgeKind::Invisible
+                && self.edge_animated(&edge_identifier(edge), overrides)
+        }) {
+            svg.push_str(EDGE_FLOW_STYLE);
+        }
+
+        if let Some(title) = &self.config.title {
+            writeln!(
+                svg,
+                "  <text class=\"title\" x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"{}\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                geometry.width / 2.0,
+                (self.config.canvas_margin() + TITLE_AREA) / 2.0,
+                escape_xml(&self.ink_color()),
+                TITLE_FONT_SIZE,
+                escape_xml(title)
+            )?;
+        }
 
-        let subgraph_fill = "#edf2f7";
-        let subgraph_stroke = "#a0aec0";
-        let subgraph_label = "#2d3748";
+        let (subgraph_stroke, subgraph_label) = if self.config.monochrome {
+            (MONOCHROME_INK, MONOCHROME_INK)
+        } else {
+            (theme.subgraph_stroke.as_str(), theme.subgraph_text.as_str())
+        };
+        let subgraph_fills = subgraph_fills(&geometry.subgraphs, &theme);
+        // C4 boundaries are conventionally dashed.
+        let subgraph_dash = if c4_data.is_some() {
+            " stroke-dasharray=\"6 4\""
+        } else {
+            ""
+        };
 
         for subgraph in &geometry.subgraphs {
+            let subgraph_fill = if self.config.monochrome {
+                MONOCHROME_PAPER
+            } else {
+                subgraph_fills[subgraph.id.as_str()].as_str()
+            };
             write!(
                 svg,
-                "  <g class=\"subgraph\" data-id=\"{}\">\n    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"16\" ry=\"16\" fill=\"{}\" fill-opacity=\"0.7\" stroke=\"{}\" stroke-width=\"1.5\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"start\" dominant-baseline=\"hanging\">{}</text>\n  </g>\n",
+                "  <g class=\"subgraph\" data-id=\"{}\" data-subgraph-id=\"{}\">\n    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"16\" ry=\"16\" fill=\"{}\" fill-opacity=\"0.7\" stroke=\"{}\" stroke-width=\"1.5\"{} />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"start\" dominant-baseline=\"hanging\">{}</text>\n  </g>\n",
+                escape_xml(&subgraph.id),
                 escape_xml(&subgraph.id),
                 subgraph.x,
                 subgraph.y,
                 subgraph.width,
                 subgraph.height,
-                subgraph_fill,
-                subgraph_stroke,
+                escape_xml(subgraph_fill),
+                escape_xml(subgraph_stroke),
+                subgraph_dash,
                 subgraph.label_x,
                 subgraph.label_y,
-                subgraph_label,
+                escape_xml(subgraph_label),
                 escape_xml(&subgraph.label)
             )?;
         }
@@ -353,15 +1531,10 @@ impl Diagram {
                 .cloned()
                 .ok_or_else(|| anyhow!("missing geometry for edge '{id}'"))?;
 
-            write!(
-                svg,
-                "  <g class=\"edge\" data-id=\"{}\">\n",
-                escape_xml(&id)
-            )?;
-
-            let mut stroke_color = "#2d3748".to_string();
+            let mut stroke_color = theme.edge.clone();
             let mut effective_kind = edge.kind;
             let mut arrow_direction = edge.arrow;
+            let edge_style = overrides.and_then(|overrides| overrides.edge_styles.get(&id));
 
             if let Some(overrides) = overrides {
                 if let Some(style) = overrides.edge_styles.get(&id) {
@@ -376,6 +1549,26 @@ impl Diagram {
                     }
                 }
             }
+            if self.config.monochrome {
+                stroke_color = MONOCHROME_INK.to_string();
+            }
+
+            let animated_class =
+                if effective_kind != EdgeKind::Invisible && self.edge_animated(&id, overrides) {
+                    " edge-animated"
+                } else {
+                    ""
+                };
+            write!(
+                svg,
+                "  <g class=\"edge edge-{}{}\" data-id=\"{}\" data-edge-id=\"{}\" data-from=\"{}\" data-to=\"{}\">\n",
+                effective_kind.as_str(),
+                animated_class,
+                escape_xml(&id),
+                escape_xml(&id),
+                escape_xml(&edge.from),
+                escape_xml(&edge.to)
+            )?;
 
             let (stroke_width_value, dash_pattern, stroke_opacity) = match effective_kind {
                 EdgeKind::Solid => (2.0_f32, None, 1.0_f32),
@@ -406,16 +1599,28 @@ impl Diagram {
             let dash_attr_ref = dash_attr.as_str();
             let opacity_attr_ref = opacity_attr.as_str();
 
-            let marker_start_attr = if arrow_direction.marker_start() {
-                " marker-start=\"url(#arrow-start)\""
-            } else {
-                ""
+            let is_junction = |id: &str| {
+                self.nodes
+                    .get(id)
+                    .is_some_and(|node| node.shape == NodeShape::Junction)
             };
-
-            let marker_end_attr = if arrow_direction.marker_end() {
-                " marker-end=\"url(#arrow-end)\""
-            } else {
-                ""
+            let class_relation = class_data.and_then(|class| class.relations.get(&id));
+            let er_relationship = er_data.and_then(|er| er.relationships.get(&id));
+            let (marker_start_attr, marker_end_attr) = match (class_relation, er_relationship) {
+                (Some(relation), _) => relation.marker_attrs(),
+                (None, Some(relationship)) => relationship.marker_attrs(),
+                (None, None) => (
+                    if arrow_direction.marker_start() && !is_junction(&edge.from) {
+                        format!(" marker-start=\"url(#{}-start)\"", edge.head.as_str())
+                    } else {
+                        String::new()
+                    },
+                    if arrow_direction.marker_end() && !is_junction(&edge.to) {
+                        format!(" marker-end=\"url(#{}-end)\"", edge.head.as_str())
+                    } else {
+                        String::new()
+                    },
+                ),
             };
 
             if route.len() == 2 {
@@ -454,9 +1659,18 @@ impl Diagram {
                 )?;
             }
 
+            if let Some(class) = class_data {
+                class.render_cardinalities(&mut svg, &id, &route, &stroke_color)?;
+            }
+
             if let Some(label) = &edge.label {
-                let label_center = label_center_for_route(&route);
+                let label_center = geometry
+                    .labels
+                    .get(&id)
+                    .copied()
+                    .unwrap_or_else(|| edge_label_center(edge, &route));
                 let lines = normalize_label_lines(label);
+                let markup = label_markup_lines(label);
 
                 if lines.is_empty() {
                     continue;
@@ -466,20 +1680,34 @@ impl Diagram {
                 let rect_x = label_center.x - box_width / 2.0;
                 let rect_y = label_center.y - box_height / 2.0;
 
-                write!(
-                    svg,
-                    "  <g pointer-events=\"none\">\n    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" ry=\"6\" fill=\"white\" fill-opacity=\"0.96\" stroke=\"{}\" stroke-width=\"1\" />\n",
-                    rect_x, rect_y, box_width, box_height, stroke_color
-                )?;
+                let colors = self.edge_label_colors(&theme, background, edge_style, &stroke_color);
+                svg.push_str("  <g pointer-events=\"none\">\n");
+                let text_paint = match &colors.fill {
+                    Some(fill) => {
+                        writeln!(
+                            svg,
+                            "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" ry=\"6\" fill=\"{}\" fill-opacity=\"0.96\" stroke=\"{}\" stroke-width=\"1\" />",
+                            rect_x,
+                            rect_y,
+                            box_width,
+                            box_height,
+                            escape_xml(fill),
+                            escape_xml(&colors.stroke)
+                        )?;
+                        format!("fill=\"{}\"", colors.text)
+                    }
+                    None => format!(
+                        "fill=\"{}\" stroke=\"{}\" stroke-width=\"3\" stroke-linejoin=\"round\" paint-order=\"stroke\"",
+                        colors.text, colors.halo
+                    ),
+                };
 
                 if lines.len() <= 1 {
-                    if let Some(single_line) = lines.first() {
+                    if let Some(single_line) = markup.first() {
                         write!(
                             svg,
-                            "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"13\" text-anchor=\"middle\" dominant-baseline=\"middle\" xml:space=\"preserve\">{}</text>\n",
-                            label_center.x,
-                            label_center.y,
-                            escape_xml(single_line)
+                            "    <text x=\"{:.1}\" y=\"{:.1}\" {} font-size=\"13\" text-anchor=\"middle\" dominant-baseline=\"middle\" xml:space=\"preserve\">{}</text>\n",
+                            label_center.x, label_center.y, text_paint, single_line
                         )?;
                     }
                 } else {
@@ -487,17 +1715,15 @@ impl Diagram {
                         label_center.y - EDGE_LABEL_LINE_HEIGHT * (lines.len() as f32 - 1.0) / 2.0;
                     write!(
                         svg,
-                        "    <text x=\"{:.1}\" fill=\"#2d3748\" font-size=\"13\" text-anchor=\"middle\">\n",
-                        label_center.x
+                        "    <text x=\"{:.1}\" {} font-size=\"13\" text-anchor=\"middle\">\n",
+                        label_center.x, text_paint
                     )?;
-                    for (idx, line_text) in lines.iter().enumerate() {
+                    for (idx, line_text) in markup.iter().enumerate() {
                         let line_y = start_y + EDGE_LABEL_LINE_HEIGHT * idx as f32;
                         write!(
                             svg,
                             "      <tspan x=\"{:.1}\" y=\"{:.1}\" dominant-baseline=\"middle\">{}</tspan>\n",
-                            label_center.x,
-                            line_y,
-                            escape_xml(line_text)
+                            label_center.x, line_y, line_text
                         )?;
                     }
                     svg.push_str("    </text>\n");
@@ -516,11 +1742,12 @@ impl Diagram {
                 .copied()
                 .ok_or_else(|| anyhow!("missing geometry for node '{id}'"))?;
 
-            let mut fill_color = node.shape.default_fill_color().to_string();
-            let mut stroke_color = "#2d3748".to_string();
-            let mut text_color = "#1a202c".to_string();
+            let (mut fill_color, mut stroke_color, mut text_color) =
+                self.base_node_colors(&theme, id, node.shape);
             let mut label_fill_override: Option<String> = None;
             let mut image_fill_override: Option<String> = None;
+            let mut border_width: Option<f32> = None;
+            let mut border_style: Option<BorderStyle> = None;
 
             if let Some(overrides) = overrides {
                 if let Some(style) = overrides.node_styles.get(id) {
@@ -539,8 +1766,17 @@ impl Diagram {
                     if let Some(image_fill) = &style.image_fill {
                         image_fill_override = Some(image_fill.clone());
                     }
+                    border_width = style.stroke_width;
+                    border_style = style.border_style;
                 }
             }
+            if self.config.monochrome {
+                fill_color = monochrome_fill(node.shape).to_string();
+                stroke_color = MONOCHROME_INK.to_string();
+                text_color = MONOCHROME_INK.to_string();
+                label_fill_override = Some(MONOCHROME_PAPER.to_string());
+                image_fill_override = None;
+            }
 
             let base_fill_color = fill_color.clone();
             let has_image = node.image.is_some();
@@ -563,18 +1799,130 @@ impl Diagram {
                     .unwrap_or_else(|| image_fill_color.clone())
             };
 
-            write!(svg, "  <g class=\"node\" data-id=\"{}\">\n", escape_xml(id))?;
-
-            node.shape.render_svg_shape(
-                &mut svg,
-                position,
-                node.width,
-                node.height,
-                &image_fill_color,
-                &stroke_color,
+            let link = self.config.links.get(id.as_str());
+            if let Some(link) = link {
+                let href = escape_xml(&link.href);
+                write!(svg, "  <a href=\"{href}\" xlink:href=\"{href}\"")?;
+                if let Some(target) = &link.target {
+                    write!(svg, " target=\"{}\"", escape_xml(target))?;
+                }
+                svg.push_str(">\n");
+                if let Some(tooltip) = &link.tooltip {
+                    writeln!(svg, "  <title>{}</title>", escape_xml(tooltip))?;
+                }
+            }
+            let group_end = if link.is_some() {
+                "  </g>\n  </a>\n"
+            } else {
+                "  </g>\n"
+            };
+            write!(
+                svg,
+                "  <g class=\"node node-{}\" data-id=\"{}\" data-node-id=\"{}\">\n",
+                node.shape.as_str(),
+                escape_xml(id),
+                escape_xml(id)
+            )?;
+            let spoken = split_label_icon(&node.label).map(|(_, text)| text);
+            let spoken_label: Vec<String> =
+                normalize_label_lines(spoken.as_deref().unwrap_or(&node.label))
+                    .iter()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect();
+            writeln!(
+                svg,
+                "    <title>{}</title>",
+                escape_xml(&spoken_label.join(" "))
             )?;
 
-            let lines = normalize_label_lines(&node.label);
+            if let Some(class) = class_data
+                && class.render_class_box(
+                    &mut svg,
+                    id,
+                    &node.label,
+                    position,
+                    node.width,
+                    node.height,
+                    &fill_color,
+                    &stroke_color,
+                    &text_color,
+                )?
+            {
+                svg.push_str(group_end);
+                continue;
+            }
+            if let Some(c4) = c4_data
+                && c4.render_element(
+                    &mut svg,
+                    id,
+                    &node.label,
+                    position,
+                    node.width,
+                    node.height,
+                    &fill_color,
+                    &stroke_color,
+                    &text_color,
+                )?
+            {
+                svg.push_str(group_end);
+                continue;
+            }
+            if let Some(er) = er_data
+                && er.render_entity(
+                    &mut svg,
+                    id,
+                    &node.label,
+                    position,
+                    node.width,
+                    node.height,
+                    &fill_color,
+                    &stroke_color,
+                    &text_color,
+                )?
+            {
+                svg.push_str(group_end);
+                continue;
+            }
+
+            if border_width.is_none() && border_style.is_none() {
+                node.shape.render_svg_shape(
+                    &mut svg,
+                    position,
+                    node.width,
+                    node.height,
+                    &image_fill_color,
+                    &stroke_color,
+                )?;
+            } else {
+                let mut shape = String::new();
+                node.shape.render_svg_shape(
+                    &mut shape,
+                    position,
+                    node.width,
+                    node.height,
+                    &image_fill_color,
+                    &stroke_color,
+                )?;
+                svg.push_str(&restyle_node_border(&shape, border_width, border_style));
+            }
+
+            let label = if node.image.is_none() {
+                self.fitted_label(&node.label)
+            } else {
+                Cow::Borrowed(node.label.as_str())
+            };
+            let icon = split_label_icon(&label);
+            let label = match &icon {
+                Some((_, text)) => Cow::Owned(text.clone()),
+                None => label,
+            };
+            let lines = if node.shape == NodeShape::Junction {
+                Vec::new()
+            } else {
+                normalize_label_lines(&label)
+            };
+            let markup = label_markup_lines(&label);
             let mut label_area_height = 0.0_f32;
 
             if let Some(image) = &node.image {
@@ -627,17 +1975,38 @@ impl Diagram {
             }
 
             if !lines.is_empty() {
+                let mut text_x = position.x;
+                if let Some((name, _)) = &icon {
+                    let icon_width = NODE_ICON_SIZE + NODE_ICON_GAP;
+                    let text_block = lines
+                        .iter()
+                        .map(|line| text_width(line, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH))
+                        .fold(0.0_f32, f32::max);
+                    text_x += icon_width / 2.0;
+                    let first_line_y = if node.image.is_some() {
+                        let label_top = position.y - node.height / 2.0;
+                        label_top
+                            + (label_area_height - NODE_TEXT_LINE_HEIGHT * lines.len() as f32) / 2.0
+                            + NODE_TEXT_LINE_HEIGHT / 2.0
+                    } else {
+                        position.y - NODE_TEXT_LINE_HEIGHT * (lines.len() as f32 - 1.0) / 2.0
+                    };
+                    svg.push_str(&icon_markup(
+                        name,
+                        &self.config.icons,
+                        text_x - text_block / 2.0 - icon_width,
+                        first_line_y - NODE_ICON_SIZE / 2.0,
+                        &text_color,
+                    ));
+                }
                 if node.image.is_some() {
-                    let text_anchor_x = position.x;
+                    let text_anchor_x = text_x;
                     if lines.len() == 1 {
                         let baseline = position.y - node.height / 2.0 + label_area_height / 2.0;
                         write!(
                             svg,
                             "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>\n",
-                            text_anchor_x,
-                            baseline,
-                            text_color,
-                            escape_xml(&lines[0])
+                            text_anchor_x, baseline, text_color, markup[0]
                         )?;
                     } else {
                         let total_text_height = NODE_TEXT_LINE_HEIGHT * lines.len() as f32;
@@ -650,14 +2019,12 @@ impl Diagram {
                             "  <text x=\"{:.1}\" fill=\"{}\" font-size=\"14\" text-anchor=\"middle\">\n",
                             text_anchor_x, text_color
                         )?;
-                        for (idx, line_text) in lines.iter().enumerate() {
+                        for (idx, line_text) in markup.iter().enumerate() {
                             let line_y = start_y + NODE_TEXT_LINE_HEIGHT * idx as f32;
                             write!(
                                 svg,
                                 "    <tspan x=\"{:.1}\" y=\"{:.1}\" dominant-baseline=\"middle\">{}</tspan>\n",
-                                text_anchor_x,
-                                line_y,
-                                escape_xml(line_text)
+                                text_anchor_x, line_y, line_text
                             )?;
                         }
                         svg.push_str("  </text>\n");
@@ -666,10 +2033,7 @@ impl Diagram {
                     write!(
                         svg,
                         "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>\n",
-                        position.x,
-                        position.y,
-                        text_color,
-                        escape_xml(&lines[0])
+                        text_x, position.y, text_color, markup[0]
                     )?;
                 } else {
                     let start_y =
@@ -677,94 +2041,552 @@ impl Diagram {
                     write!(
                         svg,
                         "  <text x=\"{:.1}\" fill=\"{}\" font-size=\"14\" text-anchor=\"middle\">\n",
-                        position.x, text_color
+                        text_x, text_color
                     )?;
-                    for (idx, line_text) in lines.iter().enumerate() {
+                    for (idx, line_text) in markup.iter().enumerate() {
                         let line_y = start_y + NODE_TEXT_LINE_HEIGHT * idx as f32;
                         write!(
                             svg,
                             "    <tspan x=\"{:.1}\" y=\"{:.1}\" dominant-baseline=\"middle\">{}</tspan>\n",
-                            position.x,
-                            line_y,
-                            escape_xml(line_text)
+                            text_x, line_y, line_text
                         )?;
                     }
                     svg.push_str("  </text>\n");
                 }
             }
 
-            svg.push_str("  </g>\n");
+            svg.push_str(group_end);
         }
 
         svg.push_str("</svg>\n");
+        if let Some(c4) = c4_data {
+            c4.append_legend(
+                &mut svg,
+                geometry.width,
+                geometry.height,
+                !geometry.subgraphs.is_empty(),
+            )?;
+        }
         Ok(svg)
     }
 
+    /// Per-edge overrides win over `themeVariables`; otherwise label boxes take the theme's
+    /// label background, or the page background, and the edge color as their border.
+    fn edge_label_colors(
+        &self,
+        theme: &Theme,
+        background: &str,
+        style: Option<&EdgeStyleOverride>,
+        edge_color: &str,
+    ) -> EdgeLabelColors {
+        let page = if Rgb::parse(background).is_some() {
+            background
+        } else if Rgb::parse(&theme.background).is_some() {
+            &theme.background
+        } else {
+            "white"
+        };
+        let fill = style
+            .and_then(|style| style.label_fill.clone())
+            .or_else(|| self.config.edge_label_fill.clone())
+            .or_else(|| theme.edge_label_background.clone())
+            // A transparent page has no color to match, so labels go boxless with a halo
+            // instead of baking a white box into the export.
+            .unwrap_or_else(|| {
+                if is_transparent(background) {
+                    "none".to_string()
+                } else {
+                    page.to_string()
+                }
+            });
+        let fill = match fill.trim().to_ascii_lowercase().as_str() {
+            "none" | "transparent" => None,
+            _ => Some(fill),
+        };
+        let stroke = style
+            .and_then(|style| style.label_stroke.clone())
+            .or_else(|| self.config.edge_label_stroke.clone())
+            .unwrap_or_else(|| edge_color.to_string());
+
+        let behind = fill.as_deref().unwrap_or(page);
+        let dark_text = Rgb::parse(EDGE_LABEL_TEXT).expect("valid edge label text color");
+        let text = match Rgb::parse(behind) {
+            Some(behind) if behind.contrast_ratio(dark_text) < WCAG_AA_CONTRAST => "#f7fafc",
+            _ => EDGE_LABEL_TEXT,
+        };
+        if self.config.monochrome {
+            return EdgeLabelColors {
+                fill: fill.map(|_| MONOCHROME_PAPER.to_string()),
+                stroke: MONOCHROME_INK.to_string(),
+                text: MONOCHROME_INK,
+                halo: MONOCHROME_PAPER.to_string(),
+            };
+        }
+        EdgeLabelColors {
+            fill,
+            stroke,
+            text,
+            halo: escape_xml(page),
+        }
+    }
+
+    /// Fill, stroke and text colors a node gets from the theme before style overrides.
+    fn base_node_colors(
+        &self,
+        theme: &Theme,
+        id: &str,
+        shape: NodeShape,
+    ) -> (String, String, String) {
+        if let DiagramKind::C4(c4) = &self.kind
+            && let Some(element) = c4.elements.get(id)
+        {
+            let (fill, stroke) = element.colors();
+            return (
+                fill.to_string(),
+                stroke.to_string(),
+                readable_text_color(fill).to_string(),
+            );
+        }
+        (
+            theme.node_fill(shape, self.config.palette),
+            theme.node_stroke.clone(),
+            theme.node_text.clone(),
+        )
+    }
+
+    /// Checks every node's label color against the fill it is drawn on, honoring style overrides
+    /// and the active palette. Colors that cannot be parsed are skipped.
+    pub fn contrast_warnings(&self, overrides: Option<&LayoutOverrides>) -> Vec<ContrastWarning> {
+        if !matches!(
+            self.kind,
+            DiagramKind::Flowchart
+                | DiagramKind::State(_)
+                | DiagramKind::Class(_)
+                | DiagramKind::Er(_)
+                | DiagramKind::C4(_)
+                | DiagramKind::Block(_)
+        ) {
+            return Vec::new();
+        }
+
+        let theme = self.config.resolved_theme();
+        let mut warnings = Vec::new();
+        for id in &self.order {
+            let Some(node) = self.nodes.get(id) else {
+                continue;
+            };
+            if node.shape == NodeShape::Junction {
+                continue;
+            }
+            let style = overrides.and_then(|overrides| overrides.node_styles.get(id));
+            let (base_fill, _, base_text) = self.base_node_colors(&theme, id, node.shape);
+            let fill = style
+                .and_then(|style| style.fill.clone())
+                .unwrap_or(base_fill);
+            let background = match (
+                &node.image,
+                style.and_then(|style| style.label_fill.clone()),
+            ) {
+                (Some(_), Some(label_fill)) => label_fill,
+                _ => fill,
+            };
+            let text = style
+                .and_then(|style| style.text.clone())
+                .unwrap_or(base_text);
+
+            let (Some(text_rgb), Some(background_rgb)) =
+                (Rgb::parse(&text), Rgb::parse(&background))
+            else {
+                continue;
+            };
+            let ratio = text_rgb.contrast_ratio(background_rgb);
+            if ratio < WCAG_AA_CONTRAST {
+                warnings.push(ContrastWarning {
+                    node_id: id.clone(),
+                    text,
+                    background,
+                    ratio,
+                    suggested_text: suggest_text_color(text_rgb, background_rgb, WCAG_AA_CONTRAST)
+                        .to_hex(),
+                });
+            }
+        }
+        warnings
+    }
+
     pub fn render_png(
         &self,
         background: &str,
         overrides: Option<&LayoutOverrides>,
         scale: f32,
+    ) -> Result<Vec<u8>> {
+        self.render_raster(background, overrides, scale, RasterFormat::Png)
+    }
+
+    /// Like [`Diagram::render_png`], encoding the image as `format`.
+    pub fn render_raster(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        scale: f32,
+        format: RasterFormat,
     ) -> Result<Vec<u8>> {
         if scale <= 0.0 {
-            bail!("scale must be greater than zero when rendering PNG output");
+            bail!(
+                "scale must be greater than zero when rendering {} output",
+                format.name()
+            );
         }
 
-        let svg = self.render_svg(background, overrides)?;
-
-        let mut options = resvg::usvg::Options::default();
-        options.font_family = "Inter".to_string();
-        options.fontdb_mut().load_system_fonts();
-
-        let tree = resvg::usvg::Tree::from_str(&svg, &options)
-            .map_err(|err| anyhow!("failed to parse generated SVG for PNG export: {err}"))?;
-
-        let size = tree.size().to_int_size();
-        let width = size.width();
-        let height = size.height();
+        self.render_raster_sized(background, overrides, &RasterSize::scaled(scale), format)
+    }
 
-        let scaled_width = ((width as f32) * scale).ceil();
-        let scaled_height = ((height as f32) * scale).ceil();
+    /// Like [`Diagram::render_raster`], sizing the image by `size`, e.g. an exact width for
+    /// slides.
+    pub fn render_raster_sized(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        size: &RasterSize,
+        format: RasterFormat,
+    ) -> Result<Vec<u8>> {
+        let svg = self.render_svg(background, overrides)?;
+        rasterize_svg_sized(&svg, size, format, &self.config.font_files)
+    }
 
-        if !scaled_width.is_finite() || !scaled_height.is_finite() {
-            bail!("scaled dimensions are not finite; try a smaller scale factor");
+    /// Renders the diagram cropped to the editor viewport, with the editor's selection styling.
+    pub fn render_viewport_svg(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        viewport: &ViewportRender,
+    ) -> Result<String> {
+        if !(viewport.width > 0.0 && viewport.height > 0.0) {
+            bail!("viewport width and height must be greater than zero");
         }
-
-        if scaled_width < 1.0 || scaled_height < 1.0 {
-            bail!("scaled dimensions collapsed below 1px; try a larger scale factor");
+        if !(viewport.zoom > 0.0 && viewport.zoom.is_finite()) {
+            bail!("viewport zoom must be greater than zero");
         }
-
-        if scaled_width > u32::MAX as f32 || scaled_height > u32::MAX as f32 {
-            bail!("scaled dimensions exceed supported limits; try a smaller scale factor");
+        let (width, height) = (
+            (viewport.width * viewport.zoom).ceil(),
+            (viewport.height * viewport.zoom).ceil(),
+        );
+        if width > MAX_VIEWPORT_SIDE
+            || height > MAX_VIEWPORT_SIDE
+            || width * height > MAX_VIEWPORT_PIXELS
+        {
+            bail!(
+                "viewport of {width:.0}x{height:.0} pixels exceeds the limit of {MAX_VIEWPORT_SIDE:.0} per side and {MAX_VIEWPORT_PIXELS:.0} in total"
+            );
         }
 
-        let scaled_width = scaled_width as u32;
-        let scaled_height = scaled_height as u32;
+        let mut svg = self.render_svg(background, overrides)?;
 
-        let mut pixmap = Pixmap::new(scaled_width, scaled_height).ok_or_else(|| {
-            anyhow!("failed to allocate {scaled_width}x{scaled_height} surface for PNG export")
-        })?;
+        let Some(size) = svg_size_regex().find(&svg).map(|m| m.range()) else {
+            bail!("generated SVG is missing its root dimensions");
+        };
+        svg.replace_range(
+            size,
+            &format!(
+                "width=\"{width:.0}\" height=\"{height:.0}\" viewBox=\"{:.1} {:.1} {:.1} {:.1}\"",
+                viewport.x, viewport.y, viewport.width, viewport.height
+            ),
+        );
 
-        let transform = Transform::from_scale(scale, scale);
-        resvg::render(&tree, transform, &mut pixmap.as_mut());
+        for (class, ids) in [
+            ("node", &viewport.selected_nodes),
+            ("edge", &viewport.selected_edges),
+        ] {
+            for id in ids {
+                // Groups also carry a `node-<shape>` or `edge-<kind>` class after the first.
+                let group = Regex::new(&format!(
+                    "<g class=\"({class}(?: [a-z-]+)*)\" data-id=\"{}\"[ >]",
+                    regex::escape(&escape_xml(id))
+                ))?;
+                let Some(classes) = group.captures(&svg).map(|captures| captures[1].to_string())
+                else {
+                    bail!("selected {class} '{id}' is not part of the diagram");
+                };
+                svg = svg.replacen(
+                    &format!("<g class=\"{classes}\" data-id=\"{}\"", escape_xml(id)),
+                    &format!(
+                        "<g class=\"{classes} selected\" data-id=\"{}\"",
+                        escape_xml(id)
+                    ),
+                    1,
+                );
+            }
+        }
 
-        let png_data = pixmap
-            .encode_png()
-            .map_err(|err| anyhow!("failed to encode PNG output: {err}"))?;
+        // The full-canvas background rect starts at the origin, so cover the viewport as well.
+        let color = viewport.selection_color.as_deref().unwrap_or("#f472b6");
+        let header = format!(
+            "  <style>.node.selected rect, .node.selected ellipse, .node.selected polygon, .node.selected path, .node.selected line, .edge.selected path, .edge.selected polyline, .edge.selected line {{ stroke: {}; }}</style>\n  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" />\n",
+            escape_xml(color),
+            viewport.x,
+            viewport.y,
+            viewport.width,
+            viewport.height,
+            escape_xml(background)
+        );
+        let body_start = svg
+            .find("<svg ")
+            .and_then(|start| svg[start..].find('>').map(|end| start + end + 2))
+            .ok_or_else(|| anyhow!("generated SVG is missing its root element"))?;
+        svg.insert_str(body_start, &header);
 
-        Ok(png_data)
+        Ok(svg)
     }
 
-    fn render_gantt_svg(
+    pub fn render_viewport_png(
         &self,
-        gantt: &GanttData,
         background: &str,
         overrides: Option<&LayoutOverrides>,
-    ) -> Result<String> {
-        let gantt_overrides = overrides.map(|ov| &ov.gantt);
-        let gantt_styles = gantt_overrides.map(|ov| &ov.style);
+        viewport: &ViewportRender,
+    ) -> Result<Vec<u8>> {
+        let svg = self.render_viewport_svg(background, overrides, viewport)?;
+        rasterize_svg(&svg, 1.0)
+    }
 
-        let row_fill_even = gantt_styles
+    /// Renders a text-free overview of the whole diagram scaled to `minimap.size`, with the
+    /// requested viewport outlined on top.
+    pub fn render_minimap_svg(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+        minimap: &MinimapRender,
+    ) -> Result<String> {
+        if !(minimap.size > 0.0 && minimap.size.is_finite()) {
+            bail!("minimap size must be greater than zero");
+        }
+        let viewport = match (minimap.x, minimap.y, minimap.width, minimap.height) {
+            (None, None, None, None) => None,
+            (Some(x), Some(y), Some(width), Some(height)) if width > 0.0 && height > 0.0 => {
+                Some((x, y, width, height))
+            }
+            (Some(_), Some(_), Some(_), Some(_)) => {
+                bail!("minimap viewport width and height must be greater than zero")
+            }
+            _ => bail!("minimap viewport needs x, y, width and height"),
+        };
+
+        let svg = match &self.kind {
+            DiagramKind::Flowchart
+            | DiagramKind::State(_)
+            | DiagramKind::Class(_)
+            | DiagramKind::Er(_)
+            | DiagramKind::C4(_)
+            | DiagramKind::Block(_) => self.render_minimap_graph(background, overrides)?,
+            _ => {
+                let full = self.render_svg(background, overrides)?;
+                minimap_text_regex().replace_all(&full, "").into_owned()
+            }
+        };
+
+        let Some(caps) = svg_size_regex().captures(&svg) else {
+            bail!("generated SVG is missing its root dimensions");
+        };
+        let (Ok(width), Ok(height)) = (caps[1].parse::<f32>(), caps[2].parse::<f32>()) else {
+            bail!("generated SVG has non-numeric root dimensions");
+        };
+        let mut svg = zoom_svg(&svg, minimap.size / width.max(height))?;
+
+        if let Some((x, y, width, height)) = viewport {
+            let end = svg
+                .rfind("</svg>")
+                .ok_or_else(|| anyhow!("generated SVG is missing its root element"))?;
+            svg.insert_str(
+                end,
+                &format!(
+                    "  <rect class=\"minimap-viewport\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#3b82f6\" fill-opacity=\"0.12\" stroke=\"#2563eb\" stroke-width=\"2\" vector-effect=\"non-scaling-stroke\" />\n",
+                    x, y, width, height
+                ),
+            );
+        }
+        Ok(svg)
+    }
+
+    /// Lays the diagram out and collects node boxes, edge routes, subgraph rectangles and the
+    /// canvas size as the SVG would draw them, for tools that need the geometry without parsing
+    /// SVG. Only graph diagrams are laid out; sequence, Gantt and the chart kinds fail.
+    pub fn layout_geometry(&self, overrides: Option<&LayoutOverrides>) -> Result<LayoutGeometry> {
+        match &self.kind {
+            DiagramKind::Flowchart
+            | DiagramKind::State(_)
+            | DiagramKind::Class(_)
+            | DiagramKind::Er(_)
+            | DiagramKind::C4(_)
+            | DiagramKind::Block(_) => {}
+            _ => bail!("layout geometry is only available for graph diagrams"),
+        }
+        let layout = self.layout(overrides)?;
+        let geometry = self.geometry(&layout)?;
+        let nodes = self
+            .order
+            .iter()
+            .filter_map(|id| {
+                let node = self.nodes.get(id)?;
+                let center = geometry.positions.get(id)?;
+                Some(NodeGeometry {
+                    id: id.clone(),
+                    label: node.label.clone(),
+                    shape: node.shape.as_str(),
+                    x: center.x,
+                    y: center.y,
+                    width: node.width,
+                    height: node.height,
+                })
+            })
+            .collect();
+        let edges = self
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let id = edge_identifier(edge);
+                let points = geometry.edges.get(&id)?.clone();
+                let label_position = geometry.labels.get(&id).copied();
+                Some(EdgeGeometry {
+                    from: edge.from.clone(),
+                    to: edge.to.clone(),
+                    label: edge.label.clone(),
+                    points,
+                    label_position,
+                    id,
+                })
+            })
+            .collect();
+        Ok(LayoutGeometry {
+            canvas: CanvasSize {
+                width: geometry.width,
+                height: geometry.height,
+            },
+            nodes,
+            edges,
+            subgraphs: geometry.subgraphs,
+        })
+    }
+
+    /// Render-space geometry of `layout`, leaving room above for the `title` when there is
+    /// one.
+    pub fn geometry(&self, layout: &LayoutComputation) -> Result<Geometry> {
+        let mut geometry = align_geometry(
+            &layout.final_positions,
+            &layout.final_routes,
+            &self.edges,
+            &self.subgraphs,
+            &self.nodes,
+            self.config.canvas_margin(),
+            self.swimlane_direction(),
+        )?;
+        if let Some(title) = &self.config.title {
+            geometry.reserve_title_area(title, self.config.canvas_margin());
+        }
+        Ok(geometry)
+    }
+
+    /// Flowchart-style minimap body: subgraph boxes, bare edge routes and node shapes, without
+    /// labels, markers or images.
+    fn render_minimap_graph(
+        &self,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+    ) -> Result<String> {
+        let layout = self.layout(overrides)?;
+        let geometry = self.geometry(&layout)?;
+        let theme = self.config.resolved_theme();
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}">
+  <rect width="100%" height="100%" fill="{}" />"#,
+            geometry.width,
+            geometry.height,
+            geometry.width,
+            geometry.height,
+            escape_xml(page_fill(background))
+        )?;
+
+        let subgraph_fills = subgraph_fills(&geometry.subgraphs, &theme);
+        for subgraph in &geometry.subgraphs {
+            writeln!(
+                svg,
+                "  <rect class=\"minimap-subgraph\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"16\" ry=\"16\" fill=\"{}\" stroke=\"{}\" vector-effect=\"non-scaling-stroke\" />",
+                subgraph.x,
+                subgraph.y,
+                subgraph.width,
+                subgraph.height,
+                escape_xml(&subgraph_fills[subgraph.id.as_str()]),
+                escape_xml(&theme.subgraph_stroke)
+            )?;
+        }
+
+        for edge in &self.edges {
+            let id = edge_identifier(edge);
+            let style = overrides.and_then(|overrides| overrides.edge_styles.get(&id));
+            if style.and_then(|style| style.line).unwrap_or(edge.kind) == EdgeKind::Invisible {
+                continue;
+            }
+            let Some(route) = geometry.edges.get(&id) else {
+                continue;
+            };
+            let color = style
+                .and_then(|style| style.color.as_deref())
+                .filter(|_| !self.config.monochrome)
+                .unwrap_or("#718096");
+            let points: Vec<String> = route
+                .iter()
+                .map(|point| format!("{:.1},{:.1}", point.x, point.y))
+                .collect();
+            writeln!(
+                svg,
+                "  <polyline class=\"minimap-edge\" points=\"{}\" fill=\"none\" stroke=\"{}\" vector-effect=\"non-scaling-stroke\" />",
+                points.join(" "),
+                escape_xml(color)
+            )?;
+        }
+
+        for id in &self.order {
+            let (Some(node), Some(position)) = (self.nodes.get(id), geometry.positions.get(id))
+            else {
+                continue;
+            };
+            let fill = if self.config.monochrome {
+                MONOCHROME_PAPER.to_string()
+            } else {
+                overrides
+                    .and_then(|overrides| overrides.node_styles.get(id))
+                    .and_then(|style| style.fill.clone())
+                    .unwrap_or_else(|| self.base_node_colors(&theme, id, node.shape).0)
+            };
+            // vector-effect is not inherited, so it goes on each element of the outline.
+            let mut outline = String::new();
+            node.shape
+                .render_svg_clip_shape(&mut outline, *position, node.width, node.height)?;
+            write!(
+                svg,
+                "  <g class=\"minimap-node\" data-id=\"{}\" fill=\"{}\" stroke=\"#4a5568\">\n{}  </g>\n",
+                escape_xml(id),
+                escape_xml(&fill),
+                outline.replace(" />", " vector-effect=\"non-scaling-stroke\" />")
+            )?;
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    fn render_gantt_svg(
+        &self,
+        gantt: &GanttData,
+        background: &str,
+        overrides: Option<&LayoutOverrides>,
+    ) -> Result<String> {
+        let gantt_overrides = overrides.map(|ov| &ov.gantt);
+        let gantt_styles = gantt_overrides.map(|ov| &ov.style);
+
+        let row_fill_even = gantt_styles
             .and_then(|style| style.row_fill_even.as_deref())
             .unwrap_or("#eff6ff");
         let row_fill_odd = gantt_styles
@@ -852,7 +2674,7 @@ impl Diagram {
             height,
             width,
             height,
-            escape_xml(background),
+            escape_xml(page_fill(background)),
         )?;
 
         if let Some(title) = &gantt.title {
@@ -989,7 +2811,7 @@ impl Diagram {
                 )?;
                 let right_x = cx + half + 8.0;
                 let left_x = cx - half - 8.0;
-                let estimated_width = (task.label.chars().count() as f32) * 7.0;
+                let estimated_width = display_width(&task.label) as f32 * 7.0;
                 let fits_right = right_x + estimated_width <= width - 8.0;
                 let (label_x, anchor) = if fits_right {
                     (right_x, "start")
@@ -1044,16 +2866,120 @@ impl Diagram {
         Ok(svg)
     }
 
+    /// The flow direction when top-level subgraphs are drawn as swimlanes.
+    pub fn swimlane_direction(&self) -> Option<Direction> {
+        (self.config.layout == LayoutEngine::Swimlanes).then_some(self.direction)
+    }
+
     pub fn layout(&self, overrides: Option<&LayoutOverrides>) -> Result<LayoutComputation> {
-        let tree_components = self.tree_components();
-        let mut auto = self.compute_auto_layout(tree_components.as_deref());
-        let tree_subgraphs = self.align_subgraph_trees(&mut auto.positions);
-        self.separate_subgraphs(
-            &mut auto.positions,
-            tree_components.is_some(),
-            &tree_subgraphs,
+        self.layout_with(overrides, &[])
+    }
+
+    /// Like [`Diagram::layout`], running `passes` in order over the automatic positions.
+    pub fn layout_with(
+        &self,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+    ) -> Result<LayoutComputation> {
+        self.layout_timed_with(overrides, passes, &mut PhaseTimer::default())
+    }
+
+    /// Like [`Diagram::layout_with`], also returning how long each layout phase took.
+    pub fn layout_timed(
+        &self,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+    ) -> Result<(LayoutComputation, Vec<PhaseTiming>)> {
+        let mut timer = PhaseTimer::started();
+        let layout = self.layout_timed_with(overrides, passes, &mut timer)?;
+        Ok((layout, timer.timings))
+    }
+
+    fn layout_timed_with(
+        &self,
+        overrides: Option<&LayoutOverrides>,
+        passes: &[&dyn LayoutPass],
+        timer: &mut PhaseTimer,
+    ) -> Result<LayoutComputation> {
+        let mut ranked = HashMap::new();
+        let mut auto = if let DiagramKind::Block(block) = &self.kind {
+            // Blocks keep the grid cells they were given at parse time.
+            AutoLayout {
+                positions: block.positions.clone(),
+                size: CanvasSize {
+                    width: 0.0,
+                    height: 0.0,
+                },
+                waypoints: HashMap::new(),
+            }
+        } else if self.config.layout == LayoutEngine::Dot {
+            let mut auto = AutoLayout {
+                positions: self.dot_positions()?,
+                size: CanvasSize {
+                    width: 0.0,
+                    height: 0.0,
+                },
+                waypoints: HashMap::new(),
+            };
+            timer.mark("rank");
+            if let Some(overrides) = overrides {
+                self.place_around_pinned(&mut auto.positions, overrides);
+            }
+            auto
+        } else if let Some(positions) = self.tree_positions() {
+            let mut auto = AutoLayout {
+                positions,
+                size: CanvasSize {
+                    width: 0.0,
+                    height: 0.0,
+                },
+                waypoints: HashMap::new(),
+            };
+            timer.mark("rank");
+            if let Some(overrides) = overrides {
+                self.place_around_pinned(&mut auto.positions, overrides);
+            }
+            auto
+        } else if self.config.layout == LayoutEngine::Swimlanes {
+            let mut auto = self.compute_auto_layout(None);
+            ranked = auto.positions.clone();
+            timer.mark("rank");
+            self.arrange_swimlanes(&mut auto.positions);
+            if let Some(overrides) = overrides {
+                self.place_around_pinned(&mut auto.positions, overrides);
+            }
+            timer.mark("separate");
+            auto
+        } else {
+            let tree_components = self.tree_components();
+            let mut auto = self.compute_auto_layout(tree_components.as_deref());
+            ranked = auto.positions.clone();
+            timer.mark("rank");
+            let tree_subgraphs = self.align_subgraph_trees(&mut auto.positions);
+            self.cluster_subgraphs(&mut auto.positions, &tree_subgraphs);
+            self.separate_subgraphs(
+                &mut auto.positions,
+                tree_components.is_some(),
+                &tree_subgraphs,
+            );
+            if let Some(overrides) = overrides {
+                self.place_around_pinned(&mut auto.positions, overrides);
+            }
+            timer.mark("separate");
+            auto
+        };
+        if !passes.is_empty() {
+            for pass in passes {
+                pass.adjust(self, &mut auto.positions)?;
+            }
+            timer.mark("passes");
+        }
+        auto.size = compute_canvas_size_for_positions(
+            &auto.positions,
+            &self.nodes,
+            self.config.canvas_margin(),
         );
-        auto.size = compute_canvas_size_for_positions(&auto.positions, &self.nodes);
+        auto.waypoints = self.shift_waypoints(&auto.waypoints, &ranked, &auto.positions);
         let mut final_positions = auto.positions.clone();
 
         if let Some(overrides) = overrides {
@@ -1064,8 +2990,11 @@ impl Diagram {
             }
         }
 
-        let auto_routes = self.compute_routes(&auto.positions, None)?;
-        let final_routes = self.compute_routes(&final_positions, overrides)?;
+        let final_waypoints =
+            self.shift_waypoints(&auto.waypoints, &auto.positions, &final_positions);
+        let auto_routes = self.compute_routes(&auto.positions, None, &auto.waypoints)?;
+        let final_routes = self.compute_routes(&final_positions, overrides, &final_waypoints)?;
+        timer.mark("route");
 
         Ok(LayoutComputation {
             auto_positions: auto.positions,
@@ -1076,116 +3005,579 @@ impl Diagram {
         })
     }
 
-    fn compute_auto_layout(&self, tree_components: Option<&[Vec<String>]>) -> AutoLayout {
-        if self.order.is_empty() {
-            let size = CanvasSize {
-                width: START_OFFSET * 2.0 + NODE_WIDTH,
-                height: START_OFFSET * 2.0 + NODE_HEIGHT,
-            };
-            return AutoLayout {
-                positions: HashMap::new(),
-                size,
-            };
+    /// Puts every node that has a `previous` position back there. New nodes keep their
+    /// automatic offset from the earlier nodes they connect to (or from the old layout as a
+    /// whole) and then step sideways across the flow until they overlap nothing.
+    fn seed_from_previous(
+        &self,
+        positions: &mut HashMap<String, Point>,
+        previous: &HashMap<String, Point>,
+    ) {
+        let auto = positions.clone();
+        let kept: Vec<&String> = self
+            .order
+            .iter()
+            .filter(|id| auto.contains_key(*id) && previous.contains_key(*id))
+            .collect();
+        if kept.is_empty() {
+            return;
         }
+        let drift = kept.iter().fold((0.0, 0.0), |(dx, dy), id| {
+            (
+                dx + previous[*id].x - auto[*id].x,
+                dy + previous[*id].y - auto[*id].y,
+            )
+        });
+        let drift = (drift.0 / kept.len() as f32, drift.1 / kept.len() as f32);
 
-        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
-        let mut levels: HashMap<String, usize> =
-            self.nodes.keys().cloned().map(|id| (id, 0_usize)).collect();
-
-        let mut indegree: HashMap<String, usize> =
-            self.nodes.keys().cloned().map(|id| (id, 0_usize)).collect();
-        let mut outgoing = indegree.clone();
-
-        for edge in &self.edges {
-            *indegree.entry(edge.to.clone()).or_insert(0) += 1;
-            *outgoing.entry(edge.from.clone()).or_insert(0) += 1;
+        let size = |id: &str| {
+            self.nodes
+                .get(id)
+                .map_or((NODE_WIDTH, NODE_HEIGHT), |node| (node.width, node.height))
+        };
+        let gap = (NODE_SPACING - NODE_WIDTH) / 2.0;
+        let rect_at = |id: &str, center: Point| {
+            let (width, height) = size(id);
+            node_rect(center, width, height).inflate(gap)
+        };
+        let mut placed: Vec<Rect> = Vec::new();
+        for id in &kept {
+            positions.insert((*id).clone(), previous[*id]);
+            placed.push(rect_at(id, previous[*id]));
         }
-        let mut incoming = indegree.clone();
 
-        let mut queue: VecDeque<String> = VecDeque::new();
-        for id in &self.order {
-            if indegree.get(id).copied().unwrap_or(0) == 0 {
-                queue.push_back(id.clone());
+        let across = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        for id in self.order.iter().filter(|id| !previous.contains_key(*id)) {
+            let Some(own) = auto.get(id).copied() else {
+                continue;
+            };
+            let anchors: Vec<Point> = self
+                .edges
+                .iter()
+                .filter_map(|edge| match (edge.from == *id, edge.to == *id) {
+                    (true, false) => Some(&edge.to),
+                    (false, true) => Some(&edge.from),
+                    _ => None,
+                })
+                .filter(|other| previous.contains_key(*other) && auto.contains_key(*other))
+                .map(|other| Point {
+                    x: previous[other].x + own.x - auto[other].x,
+                    y: previous[other].y + own.y - auto[other].y,
+                })
+                .collect();
+            let start = if anchors.is_empty() {
+                Point {
+                    x: own.x + drift.0,
+                    y: own.y + drift.1,
+                }
+            } else {
+                centroid(&anchors)
+            };
+
+            let step = gap * 2.0;
+            let mut center = start;
+            for attempt in 1..=200_u32 {
+                if !placed
+                    .iter()
+                    .any(|rect| rect.intersects(&rect_at(id, center)))
+                {
+                    break;
+                }
+                // Alternate sides, reaching further out each time.
+                let side = if attempt % 2 == 1 { 1.0 } else { -1.0 };
+                let offset = side * step * attempt.div_ceil(2) as f32;
+                center = if across {
+                    Point {
+                        x: start.x + offset,
+                        y: start.y,
+                    }
+                } else {
+                    Point {
+                        x: start.x,
+                        y: start.y + offset,
+                    }
+                };
             }
+            positions.insert(id.clone(), center);
+            placed.push(rect_at(id, center));
         }
+    }
+
+    /// Carries long-edge waypoints from `before` to `after` positions: they move with their
+    /// edge when both ends moved together and are dropped when the ends moved apart.
+    fn shift_waypoints(
+        &self,
+        waypoints: &HashMap<String, Vec<Point>>,
+        before: &HashMap<String, Point>,
+        after: &HashMap<String, Point>,
+    ) -> HashMap<String, Vec<Point>> {
+        let delta = |id: &str| {
+            let (before, after) = (before.get(id)?, after.get(id)?);
+            Some((after.x - before.x, after.y - before.y))
+        };
+        self.edges
+            .iter()
+            .filter_map(|edge| {
+                let id = edge_identifier(edge);
+                let points = waypoints.get(&id)?;
+                let (dx, dy) = delta(&edge.from)?;
+                let (to_dx, to_dy) = delta(&edge.to)?;
+                if (dx - to_dx).abs() > 0.5 || (dy - to_dy).abs() > 0.5 {
+                    return None;
+                }
+                let shifted = points
+                    .iter()
+                    .map(|point| Point {
+                        x: point.x + dx,
+                        y: point.y + dy,
+                    })
+                    .collect();
+                Some((id, shifted))
+            })
+            .collect()
+    }
 
-        let mut visited: HashSet<String> = HashSet::new();
+    #[cfg(feature = "graphviz")]
+    fn dot_positions(&self) -> Result<HashMap<String, Point>> {
+        crate::graphviz::dot_positions(self)
+    }
 
-        while let Some(node_id) = queue.pop_front() {
-            visited.insert(node_id.clone());
-            let node_level = *levels.get(&node_id).unwrap_or(&0);
+    #[cfg(not(feature = "graphviz"))]
+    fn dot_positions(&self) -> Result<HashMap<String, Point>> {
+        bail!("layout 'dot' needs oxdraw built with the graphviz feature")
+    }
 
-            for edge in self.edges.iter().filter(|edge| edge.from == node_id) {
-                let target_id = edge.to.clone();
-                let entry = levels.entry(target_id.clone()).or_insert(0);
-                if *entry < node_level + 1 {
-                    *entry = node_level + 1;
+    /// Edges in the final layout that still pass through nodes, cover nodes with their label
+    /// or cross other edges. Each crossing is reported once, on the earlier edge.
+    pub fn layout_diagnostics(&self, layout: &LayoutComputation) -> Vec<LayoutDiagnostic> {
+        let node_rects: Vec<(&String, Rect)> = self
+            .order
+            .iter()
+            .filter_map(|id| {
+                let node = self.nodes.get(id)?;
+                let center = layout.final_positions.get(id)?;
+                Some((id, node_rect(*center, node.width, node.height)))
+            })
+            .collect();
+        let routed: Vec<(&Edge, String, &Vec<Point>)> = self
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let id = edge_identifier(edge);
+                let route = layout.final_routes.get(&id)?;
+                Some((edge, id, route))
+            })
+            .collect();
+
+        let mut diagnostics = Vec::new();
+        for (idx, (edge, id, route)) in routed.iter().enumerate() {
+            let crossed: Vec<String> = node_rects
+                .iter()
+                .filter(|(node_id, _)| **node_id != edge.from && **node_id != edge.to)
+                .filter(|(_, rect)| route_intersects_rect(route, *rect))
+                .map(|(node_id, _)| (*node_id).clone())
+                .collect();
+            if !crossed.is_empty() {
+                diagnostics.push(LayoutDiagnostic {
+                    edge_id: id.clone(),
+                    kind: CollisionKind::NodeOverlap,
+                    nodes: crossed,
+                    other_edge_id: None,
+                });
+            }
+
+            if let Some(label_rect) = label_rect_for_route(edge, route) {
+                let covered: Vec<String> = node_rects
+                    .iter()
+                    .filter(|(_, rect)| label_rect.intersects(rect))
+                    .map(|(node_id, _)| (*node_id).clone())
+                    .collect();
+                if !covered.is_empty() {
+                    diagnostics.push(LayoutDiagnostic {
+                        edge_id: id.clone(),
+                        kind: CollisionKind::LabelOverlap,
+                        nodes: covered,
+                        other_edge_id: None,
+                    });
                 }
+            }
 
-                if let Some(degree) = indegree.get_mut(&target_id) {
-                    if *degree > 0 {
-                        *degree -= 1;
-                        if *degree == 0 {
-                            queue.push_back(target_id.clone());
+            for (other, other_id, other_route) in &routed[idx + 1..] {
+                if routes_intersect(route, other_route) {
+                    let mut nodes = vec![edge.from.clone(), edge.to.clone()];
+                    for node in [&other.from, &other.to] {
+                        if !nodes.contains(node) {
+                            nodes.push(node.clone());
                         }
                     }
+                    diagnostics.push(LayoutDiagnostic {
+                        edge_id: id.clone(),
+                        kind: CollisionKind::EdgeCrossing,
+                        nodes,
+                        other_edge_id: Some(other_id.clone()),
+                    });
                 }
             }
         }
+        diagnostics
+    }
 
-        if visited.len() != self.nodes.len() {
-            for id in &self.order {
-                if visited.contains(id) {
-                    continue;
-                }
-                let mut max_parent = 0_usize;
-                let mut has_parent = false;
-                for edge in self.edges.iter().filter(|edge| edge.to == *id) {
-                    has_parent = true;
-                    let parent_level = *levels.get(&edge.from).unwrap_or(&0);
-                    max_parent = max_parent.max(parent_level + 1);
-                }
-                levels.insert(id.clone(), if has_parent { max_parent } else { 0 });
-            }
+    /// Reorders each layer by the barycenter of its neighbors in the adjacent layer, sweeping
+    /// down and up, and keeps whichever ordering crosses the fewest edges.
+    fn reduce_crossings(&self, layers: &mut [Vec<String>]) {
+        const SWEEPS: usize = 8;
+        if layers.len() < 2 {
+            return;
         }
-
-        let mut layers_map: BTreeMap<usize, Vec<String>> = BTreeMap::new();
-        for id in &self.order {
-            let level = *levels.get(id).unwrap_or(&0);
-            layers_map.entry(level).or_default().push(id.clone());
+        let links = self.layer_links(layers);
+        let mut neighbors: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (upper, lower) in &links {
+            neighbors.entry(upper).or_default().push(lower);
+            neighbors.entry(lower).or_default().push(upper);
         }
 
-        let mut layers: Vec<Vec<String>> = layers_map.into_values().collect();
-        let mut rank = HashMap::new();
-        for layer in &mut layers {
-            layer.sort_by_key(|id| {
-                self.edges
+        let mut best = layers.to_vec();
+        let mut best_crossings = self.count_layer_crossings(layers);
+        for sweep in 0..SWEEPS {
+            if best_crossings == 0 {
+                break;
+            }
+            let indices: Vec<usize> = if sweep % 2 == 0 {
+                (1..layers.len()).collect()
+            } else {
+                (0..layers.len() - 1).rev().collect()
+            };
+            for idx in indices {
+                let fixed = if sweep % 2 == 0 { idx - 1 } else { idx + 1 };
+                let fixed_rank: HashMap<&str, usize> = layers[fixed]
                     .iter()
-                    .filter(|edge| edge.to == *id)
-                    .filter_map(|edge| rank.get(&edge.from))
-                    .min()
-            });
-            rank.extend(layer.iter().cloned().zip(0..));
+                    .enumerate()
+                    .map(|(rank, id)| (id.as_str(), rank))
+                    .collect();
+                // Nodes without neighbors in the fixed layer hold their current slot.
+                let mut keyed: Vec<(f32, String)> = layers[idx]
+                    .iter()
+                    .enumerate()
+                    .map(|(rank, id)| {
+                        let ranks: Vec<usize> = neighbors
+                            .get(id.as_str())
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|neighbor| fixed_rank.get(neighbor).copied())
+                            .collect();
+                        let barycenter = if ranks.is_empty() {
+                            rank as f32
+                        } else {
+                            ranks.iter().sum::<usize>() as f32 / ranks.len() as f32
+                        };
+                        (barycenter, id.clone())
+                    })
+                    .collect();
+                keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+                layers[idx] = keyed.into_iter().map(|(_, id)| id).collect();
+            }
+
+            let crossings = transpose_neighbors(layers, &links);
+            if crossings < best_crossings {
+                best = layers.to_vec();
+                best_crossings = crossings;
+            }
         }
-        let level_count = layers.len().max(1);
-        let max_node_height = self
-            .nodes
-            .values()
-            .map(|node| node.height)
-            .fold(NODE_HEIGHT, f32::max);
-        let vertical_step = NODE_SPACING.max(max_node_height + EDGE_COLLISION_MARGIN * 4.0);
+        layers.clone_from_slice(&best);
+    }
 
-        let mut positions = HashMap::new();
+    /// Crossings between edges that join adjacent layers, counting edges threaded through
+    /// [`Diagram::insert_edge_dummies`] placeholders segment by segment; other longer edges
+    /// are not counted.
+    fn count_layer_crossings(&self, layers: &[Vec<String>]) -> usize {
+        count_link_crossings(layers, &self.layer_links(layers))
+    }
 
-        let (width, height) = match self.direction {
-            Direction::TopDown | Direction::BottomTop => {
-                let base_horizontal_gap =
+    /// Pairs of ids in adjacent layers joined by an edge or by a piece of a long edge's
+    /// placeholder chain, upper layer first.
+    fn layer_links(&self, layers: &[Vec<String>]) -> Vec<(String, String)> {
+        let layer_of: HashMap<&str, usize> = layers
+            .iter()
+            .enumerate()
+            .flat_map(|(layer, ids)| ids.iter().map(move |id| (id.as_str(), layer)))
+            .collect();
+        let mut links = Vec::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            let (Some(&from), Some(&to)) = (
+                layer_of.get(edge.from.as_str()),
+                layer_of.get(edge.to.as_str()),
+            ) else {
+                continue;
+            };
+            let (upper, lower, upper_id, lower_id) = if from < to {
+                (from, to, &edge.from, &edge.to)
+            } else {
+                (to, from, &edge.to, &edge.from)
+            };
+            if upper == lower {
+                continue;
+            }
+            let mut chain = vec![upper_id.clone()];
+            chain.extend((upper + 1..lower).map(|layer| edge_dummy_id(idx, layer)));
+            chain.push(lower_id.clone());
+            if chain.iter().all(|id| layer_of.contains_key(id.as_str())) {
+                links.extend(
+                    chain
+                        .windows(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone())),
+                );
+            }
+        }
+        links
+    }
+
+    /// Threads each forward edge spanning several layers through a placeholder in every layer
+    /// it crosses, so crossing reduction orders it like a chain of short edges and layer
+    /// spacing leaves it a lane. Returns the placeholders of each edge in order, by edge id.
+    fn insert_edge_dummies(&self, layers: &mut [Vec<String>]) -> Vec<(String, Vec<String>)> {
+        let layer_of: HashMap<String, usize> = layers
+            .iter()
+            .enumerate()
+            .flat_map(|(layer, ids)| ids.iter().map(move |id| (id.clone(), layer)))
+            .collect();
+        let mut chains = Vec::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            let (Some(&from), Some(&to)) = (layer_of.get(&edge.from), layer_of.get(&edge.to))
+            else {
+                continue;
+            };
+            if to <= from + 1 {
+                continue;
+            }
+            let chain: Vec<String> = (from + 1..to)
+                .map(|layer| {
+                    let id = edge_dummy_id(idx, layer);
+                    layers[layer].push(id.clone());
+                    id
+                })
+                .collect();
+            chains.push((edge_identifier(edge), chain));
+        }
+        chains
+    }
+
+    /// Indices of the edges that close a cycle, found by a depth-first search from the
+    /// source nodes and then any node left unvisited, in declaration order. Leveling runs
+    /// these edges backwards so every node of a cycle gets its own step; they are still
+    /// drawn from their source, detouring back up to their target. Self-loops are left out.
+    fn back_edges(&self) -> HashSet<usize> {
+        let mut outgoing: HashMap<&str, Vec<(usize, &str)>> = HashMap::new();
+        let mut has_parent = HashSet::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            if edge.from == edge.to {
+                continue;
+            }
+            outgoing
+                .entry(edge.from.as_str())
+                .or_default()
+                .push((idx, edge.to.as_str()));
+            has_parent.insert(edge.to.as_str());
+        }
+        let starts = self
+            .order
+            .iter()
+            .filter(|id| !has_parent.contains(id.as_str()))
+            .chain(
+                self.order
+                    .iter()
+                    .filter(|id| has_parent.contains(id.as_str())),
+            );
+
+        let mut back = HashSet::new();
+        let mut visited: HashSet<&str> = HashSet::new();
+        let mut on_stack: HashSet<&str> = HashSet::new();
+        for start in starts {
+            if !visited.insert(start.as_str()) {
+                continue;
+            }
+            on_stack.insert(start.as_str());
+            // Each frame holds a node and how many of its outgoing edges have been followed.
+            let mut stack = vec![(start.as_str(), 0_usize)];
+            while let Some((id, next)) = stack.last_mut() {
+                let Some(&(idx, target)) = outgoing.get(*id).and_then(|edges| edges.get(*next))
+                else {
+                    on_stack.remove(*id);
+                    stack.pop();
+                    continue;
+                };
+                *next += 1;
+                if on_stack.contains(target) {
+                    back.insert(idx);
+                } else if visited.insert(target) {
+                    on_stack.insert(target);
+                    stack.push((target, 0));
+                }
+            }
+        }
+        back
+    }
+
+    fn compute_auto_layout(&self, tree_components: Option<&[Vec<String>]>) -> AutoLayout {
+        if self.order.is_empty() {
+            let size = CanvasSize {
+                width: START_OFFSET * 2.0 + NODE_WIDTH,
+                height: START_OFFSET * 2.0 + NODE_HEIGHT,
+            };
+            return AutoLayout {
+                positions: HashMap::new(),
+                size,
+                waypoints: HashMap::new(),
+            };
+        }
+
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        let mut levels: HashMap<String, usize> =
+            self.nodes.keys().cloned().map(|id| (id, 0_usize)).collect();
+
+        let mut indegree: HashMap<String, usize> =
+            self.nodes.keys().cloned().map(|id| (id, 0_usize)).collect();
+        let mut outgoing = indegree.clone();
+
+        for edge in &self.edges {
+            *indegree.entry(edge.to.clone()).or_insert(0) += 1;
+            *outgoing.entry(edge.from.clone()).or_insert(0) += 1;
+        }
+        let mut incoming = indegree.clone();
+
+        // Levels follow the edges with each cycle's back edge turned around, so the
+        // graph they walk is acyclic and every node is reached.
+        let back = self.back_edges();
+        let flow: Vec<(&str, &str)> = self
+            .edges
+            .iter()
+            .enumerate()
+            .filter(|(_, edge)| edge.from != edge.to)
+            .map(|(idx, edge)| {
+                if back.contains(&idx) {
+                    (edge.to.as_str(), edge.from.as_str())
+                } else {
+                    (edge.from.as_str(), edge.to.as_str())
+                }
+            })
+            .collect();
+        let mut pending: HashMap<&str, usize> = HashMap::new();
+        for (_, to) in &flow {
+            *pending.entry(to).or_insert(0) += 1;
+        }
+
+        let mut queue: VecDeque<&str> = self
+            .order
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !pending.contains_key(id))
+            .collect();
+
+        while let Some(node_id) = queue.pop_front() {
+            let node_level = *levels.get(node_id).unwrap_or(&0);
+
+            for &(_, target_id) in flow.iter().filter(|(from, _)| *from == node_id) {
+                let entry = levels.entry(target_id.to_string()).or_insert(0);
+                if *entry < node_level + 1 {
+                    *entry = node_level + 1;
+                }
+
+                if let Some(degree) = pending.get_mut(target_id) {
+                    *degree -= 1;
+                    if *degree == 0 {
+                        queue.push_back(target_id);
+                    }
+                }
+            }
+        }
+        self.apply_rank_groups(&mut levels);
+
+        let mut layers_map: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for id in &self.order {
+            let level = *levels.get(id).unwrap_or(&0);
+            layers_map.entry(level).or_default().push(id.clone());
+        }
+
+        let mut layers: Vec<Vec<String>> = layers_map.into_values().collect();
+        let mut rank = HashMap::new();
+        for layer in &mut layers {
+            layer.sort_by_key(|id| {
+                flow.iter()
+                    .filter(|(_, to)| *to == id.as_str())
+                    .filter_map(|(from, _)| rank.get(*from))
+                    .min()
+            });
+            rank.extend(layer.iter().cloned().zip(0..));
+        }
+        let dummies = self.insert_edge_dummies(&mut layers);
+        self.reduce_crossings(&mut layers);
+        // Placeholders take a narrow lane, or one wide enough for the edge's label.
+        let mut lanes: HashMap<&str, f32> = HashMap::new();
+        for (edge_id, chain) in &dummies {
+            let lane = self
+                .edges
+                .iter()
+                .find(|edge| edge_identifier(edge) == *edge_id)
+                .and_then(|edge| edge.label.as_ref())
+                .map_or(EDGE_LANE_WIDTH, |label| {
+                    let (width, height) = measure_label_box(&normalize_label_lines(label));
+                    if top_down { width } else { height }
+                });
+            for id in chain {
+                lanes.insert(id, lane);
+            }
+        }
+        let level_count = layers.len().max(1);
+        let row_heights: Vec<f32> = layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .filter_map(|id| self.nodes.get(id))
+                    .map(|node| node.height)
+                    .fold(NODE_HEIGHT, f32::max)
+            })
+            .collect();
+        // Bundled edges need room between layers to merge and split again.
+        let bundle_room = if self.config.edge_bundling {
+            EDGE_BUNDLE_SPLIT * 2.0
+        } else {
+            0.0
+        };
+        // Each pair of rows is spaced for its own tallest nodes, not the tallest overall.
+        let mut row_offsets = vec![0.0_f32; row_heights.len().max(1)];
+        for idx in 1..row_heights.len() {
+            let reach = (row_heights[idx - 1] + row_heights[idx]) / 2.0;
+            let step = match self.config.rank_spacing {
+                Some(gap) => reach + gap,
+                None => NODE_SPACING.max(reach + (EDGE_COLLISION_MARGIN * 4.0).max(bundle_room)),
+            };
+            row_offsets[idx] = row_offsets[idx - 1] + step;
+        }
+        let first_row = row_heights.first().copied().unwrap_or(NODE_HEIGHT);
+        let last_row = row_heights.last().copied().unwrap_or(NODE_HEIGHT);
+        let rows_span = row_offsets[row_offsets.len() - 1];
+        let cross_gap = if top_down {
+            self.config
+                .node_spacing
+                .unwrap_or(
                     (NODE_SPACING - NODE_WIDTH).max(if self.subgraphs.is_empty() {
                         EDGE_COLLISION_MARGIN * 2.0
                     } else {
                         SUBGRAPH_PADDING
-                    });
-                let inner_height = max_node_height + vertical_step * ((level_count - 1) as f32);
+                    }),
+                )
+        } else {
+            self.config
+                .node_spacing
+                .unwrap_or((NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0))
+        };
+
+        let mut positions = HashMap::new();
+
+        let (width, height) = match self.direction {
+            Direction::TopDown | Direction::BottomTop => {
+                let base_horizontal_gap = cross_gap;
+                let inner_height = first_row / 2.0 + rows_span + last_row / 2.0;
 
                 let mut layer_centers: Vec<Vec<(String, f32)>> = Vec::with_capacity(layers.len());
                 let mut layer_widths: Vec<f32> = Vec::with_capacity(layers.len());
@@ -1202,12 +3594,9 @@ impl Diagram {
                     let mut prev_width = 0.0_f32;
 
                     for (idx, id) in layer.iter().enumerate() {
-                        let width = self
-                            .nodes
-                            .get(id)
-                            .map(|node| node.width)
-                            .unwrap_or(NODE_WIDTH)
-                            .max(NODE_WIDTH);
+                        let width = lanes.get(id.as_str()).copied().unwrap_or_else(|| {
+                            self.nodes.get(id).map_or(NODE_WIDTH, |node| node.width)
+                        });
                         let half = width / 2.0;
                         if idx == 0 {
                             current = half;
@@ -1221,32 +3610,30 @@ impl Diagram {
                     let last_center = centers.last().map(|entry| entry.1).unwrap_or(0.0);
                     let last_width = layer
                         .last()
-                        .and_then(|id| self.nodes.get(id))
-                        .map(|node| node.width)
-                        .unwrap_or(NODE_WIDTH)
-                        .max(NODE_WIDTH);
+                        .map(|id| {
+                            lanes.get(id.as_str()).copied().unwrap_or_else(|| {
+                                self.nodes.get(id).map_or(NODE_WIDTH, |node| node.width)
+                            })
+                        })
+                        .unwrap_or(NODE_WIDTH);
                     let layer_width = last_center + last_width / 2.0;
 
                     layer_centers.push(centers);
-                    layer_widths.push(layer_width.max(NODE_WIDTH));
+                    layer_widths.push(layer_width.max(NODE_MIN_WIDTH));
                 }
 
                 let inner_width = layer_widths.iter().copied().fold(NODE_WIDTH, f32::max);
                 let width = inner_width + START_OFFSET * 2.0;
                 let height = inner_height + START_OFFSET * 2.0;
 
-                let vertical_span = vertical_step * ((level_count - 1) as f32);
-                let start_y = START_OFFSET + (inner_height - vertical_span) / 2.0;
-
                 for (idx, centers) in layer_centers.iter().enumerate() {
                     let layer_width = layer_widths[idx];
                     let offset_x = START_OFFSET + (inner_width - layer_width) / 2.0;
-                    let row_index = if matches!(self.direction, Direction::BottomTop) {
-                        level_count - 1 - idx
+                    let y = if matches!(self.direction, Direction::BottomTop) {
+                        START_OFFSET + last_row / 2.0 + rows_span - row_offsets[idx]
                     } else {
-                        idx
-                    } as f32;
-                    let y = start_y + row_index * vertical_step;
+                        START_OFFSET + first_row / 2.0 + row_offsets[idx]
+                    };
 
                     for (id, rel_x) in centers {
                         positions.insert(
@@ -1262,10 +3649,12 @@ impl Diagram {
                 (width, height)
             }
             Direction::LeftRight | Direction::RightLeft => {
-                let base_horizontal_gap =
-                    (NODE_SPACING - NODE_WIDTH).max(EDGE_COLLISION_MARGIN * 2.0);
-                let base_vertical_gap =
-                    (NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0);
+                let base_horizontal_gap = self.config.rank_spacing.unwrap_or(
+                    (NODE_SPACING - NODE_WIDTH)
+                        .max(EDGE_COLLISION_MARGIN * 2.0)
+                        .max(bundle_room),
+                );
+                let base_vertical_gap = cross_gap;
 
                 let mut column_widths = Vec::with_capacity(level_count);
                 for layer in &layers {
@@ -1321,11 +3710,9 @@ impl Diagram {
                     let mut prev_height = 0.0_f32;
 
                     for (idx, id) in layer.iter().enumerate() {
-                        let height = self
-                            .nodes
-                            .get(id)
-                            .map(|node| node.height)
-                            .unwrap_or(NODE_HEIGHT);
+                        let height = lanes.get(id.as_str()).copied().unwrap_or_else(|| {
+                            self.nodes.get(id).map_or(NODE_HEIGHT, |node| node.height)
+                        });
                         let half = height / 2.0;
                         if idx == 0 {
                             current_y = half;
@@ -1339,8 +3726,11 @@ impl Diagram {
                     let last_center = centers.last().map(|entry| entry.1).unwrap_or(0.0);
                     let last_height = layer
                         .last()
-                        .and_then(|id| self.nodes.get(id))
-                        .map(|node| node.height)
+                        .map(|id| {
+                            lanes.get(id.as_str()).copied().unwrap_or_else(|| {
+                                self.nodes.get(id).map_or(NODE_HEIGHT, |node| node.height)
+                            })
+                        })
                         .unwrap_or(NODE_HEIGHT);
                     let column_height = last_center + last_height / 2.0;
 
@@ -1387,6 +3777,9 @@ impl Diagram {
                 );
             }
         } else {
+            if self.subgraphs.is_empty() {
+                self.compact_layers(&layers, &mut positions, &lanes, cross_gap);
+            }
             for edge in &self.edges {
                 if top_down && levels[&edge.from] >= levels[&edge.to] {
                     incoming.entry(edge.to.clone()).and_modify(|n| *n -= 1);
@@ -1402,16 +3795,180 @@ impl Diagram {
                         Direction::TopDown | Direction::BottomTop => target.x = parent.x,
                         Direction::LeftRight | Direction::RightLeft => target.y = parent.y,
                     }
-                    if self.position_clear(&edge.to, target, &positions, true) {
+                    let node = &self.nodes[&edge.to];
+                    let bounds = node_rect(target, node.width, node.height);
+                    let lane_clear = lanes.iter().all(|(id, lane)| {
+                        positions.get(*id).is_none_or(|point| {
+                            !bounds.intersects(&node_rect(*point, *lane, *lane))
+                        })
+                    });
+                    if lane_clear && self.position_clear(&edge.to, target, &positions, true) {
                         positions.insert(edge.to.clone(), target);
                     }
                 }
             }
         }
 
+        // Each placeholder becomes a bend where the edge enters its layer and one where it
+        // leaves, so the edge runs straight past the nodes of that layer.
+        let mut reach: HashMap<&str, f32> = HashMap::new();
+        for layer in &layers {
+            let depth = layer
+                .iter()
+                .filter_map(|id| self.nodes.get(id))
+                .map(|node| if top_down { node.height } else { node.width })
+                .fold(0.0, f32::max);
+            for id in layer {
+                reach.insert(id, depth / 2.0);
+            }
+        }
+        let step = if matches!(self.direction, Direction::BottomTop | Direction::RightLeft) {
+            -1.0
+        } else {
+            1.0
+        };
+        let waypoints = dummies
+            .iter()
+            .map(|(edge_id, chain)| {
+                let points = chain
+                    .iter()
+                    .filter_map(|id| {
+                        let point = positions.remove(id)?;
+                        let offset = reach[id.as_str()] * step;
+                        Some(if top_down {
+                            [
+                                Point {
+                                    x: point.x,
+                                    y: point.y - offset,
+                                },
+                                Point {
+                                    x: point.x,
+                                    y: point.y + offset,
+                                },
+                            ]
+                        } else {
+                            [
+                                Point {
+                                    x: point.x - offset,
+                                    y: point.y,
+                                },
+                                Point {
+                                    x: point.x + offset,
+                                    y: point.y,
+                                },
+                            ]
+                        })
+                    })
+                    .flatten()
+                    .collect();
+                (edge_id.clone(), points)
+            })
+            .collect();
+
         AutoLayout {
             positions,
             size: CanvasSize { width, height },
+            waypoints,
+        }
+    }
+
+    /// Pulls each node across its layer towards the mean of its neighbours in the adjacent
+    /// layers, keeping the layer order and `gap` between neighbours, so sparse layers stop
+    /// spreading out over the widest one. Diagrams with subgraphs are packed unit by unit in
+    /// [`Diagram::cluster_subgraphs`] instead.
+    fn compact_layers(
+        &self,
+        layers: &[Vec<String>],
+        positions: &mut HashMap<String, Point>,
+        lanes: &HashMap<&str, f32>,
+        gap: f32,
+    ) {
+        const SWEEPS: usize = 4;
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        let cross = |point: &Point| if top_down { point.x } else { point.y };
+        let extent = |id: &str| {
+            lanes.get(id).copied().unwrap_or_else(|| {
+                self.nodes.get(id).map_or(
+                    0.0,
+                    |node| {
+                        if top_down { node.width } else { node.height }
+                    },
+                )
+            })
+        };
+        let leading_edge = |positions: &HashMap<String, Point>| {
+            layers
+                .iter()
+                .flatten()
+                .filter_map(|id| Some(cross(positions.get(id)?) - extent(id) / 2.0))
+                .fold(f32::INFINITY, f32::min)
+        };
+        let links = self.layer_links(layers);
+        let mut neighbors: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (upper, lower) in &links {
+            neighbors.entry(upper).or_default().push(lower);
+            neighbors.entry(lower).or_default().push(upper);
+        }
+        let start = leading_edge(positions);
+
+        for sweep in 0..SWEEPS {
+            let order: Vec<usize> = if sweep % 2 == 0 {
+                (0..layers.len()).collect()
+            } else {
+                (0..layers.len()).rev().collect()
+            };
+            for idx in order {
+                let layer = &layers[idx];
+                if layer.is_empty() {
+                    continue;
+                }
+                // Solving for centres minus the spacing before them turns the gaps into a
+                // plain ordering constraint.
+                let mut spacing = vec![0.0_f32; layer.len()];
+                for slot in 1..layer.len() {
+                    let (prev, id) = (&layer[slot - 1], &layer[slot]);
+                    spacing[slot] = spacing[slot - 1] + (extent(prev) + extent(id)) / 2.0 + gap;
+                }
+                let targets: Vec<f32> = layer
+                    .iter()
+                    .zip(&spacing)
+                    .map(|(id, offset)| {
+                        let pulls: Vec<f32> = neighbors
+                            .get(id.as_str())
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|neighbor| positions.get(*neighbor).map(cross))
+                            .collect();
+                        let target = if pulls.is_empty() {
+                            cross(&positions[id])
+                        } else {
+                            pulls.iter().sum::<f32>() / pulls.len() as f32
+                        };
+                        target - offset
+                    })
+                    .collect();
+                for ((id, offset), value) in
+                    layer.iter().zip(&spacing).zip(non_decreasing_fit(&targets))
+                {
+                    let point = positions.get_mut(id).expect("layer node placed");
+                    if top_down {
+                        point.x = value + offset;
+                    } else {
+                        point.y = value + offset;
+                    }
+                }
+            }
+        }
+
+        let shift = start - leading_edge(positions);
+        for id in layers.iter().flatten() {
+            if let Some(point) = positions.get_mut(id) {
+                if top_down {
+                    point.x += shift;
+                } else {
+                    point.y += shift;
+                }
+            }
         }
     }
 
@@ -1438,12 +3995,63 @@ impl Diagram {
         }
 
         let mut aligned = HashSet::new();
+        if !self.config.rank_groups.is_empty() {
+            return aligned;
+        }
         for subgraph in &self.subgraphs {
             visit(self, subgraph, positions, &mut aligned);
         }
         aligned
     }
 
+    /// Lifts every rank group onto the deepest level among its members, pushing descendants
+    /// down so edges that pointed forward still do.
+    fn apply_rank_groups(&self, levels: &mut HashMap<String, usize>) {
+        let group_of: HashMap<&str, usize> = self
+            .config
+            .rank_groups
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, group)| group.iter().map(move |id| (id.as_str(), idx)))
+            .collect();
+        // Edges inside a group, and back edges of cycles, cannot be satisfied together.
+        let forward: Vec<(&str, &str)> = self
+            .edges
+            .iter()
+            .filter(|edge| levels[&edge.to] > levels[&edge.from])
+            .filter(|edge| {
+                !group_of.contains_key(edge.from.as_str())
+                    || group_of.get(edge.from.as_str()) != group_of.get(edge.to.as_str())
+            })
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+
+        for _ in 0..=self.nodes.len() {
+            let mut changed = false;
+            for group in &self.config.rank_groups {
+                let level = group.iter().map(|id| levels[id]).max().unwrap_or(0);
+                for id in group {
+                    let entry = levels.get_mut(id).expect("rank group members are nodes");
+                    if *entry < level {
+                        *entry = level;
+                        changed = true;
+                    }
+                }
+            }
+            for (from, to) in &forward {
+                let min = levels[*from] + 1;
+                let entry = levels.get_mut(*to).expect("edge endpoints are nodes");
+                if *entry < min {
+                    *entry = min;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
     fn align_directed_forest(
         &self,
         members: &[String],
@@ -1481,8 +4089,10 @@ impl Diagram {
         }
 
         let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
-        let cross_gap = (NODE_SPACING - if top_down { NODE_WIDTH } else { NODE_HEIGHT })
-            .max(EDGE_COLLISION_MARGIN * 2.0);
+        let cross_gap = self.config.node_spacing.unwrap_or(
+            (NODE_SPACING - if top_down { NODE_WIDTH } else { NODE_HEIGHT })
+                .max(EDGE_COLLISION_MARGIN * 2.0),
+        );
         let mut widths = HashMap::new();
         for root in &roots {
             measure_subtree(root, &children, &mut widths);
@@ -1540,7 +4150,8 @@ impl Diagram {
                 (size(pair[0].0) / 2.0 + cross_gap + size(pair[1].0) / 2.0)
                     / (pair[1].1 - pair[0].1)
             })
-            .fold(NODE_SPACING, f32::max);
+            .reduce(f32::max)
+            .unwrap_or(NODE_SPACING);
 
         let mut levels = Vec::new();
         if reflow_depth {
@@ -1550,8 +4161,10 @@ impl Diagram {
                 let node = &self.nodes[*id];
                 sizes[*depth] = sizes[*depth].max(if top_down { node.height } else { node.width });
             }
-            let gap = (NODE_SPACING - if top_down { NODE_HEIGHT } else { NODE_WIDTH })
-                .max(EDGE_COLLISION_MARGIN * 2.0);
+            let gap = self.config.rank_spacing.unwrap_or(
+                (NODE_SPACING - if top_down { NODE_HEIGHT } else { NODE_WIDTH })
+                    .max(EDGE_COLLISION_MARGIN * 2.0),
+            );
             let main = |point: Point| if top_down { point.y } else { point.x };
             let direction = if matches!(self.direction, Direction::BottomTop | Direction::RightLeft)
             {
@@ -1588,6 +4201,10 @@ impl Diagram {
     }
 
     fn tree_components(&self) -> Option<Vec<Vec<String>>> {
+        // Tree placement derives depth from the tree itself and would undo rank groups.
+        if !self.config.rank_groups.is_empty() {
+            return None;
+        }
         let mut adjacent: HashMap<&str, Vec<&str>> = self
             .nodes
             .keys()
@@ -1646,11 +4263,11 @@ impl Diagram {
             let node = &self.nodes[id];
             if top_down { node.width } else { node.height }
         };
-        let base_gap = if top_down {
+        let base_gap = self.config.node_spacing.unwrap_or(if top_down {
             (NODE_SPACING - NODE_WIDTH).max(EDGE_COLLISION_MARGIN * 2.0)
         } else {
             (NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0)
-        };
+        });
         let mut cursor = START_OFFSET;
 
         for component in components {
@@ -1760,22 +4377,598 @@ impl Diagram {
         }
     }
 
-    fn separate_subgraphs(
+    fn place_around_pinned(
         &self,
         positions: &mut HashMap<String, Point>,
-        preserve_tree: bool,
-        tree_subgraphs: &HashSet<String>,
+        overrides: &LayoutOverrides,
     ) {
-        let mut placed: Vec<(Rect, HashSet<String>)> = Vec::new();
+        let pinned: Vec<&String> = overrides
+            .pinned
+            .iter()
+            .filter(|id| positions.contains_key(*id))
+            .collect();
+        if pinned.is_empty() {
+            return;
+        }
+
+        for id in &pinned {
+            if let Some(point) = overrides.nodes.get(*id) {
+                positions.insert((*id).clone(), *point);
+            }
+        }
+
         let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
-        for subgraph in &self.subgraphs {
-            let members = subgraph_nodes(subgraph);
-            let Some(bounds) = group_bounds(&members, positions, &self.nodes) else {
-                continue;
-            };
-            let mut frame = padded_group_bounds(bounds);
-            let mut shift = Point { x: 0.0, y: 0.0 };
-            while let Some((previous, previous_members)) = placed
+        let rect_of = |id: &str, positions: &HashMap<String, Point>| {
+            let node = &self.nodes[id];
+            node_rect(positions[id], node.width, node.height)
+        };
+        let overlaps = |a: &Rect, b: &Rect| {
+            a.min_x < b.max_x && a.max_x > b.min_x && a.min_y < b.max_y && a.max_y > b.min_y
+        };
+        let movable: Vec<&String> = self
+            .order
+            .iter()
+            .filter(|id| positions.contains_key(*id) && !overrides.pinned.contains(*id))
+            .collect();
+
+        for _ in 0..=movable.len() {
+            let mut moved = false;
+            for pinned_id in &pinned {
+                let blocker = rect_of(pinned_id, positions).inflate(PINNED_NODE_CLEARANCE);
+                let anchor = positions[*pinned_id];
+                for id in &movable {
+                    let rect = rect_of(id, positions);
+                    if !overlaps(&rect, &blocker) {
+                        continue;
+                    }
+
+                    // Push the whole rank outward on the cross axis so siblings keep their spacing.
+                    let center = positions[*id];
+                    let shift = if top_down {
+                        if center.x >= anchor.x {
+                            blocker.max_x - rect.min_x
+                        } else {
+                            blocker.min_x - rect.max_x
+                        }
+                    } else if center.y >= anchor.y {
+                        blocker.max_y - rect.min_y
+                    } else {
+                        blocker.min_y - rect.max_y
+                    };
+                    for other in &movable {
+                        let point = positions.get_mut(*other).unwrap();
+                        let (rank, cross, origin) = if top_down {
+                            (point.y - center.y, &mut point.x, center.x)
+                        } else {
+                            (point.x - center.x, &mut point.y, center.y)
+                        };
+                        let outward = if shift > 0.0 {
+                            *cross >= origin
+                        } else {
+                            *cross <= origin
+                        };
+                        if rank.abs() < 1.0 && outward {
+                            *cross += shift;
+                        }
+                    }
+                    moved = true;
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+    }
+
+    /// Lays every subgraph out as a unit, innermost first: its frame is packed beside sibling
+    /// frames and loose nodes along the layer axis, so members stay inside their container and
+    /// containers never overlap where their layers meet.
+    fn cluster_subgraphs(
+        &self,
+        positions: &mut HashMap<String, Point>,
+        tree_subgraphs: &HashSet<String>,
+    ) {
+        if self.subgraphs.is_empty() {
+            return;
+        }
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        let cross_min = |positions: &HashMap<String, Point>| {
+            positions
+                .iter()
+                .filter_map(|(id, point)| {
+                    let node = self.nodes.get(id)?;
+                    let rect = node_rect(*point, node.width, node.height);
+                    Some(if top_down { rect.min_x } else { rect.min_y })
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+        let start = cross_min(positions);
+
+        let loose: Vec<String> = self
+            .order
+            .iter()
+            .filter(|id| self.node_membership.get(*id).is_none_or(Vec::is_empty))
+            .cloned()
+            .collect();
+        self.pack_cluster(&self.subgraphs, &loose, positions, tree_subgraphs);
+
+        let shift = start - cross_min(positions);
+        if shift.is_finite() {
+            for point in positions.values_mut() {
+                if top_down {
+                    point.x += shift;
+                } else {
+                    point.y += shift;
+                }
+            }
+        }
+    }
+
+    fn pack_cluster(
+        &self,
+        subgraphs: &[Subgraph],
+        nodes: &[String],
+        positions: &mut HashMap<String, Point>,
+        tree_subgraphs: &HashSet<String>,
+    ) {
+        // Subgraphs already laid out as trees keep their shape.
+        for subgraph in subgraphs
+            .iter()
+            .filter(|subgraph| !tree_subgraphs.contains(&subgraph.id))
+        {
+            self.pack_cluster(
+                &subgraph.children,
+                &subgraph.nodes,
+                positions,
+                tree_subgraphs,
+            );
+        }
+
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        let node_gap = self.config.node_spacing.unwrap_or(if top_down {
+            (NODE_SPACING - NODE_WIDTH).max(EDGE_COLLISION_MARGIN * 2.0)
+        } else {
+            (NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0)
+        });
+        let fallback_height = self
+            .nodes
+            .values()
+            .map(|node| node.height)
+            .fold(NODE_HEIGHT, f32::max);
+        let axes = |rect: &Rect| {
+            if top_down {
+                ((rect.min_y, rect.max_y), (rect.min_x, rect.max_x))
+            } else {
+                ((rect.min_x, rect.max_x), (rect.min_y, rect.max_y))
+            }
+        };
+
+        let mut items: Vec<ClusterItem> = subgraphs
+            .iter()
+            .filter_map(|subgraph| {
+                let members = subgraph_nodes(subgraph);
+                let core = group_bounds(&members, positions, &self.nodes)?;
+                let frame = collect_subgraph_visual(
+                    subgraph,
+                    positions,
+                    &self.nodes,
+                    fallback_height,
+                    &mut Vec::new(),
+                    0,
+                    None,
+                )?;
+                Some(ClusterItem {
+                    rect: frame,
+                    span: axes(&core).0,
+                    members: members.into_iter().collect(),
+                    is_frame: true,
+                })
+            })
+            .chain(nodes.iter().filter_map(|id| {
+                let node = self.nodes.get(id)?;
+                let rect = node_rect(*positions.get(id)?, node.width, node.height);
+                Some(ClusterItem {
+                    rect,
+                    span: axes(&rect).0,
+                    members: vec![id.clone()],
+                    is_frame: false,
+                })
+            }))
+            .collect();
+        items.sort_by(|a, b| {
+            let (a_min, a_max) = axes(&a.rect).1;
+            let (b_min, b_max) = axes(&b.rect).1;
+            (a_min + a_max).total_cmp(&(b_min + b_max))
+        });
+
+        let mut placed: Vec<(Rect, (f32, f32), bool)> = Vec::new();
+        let mut settled: HashSet<String> = HashSet::new();
+        for ClusterItem {
+            mut rect,
+            span,
+            members,
+            is_frame,
+        } in items
+        {
+            if !is_frame {
+                // Earlier pushes may have carried this node along with its parent.
+                let node = &self.nodes[&members[0]];
+                rect = node_rect(positions[&members[0]], node.width, node.height);
+            }
+            let (cross_min, cross_max) = axes(&rect).1;
+            let target = placed
+                .iter()
+                .filter(|(_, other, _)| other.0 < span.1 && span.0 < other.1)
+                .map(|(other, _, other_frame)| {
+                    let gap = if is_frame || *other_frame {
+                        SUBGRAPH_PADDING
+                    } else {
+                        node_gap
+                    };
+                    axes(other).1.1 + gap
+                })
+                .fold(cross_min, f32::max);
+            let delta = target - cross_min;
+            if delta > 0.0 {
+                let mut moved = members.clone();
+                if !is_frame {
+                    // Loose descendants follow so chains stay straight.
+                    let mut stack = vec![members[0].as_str()];
+                    let mut seen = HashSet::new();
+                    while let Some(current) = stack.pop() {
+                        if !seen.insert(current) {
+                            continue;
+                        }
+                        for edge in self.edges.iter().filter(|edge| edge.from == current) {
+                            if nodes.contains(&edge.to) && !settled.contains(&edge.to) {
+                                stack.push(edge.to.as_str());
+                                if edge.to != members[0] && !moved.contains(&edge.to) {
+                                    moved.push(edge.to.clone());
+                                }
+                            }
+                        }
+                    }
+                }
+                for id in &moved {
+                    if let Some(point) = positions.get_mut(id) {
+                        if top_down {
+                            point.x += delta;
+                        } else {
+                            point.y += delta;
+                        }
+                    }
+                }
+                if top_down {
+                    rect.min_x = target;
+                    rect.max_x = cross_max + delta;
+                } else {
+                    rect.min_y = target;
+                    rect.max_y = cross_max + delta;
+                }
+            }
+            if !is_frame {
+                settled.insert(members[0].clone());
+            }
+            placed.push((rect, span, is_frame));
+        }
+    }
+
+    /// The diagram's edges as a [`Forest`]; `None` unless they form one: no cycles,
+    /// self-loops or node with two parents.
+    fn forest(&self) -> Option<Forest<'_>> {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut has_parent = HashSet::new();
+        for edge in &self.edges {
+            if edge.from == edge.to || !has_parent.insert(edge.to.as_str()) {
+                return None;
+            }
+            children
+                .entry(edge.from.as_str())
+                .or_default()
+                .push(edge.to.as_str());
+        }
+        let roots: Vec<&str> = self
+            .order
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !has_parent.contains(id))
+            .collect();
+
+        let mut reached = 0;
+        let mut stack = roots.clone();
+        while let Some(id) = stack.pop() {
+            reached += 1;
+            stack.extend(children.get(id).into_iter().flatten());
+        }
+        (reached == self.order.len()).then_some(Forest { roots, children })
+    }
+
+    /// Positions for the `tree` and `radial` layouts, or `None` when neither is selected or
+    /// the diagram is not a forest.
+    fn tree_positions(&self) -> Option<HashMap<String, Point>> {
+        let radial = match self.config.layout {
+            LayoutEngine::Tree => false,
+            LayoutEngine::Radial => true,
+            _ => return None,
+        };
+        let Forest { roots, children } = self.forest()?;
+        if roots.is_empty() {
+            return None;
+        }
+
+        // Parents come before their children, so walking it backwards settles every subtree
+        // before the node above it.
+        let mut preorder = Vec::with_capacity(self.order.len());
+        let mut depth: HashMap<&str, usize> = HashMap::new();
+        let mut stack: Vec<&str> = roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let level = depth.get(id).copied().unwrap_or(0);
+            depth.insert(id, level);
+            preorder.push(id);
+            for &child in children.get(id).into_iter().flatten().rev() {
+                depth.insert(child, level + 1);
+                stack.push(child);
+            }
+        }
+
+        let mut positions = if radial {
+            self.radial_positions(&roots, &children, &preorder, &depth)
+        } else {
+            self.tidy_positions(&roots, &children, &preorder, &depth)
+        };
+
+        let bounds = group_bounds(
+            &positions.keys().cloned().collect(),
+            &positions,
+            &self.nodes,
+        )?;
+        for point in positions.values_mut() {
+            point.x += START_OFFSET - bounds.min_x;
+            point.y += START_OFFSET - bounds.min_y;
+        }
+        Some(positions)
+    }
+
+    /// Reingold-Tilford style placement: each subtree is packed as close to its left sibling
+    /// as their level-by-level outlines allow, and parents sit centred over their children.
+    fn tidy_positions(
+        &self,
+        roots: &[&str],
+        children: &HashMap<&str, Vec<&str>>,
+        preorder: &[&str],
+        depth: &HashMap<&str, usize>,
+    ) -> HashMap<String, Point> {
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        // Size across and along the flow.
+        let sizes = |id: &str| {
+            let node = &self.nodes[id];
+            if top_down {
+                (node.width, node.height)
+            } else {
+                (node.height, node.width)
+            }
+        };
+        let gap = self.config.node_spacing.unwrap_or(if top_down {
+            (NODE_SPACING - NODE_WIDTH).max(EDGE_COLLISION_MARGIN * 2.0)
+        } else {
+            (NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0)
+        });
+
+        // Each subtree's extent on every level, relative to its root's centre.
+        let mut outlines: HashMap<&str, Vec<(f32, f32)>> = HashMap::new();
+        let mut offsets: HashMap<&str, f32> = HashMap::new();
+        for &id in preorder.iter().rev() {
+            let half = sizes(id).0 / 2.0;
+            let kids = children.get(id).map(Vec::as_slice).unwrap_or_default();
+            let mut outline = vec![(-half, half)];
+            if !kids.is_empty() {
+                let subtrees: Vec<_> = kids.iter().map(|kid| &outlines[kid]).collect();
+                let (placed, merged) = pack_outlines(&subtrees, gap);
+                let center = (placed[0] + placed[placed.len() - 1]) / 2.0;
+                for (kid, offset) in kids.iter().zip(placed) {
+                    offsets.insert(kid, offset - center);
+                }
+                outline.extend(
+                    merged
+                        .into_iter()
+                        .map(|(min, max)| (min - center, max - center)),
+                );
+            }
+            outlines.insert(id, outline);
+        }
+        let trees: Vec<_> = roots.iter().map(|root| &outlines[root]).collect();
+        let (placed, _) = pack_outlines(&trees, gap * 2.0);
+        for (root, offset) in roots.iter().zip(placed) {
+            offsets.insert(root, offset);
+        }
+
+        let levels = depth.values().copied().max().unwrap_or(0) + 1;
+        let mut level_size = vec![0.0_f32; levels];
+        for &id in preorder {
+            let size = &mut level_size[depth[id]];
+            *size = size.max(sizes(id).1);
+        }
+        let rank_gap = self.config.rank_spacing.unwrap_or(TREE_LEVEL_GAP);
+        let mut level_center = vec![0.0_f32; levels];
+        for level in 1..levels {
+            level_center[level] = level_center[level - 1]
+                + (level_size[level - 1] + level_size[level]) / 2.0
+                + rank_gap;
+        }
+        let flipped = matches!(self.direction, Direction::BottomTop | Direction::RightLeft);
+
+        let mut parent_cross: HashMap<&str, f32> = HashMap::new();
+        let mut positions = HashMap::new();
+        for &id in preorder {
+            let along = parent_cross.get(id).copied().unwrap_or(0.0) + offsets[id];
+            for &kid in children.get(id).into_iter().flatten() {
+                parent_cross.insert(kid, along);
+            }
+            let main = if flipped {
+                -level_center[depth[id]]
+            } else {
+                level_center[depth[id]]
+            };
+            let point = if top_down {
+                Point { x: along, y: main }
+            } else {
+                Point { x: main, y: along }
+            };
+            positions.insert(id.to_string(), point);
+        }
+        positions
+    }
+
+    /// Puts the root in the middle and each level on a ring, giving every subtree a wedge
+    /// in proportion to its leaves. The ring spacing grows until neighbours on the same ring
+    /// clear each other.
+    fn radial_positions(
+        &self,
+        roots: &[&str],
+        children: &HashMap<&str, Vec<&str>>,
+        preorder: &[&str],
+        depth: &HashMap<&str, usize>,
+    ) -> HashMap<String, Point> {
+        let mut leaves: HashMap<&str, usize> = HashMap::new();
+        for &id in preorder.iter().rev() {
+            let count = children
+                .get(id)
+                .map_or(1, |kids| kids.iter().map(|kid| leaves[kid]).sum());
+            leaves.insert(id, count);
+        }
+
+        // Several roots share an empty centre and start on the first ring.
+        let ring_of = |id: &str| depth[id] + usize::from(roots.len() > 1);
+        let mut wedges: HashMap<&str, (f32, f32)> = HashMap::new();
+        let total: usize = roots.iter().map(|root| leaves[root]).sum();
+        let mut start = 0.0;
+        for &root in roots {
+            let sweep = std::f32::consts::TAU * leaves[root] as f32 / total as f32;
+            wedges.insert(root, (start, sweep));
+            start += sweep;
+        }
+        for &id in preorder {
+            let (mut start, sweep) = wedges[id];
+            for &kid in children.get(id).into_iter().flatten() {
+                let share = sweep * leaves[kid] as f32 / leaves[id] as f32;
+                wedges.insert(kid, (start, share));
+                start += share;
+            }
+        }
+
+        let gap = self
+            .config
+            .node_spacing
+            .unwrap_or(EDGE_COLLISION_MARGIN * 4.0);
+        let size = |id: &str| {
+            let node = &self.nodes[id];
+            node.width.max(node.height)
+        };
+        let mut ring = preorder.iter().map(|id| size(id)).fold(0.0, f32::max)
+            + self.config.rank_spacing.unwrap_or(TREE_LEVEL_GAP);
+        for &id in preorder {
+            let level = ring_of(id);
+            if level == 0 {
+                continue;
+            }
+            let sweep = wedges[id].1.min(std::f32::consts::PI);
+            let chord = 2.0 * (sweep / 2.0).sin();
+            ring = ring.max((size(id) + gap) / (chord * level as f32));
+        }
+
+        preorder
+            .iter()
+            .map(|&id| {
+                let (start, sweep) = wedges[id];
+                let angle = start + sweep / 2.0;
+                let radius = ring * ring_of(id) as f32;
+                let point = Point {
+                    x: radius * angle.cos(),
+                    y: radius * angle.sin(),
+                };
+                (id.to_string(), point)
+            })
+            .collect()
+    }
+
+    /// Stacks the top-level subgraphs side by side across the flow, keeping every node's
+    /// layer from the automatic layout. Nodes sharing a layer within a lane stack in their
+    /// automatic order, centred in the lane; nodes outside any subgraph form a last, unframed
+    /// lane.
+    fn arrange_swimlanes(&self, positions: &mut HashMap<String, Point>) {
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        let lane_of = |id: &String| {
+            let group = self.node_membership.get(id).and_then(|path| path.first());
+            group
+                .and_then(|group| self.subgraphs.iter().position(|sg| sg.id == *group))
+                .unwrap_or(self.subgraphs.len())
+        };
+        let mut lanes: Vec<Vec<&String>> = vec![Vec::new(); self.subgraphs.len() + 1];
+        for id in self.order.iter().filter(|id| positions.contains_key(*id)) {
+            lanes[lane_of(id)].push(id);
+        }
+
+        let gap = self.config.node_spacing.unwrap_or(if top_down {
+            (NODE_SPACING - NODE_WIDTH).max(EDGE_COLLISION_MARGIN * 2.0)
+        } else {
+            (NODE_SPACING - NODE_HEIGHT).max(EDGE_COLLISION_MARGIN * 2.0)
+        });
+        let mut cursor = START_OFFSET;
+        for lane in lanes.iter().filter(|lane| !lane.is_empty()) {
+            let extent = lane
+                .iter()
+                .filter_map(|id| self.nodes.get(*id))
+                .map(|node| if top_down { node.width } else { node.height })
+                .fold(0.0, f32::max);
+            let step = extent + gap;
+
+            let mut layers: BTreeMap<i64, Vec<&String>> = BTreeMap::new();
+            for id in lane {
+                let point = positions[*id];
+                let main = if top_down { point.y } else { point.x };
+                layers.entry(main.round() as i64).or_default().push(id);
+            }
+            let slots = layers.values().map(Vec::len).max().unwrap_or(0);
+            for members in layers.values_mut() {
+                members.sort_by(|a, b| {
+                    let (a, b) = (positions[*a], positions[*b]);
+                    if top_down {
+                        a.x.total_cmp(&b.x)
+                    } else {
+                        a.y.total_cmp(&b.y)
+                    }
+                });
+                let start = cursor + (slots - members.len()) as f32 * step / 2.0;
+                for (slot, id) in members.iter().enumerate() {
+                    let cross = start + slot as f32 * step + extent / 2.0;
+                    let point = positions.get_mut(*id).expect("lane members have positions");
+                    if top_down {
+                        point.x = cross;
+                    } else {
+                        point.y = cross;
+                    }
+                }
+            }
+            cursor += slots as f32 * step - gap + SUBGRAPH_PADDING * 2.0 + SUBGRAPH_LABEL_AREA;
+        }
+    }
+
+    fn separate_subgraphs(
+        &self,
+        positions: &mut HashMap<String, Point>,
+        preserve_tree: bool,
+        tree_subgraphs: &HashSet<String>,
+    ) {
+        let mut placed: Vec<(Rect, HashSet<String>)> = Vec::new();
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        for subgraph in &self.subgraphs {
+            let members = subgraph_nodes(subgraph);
+            let Some(bounds) = group_bounds(&members, positions, &self.nodes) else {
+                continue;
+            };
+            let mut frame = padded_group_bounds(bounds);
+            let mut shift = Point { x: 0.0, y: 0.0 };
+            while let Some((previous, previous_members)) = placed
                 .iter()
                 .find(|(previous, _)| frame.intersects(previous))
             {
@@ -1968,6 +5161,7 @@ impl Diagram {
         &self,
         positions: &HashMap<String, Point>,
         overrides: Option<&LayoutOverrides>,
+        waypoints: &HashMap<String, Vec<Point>>,
     ) -> Result<HashMap<String, Vec<Point>>> {
         let mut routes = HashMap::new();
         let mut label_bounds: HashMap<String, Rect> = HashMap::new();
@@ -2008,6 +5202,7 @@ impl Diagram {
         }
 
         let mut auto_points: HashMap<usize, Vec<Point>> = HashMap::new();
+        let mut self_loops: HashMap<&str, usize> = HashMap::new();
 
         let has_override = |edge_idx: usize| -> bool {
             overrides.map_or(false, |ov| ov.edges.contains_key(&edge_ids[edge_idx]))
@@ -2029,8 +5224,22 @@ impl Diagram {
                 }
             }
 
-            if forward.is_empty() || backward.is_empty() {
-                continue;
+            // A `<-->` edge has no preferred direction, so it can take the empty side and pair
+            // with another edge running the same way; its points are reversed to match.
+            let mut flipped = Vec::new();
+            let (full, empty) = if forward.is_empty() {
+                (&mut backward, &mut forward)
+            } else {
+                (&mut forward, &mut backward)
+            };
+            if empty.is_empty()
+                && let Some(pos) = full
+                    .iter()
+                    .position(|&idx| self.edges[idx].arrow == EdgeArrowDirection::Both)
+            {
+                let idx = full.remove(pos);
+                empty.push(idx);
+                flipped.push(idx);
             }
 
             let from = *positions
@@ -2069,6 +5278,41 @@ impl Diagram {
                 continue;
             }
 
+            if forward.is_empty() || backward.is_empty() {
+                // Edges all running the same way fan out symmetrically around the straight
+                // line; the middle one of an odd count keeps it rather than detouring.
+                let reversed = forward.is_empty();
+                let parallel = if reversed { &backward } else { &forward };
+                let half_span = (parallel.len() - 1) as f32 / 2.0;
+                let step = (base_offset * 2.0).min(max_offset / half_span);
+                for (i, &edge_idx) in parallel.iter().enumerate() {
+                    let slot = i as f32 - half_span;
+                    if has_override(edge_idx) {
+                        continue;
+                    }
+                    if slot.abs() <= f32::EPSILON {
+                        let middle = Point {
+                            x: (from.x + to.x) * 0.5,
+                            y: (from.y + to.y) * 0.5,
+                        };
+                        auto_points.insert(edge_idx, vec![middle]);
+                        continue;
+                    }
+                    let mut points = Self::generate_bidir_points(
+                        from,
+                        to,
+                        slot.abs() * step,
+                        stub_base,
+                        slot.signum(),
+                    );
+                    if reversed {
+                        points.reverse();
+                    }
+                    auto_points.insert(edge_idx, points);
+                }
+                continue;
+            }
+
             let mut first_pair_resolved = false;
             if let (Some(&f_idx0), Some(&b_idx0)) = (forward.first(), backward.first()) {
                 if !has_override(f_idx0) && !has_override(b_idx0) {
@@ -2119,6 +5363,21 @@ impl Diagram {
                 points.reverse();
                 auto_points.insert(edge_idx, points);
             }
+
+            for idx in flipped {
+                if let Some(points) = auto_points.get_mut(&idx) {
+                    points.reverse();
+                }
+            }
+        }
+
+        if self.config.edge_bundling {
+            let bundled = self.bundled_edge_points(positions, &node_bounds, |idx| {
+                has_override(idx)
+                    || auto_points.contains_key(&idx)
+                    || waypoints.contains_key(&edge_ids[idx])
+            });
+            auto_points.extend(bundled);
         }
 
         for (edge_idx, edge) in self.edges.iter().enumerate() {
@@ -2138,10 +5397,23 @@ impl Diagram {
                 } else {
                     if let Some(points) = auto_points.get(&edge_idx) {
                         middle_points.extend(points.iter().copied());
+                    } else if let Some(points) = waypoints.get(edge_id) {
+                        middle_points.extend(points.iter().copied());
                     }
                     false
                 };
 
+            if edge.from == edge.to && !has_custom_override {
+                let nesting = self_loops.entry(edge.from.as_str()).or_default();
+                let path = self.self_loop_route(&node_bounds[&edge.from], *nesting);
+                *nesting += 1;
+                if let Some(label_rect) = label_rect_for_route(edge, &path) {
+                    label_bounds.insert(edge_id.clone(), label_rect.inflate(EDGE_COLLISION_MARGIN));
+                }
+                routes.insert(edge_id.clone(), path);
+                continue;
+            }
+
             if !has_custom_override && middle_points.is_empty() {
                 let backward = match self.direction {
                     Direction::TopDown => to.y < from.y,
@@ -2203,11 +5475,12 @@ impl Diagram {
                                 .unwrap(),
                         );
                     }
-                } else if let Some(label) = &edge.label
-                    && matches!(self.direction, Direction::TopDown | Direction::BottomTop)
-                {
-                    if backward {
-                        let label_width = measure_label_box(&normalize_label_lines(label)).0;
+                } else if let Some(label) = &edge.label {
+                    let (label_width, label_height) =
+                        measure_label_box(&normalize_label_lines(label));
+                    let top_down =
+                        matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+                    if backward && top_down {
                         let x = node_bounds[&edge.from]
                             .rect
                             .min_x
@@ -2215,14 +5488,29 @@ impl Diagram {
                             - EDGE_COLLISION_MARGIN
                             - label_width / 2.0;
                         middle_points.extend(side_points(from, to, x));
-                    } else {
-                        middle_points.push(Point {
-                            x: to.x,
-                            y: from.y + (to.y - from.y) * 0.65,
-                        });
-                    }
-                }
-            }
+                    } else if backward {
+                        let y = node_bounds[&edge.from]
+                            .rect
+                            .min_y
+                            .min(node_bounds[&edge.to].rect.min_y)
+                            - EDGE_COLLISION_MARGIN
+                            - label_height / 2.0;
+                        middle_points.extend(row_points(from, to, y));
+                    } else if top_down {
+                        // Bend before the target, however close the rows are.
+                        let target = node_bounds[&edge.to].rect;
+                        let y = from.y + (to.y - from.y) * 0.65;
+                        let y = if to.y >= from.y {
+                            y.min(target.min_y - EDGE_COLLISION_MARGIN)
+                        } else {
+                            y.max(target.max_y + EDGE_COLLISION_MARGIN)
+                        };
+                        middle_points.push(Point { x: to.x, y });
+                    } else if (to.y - from.y).abs() > f32::EPSILON {
+                        middle_points.push(Point { x: from.x, y: to.y });
+                    }
+                }
+            }
 
             let mut path = build_route(from, &middle_points, to);
             if !has_custom_override {
@@ -2350,6 +5638,34 @@ impl Diagram {
                         break;
                     }
                 }
+
+                // The heuristics above give up after a few tries; search the free space for
+                // anything still running through a node.
+                if self.route_collides_with_nodes(edge, &path, &node_bounds) {
+                    let own = |id: &String| node_bounds[id].rect.inflate(EDGE_COLLISION_MARGIN);
+                    let (from_rect, to_rect) = (own(&edge.from), own(&edge.to));
+                    let mut obstacles: Vec<Rect> = node_bounds
+                        .iter()
+                        .filter(|(id, _)| **id != edge.from && **id != edge.to)
+                        .map(|(_, bounds)| bounds.rect.inflate(EDGE_COLLISION_MARGIN))
+                        .collect();
+                    let node_count = obstacles.len();
+                    obstacles.extend(
+                        group_frames
+                            .iter()
+                            .filter(|(members, _)| {
+                                !members.contains(&edge.from) && !members.contains(&edge.to)
+                            })
+                            .map(|(_, frame)| *frame),
+                    );
+                    if let Some(found) = grid_route(from, from_rect, to, to_rect, &obstacles)
+                        .or_else(|| {
+                            grid_route(from, from_rect, to, to_rect, &obstacles[..node_count])
+                        })
+                    {
+                        path = found;
+                    }
+                }
             }
 
             if has_custom_override {
@@ -2371,9 +5687,248 @@ impl Diagram {
             routes.insert(edge_id.clone(), path);
         }
 
+        self.spread_edge_ports(&mut routes, &node_bounds, &edge_ids, overrides);
         Ok(routes)
     }
 
+    /// Bend points merging the edges that converge on a node with at least
+    /// [`EDGE_BUNDLE_MIN_FAN_IN`] incoming edges: each leaves its source straight, joins the
+    /// others on a shared bus partway across the gap, runs down a trunk in line with the
+    /// target and splits up to [`EDGE_BUNDLE_SPLIT`] before it, fanning out in source order.
+    /// Edges that `skip` accepts, run against the flow or have no room to bend are left alone.
+    fn bundled_edge_points(
+        &self,
+        positions: &HashMap<String, Point>,
+        node_bounds: &HashMap<String, NodeBoundary>,
+        skip: impl Fn(usize) -> bool,
+    ) -> HashMap<usize, Vec<Point>> {
+        let top_down = matches!(self.direction, Direction::TopDown | Direction::BottomTop);
+        // Main-axis coordinates are flipped for upward and leftward diagrams so the flow
+        // always runs towards larger values.
+        let sign = if matches!(self.direction, Direction::BottomTop | Direction::RightLeft) {
+            -1.0
+        } else {
+            1.0
+        };
+        let cross = |point: Point| if top_down { point.x } else { point.y };
+        let at = |main: f32, cross: f32| {
+            if top_down {
+                Point {
+                    x: cross,
+                    y: main * sign,
+                }
+            } else {
+                Point {
+                    x: main * sign,
+                    y: cross,
+                }
+            }
+        };
+        let extent = |id: &str| {
+            let rect = node_bounds[id].rect;
+            let (a, b) = if top_down {
+                (rect.min_y * sign, rect.max_y * sign)
+            } else {
+                (rect.min_x * sign, rect.max_x * sign)
+            };
+            (a.min(b), a.max(b))
+        };
+
+        let mut fan_in: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (idx, edge) in self.edges.iter().enumerate() {
+            if edge.from == edge.to
+                || skip(idx)
+                || !node_bounds.contains_key(&edge.from)
+                || !node_bounds.contains_key(&edge.to)
+            {
+                continue;
+            }
+            if extent(&edge.to).0 - extent(&edge.from).1 >= EDGE_COLLISION_MARGIN * 4.0 {
+                fan_in.entry(edge.to.as_str()).or_default().push(idx);
+            }
+        }
+
+        let mut points = HashMap::new();
+        for (target, mut members) in fan_in {
+            if members.len() < EDGE_BUNDLE_MIN_FAN_IN {
+                continue;
+            }
+            members.sort_by(|a, b| {
+                cross(positions[&self.edges[*a].from])
+                    .total_cmp(&cross(positions[&self.edges[*b].from]))
+            });
+            let trunk = cross(positions[target]);
+            let near = extent(target).0;
+            let far = members
+                .iter()
+                .map(|idx| extent(&self.edges[*idx].from).1)
+                .fold(f32::MIN, f32::max);
+            let merge = far + (near - far) * 0.3;
+            let split_reach = EDGE_BUNDLE_SPLIT.min((near - far) * 0.4);
+            let bounds = &node_bounds[target];
+            let side = if top_down {
+                bounds.width
+            } else {
+                bounds.height
+            };
+            let step = side * 0.8 / members.len() as f32;
+            let first = -((members.len() - 1) as f32) * step / 2.0;
+            for (slot, idx) in members.into_iter().enumerate() {
+                let source = cross(positions[&self.edges[idx].from]);
+                points.insert(
+                    idx,
+                    vec![
+                        at(merge, source),
+                        at(merge, trunk),
+                        at(near - split_reach, trunk),
+                        at(near - split_reach / 4.0, trunk + first + slot as f32 * step),
+                    ],
+                );
+            }
+        }
+        points
+    }
+
+    /// Routes `A --> A` as a loop off the side of the node that edges across the flow leave
+    /// free; further loops on the same node nest outside the earlier ones.
+    fn self_loop_route(&self, bounds: &NodeBoundary, nesting: usize) -> Vec<Point> {
+        let side = match self.direction {
+            Direction::TopDown | Direction::BottomTop => Side::Right,
+            Direction::LeftRight | Direction::RightLeft => Side::Top,
+        };
+        let reach = EDGE_SELF_LOOP_SIZE * (1.0 + nesting as f32);
+        let spread = if side.is_horizontal() {
+            bounds.width
+        } else {
+            bounds.height
+        } / 4.0;
+        let port = |offset: f32, at_end: bool| {
+            let (inside, outside) = side.port_segment(bounds, offset);
+            clip_segment_exit_with_shape(inside, outside, bounds, at_end).unwrap_or(inside)
+        };
+        let (start, end) = (port(-spread, false), port(spread, true));
+        let out = |point: Point| match side {
+            Side::Right => Point {
+                x: bounds.rect.max_x + reach,
+                y: point.y,
+            },
+            Side::Left => Point {
+                x: bounds.rect.min_x - reach,
+                y: point.y,
+            },
+            Side::Top => Point {
+                x: point.x,
+                y: bounds.rect.min_y - reach,
+            },
+            Side::Bottom => Point {
+                x: point.x,
+                y: bounds.rect.max_y + reach,
+            },
+        };
+        vec![start, out(start), out(end), end]
+    }
+
+    /// Gives edges that meet the same side of a node their own attachment points, spread along
+    /// that side in the order of where they come from so they do not cross at the node.
+    fn spread_edge_ports(
+        &self,
+        routes: &mut HashMap<String, Vec<Point>>,
+        node_bounds: &HashMap<String, NodeBoundary>,
+        edge_ids: &[String],
+        overrides: Option<&LayoutOverrides>,
+    ) {
+        let mut sides: BTreeMap<(&str, Side), Vec<PortEnd>> = BTreeMap::new();
+        for (edge, edge_id) in self.edges.iter().zip(edge_ids) {
+            if edge.from == edge.to || overrides.is_some_and(|ov| ov.edges.contains_key(edge_id)) {
+                continue;
+            }
+            let Some(path) = routes.get(edge_id).filter(|path| path.len() >= 2) else {
+                continue;
+            };
+            for (node_id, at_end) in [(&edge.from, false), (&edge.to, true)] {
+                let Some(bounds) = node_bounds.get(node_id) else {
+                    continue;
+                };
+                if bounds.shape == NodeShape::Junction {
+                    continue;
+                }
+                let (point, neighbor) = if at_end {
+                    (path[path.len() - 1], path[path.len() - 2])
+                } else {
+                    (path[0], path[1])
+                };
+                let side = Side::of(bounds, point);
+                let along = if side.is_horizontal() {
+                    neighbor.x
+                } else {
+                    neighbor.y
+                };
+                sides
+                    .entry((node_id.as_str(), side))
+                    .or_default()
+                    .push(PortEnd {
+                        edge_id,
+                        at_end,
+                        along,
+                    });
+            }
+        }
+
+        for ((node_id, side), mut ports) in sides {
+            if ports.len() < 2 {
+                continue;
+            }
+            ports.sort_by(|a, b| a.along.total_cmp(&b.along));
+            let bounds = &node_bounds[node_id];
+            let length = if side.is_horizontal() {
+                bounds.width
+            } else {
+                bounds.height
+            };
+            // Curved and pointed outlines narrow towards the corners.
+            let usable = match bounds.shape {
+                NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Diamond => 0.5,
+                _ => 0.8,
+            } * length;
+            let step = usable / ports.len() as f32;
+            let first = -((ports.len() - 1) as f32) * step / 2.0;
+
+            for (
+                idx,
+                PortEnd {
+                    edge_id, at_end, ..
+                },
+            ) in ports.into_iter().enumerate()
+            {
+                let offset = first + idx as f32 * step;
+                let (inside, outside) = side.port_segment(bounds, offset);
+                let Some(port) = clip_segment_exit_with_shape(inside, outside, bounds, at_end)
+                else {
+                    continue;
+                };
+                let Some(path) = routes.get_mut(edge_id) else {
+                    continue;
+                };
+                let (end, neighbor) = if at_end {
+                    (path.len() - 1, path.len() - 2)
+                } else {
+                    (0, 1)
+                };
+                // Keep a leg that met the side square-on square after the move.
+                if path.len() > 2 {
+                    let old = path[end];
+                    let leg = path[neighbor];
+                    if side.is_horizontal() && (leg.x - old.x).abs() < 0.5 {
+                        path[neighbor].x += port.x - old.x;
+                    } else if !side.is_horizontal() && (leg.y - old.y).abs() < 0.5 {
+                        path[neighbor].y += port.y - old.y;
+                    }
+                }
+                path[end] = port;
+            }
+        }
+    }
+
     fn resolve_bidirectional_pair(
         &self,
         from: Point,
@@ -2871,134 +6426,850 @@ impl Diagram {
                 .retain(|edge| edge.from != node_id && edge.to != node_id);
             self.node_membership.remove(node_id);
             prune_node_from_subgraphs(&mut self.subgraphs, node_id);
+            for group in &mut self.config.rank_groups {
+                group.retain(|id| id != node_id);
+            }
+            self.config.rank_groups.retain(|group| group.len() > 1);
+            self.config.links.remove(node_id);
+            self.config.image_sources.remove(node_id);
         }
         existed
     }
 
-    pub fn remove_edge_by_identifier(&mut self, edge_id: &str) -> bool {
-        let before = self.edges.len();
-        self.edges.retain(|edge| edge_identifier(edge) != edge_id);
-        before != self.edges.len()
-    }
-
-    pub fn to_definition(&self) -> String {
-        if let DiagramKind::Gantt(gantt) = &self.kind {
-            let mut source = gantt.original_source.clone();
-            if !source.ends_with('\n') {
-                source.push('\n');
+    /// Overrides the frontmatter spacing and layout engine with whichever of `options` are set;
+    /// negative spacing clamps to zero and non-finite spacing is ignored.
+    pub fn apply_layout_options(&mut self, options: &LayoutOptions) {
+        let config = &mut self.config;
+        for (value, target) in [
+            (options.node_spacing, &mut config.node_spacing),
+            (options.rank_spacing, &mut config.rank_spacing),
+            (options.margin, &mut config.margin),
+        ] {
+            if let Some(value) = value.filter(|value| value.is_finite()) {
+                *target = Some(value.max(0.0));
             }
-            return source;
         }
+        if let Some(layout) = options.layout {
+            config.layout = layout;
+        }
+        let refit = options.wrap_width.is_some() || options.max_label_width.is_some();
+        if let Some(width) = options.wrap_width {
+            config.wrap_width = Some(width.max(0.0));
+        }
+        if let Some(width) = options.max_label_width {
+            config.max_label_width = Some(width.max(0.0));
+        }
+        if refit {
+            self.apply_label_fitting();
+        }
+    }
 
-        let mut lines = Vec::new();
-        lines.push(format!("graph {}", self.direction.as_token()));
-
-        let mut emitted = HashSet::new();
-
-        for (idx, subgraph) in self.subgraphs.iter().enumerate() {
-            self.emit_subgraph_definition(subgraph, 1, &mut lines, &mut emitted);
-            if idx + 1 != self.subgraphs.len() {
-                lines.push(String::new());
+    /// Layers `options` over the frontmatter `fontFamily`. Fails when the font file, icon
+    /// directory or watermark image can't be read.
+    pub fn apply_render_options(&mut self, options: &RenderOptions) -> Result<()> {
+        if let Some(path) = &options.font_file {
+            let family = font_file_family(path)?;
+            self.config.font_files.push(path.clone());
+            if options.font_family.is_none() {
+                self.config.font_family = Some(family);
             }
         }
-
-        if !self.subgraphs.is_empty() && self.order.iter().any(|id| !emitted.contains(id)) {
-            lines.push(String::new());
+        if let Some(family) = options
+            .font_family
+            .as_deref()
+            .map(str::trim)
+            .filter(|family| !family.is_empty())
+        {
+            self.config.font_family = Some(family.to_string());
+        }
+        if let Some(dir) = &options.icon_dir {
+            self.config.icons.extend(load_icon_dir(dir)?);
+        }
+        if options.grid.is_some() {
+            self.config.grid = options.grid;
+        }
+        if let Some(watermark) = &options.watermark {
+            self.config.watermark = Some(watermark.load()?);
         }
+        Ok(())
+    }
 
-        for id in &self.order {
-            if emitted.contains(id) {
+    /// Reads every `%% OXDRAW IMAGE <id> <path-or-url>` image and embeds it in its node, so the
+    /// exported SVG carries it as a data URI. Relative paths resolve against `base_dir`, the
+    /// diagram's own directory; without one they resolve against the working directory.
+    ///
+    /// This reads any path and fetches any URL the source names, and blocks while it does, so
+    /// only call it for trusted sources and off any async runtime.
+    pub fn load_image_sources(&mut self, base_dir: Option<&Path>) -> Result<()> {
+        for (node_id, source) in &self.config.image_sources {
+            let Some(node) = self.nodes.get_mut(node_id) else {
                 continue;
-            }
-            if let Some(node) = self.nodes.get(id) {
-                if let Some(image) = &node.image {
-                    lines.push(Self::format_image_comment(id, image));
-                }
-                lines.push(Self::format_node_line(id, node));
-            }
+            };
+            let data = read_image_source(&source.src, base_dir).with_context(|| {
+                format!("failed to load image '{}' for node '{node_id}'", source.src)
+            })?;
+            let context = || format!("image '{}' for node '{node_id}'", source.src);
+            let mime_type = sniff_image_mime_type(&data, &source.src).with_context(context)?;
+            let (width, height) =
+                decode_image_dimensions(mime_type, &data).with_context(context)?;
+            apply_image_to_node(
+                node,
+                NodeImage {
+                    mime_type: mime_type.to_string(),
+                    data,
+                    width,
+                    height,
+                    padding: source.padding,
+                },
+            );
         }
+        Ok(())
+    }
 
-        if !self.edges.is_empty() && !lines.is_empty() {
-            lines.push(String::new());
-        }
+    /// The `fontFamily` when neither an installed font nor a registered font file provides its
+    /// first family, so raster output would silently draw a fallback face.
+    pub fn missing_font_family(&self) -> Result<Option<&str>> {
+        let Some(family) = self.config.font_family.as_deref() else {
+            return Ok(None);
+        };
+        let database = font_database_with(&self.config.font_files)?;
+        Ok((!has_font_family(&database, family)).then_some(family))
+    }
 
-        for edge in &self.edges {
-            lines.push(Self::format_edge_line(edge));
+    /// `label` broken onto more lines so its node stays within `config.wrap_width`, then with
+    /// each line cut to `config.max_label_width`. Labels of non-flowchart diagrams and markdown
+    /// labels are left alone.
+    pub(crate) fn fitted_label<'a>(&self, label: &'a str) -> Cow<'a, str> {
+        if !matches!(self.kind, DiagramKind::Flowchart) {
+            return Cow::Borrowed(label);
+        }
+        let wrapped = match self.config.wrap_width {
+            Some(width) => wrap_label(label, width - NODE_TEXT_HORIZONTAL_PADDING),
+            None => Cow::Borrowed(label),
+        };
+        match self.config.max_label_width {
+            Some(width) => match truncate_label(&wrapped, width) {
+                Cow::Borrowed(_) => wrapped,
+                Cow::Owned(truncated) => Cow::Owned(truncated),
+            },
+            None => wrapped,
         }
+    }
 
-        while matches!(lines.last(), Some(line) if line.is_empty()) {
-            lines.pop();
+    /// Resizes nodes for their wrapped and truncated labels; image nodes keep their size.
+    fn apply_label_fitting(&mut self) {
+        if self.config.wrap_width.is_none() && self.config.max_label_width.is_none() {
+            return;
+        }
+        let sizes: Vec<(String, (f32, f32))> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.image.is_none())
+            .map(|(id, node)| {
+                let label = self.fitted_label(&node.label);
+                (id.clone(), compute_node_dimensions(node.shape, &label))
+            })
+            .collect();
+        for (id, (width, height)) in sizes {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                node.width = width;
+                node.height = height;
+            }
         }
+    }
 
-        let mut output = lines.join("\n");
-        output.push('\n');
-        output
+    /// The subgraph called `id`, at any depth.
+    pub fn subgraph(&self, id: &str) -> Option<&Subgraph> {
+        fn find<'a>(subgraphs: &'a [Subgraph], id: &str) -> Option<&'a Subgraph> {
+            subgraphs.iter().find_map(|subgraph| {
+                (subgraph.id == id)
+                    .then_some(subgraph)
+                    .or_else(|| find(&subgraph.children, id))
+            })
+        }
+        find(&self.subgraphs, id)
     }
 
-    fn emit_subgraph_definition(
-        &self,
-        subgraph: &Subgraph,
-        depth: usize,
-        lines: &mut Vec<String>,
-        emitted: &mut HashSet<String>,
-    ) {
-        let indent = "    ".repeat(depth);
-        let header = if subgraph.label == subgraph.id {
-            subgraph.id.clone()
-        } else {
-            format!("{}[{}]", subgraph.id, subgraph.label)
-        };
-        lines.push(format!("{}subgraph {}", indent, header));
+    /// Replaces every subgraph in `collapsed` with one summary node under the subgraph's id
+    /// and label. Its members are hidden, edges between them dropped and edges to them from
+    /// outside re-attached to the summary node. A collapsed subgraph inside another collapsed
+    /// one is swallowed by the outer one.
+    pub fn apply_collapsed(&mut self, collapsed: &BTreeSet<String>) {
+        fn outermost(
+            subgraphs: &[Subgraph],
+            collapsed: &BTreeSet<String>,
+            out: &mut Vec<Subgraph>,
+        ) {
+            for subgraph in subgraphs {
+                if collapsed.contains(&subgraph.id) {
+                    out.push(subgraph.clone());
+                } else {
+                    outermost(&subgraph.children, collapsed, out);
+                }
+            }
+        }
+        fn ancestors(subgraphs: &[Subgraph], id: &str) -> Option<Vec<String>> {
+            for subgraph in subgraphs {
+                if subgraph.id == id {
+                    return Some(Vec::new());
+                }
+                if let Some(mut path) = ancestors(&subgraph.children, id) {
+                    path.insert(0, subgraph.id.clone());
+                    return Some(path);
+                }
+            }
+            None
+        }
+        fn replace(subgraphs: &mut Vec<Subgraph>, parent: Option<&str>, id: &str) {
+            match parent {
+                None => subgraphs.retain(|subgraph| subgraph.id != id),
+                Some(parent) => {
+                    for subgraph in subgraphs.iter_mut() {
+                        if subgraph.id == parent {
+                            subgraph.children.retain(|child| child.id != id);
+                            subgraph.nodes.push(id.to_string());
+                        } else {
+                            replace(&mut subgraph.children, Some(parent), id);
+                        }
+                    }
+                }
+            }
+        }
 
-        let inner_indent = "    ".repeat(depth + 1);
-        let direct_nodes: HashSet<&str> = subgraph.nodes.iter().map(|id| id.as_str()).collect();
-        for id in &self.order {
-            if !direct_nodes.contains(id.as_str()) {
+        let mut targets = Vec::new();
+        outermost(&self.subgraphs, collapsed, &mut targets);
+        for subgraph in targets {
+            if self.nodes.contains_key(&subgraph.id) {
                 continue;
             }
-            if emitted.insert(id.clone()) {
-                if let Some(node) = self.nodes.get(id) {
-                    if let Some(image) = &node.image {
-                        lines.push(format!(
-                            "{}{}",
-                            inner_indent,
-                            Self::format_image_comment(id, image)
-                        ));
+            let members = subgraph_nodes(&subgraph);
+            let path = ancestors(&self.subgraphs, &subgraph.id).unwrap_or_default();
+            replace(
+                &mut self.subgraphs,
+                path.last().map(String::as_str),
+                &subgraph.id,
+            );
+
+            let at = self
+                .order
+                .iter()
+                .position(|id| members.contains(id))
+                .unwrap_or(self.order.len());
+            self.order.retain(|id| !members.contains(id));
+            self.order
+                .insert(at.min(self.order.len()), subgraph.id.clone());
+            for id in &members {
+                self.nodes.remove(id);
+                self.node_membership.remove(id);
+            }
+            let (width, height) =
+                compute_node_dimensions(NodeShape::Subroutine, &self.fitted_label(&subgraph.label));
+            self.nodes.insert(
+                subgraph.id.clone(),
+                Node {
+                    label: subgraph.label.clone(),
+                    shape: NodeShape::Subroutine,
+                    image: None,
+                    width,
+                    height,
+                },
+            );
+            if !path.is_empty() {
+                self.node_membership.insert(subgraph.id.clone(), path);
+            }
+
+            self.edges
+                .retain(|edge| !(members.contains(&edge.from) && members.contains(&edge.to)));
+            for edge in &mut self.edges {
+                for end in [&mut edge.from, &mut edge.to] {
+                    if members.contains(end) {
+                        *end = subgraph.id.clone();
                     }
-                    lines.push(format!(
-                        "{}{}",
-                        inner_indent,
-                        Self::format_node_line(id, node)
-                    ));
                 }
             }
+            for group in &mut self.config.rank_groups {
+                group.retain(|id| !members.contains(id));
+            }
+            self.config.rank_groups.retain(|group| group.len() > 1);
+            self.config.links.retain(|id, _| !members.contains(id));
+            self.config
+                .image_sources
+                .retain(|id, _| !members.contains(id));
         }
+        renumber_parallel_edges(&mut self.edges);
+    }
 
-        for child in &subgraph.children {
-            self.emit_subgraph_definition(child, depth + 1, lines, emitted);
+    /// Removes everything `view` hides and applies its theme; zoom is left to the caller.
+    pub fn apply_view(&mut self, view: &LayoutView) {
+        fn hidden_members(
+            subgraphs: &[Subgraph],
+            hidden: &BTreeSet<String>,
+            out: &mut Vec<String>,
+        ) {
+            for subgraph in subgraphs {
+                if hidden.contains(&subgraph.id) {
+                    collect_subgraph_nodes(subgraph, out);
+                } else {
+                    hidden_members(&subgraph.children, hidden, out);
+                }
+            }
+        }
+        fn collect_subgraph_nodes(subgraph: &Subgraph, out: &mut Vec<String>) {
+            out.extend(subgraph.nodes.iter().cloned());
+            for child in &subgraph.children {
+                collect_subgraph_nodes(child, out);
+            }
         }
 
-        lines.push(format!("{}end", indent));
-    }
-
-    fn format_node_line(id: &str, node: &Node) -> String {
-        node.shape.format_spec(id, &node.label)
+        let mut hidden: Vec<String> = view.hidden_nodes.iter().cloned().collect();
+        hidden_members(&self.subgraphs, &view.hidden_subgraphs, &mut hidden);
+        for id in &hidden {
+            self.remove_node(id);
+        }
+        self.edges
+            .retain(|edge| !view.hidden_edges.contains(&edge_identifier(edge)));
+        if let Some(theme) = &view.theme {
+            self.config.theme = Some(theme.clone());
+            self.config.custom_theme = None;
+        }
     }
 
-    fn format_image_comment(id: &str, image: &NodeImage) -> String {
-        let encoded = BASE64_STANDARD.encode(&image.data);
-        let sanitized_padding = if image.padding.is_finite() && image.padding >= 0.0 {
-            image.padding
-        } else {
-            0.0
-        };
+    /// Nodes, edges and subgraphs that differ between `self` and `next`, keyed by id.
+    /// Override pruning is left for the caller to fill in.
+    pub fn change_set(&self, next: &Diagram) -> SourceChangeSet {
+        fn diff_ids(
+            before: &BTreeMap<String, String>,
+            after: &BTreeMap<String, String>,
+        ) -> ChangeList {
+            let mut changes = ChangeList::default();
+            for (id, signature) in after {
+                match before.get(id) {
+                    None => changes.added.push(id.clone()),
+                    Some(previous) if previous != signature => changes.modified.push(id.clone()),
+                    Some(_) => {}
+                }
+            }
+            changes.removed = before
+                .keys()
+                .filter(|id| !after.contains_key(*id))
+                .cloned()
+                .collect();
+            changes
+        }
+        fn node_signatures(diagram: &Diagram) -> BTreeMap<String, String> {
+            diagram
+                .nodes
+                .iter()
+                .map(|(id, node)| {
+                    let membership = diagram.node_membership.get(id);
+                    let signature = format!(
+                        "{}|{:?}|{}|{:?}",
+                        node.label,
+                        node.shape,
+                        node.image.is_some(),
+                        membership
+                    );
+                    (id.clone(), signature)
+                })
+                .collect()
+        }
+        fn edge_signatures(diagram: &Diagram) -> BTreeMap<String, String> {
+            diagram
+                .edges
+                .iter()
+                .map(|edge| (edge_identifier(edge), format!("{:?}", edge.label)))
+                .collect()
+        }
+        fn subgraph_signatures(
+            subgraphs: &[Subgraph],
+            parent: Option<&str>,
+            out: &mut BTreeMap<String, String>,
+        ) {
+            for subgraph in subgraphs {
+                let mut nodes = subgraph.nodes.clone();
+                nodes.sort();
+                out.insert(
+                    subgraph.id.clone(),
+                    format!("{}|{:?}|{:?}", subgraph.label, nodes, parent),
+                );
+                subgraph_signatures(&subgraph.children, Some(&subgraph.id), out);
+            }
+        }
+
+        let mut subgraphs_before = BTreeMap::new();
+        subgraph_signatures(&self.subgraphs, None, &mut subgraphs_before);
+        let mut subgraphs_after = BTreeMap::new();
+        subgraph_signatures(&next.subgraphs, None, &mut subgraphs_after);
+
+        let nodes = diff_ids(&node_signatures(self), &node_signatures(next));
+        let edges = diff_ids(&edge_signatures(self), &edge_signatures(next));
+        let subgraphs = diff_ids(&subgraphs_before, &subgraphs_after);
+        let destructive =
+            !nodes.removed.is_empty() || !edges.removed.is_empty() || !subgraphs.removed.is_empty();
+        SourceChangeSet {
+            nodes,
+            edges,
+            subgraphs,
+            pruned_overrides: PrunedOverrides::default(),
+            destructive,
+        }
+    }
+
+    /// Like [`Diagram::change_set`], but pairs a removed node with an added one of the same
+    /// label and shape as a rename, and edges that only follow such a rename likewise.
+    pub fn diff(&self, next: &Diagram) -> DiagramDiff {
+        let SourceChangeSet {
+            mut nodes,
+            mut edges,
+            subgraphs,
+            ..
+        } = self.change_set(next);
+
+        let mut renamed_nodes = Vec::new();
+        nodes.removed.retain(|removed| {
+            let before = &self.nodes[removed];
+            let renamed = nodes.added.iter().position(|added| {
+                let after = &next.nodes[added];
+                after.label == before.label && after.shape == before.shape
+            });
+            match renamed {
+                Some(index) => {
+                    renamed_nodes.push(Rename {
+                        from: removed.clone(),
+                        to: nodes.added.remove(index),
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        let new_id = |id: &str| {
+            renamed_nodes
+                .iter()
+                .find(|rename| rename.from == id)
+                .map_or(id, |rename| rename.to.as_str())
+                .to_string()
+        };
+        let mut renamed_edges = Vec::new();
+        edges.removed.retain(|removed| {
+            let Some(edge) = self
+                .edges
+                .iter()
+                .find(|edge| edge_identifier(edge) == *removed)
+            else {
+                return true;
+            };
+            let moved = Edge {
+                from: new_id(&edge.from),
+                to: new_id(&edge.to),
+                ..edge.clone()
+            };
+            let moved_id = edge_identifier(&moved);
+            let follows_rename = edges.added.contains(&moved_id)
+                && next
+                    .edges
+                    .iter()
+                    .any(|other| edge_identifier(other) == moved_id && other.label == edge.label);
+            if !follows_rename {
+                return true;
+            }
+            edges.added.retain(|added| *added != moved_id);
+            renamed_edges.push(Rename {
+                from: removed.clone(),
+                to: moved_id,
+            });
+            false
+        });
+
+        DiagramDiff {
+            nodes,
+            renamed_nodes,
+            edges,
+            renamed_edges,
+            subgraphs,
+        }
+    }
+
+    /// `next` with the nodes and edges that `diff` removed from `self` put back, plus style
+    /// overrides that draw additions in green, removals in dashed red and modified or renamed
+    /// nodes in amber.
+    pub fn diff_overlay(&self, next: &Diagram, diff: &DiagramDiff) -> (Diagram, LayoutOverrides) {
+        const ADDED: (&str, &str) = ("#c6f6d5", "#2f855a");
+        const REMOVED: (&str, &str) = ("#fed7e2", "#c53030");
+        const CHANGED: (&str, &str) = ("#feebc8", "#c05621");
+
+        let node_style = |(fill, stroke): (&str, &str), border_style| NodeStyleOverride {
+            fill: Some(fill.to_string()),
+            stroke: Some(stroke.to_string()),
+            stroke_width: Some(2.0),
+            border_style,
+            ..NodeStyleOverride::default()
+        };
+        let mut merged = next.clone();
+        let mut overrides = LayoutOverrides::default();
+
+        for id in &diff.nodes.added {
+            overrides
+                .node_styles
+                .insert(id.clone(), node_style(ADDED, None));
+        }
+        let changed = diff
+            .nodes
+            .modified
+            .iter()
+            .chain(diff.renamed_nodes.iter().map(|rename| &rename.to));
+        for id in changed {
+            overrides
+                .node_styles
+                .insert(id.clone(), node_style(CHANGED, None));
+        }
+        for id in &diff.nodes.removed {
+            merged.nodes.insert(id.clone(), self.nodes[id].clone());
+            merged.order.push(id.clone());
+            overrides
+                .node_styles
+                .insert(id.clone(), node_style(REMOVED, Some(BorderStyle::Dashed)));
+        }
+
+        for id in &diff.edges.added {
+            overrides.edge_styles.insert(
+                id.clone(),
+                EdgeStyleOverride {
+                    color: Some(ADDED.1.to_string()),
+                    ..EdgeStyleOverride::default()
+                },
+            );
+        }
+        let new_id = |id: &String| {
+            diff.renamed_nodes
+                .iter()
+                .find(|rename| rename.from == *id)
+                .map_or(id.clone(), |rename| rename.to.clone())
+        };
+        for removed in &diff.edges.removed {
+            let Some(edge) = self
+                .edges
+                .iter()
+                .find(|edge| edge_identifier(edge) == *removed)
+            else {
+                continue;
+            };
+            let edge = Edge {
+                from: new_id(&edge.from),
+                to: new_id(&edge.to),
+                ..edge.clone()
+            };
+            let id = push_edge(&mut merged.edges, edge);
+            overrides.edge_styles.insert(
+                id,
+                EdgeStyleOverride {
+                    line: Some(EdgeKind::Dashed),
+                    color: Some(REMOVED.1.to_string()),
+                    ..EdgeStyleOverride::default()
+                },
+            );
+        }
+        (merged, overrides)
+    }
+
+    pub fn remove_edge_by_identifier(&mut self, edge_id: &str) -> bool {
+        let before = self.edges.len();
+        self.edges.retain(|edge| edge_identifier(edge) != edge_id);
+        renumber_parallel_edges(&mut self.edges);
+        before != self.edges.len()
+    }
+
+    /// Fresh `n<k>` id one past the highest numbered `n` id already used by a node or subgraph.
+    pub fn next_node_id(&self) -> String {
+        fn collect<'a>(subgraphs: &'a [Subgraph], ids: &mut HashSet<&'a str>) {
+            for subgraph in subgraphs {
+                ids.insert(subgraph.id.as_str());
+                collect(&subgraph.children, ids);
+            }
+        }
+
+        let mut taken: HashSet<&str> = self.nodes.keys().map(String::as_str).collect();
+        collect(&self.subgraphs, &mut taken);
+        let mut next = taken
+            .iter()
+            .filter_map(|id| id.strip_prefix('n')?.parse::<usize>().ok())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        while taken.contains(format!("n{next}").as_str()) {
+            next += 1;
+        }
+        format!("n{next}")
+    }
+
+    /// Adds a rectangle labelled `label` under a generated id, optionally connected from `from`.
+    /// Returns the new node id and, when connected, the new edge's identifier.
+    pub fn quick_add_node(
+        &mut self,
+        label: &str,
+        from: Option<&str>,
+    ) -> Result<(String, Option<String>)> {
+        if !matches!(self.kind, DiagramKind::Flowchart) {
+            bail!("nodes can only be added to flowcharts");
+        }
+        if let Some(from) = from
+            && !self.nodes.contains_key(from)
+        {
+            bail!("node '{from}' not found");
+        }
+        let id = self.next_node_id();
+        let label = if label.trim().is_empty() {
+            id.clone()
+        } else {
+            label.trim().to_string()
+        };
+        let (width, height) =
+            compute_node_dimensions(NodeShape::Rectangle, &self.fitted_label(&label));
+        self.nodes.insert(
+            id.clone(),
+            Node {
+                label,
+                shape: NodeShape::Rectangle,
+                image: None,
+                width,
+                height,
+            },
+        );
+        self.order.push(id.clone());
+
+        let edge_id = from.map(|from| {
+            let edge = Edge {
+                from: from.to_string(),
+                to: id.clone(),
+                label: None,
+                kind: EdgeKind::Solid,
+                arrow: EdgeArrowDirection::Forward,
+                head: EdgeHead::Arrow,
+                occurrence: 0,
+            };
+            push_edge(&mut self.edges, edge)
+        });
+        Ok((id, edge_id))
+    }
+
+    pub fn to_definition(&self) -> String {
+        self.to_definition_with(&DefinitionFormat::default())
+    }
+
+    pub fn to_definition_with(&self, format: &DefinitionFormat) -> String {
+        let original_source = match &self.kind {
+            DiagramKind::Gantt(gantt) => Some(&gantt.original_source),
+            DiagramKind::Sequence(sequence) => Some(&sequence.original_source),
+            DiagramKind::State(state) => Some(&state.original_source),
+            DiagramKind::Class(class) => Some(&class.original_source),
+            DiagramKind::Er(er) => Some(&er.original_source),
+            DiagramKind::Pie(pie) => Some(&pie.original_source),
+            DiagramKind::Mindmap(mindmap) => Some(&mindmap.original_source),
+            DiagramKind::Journey(journey) => Some(&journey.original_source),
+            DiagramKind::Timeline(timeline) => Some(&timeline.original_source),
+            DiagramKind::GitGraph(gitgraph) => Some(&gitgraph.original_source),
+            DiagramKind::Quadrant(quadrant) => Some(&quadrant.original_source),
+            DiagramKind::C4(c4) => Some(&c4.original_source),
+            DiagramKind::Block(block) => Some(&block.original_source),
+            DiagramKind::Flowchart => None,
+        };
+        if let Some(original_source) = original_source {
+            let mut source = original_source.clone();
+            if !source.ends_with('\n') {
+                source.push('\n');
+            }
+            return source;
+        }
+
+        let mut lines = Vec::new();
+        if let Some(frontmatter) = &self.config.frontmatter {
+            lines.push("---".to_string());
+            lines.extend(frontmatter.lines().map(str::to_string));
+            lines.push("---".to_string());
+        }
+        lines.push(format!("graph {}", self.direction.as_token()));
+        if let Some(title) = &self.config.title {
+            let in_frontmatter = self
+                .config
+                .frontmatter
+                .as_deref()
+                .is_some_and(|frontmatter| {
+                    frontmatter.lines().any(|line| line.starts_with("title:"))
+                });
+            if !in_frontmatter {
+                lines.push(format!("    {TITLE_COMMENT_PREFIX} {title}"));
+            }
+        }
+        if let Some(title) = &self.config.acc_title {
+            lines.push(format!("    accTitle: {title}"));
+        }
+        match &self.config.acc_descr {
+            Some(descr) if descr.contains('\n') => {
+                lines.push("    accDescr {".to_string());
+                lines.extend(descr.lines().map(|line| format!("        {line}")));
+                lines.push("    }".to_string());
+            }
+            Some(descr) => lines.push(format!("    accDescr: {descr}")),
+            None => {}
+        }
+
+        let mut emitted = HashSet::new();
+
+        for (idx, subgraph) in self.subgraphs.iter().enumerate() {
+            self.emit_subgraph_definition(subgraph, 1, &mut lines, &mut emitted);
+            if idx + 1 != self.subgraphs.len() {
+                lines.push(String::new());
+            }
+        }
+
+        if !self.subgraphs.is_empty() && self.order.iter().any(|id| !emitted.contains(id)) {
+            lines.push(String::new());
+        }
+
+        for id in &self.order {
+            if emitted.contains(id) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(id) {
+                if let Some(comment) = self.image_comment_line(id, node) {
+                    lines.push(comment);
+                }
+                lines.push(Self::format_node_line(id, node));
+            }
+        }
+
+        if !self.edges.is_empty() && !lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        for (idx, group) in self.edge_groups(format.edge_grouping).iter().enumerate() {
+            if idx > 0 {
+                lines.push(String::new());
+            }
+            lines.extend(group.iter().map(|edge| Self::format_edge_line(edge)));
+        }
+
+        if !self.config.links.is_empty() {
+            lines.push(String::new());
+            for (id, link) in &self.config.links {
+                lines.push(Self::format_click_line(id, link));
+            }
+        }
+
+        if !self.config.rank_groups.is_empty() {
+            lines.push(String::new());
+            for group in &self.config.rank_groups {
+                let ids: Vec<_> = group.iter().map(|id| format_node_id(id)).collect();
+                lines.push(format!("{RANK_COMMENT_PREFIX} {}", ids.join(" ")));
+            }
+        }
+
+        while matches!(lines.last(), Some(line) if line.is_empty()) {
+            lines.pop();
+        }
+
+        let mut output = lines.join("\n");
+        output.push('\n');
+        output
+    }
+
+    fn emit_subgraph_definition(
+        &self,
+        subgraph: &Subgraph,
+        depth: usize,
+        lines: &mut Vec<String>,
+        emitted: &mut HashSet<String>,
+    ) {
+        let indent = "    ".repeat(depth);
+        let header = if subgraph.label == subgraph.id {
+            subgraph.id.clone()
+        } else {
+            format!("{}[{}]", subgraph.id, encode_entities(&subgraph.label))
+        };
+        lines.push(format!("{}subgraph {}", indent, header));
+
+        let inner_indent = "    ".repeat(depth + 1);
+        let direct_nodes: HashSet<&str> = subgraph.nodes.iter().map(|id| id.as_str()).collect();
+        for id in &self.order {
+            if !direct_nodes.contains(id.as_str()) {
+                continue;
+            }
+            if emitted.insert(id.clone()) {
+                if let Some(node) = self.nodes.get(id) {
+                    if let Some(comment) = self.image_comment_line(id, node) {
+                        lines.push(format!("{inner_indent}{comment}"));
+                    }
+                    lines.push(format!(
+                        "{}{}",
+                        inner_indent,
+                        Self::format_node_line(id, node)
+                    ));
+                }
+            }
+        }
+
+        for child in &subgraph.children {
+            self.emit_subgraph_definition(child, depth + 1, lines, emitted);
+        }
+
+        lines.push(format!("{}end", indent));
+    }
+
+    fn format_node_line(id: &str, node: &Node) -> String {
+        node.shape.format_spec(id, &node.label)
+    }
+
+    /// The `%% OXDRAW IMAGE` line for `id`: its path or URL when it was loaded from one, so
+    /// the source keeps the reference rather than an inlined copy.
+    fn image_comment_line(&self, id: &str, node: &Node) -> Option<String> {
+        match (self.config.image_sources.get(id), &node.image) {
+            (Some(source), _) => Some(format!(
+                "{} {} {} padding={}",
+                IMAGE_COMMENT_PREFIX,
+                format_node_id(id),
+                source.src,
+                Self::format_padding_value(source.padding)
+            )),
+            (None, Some(image)) => Some(Self::format_image_comment(id, image)),
+            (None, None) => None,
+        }
+    }
+
+    fn format_image_comment(id: &str, image: &NodeImage) -> String {
+        let encoded = BASE64_STANDARD.encode(&image.data);
+        let sanitized_padding = if image.padding.is_finite() && image.padding >= 0.0 {
+            image.padding
+        } else {
+            0.0
+        };
         let padding_str = Self::format_padding_value(sanitized_padding);
         format!(
             "{} {} {} padding={} {}",
-            IMAGE_COMMENT_PREFIX, id, image.mime_type, padding_str, encoded
+            IMAGE_COMMENT_PREFIX,
+            format_node_id(id),
+            image.mime_type,
+            padding_str,
+            encoded
         )
     }
 
+    fn format_click_line(id: &str, link: &NodeLink) -> String {
+        let mut line = format!(
+            "click {} href \"{}\"",
+            format_node_id(id),
+            encode_entities(&link.href)
+        );
+        if let Some(tooltip) = &link.tooltip {
+            line.push_str(&format!(" \"{}\"", encode_entities(tooltip)));
+        }
+        if let Some(target) = &link.target {
+            line.push(' ');
+            line.push_str(target);
+        }
+        line
+    }
+
     fn format_padding_value(value: f32) -> String {
         let mut formatted = format!("{value:.3}");
         if let Some(dot_index) = formatted.find('.') {
@@ -3016,39 +7287,812 @@ impl Diagram {
         }
     }
 
+    /// Splits edges into the blank-line separated blocks emitted by [`Diagram::to_definition_with`],
+    /// keeping parse order within each block.
+    fn edge_groups(&self, grouping: EdgeGrouping) -> Vec<Vec<&Edge>> {
+        let group_key = |edge: &Edge| -> Option<String> {
+            match grouping {
+                EdgeGrouping::None => None,
+                EdgeGrouping::Source => Some(edge.from.clone()),
+                EdgeGrouping::Subgraph => self
+                    .node_membership
+                    .get(&edge.from)
+                    .and_then(|chain| chain.last())
+                    .cloned(),
+            }
+        };
+        let rank: HashMap<&str, usize> = match grouping {
+            EdgeGrouping::None => HashMap::new(),
+            EdgeGrouping::Source => self
+                .order
+                .iter()
+                .enumerate()
+                .map(|(idx, id)| (id.as_str(), idx))
+                .collect(),
+            EdgeGrouping::Subgraph => {
+                fn visit<'a>(subgraphs: &'a [Subgraph], rank: &mut HashMap<&'a str, usize>) {
+                    for subgraph in subgraphs {
+                        let next = rank.len();
+                        rank.insert(subgraph.id.as_str(), next);
+                        visit(&subgraph.children, rank);
+                    }
+                }
+                let mut rank = HashMap::new();
+                visit(&self.subgraphs, &mut rank);
+                rank
+            }
+        };
+
+        let mut groups: Vec<(Option<String>, Vec<&Edge>)> = Vec::new();
+        for edge in &self.edges {
+            let key = group_key(edge);
+            match groups.iter_mut().find(|(existing, _)| *existing == key) {
+                Some((_, group)) => group.push(edge),
+                None => groups.push((key, vec![edge])),
+            }
+        }
+        // Edges from top-level nodes lead when grouping by subgraph.
+        groups.sort_by_key(|(key, _)| {
+            key.as_deref()
+                .map_or(0, |key| rank.get(key).map_or(usize::MAX, |rank| rank + 1))
+        });
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+
     fn format_edge_line(edge: &Edge) -> String {
         if let Some(label) = &edge.label {
             format!(
                 "{} {}|{}| {}",
-                edge.from,
-                edge.kind.connector(edge.arrow),
-                label,
-                edge.to
+                format_node_id(&edge.from),
+                edge.kind.connector_with_head(edge.arrow, edge.head),
+                quote_label_if_needed(label),
+                format_node_id(&edge.to)
             )
         } else {
             format!(
                 "{} {} {}",
-                edge.from,
-                edge.kind.connector(edge.arrow),
-                edge.to
+                format_node_id(&edge.from),
+                edge.kind.connector_with_head(edge.arrow, edge.head),
+                format_node_id(&edge.to)
             )
         }
     }
 }
 
-fn route_intersects_label_rects(route: &[Point], label_bounds: &HashMap<String, Rect>) -> bool {
-    label_bounds
-        .values()
-        .any(|rect| route_intersects_rect(route, *rect))
+fn svg_size_regex() -> &'static Regex {
+    static SVG_SIZE_RE: OnceLock<Regex> = OnceLock::new();
+    SVG_SIZE_RE
+        .get_or_init(|| Regex::new(r#"width="([^"]*)" height="([^"]*)" viewBox="[^"]*""#).unwrap())
 }
 
-fn route_intersects_rect(route: &[Point], rect: Rect) -> bool {
-    route
-        .windows(2)
-        .any(|segment| rect.intersects_segment(segment[0], segment[1]))
+fn minimap_text_regex() -> &'static Regex {
+    static TEXT: OnceLock<Regex> = OnceLock::new();
+    TEXT.get_or_init(|| Regex::new(r"(?s)\s*<text\b.*?</text>").unwrap())
 }
 
-fn label_overlaps_existing(
+/// Swaps the outline width of a rendered node shape and dashes it per `style`.
+fn restyle_node_border(shape: &str, width: Option<f32>, style: Option<BorderStyle>) -> String {
+    static STROKE_WIDTH: OnceLock<Regex> = OnceLock::new();
+    let stroke_width =
+        STROKE_WIDTH.get_or_init(|| Regex::new(r#"stroke-width="([^"]*)""#).unwrap());
+    let dash = style
+        .and_then(BorderStyle::dash_pattern)
+        .map(|pattern| format!(" stroke-dasharray=\"{pattern}\""))
+        .unwrap_or_default();
+    stroke_width
+        .replace_all(shape, |caps: &regex::Captures| {
+            let value = width
+                .filter(|width| width.is_finite() && *width >= 0.0)
+                .map(|width| format!("{width}"))
+                .unwrap_or_else(|| caps[1].to_string());
+            format!("stroke-width=\"{value}\"{dash}")
+        })
+        .into_owned()
+}
+
+/// Scales the root `width`/`height` of a rendered SVG, keeping its `viewBox`.
+pub fn zoom_svg(svg: &str, zoom: f32) -> Result<String> {
+    if !(zoom > 0.0 && zoom.is_finite()) {
+        bail!("zoom must be greater than zero");
+    }
+    let Some(caps) = svg_size_regex().captures(svg) else {
+        bail!("generated SVG is missing its root dimensions");
+    };
+    let (Ok(width), Ok(height)) = (caps[1].parse::<f32>(), caps[2].parse::<f32>()) else {
+        bail!("generated SVG has non-numeric root dimensions");
+    };
+    let size = caps.get(0).expect("whole match").range();
+    let view_box = &svg[size.clone()][caps[0].find("viewBox").expect("viewBox in match")..];
+    let mut zoomed = svg.to_string();
+    zoomed.replace_range(
+        size,
+        &format!(
+            "width=\"{:.0}\" height=\"{:.0}\" {}",
+            (width * zoom).ceil(),
+            (height * zoom).ceil(),
+            view_box
+        ),
+    );
+    Ok(zoomed)
+}
+
+/// Attributes holding coordinates or lengths, which [`minify_svg`] rounds.
+const GEOMETRY_ATTRIBUTES: [&str; 20] = [
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "width",
+    "height",
+    "points",
+    "d",
+    "transform",
+    "viewBox",
+    "stroke-width",
+    "stroke-dasharray",
+    "font-size",
+];
+
+/// Presentation attributes [`minify_svg`] moves into shared classes.
+const STYLE_ATTRIBUTES: [&str; 14] = [
+    "fill",
+    "stroke",
+    "stroke-width",
+    "stroke-dasharray",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-opacity",
+    "fill-opacity",
+    "opacity",
+    "font-size",
+    "font-weight",
+    "font-style",
+    "text-anchor",
+    "dominant-baseline",
+];
+
+/// Shrinks a rendered SVG for pages that embed many diagrams. It drops the XML prolog and the
+/// whitespace between tags, rounds coordinates to `decimals` places, and moves presentation
+/// attributes that several elements share into classes. Text, `<style>` and `<metadata>`
+/// content is kept as is, so [`extract_source_metadata`] still works on the result.
+pub fn minify_svg(svg: &str, decimals: usize) -> String {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| {
+        Regex::new(r"(?s)<style\b.*?</style>|<metadata\b.*?</metadata>|<\?.*?\?>|<[^>]*>|[^<]+")
+            .unwrap()
+    });
+    let attribute = ATTRIBUTE.get_or_init(|| Regex::new(r#"([\w:-]+)="([^"]*)""#).unwrap());
+    let number = NUMBER.get_or_init(|| Regex::new(r"-?\d+(?:\.\d+)?").unwrap());
+
+    let round = |value: &str| {
+        number
+            .replace_all(value, |caps: &regex::Captures| {
+                let Ok(parsed) = caps[0].parse::<f64>() else {
+                    return caps[0].to_string();
+                };
+                let mut formatted = format!("{parsed:.decimals$}");
+                if formatted.contains('.') {
+                    formatted = formatted
+                        .trim_end_matches('0')
+                        .trim_end_matches('.')
+                        .to_string();
+                }
+                if formatted == "-0" {
+                    formatted = "0".to_string();
+                }
+                formatted
+            })
+            .into_owned()
+    };
+
+    enum Token<'a> {
+        Raw(&'a str),
+        Element {
+            name: &'a str,
+            attributes: Vec<(&'a str, String)>,
+            self_closing: bool,
+        },
+    }
+
+    let mut tokens = Vec::new();
+    for found in token.find_iter(svg) {
+        let text = found.as_str();
+        let is_tag = text.starts_with('<');
+        if !is_tag && text.trim().is_empty() || text.starts_with("<?") {
+            continue;
+        }
+        let is_element = is_tag
+            && !text.starts_with("</")
+            && !text.starts_with("<!")
+            && !text.starts_with("<style")
+            && !text.starts_with("<metadata");
+        if !is_element {
+            tokens.push(Token::Raw(text));
+            continue;
+        }
+        let inner = text[1..text.len() - 1].trim_end();
+        let self_closing = inner.ends_with('/');
+        let name = inner
+            .split(|ch: char| ch.is_whitespace() || ch == '/')
+            .next()
+            .unwrap_or_default();
+        let attributes = attribute
+            .captures_iter(inner)
+            .map(|caps| {
+                let key = caps.get(1).expect("attribute name").as_str();
+                let value = if GEOMETRY_ATTRIBUTES.contains(&key) {
+                    round(&caps[2])
+                } else {
+                    caps[2].to_string()
+                };
+                (key, value)
+            })
+            .collect();
+        tokens.push(Token::Element {
+            name,
+            attributes,
+            self_closing,
+        });
+    }
+
+    // Style attributes that can move to a class; markers keep their `context-stroke` fills.
+    let movable = |name: &str, key: &str, value: &str| {
+        name != "svg" && STYLE_ATTRIBUTES.contains(&key) && !value.contains("context-")
+    };
+    let mut uses: HashMap<(&str, &str), usize> = HashMap::new();
+    for token in &tokens {
+        if let Token::Element {
+            name, attributes, ..
+        } = token
+        {
+            for (key, value) in attributes {
+                if movable(name, key, value) {
+                    *uses.entry((*key, value.as_str())).or_default() += 1;
+                }
+            }
+        }
+    }
+    // A class only pays off when the attribute it replaces is longer than ` o12` plus its
+    // share of the rule.
+    let shared = |key: &str, value: &str| {
+        uses.get(&(key, value))
+            .is_some_and(|count| *count > 1 && key.len() + value.len() > 6)
+    };
+    let mut classes: Vec<String> = Vec::new();
+    let mut class_of: HashMap<(&str, &str), String> = HashMap::new();
+
+    let mut body = String::with_capacity(svg.len());
+    for token in &tokens {
+        let (name, attributes, self_closing) = match token {
+            Token::Raw(text) => {
+                body.push_str(text);
+                continue;
+            }
+            Token::Element {
+                name,
+                attributes,
+                self_closing,
+            } => (*name, attributes, *self_closing),
+        };
+        let mut element_classes: Vec<String> = Vec::new();
+        for (key, value) in attributes {
+            if movable(name, key, value) && shared(key, value) {
+                let class = class_of.entry((*key, value.as_str())).or_insert_with(|| {
+                    let unit = if *key == "font-size" && value.parse::<f64>().is_ok() {
+                        "px"
+                    } else {
+                        ""
+                    };
+                    classes.push(format!("{key}:{value}{unit}"));
+                    format!("o{}", classes.len() - 1)
+                });
+                element_classes.push(class.clone());
+            }
+        }
+        body.push('<');
+        body.push_str(name);
+        for (key, value) in attributes {
+            if element_classes.is_empty() {
+                let _ = write!(body, " {key}=\"{value}\"");
+            } else if *key == "class" {
+                element_classes.insert(0, value.clone());
+            } else if !(movable(name, key, value) && shared(key, value)) {
+                let _ = write!(body, " {key}=\"{value}\"");
+            }
+        }
+        if !element_classes.is_empty() {
+            let _ = write!(body, " class=\"{}\"", element_classes.join(" "));
+        }
+        body.push_str(if self_closing { "/>" } else { ">" });
+    }
+
+    if classes.is_empty() {
+        return body;
+    }
+    let rules: String = classes
+        .iter()
+        .enumerate()
+        .map(|(index, style)| format!(".o{index}{{{style}}}"))
+        .collect();
+    let root_end = body
+        .find("<svg")
+        .and_then(|root| body[root..].find('>').map(|end| root + end + 1))
+        .unwrap_or(0);
+    body.insert_str(root_end, &format!("<style>{rules}</style>"));
+    body
+}
+
+/// Puts `family` first in the root `font-family` of `svg`.
+/// Spacing of the background grid.
+const GRID_SIZE: f32 = 20.0;
+
+/// Lays a faint `grid` pattern in `color` over the page background rect, under the diagram.
+fn draw_grid(svg: String, grid: GridStyle, color: &str) -> String {
+    const BACKGROUND: &str = "<rect width=\"100%\" height=\"100%\"";
+    let Some(start) = svg.find(BACKGROUND) else {
+        return svg;
+    };
+    let Some(end) = svg[start..].find("/>").map(|offset| start + offset + 2) else {
+        return svg;
+    };
+    let color = escape_xml(color);
+    let mark = match grid {
+        GridStyle::Dots => {
+            format!("<circle cx=\"1\" cy=\"1\" r=\"1\" fill=\"{color}\" fill-opacity=\"0.25\" />")
+        }
+        GridStyle::Lines => format!(
+            "<path d=\"M {GRID_SIZE} 0 L 0 0 0 {GRID_SIZE}\" fill=\"none\" stroke=\"{color}\" stroke-opacity=\"0.12\" stroke-width=\"1\" />"
+        ),
+    };
+    let pattern = format!(
+        "\n  <defs>\n    <pattern id=\"oxdraw-grid\" width=\"{GRID_SIZE}\" height=\"{GRID_SIZE}\" patternUnits=\"userSpaceOnUse\">{mark}</pattern>\n  </defs>\n  <rect class=\"grid\" width=\"100%\" height=\"100%\" fill=\"url(#oxdraw-grid)\" />"
+    );
+    let mut gridded = svg;
+    gridded.insert_str(end, &pattern);
+    gridded
+}
+
+/// Gap between a watermark and the canvas edges.
+const WATERMARK_INSET: f32 = 12.0;
+const WATERMARK_FONT_SIZE: f32 = 12.0;
+/// Height a watermark image is scaled to.
+const WATERMARK_IMAGE_HEIGHT: f32 = 24.0;
+
+impl Watermark {
+    /// Reads the image, failing when it is missing or not a PNG.
+    pub fn load(&self) -> Result<WatermarkStamp> {
+        let text = self
+            .text
+            .as_deref()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string);
+        let image = match &self.image {
+            Some(path) => {
+                let data = std::fs::read(path)
+                    .with_context(|| format!("failed to read '{}'", path.display()))?;
+                let (width, height) = decode_image_dimensions("image/png", &data)
+                    .with_context(|| format!("watermark image '{}'", path.display()))?;
+                Some(NodeImage {
+                    mime_type: "image/png".to_string(),
+                    data,
+                    width,
+                    height,
+                    padding: 0.0,
+                })
+            }
+            None => None,
+        };
+        if text.is_none() && image.is_none() {
+            bail!("a watermark needs text or an image");
+        }
+        Ok(WatermarkStamp {
+            text,
+            image,
+            corner: self.corner,
+        })
+    }
+}
+
+impl WatermarkStamp {
+    /// `svg` with the watermark drawn in its corner, on top of everything else.
+    pub fn apply(&self, svg: String, color: &str) -> Result<String> {
+        static VIEW_BOX: OnceLock<Regex> = OnceLock::new();
+        let view_box = VIEW_BOX.get_or_init(|| {
+            Regex::new(r#"<svg\b[^>]*\bviewBox="([-\d.]+) ([-\d.]+) ([\d.]+) ([\d.]+)""#)
+                .expect("valid viewBox pattern")
+        });
+        let Some(caps) = view_box.captures(&svg) else {
+            bail!("generated SVG is missing its viewBox");
+        };
+        let [min_x, min_y, width, height] =
+            [1, 2, 3, 4].map(|idx| caps[idx].parse::<f32>().unwrap_or(0.0));
+        let Some(end) = svg.rfind("</svg>") else {
+            bail!("generated SVG is missing its closing tag");
+        };
+
+        let image_size = self.image.as_ref().map(|image| {
+            let aspect = image.width.max(1) as f32 / image.height.max(1) as f32;
+            (WATERMARK_IMAGE_HEIGHT * aspect, WATERMARK_IMAGE_HEIGHT)
+        });
+        let gap = if image_size.is_some() && self.text.is_some() {
+            6.0
+        } else {
+            0.0
+        };
+        let text_size = self.text.as_deref().map_or(0.0, |text| {
+            text_width(
+                text,
+                WATERMARK_FONT_SIZE,
+                NODE_TEXT_CHAR_WIDTH * WATERMARK_FONT_SIZE / NODE_TEXT_FONT_SIZE,
+            )
+        });
+        let total_width = image_size.map_or(0.0, |(width, _)| width) + gap + text_size;
+        let row_height = image_size.map_or(WATERMARK_FONT_SIZE, |(_, height)| {
+            height.max(WATERMARK_FONT_SIZE)
+        });
+        let left = match self.corner {
+            WatermarkCorner::TopLeft | WatermarkCorner::BottomLeft => min_x + WATERMARK_INSET,
+            WatermarkCorner::TopRight | WatermarkCorner::BottomRight => {
+                min_x + width - WATERMARK_INSET - total_width
+            }
+        };
+        let middle = match self.corner {
+            WatermarkCorner::TopLeft | WatermarkCorner::TopRight => {
+                min_y + WATERMARK_INSET + row_height / 2.0
+            }
+            WatermarkCorner::BottomLeft | WatermarkCorner::BottomRight => {
+                min_y + height - WATERMARK_INSET - row_height / 2.0
+            }
+        };
+
+        let mut stamp = String::from("<g class=\"watermark\">\n");
+        if let (Some(image), Some((image_width, image_height))) = (&self.image, image_size) {
+            let data_uri = format!(
+                "data:{};base64,{}",
+                image.mime_type,
+                BASE64_STANDARD.encode(&image.data)
+            );
+            writeln!(
+                stamp,
+                "  <image x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" href=\"{}\" />",
+                left,
+                middle - image_height / 2.0,
+                image_width,
+                image_height,
+                data_uri
+            )?;
+        }
+        if let Some(text) = &self.text {
+            let x = left + image_size.map_or(0.0, |(width, _)| width) + gap;
+            writeln!(
+                stamp,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"{}\" dominant-baseline=\"middle\">{}</text>",
+                x,
+                middle,
+                escape_xml(color),
+                WATERMARK_FONT_SIZE,
+                escape_xml(text)
+            )?;
+        }
+        stamp.push_str("</g>\n");
+
+        let mut stamped = svg;
+        stamped.insert_str(end, &stamp);
+        Ok(stamped)
+    }
+}
+
+fn prepend_root_font_family(svg: String, family: &str) -> String {
+    let Some(root) = svg.find("<svg") else {
+        return svg;
+    };
+    let root_end = root + svg[root..].find('>').unwrap_or(0) + 1;
+    let mut styled = svg[..root_end].replacen(
+        " font-family=\"",
+        &format!(" font-family=\"{}, ", escape_xml(family)),
+        1,
+    );
+    styled.push_str(&svg[root_end..]);
+    styled
+}
+
+/// Adds an `@font-face` for the characters `svg` draws and puts that family first in the root
+/// `font-family`.
+fn embed_font(svg: String) -> Result<String> {
+    static TEXT: OnceLock<Regex> = OnceLock::new();
+    let text = TEXT.get_or_init(|| {
+        Regex::new(r"<(?:text|tspan)\b[^>]*>([^<]*)").expect("valid text content pattern")
+    });
+    let chars: BTreeSet<char> = text
+        .captures_iter(&svg)
+        .flat_map(|captures| decode_entities(&captures[1]).chars().collect::<Vec<_>>())
+        .collect();
+    let Some(font_face) = embedded_font_face(chars) else {
+        bail!("no system font is available to embed in the SVG");
+    };
+
+    let Some(root) = svg.find("<svg") else {
+        return Ok(svg);
+    };
+    let root_end = root + svg[root..].find('>').unwrap_or(0) + 1;
+    let mut embedded = svg[..root_end].replacen(
+        " font-family=\"",
+        &format!(" font-family=\"{EMBEDDED_FONT_FAMILY}, "),
+        1,
+    );
+    write!(embedded, "\n  <style>{font_face}</style>")?;
+    embedded.push_str(&svg[root_end..]);
+    Ok(embedded)
+}
+
+/// Markers for `--o` and `--x` edge ends, placed so they sit just outside the node.
+const EDGE_HEAD_MARKER_DEFS: &str = r##"        <marker id="circle-end" markerWidth="8" markerHeight="8" refX="6.5" refY="4" orient="auto" markerUnits="strokeWidth">
+            <circle cx="4" cy="4" r="2.5" fill="context-stroke" />
+        </marker>
+        <marker id="circle-start" markerWidth="8" markerHeight="8" refX="1.5" refY="4" orient="auto" markerUnits="strokeWidth">
+            <circle cx="4" cy="4" r="2.5" fill="context-stroke" />
+        </marker>
+        <marker id="cross-end" markerWidth="8" markerHeight="8" refX="6" refY="4" orient="auto" markerUnits="strokeWidth">
+            <path d="M2,1.5 L5.5,6.5 M5.5,1.5 L2,6.5" fill="none" stroke="context-stroke" stroke-width="1" />
+        </marker>
+        <marker id="cross-start" markerWidth="8" markerHeight="8" refX="2" refY="4" orient="auto" markerUnits="strokeWidth">
+            <path d="M2.5,1.5 L6,6.5 M6,1.5 L2.5,6.5" fill="none" stroke="context-stroke" stroke-width="1" />
+        </marker>
+"##;
+
+/// Stylesheet for `edge-animated` groups, which raster exports strip again.
+const EDGE_FLOW_STYLE: &str = "  <style id=\"oxdraw-edge-flow\">@keyframes oxdraw-edge-flow { to { stroke-dashoffset: -14; } } .edge-animated > line, .edge-animated > polyline { stroke-dasharray: 8 6; animation: oxdraw-edge-flow 0.6s linear infinite; } @media (prefers-reduced-motion: reduce) { .edge-animated > line, .edge-animated > polyline { animation: none; } }</style>\n";
+
+/// Image encoding for [`Diagram::render_raster`] and [`rasterize_svg_as`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    Png,
+    /// Lossy, with `quality` from 1 to 100. JPEG has no alpha channel, so transparent pixels
+    /// are flattened onto white.
+    Jpeg {
+        quality: u8,
+    },
+    /// Lossless WebP, keeping the alpha channel.
+    Webp,
+}
+
+/// Output size for [`Diagram::render_raster_sized`] and [`rasterize_svg_sized`]. The first of
+/// `width`, `height`, `dpi` and `scale` that is set decides the size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterSize {
+    /// Multiplies the SVG's own size.
+    pub scale: f32,
+    /// Exact image width in pixels; the height keeps the aspect ratio.
+    pub width: Option<u32>,
+    /// Exact image height in pixels; the width keeps the aspect ratio.
+    pub height: Option<u32>,
+    /// Pixels per inch, against the 96 per inch of SVG units, and recorded in PNG and JPEG
+    /// output either way.
+    pub dpi: Option<f32>,
+}
+
+impl Default for RasterSize {
+    fn default() -> Self {
+        RasterSize::scaled(1.0)
+    }
+}
+
+impl RasterSize {
+    pub fn scaled(scale: f32) -> Self {
+        RasterSize {
+            scale,
+            width: None,
+            height: None,
+            dpi: None,
+        }
+    }
+
+    /// Scale factor and pixel size for an SVG of `width` x `height`.
+    fn resolve(&self, width: f32, height: f32) -> Result<(f32, f32, f32)> {
+        if self.width == Some(0) || self.height == Some(0) {
+            bail!("raster width and height must be at least 1px");
+        }
+        if let Some(dpi) = self.dpi
+            && !(dpi > 0.0 && dpi.is_finite())
+        {
+            bail!("DPI must be greater than zero");
+        }
+        if let Some(target) = self.width {
+            let scale = target as f32 / width;
+            return Ok((scale, target as f32, (height * scale).round().max(1.0)));
+        }
+        if let Some(target) = self.height {
+            let scale = target as f32 / height;
+            return Ok((scale, (width * scale).round().max(1.0), target as f32));
+        }
+        let scale = self.dpi.map_or(self.scale, |dpi| dpi / 96.0);
+        Ok((scale, (width * scale).ceil(), (height * scale).ceil()))
+    }
+}
+
+impl RasterFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            RasterFormat::Png => "PNG",
+            RasterFormat::Jpeg { .. } => "JPEG",
+            RasterFormat::Webp => "WebP",
+        }
+    }
+}
+
+/// Rasterizes an SVG produced by [`Diagram::render_svg`] into PNG bytes.
+pub fn rasterize_svg(svg: &str, scale: f32) -> Result<Vec<u8>> {
+    rasterize_svg_as(svg, scale, RasterFormat::Png)
+}
+
+/// Rasterizes an SVG produced by [`Diagram::render_svg`] and encodes it as `format`.
+pub fn rasterize_svg_as(svg: &str, scale: f32, format: RasterFormat) -> Result<Vec<u8>> {
+    rasterize_svg_with_fonts(svg, scale, format, &[])
+}
+
+/// Like [`rasterize_svg_as`], also drawing with the fonts in `font_files`.
+pub fn rasterize_svg_with_fonts(
+    svg: &str,
+    scale: f32,
+    format: RasterFormat,
+    font_files: &[PathBuf],
+) -> Result<Vec<u8>> {
+    rasterize_svg_sized(svg, &RasterSize::scaled(scale), format, font_files)
+}
+
+/// Like [`rasterize_svg_with_fonts`], sizing the image by `size`.
+pub fn rasterize_svg_sized(
+    svg: &str,
+    size: &RasterSize,
+    format: RasterFormat,
+    font_files: &[PathBuf],
+) -> Result<Vec<u8>> {
+    // A still image would only keep the dashes, so animated edges go back to their usual stroke.
+    let svg = &svg.replacen(EDGE_FLOW_STYLE, "", 1);
+    let mut options = resvg::usvg::Options::default();
+    options.font_family = "Inter".to_string();
+    options.fontdb = font_database_with(font_files)?;
+
+    let tree = resvg::usvg::Tree::from_str(svg, &options)
+        .map_err(|err| anyhow!("failed to parse generated SVG for PNG export: {err}"))?;
+
+    let tree_size = tree.size().to_int_size();
+    let (scale, scaled_width, scaled_height) =
+        size.resolve(tree_size.width() as f32, tree_size.height() as f32)?;
+
+    if !scaled_width.is_finite() || !scaled_height.is_finite() {
+        bail!("scaled dimensions are not finite; try a smaller scale factor");
+    }
+
+    if scaled_width < 1.0 || scaled_height < 1.0 {
+        bail!("scaled dimensions collapsed below 1px; try a larger scale factor");
+    }
+
+    if scaled_width > u32::MAX as f32 || scaled_height > u32::MAX as f32 {
+        bail!("scaled dimensions exceed supported limits; try a smaller scale factor");
+    }
+
+    let scaled_width = scaled_width as u32;
+    let scaled_height = scaled_height as u32;
+
+    let mut pixmap = Pixmap::new(scaled_width, scaled_height).ok_or_else(|| {
+        anyhow!("failed to allocate {scaled_width}x{scaled_height} surface for PNG export")
+    })?;
+
+    let transform = Transform::from_scale(scale, scale);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    match format {
+        RasterFormat::Png => {
+            let png = pixmap
+                .encode_png()
+                .map_err(|err| anyhow!("failed to encode PNG output: {err}"))?;
+            Ok(match size.dpi {
+                Some(dpi) => set_png_dpi(png, dpi),
+                None => png,
+            })
+        }
+        RasterFormat::Jpeg { quality } => encode_jpeg(&pixmap, quality, size.dpi),
+        RasterFormat::Webp => encode_webp(&pixmap),
+    }
+}
+
+/// Adds a `pHYs` chunk recording `dpi` right after the `IHDR` chunk of `png`, so image
+/// editors and slide tools place it at the intended physical size.
+fn set_png_dpi(mut png: Vec<u8>, dpi: f32) -> Vec<u8> {
+    // 8-byte signature, then IHDR: length, type, 13 bytes of data and the CRC.
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    if png.len() < IHDR_END {
+        return png;
+    }
+    let per_metre = (dpi / 0.0254).round() as u32;
+    let mut chunk = Vec::with_capacity(21);
+    chunk.extend_from_slice(&9u32.to_be_bytes());
+    chunk.extend_from_slice(b"pHYs");
+    chunk.extend_from_slice(&per_metre.to_be_bytes());
+    chunk.extend_from_slice(&per_metre.to_be_bytes());
+    chunk.push(1);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    png.splice(IHDR_END..IHDR_END, chunk);
+    png
+}
+
+fn encode_jpeg(pixmap: &Pixmap, quality: u8, dpi: Option<f32>) -> Result<Vec<u8>> {
+    if !(1..=100).contains(&quality) {
+        bail!("JPEG quality must be between 1 and 100");
+    }
+    let (Ok(width), Ok(height)) = (
+        u16::try_from(pixmap.width()),
+        u16::try_from(pixmap.height()),
+    ) else {
+        bail!("JPEG output is limited to 65535px per side; try a smaller scale factor");
+    };
+    // Pixels are premultiplied, so compositing onto white only adds the uncovered share.
+    let rgb: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let uncovered = 255 - pixel.alpha();
+            [pixel.red(), pixel.green(), pixel.blue()].map(|channel| channel + uncovered)
+        })
+        .collect();
+    let mut jpeg = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut jpeg, quality);
+    if let Some(dpi) = dpi {
+        let density = dpi.round().clamp(1.0, f32::from(u16::MAX)) as u16;
+        encoder.set_density(jpeg_encoder::PixelDensity::dpi(density));
+    }
+    encoder
+        .encode(&rgb, width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|err| anyhow!("failed to encode JPEG output: {err}"))?;
+    Ok(jpeg)
+}
+
+fn encode_webp(pixmap: &Pixmap) -> Result<Vec<u8>> {
+    let rgba: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let mut webp = Vec::new();
+    image_webp::WebPEncoder::new(&mut webp)
+        .encode(
+            &rgba,
+            pixmap.width(),
+            pixmap.height(),
+            image_webp::ColorType::Rgba8,
+        )
+        .map_err(|err| anyhow!("failed to encode WebP output: {err}"))?;
+    Ok(webp)
+}
+
+fn route_intersects_label_rects(route: &[Point], label_bounds: &HashMap<String, Rect>) -> bool {
+    label_bounds
+        .values()
+        .any(|rect| route_intersects_rect(route, *rect))
+}
+
+fn route_intersects_rect(route: &[Point], rect: Rect) -> bool {
+    route
+        .windows(2)
+        .any(|segment| rect.intersects_segment(segment[0], segment[1]))
+}
+
+fn label_overlaps_existing(
     current_id: &str,
     rect: Rect,
     label_bounds: &HashMap<String, Rect>,
@@ -3289,32 +8333,18 @@ impl NodeShape {
             NodeShape::Trapezoid => "trapezoid",
             NodeShape::TrapezoidAlt => "trapezoid-alt",
             NodeShape::Asymmetric => "asymmetric",
+            NodeShape::Junction => "junction",
         }
     }
 
-    fn default_fill_color(&self) -> &'static str {
-        match self {
-            NodeShape::Rectangle => "#fde68a",
-            NodeShape::Stadium => "#c4f1f9",
-            NodeShape::Circle => "#e9d8fd",
-            NodeShape::DoubleCircle => "#bfdbfe",
-            NodeShape::Diamond => "#fbcfe8",
-            NodeShape::Subroutine => "#fed7aa",
-            NodeShape::Cylinder => "#bbf7d0",
-            NodeShape::Hexagon => "#fca5a5",
-            NodeShape::Parallelogram => "#c7d2fe",
-            NodeShape::ParallelogramAlt => "#a5f3fc",
-            NodeShape::Trapezoid => "#fce7f3",
-            NodeShape::TrapezoidAlt => "#fcd5ce",
-            NodeShape::Asymmetric => "#f5d0fe",
-        }
-    }
-
-    fn format_spec(&self, id: &str, label: &str) -> String {
+    pub(crate) fn format_spec(&self, raw_id: &str, label: &str) -> String {
+        let id = format_node_id(raw_id);
+        let same_as_id = label == raw_id;
+        let label = quote_label_if_needed(label);
         match self {
             NodeShape::Rectangle => {
-                if label == id {
-                    id.to_string()
+                if same_as_id {
+                    id.into_owned()
                 } else {
                     format!("{id}[{label}]")
                 }
@@ -3331,6 +8361,7 @@ impl NodeShape {
             NodeShape::Trapezoid => format!("{id}[/{label}\\]"),
             NodeShape::TrapezoidAlt => format!("{id}[\\{label}/]"),
             NodeShape::Asymmetric => format!("{id}>{label}]"),
+            NodeShape::Junction => format!("{id}@{{ shape: f-circ }}"),
         }
     }
 
@@ -3371,6 +8402,15 @@ impl NodeShape {
                 "  <ellipse cx=\"{:.1}\" cy=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />\n",
                 position.x, position.y, half_w, half_h, fill_color, stroke_color
             ),
+            NodeShape::Junction => writeln!(
+                svg,
+                "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\" />",
+                position.x,
+                position.y,
+                half_w.min(half_h),
+                fill_color,
+                stroke_color
+            ),
             NodeShape::DoubleCircle => {
                 write!(
                     svg,
@@ -3581,7 +8621,7 @@ impl NodeShape {
                 width,
                 height
             ),
-            NodeShape::Circle | NodeShape::DoubleCircle => write!(
+            NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Junction => write!(
                 svg,
                 "      <ellipse cx=\"{:.1}\" cy=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" />\n",
                 position.x, position.y, half_w, half_h
@@ -3742,7 +8782,7 @@ impl NodeShape {
                 height,
                 stroke_color
             ),
-            NodeShape::Circle | NodeShape::DoubleCircle => {
+            NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Junction => {
                 write!(
                     svg,
                     "  <ellipse cx=\"{:.1}\" cy=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\" />\n",
@@ -3912,15 +8952,44 @@ impl EdgeKind {
     pub fn connector(&self, arrow: EdgeArrowDirection) -> &'static str {
         match (self, arrow) {
             (EdgeKind::Invisible, _) => "~~~",
+            (EdgeKind::Thick, EdgeArrowDirection::Both) => "<==>",
             (EdgeKind::Thick, EdgeArrowDirection::None) => "===",
             (EdgeKind::Thick, _) => "==>",
+            (EdgeKind::Dashed, EdgeArrowDirection::Both) => "<-.->",
             (EdgeKind::Dashed, EdgeArrowDirection::None) => "-.->",
             (EdgeKind::Dashed, _) => "-.->",
+            (EdgeKind::Solid, EdgeArrowDirection::Both) => "<-->",
             (EdgeKind::Solid, EdgeArrowDirection::None) => "---",
             (EdgeKind::Solid, _) => "-->",
         }
     }
 
+    /// Like [`EdgeKind::connector`], spelling circle and cross ends as `--o` or `x--x`.
+    pub fn connector_with_head(
+        &self,
+        arrow: EdgeArrowDirection,
+        head: EdgeHead,
+    ) -> Cow<'static, str> {
+        let end = match head {
+            EdgeHead::Arrow => return Cow::Borrowed(self.connector(arrow)),
+            EdgeHead::Circle => 'o',
+            EdgeHead::Cross => 'x',
+        };
+        let line = match self {
+            EdgeKind::Solid => "--",
+            EdgeKind::Dashed => "-.-",
+            EdgeKind::Thick => "==",
+            EdgeKind::Invisible => return Cow::Borrowed(self.connector(arrow)),
+        };
+        match arrow {
+            EdgeArrowDirection::Both => Cow::Owned(format!("{end}{line}{end}")),
+            EdgeArrowDirection::None => Cow::Borrowed(self.connector(arrow)),
+            EdgeArrowDirection::Forward | EdgeArrowDirection::Backward => {
+                Cow::Owned(format!("{line}{end}"))
+            }
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             EdgeKind::Solid => "solid",
@@ -3931,100 +9000,423 @@ impl EdgeKind {
     }
 }
 
-fn normalize_label_lines(label: &str) -> Vec<String> {
-    let mut normalized = label.to_string();
-    for (pattern, replacement) in [
-        ("<br/>", "\n"),
-        ("<br />", "\n"),
-        ("<br>", "\n"),
-        ("<BR/>", "\n"),
-        ("<BR />", "\n"),
-        ("<BR>", "\n"),
-    ] {
-        normalized = normalized.replace(pattern, replacement);
-    }
-
-    normalized
-        .split('\n')
+pub(crate) fn normalize_label_lines(label: &str) -> Vec<String> {
+    label_lines(label)
+        .into_iter()
+        .map(|line| {
+            if is_markdown_label(label) {
+                markdown_runs(&line)
+                    .into_iter()
+                    .map(|run| run.text)
+                    .collect()
+            } else {
+                line
+            }
+        })
         .map(|line| {
             if line.is_empty() {
                 " ".to_string()
             } else {
-                line.to_string()
+                line
             }
         })
         .collect()
 }
 
-fn measure_label_box(lines: &[String]) -> (f32, f32) {
-    let mut max_chars = 0_usize;
-    for line in lines {
-        max_chars = max_chars.max(line.chars().count());
-    }
-
-    let width = (EDGE_LABEL_CHAR_WIDTH * max_chars as f32 + EDGE_LABEL_HORIZONTAL_PADDING)
-        .max(EDGE_LABEL_MIN_WIDTH);
-    let height = (EDGE_LABEL_LINE_HEIGHT * lines.len() as f32 + EDGE_LABEL_VERTICAL_PADDING)
-        .max(EDGE_LABEL_MIN_HEIGHT);
-
-    (width, height)
+pub(crate) fn label_lines(label: &str) -> Vec<String> {
+    static LINE_BREAK: OnceLock<Regex> = OnceLock::new();
+    let line_break = LINE_BREAK
+        .get_or_init(|| Regex::new(r"(?i)<br\s*/?\s*>|\\n").expect("valid line break regex"));
+    let label = if is_markdown_label(label) {
+        &label[1..label.len() - 1]
+    } else {
+        label
+    };
+    line_break
+        .replace_all(label, "\n")
+        .split('\n')
+        .map(str::to_string)
+        .collect()
 }
 
-fn raw_node_text_width(lines: &[String]) -> f32 {
-    let max_chars = lines
-        .iter()
-        .map(|line| line.chars().count().max(1))
-        .max()
-        .unwrap_or(1);
-    NODE_TEXT_CHAR_WIDTH * max_chars as f32 + NODE_TEXT_HORIZONTAL_PADDING
-}
+/// Greedy word wrap of each line of `label` to `max_width` pixels of node text. Words wider
+/// than a whole line are split between characters.
+fn wrap_label(label: &str, max_width: f32) -> Cow<'_, str> {
+    let max_width = max_width.max(NODE_TEXT_CHAR_WIDTH);
+    let fits =
+        |text: &str| text_width(text, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH) <= max_width;
+    let lines = label_lines(label);
+    if is_markdown_label(label) || lines.iter().all(|line| fits(line)) {
+        return Cow::Borrowed(label);
+    }
 
-fn raw_node_text_height(lines: &[String]) -> f32 {
-    NODE_TEXT_LINE_HEIGHT * lines.len().max(1) as f32 + NODE_TEXT_VERTICAL_PADDING
+    let mut wrapped: Vec<String> = Vec::new();
+    for line in lines {
+        if fits(&line) {
+            wrapped.push(line);
+            continue;
+        }
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{current} {word}")
+            };
+            if fits(&candidate) {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                wrapped.push(std::mem::take(&mut current));
+            }
+            for ch in word.chars() {
+                current.push(ch);
+                if !fits(&current) && current.chars().count() > 1 {
+                    current.pop();
+                    wrapped.push(std::mem::replace(&mut current, ch.to_string()));
+                }
+            }
+        }
+        wrapped.push(current);
+    }
+    Cow::Owned(wrapped.join("\n"))
 }
 
-fn compute_node_dimensions_from_lines(shape: NodeShape, lines: &[String]) -> (f32, f32) {
-    let mut width = raw_node_text_width(lines).max(NODE_WIDTH);
-    let mut height = raw_node_text_height(lines).max(NODE_HEIGHT);
-
-    if matches!(shape, NodeShape::Circle | NodeShape::DoubleCircle) {
-        let size = width.max(height);
-        width = size;
-        height = size;
+/// `label` with every line longer than `max_width` cut short and ended with `…`. Markdown
+/// labels are left alone.
+fn truncate_label(label: &str, max_width: f32) -> Cow<'_, str> {
+    let fits =
+        |text: &str| text_width(text, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH) <= max_width;
+    let lines = label_lines(label);
+    if is_markdown_label(label) || lines.iter().all(|line| fits(line)) {
+        return Cow::Borrowed(label);
     }
 
-    (width, height)
+    let truncated: Vec<String> = lines
+        .into_iter()
+        .map(|line| {
+            if fits(&line) {
+                return line;
+            }
+            let mut kept: Vec<char> = line.chars().collect();
+            while kept.len() > 1 {
+                kept.pop();
+                let candidate = format!("{}…", kept.iter().collect::<String>().trim_end());
+                if fits(&candidate) {
+                    return candidate;
+                }
+            }
+            format!("{}…", kept.iter().collect::<String>())
+        })
+        .collect();
+    Cow::Owned(truncated.join("\n"))
 }
 
-fn compute_node_dimensions(shape: NodeShape, label: &str) -> (f32, f32) {
-    let lines = normalize_label_lines(label);
-    compute_node_dimensions_from_lines(shape, &lines)
+fn is_markdown_label(label: &str) -> bool {
+    label.len() >= 2 && label.starts_with('`') && label.ends_with('`')
 }
 
-fn label_center_for_route(route: &[Point]) -> Point {
-    if route.is_empty() {
-        return Point {
-            x: 0.0,
-            y: -EDGE_LABEL_VERTICAL_OFFSET,
+struct MarkdownRun {
+    text: String,
+    bold: bool,
+    italic: bool,
+}
+
+/// Splits a markdown label line into bold and italic runs, pairing `*` and `_` delimiters
+/// with the CommonMark flanking rules: `_` inside a word, a delimiter between spaces and one
+/// without a partner stay literal text.
+fn markdown_runs(line: &str) -> Vec<MarkdownRun> {
+    /// A run of `*` or `_` in a markdown label, and how much of it emphasis used.
+    struct Delimiter {
+        ch: char,
+        /// Characters not yet used by a match.
+        left: usize,
+        can_open: bool,
+        can_close: bool,
+        /// Emphasis this run opens (positive) or closes (negative): bold counts 2, italic 1.
+        toggles: Vec<i8>,
+    }
+    enum Piece {
+        Text(String),
+        Delimiter(Delimiter),
+    }
+    let chars: Vec<char> = line.chars().collect();
+    let mut pieces = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let ch = chars[index];
+        if !matches!(ch, '*' | '_') {
+            match pieces.last_mut() {
+                Some(Piece::Text(text)) => text.push(ch),
+                _ => pieces.push(Piece::Text(ch.to_string())),
+            }
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < chars.len() && chars[index] == ch {
+            index += 1;
+        }
+        let before = start.checked_sub(1).map(|i| chars[i]);
+        let after = chars.get(index).copied();
+        let space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+        let punct = |c: Option<char>| c.is_some_and(|c| c.is_ascii_punctuation());
+        let left_flanking = !space(after) && (!punct(after) || space(before) || punct(before));
+        let right_flanking = !space(before) && (!punct(before) || space(after) || punct(after));
+        let (can_open, can_close) = if ch == '*' {
+            (left_flanking, right_flanking)
+        } else {
+            (
+                left_flanking && (!right_flanking || punct(before)),
+                right_flanking && (!left_flanking || punct(after)),
+            )
         };
+        pieces.push(Piece::Delimiter(Delimiter {
+            ch,
+            left: index - start,
+            can_open,
+            can_close,
+            toggles: Vec::new(),
+        }));
     }
 
-    let fallback = centroid(route);
-    if route.len() <= 2 {
+    // Pair each closer with the nearest open run of the same character before it.
+    for closer in 0..pieces.len() {
+        loop {
+            let (ch, closer_left) = match &pieces[closer] {
+                Piece::Delimiter(d) if d.can_close && d.left > 0 => (d.ch, d.left),
+                _ => break,
+            };
+            let Some(opener) = (0..closer).rev().find(|&i| {
+                matches!(&pieces[i], Piece::Delimiter(d) if d.ch == ch && d.can_open && d.left > 0)
+            }) else {
+                break;
+            };
+            let Piece::Delimiter(open) = &mut pieces[opener] else {
+                unreachable!()
+            };
+            let used = if open.left >= 2 && closer_left >= 2 {
+                2
+            } else {
+                1
+            };
+            open.left -= used;
+            open.toggles.push(used as i8);
+            // Runs between a matched pair can no longer pair with anything outside it.
+            for piece in &mut pieces[opener + 1..closer] {
+                if let Piece::Delimiter(d) = piece {
+                    d.can_open = false;
+                    d.can_close = false;
+                }
+            }
+            let Piece::Delimiter(close) = &mut pieces[closer] else {
+                unreachable!()
+            };
+            close.left -= used;
+            close.toggles.push(-(used as i8));
+        }
+    }
+
+    let mut runs: Vec<MarkdownRun> = Vec::new();
+    let (mut bold, mut italic) = (0usize, 0usize);
+    let mut push = |text: &str, bold: usize, italic: usize| {
+        let (bold, italic) = (bold > 0, italic > 0);
+        match runs.last_mut() {
+            Some(run) if run.bold == bold && run.italic == italic => run.text.push_str(text),
+            _ if text.is_empty() => {}
+            _ => runs.push(MarkdownRun {
+                text: text.to_string(),
+                bold,
+                italic,
+            }),
+        }
+    };
+    for piece in &pieces {
+        match piece {
+            Piece::Text(text) => push(text, bold, italic),
+            Piece::Delimiter(d) => {
+                let literal = d.ch.to_string().repeat(d.left);
+                let closes = d.toggles.first().is_some_and(|&toggle| toggle < 0);
+                if !closes {
+                    push(&literal, bold, italic);
+                }
+                for &toggle in &d.toggles {
+                    let count = if toggle.abs() == 2 {
+                        &mut bold
+                    } else {
+                        &mut italic
+                    };
+                    if toggle > 0 {
+                        *count += 1;
+                    } else {
+                        *count = count.saturating_sub(1);
+                    }
+                }
+                if closes {
+                    push(&literal, bold, italic);
+                }
+            }
+        }
+    }
+    runs
+}
+
+fn label_markup_lines(label: &str) -> Vec<String> {
+    let markdown = is_markdown_label(label);
+    label_lines(label)
+        .into_iter()
+        .map(|line| {
+            if !markdown {
+                return escape_xml(if line.is_empty() { " " } else { &line });
+            }
+            let mut markup = String::new();
+            for run in markdown_runs(&line) {
+                let text = escape_xml(&run.text);
+                let style = match (run.bold, run.italic) {
+                    (false, false) => "",
+                    (true, false) => " font-weight=\"bold\"",
+                    (false, true) => " font-style=\"italic\"",
+                    (true, true) => " font-weight=\"bold\" font-style=\"italic\"",
+                };
+                if style.is_empty() {
+                    markup.push_str(&text);
+                } else {
+                    markup.push_str(&format!("<tspan{style}>{text}</tspan>"));
+                }
+            }
+            if markup.is_empty() {
+                markup.push(' ');
+            }
+            markup
+        })
+        .collect()
+}
+
+fn measure_label_box(lines: &[String]) -> (f32, f32) {
+    let text_width = lines
+        .iter()
+        .map(|line| text_width(line, EDGE_LABEL_FONT_SIZE, EDGE_LABEL_CHAR_WIDTH))
+        .fold(0.0_f32, f32::max);
+
+    let width = (text_width + EDGE_LABEL_HORIZONTAL_PADDING).max(EDGE_LABEL_MIN_WIDTH);
+    let height = (EDGE_LABEL_LINE_HEIGHT * lines.len() as f32 + EDGE_LABEL_VERTICAL_PADDING)
+        .max(EDGE_LABEL_MIN_HEIGHT);
+
+    (width, height)
+}
+
+fn raw_node_text_width(lines: &[String]) -> f32 {
+    let text_width = lines
+        .iter()
+        .map(|line| text_width(line, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH))
+        .fold(NODE_TEXT_CHAR_WIDTH, f32::max);
+    text_width + NODE_TEXT_HORIZONTAL_PADDING
+}
+
+fn raw_node_text_height(lines: &[String]) -> f32 {
+    NODE_TEXT_LINE_HEIGHT * lines.len().max(1) as f32 + NODE_TEXT_VERTICAL_PADDING
+}
+
+fn compute_node_dimensions_from_lines(shape: NodeShape, lines: &[String]) -> (f32, f32) {
+    if shape == NodeShape::Junction {
+        return (JUNCTION_SIZE, JUNCTION_SIZE);
+    }
+
+    let mut width = raw_node_text_width(lines).max(NODE_MIN_WIDTH);
+    let mut height = raw_node_text_height(lines).max(NODE_HEIGHT);
+
+    match shape {
+        NodeShape::Circle | NodeShape::DoubleCircle => {
+            let size = width.max(height);
+            width = size;
+            height = size;
+        }
+        // The text box has to fit inside the rhombus: text_w / w + text_h / h <= 1.
+        NodeShape::Diamond => {
+            let text_width = width - NODE_TEXT_HORIZONTAL_PADDING;
+            let text_height = height - NODE_TEXT_VERTICAL_PADDING;
+            height = (text_height * 2.0).max(height);
+            width = (text_width / (1.0 - text_height / height)
+                + NODE_TEXT_HORIZONTAL_PADDING / 2.0)
+                .max(width);
+        }
+        // Slanted sides take a quarter of the width at the top and bottom edges.
+        NodeShape::Hexagon => width = width.max(raw_node_text_width(lines) / 0.8),
+        _ => {}
+    }
+
+    (width, height)
+}
+
+pub(crate) fn compute_node_dimensions(shape: NodeShape, label: &str) -> (f32, f32) {
+    let Some((_, text)) = split_label_icon(label) else {
+        return compute_node_dimensions_from_lines(shape, &normalize_label_lines(label));
+    };
+    let lines = normalize_label_lines(&text);
+    let (width, height) = compute_node_dimensions_from_lines(shape, &lines);
+    let with_icon = raw_node_text_width(&lines) + NODE_ICON_SIZE + NODE_ICON_GAP;
+    (width.max(with_icon), height)
+}
+
+/// Like [`label_center_for_route`], but keeps self-loop labels clear of the loop by placing
+/// them just beyond its far side.
+fn edge_label_center(edge: &Edge, route: &[Point]) -> Point {
+    let Some(label) = edge
+        .label
+        .as_ref()
+        .filter(|_| edge.from == edge.to && route.len() >= 4)
+    else {
+        return label_center_for_route(route);
+    };
+    let (first, last) = (route[0], route[route.len() - 1]);
+    let (near, far) = (route[1], route[route.len() - 2]);
+    let anchor = Point {
+        x: (first.x + last.x) / 2.0,
+        y: (first.y + last.y) / 2.0,
+    };
+    let outer = Point {
+        x: (near.x + far.x) / 2.0,
+        y: (near.y + far.y) / 2.0,
+    };
+    let (dx, dy) = (outer.x - anchor.x, outer.y - anchor.y);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length <= f32::EPSILON {
+        return label_center_for_route(route);
+    }
+    let (dx, dy) = (dx / length, dy / length);
+    let (width, height) = measure_label_box(&normalize_label_lines(label));
+    let clearance = dx.abs() * width / 2.0 + dy.abs() * height / 2.0 + EDGE_COLLISION_MARGIN;
+    Point {
+        x: outer.x + dx * clearance,
+        y: outer.y + dy * clearance,
+    }
+}
+
+fn label_center_for_route(route: &[Point]) -> Point {
+    if route.is_empty() {
         return Point {
-            x: fallback.x,
-            y: fallback.y - EDGE_LABEL_VERTICAL_OFFSET,
+            x: 0.0,
+            y: -EDGE_LABEL_VERTICAL_OFFSET,
         };
     }
 
-    let handle_points = &route[1..route.len() - 1];
-    if handle_points.is_empty() {
+    let fallback = centroid(route);
+    // Nudge straight labels towards the source end so upward (BT) edges mirror
+    // downward ones instead of drifting onto the arrowhead.
+    let offset = if route[route.len() - 1].y < route[0].y - f32::EPSILON {
+        EDGE_LABEL_VERTICAL_OFFSET
+    } else {
+        -EDGE_LABEL_VERTICAL_OFFSET
+    };
+    if route.len() <= 2 {
         return Point {
             x: fallback.x,
-            y: fallback.y - EDGE_LABEL_VERTICAL_OFFSET,
+            y: fallback.y + offset,
         };
     }
 
+    let handle_points = &route[1..route.len() - 1];
     if handle_points.len() == 1 {
         return handle_points[0];
     }
@@ -4044,6 +9436,94 @@ fn label_center_for_route(route: &[Point]) -> Point {
     best
 }
 
+/// Closest non-decreasing sequence to `values` in the least-squares sense, found by pooling
+/// adjacent values that are out of order into their mean.
+fn non_decreasing_fit(values: &[f32]) -> Vec<f32> {
+    let mut blocks: Vec<(f32, usize)> = Vec::with_capacity(values.len());
+    for &value in values {
+        blocks.push((value, 1));
+        while blocks.len() >= 2 && blocks[blocks.len() - 2].0 > blocks[blocks.len() - 1].0 {
+            let (mean, count) = blocks.pop().expect("two blocks");
+            let (prev_mean, prev_count) = blocks.pop().expect("two blocks");
+            let total = count + prev_count;
+            blocks.push((
+                (mean * count as f32 + prev_mean * prev_count as f32) / total as f32,
+                total,
+            ));
+        }
+    }
+    blocks
+        .into_iter()
+        .flat_map(|(mean, count)| std::iter::repeat_n(mean, count))
+        .collect()
+}
+
+/// Id of the layout placeholder for edge `edge_idx` in `layer`; the leading control
+/// character keeps it clear of any id a definition can declare.
+fn edge_dummy_id(edge_idx: usize, layer: usize) -> String {
+    format!("\u{1}{edge_idx}:{layer}")
+}
+
+/// Crossings among `links` between each pair of adjacent layers.
+fn count_link_crossings(layers: &[Vec<String>], links: &[(String, String)]) -> usize {
+    let slots: HashMap<&str, (usize, usize)> = layers
+        .iter()
+        .enumerate()
+        .flat_map(|(layer, ids)| {
+            ids.iter()
+                .enumerate()
+                .map(move |(rank, id)| (id.as_str(), (layer, rank)))
+        })
+        .collect();
+    let mut between: Vec<Vec<(usize, usize)>> = vec![Vec::new(); layers.len()];
+    for (upper, lower) in links {
+        let (Some(&upper), Some(&lower)) = (slots.get(upper.as_str()), slots.get(lower.as_str()))
+        else {
+            continue;
+        };
+        if lower.0 == upper.0 + 1 {
+            between[upper.0].push((upper.1, lower.1));
+        }
+    }
+    between
+        .iter()
+        .map(|pairs| {
+            let mut crossings = 0;
+            for (idx, a) in pairs.iter().enumerate() {
+                for b in &pairs[idx + 1..] {
+                    if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                        crossings += 1;
+                    }
+                }
+            }
+            crossings
+        })
+        .sum()
+}
+
+/// Swaps adjacent nodes while doing so removes crossings that barycenters alone leave
+/// behind, returning the final crossing count.
+fn transpose_neighbors(layers: &mut [Vec<String>], links: &[(String, String)]) -> usize {
+    let mut crossings = count_link_crossings(layers, links);
+    let mut improved = true;
+    while improved && crossings > 0 {
+        improved = false;
+        for layer in 0..layers.len() {
+            for slot in 1..layers[layer].len() {
+                layers[layer].swap(slot - 1, slot);
+                let swapped = count_link_crossings(layers, links);
+                if swapped < crossings {
+                    crossings = swapped;
+                    improved = true;
+                } else {
+                    layers[layer].swap(slot - 1, slot);
+                }
+            }
+        }
+    }
+    crossings
+}
+
 fn build_route(start: Point, middle: &[Point], end: Point) -> Vec<Point> {
     let mut route = Vec::with_capacity(middle.len() + 2);
     route.push(start);
@@ -4052,6 +9532,192 @@ fn build_route(start: Point, middle: &[Point], end: Point) -> Vec<Point> {
     route
 }
 
+/// Entry in [`grid_route`]'s open set, ordered so the cheapest estimate pops first.
+struct GridStep {
+    estimate: f32,
+    state: usize,
+}
+
+impl PartialEq for GridStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for GridStep {}
+
+impl PartialOrd for GridStep {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GridStep {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then_with(|| other.state.cmp(&self.state))
+    }
+}
+
+/// Orthogonal A* route between the centres of two nodes, given with their collision rects,
+/// that keeps clear of every rect in `obstacles`.
+///
+/// The search runs over a sparse grid whose lines pass through both centres and just outside
+/// each rect, so any route around the obstacles has an equivalent on the grid. Routes leave and
+/// enter the nodes straight through one of their sides, and each bend costs
+/// [`EDGE_ROUTER_BEND_PENALTY`] on top of the length travelled. Returns `None` without
+/// searching when the grid would exceed [`EDGE_ROUTER_MAX_STATES`].
+fn grid_route(
+    from: Point,
+    from_rect: Rect,
+    to: Point,
+    to_rect: Rect,
+    obstacles: &[Rect],
+) -> Option<Vec<Point>> {
+    let walls: Vec<Rect> = obstacles
+        .iter()
+        .copied()
+        .chain([from_rect, to_rect])
+        .collect();
+    let mut xs = vec![from.x, to.x];
+    let mut ys = vec![from.y, to.y];
+    for rect in &walls {
+        xs.extend([
+            rect.min_x - EDGE_COLLISION_MARGIN,
+            rect.max_x + EDGE_COLLISION_MARGIN,
+        ]);
+        ys.extend([
+            rect.min_y - EDGE_COLLISION_MARGIN,
+            rect.max_y + EDGE_COLLISION_MARGIN,
+        ]);
+    }
+    for lines in [&mut xs, &mut ys] {
+        lines.sort_by(f32::total_cmp);
+        lines.dedup_by(|a, b| (*a - *b).abs() < 0.5);
+    }
+    let index =
+        |lines: &[f32], value: f32| lines.iter().position(|line| (line - value).abs() < 0.5);
+    let point = |(i, j): (usize, usize)| Point { x: xs[i], y: ys[j] };
+    let blocked = |a: Point, b: Point| walls.iter().any(|rect| rect.intersects_segment(a, b));
+    let blocked_by_obstacle =
+        |a: Point, b: Point| obstacles.iter().any(|rect| rect.intersects_segment(a, b));
+
+    // Each state is a grid point plus the direction it was entered in, so bends can be
+    // charged: 0 and 1 are horizontal moves, 2 and 3 vertical.
+    const DIRECTIONS: usize = 4;
+    let columns = xs.len();
+    if columns * ys.len() * DIRECTIONS > EDGE_ROUTER_MAX_STATES {
+        return None;
+    }
+    let state_of = |(i, j): (usize, usize), dir: usize| (j * columns + i) * DIRECTIONS + dir;
+    let cell_of = |state: usize| {
+        let cell = state / DIRECTIONS;
+        (cell % columns, cell / columns)
+    };
+    // The grid points straight out from each side of a node, with the direction of travel
+    // leaving it.
+    let doors = |center: Point, rect: Rect| -> Vec<((usize, usize), usize)> {
+        let cx = index(&xs, center.x);
+        let cy = index(&ys, center.y);
+        [
+            (index(&xs, rect.min_x - EDGE_COLLISION_MARGIN).zip(cy), 0),
+            (index(&xs, rect.max_x + EDGE_COLLISION_MARGIN).zip(cy), 1),
+            (cx.zip(index(&ys, rect.min_y - EDGE_COLLISION_MARGIN)), 2),
+            (cx.zip(index(&ys, rect.max_y + EDGE_COLLISION_MARGIN)), 3),
+        ]
+        .into_iter()
+        .filter_map(|(cell, dir)| Some((cell?, dir)))
+        .filter(|(cell, _)| !blocked_by_obstacle(center, point(*cell)))
+        .collect()
+    };
+    let goals = doors(to, to_rect);
+    let heuristic = |cell: (usize, usize)| {
+        let p = point(cell);
+        (p.x - to.x).abs() + (p.y - to.y).abs()
+    };
+
+    // One state past the grid stands for arriving at `to`'s centre.
+    let arrived = xs.len() * ys.len() * DIRECTIONS;
+    let mut cost = vec![f32::INFINITY; arrived + 1];
+    let mut came_from = vec![usize::MAX; arrived + 1];
+    let mut open = std::collections::BinaryHeap::new();
+    for (cell, dir) in doors(from, from_rect) {
+        let state = state_of(cell, dir);
+        cost[state] = 0.0;
+        open.push(GridStep {
+            estimate: heuristic(cell),
+            state,
+        });
+    }
+
+    let bend = |dir: usize, next_dir: usize| {
+        if dir / 2 != next_dir / 2 {
+            EDGE_ROUTER_BEND_PENALTY
+        } else {
+            0.0
+        }
+    };
+
+    while let Some(GridStep { state, .. }) = open.pop() {
+        if state == arrived {
+            let mut cells = Vec::new();
+            let mut current = came_from[arrived];
+            while current != usize::MAX {
+                cells.push(point(cell_of(current)));
+                current = came_from[current];
+            }
+            cells.reverse();
+            let mut route = build_route(from, &cells, to);
+            simplify_route(&mut route);
+            return Some(route);
+        }
+        let cell = cell_of(state);
+        let dir = state % DIRECTIONS;
+        if let Some((_, door)) = goals.iter().find(|(goal, _)| *goal == cell) {
+            // Doors face out of the node, so entering travels the opposite way.
+            let final_cost = cost[state] + heuristic(cell) + bend(dir, door ^ 1);
+            if final_cost < cost[arrived] {
+                cost[arrived] = final_cost;
+                came_from[arrived] = state;
+                open.push(GridStep {
+                    estimate: final_cost,
+                    state: arrived,
+                });
+            }
+        }
+        let (i, j) = cell;
+        let neighbours = [
+            (i.checked_sub(1).map(|i| (i, j)), 0),
+            ((i + 1 < xs.len()).then_some((i + 1, j)), 1),
+            (j.checked_sub(1).map(|j| (i, j)), 2),
+            ((j + 1 < ys.len()).then_some((i, j + 1)), 3),
+        ];
+        for (next, next_dir) in neighbours {
+            let Some(next) = next else {
+                continue;
+            };
+            let (a, b) = (point(cell), point(next));
+            if blocked(a, b) {
+                continue;
+            }
+            let next_cost =
+                cost[state] + (a.x - b.x).abs() + (a.y - b.y).abs() + bend(dir, next_dir);
+            let next_state = state_of(next, next_dir);
+            if next_cost < cost[next_state] {
+                cost[next_state] = next_cost;
+                came_from[next_state] = state;
+                open.push(GridStep {
+                    estimate: next_cost + heuristic(next),
+                    state: next_state,
+                });
+            }
+        }
+    }
+    None
+}
+
 fn side_points(from: Point, to: Point, x: f32) -> [Point; 3] {
     [
         Point { x, y: from.y },
@@ -4121,7 +9787,7 @@ fn label_rect_for_route(edge: &Edge, route: &[Point]) -> Option<Rect> {
     }
 
     let (box_width, box_height) = measure_label_box(&lines);
-    let center = label_center_for_route(route);
+    let center = edge_label_center(edge, route);
 
     Some(Rect {
         min_x: center.x - box_width / 2.0,
@@ -4230,7 +9896,7 @@ impl NodeBoundary {
 
     fn contains_point(&self, point: Point) -> bool {
         match self.shape {
-            NodeShape::Circle | NodeShape::DoubleCircle => {
+            NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Junction => {
                 let rx = self.width / 2.0;
                 let ry = self.height / 2.0;
                 if rx <= 0.0 || ry <= 0.0 {
@@ -4255,6 +9921,64 @@ impl NodeBoundary {
     }
 }
 
+/// One edge endpoint waiting for a port on a node side.
+struct PortEnd<'a> {
+    edge_id: &'a str,
+    /// Whether this is the route's last point.
+    at_end: bool,
+    /// The neighbouring route point's coordinate along the side.
+    along: f32,
+}
+
+/// Side of a node that an edge attaches to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Side {
+    fn of(bounds: &NodeBoundary, point: Point) -> Self {
+        let dx = (point.x - bounds.center.x) / (bounds.width / 2.0).max(f32::EPSILON);
+        let dy = (point.y - bounds.center.y) / (bounds.height / 2.0).max(f32::EPSILON);
+        if dy.abs() >= dx.abs() {
+            if dy < 0.0 { Side::Top } else { Side::Bottom }
+        } else if dx < 0.0 {
+            Side::Left
+        } else {
+            Side::Right
+        }
+    }
+
+    fn is_horizontal(self) -> bool {
+        matches!(self, Side::Top | Side::Bottom)
+    }
+
+    /// A segment from inside the node straight out through this side, `offset` from its middle.
+    fn port_segment(self, bounds: &NodeBoundary, offset: f32) -> (Point, Point) {
+        let center = bounds.center;
+        let reach = bounds.width.max(bounds.height);
+        let (inside, direction) = match self {
+            Side::Top => ((center.x + offset, center.y), (0.0, -1.0)),
+            Side::Bottom => ((center.x + offset, center.y), (0.0, 1.0)),
+            Side::Left => ((center.x, center.y + offset), (-1.0, 0.0)),
+            Side::Right => ((center.x, center.y + offset), (1.0, 0.0)),
+        };
+        (
+            Point {
+                x: inside.0,
+                y: inside.1,
+            },
+            Point {
+                x: inside.0 + direction.0 * reach,
+                y: inside.1 + direction.1 * reach,
+            },
+        )
+    }
+}
+
 fn trim_route_endpoints(
     path: &mut Vec<Point>,
     from_bounds: &NodeBoundary,
@@ -4264,7 +9988,8 @@ fn trim_route_endpoints(
         return;
     }
 
-    if from_bounds.contains_point(path[0]) {
+    // Junction dots are drawn over the stem, so edges meet at their centre.
+    if from_bounds.shape != NodeShape::Junction && from_bounds.contains_point(path[0]) {
         if let Some(trimmed) = clip_segment_exit_with_shape(path[0], path[1], from_bounds, false) {
             path[0] = trimmed;
         }
@@ -4275,7 +10000,7 @@ fn trim_route_endpoints(
     }
 
     let last = path.len() - 1;
-    if to_bounds.contains_point(path[last]) {
+    if to_bounds.shape != NodeShape::Junction && to_bounds.contains_point(path[last]) {
         if let Some(trimmed) =
             clip_segment_exit_with_shape(path[last], path[last - 1], to_bounds, true)
         {
@@ -4291,7 +10016,7 @@ fn clip_segment_exit_with_shape(
     extend_outward: bool,
 ) -> Option<Point> {
     match bounds.shape {
-        NodeShape::Circle | NodeShape::DoubleCircle => {
+        NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Junction => {
             clip_segment_exit_circle(start, next, bounds, extend_outward)
         }
         NodeShape::Diamond => clip_segment_exit_diamond(start, next, bounds, extend_outward),
@@ -4595,12 +10320,42 @@ fn points_close(a: Point, b: Point) -> bool {
     (dx * dx + dy * dy).sqrt() < 1e-2_f32
 }
 
+impl Geometry {
+    /// Moves everything down by [`TITLE_AREA`] and, when the title is wider than the diagram,
+    /// widens the canvas and centres the diagram under it.
+    fn reserve_title_area(&mut self, title: &str, margin: f32) {
+        let title_width = text_width(title, TITLE_FONT_SIZE, TITLE_FONT_SIZE * 0.55) + margin * 2.0;
+        let offset = Point {
+            x: ((title_width - self.width) / 2.0).max(0.0),
+            y: TITLE_AREA,
+        };
+        let moved = |point: &mut Point| {
+            point.x += offset.x;
+            point.y += offset.y;
+        };
+        self.positions.values_mut().for_each(moved);
+        self.edges.values_mut().flatten().for_each(moved);
+        self.labels.values_mut().for_each(moved);
+        for subgraph in &mut self.subgraphs {
+            subgraph.x += offset.x;
+            subgraph.y += offset.y;
+            subgraph.label_x += offset.x;
+            subgraph.label_y += offset.y;
+        }
+        moved(&mut self.shift);
+        self.width = self.width.max(title_width);
+        self.height += TITLE_AREA;
+    }
+}
+
 pub fn align_geometry(
     positions: &HashMap<String, Point>,
     routes: &HashMap<String, Vec<Point>>,
     edges: &[Edge],
     subgraphs: &[Subgraph],
     nodes: &HashMap<String, Node>,
+    margin: f32,
+    lanes: Option<Direction>,
 ) -> Result<Geometry> {
     if positions.is_empty() {
         bail!("diagram does not declare any nodes");
@@ -4640,6 +10395,7 @@ pub fn align_geometry(
         }
     }
 
+    let labels = place_edge_labels(edges, routes, positions, nodes);
     for edge in edges {
         if let Some(label) = &edge.label {
             let identifier = edge_identifier(edge);
@@ -4653,7 +10409,10 @@ pub fn align_geometry(
             }
 
             let (box_width, box_height) = measure_label_box(&lines);
-            let center = label_center_for_route(route);
+            let center = labels
+                .get(&identifier)
+                .copied()
+                .unwrap_or_else(|| edge_label_center(edge, route));
             let half_w = box_width / 2.0;
             let half_h = box_height / 2.0;
 
@@ -4664,7 +10423,7 @@ pub fn align_geometry(
         }
     }
 
-    let unshifted_subgraphs = compute_subgraph_visuals(subgraphs, positions, nodes, routes);
+    let unshifted_subgraphs = compute_subgraph_visuals(subgraphs, positions, nodes, routes, lanes);
     for sg in &unshifted_subgraphs {
         min_x = min_x.min(sg.x);
         max_x = max_x.max(sg.x + sg.width);
@@ -4676,11 +10435,11 @@ pub fn align_geometry(
         bail!("unable to compute diagram bounds");
     }
 
-    let width = (max_x - min_x).max(fallback_width) + LAYOUT_MARGIN * 2.0;
-    let height = (max_y - min_y).max(fallback_height) + LAYOUT_MARGIN * 2.0;
+    let width = (max_x - min_x).max(fallback_width) + margin * 2.0;
+    let height = (max_y - min_y).max(fallback_height) + margin * 2.0;
 
-    let shift_x = LAYOUT_MARGIN - min_x;
-    let shift_y = LAYOUT_MARGIN - min_y;
+    let shift_x = margin - min_x;
+    let shift_y = margin - min_y;
 
     let mut shifted_positions = HashMap::new();
     for (id, point) in positions {
@@ -4705,6 +10464,19 @@ pub fn align_geometry(
         shifted_routes.insert(id.clone(), shifted);
     }
 
+    let shifted_labels = labels
+        .into_iter()
+        .map(|(id, point)| {
+            (
+                id,
+                Point {
+                    x: point.x + shift_x,
+                    y: point.y + shift_y,
+                },
+            )
+        })
+        .collect();
+
     let shifted_subgraphs = unshifted_subgraphs
         .into_iter()
         .map(|mut sg| {
@@ -4719,40 +10491,163 @@ pub fn align_geometry(
     Ok(Geometry {
         positions: shifted_positions,
         edges: shifted_routes,
+        labels: shifted_labels,
         subgraphs: shifted_subgraphs,
         width,
         height,
+        shift: Point {
+            x: shift_x,
+            y: shift_y,
+        },
     })
 }
 
-fn compute_subgraph_visuals(
-    subgraphs: &[Subgraph],
-    positions: &HashMap<String, Point>,
-    definitions: &HashMap<String, Node>,
+/// Places each edge label at its route's usual spot, then moves any label that would overlap
+/// an earlier one along its route, or failing that off to the side, to the nearest spot clear
+/// of the earlier labels and of every node.
+fn place_edge_labels(
+    edges: &[Edge],
     routes: &HashMap<String, Vec<Point>>,
-) -> Vec<SubgraphVisual> {
-    let mut visuals = Vec::new();
-    let fallback_height = definitions
-        .values()
-        .map(|node| node.height)
-        .fold(NODE_HEIGHT, f32::max);
-    for subgraph in subgraphs {
-        collect_subgraph_visual(
-            subgraph,
-            positions,
-            definitions,
-            fallback_height,
-            &mut visuals,
-            0,
-            None,
-        );
-    }
+    positions: &HashMap<String, Point>,
+    nodes: &HashMap<String, Node>,
+) -> HashMap<String, Point> {
+    let node_rects: Vec<Rect> = positions
+        .iter()
+        .filter_map(|(id, center)| {
+            let node = nodes.get(id)?;
+            Some(node_rect(*center, node.width, node.height))
+        })
+        .collect();
+    let mut placed: Vec<Rect> = Vec::new();
+    let mut centers = HashMap::new();
 
-    for visual in visuals.iter_mut().filter(|visual| visual.depth == 0) {
-        let label_width = NODE_TEXT_CHAR_WIDTH * visual.label.chars().count() as f32;
-        let label_bounds = Rect {
-            min_x: visual.label_x,
-            max_x: visual.label_x + label_width,
+    for edge in edges {
+        let Some(label) = &edge.label else {
+            continue;
+        };
+        let lines = normalize_label_lines(label);
+        let id = edge_identifier(edge);
+        if lines.is_empty() || centers.contains_key(&id) {
+            continue;
+        }
+        let Some(route) = routes.get(&id) else {
+            continue;
+        };
+
+        let (width, height) = measure_label_box(&lines);
+        let rect_at =
+            |center: Point| node_rect(center, width, height).inflate(EDGE_COLLISION_MARGIN / 2.0);
+        let overlaps_label = |rect: &Rect| placed.iter().any(|other| rect.intersects(other));
+        let mut center = edge_label_center(edge, route);
+        if overlaps_label(&rect_at(center)) {
+            center = label_candidates(route, center, width, height)
+                .into_iter()
+                .find(|candidate| {
+                    let rect = rect_at(*candidate);
+                    !overlaps_label(&rect) && !node_rects.iter().any(|node| rect.intersects(node))
+                })
+                .unwrap_or(center);
+        }
+        placed.push(rect_at(center));
+        centers.insert(id, center);
+    }
+
+    centers
+}
+
+/// Alternative spots for a label first placed at `center`: points along the route nearest
+/// first, then steps away from the route on either side.
+fn label_candidates(route: &[Point], center: Point, width: f32, height: f32) -> Vec<Point> {
+    let distance = |a: Point, b: Point| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+    let length: f32 = route
+        .windows(2)
+        .map(|points| distance(points[0], points[1]))
+        .sum();
+
+    let mut along = Vec::new();
+    let mut travelled = EDGE_ORTHO_MIN_STUB;
+    while travelled <= length - EDGE_ORTHO_MIN_STUB {
+        along.extend(point_along_route(route, travelled));
+        travelled += EDGE_LABEL_NUDGE_STEP;
+    }
+    along.sort_by(|a, b| distance(*a, center).total_cmp(&distance(*b, center)));
+
+    for step in 1..=2 {
+        let step = step as f32;
+        let (dx, dy) = (
+            (width + EDGE_COLLISION_MARGIN) * step,
+            (height + EDGE_COLLISION_MARGIN) * step,
+        );
+        along.extend([
+            Point {
+                x: center.x,
+                y: center.y - dy,
+            },
+            Point {
+                x: center.x,
+                y: center.y + dy,
+            },
+            Point {
+                x: center.x + dx,
+                y: center.y,
+            },
+            Point {
+                x: center.x - dx,
+                y: center.y,
+            },
+        ]);
+    }
+    along
+}
+
+fn point_along_route(route: &[Point], mut distance: f32) -> Option<Point> {
+    for points in route.windows(2) {
+        let (a, b) = (points[0], points[1]);
+        let segment = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        if segment > f32::EPSILON && distance <= segment {
+            let t = distance / segment;
+            return Some(Point {
+                x: a.x + (b.x - a.x) * t,
+                y: a.y + (b.y - a.y) * t,
+            });
+        }
+        distance -= segment;
+    }
+    None
+}
+
+fn compute_subgraph_visuals(
+    subgraphs: &[Subgraph],
+    positions: &HashMap<String, Point>,
+    definitions: &HashMap<String, Node>,
+    routes: &HashMap<String, Vec<Point>>,
+    lanes: Option<Direction>,
+) -> Vec<SubgraphVisual> {
+    let mut visuals = Vec::new();
+    let fallback_height = definitions
+        .values()
+        .map(|node| node.height)
+        .fold(NODE_HEIGHT, f32::max);
+    for subgraph in subgraphs {
+        collect_subgraph_visual(
+            subgraph,
+            positions,
+            definitions,
+            fallback_height,
+            &mut visuals,
+            0,
+            None,
+        );
+    }
+    if let Some(direction) = lanes {
+        stretch_into_lanes(&mut visuals, direction, positions, definitions);
+    }
+
+    for visual in visuals.iter_mut().filter(|visual| visual.depth == 0) {
+        let label_width = text_width(&visual.label, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH);
+        let label_bounds = Rect {
+            min_x: visual.label_x,
+            max_x: visual.label_x + label_width,
             min_y: visual.y,
             max_y: visual.y + SUBGRAPH_LABEL_AREA,
         };
@@ -4773,6 +10668,104 @@ fn compute_subgraph_visuals(
     visuals
 }
 
+/// Widens the top-level frames into lanes: each spans the whole diagram along the flow and
+/// meets its neighbours halfway across the gap between them.
+fn stretch_into_lanes(
+    visuals: &mut [SubgraphVisual],
+    direction: Direction,
+    positions: &HashMap<String, Point>,
+    definitions: &HashMap<String, Node>,
+) {
+    let top_down = matches!(direction, Direction::TopDown | Direction::BottomTop);
+    let ids: HashSet<String> = positions.keys().cloned().collect();
+    let mut span = group_bounds(&ids, positions, definitions).map(padded_group_bounds);
+    for visual in visuals.iter().filter(|visual| visual.depth == 0) {
+        expand_bounds(
+            &mut span,
+            Rect {
+                min_x: visual.x,
+                max_x: visual.x + visual.width,
+                min_y: visual.y,
+                max_y: visual.y + visual.height,
+            },
+        );
+    }
+
+    let Some(span) = span else {
+        return;
+    };
+
+    let mut lanes: Vec<&mut SubgraphVisual> = visuals
+        .iter_mut()
+        .filter(|visual| visual.depth == 0)
+        .collect();
+    let cross = |visual: &SubgraphVisual| {
+        if top_down {
+            (visual.x, visual.x + visual.width)
+        } else {
+            (visual.y, visual.y + visual.height)
+        }
+    };
+    lanes.sort_by(|a, b| cross(a).0.total_cmp(&cross(b).0));
+    let mut bounds: Vec<(f32, f32)> = lanes.iter().map(|lane| cross(lane)).collect();
+    for idx in 1..bounds.len() {
+        let middle = (bounds[idx - 1].1 + bounds[idx].0) / 2.0;
+        if bounds[idx - 1].1 < middle {
+            bounds[idx - 1].1 = middle;
+            bounds[idx].0 = middle;
+        }
+    }
+
+    for (lane, (start, end)) in lanes.into_iter().zip(bounds) {
+        if top_down {
+            lane.x = start;
+            lane.width = end - start;
+            lane.y = span.min_y;
+            lane.height = span.max_y - span.min_y;
+        } else {
+            lane.y = start;
+            lane.height = end - start;
+            lane.x = span.min_x;
+            lane.width = span.max_x - span.min_x;
+        }
+        lane.label_x = lane.x + SUBGRAPH_LABEL_INSET_X;
+        lane.label_y = lane.y + SUBGRAPH_LABEL_TEXT_BASELINE;
+    }
+}
+
+/// Fill for each subgraph by id: top-level subgraphs take the theme's palette tints in source
+/// order, and nested ones a paler shade of their top-level ancestor's.
+fn subgraph_fills<'a>(subgraphs: &'a [SubgraphVisual], theme: &Theme) -> HashMap<&'a str, String> {
+    let parents: HashMap<&str, Option<&str>> = subgraphs
+        .iter()
+        .map(|subgraph| (subgraph.id.as_str(), subgraph.parent_id.as_deref()))
+        .collect();
+    let mut roots: Vec<&SubgraphVisual> = subgraphs
+        .iter()
+        .filter(|subgraph| subgraph.parent_id.is_none())
+        .collect();
+    roots.sort_by_key(|subgraph| subgraph.order);
+    let root_index: HashMap<&str, usize> = roots
+        .iter()
+        .enumerate()
+        .map(|(index, subgraph)| (subgraph.id.as_str(), index))
+        .collect();
+    subgraphs
+        .iter()
+        .map(|subgraph| {
+            let mut root = subgraph.id.as_str();
+            while let Some(Some(parent)) = parents.get(root) {
+                root = parent;
+            }
+            let index = root_index.get(root).copied().unwrap_or_default();
+            (
+                subgraph.id.as_str(),
+                theme.subgraph_fill(index, subgraph.depth),
+            )
+        })
+        .collect()
+}
+
 fn collect_subgraph_visual(
     subgraph: &Subgraph,
     positions: &HashMap<String, Point>,
@@ -4875,6 +10868,7 @@ fn expand_bounds(target: &mut Option<Rect>, rect: Rect) {
 fn compute_canvas_size_for_positions(
     positions: &HashMap<String, Point>,
     nodes: &HashMap<String, Node>,
+    margin: f32,
 ) -> CanvasSize {
     let fallback_width = nodes
         .values()
@@ -4908,12 +10902,45 @@ fn compute_canvas_size_for_positions(
         max_y = max_y.max(point.y + height / 2.0);
     }
 
-    let width = (max_x - min_x).max(fallback_width) + LAYOUT_MARGIN * 2.0;
-    let height = (max_y - min_y).max(fallback_height) + LAYOUT_MARGIN * 2.0;
+    let width = (max_x - min_x).max(fallback_width) + margin * 2.0;
+    let height = (max_y - min_y).max(fallback_height) + margin * 2.0;
 
     CanvasSize { width, height }
 }
 
+struct Forest<'a> {
+    roots: Vec<&'a str>,
+    /// Children of every node in declaration order.
+    children: HashMap<&'a str, Vec<&'a str>>,
+}
+
+/// Lines up subtree outlines left to right, each as close to the ones before it as `gap`
+/// allows on every level they share. Returns each outline's offset and the combined outline.
+fn pack_outlines(outlines: &[&Vec<(f32, f32)>], gap: f32) -> (Vec<f32>, Vec<(f32, f32)>) {
+    let mut merged: Vec<(f32, f32)> = Vec::new();
+    let mut offsets = Vec::with_capacity(outlines.len());
+    for outline in outlines {
+        let offset = if merged.is_empty() {
+            0.0
+        } else {
+            merged
+                .iter()
+                .zip(outline.iter())
+                .map(|(placed, next)| placed.1 - next.0 + gap)
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        for (level, &(min, max)) in outline.iter().enumerate() {
+            let (min, max) = (min + offset, max + offset);
+            match merged.get_mut(level) {
+                Some(extent) => *extent = (extent.0.min(min), extent.1.max(max)),
+                None => merged.push((min, max)),
+            }
+        }
+        offsets.push(offset);
+    }
+    (offsets, merged)
+}
+
 fn subgraph_nodes(subgraph: &Subgraph) -> HashSet<String> {
     let mut nodes: HashSet<_> = subgraph.nodes.iter().cloned().collect();
     for child in &subgraph.children {
@@ -5021,15 +11048,45 @@ pub fn centroid(points: &[Point]) -> Point {
 }
 
 pub fn edge_identifier(edge: &Edge) -> String {
-    format!(
+    let id = format!(
         "{} {} {}",
         edge.from,
-        edge.kind.connector(edge.arrow),
+        edge.kind.connector_with_head(edge.arrow, edge.head),
         edge.to
-    )
+    );
+    match edge.occurrence {
+        0 => id,
+        n => format!("{id} #{}", n + 1),
+    }
+}
+
+pub(crate) fn same_connection(a: &Edge, b: &Edge) -> bool {
+    a.from == b.from && a.to == b.to && a.kind == b.kind && a.arrow == b.arrow && a.head == b.head
+}
+
+/// Appends `edge`, numbering it after any identical edges already present, and returns its
+/// identifier.
+pub(crate) fn push_edge(edges: &mut Vec<Edge>, mut edge: Edge) -> String {
+    edge.occurrence = edges
+        .iter()
+        .filter(|other| same_connection(other, &edge))
+        .count();
+    let id = edge_identifier(&edge);
+    edges.push(edge);
+    id
+}
+
+fn renumber_parallel_edges(edges: &mut [Edge]) {
+    for idx in 0..edges.len() {
+        let occurrence = edges[..idx]
+            .iter()
+            .filter(|other| same_connection(other, &edges[idx]))
+            .count();
+        edges[idx].occurrence = occurrence;
+    }
 }
 
-fn parse_graph_header(line: &str) -> Result<Direction> {
+pub(crate) fn parse_graph_header(line: &str) -> Result<Direction> {
     let mut parts = line.split_whitespace();
     let keyword = parts
         .next()
@@ -5054,7 +11111,88 @@ fn parse_graph_header(line: &str) -> Result<Direction> {
     Ok(direction)
 }
 
-fn extract_mermaid_diagram_source(source: &str) -> String {
+fn parse_frontmatter(lines: &[&str]) -> Result<DiagramConfig> {
+    if lines.is_empty() {
+        return Ok(DiagramConfig::default());
+    }
+
+    let raw = lines.join("\n");
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(&raw).map_err(|err| anyhow!("invalid frontmatter: {err}"))?;
+    let config = &value["config"];
+    let spacing = |key: &str| {
+        config["flowchart"][key]
+            .as_f64()
+            .or_else(|| config[key].as_f64())
+            .filter(|value| value.is_finite())
+            .map(|value| value.max(0.0) as f32)
+    };
+
+    let theme_variable = |key: &str| config["themeVariables"][key].as_str().map(str::to_string);
+
+    let palette = match config["palette"].as_str() {
+        Some(name) => Palette::parse(name).ok_or_else(|| {
+            anyhow!("unsupported palette '{name}'; expected default, okabe-ito, tol or grayscale")
+        })?,
+        None => Palette::default(),
+    };
+
+    let layout = match config["layout"].as_str() {
+        None | Some("auto") | Some("dagre") => LayoutEngine::Auto,
+        Some("dot") => LayoutEngine::Dot,
+        Some("swimlanes") => LayoutEngine::Swimlanes,
+        Some("tree") => LayoutEngine::Tree,
+        Some("radial") => LayoutEngine::Radial,
+        Some(name) => {
+            bail!("unsupported layout '{name}'; expected auto, dot, swimlanes, tree or radial")
+        }
+    };
+
+    let grid = match config["grid"].as_str() {
+        None | Some("none") => None,
+        Some("dots") => Some(GridStyle::Dots),
+        Some("lines") => Some(GridStyle::Lines),
+        Some(name) => bail!("unsupported grid '{name}'; expected dots, lines or none"),
+    };
+
+    Ok(DiagramConfig {
+        title: value["title"].as_str().map(str::to_string),
+        theme: config["theme"].as_str().map(str::to_string),
+        palette,
+        monochrome: config["monochrome"].as_bool().unwrap_or(false),
+        edge_label_fill: theme_variable("edgeLabelBackground"),
+        edge_label_stroke: theme_variable("edgeLabelBorder"),
+        node_spacing: spacing("nodeSpacing"),
+        rank_spacing: spacing("rankSpacing"),
+        margin: spacing("diagramPadding"),
+        wrap_width: spacing("wrappingWidth"),
+        max_label_width: spacing("maxLabelWidth"),
+        frontmatter: Some(raw),
+        rank_groups: Vec::new(),
+        links: BTreeMap::new(),
+        image_sources: BTreeMap::new(),
+        layout,
+        edge_bundling: config["edgeBundling"].as_bool().unwrap_or(false),
+        animate_edges: config["animateEdges"].as_bool().unwrap_or(false),
+        grid,
+        custom_theme: None,
+        theme_css: config["themeCSS"].as_str().map(str::to_string),
+        stylesheet: None,
+        embed_font: config["embedFont"].as_bool().unwrap_or(false),
+        font_family: config["fontFamily"]
+            .as_str()
+            .map(str::trim)
+            .filter(|family| !family.is_empty())
+            .map(str::to_string),
+        font_files: Vec::new(),
+        icons: BTreeMap::new(),
+        acc_title: None,
+        acc_descr: None,
+        watermark: None,
+    })
+}
+
+pub(crate) fn extract_mermaid_diagram_source(source: &str) -> String {
     if starts_with_supported_diagram_header(source) {
         return source.to_string();
     }
@@ -5096,10 +11234,11 @@ fn extract_mermaid_diagram_source(source: &str) -> String {
     source.to_string()
 }
 
-fn starts_with_supported_diagram_header(source: &str) -> bool {
+/// The first line of `source` outside the frontmatter and comments, with its 1-based number.
+pub(crate) fn diagram_header(source: &str) -> Option<(usize, &str)> {
     let mut in_frontmatter = false;
 
-    for line in source.lines() {
+    for (index, line) in source.lines().enumerate() {
         let trimmed = line.trim();
         if trimmed.is_empty() || trimmed.starts_with("%%") {
             continue;
@@ -5114,15 +11253,43 @@ fn starts_with_supported_diagram_header(source: &str) -> bool {
             continue;
         }
 
-        let keyword = trimmed
-            .split_whitespace()
-            .next()
-            .unwrap_or_default()
-            .to_ascii_lowercase();
-        return keyword == "graph" || keyword == "gantt";
+        return Some((index + 1, trimmed));
     }
 
-    false
+    None
+}
+
+fn starts_with_supported_diagram_header(source: &str) -> bool {
+    let Some((_, header)) = diagram_header(source) else {
+        return false;
+    };
+    let keyword = header
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    matches!(
+        keyword.as_str(),
+        "graph"
+            | "gantt"
+            | "sequencediagram"
+            | "statediagram"
+            | "statediagram-v2"
+            | "classdiagram"
+            | "classdiagram-v2"
+            | "erdiagram"
+            | "pie"
+            | "mindmap"
+            | "journey"
+            | "timeline"
+            | "gitgraph"
+            | "gitgraph:"
+            | "quadrantchart"
+            | "c4context"
+            | "c4container"
+            | "block-beta"
+            | "block"
+    )
 }
 
 fn parse_gantt_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
@@ -5402,6 +11569,7 @@ fn parse_gantt_diagram(lines: Vec<String>, original_source: &str) -> Result<Diag
     };
 
     Ok(Diagram {
+        config: DiagramConfig::default(),
         kind: DiagramKind::Gantt(GanttData {
             title,
             date_format,
@@ -5523,7 +11691,113 @@ fn parse_gantt_datetime(value: &str, date_format: &str) -> Option<f64> {
         return trimmed.parse::<f64>().ok().map(|s| s / 86_400.0);
     }
 
-    parse_yyyy_mm_dd(trimmed)
+    // Formats this parser can't read still accept strict ISO dates.
+    parse_with_date_format(trimmed, date_format)
+        .or_else(|| parse_with_date_format(trimmed, "YYYY-MM-DD"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateToken {
+    Year,
+    ShortYear,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Literal(char),
+}
+
+/// Splits a Moment-style `dateFormat` such as `DD/MM/YYYY HH:mm` into (token, padded width) pairs.
+fn date_format_tokens(date_format: &str) -> Vec<(DateToken, usize)> {
+    const TOKENS: [(&str, DateToken); 12] = [
+        ("YYYY", DateToken::Year),
+        ("YY", DateToken::ShortYear),
+        ("MM", DateToken::Month),
+        ("M", DateToken::Month),
+        ("DD", DateToken::Day),
+        ("D", DateToken::Day),
+        ("HH", DateToken::Hour),
+        ("H", DateToken::Hour),
+        ("mm", DateToken::Minute),
+        ("m", DateToken::Minute),
+        ("ss", DateToken::Second),
+        ("s", DateToken::Second),
+    ];
+
+    let mut tokens = Vec::new();
+    let mut rest = date_format;
+    while let Some(ch) = rest.chars().next() {
+        match TOKENS.iter().find(|(pattern, _)| rest.starts_with(pattern)) {
+            Some((pattern, token)) => {
+                tokens.push((*token, pattern.len()));
+                rest = &rest[pattern.len()..];
+            }
+            None => {
+                tokens.push((DateToken::Literal(ch), 1));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_with_date_format(value: &str, date_format: &str) -> Option<f64> {
+    let tokens = date_format_tokens(date_format);
+    if tokens
+        .iter()
+        .all(|(token, _)| matches!(token, DateToken::Literal(_)))
+    {
+        return None;
+    }
+
+    let (mut year, mut month, mut day) = (1970_i32, 1_u32, 1_u32);
+    let (mut hour, mut minute, mut second) = (0_u32, 0_u32, 0_u32);
+    let mut rest = value;
+    for (token, width) in tokens {
+        if let DateToken::Literal(expected) = token {
+            rest = rest.strip_prefix(expected)?;
+            continue;
+        }
+
+        // Single-letter tokens such as `D` accept one or two digits.
+        let max_digits = if width == 1 { 2 } else { width };
+        let digits = rest
+            .chars()
+            .take(max_digits)
+            .take_while(char::is_ascii_digit)
+            .count();
+        if digits == 0 || (width > 1 && digits != width) {
+            return None;
+        }
+        let number: u32 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+
+        match token {
+            DateToken::Year => year = number as i32,
+            DateToken::ShortYear => year = 2000 + number as i32,
+            DateToken::Month => month = number,
+            DateToken::Day => day = number,
+            DateToken::Hour => hour = number,
+            DateToken::Minute => minute = number,
+            DateToken::Second => second = number,
+            DateToken::Literal(_) => unreachable!(),
+        }
+    }
+
+    if !rest.is_empty() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    // Days past the end of the month, such as 30/02, would otherwise roll into the next one.
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    Some(days as f64 + (hour * 3600 + minute * 60 + second) as f64 / 86_400.0)
 }
 
 fn parse_yyyy_mm_dd(value: &str) -> Option<f64> {
@@ -5585,7 +11859,47 @@ pub(crate) fn format_gantt_day(day: f64, date_format: &str) -> String {
         return format!("{h:02}:{m:02}");
     }
 
-    format!("{day:.2}")
+    let tokens = date_format_tokens(date_format);
+    if tokens
+        .iter()
+        .all(|(token, _)| matches!(token, DateToken::Literal(_)))
+    {
+        return format!("{day:.2}");
+    }
+
+    let has_time = tokens.iter().any(|(token, _)| {
+        matches!(
+            token,
+            DateToken::Hour | DateToken::Minute | DateToken::Second
+        )
+    });
+    let day = if has_time { day } else { day.round() };
+    let mut whole_days = day.floor() as i64;
+    let mut seconds = ((day - day.floor()) * 86_400.0).round() as i64;
+    if seconds >= 86_400 {
+        whole_days += 1;
+        seconds -= 86_400;
+    }
+    let (year, month, day_of_month) = civil_from_days(whole_days);
+
+    let mut formatted = String::new();
+    for (token, width) in tokens {
+        let value = match token {
+            DateToken::Literal(ch) => {
+                formatted.push(ch);
+                continue;
+            }
+            DateToken::Year => year as i64,
+            DateToken::ShortYear => (year as i64).rem_euclid(100),
+            DateToken::Month => month as i64,
+            DateToken::Day => day_of_month as i64,
+            DateToken::Hour => seconds / 3600,
+            DateToken::Minute => (seconds / 60) % 60,
+            DateToken::Second => seconds % 60,
+        };
+        let _ = write!(formatted, "{value:0width$}");
+    }
+    formatted
 }
 
 fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
@@ -5599,7 +11913,7 @@ fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
     era * 146_097 + doe - 719_468
 }
 
-fn civil_from_days(days_since_unix_epoch: i64) -> (i32, u32, u32) {
+pub(crate) fn civil_from_days(days_since_unix_epoch: i64) -> (i32, u32, u32) {
     let z = days_since_unix_epoch + 719_468;
     let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
     let doe = z - era * 146_097;
@@ -5760,6 +12074,8 @@ fn push_gantt_edge(
         label: None,
         kind: EdgeKind::Solid,
         arrow: EdgeArrowDirection::Forward,
+        head: EdgeHead::Arrow,
+        occurrence: 0,
     });
 }
 
@@ -5783,7 +12099,10 @@ fn parse_subgraph_header(raw: &str) -> Result<(String, String)> {
             } else {
                 label_part
             };
-            return Ok((normalize_subgraph_id(id_part), label.to_string()));
+            return Ok((
+                normalize_subgraph_id(id_part),
+                decode_entities(label).into_owned(),
+            ));
         }
     }
 
@@ -5808,18 +12127,168 @@ fn normalize_subgraph_id(raw: &str) -> String {
         .collect()
 }
 
-fn parse_image_comment(line: &str) -> Result<Option<(String, NodeImage)>> {
+/// Reads `%% OXDRAW RANK A B "C d"` into the listed node ids.
+fn parse_rank_comment(line: &str) -> Result<Option<Vec<String>>> {
+    let Some(mut rest) = line
+        .strip_prefix(RANK_COMMENT_PREFIX)
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    else {
+        return Ok(None);
+    };
+
+    let mut ids = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| anyhow!("rank comment node id is missing its closing quote"))?;
+            ids.push(decode_entities(&quoted[..end]).into_owned());
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            ids.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+    }
+    if ids.len() < 2 {
+        bail!("rank comment needs at least two node ids");
+    }
+    Ok(Some(ids))
+}
+
+/// Drops ids that name no node, reporting each, and groups left with a single member.
+fn resolve_rank_groups(
+    comments: Vec<(Vec<String>, LineSpan)>,
+    nodes: &HashMap<String, Node>,
+    diagnostics: &mut Option<&mut Vec<ParseError>>,
+) -> Result<Vec<Vec<String>>> {
+    let mut groups = Vec::new();
+    for (mut group, span) in comments {
+        for id in group.iter().filter(|id| !nodes.contains_key(*id)) {
+            recover(
+                diagnostics,
+                span.error(
+                    DiagnosticCode::UnknownNode,
+                    format!("rank comment references unknown node '{id}'"),
+                ),
+            )?;
+        }
+        group.retain(|id| nodes.contains_key(id));
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+    Ok(groups)
+}
+
+/// Text after `keyword:` on an `accTitle: ...` or `accDescr: ...` line.
+fn accessibility_value<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    line.strip_prefix(keyword)?.trim_start().strip_prefix(':')
+}
+
+/// Parses the rest of a `click <id> [href] "<url>" ["tooltip"] [_target]` statement.
+/// Callback forms such as `click A callback` return `None`, since exported SVG runs no scripts.
+fn parse_click_statement(rest: &str) -> Result<Option<(String, NodeLink)>> {
+    fn quoted(text: &str) -> Result<Option<(String, &str)>> {
+        let Some(inner) = text.strip_prefix('"') else {
+            return Ok(None);
+        };
+        let end = inner
+            .find('"')
+            .ok_or_else(|| anyhow!("click statement is missing a closing quote"))?;
+        Ok(Some((
+            decode_entities(&inner[..end]).into_owned(),
+            inner[end + 1..].trim_start(),
+        )))
+    }
+
+    let rest = rest.trim();
+    let (node_id, rest) = match quoted(rest)? {
+        Some(quoted) => quoted,
+        None => {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (rest[..end].to_string(), rest[end..].trim_start())
+        }
+    };
+    if node_id.is_empty() {
+        bail!("click statement missing node identifier");
+    }
+    let rest = rest
+        .strip_prefix("href")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .map_or(rest, str::trim_start);
+    let Some((href, mut rest)) = quoted(rest)? else {
+        return Ok(None);
+    };
+    if href.is_empty() {
+        bail!("click statement for '{node_id}' has an empty link");
+    }
+    // Inline SVG would run these on click.
+    if href
+        .trim_start()
+        .get(..11)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("javascript:"))
+    {
+        bail!("click statement for '{node_id}' links to a script, which is not allowed");
+    }
+
+    let mut link = NodeLink {
+        href,
+        tooltip: None,
+        target: None,
+    };
+    while !rest.is_empty() {
+        if let Some((tooltip, after)) = quoted(rest)? {
+            link.tooltip = Some(tooltip);
+            rest = after;
+            continue;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let token = &rest[..end];
+        if !token.starts_with('_') {
+            bail!("unexpected '{token}' in click statement for '{node_id}'");
+        }
+        link.target = Some(token.to_string());
+        rest = rest[end..].trim_start();
+    }
+    Ok(Some((node_id, link)))
+}
+
+/// Body of a `%% OXDRAW IMAGE` comment: an embedded image, or a path or URL to load later.
+enum ImageComment {
+    Inline(NodeImage),
+    Source(NodeImageSource),
+}
+
+fn parse_image_comment(line: &str) -> Result<Option<(String, ImageComment)>> {
     let Some(rest) = line.strip_prefix(IMAGE_COMMENT_PREFIX) else {
         return Ok(None);
     };
 
-    let mut parts = rest.trim_start().split_whitespace();
-    let node_id = parts
-        .next()
-        .ok_or_else(|| anyhow!("image comment missing node identifier"))?;
+    let rest = rest.trim_start();
+    let (node_id, rest) = match rest.strip_prefix('"') {
+        Some(quoted) => {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| anyhow!("image comment node id is missing its closing quote"))?;
+            (decode_entities(&quoted[..end]), &quoted[end + 1..])
+        }
+        None => {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (Cow::Borrowed(&rest[..end]), &rest[end..])
+        }
+    };
+    if node_id.is_empty() {
+        bail!("image comment missing node identifier");
+    }
+    let mut parts = rest.split_whitespace();
     let mime_type = parts
         .next()
-        .ok_or_else(|| anyhow!("image comment missing MIME type"))?;
+        .ok_or_else(|| anyhow!("image comment missing MIME type or image path"))?;
 
     let mut padding = 0.0_f32;
     let mut payload_tokens = Vec::new();
@@ -5834,6 +12303,22 @@ fn parse_image_comment(line: &str) -> Result<Option<(String, NodeImage)>> {
         }
     }
 
+    if !mime_type.starts_with("image/") {
+        if !payload_tokens.is_empty() {
+            bail!(
+                "unexpected '{}' after image path for '{node_id}'",
+                payload_tokens[0]
+            );
+        }
+        return Ok(Some((
+            node_id.to_string(),
+            ImageComment::Source(NodeImageSource {
+                src: mime_type.to_string(),
+                padding,
+            }),
+        )));
+    }
+
     let encoded_payload = payload_tokens.join("");
     if encoded_payload.is_empty() {
         bail!("image comment missing base64 payload");
@@ -5847,24 +12332,106 @@ fn parse_image_comment(line: &str) -> Result<Option<(String, NodeImage)>> {
 
     Ok(Some((
         node_id.to_string(),
-        NodeImage {
+        ImageComment::Inline(NodeImage {
             mime_type: mime_type.to_string(),
             data,
             width,
             height,
             padding,
-        },
+        }),
     )))
 }
 
 pub(crate) fn decode_image_dimensions(mime_type: &str, data: &[u8]) -> Result<(u32, u32)> {
     match mime_type {
         "image/png" => parse_png_dimensions(data),
+        "image/jpeg" | "image/gif" | "image/webp" => {
+            let size = imagesize::blob_size(data)
+                .map_err(|err| anyhow!("invalid {mime_type} image: {err}"))?;
+            Ok((size.width.try_into()?, size.height.try_into()?))
+        }
+        "image/svg+xml" => {
+            let tree = resvg::usvg::Tree::from_data(data, &resvg::usvg::Options::default())
+                .map_err(|err| anyhow!("invalid SVG image: {err}"))?;
+            let size = tree.size();
+            Ok((size.width().ceil() as u32, size.height().ceil() as u32))
+        }
         other => bail!("unsupported node image mime type '{other}'"),
     }
 }
 
-fn apply_image_to_node(node: &mut Node, mut image: NodeImage) {
+/// The MIME type of an image read from `src`, told from its leading bytes; SVG has no magic
+/// number, so it is recognised by its root element or a `.svg` extension.
+fn sniff_image_mime_type(data: &[u8], src: &str) -> Result<&'static str> {
+    Ok(match imagesize::image_type(data) {
+        Ok(imagesize::ImageType::Png) => "image/png",
+        Ok(imagesize::ImageType::Jpeg) => "image/jpeg",
+        Ok(imagesize::ImageType::Gif) => "image/gif",
+        Ok(imagesize::ImageType::Webp) => "image/webp",
+        _ => {
+            let path = src.split(['?', '#']).next().unwrap_or(src);
+            let head = String::from_utf8_lossy(&data[..data.len().min(512)]);
+            if head.contains("<svg") || path.to_ascii_lowercase().ends_with(".svg") {
+                "image/svg+xml"
+            } else {
+                bail!("not a PNG, JPEG, GIF, WebP or SVG image")
+            }
+        }
+    })
+}
+
+/// Largest node image `read_image_source` accepts.
+const MAX_IMAGE_SOURCE_BYTES: u64 = 10 * 1024 * 1024;
+/// How long `fetch_image_source` waits to connect, and for the whole download.
+#[cfg(not(target_arch = "wasm32"))]
+const IMAGE_SOURCE_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+#[cfg(not(target_arch = "wasm32"))]
+const IMAGE_SOURCE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+fn read_image_source(src: &str, base_dir: Option<&Path>) -> Result<Vec<u8>> {
+    if src.starts_with("http://") || src.starts_with("https://") {
+        return fetch_image_source(src);
+    }
+    let path = match base_dir {
+        Some(base) if Path::new(src).is_relative() => base.join(src),
+        _ => PathBuf::from(src),
+    };
+    let size = std::fs::metadata(&path)
+        .with_context(|| format!("failed to read '{}'", path.display()))?
+        .len();
+    if size > MAX_IMAGE_SOURCE_BYTES {
+        bail!("image is larger than {MAX_IMAGE_SOURCE_BYTES} bytes");
+    }
+    std::fs::read(&path).with_context(|| format!("failed to read '{}'", path.display()))
+}
+
+/// Blocks until the download finishes, so async callers run it with `spawn_blocking`. A
+/// server that doesn't connect or answer within the timeouts fails the download.
+#[cfg(not(target_arch = "wasm32"))]
+fn fetch_image_source(url: &str) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let client = reqwest::blocking::Client::builder()
+        .connect_timeout(IMAGE_SOURCE_CONNECT_TIMEOUT)
+        .timeout(IMAGE_SOURCE_TIMEOUT)
+        .build()?;
+    let response = client.get(url).send()?.error_for_status()?;
+    let mut data = Vec::new();
+    response
+        .take(MAX_IMAGE_SOURCE_BYTES + 1)
+        .read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_IMAGE_SOURCE_BYTES {
+        bail!("image is larger than {MAX_IMAGE_SOURCE_BYTES} bytes");
+    }
+    Ok(data)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn fetch_image_source(_url: &str) -> Result<Vec<u8>> {
+    bail!("loading node images from URLs is not supported in this build")
+}
+
+fn apply_image_to_node(node: &mut Node, mut image: NodeImage) {
     if image.padding.is_nan() || !image.padding.is_finite() {
         image.padding = 0.0;
     }
@@ -5972,15 +12539,11 @@ fn parse_node_line(
     node_membership: &mut HashMap<String, Vec<String>>,
     subgraph_stack: &mut Vec<SubgraphBuilder>,
 ) -> Result<bool> {
-    if line.contains("-->") || line.contains("-.->") {
+    if find_unquoted(line, "-->").is_some() || find_unquoted(line, "-.->").is_some() {
         return Ok(false);
     }
 
-    let spec = match NodeSpec::parse(line) {
-        Ok(spec) => spec,
-        Err(_) => return Ok(false),
-    };
-
+    let spec = NodeSpec::parse(line)?;
     let (id, _) = insert_node_spec(spec, nodes, order);
     record_node_membership(&id, subgraph_stack, node_membership);
 
@@ -5994,51 +12557,186 @@ fn parse_edge_line(
     node_membership: &mut HashMap<String, Vec<String>>,
     subgraph_stack: &mut Vec<SubgraphBuilder>,
 ) -> Result<Option<Edge>> {
-    const EDGE_PATTERNS: [(&str, EdgeKind, EdgeArrowDirection, Option<&str>); 4] = [
-        ("-.->", EdgeKind::Dashed, EdgeArrowDirection::Forward, None),
+    // Circle and cross ends come first so `--o` is not read as `--` plus a node. Their
+    // two-ended forms need a space before them so `o` or `x` at the end of a node id stays part
+    // of the id.
+    const EDGE_PATTERNS: [(&str, EdgeKind, EdgeArrowDirection, EdgeHead, Option<&str>); 18] = [
+        (
+            " o-.-o",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Both,
+            EdgeHead::Circle,
+            None,
+        ),
+        (
+            " x-.-x",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Both,
+            EdgeHead::Cross,
+            None,
+        ),
+        (
+            "-.-o",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Circle,
+            None,
+        ),
+        (
+            "-.-x",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Cross,
+            None,
+        ),
+        (
+            " o==o",
+            EdgeKind::Thick,
+            EdgeArrowDirection::Both,
+            EdgeHead::Circle,
+            None,
+        ),
+        (
+            " x==x",
+            EdgeKind::Thick,
+            EdgeArrowDirection::Both,
+            EdgeHead::Cross,
+            None,
+        ),
+        (
+            "==o",
+            EdgeKind::Thick,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Circle,
+            None,
+        ),
+        (
+            "==x",
+            EdgeKind::Thick,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Cross,
+            None,
+        ),
+        (
+            " o--o",
+            EdgeKind::Solid,
+            EdgeArrowDirection::Both,
+            EdgeHead::Circle,
+            Some("--"),
+        ),
+        (
+            " x--x",
+            EdgeKind::Solid,
+            EdgeArrowDirection::Both,
+            EdgeHead::Cross,
+            Some("--"),
+        ),
+        (
+            "--o",
+            EdgeKind::Solid,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Circle,
+            Some("--"),
+        ),
+        (
+            "--x",
+            EdgeKind::Solid,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Cross,
+            Some("--"),
+        ),
+        (
+            "<-.->",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Both,
+            EdgeHead::Arrow,
+            None,
+        ),
+        (
+            "<==>",
+            EdgeKind::Thick,
+            EdgeArrowDirection::Both,
+            EdgeHead::Arrow,
+            None,
+        ),
+        (
+            "-.->",
+            EdgeKind::Dashed,
+            EdgeArrowDirection::Forward,
+            EdgeHead::Arrow,
+            None,
+        ),
         (
             "<-->",
             EdgeKind::Solid,
             EdgeArrowDirection::Both,
+            EdgeHead::Arrow,
             Some("--"),
         ),
         (
             "-->",
             EdgeKind::Solid,
             EdgeArrowDirection::Forward,
+            EdgeHead::Arrow,
+            Some("--"),
+        ),
+        (
+            "---",
+            EdgeKind::Solid,
+            EdgeArrowDirection::None,
+            EdgeHead::Arrow,
             Some("--"),
         ),
-        ("---", EdgeKind::Solid, EdgeArrowDirection::None, Some("--")),
     ];
 
-    let mut parts = None;
-    for (pattern, kind, arrow, inline_prefix) in EDGE_PATTERNS {
-        if let Some((lhs, rhs)) = line.split_once(pattern) {
-            parts = Some((lhs.trim(), rhs.trim(), kind, arrow, inline_prefix));
-            break;
-        }
-    }
-
-    let Some((lhs, rhs, kind, arrow, inline_prefix)) = parts else {
+    let Some((idx, len, kind, mut arrow, head, inline_prefix)) = EDGE_PATTERNS
+        .into_iter()
+        .find_map(|(pattern, kind, arrow, head, inline_prefix)| {
+            let idx = find_unquoted(line, pattern)?;
+            // A head letter must end the connector, as in `A --o B` but not `A --oB`.
+            let rhs = &line[idx + pattern.len()..];
+            let ends_connector = head == EdgeHead::Arrow
+                || rhs.is_empty()
+                || rhs.starts_with(|ch: char| ch.is_whitespace() || ch == '|');
+            ends_connector.then_some((idx, pattern.len(), kind, arrow, head, inline_prefix))
+        })
+    else {
         return Ok(None);
     };
+    let (lhs, rhs) = (line[..idx].trim(), line[idx + len..].trim());
 
     let mut label: Option<String> = None;
     let mut from_buffer: Option<String> = None;
     let mut from_segment = lhs;
     let rhs_clean = if let Some(rest) = rhs.strip_prefix('|') {
-        let Some(end_idx) = rest.find('|') else {
+        let Some(end_idx) = find_unquoted(rest, "|") else {
             bail!("edge label missing closing '|' in line: '{line}'");
         };
         let label_text = rest[..end_idx].trim();
         let target = rest[end_idx + 1..].trim();
-        label = Some(label_text.trim_matches('"').to_string());
+        label = Some(decode_label(label_text));
         target
     } else {
         if let Some(prefix) = inline_prefix {
             if let Some((maybe_from, inline_label)) = extract_inline_label(from_segment, prefix) {
                 label = Some(inline_label);
-                from_buffer = Some(maybe_from);
+                // `A <-- text --> B` opens with `<--` and `A o-- text --o B` with `o--`, so
+                // the edge points both ways.
+                let opening = match head {
+                    EdgeHead::Arrow => '<',
+                    EdgeHead::Circle => 'o',
+                    EdgeHead::Cross => 'x',
+                };
+                let opening = maybe_from
+                    .strip_suffix(opening)
+                    .filter(|from| head == EdgeHead::Arrow || from.ends_with(char::is_whitespace));
+                from_buffer = Some(match opening {
+                    Some(from) if arrow == EdgeArrowDirection::Forward => {
+                        arrow = EdgeArrowDirection::Both;
+                        from.trim_end().to_string()
+                    }
+                    _ => maybe_from,
+                });
             }
         }
         if let Some(buffer) = &from_buffer {
@@ -6059,6 +12757,8 @@ fn parse_edge_line(
         label,
         kind,
         arrow,
+        head,
+        occurrence: 0,
     }))
 }
 
@@ -6079,14 +12779,144 @@ fn extract_inline_label(segment: &str, prefix: &str) -> Option<(String, String)>
             if from.is_empty() || label.is_empty() {
                 None
             } else {
-                Some((from.to_string(), label.trim_matches('"').to_string()))
+                Some((from.to_string(), decode_label(label)))
             }
         }
         _ => None,
     }
 }
 
-fn intern_node(
+/// Parses a flowchart edge statement that sits outside any subgraph.
+pub(crate) fn parse_edge_statement(
+    line: &str,
+    nodes: &mut HashMap<String, Node>,
+    order: &mut Vec<String>,
+) -> Result<Option<Edge>> {
+    parse_edge_line(line, nodes, order, &mut HashMap::new(), &mut Vec::new())
+}
+
+fn find_unquoted(haystack: &str, needle: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (idx, ch) in haystack.char_indices() {
+        if ch == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && haystack[idx..].starts_with(needle) {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+fn unquote_label(label: &str) -> &str {
+    if label.len() >= 2 && label.starts_with('"') && label.ends_with('"') {
+        &label[1..label.len() - 1]
+    } else {
+        label.trim_matches('"')
+    }
+}
+
+pub(crate) fn decode_label(label: &str) -> String {
+    decode_entities(unquote_label(label)).into_owned()
+}
+
+/// Decodes Mermaid (`#quot;`, `#9829;`) and HTML (`&amp;`, `&#x2665;`) entities.
+/// Unknown entities are left untouched.
+fn decode_entities(text: &str) -> Cow<'_, str> {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    if !text.contains(';') {
+        return Cow::Borrowed(text);
+    }
+    let pattern = ENTITY.get_or_init(|| {
+        Regex::new(r"(?:#|&#?)(x[0-9A-Fa-f]+|[0-9]+|[A-Za-z][A-Za-z0-9]*);")
+            .expect("valid entity pattern")
+    });
+    pattern.replace_all(text, |caps: &regex::Captures| {
+        let whole = &caps[0];
+        let body = &caps[1];
+        let allows_numeric = whole.starts_with('#') || whole.starts_with("&#");
+        let decoded = if allows_numeric && body.starts_with('x') {
+            u32::from_str_radix(&body[1..], 16)
+                .ok()
+                .and_then(char::from_u32)
+        } else if body.as_bytes()[0].is_ascii_digit() {
+            if allows_numeric {
+                body.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                None
+            }
+        } else if whole.starts_with("&#") {
+            None
+        } else {
+            named_entity(body)
+        };
+        decoded.map_or_else(|| whole.to_string(), |ch| ch.to_string())
+    })
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    let ch = match name {
+        "quot" => '"',
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "deg" => '°',
+        "times" => '×',
+        "middot" => '·',
+        "bull" => '•',
+        "hellip" => '…',
+        "ndash" => '–',
+        "mdash" => '—',
+        "laquo" => '«',
+        "raquo" => '»',
+        "larr" => '←',
+        "rarr" => '→',
+        "hearts" => '♥',
+        "euro" => '€',
+        _ => return None,
+    };
+    Some(ch)
+}
+
+/// Inverse of [`decode_entities`] for serialization: double quotes always become `#quot;`,
+/// and `#`/`&` are escaped only when the text would otherwise be decoded differently.
+pub(crate) fn encode_entities(label: &str) -> Cow<'_, str> {
+    let ambiguous = decode_entities(label) != label;
+    if !ambiguous && !label.contains('"') {
+        return Cow::Borrowed(label);
+    }
+    let mut encoded = String::with_capacity(label.len() + 8);
+    for ch in label.chars() {
+        match ch {
+            '"' => encoded.push_str("#quot;"),
+            '#' if ambiguous => encoded.push_str("#35;"),
+            '&' if ambiguous => encoded.push_str("#38;"),
+            _ => encoded.push(ch),
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+pub(crate) fn quote_label_if_needed(label: &str) -> Cow<'_, str> {
+    const SPECIAL: [&str; 14] = [
+        "[", "]", "(", ")", "{", "}", "<", ">", "|", "&", ";", "--", "-.", "==",
+    ];
+    let label = encode_entities(label);
+    let needs_quotes = label.trim() != label
+        || is_markdown_label(&label)
+        || SPECIAL.iter().any(|pattern| label.contains(pattern));
+    if needs_quotes {
+        Cow::Owned(format!("\"{label}\""))
+    } else {
+        label
+    }
+}
+
+pub(crate) fn intern_node(
     raw: &str,
     nodes: &mut HashMap<String, Node>,
     order: &mut Vec<String>,
@@ -6117,11 +12947,13 @@ fn insert_node_spec(
         }
         Entry::Occupied(mut entry) => {
             let node = entry.get_mut();
+            let (placeholder_width, placeholder_height) =
+                compute_node_dimensions(NodeShape::Rectangle, &id);
             let is_placeholder = node.label == id
                 && matches!(node.shape, NodeShape::Rectangle)
                 && node.image.is_none()
-                && (node.width - NODE_WIDTH).abs() < f32::EPSILON
-                && (node.height - NODE_HEIGHT).abs() < f32::EPSILON;
+                && (node.width - placeholder_width).abs() < f32::EPSILON
+                && (node.height - placeholder_height).abs() < f32::EPSILON;
 
             if is_placeholder {
                 let (width, height) = compute_node_dimensions(shape, &label);
@@ -6135,6 +12967,59 @@ fn insert_node_spec(
     (id, inserted)
 }
 
+/// Words that start statements in Mermaid flowcharts, so they can only be used as ids when quoted.
+const RESERVED_NODE_IDS: [&str; 10] = [
+    "end",
+    "graph",
+    "flowchart",
+    "subgraph",
+    "direction",
+    "style",
+    "classDef",
+    "class",
+    "click",
+    "linkStyle",
+];
+
+/// Whether `id` can be written without quotes and read back unchanged.
+fn is_plain_node_id(id: &str) -> bool {
+    !id.is_empty()
+        && !RESERVED_NODE_IDS.contains(&id)
+        && !["--", "-.", ".-", "=="]
+            .iter()
+            .any(|edge| id.contains(edge))
+        && id
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+}
+
+fn validate_node_id(id: &str) -> Result<()> {
+    if is_plain_node_id(id) {
+        return Ok(());
+    }
+    let first_word = id.split_whitespace().next().unwrap_or(id);
+    if RESERVED_NODE_IDS.contains(&first_word) {
+        bail!(
+            "'{first_word}' is a reserved word and cannot be used as a node id; quote it as \"{first_word}\" or change its case"
+        );
+    }
+    if let Some(ch) = id.chars().find(|ch| ch.is_whitespace()) {
+        bail!("node id '{id}' contains {ch:?}; wrap the id in double quotes, e.g. \"{id}\"");
+    }
+    bail!(
+        "node id '{id}' contains characters outside letters, digits, '_', '-' and '.'; wrap it in double quotes, e.g. \"{id}\""
+    )
+}
+
+/// Writes `id` so [`NodeSpec::parse`] reads it back unchanged, quoting it when needed.
+pub(crate) fn format_node_id(id: &str) -> Cow<'_, str> {
+    if is_plain_node_id(id) {
+        Cow::Borrowed(id)
+    } else {
+        Cow::Owned(format!("\"{}\"", encode_entities(id)))
+    }
+}
+
 struct NodeSpec {
     id: String,
     label: String,
@@ -6148,36 +13033,44 @@ impl NodeSpec {
             bail!("encountered empty node reference");
         }
 
-        let mut id_end = trimmed.len();
-        for (idx, ch) in trimmed.char_indices() {
-            if matches!(ch, '[' | '(' | '{' | '>') {
-                id_end = idx;
-                break;
+        let (id, remainder) = if let Some(quoted) = trimmed.strip_prefix('"') {
+            let Some(end) = quoted.find('"') else {
+                bail!("quoted node id is missing its closing '\"' in segment '{trimmed}'");
+            };
+            let id = decode_entities(&quoted[..end]).into_owned();
+            if id.trim().is_empty() {
+                bail!("quoted node id cannot be empty in segment '{trimmed}'");
+            }
+            (id, quoted[end + 1..].trim())
+        } else {
+            let mut id_end = trimmed.len();
+            for (idx, ch) in trimmed.char_indices() {
+                if matches!(ch, '[' | '(' | '{' | '>') || trimmed[idx..].starts_with("@{") {
+                    id_end = idx;
+                    break;
+                }
             }
-        }
 
-        let id = trimmed[..id_end].trim();
-        if id.is_empty() {
-            bail!("node identifier missing in segment '{trimmed}'");
-        }
+            let id = trimmed[..id_end].trim();
+            if id.is_empty() {
+                bail!("node identifier missing in segment '{trimmed}'");
+            }
+            validate_node_id(id)?;
+            (id.to_string(), trimmed[id_end..].trim())
+        };
 
-        let remainder = trimmed[id_end..].trim();
         let (label, shape) = if remainder.is_empty() {
-            (id.to_string(), NodeShape::Rectangle)
+            (id.clone(), NodeShape::Rectangle)
         } else if let Some((label, shape)) = Self::parse_shape_spec(remainder) {
             (label, shape)
         } else {
             (trimmed.to_string(), NodeShape::Rectangle)
         };
 
-        let label = label.trim_matches('"').to_string();
+        let label = decode_label(&label);
         Ok(NodeSpec {
-            id: id.to_string(),
-            label: if label.is_empty() {
-                id.to_string()
-            } else {
-                label
-            },
+            label: if label.is_empty() { id.clone() } else { label },
+            id,
             shape,
         })
     }
@@ -6188,6 +13081,20 @@ impl NodeSpec {
             return None;
         }
 
+        if let Some(attrs) = trimmed
+            .strip_prefix("@{")
+            .and_then(|rest| rest.strip_suffix('}'))
+        {
+            let shape = attrs.split(',').find_map(|attr| {
+                let (key, value) = attr.split_once(':')?;
+                (key.trim() == "shape").then(|| value.trim().trim_matches('"'))
+            })?;
+            return match shape {
+                "f-circ" | "junction" => Some((String::new(), NodeShape::Junction)),
+                _ => None,
+            };
+        }
+
         if trimmed.starts_with("(((") && trimmed.ends_with(")))") && trimmed.len() >= 6 {
             let inner = trimmed[3..trimmed.len() - 3].trim();
             return Some((inner.to_string(), NodeShape::DoubleCircle));
@@ -6223,137 +13130,2860 @@ impl NodeSpec {
             return Some((inner.to_string(), NodeShape::ParallelogramAlt));
         }
 
-        if trimmed.starts_with("[/") && trimmed.ends_with("\\]") && trimmed.len() >= 4 {
-            let inner = trimmed[2..trimmed.len() - 2].trim();
-            return Some((inner.to_string(), NodeShape::Trapezoid));
-        }
+        if trimmed.starts_with("[/") && trimmed.ends_with("\\]") && trimmed.len() >= 4 {
+            let inner = trimmed[2..trimmed.len() - 2].trim();
+            return Some((inner.to_string(), NodeShape::Trapezoid));
+        }
+
+        if trimmed.starts_with("[\\") && trimmed.ends_with("/]") && trimmed.len() >= 4 {
+            let inner = trimmed[2..trimmed.len() - 2].trim();
+            return Some((inner.to_string(), NodeShape::TrapezoidAlt));
+        }
+
+        if trimmed.starts_with('(') && trimmed.ends_with(')') && trimmed.len() >= 2 {
+            let mut inner = trimmed[1..trimmed.len() - 1].trim().to_string();
+            if inner.starts_with('[') && inner.ends_with(']') && inner.len() >= 2 {
+                inner = inner[1..inner.len() - 1].trim().to_string();
+            }
+            return Some((inner, NodeShape::Stadium));
+        }
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.len() >= 2 {
+            let inner = trimmed[1..trimmed.len() - 1].trim();
+            return Some((inner.to_string(), NodeShape::Rectangle));
+        }
+
+        if trimmed.starts_with('{') && trimmed.ends_with('}') && trimmed.len() >= 2 {
+            let inner = trimmed[1..trimmed.len() - 1].trim();
+            return Some((inner.to_string(), NodeShape::Diamond));
+        }
+
+        if trimmed.starts_with('>') && trimmed.ends_with(']') && trimmed.len() >= 2 {
+            let inner = trimmed[1..trimmed.len() - 1].trim();
+            return Some((inner.to_string(), NodeShape::Asymmetric));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_extended_shape_syntax() {
+        let cases = [
+            ("sub[[Subroutine]]", NodeShape::Subroutine, "Subroutine"),
+            ("db[(Database)]", NodeShape::Cylinder, "Database"),
+            ("hex{{Prep}}", NodeShape::Hexagon, "Prep"),
+            ("stop(((Stop)))", NodeShape::DoubleCircle, "Stop"),
+            ("lean[/Tilt/]", NodeShape::Parallelogram, "Tilt"),
+            ("leanAlt[\\Lean\\]", NodeShape::ParallelogramAlt, "Lean"),
+            ("prio[/Priority\\]", NodeShape::Trapezoid, "Priority"),
+            ("manual[\\Manual/]", NodeShape::TrapezoidAlt, "Manual"),
+            ("asym>Skewed]", NodeShape::Asymmetric, "Skewed"),
+            ("term([Terminal])", NodeShape::Stadium, "Terminal"),
+        ];
+
+        for (input, expected_shape, expected_label) in cases {
+            let spec = NodeSpec::parse(input).unwrap();
+            assert_eq!(spec.shape, expected_shape, "shape mismatch for {input}");
+            assert_eq!(spec.label, expected_label, "label mismatch for {input}");
+        }
+    }
+
+    #[test]
+    fn formats_extended_shapes() {
+        let cases = [
+            (NodeShape::Subroutine, "sub", "Task", "sub[[Task]]"),
+            (NodeShape::Cylinder, "db", "Data", "db[(Data)]"),
+            (NodeShape::Hexagon, "hex", "Prep", "hex{{Prep}}"),
+            (NodeShape::DoubleCircle, "stop", "Stop", "stop(((Stop)))"),
+            (NodeShape::Parallelogram, "lean", "Tilt", "lean[/Tilt/]"),
+            (
+                NodeShape::ParallelogramAlt,
+                "leanAlt",
+                "Tilt",
+                "leanAlt[\\Tilt\\]",
+            ),
+            (
+                NodeShape::Trapezoid,
+                "prio",
+                "Priority",
+                "prio[/Priority\\]",
+            ),
+            (
+                NodeShape::TrapezoidAlt,
+                "manual",
+                "Manual",
+                "manual[\\Manual/]",
+            ),
+            (NodeShape::Asymmetric, "asym", "Skewed", "asym>Skewed]"),
+        ];
+
+        for (shape, id, label, expected) in cases {
+            assert_eq!(
+                shape.format_spec(id, label),
+                expected,
+                "format mismatch for {id}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_inline_edge_labels() {
+        let source = r#"
+graph TD
+    A -- Yes --> B;
+    B --> C;
+"#;
+
+        let diagram = Diagram::parse(source).expect("diagram parse should succeed");
+        assert_eq!(diagram.edges.len(), 2, "expected two edges");
+
+        let yes_edge = diagram
+            .edges
+            .iter()
+            .find(|edge| edge.label.as_deref() == Some("Yes"))
+            .expect("expected inline labeled edge");
+        assert_eq!(yes_edge.from, "A");
+        assert_eq!(yes_edge.to, "B");
+    }
+
+    #[test]
+    fn parses_bidirectional_edges_and_quoted_labels() {
+        let diagram = Diagram::parse("graph TD\nA[\"From\"] <-->|\"sync\"| B[\"To\"]")
+            .expect("diagram parse should succeed");
+
+        assert_eq!(diagram.nodes.len(), 2);
+        assert_eq!(diagram.nodes["A"].label, "From");
+        assert_eq!(diagram.nodes["B"].label, "To");
+        assert_eq!(diagram.edges[0].arrow, EdgeArrowDirection::Both);
+        assert_eq!(diagram.edges[0].label.as_deref(), Some("sync"));
+    }
+
+    #[test]
+    fn round_trips_bidirectional_edge_variants() {
+        let diagram =
+            Diagram::parse("graph LR\nA <--> B\nB <-.-> C\nC <==> D\nD <-- sync --> A\n").unwrap();
+        let kinds: Vec<_> = diagram
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.kind, edge.arrow))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("A", "B", EdgeKind::Solid, EdgeArrowDirection::Both),
+                ("B", "C", EdgeKind::Dashed, EdgeArrowDirection::Both),
+                ("C", "D", EdgeKind::Thick, EdgeArrowDirection::Both),
+                ("D", "A", EdgeKind::Solid, EdgeArrowDirection::Both),
+            ]
+        );
+        assert_eq!(diagram.edges[3].label.as_deref(), Some("sync"));
+        assert_eq!(diagram.nodes.len(), 4);
+
+        let definition = diagram.to_definition();
+        assert!(definition.contains("A <--> B"));
+        assert!(definition.contains("B <-.-> C"));
+        assert!(definition.contains("C <==> D"));
+        assert!(definition.contains("D <-->|sync| A"));
+        assert_eq!(Diagram::parse(&definition).unwrap().edges.len(), 4);
+    }
+
+    #[test]
+    fn round_trips_circle_and_cross_edge_heads() {
+        let source = "graph LR\nA --o B\nB --x C\nC o--o D\nD x==x A\nA -.-o C\nA -- stop --x D\nB o-- sync --o D\nFox --> Box\n";
+        let diagram = Diagram::parse(source).unwrap();
+        let heads: Vec<_> = diagram
+            .edges
+            .iter()
+            .map(|edge| (edge_identifier(edge), edge.arrow, edge.head))
+            .collect();
+        assert_eq!(
+            heads,
+            [
+                (
+                    "A --o B".into(),
+                    EdgeArrowDirection::Forward,
+                    EdgeHead::Circle
+                ),
+                (
+                    "B --x C".into(),
+                    EdgeArrowDirection::Forward,
+                    EdgeHead::Cross
+                ),
+                (
+                    "C o--o D".into(),
+                    EdgeArrowDirection::Both,
+                    EdgeHead::Circle
+                ),
+                ("D x==x A".into(), EdgeArrowDirection::Both, EdgeHead::Cross),
+                (
+                    "A -.-o C".into(),
+                    EdgeArrowDirection::Forward,
+                    EdgeHead::Circle
+                ),
+                (
+                    "A --x D".into(),
+                    EdgeArrowDirection::Forward,
+                    EdgeHead::Cross
+                ),
+                (
+                    "B o--o D".into(),
+                    EdgeArrowDirection::Both,
+                    EdgeHead::Circle
+                ),
+                (
+                    "Fox --> Box".into(),
+                    EdgeArrowDirection::Forward,
+                    EdgeHead::Arrow
+                ),
+            ]
+        );
+        assert_eq!(diagram.edges[5].label.as_deref(), Some("stop"));
+        assert_eq!(diagram.edges[6].label.as_deref(), Some("sync"));
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("<marker id=\"circle-end\""));
+        assert!(svg.contains("marker-start=\"url(#cross-start)\" marker-end=\"url(#cross-end)\""));
+        let plain = Diagram::parse("graph LR\nA --> B\n").unwrap();
+        assert!(
+            !plain
+                .render_svg("white", None)
+                .unwrap()
+                .contains("circle-end")
+        );
+
+        let definition = diagram.to_definition();
+        assert!(definition.contains("A --x|stop| D"));
+        assert!(definition.contains("B o--o|sync| D"));
+        let reparsed = Diagram::parse(&definition).unwrap();
+        let reparsed_ids: Vec<_> = reparsed.edges.iter().map(edge_identifier).collect();
+        let ids: Vec<_> = diagram.edges.iter().map(edge_identifier).collect();
+        assert_eq!(reparsed_ids, ids);
+    }
+
+    #[test]
+    fn pairs_bidirectional_edge_with_same_direction_edge() {
+        let diagram = Diagram::parse("graph LR\nA <--> B\nA --> B\n").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let both = &layout.final_routes[&edge_identifier(&diagram.edges[0])];
+        let forward = &layout.final_routes[&edge_identifier(&diagram.edges[1])];
+        assert!(both.len() > 2 && forward.len() > 2);
+        assert!((both[1].y - forward[1].y).abs() > 1.0);
+    }
+
+    #[test]
+    fn fans_out_parallel_edges_with_distinct_ids() {
+        let diagram =
+            Diagram::parse("graph LR\nA -->|one| B\nA -->|two| B\nA -->|three| B\n").unwrap();
+        let ids: Vec<_> = diagram.edges.iter().map(edge_identifier).collect();
+        assert_eq!(ids, ["A --> B", "A --> B #2", "A --> B #3"]);
+
+        let layout = diagram.layout(None).unwrap();
+        let middle_y = |id: &str| {
+            let route = &layout.final_routes[id];
+            route[route.len() / 2].y
+        };
+        let straight = middle_y("A --> B #2");
+        let above = middle_y("A --> B") - straight;
+        let below = middle_y("A --> B #3") - straight;
+        assert!(above.abs() > 10.0);
+        assert!(
+            (above + below).abs() < 1.0,
+            "offsets should mirror: {above} {below}"
+        );
+
+        let mut trimmed = diagram.clone();
+        assert!(trimmed.remove_edge_by_identifier("A --> B"));
+        let ids: Vec<_> = trimmed.edges.iter().map(edge_identifier).collect();
+        assert_eq!(ids, ["A --> B", "A --> B #2"]);
+        assert_eq!(trimmed.edges[0].label.as_deref(), Some("two"));
+    }
+
+    #[test]
+    fn parses_quoted_labels_with_special_characters() {
+        let source = "graph TD\nA[\"Text with [brackets] and (parens)\"] -->|\"a|b --> c\"| B(\"x --> y\")\nC{\"{braces}\"}";
+        let diagram = Diagram::parse(source).expect("diagram parse should succeed");
+
+        assert_eq!(
+            diagram.nodes["A"].label,
+            "Text with [brackets] and (parens)"
+        );
+        assert_eq!(diagram.nodes["B"].label, "x --> y");
+        assert_eq!(diagram.nodes["B"].shape, NodeShape::Stadium);
+        assert_eq!(diagram.nodes["C"].label, "{braces}");
+        assert_eq!(diagram.edges[0].label.as_deref(), Some("a|b --> c"));
+
+        let definition = diagram.to_definition();
+        assert!(definition.contains("A[\"Text with [brackets] and (parens)\"]"));
+        assert!(definition.contains("|\"a|b --> c\"|"));
+
+        let reparsed = Diagram::parse(&definition).expect("definition should round-trip");
+        assert_eq!(reparsed.nodes["C"].label, "{braces}");
+        assert_eq!(reparsed.edges[0].label.as_deref(), Some("a|b --> c"));
+
+        let plain = Diagram::parse("graph TD\nA[Plain label]").unwrap();
+        assert!(plain.to_definition().contains("A[Plain label]"));
+    }
+
+    #[test]
+    fn parses_junction_nodes() {
+        let diagram =
+            Diagram::parse("graph TD\nA --> J\nJ@{ shape: f-circ }\nJ --> B\nJ --> C").unwrap();
+
+        let junction = &diagram.nodes["J"];
+        assert_eq!(junction.shape, NodeShape::Junction);
+        assert_eq!(
+            (junction.width, junction.height),
+            (JUNCTION_SIZE, JUNCTION_SIZE)
+        );
+        assert!(diagram.to_definition().contains("J@{ shape: f-circ }"));
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("marker-end=\"url(#arrow-end)\"").count(), 2);
+    }
+
+    #[test]
+    fn splits_node_labels_on_html_breaks() {
+        let diagram =
+            Diagram::parse("graph TD\nA[One<br/>Two<BR >Three<Br/ >Four]\nB[Single]").unwrap();
+
+        assert_eq!(
+            normalize_label_lines(&diagram.nodes["A"].label),
+            ["One", "Two", "Three", "Four"]
+        );
+        assert!(diagram.nodes["A"].height > diagram.nodes["B"].height);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(">Four</tspan>"));
+    }
+
+    #[test]
+    fn lays_out_unpinned_nodes_around_pinned_ones() {
+        let diagram = Diagram::parse("graph TD\nA --> B\nA --> C\nA --> D").unwrap();
+        let auto = diagram.layout(None).unwrap().auto_positions;
+
+        let mut overrides = LayoutOverrides::default();
+        overrides.pin("B", auto["C"]);
+        let layout = diagram.layout(Some(&overrides)).unwrap();
+
+        let pinned_at = (auto["C"].x, auto["C"].y);
+        let auto_b = layout.auto_positions["B"];
+        let final_b = layout.final_positions["B"];
+        assert_eq!((auto_b.x, auto_b.y), pinned_at);
+        assert_eq!((final_b.x, final_b.y), pinned_at);
+        let rect_of = |id: &str| {
+            let node = &diagram.nodes[id];
+            node_rect(layout.auto_positions[id], node.width, node.height)
+        };
+        let pinned = rect_of("B");
+        for id in ["C", "D"] {
+            let rect = rect_of(id);
+            assert!(
+                !rect.intersects(&pinned),
+                "{id} should be moved clear of pinned node"
+            );
+        }
+
+        assert!(!overrides.clear_unpinned_positions());
+        overrides.nodes.insert("C".into(), Point { x: 0.0, y: 0.0 });
+        assert!(overrides.clear_unpinned_positions());
+        assert!(overrides.nodes.contains_key("B") && !overrides.nodes.contains_key("C"));
+
+        let json = serde_json::to_string(&overrides).unwrap();
+        assert!(json.contains("\"pinned\":[\"B\"]"));
+    }
+
+    #[test]
+    fn renders_markdown_string_labels() {
+        let diagram =
+            Diagram::parse("graph TD\nA[\"`**Bold** and _it_`\"] --> B[Plain *stars*]").unwrap();
+
+        assert_eq!(diagram.nodes["A"].label, "`**Bold** and _it_`");
+        assert_eq!(
+            normalize_label_lines(&diagram.nodes["A"].label),
+            ["Bold and it"]
+        );
+        assert!(
+            diagram
+                .to_definition()
+                .contains("A[\"`**Bold** and _it_`\"]")
+        );
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(
+            "<tspan font-weight=\"bold\">Bold</tspan> and <tspan font-style=\"italic\">it</tspan>"
+        ));
+        assert!(svg.contains(">Plain *stars*</text>"));
+    }
+
+    #[test]
+    fn markdown_labels_keep_unpaired_and_intraword_delimiters() {
+        let runs = |line: &str| {
+            markdown_runs(line)
+                .into_iter()
+                .map(|run| (run.text, run.bold, run.italic))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            runs("snake_case_name and _it_"),
+            [
+                ("snake_case_name and ".to_string(), false, false),
+                ("it".to_string(), false, true),
+            ]
+        );
+        assert_eq!(
+            runs("2 * 3 = *six"),
+            [("2 * 3 = *six".to_string(), false, false)]
+        );
+        assert_eq!(
+            runs("***both*** **bold"),
+            [
+                ("both".to_string(), true, true),
+                (" **bold".to_string(), false, false),
+            ]
+        );
+        assert_eq!(
+            normalize_label_lines("`snake_case_name * 2`"),
+            ["snake_case_name * 2"]
+        );
+    }
+
+    #[test]
+    fn sizes_nodes_from_label_text() {
+        let diagram = Diagram::parse(
+            "graph TD\nA[Go] --> B[A much longer label here]\nB --> C{Is the build green?}",
+        )
+        .unwrap();
+
+        assert_eq!(diagram.nodes["A"].width, NODE_MIN_WIDTH);
+        assert!(diagram.nodes["B"].width > NODE_WIDTH);
+
+        let decision = &diagram.nodes["C"];
+        let lines = normalize_label_lines(&decision.label);
+        let text_width = raw_node_text_width(&lines) - NODE_TEXT_HORIZONTAL_PADDING;
+        let text_height = raw_node_text_height(&lines) - NODE_TEXT_VERTICAL_PADDING;
+        assert!(text_width / decision.width + text_height / decision.height <= 1.0);
+    }
+
+    #[test]
+    fn sizes_labels_with_font_metrics() {
+        let narrow = text_width(
+            "iiiiiiiiiiiiiiii",
+            NODE_TEXT_FONT_SIZE,
+            NODE_TEXT_CHAR_WIDTH,
+        );
+        let wide = text_width(
+            "WWWWWWWWWWWWWWWW",
+            NODE_TEXT_FONT_SIZE,
+            NODE_TEXT_CHAR_WIDTH,
+        );
+        if narrow == wide {
+            // No system font to measure with; the per-character estimate stands in.
+            assert_eq!(narrow, 16.0 * NODE_TEXT_CHAR_WIDTH);
+            return;
+        }
+        assert!(narrow < wide);
+        let (narrow_node, _) =
+            compute_node_dimensions(NodeShape::Rectangle, "iiiiiiiiiiiiiiiiiiiiiiii");
+        let (wide_node, _) =
+            compute_node_dimensions(NodeShape::Rectangle, "WWWWWWWWWWWWWWWWWWWWWWWW");
+        assert!(narrow_node < wide_node);
+        let (narrow_label, _) = measure_label_box(&["iiiiiiiiiiii".to_string()]);
+        let (wide_label, _) = measure_label_box(&["WWWWWWWWWWWW".to_string()]);
+        assert!(narrow_label < wide_label);
+    }
+
+    #[test]
+    fn wraps_long_labels_within_the_wrapping_width() {
+        let long = "Validate the incoming request payload against the published schema";
+        let source = format!(
+            "graph TD\nA[{long}] --> B[Short]\nB --> C[Supercalifragilisticexpialidocious]\n"
+        );
+        let unwrapped = Diagram::parse(&source).unwrap();
+        assert!(unwrapped.nodes["A"].width > 300.0);
+
+        let framed = format!("---\nconfig:\n  flowchart:\n    wrappingWidth: 160\n---\n{source}");
+        let diagram = Diagram::parse(&framed).unwrap();
+        assert_eq!(diagram.config.wrap_width, Some(160.0));
+        let node = &diagram.nodes["A"];
+        assert!(node.width <= 160.0 + 0.5, "width {}", node.width);
+        assert!(node.height > unwrapped.nodes["A"].height);
+        assert_eq!(node.label, long);
+        assert!(diagram.nodes["C"].width <= 160.0 + 0.5);
+        assert_eq!(diagram.nodes["B"].width, unwrapped.nodes["B"].width);
+
+        let wrapped = wrap_label(long, 100.0);
+        let lines = normalize_label_lines(&wrapped);
+        assert!(lines.len() > 2);
+        assert_eq!(lines.join(" "), long);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("dominant-baseline=\"middle\">Validate the"));
+
+        let mut narrowed = unwrapped.clone();
+        narrowed.apply_layout_options(&LayoutOptions {
+            wrap_width: Some(160.0),
+            ..LayoutOptions::default()
+        });
+        assert_eq!(narrowed.nodes["A"].width, node.width);
+        assert_eq!(
+            wrap_label("`**bold** words that go on and on`", 40.0),
+            "`**bold** words that go on and on`"
+        );
+    }
+
+    #[test]
+    fn long_labels_are_truncated_with_an_ellipsis() {
+        let long = "crate::render::svg::emit_node_with_a_long_generated_name";
+        let source = format!("graph TD\nA[{long}] --> B[Short]\n");
+        let full = Diagram::parse(&source).unwrap();
+
+        let framed = format!("---\nconfig:\n  maxLabelWidth: 120\n---\n{source}");
+        let diagram = Diagram::parse(&framed).unwrap();
+        assert_eq!(diagram.config.max_label_width, Some(120.0));
+        assert!(diagram.nodes["A"].width < full.nodes["A"].width);
+        assert_eq!(diagram.nodes["B"].width, full.nodes["B"].width);
+        assert_eq!(diagram.nodes["A"].label, long);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(&format!("<title>{long}</title>")));
+        let text = &svg[svg.find("dominant-baseline=\"middle\">crate::").unwrap()..];
+        assert!(text[..text.find("</text>").unwrap()].ends_with('…'));
+
+        let mut narrowed = full.clone();
+        narrowed.apply_layout_options(&LayoutOptions {
+            max_label_width: Some(120.0),
+            ..LayoutOptions::default()
+        });
+        assert_eq!(narrowed.nodes["A"].width, diagram.nodes["A"].width);
+        let cut = truncate_label("fits<br>too long to fit", 60.0);
+        assert!(cut.starts_with("fits\ntoo") && cut.ends_with('…'), "{cut}");
+        assert_eq!(
+            truncate_label("`**bold** on and on`", 20.0),
+            "`**bold** on and on`"
+        );
+    }
+
+    #[test]
+    fn reads_frontmatter_config() {
+        let source = "---\ntitle: Spaced\nconfig:\n  theme: dark\n  flowchart:\n    nodeSpacing: 70\n    rankSpacing: 40\n---\ngraph TD\nA --> B\nA --> C";
+        let diagram = Diagram::parse(source).unwrap();
+
+        assert_eq!(diagram.config.title.as_deref(), Some("Spaced"));
+        assert_eq!(diagram.config.theme.as_deref(), Some("dark"));
+        let positions = diagram.layout(None).unwrap().final_positions;
+        assert_eq!(
+            positions["C"].x - positions["B"].x,
+            diagram.nodes["B"].width + 70.0
+        );
+        assert_eq!(positions["B"].y - positions["A"].y, NODE_HEIGHT + 40.0);
+
+        assert!(diagram.to_definition().starts_with("---\ntitle: Spaced\n"));
+        assert!(
+            diagram
+                .render_svg("white", None)
+                .unwrap()
+                .contains(">Spaced</text>")
+        );
+        assert!(Diagram::parse("---\ntitle: [unclosed\n---\ngraph TD\nA").is_err());
+    }
+
+    #[test]
+    fn strips_trailing_comments() {
+        let source = "graph TD %% header note\nA --> B %% main path\nB[\"Rate 50%% off\"] %% quoted\nsubgraph G %% group\nC\nend";
+        let diagram = Diagram::parse(source).unwrap();
+
+        assert_eq!(diagram.edges[0].to, "B");
+        assert_eq!(diagram.nodes["B"].label, "Rate 50%% off");
+        assert_eq!(diagram.subgraphs[0].id, "G");
+        assert_eq!(diagram.nodes.len(), 3);
+    }
+
+    #[test]
+    fn decodes_label_entities() {
+        let diagram = Diagram::parse(
+            "graph TD\n  A[\"He said #quot;hi#quot; &amp; left #9829;\"] -->|a &lt; b| B[&#x2665; #35;1]\n",
+        )
+        .unwrap();
+        assert_eq!(diagram.nodes["A"].label, "He said \"hi\" & left \u{2665}");
+        assert_eq!(diagram.nodes["B"].label, "\u{2665} #1");
+        assert_eq!(diagram.edges[0].label.as_deref(), Some("a < b"));
+
+        let reparsed = Diagram::parse(&diagram.to_definition()).unwrap();
+        assert_eq!(reparsed.nodes["A"].label, diagram.nodes["A"].label);
+        assert_eq!(reparsed.nodes["B"].label, diagram.nodes["B"].label);
+        assert_eq!(reparsed.edges[0].label, diagram.edges[0].label);
+
+        let mut literal = diagram.clone();
+        literal.nodes.get_mut("B").unwrap().label = "C#35; & #quot;".to_string();
+        let reparsed = Diagram::parse(&literal.to_definition()).unwrap();
+        assert_eq!(reparsed.nodes["B"].label, "C#35; & #quot;");
+    }
+
+    #[test]
+    fn reports_parse_errors_with_locations() {
+        let err = Diagram::parse("graph TD\n  A --> B\n\n    C -->|oops D\n").unwrap_err();
+        let parse_error = err.downcast_ref::<ParseError>().unwrap();
+        assert_eq!(parse_error.code, DiagnosticCode::InvalidEdge);
+        assert_eq!(
+            (parse_error.line, parse_error.column, parse_error.end_column),
+            (4, 5, 17)
+        );
+
+        let err = Diagram::parse("graph TD\n  subgraph one\n  A --> B\n").unwrap_err();
+        let parse_error = err.downcast_ref::<ParseError>().unwrap();
+        assert_eq!(parse_error.code, DiagnosticCode::UnclosedSubgraph);
+        assert_eq!(parse_error.line, 2);
+
+        let json = serde_json::to_string(parse_error).unwrap();
+        assert!(json.contains("\"code\":\"unclosed-subgraph\""));
+        assert!(json.contains("\"endColumn\":15"));
+    }
+
+    #[test]
+    fn lenient_parse_collects_all_errors() {
+        let source = "graph TD\n  A --> B\n  C -->|oops D\n  subgraph\n  B --> E\n  end\n  end\n  subgraph open\n  F\n";
+        assert!(Diagram::parse(source).is_err());
+
+        let (diagram, diagnostics) = Diagram::parse_lenient(source).unwrap();
+        let codes: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                (3, DiagnosticCode::InvalidEdge),
+                (4, DiagnosticCode::InvalidSubgraph),
+                (7, DiagnosticCode::UnmatchedEnd),
+                (8, DiagnosticCode::UnclosedSubgraph),
+            ]
+        );
+        assert!(diagram.nodes.contains_key("E"));
+        assert_eq!(diagram.edges.len(), 2);
+        assert_eq!(diagram.subgraphs.len(), 1);
+        assert_eq!(diagram.subgraphs[0].nodes, ["F"]);
+
+        assert!(Diagram::parse_lenient("graph TD\n  A -->|x\n").is_err());
+    }
+
+    #[test]
+    fn enforces_parse_limits() {
+        let limits = ParseLimits {
+            max_nodes: Some(3),
+            max_edges: Some(2),
+            max_label_len: Some(8),
+            max_depth: Some(1),
+            ..ParseLimits::default()
+        };
+        let limit_error = |source: &str| {
+            let err = Diagram::parse_with_limits(source, &limits).unwrap_err();
+            let parse_error = err.downcast_ref::<ParseError>().unwrap().clone();
+            assert_eq!(parse_error.code, DiagnosticCode::LimitExceeded);
+            parse_error
+        };
+
+        assert!(Diagram::parse_with_limits("graph TD\n  A --> B\n  B --> C\n", &limits).is_ok());
+        assert_eq!(limit_error("graph TD\n  A --> B\n  C --> D\n").line, 3);
+        assert_eq!(
+            limit_error("graph TD\n  A --> B\n  B --> A\n  A --> B\n").line,
+            4
+        );
+        assert_eq!(
+            limit_error("graph TD\n  subgraph one\n  subgraph two\n  A\n  end\n  end\n").line,
+            3
+        );
+        assert!(
+            limit_error("graph TD\n  A[A rather long label]\n")
+                .message
+                .contains("node 'A'")
+        );
+        assert!(
+            Diagram::parse_with_limits(
+                "graph TD\n  A --> B\n",
+                &ParseLimits {
+                    max_source_bytes: Some(8),
+                    ..ParseLimits::default()
+                }
+            )
+            .is_err()
+        );
+
+        assert!(
+            Diagram::parse_with_limits("sequenceDiagram\n  A->>B: hi\n  B-->>A: ok\n", &limits)
+                .is_ok()
+        );
+        assert!(
+            limit_error("sequenceDiagram\n  A->>B: 1\n  B->>C: 2\n  C->>A: 3\n")
+                .message
+                .contains("edge count")
+        );
+        assert!(
+            limit_error("sequenceDiagram\n  A->>B: 1\n  C->>D: 2\n")
+                .message
+                .contains("node count")
+        );
+        assert!(
+            limit_error("pie\n  \"a\" : 1\n  \"b\" : 1\n  \"c\" : 1\n  \"d\" : 1\n")
+                .message
+                .contains("node count")
+        );
+    }
+
+    #[test]
+    fn parses_sequence_diagrams() {
+        let source = "sequenceDiagram\n    actor U as User\n    U->>+API: request\n    loop retry\n        API-->>DB: query\n    end\n    API-->>-U: done\n";
+        let diagram = Diagram::parse(source).expect("sequence diagram should parse");
+        let DiagramKind::Sequence(sequence) = &diagram.kind else {
+            panic!("expected a sequence diagram");
+        };
+        let ids: Vec<_> = sequence
+            .participants
+            .iter()
+            .map(|p| p.id.as_str())
+            .collect();
+        assert_eq!(ids, ["U", "API", "DB"]);
+        assert!(sequence.participants[0].actor);
+        assert!(matches!(&sequence.events[1], SequenceEvent::Activate(id) if id == "API"));
+        assert!(matches!(
+            &sequence.events[2],
+            SequenceEvent::BlockStart { kind: SequenceBlockKind::Loop, label } if label == "retry"
+        ));
+        assert!(
+            matches!(&sequence.events.last(), Some(SequenceEvent::Deactivate(id)) if id == "API")
+        );
+        assert_eq!(diagram.to_definition(), source);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("sequence-activation"));
+        assert!(svg.contains("data-block-kind=\"loop\""));
+
+        let err = Diagram::parse("sequenceDiagram\n    loop forever\n    A->>B: hi\n").unwrap_err();
+        assert!(format!("{err:#}").contains("missing its 'end'"));
+    }
+
+    #[test]
+    fn quick_adds_nodes_with_generated_ids() {
+        let mut diagram =
+            Diagram::parse("graph TD\n  A --> n2\n  subgraph n3 [Group]\n    B\n  end\n").unwrap();
+        assert_eq!(diagram.next_node_id(), "n4");
+
+        let (id, edge_id) = diagram.quick_add_node("Next", Some("A")).unwrap();
+        assert_eq!(id, "n4");
+        assert_eq!(edge_id.as_deref(), Some("A --> n4"));
+        let (id, edge_id) = diagram.quick_add_node("  ", None).unwrap();
+        assert_eq!((id.as_str(), edge_id), ("n5", None));
+
+        let reparsed = Diagram::parse(&diagram.to_definition()).unwrap();
+        assert_eq!(reparsed.nodes["n4"].label, "Next");
+        assert_eq!(reparsed.nodes["n5"].label, "n5");
+        assert!(
+            reparsed
+                .edges
+                .iter()
+                .any(|edge| edge.from == "A" && edge.to == "n4")
+        );
+        assert!(diagram.quick_add_node("Orphan", Some("missing")).is_err());
+    }
+
+    #[test]
+    fn parses_state_diagrams_into_flowchart_model() {
+        let source = "stateDiagram-v2\n    [*] --> Idle\n    Idle --> Busy : start\n    state Busy {\n        [*] --> Working\n        Working --> [*]\n    }\n    Busy --> [*]\n    state \"Waiting\" as Idle\n";
+        let diagram = Diagram::parse(source).expect("state diagram should parse");
+        assert!(matches!(diagram.kind, DiagramKind::State(_)));
+        assert_eq!(diagram.nodes["Idle"].label, "Waiting");
+        assert_eq!(diagram.nodes["__start"].shape, NodeShape::Junction);
+        assert_eq!(diagram.nodes["__end"].shape, NodeShape::DoubleCircle);
+        assert!(!diagram.nodes.contains_key("Busy"));
+        assert_eq!(diagram.subgraphs[0].id, "Busy");
+        assert_eq!(diagram.node_membership["Working"], vec!["Busy".to_string()]);
+
+        let transitions: Vec<_> = diagram
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.label.as_deref()))
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                ("__start", "Idle", None),
+                ("Idle", "Busy__start", Some("start")),
+                ("Busy__start", "Working", None),
+                ("Working", "Busy__end", None),
+                ("Busy__end", "__end", None),
+            ]
+        );
+        assert_eq!(diagram.to_definition(), source);
+        assert!(diagram.render_svg("white", None).is_ok());
+        assert!(Diagram::parse("stateDiagram-v2\n    state A {\n    A1 --> A2\n").is_err());
+    }
+
+    #[test]
+    fn groups_edges_by_source_and_subgraph() {
+        let diagram = Diagram::parse(
+            "graph TD\n  subgraph Back\n    API --> DB\n  end\n  UI --> API\n  API --> Log\n  UI --> Log\n",
+        )
+        .unwrap();
+
+        let by_source = diagram.to_definition_with(&DefinitionFormat {
+            edge_grouping: EdgeGrouping::Source,
+        });
+        assert!(by_source.contains("API --> DB\nAPI --> Log\n\nUI --> API\nUI --> Log\n"));
+
+        let by_subgraph = diagram.to_definition_with(&DefinitionFormat {
+            edge_grouping: EdgeGrouping::Subgraph,
+        });
+        assert!(by_subgraph.contains("UI --> API\nUI --> Log\n\nAPI --> DB\nAPI --> Log\n"));
+
+        let reparsed = Diagram::parse(&by_subgraph).unwrap();
+        assert_eq!(reparsed.edges.len(), diagram.edges.len());
+    }
+
+    #[test]
+    fn parses_class_diagrams() {
+        let diagram = Diagram::parse(
+            "classDiagram\n  class Animal {\n    <<abstract>>\n    +String name\n    +speak() void\n  }\n  Animal <|-- Dog\n  Kennel \"1\" o-- \"*\" Dog : holds\n  Dog ..> Bone\n  Box~T~ : +T value\n",
+        )
+        .unwrap();
+        let DiagramKind::Class(class) = &diagram.kind else {
+            panic!("expected a class diagram");
+        };
+
+        let animal = &class.classes["Animal"];
+        assert_eq!(animal.annotation.as_deref(), Some("abstract"));
+        assert_eq!(animal.attributes, vec!["+String name"]);
+        assert_eq!(animal.methods, vec!["+speak() void"]);
+        assert_eq!(diagram.nodes["Box"].label, "Box<T>");
+        assert_eq!(class.classes["Box"].attributes, vec!["+T value"]);
+
+        let inheritance = &class.relations[&edge_identifier(&diagram.edges[0])];
+        assert_eq!(inheritance.start, Some(ClassMarker::Inheritance));
+        assert_eq!(inheritance.end, None);
+
+        let aggregation = &class.relations[&edge_identifier(&diagram.edges[1])];
+        assert_eq!(aggregation.start, Some(ClassMarker::Aggregation));
+        assert_eq!(aggregation.start_cardinality.as_deref(), Some("1"));
+        assert_eq!(aggregation.end_cardinality.as_deref(), Some("*"));
+        assert_eq!(diagram.edges[1].label.as_deref(), Some("holds"));
+
+        assert_eq!(diagram.edges[2].kind, EdgeKind::Dashed);
+        let dependency = &class.relations[&edge_identifier(&diagram.edges[2])];
+        assert_eq!(dependency.end, Some(ClassMarker::Arrow));
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("marker-start=\"url(#class-inheritance)\""));
+        assert!(svg.contains("\u{ab}abstract\u{bb}"));
+    }
+
+    #[test]
+    fn quotes_reserved_and_special_node_ids() {
+        let source = "graph TD\n  subgraph S\n    \"end\" --> \"my node\"[Label]\n  end\n  subgraphs --> a.b-c\n";
+        let diagram = Diagram::parse(source).unwrap();
+        assert!(diagram.nodes.contains_key("end"));
+        assert_eq!(diagram.nodes["my node"].label, "Label");
+        assert!(diagram.nodes.contains_key("subgraphs"));
+        assert!(diagram.nodes.contains_key("a.b-c"));
+        assert_eq!(diagram.subgraphs[0].nodes, vec!["end", "my node"]);
+
+        let definition = diagram.to_definition();
+        assert!(definition.contains("\"end\" --> \"my node\""));
+        assert!(definition.contains("\"my node\"[Label]"));
+        assert!(definition.contains("subgraphs --> a.b-c"));
+        let reparsed = Diagram::parse(&definition).unwrap();
+        assert_eq!(reparsed.to_definition(), definition);
+
+        let err = Diagram::parse("graph TD\n  A --> end\n").unwrap_err();
+        assert!(format!("{err:#}").contains("'end' is a reserved word"));
+        let err = Diagram::parse("graph TD\n  my node --> B\n").unwrap_err();
+        assert!(format!("{err:#}").contains("wrap the id in double quotes"));
+    }
+
+    #[test]
+    fn parses_er_diagrams() {
+        let diagram = Diagram::parse(
+            "erDiagram\n  CUSTOMER ||--o{ ORDER : places\n  ORDER }|..|| LINE-ITEM : \"has many\"\n  ORDER {\n    int id PK\n    int customerId FK, UK \"owner\"\n  }\n  p[Person]\n",
+        )
+        .unwrap();
+        let DiagramKind::Er(er) = &diagram.kind else {
+            panic!("expected an ER diagram");
+        };
+
+        assert_eq!(diagram.order, vec!["CUSTOMER", "ORDER", "LINE-ITEM", "p"]);
+        assert_eq!(diagram.nodes["p"].label, "Person");
+        let attributes = &er.entities["ORDER"].attributes;
+        assert_eq!(attributes[0].keys, vec!["PK"]);
+        assert_eq!(attributes[1].name, "customerId");
+        assert_eq!(attributes[1].keys, vec!["FK", "UK"]);
+        assert_eq!(attributes[1].comment.as_deref(), Some("owner"));
+
+        let places = &er.relationships[&edge_identifier(&diagram.edges[0])];
+        assert_eq!(places.from, ErCardinality::ExactlyOne);
+        assert_eq!(places.to, ErCardinality::ZeroOrMore);
+        assert_eq!(diagram.edges[1].kind, EdgeKind::Dashed);
+        assert_eq!(diagram.edges[1].label.as_deref(), Some("has many"));
+        let has_many = &er.relationships[&edge_identifier(&diagram.edges[1])];
+        assert_eq!(has_many.from, ErCardinality::OneOrMore);
+        assert_eq!(has_many.to, ErCardinality::ExactlyOne);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("marker-end=\"url(#er-zero-or-more)\""));
+    }
+
+    #[test]
+    fn rejects_malformed_er_statements() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("erDiagram\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        for relationship in [
+            "  CUSTOMER ||--x{ ORDER : places",
+            "  CUSTOMER {{--o{ ORDER : places",
+            "  CUSTOMER ||--o{ ORDER",
+            "  CUSTOMER ||..o ORDER : places",
+        ] {
+            let message = error(relationship);
+            assert!(message.contains("invalid ER relationship"), "{message}");
+        }
+        assert!(error("  ORDER {\n    int id\n").contains("missing its closing '}'"));
+        assert!(error("  ORDER {\n    int\n  }").contains("invalid attribute 'int'"));
+        assert!(error("  ORDER {\n    int id PK, XK\n  }").contains("invalid attribute"));
+        assert!(error("  bad/name").contains("unrecognized ER diagram statement"));
+        assert!(error("").contains("does not declare any entities"));
+
+        // An entity can relate to itself, and a quoted empty label leaves the edge unlabeled.
+        let diagram = Diagram::parse(
+            "erDiagram\n  PERSON }o--o{ PERSON : knows\n  PERSON ||--|| PET : \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(diagram.order, vec!["PERSON", "PET"]);
+        assert_eq!(diagram.edges[0].from, diagram.edges[0].to);
+        assert_eq!(diagram.edges[1].label, None);
+        diagram.render_svg("white", None).unwrap();
+    }
+
+    #[test]
+    fn reports_layout_collisions() {
+        let diagram = Diagram::parse("graph LR\n  A --> C\n  B\n  D -->|note| E\n").unwrap();
+        let point = |x: f32, y: f32| Point { x, y };
+        let positions: HashMap<String, Point> = [
+            ("A", point(0.0, 0.0)),
+            ("B", point(200.0, 0.0)),
+            ("C", point(400.0, 0.0)),
+            ("D", point(300.0, -200.0)),
+            ("E", point(300.0, 200.0)),
+        ]
+        .into_iter()
+        .map(|(id, p)| (id.to_string(), p))
+        .collect();
+        let routes: HashMap<String, Vec<Point>> = [
+            ("A --> C", vec![point(70.0, 0.0), point(330.0, 0.0)]),
+            ("D --> E", vec![point(300.0, -175.0), point(300.0, 175.0)]),
+        ]
+        .into_iter()
+        .map(|(id, route)| (id.to_string(), route))
+        .collect();
+        let layout = LayoutComputation {
+            auto_positions: positions.clone(),
+            auto_routes: routes.clone(),
+            auto_size: CanvasSize {
+                width: 0.0,
+                height: 0.0,
+            },
+            final_positions: positions,
+            final_routes: routes,
+        };
+
+        let diagnostics = diagram.layout_diagnostics(&layout);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].edge_id, "A --> C");
+        assert_eq!(diagnostics[0].kind, CollisionKind::NodeOverlap);
+        assert_eq!(diagnostics[0].nodes, vec!["B"]);
+        assert_eq!(diagnostics[1].kind, CollisionKind::EdgeCrossing);
+        assert_eq!(diagnostics[1].other_edge_id.as_deref(), Some("D --> E"));
+        assert_eq!(diagnostics[1].nodes, vec!["A", "C", "D", "E"]);
+
+        let auto = diagram.layout(None).unwrap();
+        assert!(
+            diagram
+                .layout_diagnostics(&auto)
+                .iter()
+                .all(|diagnostic| diagnostic.kind != CollisionKind::NodeOverlap)
+        );
+    }
+
+    #[test]
+    fn parses_pie_charts() {
+        let diagram =
+            Diagram::parse("pie title Pets\n  \"Dogs\" : 3\n  \"Cats\" : 1.5\n  \"Fish\" : 0.5\n")
+                .unwrap();
+        let DiagramKind::Pie(pie) = &diagram.kind else {
+            panic!("expected a pie chart");
+        };
+        assert_eq!(pie.title.as_deref(), Some("Pets"));
+        assert!(!pie.show_data);
+        assert_eq!(pie.slices.len(), 3);
+        assert_eq!(pie.slices[1].label, "Cats");
+        assert_eq!(pie.total(), 5.0);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"pie-slice\"").count(), 3);
+        assert!(svg.contains(">60%</text>"));
+
+        let show_data = Diagram::parse("pie showData\n  \"A\" : 2\n").unwrap();
+        let svg = show_data.render_svg("white", None).unwrap();
+        assert!(svg.contains("<circle class=\"pie-slice\""));
+        assert!(svg.contains(">A [2]</text>"));
+
+        assert!(Diagram::parse("pie\n  Dogs : 3\n").is_err());
+        assert!(Diagram::parse("pie\n  \"Dogs\" : -3\n").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_and_degenerate_pie_charts() {
+        let error = |source: &str| Diagram::parse(source).unwrap_err().to_string();
+        assert!(error("pie\n").contains("does not declare any slices"));
+        assert!(error("pie title Pets\n").contains("does not declare any slices"));
+        assert!(error("pie\n  \"A\" : 0\n  \"B\" : 0\n").contains("must not all be zero"));
+        assert!(error("pie\n  \"A\" : 1e308\n  \"B\" : 1e308\n").contains("too large"));
+        assert!(error("pie\n  \"A\" : NaN\n").contains("non-negative"));
+        assert!(error("pie\n  \"A\" : lots\n").contains("invalid value 'lots'"));
+        assert!(error("pie\n  \"A\"\n").contains("expected '\"Label\" : value'"));
+        assert!(error("pie titles Pets\n  \"A\" : 1\n").contains("unexpected 'titles Pets'"));
+
+        // A zero slice next to others is drawn as nothing, and a colon may sit in the label.
+        let diagram = Diagram::parse("pie\n  \"A:B\" : 0\n  \"C\" : 2\n").unwrap();
+        let DiagramKind::Pie(pie) = &diagram.kind else {
+            panic!("expected a pie chart");
+        };
+        assert_eq!(pie.slices[0].label, "A:B");
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(!svg.contains("NaN"));
+        assert!(svg.contains(">100%</text>"));
+    }
+
+    #[test]
+    fn renders_editor_viewport() {
+        let diagram = Diagram::parse("graph TD\n  A --> B\n").unwrap();
+        let viewport = ViewportRender {
+            x: 10.0,
+            y: 20.0,
+            width: 200.0,
+            height: 100.0,
+            zoom: 1.5,
+            selected_nodes: vec!["A".to_string()],
+            selected_edges: vec!["A --> B".to_string()],
+            selection_color: None,
+        };
+
+        let svg = diagram
+            .render_viewport_svg("white", None, &viewport)
+            .unwrap();
+        assert!(svg.contains("width=\"300\" height=\"150\" viewBox=\"10.0 20.0 200.0 100.0\""));
+        assert!(svg.contains("<g class=\"node node-rectangle selected\" data-id=\"A\""));
+        assert!(svg.contains("<g class=\"node node-rectangle\" data-id=\"B\""));
+        assert!(svg.contains("<g class=\"edge edge-solid selected\" data-id=\"A --&gt; B\""));
+        assert!(svg.contains("stroke: #f472b6;"));
+
+        let png = diagram
+            .render_viewport_png("white", None, &viewport)
+            .unwrap();
+        assert_eq!(&png[16..24], &[0, 0, 1, 44, 0, 0, 0, 150]);
+
+        for (width, height, zoom) in [(50_000.0, 10.0, 1.0), (10_000.0, 10_000.0, 1.0)] {
+            let huge = ViewportRender {
+                width,
+                height,
+                zoom,
+                ..viewport.clone()
+            };
+            assert!(diagram.render_viewport_png("white", None, &huge).is_err());
+        }
+        let zoomed = ViewportRender {
+            zoom: 1_000.0,
+            ..viewport.clone()
+        };
+        assert!(diagram.render_viewport_svg("white", None, &zoomed).is_err());
+
+        let missing = ViewportRender {
+            selected_nodes: vec!["Z".to_string()],
+            ..viewport
+        };
+        assert!(
+            diagram
+                .render_viewport_svg("white", None, &missing)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parses_custom_gantt_date_formats() {
+        let day = parse_gantt_datetime("02/01/2024", "DD/MM/YYYY").unwrap();
+        assert_eq!(
+            day,
+            parse_gantt_datetime("2024-01-02", "YYYY-MM-DD").unwrap()
+        );
+        assert_eq!(format_gantt_day(day, "DD/MM/YYYY"), "02/01/2024");
+        assert_eq!(format_gantt_day(day, "D.M.YY"), "2.1.24");
+
+        let with_time = parse_gantt_datetime("2024-01-02 06:30", "YYYY-MM-DD HH:mm").unwrap();
+        assert!((with_time - day - 6.5 / 24.0).abs() < 1e-6);
+        assert_eq!(
+            format_gantt_day(with_time, "YYYY-MM-DD HH:mm"),
+            "2024-01-02 06:30"
+        );
+
+        assert!(parse_gantt_datetime("13/13/2024", "DD/MM/YYYY").is_none());
+        assert!(parse_gantt_datetime("2/1/2024", "DD/MM/YYYY").is_none());
+    }
+
+    #[test]
+    fn rejects_dates_that_do_not_fit_the_gantt_format() {
+        assert!(parse_gantt_datetime("29/02/2024", "DD/MM/YYYY").is_some());
+        for (value, format) in [
+            ("30/02/2024", "DD/MM/YYYY"),
+            ("29/02/2023", "DD/MM/YYYY"),
+            ("31/04/2024", "DD/MM/YYYY"),
+            ("00/01/2024", "DD/MM/YYYY"),
+            ("02-01-2024", "DD/MM/YYYY"),
+            ("02/01/2024 extra", "DD/MM/YYYY"),
+            ("2024-01-02 24:00", "YYYY-MM-DD HH:mm"),
+            ("2024-01-02 06:60", "YYYY-MM-DD HH:mm"),
+            ("", "DD/MM/YYYY"),
+        ] {
+            assert_eq!(
+                parse_gantt_datetime(value, format),
+                None,
+                "{value} as {format}"
+            );
+        }
+
+        // Plain ISO dates are accepted whatever the format, and a time just before midnight
+        // stays on its day.
+        assert_eq!(
+            parse_gantt_datetime("2024-01-02", "DD MMM YYYY HH:mm"),
+            parse_gantt_datetime("2024-01-02", "YYYY-MM-DD")
+        );
+        let almost_midnight =
+            parse_gantt_datetime("2024-01-02 23:59:59", "YYYY-MM-DD HH:mm:ss").unwrap();
+        assert_eq!(
+            format_gantt_day(almost_midnight, "YYYY-MM-DD HH:mm:ss"),
+            "2024-01-02 23:59:59"
+        );
+    }
+
+    #[test]
+    fn parses_mindmaps() {
+        let diagram = Diagram::parse(
+            "mindmap\n  root((Plan))\n    Build\n      parser[Parser]\n      ::icon(fa fa-code)\n      Layout (radial)\n    Ship)Release(\n",
+        )
+        .unwrap();
+        let DiagramKind::Mindmap(mindmap) = &diagram.kind else {
+            panic!("expected a mindmap");
+        };
+        assert_eq!(mindmap.nodes.len(), 5);
+        assert_eq!(mindmap.nodes[0].id, "root");
+        assert_eq!(mindmap.nodes[0].shape, MindmapShape::Circle);
+        assert_eq!(mindmap.nodes[0].children, vec![1, 4]);
+        assert_eq!(mindmap.nodes[2].id, "parser");
+        assert_eq!(mindmap.nodes[2].shape, MindmapShape::Square);
+        assert_eq!(mindmap.nodes[3].label, "Layout (radial)");
+        assert_eq!(mindmap.nodes[3].parent, Some(1));
+        assert_eq!(mindmap.nodes[4].shape, MindmapShape::Cloud);
+        assert_eq!(diagram.edges.len(), 4);
+
+        let positions = mindmap.radial_layout();
+        assert_eq!((positions[0].x, positions[0].y), (0.0, 0.0));
+        let distance = |point: &Point| (point.x * point.x + point.y * point.y).sqrt();
+        assert!(distance(&positions[2]) > distance(&positions[1]));
+        assert!((distance(&positions[1]) - distance(&positions[4])).abs() < 0.01);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"mindmap-node\"").count(), 5);
+        assert_eq!(svg.matches("class=\"mindmap-branch\"").count(), 4);
+
+        assert!(Diagram::parse("mindmap\n  A\n  B\n").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_mindmaps() {
+        let error = |source: &str| Diagram::parse(source).unwrap_err().to_string();
+        assert!(error("mindmap\n").contains("does not declare a root node"));
+        assert!(error("mindmap\n  ::icon(fa fa-book)\n").contains("does not declare a root"));
+        assert!(error("mindmap\n  root((Plan\n").contains("unterminated shape"));
+        assert!(error("mindmap\n  root\n    [x\n").contains("unterminated shape"));
+        assert!(error("mindmap\n  root\n    []\n").contains("empty label"));
+        assert!(error("mindmap\n    root\n  sibling\n").contains("only have one root"));
+
+        // Dedenting to a level between two ancestors attaches to the nearest shallower one, and
+        // a repeated id gets a generated one instead.
+        let diagram =
+            Diagram::parse("mindmap\n  root\n      deep[Deep]\n    middle\n    deep[Again]\n")
+                .unwrap();
+        let DiagramKind::Mindmap(mindmap) = &diagram.kind else {
+            panic!("expected a mindmap");
+        };
+        assert_eq!(mindmap.nodes[0].children, vec![1, 2, 3]);
+        assert_eq!(mindmap.nodes[3].id, "node3");
+        assert_eq!(mindmap.nodes[3].label, "Again");
+
+        // A lone root still lays out and renders.
+        let single = Diagram::parse("mindmap\n  Alone\n").unwrap();
+        let svg = single.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"mindmap-node\"").count(), 1);
+        assert!(!svg.contains("NaN"));
+    }
+
+    #[test]
+    fn parses_journey_diagrams() {
+        let diagram = Diagram::parse(
+            "journey\n  title My day\n  Wake up: 2\n  section Work\n    Make tea: 5: Me\n    Do work: 1: Me, Cat\n",
+        )
+        .unwrap();
+        let DiagramKind::Journey(journey) = &diagram.kind else {
+            panic!("expected a journey");
+        };
+        assert_eq!(journey.title.as_deref(), Some("My day"));
+        assert_eq!(journey.sections, vec!["Work".to_string()]);
+        assert_eq!(journey.actors, vec!["Me".to_string(), "Cat".to_string()]);
+        assert_eq!(journey.tasks.len(), 3);
+        assert_eq!(journey.tasks[0].section_index, None);
+        assert!(journey.tasks[0].actors.is_empty());
+        assert_eq!(journey.tasks[2].score, 1);
+        assert_eq!(journey.tasks[2].section_index, Some(0));
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"journey-task\"").count(), 3);
+        assert_eq!(svg.matches("class=\"journey-score\"").count(), 3);
+        assert_eq!(svg.matches("class=\"journey-section\"").count(), 1);
+
+        assert!(Diagram::parse("journey\n  section Work\n    Make tea: 6: Me\n").is_err());
+        assert!(Diagram::parse("journey\n  title Empty\n").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_journey_tasks() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("journey\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        for score in ["0", "6", "3.5", "high", ""] {
+            let message = error(&format!("  Make tea: {score}: Me\n"));
+            assert!(
+                message.contains("expected a whole number from 1 to 5"),
+                "{message}"
+            );
+        }
+        assert!(error("  Make tea\n").contains("expected 'Task: score: actors'"));
+        assert!(error("  : 3: Me\n").contains("missing a name"));
+        assert!(error("  section Work\n").contains("does not declare any tasks"));
+
+        // Empty sections are kept, and an actor listed twice on a task gets one dot.
+        let diagram =
+            Diagram::parse("journey\n  section Idle\n  section Work\n    Tea: 3: Cat, , Cat\n")
+                .unwrap();
+        let DiagramKind::Journey(journey) = &diagram.kind else {
+            panic!("expected a journey");
+        };
+        assert_eq!(journey.sections.len(), 2);
+        assert_eq!(journey.tasks[0].section_index, Some(1));
+        assert_eq!(journey.tasks[0].actors, vec!["Cat".to_string()]);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(!svg.contains("NaN"));
+    }
+
+    #[test]
+    fn applies_palettes_and_checks_contrast() {
+        let diagram = Diagram::parse(
+            "---\nconfig:\n  palette: okabe-ito\n---\ngraph TD\n  A[Start] --> B(Next)\n",
+        )
+        .unwrap();
+        assert_eq!(diagram.config.palette, Palette::OkabeIto);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("fill=\"#f5d999\""));
+        assert!(diagram.contrast_warnings(None).is_empty());
+
+        let mut overrides = LayoutOverrides::default();
+        overrides.node_styles.insert(
+            "B".to_string(),
+            NodeStyleOverride {
+                fill: Some("#777777".to_string()),
+                text: Some("#999999".to_string()),
+                ..Default::default()
+            },
+        );
+        let warnings = diagram.contrast_warnings(Some(&overrides));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].node_id, "B");
+        assert!(warnings[0].ratio < 2.0);
+        let suggested = Rgb::parse(&warnings[0].suggested_text).unwrap();
+        assert!(suggested.contrast_ratio(Rgb::parse("#777777").unwrap()) >= WCAG_AA_CONTRAST);
+
+        assert_eq!(Rgb::parse("#fff"), Rgb::parse("white"));
+        assert_eq!(readable_text_color("#f0e442"), "#1a202c");
+        assert_eq!(readable_text_color("#0072b2"), "#ffffff");
+        assert!(Diagram::parse("---\nconfig:\n  palette: rainbow\n---\ngraph TD\n  A\n").is_err());
+    }
+
+    #[test]
+    fn styles_edge_label_backgrounds() {
+        let source = "graph LR\n  A -->|go| B\n  B -->|back| A\n";
+        let diagram = Diagram::parse(source).unwrap();
+        let svg = diagram.render_svg("#0f172a", None).unwrap();
+        assert!(svg.contains("fill=\"#0f172a\" fill-opacity=\"0.96\""));
+        assert!(svg.contains("fill=\"#f7fafc\" font-size=\"13\""));
+
+        let themed = Diagram::parse(&format!(
+            "---\nconfig:\n  themeVariables:\n    edgeLabelBackground: '#fef3c7'\n    edgeLabelBorder: '#d97706'\n---\n{source}"
+        ))
+        .unwrap();
+        let mut overrides = LayoutOverrides::default();
+        overrides.edge_styles.insert(
+            "B --> A".to_string(),
+            EdgeStyleOverride {
+                label_fill: Some("none".to_string()),
+                ..Default::default()
+            },
+        );
+        let svg = themed.render_svg("white", Some(&overrides)).unwrap();
+        assert_eq!(svg.matches("fill=\"#fef3c7\"").count(), 1);
+        assert!(svg.contains("stroke=\"#d97706\" stroke-width=\"1\""));
+        assert!(svg.contains(
+            "stroke=\"white\" stroke-width=\"3\" stroke-linejoin=\"round\" paint-order=\"stroke\""
+        ));
+    }
+
+    #[test]
+    fn parses_timelines() {
+        let diagram = Diagram::parse(
+            "timeline\n  title Releases\n  2021 : Alpha\n  section Stable\n    2022 : 1.0 : 1.1\n         : 1.2\n    2023\n",
+        )
+        .unwrap();
+        let DiagramKind::Timeline(timeline) = &diagram.kind else {
+            panic!("expected a timeline");
+        };
+        assert_eq!(timeline.title.as_deref(), Some("Releases"));
+        assert_eq!(timeline.periods.len(), 3);
+        assert_eq!(timeline.periods[0].section_index, None);
+        assert_eq!(timeline.periods[1].events, vec!["1.0", "1.1", "1.2"]);
+        assert_eq!(timeline.periods[1].section_index, Some(0));
+        assert!(timeline.periods[2].events.is_empty());
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"timeline-period\"").count(), 3);
+        assert_eq!(svg.matches("class=\"timeline-event\"").count(), 4);
+
+        assert!(Diagram::parse("timeline\n  : Orphan event\n").is_err());
+        assert!(Diagram::parse("timeline\n  title Empty\n").is_err());
+    }
+
+    #[test]
+    fn keeps_colons_inside_timeline_periods_and_events() {
+        let error = |source: &str| Diagram::parse(source).unwrap_err().to_string();
+        assert!(error("timeline\n").contains("does not declare any time periods"));
+        assert!(error("timeline\n  section Empty\n").contains("any time periods"));
+        assert!(error("timeline\n  section S\n  : Orphan\n").contains("before any time period"));
+
+        // Colons inside a time or an event stay put; empty events are dropped.
+        let diagram =
+            Diagram::parse("timeline\n  10:30 : Standup : v1:rc :\n  11:00 : :\n").unwrap();
+        let DiagramKind::Timeline(timeline) = &diagram.kind else {
+            panic!("expected a timeline");
+        };
+        assert_eq!(timeline.periods[0].label, "10:30");
+        assert_eq!(timeline.periods[0].events, vec!["Standup", "v1:rc"]);
+        assert_eq!(timeline.periods[1].label, "11:00");
+        assert!(timeline.periods[1].events.is_empty());
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"timeline-event\"").count(), 2);
+    }
+
+    #[test]
+    fn renders_monochrome_patterns() {
+        let mut diagram =
+            Diagram::parse("graph LR\n  A[Start] -->|go| B{Check}\n  B -.-> C((Done))\n").unwrap();
+        let mut overrides = LayoutOverrides::default();
+        overrides.node_styles.insert(
+            "A".to_string(),
+            NodeStyleOverride {
+                fill: Some("#ff0000".to_string()),
+                ..Default::default()
+            },
+        );
+        diagram.config.monochrome = true;
+        let svg = diagram.render_svg("white", Some(&overrides)).unwrap();
+        assert!(svg.contains("<pattern id=\"oxdraw-mono-diagonal\""));
+        assert!(svg.contains("fill=\"url(#oxdraw-mono-diagonal)\""));
+        assert!(svg.contains("fill=\"url(#oxdraw-mono-dots)\""));
+        assert!(svg.contains("stroke-dasharray=\"8 6\""));
+        assert!(!svg.contains("#ff0000"));
+        assert!(!svg.contains(Palette::Default.node_fill(NodeShape::Diamond)));
+
+        let parsed =
+            Diagram::parse("---\nconfig:\n  monochrome: true\n---\ngraph TD\n  A --> B\n").unwrap();
+        assert!(parsed.config.monochrome);
+    }
+
+    #[test]
+    fn monochrome_prints_greys_on_white() {
+        let mut dark =
+            Diagram::parse("---\ntitle: Flow\nconfig:\n  theme: dark\n---\ngraph TD\n  A --> B\n")
+                .unwrap();
+        dark.config.monochrome = true;
+        let svg = dark
+            .render_svg(&dark.config.page_background(None), None)
+            .unwrap();
+        assert!(svg.contains("<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\" />"));
+        assert!(svg.contains("<text class=\"title\" x=\"150.0\" y=\"58.0\" fill=\"#000000\""));
+        assert!(!svg.contains("#0f172a"));
+
+        let mut pie =
+            Diagram::parse("pie title Pets\n    \"Dogs\" : 386\n    \"Cats\" : 85\n").unwrap();
+        pie.config.monochrome = true;
+        let svg = pie.render_svg("white", None).unwrap();
+        let paint = Regex::new(r#"(?:fill|stroke)="(#[0-9a-f]{6})""#).unwrap();
+        for color in paint.captures_iter(&svg) {
+            let Rgb { r, g, b } = Rgb::parse(&color[1]).unwrap();
+            assert!(r == g && g == b, "{} is not grey", &color[1]);
+        }
+
+        for grey in Palette::Grayscale.categorical().unwrap() {
+            let fill = Rgb::parse(grey).unwrap();
+            let text = Rgb::parse(readable_text_color(grey)).unwrap();
+            assert!(fill.contrast_ratio(text) >= WCAG_AA_CONTRAST, "{grey}");
+        }
+        let red = Rgb::parse("#c53030").unwrap();
+        let white = Rgb::parse("#ffffff").unwrap();
+        assert!((red.grayscale().contrast_ratio(white) - red.contrast_ratio(white)).abs() < 0.05);
+        assert_eq!(
+            grayscale_colors("<rect fill=\"#c53030\" /><text>Issue #c53030</text>"),
+            format!(
+                "<rect fill=\"{}\" /><text>Issue #c53030</text>",
+                red.grayscale().to_hex()
+            )
+        );
+    }
+
+    #[test]
+    fn parses_git_graphs() {
+        let diagram = Diagram::parse(
+            "gitGraph TB:\n  commit id: \"a\"\n  branch dev order: 2\n  commit id: \"b\" tag: \"v1\" type: HIGHLIGHT\n  branch fix\n  commit\n  checkout main\n  merge dev\n  cherry-pick id: \"c2\"\n",
+        )
+        .unwrap();
+        let DiagramKind::GitGraph(graph) = &diagram.kind else {
+            panic!("expected a git graph");
+        };
+        assert!(matches!(graph.direction, Direction::TopDown));
+        assert_eq!(graph.branches.len(), 3);
+        assert_eq!(graph.lanes(), vec![0, 1, 2]);
+        assert_eq!(graph.commits.len(), 5);
+        assert_eq!(graph.commits[1].tag.as_deref(), Some("v1"));
+        assert_eq!(graph.commits[1].kind, GitCommitKind::Highlight);
+        assert_eq!(graph.commits[2].id, "c2");
+        assert_eq!(graph.commits[2].branch, 2);
+        assert_eq!(graph.commits[3].kind, GitCommitKind::Merge);
+        assert_eq!(graph.commits[3].parents, vec![0, 1]);
+        assert_eq!(graph.commits[4].kind, GitCommitKind::CherryPick);
+        assert_eq!(graph.commits[4].parents, vec![3, 2]);
+        assert_eq!(diagram.edges.len(), 6);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"git-commit\"").count(), 5);
+        assert_eq!(svg.matches("class=\"git-edge\"").count(), 6);
+
+        assert!(Diagram::parse("gitGraph\n  checkout nope\n").is_err());
+        assert!(Diagram::parse("gitGraph\n  commit\n  branch dev\n  merge dev\n").is_err());
+        assert!(Diagram::parse("gitGraph\n  commit id: \"x\"\n  commit id: \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_git_graph_operations() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("gitGraph\n  commit id: \"a\"\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("  checkout nope\n").contains("branch 'nope' does not exist"));
+        assert!(error("  switch nope\n").contains("branch 'nope' does not exist"));
+        assert!(error("  merge nope\n").contains("branch 'nope' does not exist"));
+        assert!(error("  merge main\n").contains("into itself"));
+        assert!(error("  branch dev\n  checkout main\n  merge dev\n").contains("no new commits"));
+        assert!(error("  branch main\n").contains("already exists"));
+        assert!(error("  branch\n").contains("needs a branch name"));
+        assert!(error("  branch dev order: first\n").contains("invalid order 'first'"));
+        assert!(error("  commit type: BOLD\n").contains("unknown commit type 'BOLD'"));
+        assert!(error("  cherry-pick\n").contains("needs the id"));
+        assert!(error("  branch dev\n  cherry-pick id: \"zz\"\n").contains("'zz' does not exist"));
+        assert!(error("  cherry-pick id: \"a\"\n").contains("already on the current branch"));
+        assert!(error("  rebase dev\n").contains("unsupported gitGraph statement 'rebase'"));
+
+        let message = Diagram::parse("gitGraph RL:\n  commit\n")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("unsupported gitGraph orientation 'RL'"),
+            "{message}"
+        );
+        let message = Diagram::parse("gitGraph\n  branch dev\n")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("does not contain any commits"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn parses_quadrant_charts() {
+        let diagram = Diagram::parse(
+            "quadrantChart\n  title Campaigns\n  x-axis Low Reach --> High Reach\n  y-axis Low Engagement\n  quadrant-1 Expand\n  quadrant-3 Re-evaluate\n  Campaign A: [0.3, 0.6]\n  Campaign B : [ 0.9 , 0.1 ]\n",
+        )
+        .unwrap();
+        let DiagramKind::Quadrant(chart) = &diagram.kind else {
+            panic!("expected a quadrant chart");
+        };
+        assert_eq!(chart.title.as_deref(), Some("Campaigns"));
+        assert_eq!(
+            chart.x_axis,
+            (
+                Some("Low Reach".to_string()),
+                Some("High Reach".to_string())
+            )
+        );
+        assert_eq!(chart.y_axis, (Some("Low Engagement".to_string()), None));
+        assert_eq!(chart.quadrants[0].as_deref(), Some("Expand"));
+        assert_eq!(chart.quadrants[1], None);
+        assert_eq!(chart.quadrants[2].as_deref(), Some("Re-evaluate"));
+        assert_eq!(
+            chart.points[1],
+            QuadrantPoint {
+                label: "Campaign B".to_string(),
+                x: 0.9,
+                y: 0.1,
+            }
+        );
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"quadrant\"").count(), 4);
+        assert_eq!(svg.matches("class=\"quadrant-point\"").count(), 2);
+        assert!(svg.contains(">High Reach</text>"));
+
+        assert!(Diagram::parse("quadrantChart\n  A: [1.2, 0.5]\n").is_err());
+        assert!(Diagram::parse("quadrantChart\n  quadrant-5 Nope\n").is_err());
+    }
+
+    #[test]
+    fn rejects_quadrant_points_outside_the_chart() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("quadrantChart\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        for point in [
+            "A: [-0.1, 0.5]",
+            "A: [0.5, 1.01]",
+            "A: [NaN, 0.5]",
+            "A: [x, 0.5]",
+        ] {
+            let message = error(&format!("  {point}\n"));
+            assert!(
+                message.contains("expected a number from 0 to 1"),
+                "{message}"
+            );
+        }
+        for line in ["A: [0.5]", "A: 0.5, 0.5", ": [0.1, 0.2]", "A [0.1, 0.2]"] {
+            let message = error(&format!("  {line}\n"));
+            assert!(message.contains("expected a quadrant point"), "{message}");
+        }
+        assert!(error("  quadrant-0 Zero\n").contains("unknown quadrant 'quadrant-0'"));
+
+        // Points on the border are allowed, an axis may name only its high end, and a chart
+        // without points still draws its four quadrants.
+        let diagram =
+            Diagram::parse("quadrantChart\n  x-axis --> High\n  Corner: [0, 1]\n  Edge: [1, 0]\n")
+                .unwrap();
+        let DiagramKind::Quadrant(chart) = &diagram.kind else {
+            panic!("expected a quadrant chart");
+        };
+        assert_eq!(chart.x_axis, (None, Some("High".to_string())));
+        assert_eq!((chart.points[0].x, chart.points[0].y), (0.0, 1.0));
+        diagram.render_svg("white", None).unwrap();
+
+        let empty = Diagram::parse("quadrantChart\n  title Nothing yet\n").unwrap();
+        let svg = empty.render_svg("white", None).unwrap();
+        assert_eq!(svg.matches("class=\"quadrant\"").count(), 4);
+        assert_eq!(svg.matches("class=\"quadrant-point\"").count(), 0);
+    }
+
+    #[test]
+    fn aligns_rank_groups() {
+        let source = "graph TD\nA --> B\nB --> C\nA --> D\nD --> E\n%% OXDRAW RANK C D";
+        let diagram = Diagram::parse(source).unwrap();
+        assert_eq!(diagram.config.rank_groups, [["C", "D"]]);
+
+        let positions = diagram.layout(None).unwrap().final_positions;
+        assert_eq!(positions["C"].y, positions["D"].y);
+        assert!(positions["E"].y > positions["D"].y);
+        assert!(diagram.to_definition().ends_with("\n%% OXDRAW RANK C D\n"));
+
+        let (_, diagnostics) =
+            Diagram::parse_lenient("graph TD\nA --> B\n%% OXDRAW RANK A Z").unwrap();
+        assert_eq!(diagnostics[0].code, DiagnosticCode::UnknownNode);
+        assert!(Diagram::parse("graph TD\nA --> B\n%% OXDRAW RANK A").is_err());
+    }
+
+    #[test]
+    fn spreads_edges_over_distinct_ports() {
+        let diagram = Diagram::parse("graph TD\nA --> C\nB --> C\nC --> D").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let ends: Vec<Point> = ["A --> C", "B --> C"]
+            .iter()
+            .map(|id| *layout.final_routes[*id].last().unwrap())
+            .collect();
+        let top = layout.final_positions["C"].y - diagram.nodes["C"].height / 2.0;
+        assert!(ends.iter().all(|end| end.y <= top + 0.5));
+        assert!((ends[0].x - ends[1].x).abs() > 10.0);
+    }
+
+    #[test]
+    fn keeps_spread_ports_on_round_outlines_and_parallel_edges() {
+        let diagram =
+            Diagram::parse("graph TD\nA((Hub)) --> B\nA --> C\nA --> D\nB --> A").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let center = layout.final_positions["A"];
+        let radius = diagram.nodes["A"].width / 2.0;
+        let starts: Vec<Point> = ["A --> B", "A --> C", "A --> D"]
+            .iter()
+            .map(|id| layout.final_routes[*id][0])
+            .collect();
+        for (idx, start) in starts.iter().enumerate() {
+            let distance = ((start.x - center.x).powi(2) + (start.y - center.y).powi(2)).sqrt();
+            assert!((distance - radius).abs() < 1.5, "{start:?}");
+            assert!(
+                starts[..idx]
+                    .iter()
+                    .all(|other| !points_close(*other, *start))
+            );
+        }
+
+        let parallel = Diagram::parse("graph TD\nA --> B\nA -.-> B").unwrap();
+        let layout = parallel.layout(None).unwrap();
+        let ends: Vec<Point> = layout
+            .final_routes
+            .values()
+            .map(|route| *route.last().unwrap())
+            .collect();
+        assert_eq!(ends.len(), 2);
+        assert!((ends[0].x - ends[1].x).abs() > 10.0);
+
+        // A single edge keeps the centre of the side.
+        let single = Diagram::parse("graph TD\nA --> B").unwrap();
+        let layout = single.layout(None).unwrap();
+        let end = *layout.final_routes["A --> B"].last().unwrap();
+        assert!((end.x - layout.final_positions["B"].x).abs() < 0.5);
+    }
+
+    #[test]
+    fn routes_self_loops_beside_the_node() {
+        let diagram = Diagram::parse("graph TD\nA --> B\nB -->|again| B\nB -.-> B").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let right = layout.final_positions["B"].x + diagram.nodes["B"].width / 2.0;
+        let inner = &layout.final_routes["B --> B"];
+        let outer = &layout.final_routes["B -.-> B"];
+        assert_eq!(inner.len(), 4);
+        assert!(inner[1].x > right && outer[1].x > inner[1].x);
+
+        let edge = diagram
+            .edges
+            .iter()
+            .find(|edge| edge.label.is_some())
+            .unwrap();
+        let label = label_rect_for_route(edge, inner).unwrap();
+        assert!(label.min_x > inner[1].x);
+    }
+
+    #[test]
+    fn routes_self_loops_on_lone_round_and_overridden_nodes() {
+        // Left to right the loop leaves the top, even when the node has no other edges.
+        let diagram = Diagram::parse("graph LR\nA((Solo)) --> A").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let route = &layout.final_routes["A --> A"];
+        let center = layout.final_positions["A"];
+        let node = &diagram.nodes["A"];
+        assert_eq!(route.len(), 4);
+        assert!(route[1].y < center.y - node.height / 2.0);
+        assert!(route[0].x < route[3].x);
+        for end in [route[0], route[3]] {
+            let distance = ((end.x - center.x).powi(2) + (end.y - center.y).powi(2)).sqrt();
+            assert!((distance - node.width / 2.0).abs() < 1.5, "{end:?}");
+        }
+
+        // Saved waypoints win over the automatic loop.
+        let waypoint = Point {
+            x: center.x - 200.0,
+            y: center.y + 200.0,
+        };
+        let mut overrides = LayoutOverrides::default();
+        overrides.edges.insert(
+            "A --> A".to_string(),
+            EdgeOverride {
+                points: vec![waypoint],
+            },
+        );
+        let layout = diagram.layout(Some(&overrides)).unwrap();
+        assert!(
+            layout.final_routes["A --> A"]
+                .iter()
+                .any(|point| points_close(*point, waypoint))
+        );
+    }
+
+    #[test]
+    fn routes_long_edges_through_layer_lanes() {
+        let diagram = Diagram::parse("graph TD\nA --> B\nB --> C\nA -->|skip| C").unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let route = &layout.final_routes["A --> C"];
+        assert_eq!(route.len(), 4);
+
+        let b = &diagram.nodes["B"];
+        let bounds = node_rect(layout.final_positions["B"], b.width, b.height);
+        assert!(!route_intersects_rect(route, bounds));
+        let edge = diagram
+            .edges
+            .iter()
+            .find(|edge| edge.label.is_some())
+            .unwrap();
+        assert!(
+            !label_rect_for_route(edge, route)
+                .unwrap()
+                .intersects(&bounds)
+        );
+    }
+
+    #[test]
+    fn keeps_layer_placeholders_out_of_cyclic_and_sideways_layouts() {
+        for source in [
+            "graph TD\nA --> B\nB --> C\nC --> D\nA --> D\nD --> A",
+            "graph LR\nA --> B\nB --> C\nC --> D\nA --> D\nA --> C",
+        ] {
+            let diagram = Diagram::parse(source).unwrap();
+            let layout = diagram.layout(None).unwrap();
+            let mut placed: Vec<&String> = layout.final_positions.keys().collect();
+            let mut declared: Vec<&String> = diagram.nodes.keys().collect();
+            placed.sort();
+            declared.sort();
+            assert_eq!(placed, declared, "{source}");
+
+            let route = &layout.final_routes["A --> D"];
+            for id in ["B", "C"] {
+                let node = &diagram.nodes[id];
+                let bounds = node_rect(layout.final_positions[id], node.width, node.height);
+                assert!(!route_intersects_rect(route, bounds), "{source}: {id}");
+            }
+        }
+
+        let layer = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let link = |a: &str, b: &str| (a.to_string(), b.to_string());
+        let layers = [layer(&["A", "B"]), layer(&["C", "D"]), layer(&["E"])];
+        assert_eq!(
+            count_link_crossings(&layers, &[link("A", "D"), link("B", "C")]),
+            1
+        );
+        // Links that skip a layer or point at unknown ids are not counted.
+        assert_eq!(
+            count_link_crossings(&layers, &[link("A", "E"), link("B", "Z"), link("A", "C")]),
+            0
+        );
+        assert_eq!(count_link_crossings(&[], &[]), 0);
+    }
+
+    #[test]
+    fn compacts_layers_towards_neighbours() {
+        let diagram = Diagram::parse("graph TD\nA --> X\nB --> D\nC --> D").unwrap();
+        let positions = diagram.layout(None).unwrap().final_positions;
+        let between = (positions["B"].x + positions["C"].x) / 2.0;
+        assert!((positions["D"].x - between).abs() < 1.0);
+        assert!((positions["X"].x - positions["A"].x).abs() < 1.0);
+
+        assert_eq!(
+            non_decreasing_fit(&[1.0, 3.0, 2.0, 4.0]),
+            [1.0, 2.5, 2.5, 4.0]
+        );
+    }
+
+    #[test]
+    fn compacts_isolated_and_crowded_layers_without_overlap() {
+        for source in [
+            "graph TD\nA --> D\nB --> D\nC --> D\nX\nY",
+            "graph LR\nA --> B\nA --> C\nA --> D\nA --> E\nB --> F\nE --> F\nF --> A",
+            "graph TD\nSolo",
+        ] {
+            let diagram = Diagram::parse(source).unwrap();
+            let positions = diagram.layout(None).unwrap().final_positions;
+            let rects: Vec<(&String, Rect)> = diagram
+                .nodes
+                .iter()
+                .map(|(id, node)| (id, node_rect(positions[id], node.width, node.height)))
+                .collect();
+            for (idx, (id, rect)) in rects.iter().enumerate() {
+                assert!(rect.min_x.is_finite() && rect.min_y.is_finite(), "{source}");
+                for (other, other_rect) in &rects[idx + 1..] {
+                    assert!(!rect.intersects(other_rect), "{source}: {id} and {other}");
+                }
+            }
+        }
+
+        assert!(non_decreasing_fit(&[]).is_empty());
+        assert_eq!(non_decreasing_fit(&[5.0]), [5.0]);
+        assert_eq!(non_decreasing_fit(&[1.0, 2.0, 3.0]), [1.0, 2.0, 3.0]);
+        assert_eq!(non_decreasing_fit(&[3.0, 2.0, 1.0]), [2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn moves_overlapping_edge_labels_apart() {
+        let diagram = Diagram::parse("graph LR\n  A -->|first| B\n  C -->|second| D").unwrap();
+        let point = |x: f32, y: f32| Point { x, y };
+        let positions: HashMap<String, Point> = [
+            ("A", point(-200.0, 0.0)),
+            ("B", point(400.0, 0.0)),
+            ("C", point(100.0, -300.0)),
+            ("D", point(100.0, 300.0)),
+        ]
+        .into_iter()
+        .map(|(id, p)| (id.to_string(), p))
+        .collect();
+        // Both routes share a midpoint, so both labels start out in the same spot.
+        let routes: HashMap<String, Vec<Point>> = [
+            ("A --> B", vec![point(-130.0, 0.0), point(330.0, 0.0)]),
+            ("C --> D", vec![point(100.0, -275.0), point(100.0, 275.0)]),
+        ]
+        .into_iter()
+        .map(|(id, route)| (id.to_string(), route))
+        .collect();
+
+        let geometry = align_geometry(
+            &positions,
+            &routes,
+            &diagram.edges,
+            &diagram.subgraphs,
+            &diagram.nodes,
+            LAYOUT_MARGIN,
+            None,
+        )
+        .unwrap();
+        let rect = |id: &str, text: &str| {
+            let (width, height) = measure_label_box(&[text.to_string()]);
+            node_rect(geometry.labels[id], width, height)
+        };
+        let first = rect("A --> B", "first");
+        let second = rect("C --> D", "second");
+        assert!(!first.intersects(&second));
+        // The second label slides along its own vertical route.
+        assert!((geometry.labels["C --> D"].x - (100.0 + geometry.shift.x)).abs() < 0.5);
+    }
+
+    #[test]
+    fn places_edge_labels_on_degenerate_and_missing_routes() {
+        let point = |x: f32, y: f32| Point { x, y };
+        assert!(point_along_route(&[], 0.0).is_none());
+        assert!(point_along_route(&[point(1.0, 1.0)], 0.0).is_none());
+        // Repeated points are skipped rather than dividing by a zero-length segment.
+        let repeated = [point(0.0, 0.0), point(0.0, 0.0), point(10.0, 0.0)];
+        assert!(points_close(
+            point_along_route(&repeated, 4.0).unwrap(),
+            point(4.0, 0.0)
+        ));
+        assert!(point_along_route(&repeated, 11.0).is_none());
+
+        // Too short to slide along, so only the sideways steps remain.
+        let candidates = label_candidates(&[point(0.0, 0.0)], point(0.0, 0.0), 40.0, 20.0);
+        assert_eq!(candidates.len(), 8);
+        assert!(
+            candidates
+                .iter()
+                .all(|c| !points_close(*c, point(0.0, 0.0)))
+        );
+
+        let diagram =
+            Diagram::parse("graph LR\n  A -->|same| B\n  C -->|same| D\n  E -->|lost| F").unwrap();
+        let positions: HashMap<String, Point> = [
+            ("A", point(-300.0, 0.0)),
+            ("B", point(300.0, 0.0)),
+            ("C", point(-300.0, 200.0)),
+            ("D", point(300.0, 200.0)),
+            ("E", point(-300.0, 400.0)),
+            ("F", point(300.0, 400.0)),
+        ]
+        .into_iter()
+        .map(|(id, p)| (id.to_string(), p))
+        .collect();
+        // Both routes collapse onto one spot; the third edge has no route at all.
+        let routes: HashMap<String, Vec<Point>> = ["A --> B", "C --> D"]
+            .into_iter()
+            .map(|id| (id.to_string(), vec![point(0.0, 100.0), point(0.0, 100.0)]))
+            .collect();
+        let centers = place_edge_labels(&diagram.edges, &routes, &positions, &diagram.nodes);
+        assert_eq!(centers.len(), 2);
+        let (width, height) = measure_label_box(&["same".to_string()]);
+        let first = node_rect(centers["A --> B"], width, height);
+        let second = node_rect(centers["C --> D"], width, height);
+        assert!(!first.intersects(&second));
+    }
+
+    #[test]
+    fn grid_router_handles_open_buried_and_offset_endpoints() {
+        let rect = |min_x: f32, min_y: f32, max_x: f32, max_y: f32| Rect {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+        };
+        let from = Point { x: 0.0, y: 0.0 };
+        let from_rect = rect(-20.0, -20.0, 20.0, 20.0);
+
+        // Nothing in the way: a single straight run between the centres.
+        let to = Point { x: 400.0, y: 0.0 };
+        let to_rect = rect(380.0, -20.0, 420.0, 20.0);
+        let route = grid_route(from, from_rect, to, to_rect, &[]).unwrap();
+        assert_eq!(route.len(), 2);
+        assert!(points_close(route[0], from) && points_close(route[1], to));
+
+        // Diagonal neighbours are joined with a single bend.
+        let to = Point { x: 300.0, y: 300.0 };
+        let to_rect = rect(280.0, 280.0, 320.0, 320.0);
+        let route = grid_route(from, from_rect, to, to_rect, &[]).unwrap();
+        assert_eq!(route.len(), 3);
+        for segment in route.windows(2) {
+            assert!(segment[0].x == segment[1].x || segment[0].y == segment[1].y);
+        }
+
+        // A start buried under an obstacle has no door to leave through.
+        let buried = [rect(-100.0, -100.0, 100.0, 100.0)];
+        assert!(grid_route(from, from_rect, to, to_rect, &buried).is_none());
+    }
+
+    #[test]
+    fn grid_router_finds_a_way_around_obstacles() {
+        let rect = |min_x: f32, min_y: f32, max_x: f32, max_y: f32| Rect {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+        };
+        let from = Point { x: 0.0, y: 0.0 };
+        let to = Point { x: 400.0, y: 0.0 };
+        // A wall between the endpoints with a lid over its top, leaving only the way below.
+        let obstacles = [
+            rect(150.0, -200.0, 250.0, 200.0),
+            rect(-100.0, -300.0, 500.0, -190.0),
+        ];
+
+        let from_rect = rect(-20.0, -20.0, 20.0, 20.0);
+        let to_rect = rect(380.0, -20.0, 420.0, 20.0);
+
+        let route = grid_route(from, from_rect, to, to_rect, &obstacles).unwrap();
+        assert!(points_close(route[0], from));
+        assert!(points_close(route[route.len() - 1], to));
+        for segment in route.windows(2) {
+            assert!(segment[0].x == segment[1].x || segment[0].y == segment[1].y);
+            assert!(
+                !obstacles
+                    .iter()
+                    .any(|rect| rect.intersects_segment(segment[0], segment[1]))
+            );
+        }
+        assert!(route.iter().any(|point| point.y > 200.0));
+        // Down out of the start node, across below the wall and up into the target.
+        assert_eq!(route.len(), 4);
+        assert_eq!(route[1].x, from.x);
+        assert_eq!(route[2].x, to.x);
+
+        let boxed_in = [
+            rect(320.0, -100.0, 480.0, -50.0),
+            rect(320.0, 50.0, 480.0, 100.0),
+            rect(320.0, -100.0, 340.0, 100.0),
+            rect(460.0, -100.0, 480.0, 100.0),
+        ];
+        assert!(grid_route(from, from_rect, to, to_rect, &boxed_in).is_none());
+
+        // Too many obstacles for the search budget: give up instead of searching.
+        let crowded: Vec<Rect> = (0..300)
+            .map(|i| {
+                let offset = i as f32 * 30.0;
+                rect(offset, offset + 300.0, offset + 10.0, offset + 310.0)
+            })
+            .collect();
+        assert!(grid_route(from, from_rect, to, to_rect, &crowded).is_none());
+    }
+
+    #[test]
+    fn output_is_identical_across_runs() {
+        let source = "graph TD\n  subgraph S\n    B\n    C\n  end\n  A -->|go| B\n  A --> C\n  A --> D\n  B --> E\n  C --> E\n  D -->|back| A\n  E --> E\n  A --> E";
+        let render = || {
+            Diagram::parse(source)
+                .unwrap()
+                .render_svg("white", None)
+                .unwrap()
+        };
+        let first = render();
+        for _ in 0..5 {
+            assert_eq!(render(), first);
+        }
+
+        // The layout block is saved into the source, so its keys must not shuffle either.
+        let mut overrides = LayoutOverrides::default();
+        for id in ["Z", "A", "M"] {
+            overrides.nodes.insert(id.into(), Point { x: 0.0, y: 0.0 });
+        }
+        let json = serde_json::to_string(&overrides).unwrap();
+        assert!(json.find("\"A\"") < json.find("\"M\"") && json.find("\"M\"") < json.find("\"Z\""));
+    }
+
+    #[test]
+    fn swimlanes_span_the_diagram_and_hold_their_members() {
+        let diagram = Diagram::parse(
+            "---\nconfig:\n  layout: swimlanes\n---\ngraph LR\nsubgraph Sales\nA --> B\nend\nsubgraph Ops\nC --> D\nC --> E\nend\nA --> C\nB --> D\n",
+        )
+        .unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let geometry = align_geometry(
+            &layout.final_positions,
+            &layout.final_routes,
+            &diagram.edges,
+            &diagram.subgraphs,
+            &diagram.nodes,
+            LAYOUT_MARGIN,
+            diagram.swimlane_direction(),
+        )
+        .unwrap();
+        let lane = |id: &str| geometry.subgraphs.iter().find(|sg| sg.id == id).unwrap();
+        let (sales, ops) = (lane("Sales"), lane("Ops"));
+        assert_eq!((sales.x, sales.width), (ops.x, ops.width));
+        assert!((sales.y + sales.height - ops.y).abs() < 0.1);
+
+        for (lane, members) in [(sales, ["A", "B"].as_slice()), (ops, &["C", "D", "E"])] {
+            for id in members {
+                let point = geometry.positions[*id];
+                assert!(point.y > lane.y && point.y < lane.y + lane.height, "{id}");
+            }
+        }
+        assert!(geometry.positions["A"].x < geometry.positions["C"].x);
+    }
+
+    #[test]
+    fn tree_layouts_pack_strict_trees_and_skip_other_graphs() {
+        let source =
+            "graph TD\nR --> A\nR --> B\nA --> A1\nA --> A2\nB --> B1\nB --> B2\nB --> B3\n";
+        let area = |diagram: &Diagram| {
+            let positions = diagram.layout(None).unwrap().final_positions;
+            let bounds = group_bounds(
+                &positions.keys().cloned().collect(),
+                &positions,
+                &diagram.nodes,
+            )
+            .unwrap();
+            (bounds.max_x - bounds.min_x) * (bounds.max_y - bounds.min_y)
+        };
+        let layered = Diagram::parse(source).unwrap();
+        let mut tree = layered.clone();
+        tree.config.layout = LayoutEngine::Tree;
+        assert!(area(&tree) < area(&layered));
+        let positions = tree.layout(None).unwrap().final_positions;
+        assert!((positions["R"].x - (positions["A"].x + positions["B"].x) / 2.0).abs() < 0.1);
+        assert!((positions["B"].x - positions["B2"].x).abs() < 0.1);
+
+        let mut radial = layered.clone();
+        radial.config.layout = LayoutEngine::Radial;
+        let positions = radial.layout(None).unwrap().final_positions;
+        let distance = |id: &str| {
+            let (a, b) = (positions["R"], positions[id]);
+            (a.x - b.x).hypot(a.y - b.y)
+        };
+        assert!((distance("A") - distance("B")).abs() < 0.1);
+        assert!((distance("A1") - 2.0 * distance("A")).abs() < 0.1);
+
+        let mut shared = Diagram::parse("graph TD\nA --> C\nB --> C\n").unwrap();
+        let layered = shared.layout(None).unwrap().final_positions;
+        shared.config.layout = LayoutEngine::Tree;
+        let positions = shared.layout(None).unwrap().final_positions;
+        assert!(
+            ["A", "B", "C"]
+                .iter()
+                .all(|id| points_close(positions[*id], layered[*id]))
+        );
+    }
+
+    #[test]
+    fn collapsed_subgraphs_become_summary_nodes() {
+        let mut diagram = Diagram::parse(
+            "graph TD\nStart --> A\nsubgraph Outer[Outer work]\nA --> B\nsubgraph Inner\nC\nend\nB --> C\nend\nC --> End\nStart --> B\n",
+        )
+        .unwrap();
+        diagram.apply_collapsed(&BTreeSet::from(["Outer".to_string(), "Inner".to_string()]));
+
+        assert_eq!(diagram.order, ["Start", "Outer", "End"]);
+        assert_eq!(diagram.nodes["Outer"].label, "Outer work");
+        assert!(diagram.subgraphs.is_empty());
+        let ids: Vec<_> = diagram.edges.iter().map(edge_identifier).collect();
+        assert_eq!(
+            ids,
+            ["Start --> Outer", "Outer --> End", "Start --> Outer #2"]
+        );
+        diagram.layout(None).unwrap();
+
+        let mut inner_only = Diagram::parse(
+            "graph TD\nsubgraph Outer\nA --> B\nsubgraph Inner\nC --> D\nend\nend\nB --> C\n",
+        )
+        .unwrap();
+        inner_only.apply_collapsed(&BTreeSet::from(["Inner".to_string()]));
+        assert_eq!(inner_only.subgraphs[0].nodes, ["A", "B", "Inner"]);
+        assert!(inner_only.subgraphs[0].children.is_empty());
+        assert_eq!(inner_only.node_membership["Inner"], ["Outer"]);
+        assert_eq!(edge_identifier(&inner_only.edges[1]), "B --> Inner");
+    }
+
+    #[test]
+    fn breaks_cycles_before_leveling() {
+        let diagram =
+            Diagram::parse("graph TD\nA --> B\nD --> E\nB --> C\nC --> D\nE --> B\nE --> E\n")
+                .unwrap();
+        let back: Vec<_> = diagram
+            .back_edges()
+            .into_iter()
+            .map(|idx| edge_identifier(&diagram.edges[idx]))
+            .collect();
+        assert_eq!(back, ["E --> B"]);
+
+        let layout = diagram.layout(None).unwrap();
+        let y = |id: &str| layout.final_positions[id].y;
+        for (upper, lower) in [("A", "B"), ("B", "C"), ("C", "D"), ("D", "E")] {
+            assert!(y(upper) < y(lower), "{upper} should sit above {lower}");
+        }
+        let route = &layout.final_routes["E --> B"];
+        let end = route[route.len() - 1];
+        assert!((end.y - y("B")).abs() < y("C") - y("B"));
+        assert!(route[0].y > y("D"));
+    }
+
+    #[test]
+    fn bundles_edges_converging_on_a_busy_node() {
+        let source = "---\nconfig:\n  edgeBundling: true\n---\ngraph TD\nA --> T\nB --> T\nC --> T\nD --> T\nT --> E\n";
+        let diagram = Diagram::parse(source).unwrap();
+        assert!(diagram.config.edge_bundling);
+
+        let layout = diagram.layout(None).unwrap();
+        let target = layout.final_positions["T"];
+        let trunk_y = target.y - NODE_HEIGHT / 2.0 - EDGE_BUNDLE_SPLIT;
+        let mut ends = Vec::new();
+        for from in ["A", "B", "C", "D"] {
+            let route = &layout.final_routes[&format!("{from} --> T")];
+            assert!(
+                route.iter().any(
+                    |point| (point.x - target.x).abs() < 0.5 && (point.y - trunk_y).abs() < 0.5
+                ),
+                "{from} should join the trunk: {route:?}"
+            );
+            ends.push(route[route.len() - 1].x);
+        }
+        ends.dedup_by(|a, b| (*a - *b).abs() < 1.0);
+        assert_eq!(ends.len(), 4);
+
+        let plain = Diagram::parse(source.replace("true", "false").as_str()).unwrap();
+        let layout = plain.layout(None).unwrap();
+        assert_eq!(layout.final_routes["A --> T"].len(), 2);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn renders_with_built_in_and_custom_themes() {
+        let source = "---\nconfig:\n  theme: dark\n---\ngraph TD\nA[Build] --> B{Ship?}\n";
+        let diagram = Diagram::parse(source).unwrap();
+        let theme = diagram.config.resolved_theme();
+        assert_eq!(theme.name, "dark");
+        let background = diagram.config.page_background(None);
+        assert_eq!(background, "#0f172a");
+        let svg = diagram.render_svg(&background, None).unwrap();
+        assert!(svg.contains("fill=\"#334155\" stroke=\"#94a3b8\""));
+        assert!(svg.contains("stroke=\"#cbd5e1\""));
+        assert_eq!(diagram.config.page_background(Some("white")), "white");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("brand.toml");
+        std::fs::write(
+            &path,
+            "extends = \"forest\"\nedge = \"#ff0000\"\nfontFamily = \"Georgia, serif\"\n\n[nodeFills]\ndiamond = \"#00ff00\"\n",
+        )
+        .unwrap();
+        let custom = Theme::resolve(path.to_str().unwrap()).unwrap();
+        assert_eq!(custom.name, "brand");
+        assert_eq!(custom.edge, "#ff0000");
+        assert_eq!(custom.node_stroke, "#13540c");
+        assert_eq!(
+            custom.node_fill(NodeShape::Diamond, Palette::Default),
+            "#00ff00"
+        );
+        assert_eq!(
+            custom.node_fill(NodeShape::Stadium, Palette::Default),
+            "#d9f0c2"
+        );
+        assert_eq!(
+            custom.node_fill(NodeShape::Stadium, Palette::Tol),
+            "#99ddff"
+        );
+
+        let mut themed = diagram.clone();
+        themed.config.custom_theme = Some(custom);
+        let svg = themed.render_svg("white", None).unwrap();
+        assert!(svg.contains("font-family=\"Georgia, serif\""));
+        assert!(svg.contains("fill=\"#00ff00\""));
+
+        let json = dir.path().join("broken.json");
+        std::fs::write(&json, "{\"extends\": \"sepia\"}").unwrap();
+        assert!(Theme::resolve(json.to_str().unwrap()).is_err());
+        assert!(Theme::resolve("sepia").is_err());
+    }
+
+    #[test]
+    fn animates_edges_in_svg_but_not_raster_output() {
+        let still = Diagram::parse("graph LR\nA --> B\nB --> C\n").unwrap();
+        let animated =
+            Diagram::parse("---\nconfig:\n  animateEdges: true\n---\ngraph LR\nA --> B\nB --> C\n")
+                .unwrap();
+        assert!(animated.config.animate_edges);
+        assert!(
+            !still
+                .render_svg("white", None)
+                .unwrap()
+                .contains("oxdraw-edge-flow")
+        );
+
+        let mut overrides = LayoutOverrides::default();
+        overrides.edge_styles.insert(
+            "B --> C".into(),
+            EdgeStyleOverride {
+                animated: Some(false),
+                ..EdgeStyleOverride::default()
+            },
+        );
+        let svg = animated.render_svg("white", Some(&overrides)).unwrap();
+        assert_eq!(svg.matches(EDGE_FLOW_STYLE).count(), 1);
+        assert!(svg.contains("<g class=\"edge edge-solid edge-animated\" data-id=\"A --&gt; B\""));
+        assert!(svg.contains("<g class=\"edge edge-solid\" data-id=\"B --&gt; C\""));
+
+        overrides.edge_styles.insert(
+            "A --> B".into(),
+            EdgeStyleOverride {
+                animated: Some(true),
+                ..EdgeStyleOverride::default()
+            },
+        );
+        assert!(
+            still
+                .render_svg("white", Some(&overrides))
+                .unwrap()
+                .contains("edge-animated")
+        );
+
+        assert_eq!(
+            animated.render_png("white", None, 1.0).unwrap(),
+            still.render_png("white", None, 1.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn overrides_node_border_width_and_dash() {
+        let diagram = Diagram::parse("graph LR\nA[Start] --> B((End))\n").unwrap();
+        let mut overrides = LayoutOverrides::default();
+        overrides.node_styles.insert(
+            "A".into(),
+            NodeStyleOverride {
+                stroke_width: Some(3.5),
+                border_style: Some(BorderStyle::Dashed),
+                ..NodeStyleOverride::default()
+            },
+        );
+        overrides.node_styles.insert(
+            "B".into(),
+            NodeStyleOverride {
+                border_style: Some(BorderStyle::Dotted),
+                ..NodeStyleOverride::default()
+            },
+        );
+        let svg = diagram.render_svg("white", Some(&overrides)).unwrap();
+        assert!(svg.contains("rx=\"8\" ry=\"8\" fill=\"#fde68a\" stroke=\"#2d3748\" stroke-width=\"3.5\" stroke-dasharray=\"8 6\""));
+        assert!(
+            svg.contains("<ellipse") && svg.contains("stroke-width=\"2\" stroke-dasharray=\"2 4\"")
+        );
+
+        let json = serde_json::to_string(&overrides.node_styles["A"]).unwrap();
+        assert_eq!(json, r#"{"stroke_width":3.5,"border_style":"dashed"}"#);
+    }
+
+    #[test]
+    fn describes_the_svg_for_screen_readers() {
+        let source = "graph LR\naccTitle: Checkout flow\naccDescr {\n  Cart leads to\n  payment\n}\nA[Cart] --> B[Pay & go]\n";
+        let diagram = Diagram::parse(source).unwrap();
+        assert_eq!(diagram.config.acc_title.as_deref(), Some("Checkout flow"));
+        assert_eq!(
+            diagram.config.acc_descr.as_deref(),
+            Some("Cart leads to\npayment")
+        );
+        assert_eq!(diagram.nodes.len(), 2);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(
+            " role=\"img\" aria-labelledby=\"oxdraw-title\" aria-describedby=\"oxdraw-desc\">\n  <title id=\"oxdraw-title\">Checkout flow</title>\n  <desc id=\"oxdraw-desc\">Cart leads to\npayment</desc>\n"
+        ));
+        assert!(svg.contains("data-node-id=\"B\">\n    <title>Pay &amp; go</title>\n"));
+
+        let reparsed = Diagram::parse(&diagram.to_definition()).unwrap();
+        assert_eq!(reparsed.config.acc_title, diagram.config.acc_title);
+        assert_eq!(reparsed.config.acc_descr, diagram.config.acc_descr);
+
+        // Every diagram kind is described, with `title` standing in for a missing `accTitle`.
+        let pie =
+            Diagram::parse("---\ntitle: Pets\n---\npie\naccDescr: Share of pets\n\"Dogs\" : 3\n")
+                .unwrap();
+        let svg = pie.render_svg("white", None).unwrap();
+        assert!(svg.contains("<title id=\"oxdraw-title\">Pets</title>\n  <desc id=\"oxdraw-desc\">Share of pets</desc>"));
+        let bare = Diagram::parse("graph LR\nA --> B\n").unwrap();
+        let svg = bare.render_svg("white", None).unwrap();
+        assert!(svg.contains(" role=\"img\">") && !svg.contains("oxdraw-title"));
+    }
+
+    #[test]
+    fn wraps_linked_nodes_in_anchors_with_data_attributes() {
+        let source = "graph LR\nA --> B\nB --> C\nclick A href \"https://example.com/a?x=1&y=2\" \"Open A\" _blank\nclick B \"docs/b.html\"\nclick C callback\n";
+        let diagram = Diagram::parse(source).unwrap();
+        assert_eq!(diagram.config.links.len(), 2);
+        assert_eq!(
+            diagram.config.links["A"],
+            NodeLink {
+                href: "https://example.com/a?x=1&y=2".into(),
+                tooltip: Some("Open A".into()),
+                target: Some("_blank".into()),
+            }
+        );
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("  <a href=\"https://example.com/a?x=1&amp;y=2\" xlink:href=\"https://example.com/a?x=1&amp;y=2\" target=\"_blank\">\n  <title>Open A</title>\n  <g class=\"node node-rectangle\" data-id=\"A\" data-node-id=\"A\">"));
+        assert!(svg.contains("<a href=\"docs/b.html\" xlink:href=\"docs/b.html\">\n  <g class=\"node node-rectangle\" data-id=\"B\""));
+        assert!(svg.contains("<g class=\"edge edge-solid\" data-id=\"A --&gt; B\" data-edge-id=\"A --&gt; B\" data-from=\"A\" data-to=\"B\">"));
+        assert_eq!(svg.matches("<a ").count(), svg.matches("</a>").count());
+
+        let definition = diagram.to_definition();
+        assert!(definition.contains(
+            "click A href \"https://example.com/a?x=1&y=2\" \"Open A\" _blank\nclick B href \"docs/b.html\"\n"
+        ));
+        assert_eq!(
+            Diagram::parse(&definition).unwrap().config.links,
+            diagram.config.links
+        );
+
+        let mut trimmed = diagram.clone();
+        trimmed.remove_node("A");
+        assert!(!trimmed.config.links.contains_key("A"));
+
+        assert!(Diagram::parse("graph LR\nA --> B\nclick A \"javascript:alert(1)\"\n").is_err());
+        let (_, diagnostics) =
+            Diagram::parse_lenient("graph LR\nA --> B\nclick Z \"https://example.com\"\n").unwrap();
+        assert_eq!(diagnostics[0].code, DiagnosticCode::UnknownNode);
+    }
+
+    #[test]
+    fn emits_css_classes_and_embedded_stylesheets() {
+        let source = "---\nconfig:\n  themeCSS: '.node-diamond polygon { fill: gold; }'\n---\ngraph TD\nsubgraph S\nA{Ship?} -.-> B\nend\n";
+        let mut diagram = Diagram::parse(source).unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("<g class=\"node node-diamond\" data-id=\"A\""));
+        assert!(svg.contains("<g class=\"node node-rectangle\" data-id=\"B\""));
+        assert!(svg.contains("<g class=\"edge edge-dashed\" data-id=\"A -.-&gt; B\""));
+        assert!(svg.contains("<g class=\"subgraph\" data-id=\"S\""));
+        assert!(
+            svg.contains(
+                "  <style><![CDATA[\n.node-diamond polygon { fill: gold; }\n]]></style>\n"
+            )
+        );
+
+        diagram.config.theme_css = None;
+        diagram.config.stylesheet = Some("https://example.com/diagram.css".to_string());
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("@import url(\"https://example.com/diagram.css\");"));
+        assert!(diagram.render_png("white", None, 1.0).is_ok());
+
+        diagram.config.stylesheet = None;
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(!svg.contains("<style>"));
+    }
+
+    #[test]
+    fn embeds_a_subset_of_the_label_font() {
+        let mut diagram =
+            Diagram::parse("---\nconfig:\n  embedFont: true\n---\ngraph LR\nA[Hi] -->|go| B\n")
+                .unwrap();
+        assert!(diagram.config.embed_font);
+        let Ok(svg) = diagram.render_svg("white", None) else {
+            // No system font to embed in this environment.
+            return;
+        };
+        assert!(svg.contains("font-family=\"oxdraw-embedded, Inter, system-ui, sans-serif\""));
+        let start = svg.find("base64,").unwrap() + 7;
+        let end = start + svg[start..].find(')').unwrap();
+        let font = BASE64_STANDARD.decode(&svg[start..end]).unwrap();
+        let face = ttf_parser::Face::parse(&font, 0).unwrap();
+        let outlined = |ch: char| {
+            let glyph = face.glyph_index(ch).unwrap();
+            face.glyph_bounding_box(glyph).is_some()
+        };
+        assert!(outlined('H') && outlined('g') && outlined('B'));
+        assert!(!outlined('Z'));
+        assert!(diagram.render_png("white", None, 1.0).is_ok());
+
+        diagram.config.embed_font = false;
+        assert!(
+            !diagram
+                .render_svg("white", None)
+                .unwrap()
+                .contains("@font-face")
+        );
+    }
+
+    #[test]
+    fn custom_fonts_reach_svg_and_raster_output() {
+        let mut diagram =
+            Diagram::parse("---\nconfig:\n  fontFamily: Acme Sans\n---\ngraph LR\nA --> B\n")
+                .unwrap();
+        assert_eq!(diagram.config.font_family.as_deref(), Some("Acme Sans"));
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("font-family=\"Acme Sans, Inter, system-ui, sans-serif\""));
+        assert_eq!(diagram.missing_font_family().unwrap(), Some("Acme Sans"));
+
+        let missing = RenderOptions {
+            font_file: Some(PathBuf::from("/nonexistent/acme.ttf")),
+            ..RenderOptions::default()
+        };
+        assert!(diagram.apply_render_options(&missing).is_err());
+
+        let Some(face) = embedded_font_face("AB".chars()) else {
+            // No system font to copy into a font file in this environment.
+            return;
+        };
+        let start = face.find("base64,").unwrap() + 7;
+        let end = start + face[start..].find(')').unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("brand.ttf");
+        std::fs::write(&path, BASE64_STANDARD.decode(&face[start..end]).unwrap()).unwrap();
+        diagram
+            .apply_render_options(&RenderOptions {
+                font_file: Some(path.clone()),
+                ..RenderOptions::default()
+            })
+            .unwrap();
+        let family = font_file_family(&path).unwrap();
+        assert_eq!(diagram.config.font_family.as_deref(), Some(family.as_str()));
+        assert_eq!(diagram.config.font_files, [path]);
+        assert_eq!(diagram.missing_font_family().unwrap(), None);
+        assert!(diagram.render_png("white", None, 1.0).is_ok());
+    }
+
+    #[test]
+    fn minified_svg_is_smaller_and_renders_the_same() {
+        let diagram = Diagram::parse(
+            "graph TD\nA[Start] -->|go| B{Check}\nB --> C[Done]\nB -.-> D((Retry))\nsubgraph Loop\nD\nend\n",
+        )
+        .unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        let minified = minify_svg(&svg, 1);
+        assert!(minified.len() * 10 < svg.len() * 9);
+        assert!(minified.starts_with("<svg "));
+        assert!(!minified.contains(">\n"));
+        assert!(
+            minified.contains("<style>.o0{fill:white}") && minified.contains("{stroke:#2d3748}")
+        );
+        assert!(!minified.contains(".0\""));
+        assert!(minified.contains(">Start</tspan>") || minified.contains(">Start</text>"));
+        assert_eq!(
+            rasterize_svg(&minified, 1.0).unwrap(),
+            rasterize_svg(&svg, 1.0).unwrap()
+        );
+
+        let whole = minify_svg(&svg, 0);
+        assert!(whole.len() < minified.len());
+        assert!(
+            !Regex::new(r#" (?:x|y|width|height)="-?\d+\.\d"#)
+                .unwrap()
+                .is_match(&whole)
+        );
+    }
 
-        if trimmed.starts_with("[\\") && trimmed.ends_with("/]") && trimmed.len() >= 4 {
-            let inner = trimmed[2..trimmed.len() - 2].trim();
-            return Some((inner.to_string(), NodeShape::TrapezoidAlt));
-        }
+    #[test]
+    fn wide_characters_get_full_width_label_boxes() {
+        assert_eq!(display_width("处理中"), 6);
+        assert_eq!(display_width("ok 👍"), 5);
+        assert_eq!(
+            display_width("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}"),
+            2
+        );
 
-        if trimmed.starts_with('(') && trimmed.ends_with(')') && trimmed.len() >= 2 {
-            let mut inner = trimmed[1..trimmed.len() - 1].trim().to_string();
-            if inner.starts_with('[') && inner.ends_with(']') && inner.len() >= 2 {
-                inner = inner[1..inner.len() - 1].trim().to_string();
-            }
-            return Some((inner, NodeShape::Stadium));
-        }
+        let cjk = ["处理中".to_string()];
+        let (width, _) = measure_label_box(&cjk);
+        assert!(width >= 3.0 * EDGE_LABEL_FONT_SIZE + EDGE_LABEL_HORIZONTAL_PADDING);
+        assert!(width > measure_label_box(&["abc".to_string()]).0);
+
+        let text = text_width("接收多模态输入", NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH);
+        assert!(text >= 7.0 * NODE_TEXT_FONT_SIZE);
+        let family = text_width(
+            "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}",
+            NODE_TEXT_FONT_SIZE,
+            NODE_TEXT_CHAR_WIDTH,
+        );
+        assert!(family <= 2.0 * NODE_TEXT_FONT_SIZE);
+    }
 
-        if trimmed.starts_with('[') && trimmed.ends_with(']') && trimmed.len() >= 2 {
-            let inner = trimmed[1..trimmed.len() - 1].trim();
-            return Some((inner.to_string(), NodeShape::Rectangle));
-        }
+    #[test]
+    fn transparent_backgrounds_leave_the_page_unpainted() {
+        let diagram = Diagram::parse("graph LR\nA -->|go| B\n").unwrap();
+        let svg = diagram.render_svg("transparent", None).unwrap();
+        assert!(svg.contains("<rect width=\"100%\" height=\"100%\" fill=\"none\" />"));
+        assert!(!svg.contains("fill=\"transparent\""));
+        assert!(!svg.contains("fill=\"white\""));
+
+        let png = diagram.render_png("transparent", None, 1.0).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 0);
+        let png = diagram.render_png("white", None, 1.0).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 255);
+    }
 
-        if trimmed.starts_with('{') && trimmed.ends_with('}') && trimmed.len() >= 2 {
-            let inner = trimmed[1..trimmed.len() - 1].trim();
-            return Some((inner.to_string(), NodeShape::Diamond));
-        }
+    #[test]
+    fn orders_layers_to_reduce_crossings() {
+        let diagram =
+            Diagram::parse("graph TD\n  A --> X\n  C --> X\n  B --> Y\n  B --> Z\n  C --> Z")
+                .unwrap();
+        let mut layers: Vec<Vec<String>> = [vec!["A", "B", "C"], vec!["X", "Y", "Z"]]
+            .iter()
+            .map(|layer| layer.iter().map(|id| id.to_string()).collect())
+            .collect();
+        assert_eq!(diagram.count_layer_crossings(&layers), 2);
 
-        if trimmed.starts_with('>') && trimmed.ends_with(']') && trimmed.len() >= 2 {
-            let inner = trimmed[1..trimmed.len() - 1].trim();
-            return Some((inner.to_string(), NodeShape::Asymmetric));
-        }
+        diagram.reduce_crossings(&mut layers);
+        assert_eq!(diagram.count_layer_crossings(&layers), 0);
+        // `C` shares a child with both `A` and `B`, so it moves between them.
+        assert_eq!(layers[0], vec!["A", "C", "B"]);
+    }
 
-        None
+    #[test]
+    fn parses_block_diagrams() {
+        let diagram = Diagram::parse(
+            "block-beta\n  columns 3\n  a b:2\n  space c\n  block:group:3\n    columns 2\n    d e\n  end\n  a --> c\n  c -- \"next\" --> f",
+        )
+        .unwrap();
+        let DiagramKind::Block(block) = &diagram.kind else {
+            panic!("expected a block diagram");
+        };
+        assert_eq!(block.columns, Some(3));
+        assert_eq!(diagram.edges.len(), 2);
+        assert_eq!(diagram.edges[1].label.as_deref(), Some("next"));
+        assert_eq!(diagram.subgraphs[0].nodes, vec!["d", "e"]);
+        assert_eq!(diagram.node_membership["d"], vec!["group"]);
+
+        let layout = diagram.layout(None).unwrap();
+        let position = |id: &str| layout.final_positions[id];
+        // `b` spans two columns, so it is wider than `a` and centered over columns 2 and 3.
+        assert!(diagram.nodes["b"].width > diagram.nodes["a"].width * 2.0);
+        assert_eq!(position("a").y, position("b").y);
+        assert!(position("c").x > position("a").x && position("c").y > position("b").y);
+        assert!(position("d").y > position("c").y);
+        assert_eq!(position("d").y, position("e").y);
+        // `f` only appears in an edge, so it takes the next free cell.
+        assert!(position("f").y > position("d").y);
+
+        let err = Diagram::parse("block-beta\n  a\n  a").unwrap_err();
+        assert!(format!("{err:#}").contains("declared more than once"));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn rejects_malformed_block_diagrams() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("block-beta\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("  columns 0\n  a").contains("positive column count"));
+        assert!(error("  columns -1\n  a").contains("positive column count"));
+        assert!(error("  a:0").contains("block 'a' must span at least one column"));
+        assert!(error("  block:g:0\n  end").contains("block 'g' must span"));
+        assert!(error("  block:g[\"Group\n  a\n  end").contains("missing closing ']' in 'g["));
+        assert!(error("  end").contains("unexpected 'end'"));
+        assert!(error("  block:g\n  a\n  end\n  end").contains("unexpected 'end'"));
+        assert!(error("  block:g\n  a").contains("block 'g' is missing its closing 'end'"));
+        assert!(error("  block:g\n  a\n  end\n  g").contains("declared more than once"));
+        assert!(error("").contains("does not declare any blocks"));
+        assert!(error("  space:2").contains("does not declare any blocks"));
+
+        // Spans wider than the grid are clamped, and empty groups still lay out.
+        let diagram = Diagram::parse("block-beta\n  columns 2\n  a:5\n  block:g\n  end").unwrap();
+        let DiagramKind::Block(block) = &diagram.kind else {
+            panic!("expected a block diagram");
+        };
+        assert!(
+            block
+                .positions
+                .values()
+                .all(|p| p.x.is_finite() && p.y.is_finite())
+        );
+    }
 
     #[test]
-    fn parses_extended_shape_syntax() {
-        let cases = [
-            ("sub[[Subroutine]]", NodeShape::Subroutine, "Subroutine"),
-            ("db[(Database)]", NodeShape::Cylinder, "Database"),
-            ("hex{{Prep}}", NodeShape::Hexagon, "Prep"),
-            ("stop(((Stop)))", NodeShape::DoubleCircle, "Stop"),
-            ("lean[/Tilt/]", NodeShape::Parallelogram, "Tilt"),
-            ("leanAlt[\\Lean\\]", NodeShape::ParallelogramAlt, "Lean"),
-            ("prio[/Priority\\]", NodeShape::Trapezoid, "Priority"),
-            ("manual[\\Manual/]", NodeShape::TrapezoidAlt, "Manual"),
-            ("asym>Skewed]", NodeShape::Asymmetric, "Skewed"),
-            ("term([Terminal])", NodeShape::Stadium, "Terminal"),
-        ];
+    fn parses_c4_diagrams() {
+        let diagram = Diagram::parse(
+            "C4Container\n  title Banking\n  Person(customer, \"Customer\", \"Has accounts, and cards\")\n  System_Boundary(c1, \"Internet Banking\") {\n    Container(web, \"Web App\", \"Rust, Axum\", \"Serves pages\")\n    ContainerDb(db, Database, \"Postgres\")\n  }\n  System_Ext(mail, \"Mail\")\n  Rel(customer, web, \"Uses\", \"HTTPS\")\n  Rel_Back(customer, mail, \"Emails\")\n  BiRel(web, db, \"Reads\", $tags=\"sql\")\n",
+        )
+        .unwrap();
+        let DiagramKind::C4(c4) = &diagram.kind else {
+            panic!("expected a C4 diagram");
+        };
+        assert_eq!(diagram.config.title.as_deref(), Some("Banking"));
+        assert_eq!(
+            c4.elements["customer"].description.as_deref(),
+            Some("Has accounts, and cards")
+        );
+        let web = &c4.elements["web"];
+        assert_eq!(web.kind, C4ElementKind::Container);
+        assert_eq!(web.technology.as_deref(), Some("Rust, Axum"));
+        assert_eq!(c4.elements["db"].shape, C4ElementShape::Database);
+        assert!(c4.elements["mail"].external);
+        assert_eq!(diagram.nodes["db"].shape, NodeShape::Cylinder);
+        assert_eq!(diagram.subgraphs.len(), 1);
+        assert_eq!(diagram.subgraphs[0].nodes, ["web", "db"]);
+
+        let edges: Vec<(&str, &str, Option<&str>)> = diagram
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str(), edge.label.as_deref()))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("customer", "web", Some("Uses [HTTPS]")),
+                ("mail", "customer", Some("Emails")),
+                ("web", "db", Some("Reads")),
+            ]
+        );
+        assert_eq!(diagram.edges[2].arrow, EdgeArrowDirection::Both);
 
-        for (input, expected_shape, expected_label) in cases {
-            let spec = NodeSpec::parse(input).unwrap();
-            assert_eq!(spec.shape, expected_shape, "shape mismatch for {input}");
-            assert_eq!(spec.label, expected_label, "label mismatch for {input}");
-        }
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(">[Container: Rust, Axum]</text>"));
+        assert!(svg.contains("class=\"c4-legend\""));
+        assert!(svg.contains(">External Software System</text>"));
+        assert!(svg.contains("fill=\"#08427b\""));
+        assert!(diagram.to_definition().starts_with("C4Container\n"));
+
+        assert!(Diagram::parse("C4Context\n  Person(a, \"A\")\n  Rel(a, b, \"Uses\")\n").is_err());
+        assert!(Diagram::parse("C4Context\n  Boundary(b, \"B\") {\n  Person(a, \"A\")\n").is_err());
     }
 
     #[test]
-    fn formats_extended_shapes() {
-        let cases = [
-            (NodeShape::Subroutine, "sub", "Task", "sub[[Task]]"),
-            (NodeShape::Cylinder, "db", "Data", "db[(Data)]"),
-            (NodeShape::Hexagon, "hex", "Prep", "hex{{Prep}}"),
-            (NodeShape::DoubleCircle, "stop", "Stop", "stop(((Stop)))"),
-            (NodeShape::Parallelogram, "lean", "Tilt", "lean[/Tilt/]"),
-            (
-                NodeShape::ParallelogramAlt,
-                "leanAlt",
-                "Tilt",
-                "leanAlt[\\Tilt\\]",
-            ),
-            (
-                NodeShape::Trapezoid,
-                "prio",
-                "Priority",
-                "prio[/Priority\\]",
-            ),
-            (
-                NodeShape::TrapezoidAlt,
-                "manual",
-                "Manual",
-                "manual[\\Manual/]",
-            ),
-            (NodeShape::Asymmetric, "asym", "Skewed", "asym>Skewed]"),
-        ];
-
-        for (shape, id, label, expected) in cases {
-            assert_eq!(
-                shape.format_spec(id, label),
-                expected,
-                "format mismatch for {id}"
-            );
-        }
+    fn rejects_malformed_c4_statements() {
+        let error = |body: &str| {
+            Diagram::parse(&format!("C4Context\n{body}"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("  Person(, \"A\")\n").contains("needs an alias and a label"));
+        assert!(error("  Person(a)\n").contains("needs an alias and a label"));
+        assert!(error("  Person(a, \"A)\n").contains("unterminated quote"));
+        assert!(error("  Person(a, \"A\") {\n  }\n").contains("cannot contain other elements"));
+        assert!(error("  Boundary(b, \"B\")\n").contains("must open a '{' block"));
+        assert!(error("  Person(a, \"A\")\n  System(a, \"Again\")\n").contains("more than once"));
+        assert!(error("  Person(a, \"A\")\n  }\n").contains("without an open boundary"));
+        assert!(error("  Person(a, \"A\")\n  Rel(a)\n").contains("needs a source and a target"));
+        assert!(error("  Person(a, \"A\")\n  Rel(a, , \"x\")\n").contains("source and a target"));
+        assert!(error("  Person(a, \"A\")\n  Deployment_Node(d, \"D\")\n").contains("unsupported"));
+        assert!(error("  Person a\n").contains("unrecognized C4 statement"));
+        assert!(error("  title Only a title\n").contains("does not declare any elements"));
+
+        // Empty boundaries are dropped rather than drawn as empty boxes.
+        let diagram =
+            Diagram::parse("C4Context\n  Boundary(b, \"B\") {\n  }\n  Person(a, \"A\")\n").unwrap();
+        assert!(diagram.subgraphs.is_empty());
+        diagram.render_svg("white", None).unwrap();
     }
 
     #[test]
-    fn parses_inline_edge_labels() {
-        let source = r#"
-graph TD
-    A -- Yes --> B;
-    B --> C;
-"#;
+    fn renders_minimap_thumbnails() {
+        let diagram =
+            Diagram::parse("graph LR\nA[Start] --> B[Middle]\nB --> C[End]\nsubgraph g\nC\nend")
+                .unwrap();
+        let minimap = MinimapRender {
+            size: 100.0,
+            x: Some(0.0),
+            y: Some(0.0),
+            width: Some(120.0),
+            height: Some(80.0),
+        };
+        let svg = diagram.render_minimap_svg("white", None, &minimap).unwrap();
+        let caps = svg_size_regex().captures(&svg).unwrap();
+        let (width, height) = (
+            caps[1].parse::<f32>().unwrap(),
+            caps[2].parse::<f32>().unwrap(),
+        );
+        assert_eq!(width.max(height), 100.0);
+        assert!(!svg.contains("<text"));
+        assert_eq!(svg.matches("class=\"minimap-node\"").count(), 3);
+        assert_eq!(svg.matches("class=\"minimap-edge\"").count(), 2);
+        assert_eq!(svg.matches("class=\"minimap-subgraph\"").count(), 1);
+        assert!(svg.contains(
+            "class=\"minimap-viewport\" x=\"0.0\" y=\"0.0\" width=\"120.0\" height=\"80.0\""
+        ));
+
+        let pie = Diagram::parse("pie\n  \"Dogs\" : 3\n  \"Cats\" : 2\n").unwrap();
+        let svg = pie
+            .render_minimap_svg("white", None, &MinimapRender::default())
+            .unwrap();
+        assert!(!svg.contains("<text"));
+        assert!(!svg.contains("minimap-viewport"));
 
-        let diagram = Diagram::parse(source).expect("diagram parse should succeed");
-        assert_eq!(diagram.edges.len(), 2, "expected two edges");
+        let partial = MinimapRender {
+            x: Some(0.0),
+            ..MinimapRender::default()
+        };
+        assert!(diagram.render_minimap_svg("white", None, &partial).is_err());
+    }
 
-        let yes_edge = diagram
-            .edges
+    #[test]
+    fn diff_pairs_renamed_nodes_and_their_edges() {
+        let before =
+            Diagram::parse("graph TD\n  A[Start] --> Auth[Login]\n  Auth --> DB\n  Auth --> Old")
+                .unwrap();
+        let after =
+            Diagram::parse("graph TD\n  A[Start] --> SignIn[Login]\n  SignIn --> DB\n  DB --> New")
+                .unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.nodes.added, vec!["New"]);
+        assert_eq!(diff.nodes.removed, vec!["Old"]);
+        assert_eq!(
+            diff.renamed_nodes,
+            vec![Rename {
+                from: "Auth".to_string(),
+                to: "SignIn".to_string(),
+            }]
+        );
+        let renamed_edges: Vec<_> = diff
+            .renamed_edges
             .iter()
-            .find(|edge| edge.label.as_deref() == Some("Yes"))
-            .expect("expected inline labeled edge");
-        assert_eq!(yes_edge.from, "A");
-        assert_eq!(yes_edge.to, "B");
+            .map(|rename| (rename.from.as_str(), rename.to.as_str()))
+            .collect();
+        assert_eq!(
+            renamed_edges,
+            [
+                ("A --> Auth", "A --> SignIn"),
+                ("Auth --> DB", "SignIn --> DB")
+            ]
+        );
+        assert_eq!(diff.edges.added, vec!["DB --> New"]);
+        assert_eq!(diff.edges.removed, vec!["Auth --> Old"]);
+        assert!(before.diff(&before).is_empty());
+
+        let (merged, overrides) = before.diff_overlay(&after, &diff);
+        assert!(merged.nodes.contains_key("Old"));
+        assert!(
+            merged
+                .edges
+                .iter()
+                .any(|edge| edge.from == "SignIn" && edge.to == "Old")
+        );
+        assert_eq!(
+            overrides.node_styles["Old"].border_style,
+            Some(BorderStyle::Dashed)
+        );
+        assert_eq!(
+            overrides.edge_styles["SignIn --> Old"].line,
+            Some(EdgeKind::Dashed)
+        );
+        assert!(overrides.node_styles.contains_key("New"));
+        assert!(overrides.node_styles.contains_key("SignIn"));
+        merged.render_svg("white", Some(&overrides)).unwrap();
     }
 
     #[test]
-    fn parses_bidirectional_edges_and_quoted_labels() {
-        let diagram = Diagram::parse("graph TD\nA[\"From\"] <-->|\"sync\"| B[\"To\"]")
-            .expect("diagram parse should succeed");
+    fn reports_source_change_sets() {
+        let before = Diagram::parse(
+            "graph TD\n  subgraph group [Group]\n    A[Start]\n    B\n  end\n  A --> B\n  B --> C",
+        )
+        .unwrap();
+        let after = Diagram::parse(
+            "graph TD\n  subgraph group [Renamed]\n    A[Begin]\n  end\n  A --> D\n  A --> C\n  C --> D",
+        )
+        .unwrap();
+
+        let changes = before.change_set(&after);
+        assert_eq!(changes.nodes.added, vec!["D"]);
+        assert_eq!(changes.nodes.removed, vec!["B"]);
+        assert_eq!(changes.nodes.modified, vec!["A"]);
+        assert_eq!(changes.edges.added, vec!["A --> C", "A --> D", "C --> D"]);
+        assert_eq!(changes.edges.removed, vec!["A --> B", "B --> C"]);
+        assert_eq!(changes.subgraphs.modified, vec!["group"]);
+        assert!(changes.destructive);
+        assert!(before.change_set(&before).nodes.is_empty());
+
+        let mut overrides = LayoutOverrides::default();
+        overrides.pin("B", Point { x: 1.0, y: 2.0 });
+        overrides
+            .edges
+            .insert("A --> B".to_string(), EdgeOverride::default());
+        let nodes: HashSet<String> = after.nodes.keys().cloned().collect();
+        let edges: HashSet<String> = after.edges.iter().map(edge_identifier).collect();
+        let pruned = overrides.prune(&nodes, &edges);
+        assert_eq!(pruned.nodes, vec!["B"]);
+        assert_eq!(pruned.edges, vec!["A --> B"]);
+        assert!(overrides.is_empty());
+    }
 
-        assert_eq!(diagram.nodes.len(), 2);
-        assert_eq!(diagram.nodes["A"].label, "From");
-        assert_eq!(diagram.nodes["B"].label, "To");
-        assert_eq!(diagram.edges[0].arrow, EdgeArrowDirection::Both);
-        assert_eq!(diagram.edges[0].label.as_deref(), Some("sync"));
+    #[test]
+    fn applies_layout_views() {
+        let mut diagram = Diagram::parse(
+            "graph TD\nA --> B\nB --> C\nA --> C\nsubgraph infra [Infra]\nD\nE\nend\nC --> D",
+        )
+        .unwrap();
+        let overrides: LayoutOverrides = serde_json::from_str(
+            r#"{"views": {"overview": {"hidden_nodes": ["B"], "hidden_edges": ["A --> C"], "hidden_subgraphs": ["infra"], "theme": "dark", "zoom": 1.5}}}"#,
+        )
+        .unwrap();
+        assert!(!overrides.is_empty());
+
+        let view = overrides.view("overview").unwrap();
+        diagram.apply_view(view);
+        let mut ids: Vec<&str> = diagram.nodes.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, ["A", "C"]);
+        assert!(diagram.edges.is_empty());
+        assert_eq!(diagram.config.theme.as_deref(), Some("dark"));
+
+        let error = overrides.view("detail").unwrap_err().to_string();
+        assert!(error.contains("unknown view 'detail'"), "{error}");
+        assert!(error.contains("overview"), "{error}");
+
+        let svg = diagram.render_svg("white", Some(&overrides)).unwrap();
+        let zoomed = zoom_svg(&svg, view.zoom.unwrap()).unwrap();
+        let size = |svg: &str| {
+            let caps = svg_size_regex().captures(svg).unwrap();
+            (
+                caps[1].parse::<f32>().unwrap(),
+                caps[2].parse::<f32>().unwrap(),
+            )
+        };
+        let (width, height) = size(&svg);
+        assert_eq!(size(&zoomed), ((width * 1.5).ceil(), (height * 1.5).ceil()));
+        assert!(zoom_svg(&svg, 0.0).is_err());
+
+        let json = serde_json::to_value(&overrides).unwrap();
+        assert_eq!(json["views"]["overview"]["hidden_subgraphs"][0], "infra");
     }
 
     #[test]
@@ -6395,6 +16025,105 @@ graph TD
         close(positions["G"].x, positions["J"].x);
     }
 
+    #[test]
+    fn lays_out_subgraphs_as_nested_units() {
+        let diagram = Diagram::parse(
+            "graph TD\nA --> B1\nA --> C1\nA --> L\nsubgraph Outer\nsubgraph Inner\nB1 --> B2\nend\nD --> B2\nend\nsubgraph Right\nC1 --> C2\nC1 --> C3\nend\nB1 --> C3\nL --> C2",
+        )
+        .unwrap();
+        let layout = diagram.layout(None).unwrap();
+        let visuals = compute_subgraph_visuals(
+            &diagram.subgraphs,
+            &layout.final_positions,
+            &diagram.nodes,
+            &HashMap::new(),
+            None,
+        );
+        let frame = |id: &str| {
+            let visual = visuals.iter().find(|visual| visual.id == id).unwrap();
+            Rect {
+                min_x: visual.x,
+                max_x: visual.x + visual.width,
+                min_y: visual.y,
+                max_y: visual.y + visual.height,
+            }
+        };
+
+        let (outer, inner, right) = (frame("Outer"), frame("Inner"), frame("Right"));
+        assert!(inner.min_x > outer.min_x && inner.max_x < outer.max_x);
+        assert!(!outer.intersects(&right));
+        for (id, node) in &diagram.nodes {
+            let rect = node_rect(layout.final_positions[id], node.width, node.height);
+            let member = diagram
+                .node_membership
+                .get(id)
+                .is_some_and(|path| !path.is_empty());
+            if !member {
+                assert!(!rect.intersects(&outer) && !rect.intersects(&right), "{id}");
+            }
+        }
+    }
+
+    #[test]
+    fn clusters_cyclic_sideways_and_empty_subgraphs() {
+        let frames = |source: &str| {
+            let diagram = Diagram::parse(source).unwrap();
+            let layout = diagram.layout(None).unwrap();
+            assert!(
+                layout
+                    .final_positions
+                    .values()
+                    .all(|point| point.x.is_finite() && point.y.is_finite())
+            );
+            let rects: HashMap<String, Rect> = diagram
+                .nodes
+                .iter()
+                .map(|(id, node)| {
+                    let rect = node_rect(layout.final_positions[id], node.width, node.height);
+                    (id.clone(), rect)
+                })
+                .collect();
+            let frames: HashMap<String, Rect> = compute_subgraph_visuals(
+                &diagram.subgraphs,
+                &layout.final_positions,
+                &diagram.nodes,
+                &HashMap::new(),
+                None,
+            )
+            .into_iter()
+            .map(|visual| {
+                let rect = Rect {
+                    min_x: visual.x,
+                    max_x: visual.x + visual.width,
+                    min_y: visual.y,
+                    max_y: visual.y + visual.height,
+                };
+                (visual.id, rect)
+            })
+            .collect();
+            (rects, frames)
+        };
+
+        // Cycles inside and between subgraphs, laid out left to right.
+        let (nodes, frames_lr) = frames(
+            "graph LR\nA --> B\nsubgraph S\nB --> C\nC --> B\nend\nsubgraph T\nD --> E\nend\nA --> D\nE --> A",
+        );
+        assert!(!frames_lr["S"].intersects(&frames_lr["T"]));
+        for frame in frames_lr.values() {
+            assert!(!nodes["A"].intersects(frame));
+        }
+
+        // A subgraph without members draws no frame and leaves loose nodes alone.
+        let (nodes, frames_empty) = frames("graph TD\nsubgraph Empty\nend\nA --> B\nB --> A");
+        assert!(!frames_empty.contains_key("Empty"));
+        assert!(!nodes["A"].intersects(&nodes["B"]));
+
+        // A lone member still sits inside its frame.
+        let (nodes, frames_single) = frames("graph TD\nsubgraph S\nA\nend");
+        let frame = frames_single["S"];
+        assert!(nodes["A"].min_x > frame.min_x && nodes["A"].max_x < frame.max_x);
+    }
+
     #[test]
     fn preserves_recursive_tree_layout_inside_subgraphs() {
         let diagram = Diagram::parse(
@@ -6439,7 +16168,7 @@ graph TD
             ("D".into(), Point { x: 200.0, y: 640.0 }),
         ]);
         let routes = diagram
-            .compute_routes(&positions, None)
+            .compute_routes(&positions, None, &HashMap::new())
             .expect("routing should succeed");
         let edge = diagram
             .edges
@@ -6466,4 +16195,264 @@ graph TD
 
         assert!(route.iter().any(|point| point.x < min_x || point.x > max_x));
     }
+
+    #[test]
+    fn node_images_load_from_paths_relative_to_the_diagram() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = Pixmap::new(40, 20).unwrap().encode_png().unwrap();
+        std::fs::create_dir(dir.path().join("assets")).unwrap();
+        std::fs::write(dir.path().join("assets/logo.png"), &png).unwrap();
+
+        let source =
+            "graph TD\n    %% OXDRAW IMAGE A ./assets/logo.png padding=4\n    A[Logo] --> B\n";
+        let mut diagram = Diagram::parse(source).unwrap();
+        assert_eq!(
+            diagram.config.image_sources["A"],
+            NodeImageSource {
+                src: "./assets/logo.png".to_string(),
+                padding: 4.0,
+            }
+        );
+        assert!(diagram.nodes["A"].image.is_none());
+
+        diagram.load_image_sources(Some(dir.path())).unwrap();
+        let image = diagram.nodes["A"].image.as_ref().unwrap();
+        assert_eq!((image.width, image.height), (40, 20));
+        assert_eq!(image.data, png);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("href=\"data:image/png;base64,"));
+        assert!(
+            diagram
+                .to_definition()
+                .contains("%% OXDRAW IMAGE A ./assets/logo.png padding=4")
+        );
+
+        let err = Diagram::parse(source)
+            .unwrap()
+            .load_image_sources(None)
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("failed to load image './assets/logo.png'"));
+    }
+
+    #[test]
+    fn node_image_sources_keep_their_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut jpeg = Vec::new();
+        jpeg_encoder::Encoder::new(&mut jpeg, 80)
+            .encode(&[200; 30 * 10 * 3], 30, 10, jpeg_encoder::ColorType::Rgb)
+            .unwrap();
+        std::fs::write(dir.path().join("photo.jpg"), &jpeg).unwrap();
+        std::fs::write(
+            dir.path().join("icon"),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"16\" height=\"12\"/>",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("notes.png"), "not an image").unwrap();
+
+        let mut diagram = Diagram::parse(
+            "graph TD\n    %% OXDRAW IMAGE A photo.jpg\n    %% OXDRAW IMAGE B icon\n    A --> B\n",
+        )
+        .unwrap();
+        diagram.load_image_sources(Some(dir.path())).unwrap();
+        let image = |id: &str| diagram.nodes[id].image.as_ref().unwrap();
+        assert_eq!(image("A").mime_type, "image/jpeg");
+        assert_eq!((image("A").width, image("A").height), (30, 10));
+        assert_eq!(image("B").mime_type, "image/svg+xml");
+        assert_eq!((image("B").width, image("B").height), (16, 12));
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("href=\"data:image/jpeg;base64,"));
+        assert!(svg.contains("href=\"data:image/svg+xml;base64,"));
+
+        let err = Diagram::parse("graph TD\n    %% OXDRAW IMAGE A notes.png\n    A\n")
+            .unwrap()
+            .load_image_sources(Some(dir.path()))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("not a PNG, JPEG, GIF, WebP or SVG image"));
+    }
+
+    #[test]
+    fn icon_tokens_draw_beside_the_label() {
+        assert_eq!(
+            split_label_icon("Web fab:fa-github repo"),
+            Some(("github".to_string(), "Web repo".to_string()))
+        );
+        assert_eq!(split_label_icon("prefa:fa-x"), None);
+        assert_eq!(builtin_icon("fa-gear"), builtin_icon("cog"));
+
+        let mut diagram = Diagram::parse(
+            "graph LR\n    A[fa:fa-server Web] --> B[Web]\n    B --> C[fa:fa-logo Brand]\n",
+        )
+        .unwrap();
+        assert!(diagram.nodes["A"].width > diagram.nodes["B"].width);
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains(&format!("d=\"{}\"", builtin_icon("server").unwrap())));
+        assert!(svg.contains("<title>Web</title>"));
+        assert!(!svg.contains("fa:fa-server Web</text>"));
+        assert!(svg.contains("<rect class=\"icon\""));
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("logo.svg"),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 16 16\"><circle cx=\"8\" cy=\"8\" r=\"6\"/></svg>",
+        )
+        .unwrap();
+        diagram
+            .apply_render_options(&RenderOptions {
+                icon_dir: Some(dir.path().to_path_buf()),
+                ..RenderOptions::default()
+            })
+            .unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("<image class=\"icon\""));
+        assert!(!svg.contains("<rect class=\"icon\""));
+        diagram.render_png("white", None, 1.0).unwrap();
+    }
+
+    #[test]
+    fn escaped_newlines_break_node_and_edge_labels() {
+        let diagram = Diagram::parse(
+            "graph TD\n    A[Line one\\nLine two] -->|first\\nsecond| B[Line one]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            normalize_label_lines(&diagram.nodes["A"].label),
+            ["Line one", "Line two"]
+        );
+        assert!(diagram.nodes["A"].height > diagram.nodes["B"].height);
+        assert_eq!(diagram.nodes["A"].width, diagram.nodes["B"].width);
+
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("dominant-baseline=\"middle\">Line two</tspan>"));
+        assert!(svg.contains("dominant-baseline=\"middle\">second</tspan>"));
+        assert!(svg.contains("<title>Line one Line two</title>"));
+        assert!(!svg.contains("\\n"));
+    }
+
+    #[test]
+    fn title_gets_its_own_band_above_the_diagram() {
+        let untitled = Diagram::parse("graph TD\n    A --> B\n").unwrap();
+        let titled = Diagram::parse(
+            "graph TD\n    %% oxdraw-title A rather long title for such a small diagram\n    A --> B\n",
+        )
+        .unwrap();
+        assert_eq!(
+            titled.config.title.as_deref(),
+            Some("A rather long title for such a small diagram")
+        );
+
+        let plain = untitled.geometry(&untitled.layout(None).unwrap()).unwrap();
+        let geometry = titled.geometry(&titled.layout(None).unwrap()).unwrap();
+        assert_eq!(geometry.height, plain.height + TITLE_AREA);
+        assert!(geometry.width > plain.width);
+        assert_eq!(
+            geometry.positions["A"].y - geometry.shift.y,
+            plain.positions["A"].y - plain.shift.y
+        );
+        assert!(geometry.positions["A"].y - titled.nodes["A"].height / 2.0 > TITLE_AREA);
+
+        let svg = titled.render_svg("white", None).unwrap();
+        assert!(svg.contains("<title id=\"oxdraw-title\">A rather long title"));
+        assert!(svg.contains("class=\"title\""));
+        let source = titled.to_definition();
+        assert!(source.contains("%% oxdraw-title A rather long title for such a small diagram"));
+        assert_eq!(
+            Diagram::parse(&source).unwrap().config.title,
+            titled.config.title
+        );
+
+        let frontmatter = Diagram::parse(
+            "---\ntitle: Front\n---\n%% oxdraw-title Comment\ngraph TD\n    A --> B\n",
+        )
+        .unwrap();
+        assert_eq!(frontmatter.config.title.as_deref(), Some("Front"));
+        assert!(!frontmatter.to_definition().contains(TITLE_COMMENT_PREFIX));
+    }
+
+    #[test]
+    fn watermarks_are_stamped_in_the_chosen_corner() {
+        let mut diagram = Diagram::parse("graph TD\n    A --> B\n").unwrap();
+        let plain = diagram.render_svg("white", None).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let logo = dir.path().join("logo.png");
+        std::fs::write(&logo, Pixmap::new(48, 24).unwrap().encode_png().unwrap()).unwrap();
+        diagram
+            .apply_render_options(&RenderOptions {
+                watermark: Some(Watermark {
+                    text: Some("INTERNAL & RESTRICTED".to_string()),
+                    image: Some(logo),
+                    corner: WatermarkCorner::TopLeft,
+                }),
+                ..RenderOptions::default()
+            })
+            .unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert_eq!(
+            svg_size_regex().captures(&svg).unwrap()[0],
+            svg_size_regex().captures(&plain).unwrap()[0]
+        );
+        let stamp = &svg[svg.find("<g class=\"watermark\">").unwrap()..];
+        assert!(stamp.ends_with("</g>\n</svg>\n"));
+        assert!(stamp.contains(
+            "<image x=\"12.0\" y=\"12.0\" width=\"48.0\" height=\"24.0\" href=\"data:image/png;base64,"
+        ));
+        assert!(stamp.contains("<text x=\"66.0\" y=\"24.0\""));
+        assert!(stamp.contains(">INTERNAL &amp; RESTRICTED</text>"));
+        diagram.render_png("white", None, 1.0).unwrap();
+
+        let err = Watermark::default().load().unwrap_err();
+        assert!(err.to_string().contains("needs text or an image"));
+    }
+
+    #[test]
+    fn subgraphs_take_palette_tints_by_order() {
+        let source = "graph TD\n    subgraph api [API]\n        A\n        subgraph db [Storage]\n            B\n        end\n    end\n    subgraph web [Web]\n        C\n    end\n";
+        let diagram = Diagram::parse(source).unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        let fill = |id: &str| {
+            let group = &svg[svg.find(&format!("data-subgraph-id=\"{id}\"")).unwrap()..];
+            let fill = &group[group.find(" fill=\"").unwrap() + 7..];
+            fill[..fill.find('"').unwrap()].to_string()
+        };
+        assert_eq!(fill("api"), "#bee3f8");
+        assert_eq!(fill("web"), "#c6f6d5");
+        assert_eq!(fill("db"), "#ceeafa");
+
+        let theme = Theme::built_in("neutral").unwrap();
+        assert_eq!(theme.subgraph_fill(1, 2), theme.subgraph_fill);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flat.toml");
+        std::fs::write(&path, "subgraphFill = \"#eeeeee\"\n").unwrap();
+        assert_eq!(Theme::load(&path).unwrap().subgraph_fill(3, 0), "#eeeeee");
+    }
+
+    #[test]
+    fn grid_is_drawn_over_the_background() {
+        let source = "---\nconfig:\n  grid: lines\n---\ngraph TD\n    A --> B\n";
+        let mut diagram = Diagram::parse(source).unwrap();
+        assert_eq!(diagram.config.grid, Some(GridStyle::Lines));
+        let svg = diagram.render_svg("white", None).unwrap();
+        let background = svg.find("<rect width=\"100%\" height=\"100%\" fill=\"white\" />");
+        let grid = svg.find(
+            "<rect class=\"grid\" width=\"100%\" height=\"100%\" fill=\"url(#oxdraw-grid)\" />",
+        );
+        assert!(background.unwrap() < grid.unwrap());
+        assert!(grid.unwrap() < svg.find("<g class=\"edge").unwrap());
+        assert!(svg.contains("<path d=\"M 20 0 L 0 0 0 20\""));
+
+        diagram
+            .apply_render_options(&RenderOptions {
+                grid: Some(GridStyle::Dots),
+                ..RenderOptions::default()
+            })
+            .unwrap();
+        let svg = diagram.render_svg("white", None).unwrap();
+        assert!(svg.contains("<pattern id=\"oxdraw-grid\" width=\"20\" height=\"20\""));
+        assert!(svg.contains("<circle cx=\"1\" cy=\"1\" r=\"1\""));
+        diagram.render_png("white", None, 1.0).unwrap();
+
+        let err =
+            Diagram::parse("---\nconfig:\n  grid: hex\n---\ngraph TD\n    A --> B\n").unwrap_err();
+        assert!(format!("{err:#}").contains("unsupported grid 'hex'"));
+    }
 }
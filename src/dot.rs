@@ -0,0 +1,730 @@
+use anyhow::{Context, Result, bail};
+use std::collections::{HashMap, HashSet};
+
+use crate::diagram::{encode_entities, format_node_id, quote_label_if_needed};
+use crate::{Diagram, EdgeArrowDirection, EdgeHead, EdgeKind, NodeShape, TITLE_COMMENT_PREFIX};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A name, numeral, quoted string or HTML string (already reduced to plain text).
+    Id(String),
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Equals,
+    Semicolon,
+    Comma,
+    Colon,
+    /// `->` or `--`.
+    EdgeOp,
+}
+
+/// Splits DOT source into tokens with their 1-based line numbers, dropping comments and
+/// joining `"a" + "b"` concatenations. Stops after `limit` tokens when one is given.
+fn tokenize(source: &str, limit: Option<usize>) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut line = 1;
+    let mut at_line_start = true;
+    let mut index = 0;
+    // Set after a quoted string, so a following `+` joins the next one onto it.
+    let mut last_quoted = false;
+    let mut pending_concat = false;
+
+    while index < chars.len() && limit.is_none_or(|limit| tokens.len() < limit) {
+        let ch = chars[index];
+        if ch == '\n' {
+            line += 1;
+            at_line_start = true;
+            index += 1;
+            continue;
+        }
+        if ch.is_whitespace() {
+            index += 1;
+            continue;
+        }
+        let starts_line = std::mem::replace(&mut at_line_start, false);
+        if ch == '#' && starts_line {
+            while index < chars.len() && chars[index] != '\n' {
+                index += 1;
+            }
+            continue;
+        }
+        if ch == '/' && chars.get(index + 1) == Some(&'/') {
+            while index < chars.len() && chars[index] != '\n' {
+                index += 1;
+            }
+            continue;
+        }
+        if ch == '/' && chars.get(index + 1) == Some(&'*') {
+            index += 2;
+            while index < chars.len()
+                && !(chars[index] == '*' && chars.get(index + 1) == Some(&'/'))
+            {
+                if chars[index] == '\n' {
+                    line += 1;
+                }
+                index += 1;
+            }
+            index += 2;
+            continue;
+        }
+
+        let start_line = line;
+        let token = match ch {
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '=' => Token::Equals,
+            ';' => Token::Semicolon,
+            ',' => Token::Comma,
+            ':' => Token::Colon,
+            '+' if last_quoted => {
+                pending_concat = true;
+                index += 1;
+                continue;
+            }
+            '-' if matches!(chars.get(index + 1), Some('>' | '-')) => {
+                index += 2;
+                last_quoted = false;
+                tokens.push((Token::EdgeOp, start_line));
+                continue;
+            }
+            '"' => {
+                let mut text = String::new();
+                index += 1;
+                loop {
+                    match chars.get(index) {
+                        None => bail!("DOT line {start_line}: unterminated quoted string"),
+                        Some('"') => break,
+                        Some('\\') if chars.get(index + 1) == Some(&'"') => {
+                            text.push('"');
+                            index += 1;
+                        }
+                        Some('\\') if chars.get(index + 1) == Some(&'\n') => {
+                            line += 1;
+                            index += 1;
+                        }
+                        Some(&other) => {
+                            if other == '\n' {
+                                line += 1;
+                            }
+                            text.push(other);
+                        }
+                    }
+                    index += 1;
+                }
+                index += 1;
+                if std::mem::take(&mut pending_concat)
+                    && let Some((Token::Id(previous), _)) = tokens.last_mut()
+                {
+                    previous.push_str(&text);
+                } else {
+                    tokens.push((Token::Id(text), start_line));
+                }
+                last_quoted = true;
+                continue;
+            }
+            '<' => {
+                let mut depth = 0;
+                let mut html = String::new();
+                loop {
+                    let Some(&next) = chars.get(index) else {
+                        bail!("DOT line {start_line}: unterminated HTML string");
+                    };
+                    match next {
+                        '<' => depth += 1,
+                        '>' => depth -= 1,
+                        '\n' => line += 1,
+                        _ => {}
+                    }
+                    html.push(next);
+                    index += 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                last_quoted = false;
+                tokens.push((Token::Id(html_text(&html)), start_line));
+                continue;
+            }
+            _ if ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == '-' || !ch.is_ascii() => {
+                let mut text = String::new();
+                while let Some(&next) = chars.get(index) {
+                    let numeral = ch.is_ascii_digit() || ch == '.' || ch == '-';
+                    let continues = if numeral {
+                        next.is_ascii_digit() || next == '.' || (next == '-' && text.is_empty())
+                    } else {
+                        next.is_alphanumeric() || next == '_' || !next.is_ascii()
+                    };
+                    if !continues {
+                        break;
+                    }
+                    text.push(next);
+                    index += 1;
+                }
+                last_quoted = false;
+                tokens.push((Token::Id(text), start_line));
+                continue;
+            }
+            other => bail!("DOT line {start_line}: unexpected character '{other}'"),
+        };
+        index += 1;
+        last_quoted = false;
+        tokens.push((token, start_line));
+    }
+    Ok(tokens)
+}
+
+/// Plain text of an HTML-like label: `<br/>` becomes a line break, other tags are dropped and
+/// the XML entities are decoded.
+fn html_text(html: &str) -> String {
+    let inner = &html[1..html.len() - 1];
+    let mut text = String::new();
+    let mut rest = inner;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = rest[start + 1..start + end].trim().to_ascii_lowercase();
+        if tag.starts_with("br") {
+            text.push_str("\\n");
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+type Attributes = HashMap<String, String>;
+
+struct DotNode {
+    id: String,
+    attributes: Attributes,
+    cluster: Option<usize>,
+}
+
+struct DotEdge {
+    from: String,
+    to: String,
+    attributes: Attributes,
+}
+
+struct Cluster {
+    id: String,
+    label: Option<String>,
+    parent: Option<usize>,
+}
+
+/// Attribute defaults set by `node [...]` and `edge [...]`, scoped to a (sub)graph body.
+#[derive(Clone, Default)]
+struct Defaults {
+    node: Attributes,
+    edge: Attributes,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    directed: bool,
+    name: String,
+    graph_attributes: Attributes,
+    nodes: Vec<DotNode>,
+    node_index: HashMap<String, usize>,
+    edges: Vec<DotEdge>,
+    clusters: Vec<Cluster>,
+    /// Every node mention in order, so a subgraph used as an edge end knows its nodes.
+    mentions: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<()> {
+        if !self.eat(&expected) {
+            bail!("DOT line {}: expected {what}", self.line());
+        }
+        Ok(())
+    }
+
+    fn id(&mut self, what: &str) -> Result<String> {
+        let line = self.line();
+        match self.next() {
+            Some(Token::Id(id)) => Ok(id),
+            _ => bail!("DOT line {line}: expected {what}"),
+        }
+    }
+
+    fn graph(&mut self) -> Result<()> {
+        if self.peek_keyword("strict") {
+            self.position += 1;
+        }
+        self.directed = if self.peek_keyword("digraph") {
+            true
+        } else if self.peek_keyword("graph") {
+            false
+        } else {
+            bail!("DOT line {}: expected 'graph' or 'digraph'", self.line());
+        };
+        self.position += 1;
+        if matches!(self.peek(), Some(Token::Id(_))) {
+            self.name = self.id("a graph name")?;
+        }
+        self.expect(Token::LBrace, "'{' to open the graph")?;
+        self.statements(&mut Defaults::default(), None)?;
+        if self.position < self.tokens.len() {
+            bail!("DOT line {}: unexpected text after the graph", self.line());
+        }
+        Ok(())
+    }
+
+    /// Statements up to and including the closing `}`.
+    fn statements(&mut self, defaults: &mut Defaults, cluster: Option<usize>) -> Result<()> {
+        loop {
+            match self.peek() {
+                None => bail!("DOT line {}: missing '}}'", self.line()),
+                Some(Token::RBrace) => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(Token::Semicolon) => self.position += 1,
+                _ => self.statement(defaults, cluster)?,
+            }
+        }
+    }
+
+    fn statement(&mut self, defaults: &mut Defaults, cluster: Option<usize>) -> Result<()> {
+        for keyword in ["graph", "node", "edge"] {
+            if self.peek_keyword(keyword)
+                && self.tokens.get(self.position + 1).map(|(token, _)| token)
+                    == Some(&Token::LBracket)
+            {
+                self.position += 1;
+                let attributes = self.attribute_lists()?;
+                match keyword {
+                    "node" => defaults.node.extend(attributes),
+                    "edge" => defaults.edge.extend(attributes),
+                    _ => self.set_graph_attributes(attributes, cluster),
+                }
+                return Ok(());
+            }
+        }
+
+        let is_subgraph = self.peek_keyword("subgraph") || self.peek() == Some(&Token::LBrace);
+        let operand = if is_subgraph {
+            self.subgraph(defaults, cluster)?
+        } else {
+            let id = self.id("a node, edge, subgraph or attribute")?;
+            if self.eat(&Token::Equals) {
+                let value = self.id("an attribute value")?;
+                self.set_graph_attributes(HashMap::from([(id, value)]), cluster);
+                return Ok(());
+            }
+            self.skip_port()?;
+            vec![id]
+        };
+
+        if self.peek() != Some(&Token::EdgeOp) {
+            if !is_subgraph {
+                let attributes = self.attribute_lists()?;
+                let node = self.touch_node(&operand[0], defaults, cluster);
+                self.nodes[node].attributes.extend(attributes);
+            }
+            return Ok(());
+        }
+
+        let mut operands = vec![operand];
+        while self.eat(&Token::EdgeOp) {
+            let operand = if self.peek_keyword("subgraph") || self.peek() == Some(&Token::LBrace) {
+                self.subgraph(defaults, cluster)?
+            } else {
+                let id = self.id("a node after the edge operator")?;
+                self.skip_port()?;
+                vec![id]
+            };
+            operands.push(operand);
+        }
+        let mut attributes = defaults.edge.clone();
+        attributes.extend(self.attribute_lists()?);
+        for ids in &operands {
+            for id in ids {
+                self.touch_node(id, defaults, cluster);
+            }
+        }
+        for pair in operands.windows(2) {
+            for from in &pair[0] {
+                for to in &pair[1] {
+                    self.edges.push(DotEdge {
+                        from: from.clone(),
+                        to: to.clone(),
+                        attributes: attributes.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses `subgraph name { ... }` or `{ ... }` and returns the nodes mentioned in it, for
+    /// edges that use it as an end.
+    fn subgraph(&mut self, defaults: &Defaults, parent: Option<usize>) -> Result<Vec<String>> {
+        let mut name = None;
+        if self.peek_keyword("subgraph") {
+            self.position += 1;
+            if matches!(self.peek(), Some(Token::Id(_))) {
+                name = Some(self.id("a subgraph name")?);
+            }
+        }
+        self.expect(Token::LBrace, "'{' to open the subgraph")?;
+        let cluster = match name {
+            Some(name) if name.to_ascii_lowercase().starts_with("cluster") => {
+                self.clusters.push(Cluster {
+                    id: name,
+                    label: None,
+                    parent,
+                });
+                Some(self.clusters.len() - 1)
+            }
+            _ => parent,
+        };
+        let first_mention = self.mentions.len();
+        self.statements(&mut defaults.clone(), cluster)?;
+
+        let mut members: Vec<String> = Vec::new();
+        for id in &self.mentions[first_mention..] {
+            if !members.contains(id) {
+                members.push(id.clone());
+            }
+        }
+        Ok(members)
+    }
+
+    fn attribute_lists(&mut self) -> Result<Attributes> {
+        let mut attributes = Attributes::new();
+        while self.eat(&Token::LBracket) {
+            while !self.eat(&Token::RBracket) {
+                let name = self.id("an attribute name")?;
+                self.expect(Token::Equals, "'=' after the attribute name")?;
+                let value = self.id("an attribute value")?;
+                attributes.insert(name.to_ascii_lowercase(), value);
+                let _ = self.eat(&Token::Comma) || self.eat(&Token::Semicolon);
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// Skips the `:port` and `:compass` suffixes of a node reference.
+    fn skip_port(&mut self) -> Result<()> {
+        while self.eat(&Token::Colon) {
+            self.id("a port name")?;
+        }
+        Ok(())
+    }
+
+    fn set_graph_attributes(&mut self, attributes: Attributes, cluster: Option<usize>) {
+        match cluster {
+            Some(index) => {
+                if let Some(label) = attributes.get("label") {
+                    self.clusters[index].label = Some(label.clone());
+                }
+            }
+            None => self.graph_attributes.extend(attributes),
+        }
+    }
+
+    /// Index of node `id`, adding it with the current defaults the first time it's mentioned.
+    /// A node first mentioned outside any cluster moves into the first cluster that names it.
+    fn touch_node(&mut self, id: &str, defaults: &Defaults, cluster: Option<usize>) -> usize {
+        self.mentions.push(id.to_string());
+        if let Some(&index) = self.node_index.get(id) {
+            let node = &mut self.nodes[index];
+            if node.cluster.is_none() {
+                node.cluster = cluster;
+            }
+            return index;
+        }
+        self.nodes.push(DotNode {
+            id: id.to_string(),
+            attributes: defaults.node.clone(),
+            cluster,
+        });
+        self.node_index.insert(id.to_string(), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+}
+
+/// Closest flowchart shape to a DOT `shape`, with `style=rounded` boxes drawn rounded.
+fn node_shape(attributes: &Attributes) -> NodeShape {
+    let rounded = attributes
+        .get("style")
+        .is_some_and(|style| style.contains("rounded"));
+    let shape = attributes
+        .get("shape")
+        .map(|shape| shape.to_ascii_lowercase());
+    match shape.as_deref().unwrap_or("ellipse") {
+        "box" | "rect" | "rectangle" | "square" | "record" | "plaintext" | "plain" | "none"
+        | "note" | "tab" | "folder" | "underline"
+            if rounded =>
+        {
+            NodeShape::Stadium
+        }
+        "box" | "rect" | "rectangle" | "square" | "record" | "plaintext" | "plain" | "none"
+        | "note" | "tab" | "folder" | "underline" => NodeShape::Rectangle,
+        "circle" => NodeShape::Circle,
+        "doublecircle" => NodeShape::DoubleCircle,
+        "diamond" | "mdiamond" => NodeShape::Diamond,
+        "box3d" | "component" => NodeShape::Subroutine,
+        "cylinder" => NodeShape::Cylinder,
+        "hexagon" => NodeShape::Hexagon,
+        "parallelogram" => NodeShape::Parallelogram,
+        "trapezium" => NodeShape::Trapezoid,
+        "invtrapezium" => NodeShape::TrapezoidAlt,
+        "cds" | "rarrow" => NodeShape::Asymmetric,
+        "point" => NodeShape::Junction,
+        _ => NodeShape::Stadium,
+    }
+}
+
+/// A DOT label as an oxdraw label: `\N` and `\G` expand to the node and graph names and the
+/// `\l`/`\r` line ends become plain `\n` breaks.
+fn label_text(label: &str, node_id: &str, graph_name: &str) -> String {
+    let label = label
+        .replace("\\N", node_id)
+        .replace("\\G", graph_name)
+        .replace("\\l", "\\n")
+        .replace("\\r", "\\n");
+    label.trim_end_matches("\\n").to_string()
+}
+
+/// Whether `source` is a Graphviz DOT graph rather than a Mermaid definition. Both can start
+/// with `graph`; DOT follows it with an optional name and `{`.
+pub fn is_dot_source(source: &str) -> bool {
+    let Ok(tokens) = tokenize(source, Some(4)) else {
+        return false;
+    };
+    let keyword = |index: usize, name: &str| matches!(tokens.get(index), Some((Token::Id(id), _)) if id.eq_ignore_ascii_case(name));
+    let brace = |index: usize| matches!(tokens.get(index), Some((Token::LBrace, _)));
+    let start = usize::from(keyword(0, "strict"));
+    if keyword(start, "digraph") {
+        return true;
+    }
+    keyword(start, "graph")
+        && (brace(start + 1)
+            || (matches!(tokens.get(start + 1), Some((Token::Id(_), _))) && brace(start + 2)))
+}
+
+/// Converts a Graphviz DOT graph to a Mermaid flowchart definition. Nodes keep their names
+/// and labels, clusters become subgraphs, `rankdir` sets the direction and the graph `label`
+/// the title; edge `style`, `dir` and `label` carry over. Colors and other attributes are
+/// dropped.
+pub fn dot_to_mermaid(source: &str) -> Result<String> {
+    let mut parser = Parser {
+        tokens: tokenize(source, None)?,
+        position: 0,
+        directed: false,
+        name: String::new(),
+        graph_attributes: Attributes::new(),
+        nodes: Vec::new(),
+        node_index: HashMap::new(),
+        edges: Vec::new(),
+        clusters: Vec::new(),
+        mentions: Vec::new(),
+    };
+    parser.graph()?;
+    if parser.nodes.is_empty() {
+        bail!("the DOT graph has no nodes");
+    }
+
+    let graph_name = parser.name.clone();
+    let direction = match parser
+        .graph_attributes
+        .get("rankdir")
+        .map(|rankdir| rankdir.to_ascii_uppercase())
+        .as_deref()
+    {
+        Some("LR") => "LR",
+        Some("RL") => "RL",
+        Some("BT") => "BT",
+        _ => "TD",
+    };
+
+    let mut lines = vec![format!("graph {direction}")];
+    if let Some(title) = parser.graph_attributes.get("label") {
+        let title = label_text(title, "", &graph_name).replace("\\n", " ");
+        lines.push(format!("    {TITLE_COMMENT_PREFIX} {title}"));
+    }
+
+    let mut used_ids: HashSet<String> = parser.node_index.keys().cloned().collect();
+    let cluster_ids: Vec<String> = parser
+        .clusters
+        .iter()
+        .enumerate()
+        .map(|(index, cluster)| {
+            let name = cluster.id["cluster".len()..].trim_start_matches(['_', '-', ' ']);
+            let base: String = name
+                .chars()
+                .map(|ch| {
+                    if ch.is_alphanumeric() || ch == '_' {
+                        ch
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let base = if base.is_empty() {
+                format!("cluster{}", index + 1)
+            } else {
+                base
+            };
+            let mut id = base.clone();
+            let mut suffix = 2;
+            while !used_ids.insert(id.clone()) {
+                id = format!("{base}_{suffix}");
+                suffix += 1;
+            }
+            id
+        })
+        .collect();
+
+    let node_line = |node: &DotNode| {
+        let label = match node.attributes.get("label") {
+            Some(label) if !label.is_empty() => label_text(label, &node.id, &graph_name),
+            _ => node.id.clone(),
+        };
+        node_shape(&node.attributes).format_spec(&node.id, &label)
+    };
+    fn emit_cluster(
+        index: usize,
+        depth: usize,
+        parser: &Parser,
+        cluster_ids: &[String],
+        node_line: &dyn Fn(&DotNode) -> String,
+        lines: &mut Vec<String>,
+    ) {
+        let indent = "    ".repeat(depth);
+        let cluster = &parser.clusters[index];
+        let id = &cluster_ids[index];
+        match &cluster.label {
+            Some(label) if !label.is_empty() => {
+                lines.push(format!("{indent}subgraph {id}[{}]", encode_entities(label)))
+            }
+            _ => lines.push(format!("{indent}subgraph {id}")),
+        }
+        for node in parser
+            .nodes
+            .iter()
+            .filter(|node| node.cluster == Some(index))
+        {
+            lines.push(format!("{indent}    {}", node_line(node)));
+        }
+        for child in
+            (0..parser.clusters.len()).filter(|child| parser.clusters[*child].parent == Some(index))
+        {
+            emit_cluster(child, depth + 1, parser, cluster_ids, node_line, lines);
+        }
+        lines.push(format!("{indent}end"));
+    }
+    for index in (0..parser.clusters.len()).filter(|index| parser.clusters[*index].parent.is_none())
+    {
+        emit_cluster(index, 1, &parser, &cluster_ids, &node_line, &mut lines);
+    }
+    for node in parser.nodes.iter().filter(|node| node.cluster.is_none()) {
+        lines.push(format!("    {}", node_line(node)));
+    }
+
+    for edge in &parser.edges {
+        let style = edge.attributes.get("style").map(String::as_str);
+        let kind = match style {
+            Some(style) if style.contains("invis") => EdgeKind::Invisible,
+            Some(style) if style.contains("dashed") || style.contains("dotted") => EdgeKind::Dashed,
+            Some(style) if style.contains("bold") => EdgeKind::Thick,
+            _ => EdgeKind::Solid,
+        };
+        let default_dir = if parser.directed { "forward" } else { "none" };
+        let (from, to, arrow) = match edge
+            .attributes
+            .get("dir")
+            .map_or(default_dir, String::as_str)
+        {
+            "back" => (&edge.to, &edge.from, EdgeArrowDirection::Forward),
+            "both" => (&edge.from, &edge.to, EdgeArrowDirection::Both),
+            "none" => (&edge.from, &edge.to, EdgeArrowDirection::None),
+            _ => (&edge.from, &edge.to, EdgeArrowDirection::Forward),
+        };
+        let head = match edge.attributes.get("arrowhead").map(String::as_str) {
+            Some("dot" | "odot") => EdgeHead::Circle,
+            Some("tee" | "box" | "obox") => EdgeHead::Cross,
+            _ => EdgeHead::Arrow,
+        };
+        let connector = kind.connector_with_head(arrow, head);
+        let label = edge
+            .attributes
+            .get("label")
+            .or_else(|| edge.attributes.get("xlabel"))
+            .filter(|label| !label.is_empty())
+            .map(|label| label_text(label, "", &graph_name));
+        lines.push(match label {
+            Some(label) => format!(
+                "    {} {connector}|{}| {}",
+                format_node_id(from),
+                quote_label_if_needed(&label),
+                format_node_id(to)
+            ),
+            None => format!(
+                "    {} {connector} {}",
+                format_node_id(from),
+                format_node_id(to)
+            ),
+        });
+    }
+
+    let mut definition = lines.join("\n");
+    definition.push('\n');
+    Ok(definition)
+}
+
+/// Parses a Graphviz DOT graph into a flowchart; see [`dot_to_mermaid`].
+pub fn parse_dot(source: &str) -> Result<Diagram> {
+    let definition = dot_to_mermaid(source)?;
+    Diagram::parse(&definition).context("the DOT graph could not be converted to a flowchart")
+}
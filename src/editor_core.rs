@@ -2,17 +2,28 @@ use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-use crate::diagram::{LayoutOverrides, Point, align_geometry, edge_identifier};
+use crate::diagram::{LayoutOverrides, Point, edge_identifier};
 use crate::utils::split_source_and_overrides;
-use crate::{CanvasSize, Diagram, DiagramKind, EdgeArrowDirection, EdgeKind, EdgeOverride};
+use crate::{
+    BorderStyle, CanvasSize, Diagram, DiagramKind, EdgeArrowDirection, EdgeKind, EdgeOverride,
+    LayoutDiagnostic, SourceChangeSet,
+};
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DiagramViewModel {
     pub kind: String,
+    /// Heading drawn above the diagram, from the frontmatter `title` or `%% oxdraw-title`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     pub background: String,
     pub auto_size: CanvasSize,
+    /// Size of the rendered SVG canvas, in render space.
     pub render_size: CanvasSize,
+    /// Render space is diagram space plus this shift. Node positions and edge
+    /// points are in diagram space; subgraph frames and `render_size` are in
+    /// render space, matching the SVG returned by `render_svg`.
+    pub coordinate_shift: Point,
     pub nodes: Vec<NodeViewModel>,
     pub edges: Vec<EdgeViewModel>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -20,6 +31,8 @@ pub struct DiagramViewModel {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gantt: Option<GanttViewModel>,
     pub source: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub layout_diagnostics: Vec<LayoutDiagnostic>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -42,8 +55,13 @@ pub struct NodeViewModel {
     pub label_fill_color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_fill_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_width: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_style: Option<BorderStyle>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub membership: Vec<String>,
+    pub pinned: bool,
     pub width: f32,
     pub height: f32,
 }
@@ -81,6 +99,12 @@ pub struct EdgeViewModel {
     pub color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arrow_direction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_fill_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_stroke_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animated: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -133,6 +157,8 @@ pub struct LayoutUpdateInput {
     pub edges: HashMap<String, Option<EdgeOverride>>,
     #[serde(default)]
     pub gantt_tasks: HashMap<String, Option<GanttTaskUpdateInput>>,
+    #[serde(default)]
+    pub pinned: HashMap<String, bool>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -165,6 +191,10 @@ pub struct NodeStylePatchInput {
     pub label_fill: Option<Option<String>>,
     #[serde(default)]
     pub image_fill: Option<Option<String>>,
+    #[serde(default)]
+    pub stroke_width: Option<Option<f32>>,
+    #[serde(default)]
+    pub border_style: Option<Option<BorderStyle>>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -175,6 +205,12 @@ pub struct EdgeStylePatchInput {
     pub color: Option<Option<String>>,
     #[serde(default)]
     pub arrow: Option<Option<EdgeArrowDirection>>,
+    #[serde(default)]
+    pub label_fill: Option<Option<String>>,
+    #[serde(default)]
+    pub label_stroke: Option<Option<String>>,
+    #[serde(default)]
+    pub animated: Option<Option<bool>>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -287,8 +323,9 @@ impl EditorCore {
     }
 
     pub fn render_svg(&self) -> Result<String> {
-        let diagram = Diagram::parse(&self.definition)?;
+        let mut diagram = Diagram::parse(&self.definition)?;
         let effective = self.effective_overrides();
+        diagram.apply_collapsed(&effective.collapsed);
         let override_ref = if effective.is_empty() {
             None
         } else {
@@ -298,16 +335,11 @@ impl EditorCore {
     }
 
     pub fn view_model(&self) -> Result<DiagramViewModel> {
-        let diagram = Diagram::parse(&self.definition)?;
+        let mut diagram = Diagram::parse(&self.definition)?;
         let effective_overrides = self.effective_overrides();
+        diagram.apply_collapsed(&effective_overrides.collapsed);
         let layout = diagram.layout(Some(&effective_overrides))?;
-        let geometry = align_geometry(
-            &layout.final_positions,
-            &layout.final_routes,
-            &diagram.edges,
-            &diagram.subgraphs,
-            &diagram.nodes,
-        )?;
+        let geometry = diagram.geometry(&layout)?;
 
         let mut nodes = Vec::new();
         for id in &diagram.order {
@@ -340,7 +372,10 @@ impl EditorCore {
                 text_color: style.and_then(|s| s.text.clone()),
                 label_fill_color: style.and_then(|s| s.label_fill.clone()),
                 image_fill_color: style.and_then(|s| s.image_fill.clone()),
+                stroke_width: style.and_then(|s| s.stroke_width),
+                border_style: style.and_then(|s| s.border_style),
                 membership: diagram.node_membership.get(id).cloned().unwrap_or_default(),
+                pinned: self.overrides.pinned.contains(id),
                 width: node.width,
                 height: node.height,
             });
@@ -379,6 +414,9 @@ impl EditorCore {
                 arrow_direction: style
                     .and_then(|s| s.arrow)
                     .map(|direction| direction.as_str().to_string()),
+                label_fill_color: style.and_then(|s| s.label_fill.clone()),
+                label_stroke_color: style.and_then(|s| s.label_stroke.clone()),
+                animated: style.and_then(|s| s.animated),
             });
         }
 
@@ -402,6 +440,18 @@ impl EditorCore {
 
         let (kind, gantt) = match &diagram.kind {
             DiagramKind::Flowchart => ("flowchart".to_string(), None),
+            DiagramKind::Sequence(_) => ("sequence".to_string(), None),
+            DiagramKind::State(_) => ("state".to_string(), None),
+            DiagramKind::Class(_) => ("class".to_string(), None),
+            DiagramKind::Er(_) => ("er".to_string(), None),
+            DiagramKind::Pie(_) => ("pie".to_string(), None),
+            DiagramKind::Mindmap(_) => ("mindmap".to_string(), None),
+            DiagramKind::Journey(_) => ("journey".to_string(), None),
+            DiagramKind::Timeline(_) => ("timeline".to_string(), None),
+            DiagramKind::GitGraph(_) => ("gitGraph".to_string(), None),
+            DiagramKind::Quadrant(_) => ("quadrantChart".to_string(), None),
+            DiagramKind::C4(_) => ("c4".to_string(), None),
+            DiagramKind::Block(_) => ("block".to_string(), None),
             DiagramKind::Gantt(gantt) => {
                 let gantt_overrides = &self.overrides.gantt;
                 let row_fill_even = gantt_overrides
@@ -506,11 +556,14 @@ impl EditorCore {
                 width: geometry.width,
                 height: geometry.height,
             },
+            coordinate_shift: geometry.shift,
+            title: diagram.config.title.clone(),
             nodes,
             edges,
             subgraphs,
             gantt,
             source: self.source()?,
+            layout_diagnostics: diagram.layout_diagnostics(&layout),
         })
     }
 
@@ -521,11 +574,36 @@ impl EditorCore {
                     self.overrides.nodes.insert(id, point);
                 }
                 None => {
-                    self.overrides.nodes.remove(&id);
+                    if !self.overrides.pinned.contains(&id) {
+                        self.overrides.nodes.remove(&id);
+                    }
                 }
             }
         }
 
+        if update.pinned.values().any(|pin| *pin) {
+            let current_positions = Diagram::parse(&self.definition)
+                .and_then(|diagram| diagram.layout(Some(&self.overrides)))
+                .map(|layout| layout.final_positions)
+                .unwrap_or_default();
+            for (id, pin) in &update.pinned {
+                let position = self
+                    .overrides
+                    .nodes
+                    .get(id)
+                    .or_else(|| current_positions.get(id))
+                    .copied();
+                if let (true, Some(position)) = (*pin, position) {
+                    self.overrides.pin(id, position);
+                }
+            }
+        }
+        for (id, pin) in update.pinned {
+            if !pin {
+                self.overrides.unpin(&id);
+            }
+        }
+
         for (id, value) in update.edges {
             match value {
                 Some(edge_override) if !edge_override.points.is_empty() => {
@@ -580,6 +658,12 @@ impl EditorCore {
                     if let Some(value) = patch.image_fill {
                         current.image_fill = value;
                     }
+                    if let Some(value) = patch.stroke_width {
+                        current.stroke_width = value;
+                    }
+                    if let Some(value) = patch.border_style {
+                        current.border_style = value;
+                    }
                     if current.is_empty() {
                         self.overrides.node_styles.remove(&id);
                     } else {
@@ -605,6 +689,15 @@ impl EditorCore {
                     if let Some(value) = patch.arrow {
                         current.arrow = value;
                     }
+                    if let Some(value) = patch.label_fill {
+                        current.label_fill = value;
+                    }
+                    if let Some(value) = patch.label_stroke {
+                        current.label_stroke = value;
+                    }
+                    if let Some(value) = patch.animated {
+                        current.animated = value;
+                    }
                     if current.is_empty() {
                         self.overrides.edge_styles.remove(&id);
                     } else {
@@ -639,18 +732,26 @@ impl EditorCore {
         }
     }
 
-    pub fn set_source(&mut self, source: &str) -> Result<()> {
+    pub fn relayout(&mut self) {
+        self.overrides.clear_unpinned_positions();
+    }
+
+    pub fn set_source(&mut self, source: &str) -> Result<SourceChangeSet> {
         let (definition, parsed_overrides) = split_source_and_overrides(source)?;
         let diagram = Diagram::parse(&definition)?;
         let node_ids: HashSet<String> = diagram.nodes.keys().cloned().collect();
         let edge_ids: HashSet<String> = diagram.edges.iter().map(edge_identifier).collect();
+        let mut changes = match Diagram::parse(&self.definition) {
+            Ok(previous) => previous.change_set(&diagram),
+            Err(_) => SourceChangeSet::default(),
+        };
 
         self.definition = definition;
         self.overrides = parsed_overrides;
-        self.overrides.prune(&node_ids, &edge_ids);
+        changes.record_pruned(self.overrides.prune(&node_ids, &edge_ids));
         self.drag_state = None;
 
-        Ok(())
+        Ok(changes)
     }
 
     pub fn set_background(&mut self, background: impl Into<String>) {
@@ -822,13 +923,7 @@ impl EditorCore {
         let diagram = Diagram::parse(&self.definition)?;
         let effective = self.effective_overrides();
         let layout = diagram.layout(Some(&effective))?;
-        let geometry = align_geometry(
-            &layout.final_positions,
-            &layout.final_routes,
-            &diagram.edges,
-            &diagram.subgraphs,
-            &diagram.nodes,
-        )?;
+        let geometry = diagram.geometry(&layout)?;
 
         let visual = geometry
             .subgraphs
@@ -837,8 +932,8 @@ impl EditorCore {
             .ok_or_else(|| anyhow!("subgraph '{id}' not found"))?;
 
         let members: Vec<String> = diagram
-            .nodes
-            .keys()
+            .order
+            .iter()
             .filter(|node_id| {
                 diagram
                     .node_membership
@@ -1083,6 +1178,18 @@ impl EditorCore {
         Ok(update)
     }
 
+    pub fn quick_add_node(
+        &mut self,
+        label: &str,
+        from: Option<&str>,
+    ) -> Result<(String, Option<String>)> {
+        let mut diagram = Diagram::parse(&self.definition)?;
+        let added = diagram.quick_add_node(label, from)?;
+        self.definition = diagram.to_definition();
+        self.drag_state = None;
+        Ok(added)
+    }
+
     pub fn delete_node(&mut self, id: &str) -> Result<bool> {
         let mut diagram = Diagram::parse(&self.definition)?;
         if !diagram.remove_node(id) {
@@ -1267,6 +1374,11 @@ mod wasm {
             Ok(())
         }
 
+        #[wasm_bindgen(js_name = relayout)]
+        pub fn relayout(&self) {
+            self.inner.borrow_mut().relayout();
+        }
+
         #[wasm_bindgen(js_name = applyStyleUpdate)]
         pub fn apply_style_update(&self, update: JsValue) -> Result<(), JsValue> {
             let update: StyleUpdateInput =
@@ -1276,11 +1388,13 @@ mod wasm {
         }
 
         #[wasm_bindgen(js_name = setSource)]
-        pub fn set_source(&self, source: &str) -> Result<(), JsValue> {
-            self.inner
+        pub fn set_source(&self, source: &str) -> Result<JsValue, JsValue> {
+            let changes = self
+                .inner
                 .borrow_mut()
                 .set_source(source)
-                .map_err(to_js_error)
+                .map_err(to_js_error)?;
+            serde_wasm_bindgen::to_value(&changes).map_err(to_js_error)
         }
 
         #[wasm_bindgen(js_name = setBackground)]
@@ -1429,6 +1543,15 @@ mod wasm {
             self.inner.borrow().source().map_err(to_js_error)
         }
 
+        #[wasm_bindgen(js_name = quickAddNode)]
+        pub fn quick_add_node(&self, label: &str, from: Option<String>) -> Result<String, JsValue> {
+            self.inner
+                .borrow_mut()
+                .quick_add_node(label, from.as_deref())
+                .map(|(id, _)| id)
+                .map_err(to_js_error)
+        }
+
         #[wasm_bindgen(js_name = deleteNode)]
         pub fn delete_node(&self, id: &str) -> Result<bool, JsValue> {
             self.inner.borrow_mut().delete_node(id).map_err(to_js_error)
@@ -0,0 +1,417 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+const ER_ROW_HEIGHT: f32 = 24.0;
+const ER_HEADER_HEIGHT: f32 = 32.0;
+const ER_CELL_PADDING: f32 = 10.0;
+const ER_HEADER_FILL: &str = "#cbd5e0";
+
+pub(crate) const ER_MARKER_DEFS: &str = r##"        <marker id="er-exactly-one" markerWidth="22" markerHeight="18" refX="20" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M10,2 L10,16 M15,2 L15,16" fill="none" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="er-zero-or-one" markerWidth="22" markerHeight="18" refX="20" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <circle cx="6" cy="9" r="4" fill="#ffffff" stroke="context-stroke" stroke-width="1.5" />
+            <path d="M15,2 L15,16" fill="none" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="er-one-or-more" markerWidth="22" markerHeight="18" refX="20" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <path d="M8,2 L8,16 M12,9 L20,2 M12,9 L20,16 M12,9 L20,9" fill="none" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+        <marker id="er-zero-or-more" markerWidth="22" markerHeight="18" refX="20" refY="9" orient="auto-start-reverse" markerUnits="userSpaceOnUse">
+            <circle cx="6" cy="9" r="4" fill="#ffffff" stroke="context-stroke" stroke-width="1.5" />
+            <path d="M12,9 L20,2 M12,9 L20,16 M12,9 L20,9" fill="none" stroke="context-stroke" stroke-width="1.5" />
+        </marker>
+"##;
+
+/// `erDiagram` sources are translated into the flowchart model so entities reuse the layered
+/// layout and editor overrides; the attribute tables and crow's-foot markers come from this data.
+#[derive(Debug, Clone)]
+pub struct ErDiagramData {
+    pub entities: HashMap<String, ErEntity>,
+    /// Relationship cardinalities keyed by [`edge_identifier`].
+    pub relationships: HashMap<String, ErRelationship>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ErEntity {
+    pub attributes: Vec<ErAttribute>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErAttribute {
+    pub data_type: String,
+    pub name: String,
+    /// `PK`, `FK` and `UK` markers in source order.
+    pub keys: Vec<String>,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErRelationship {
+    /// Cardinality at the edge's source entity.
+    pub from: ErCardinality,
+    /// Cardinality at the edge's target entity.
+    pub to: ErCardinality,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErCardinality {
+    ZeroOrOne,
+    ExactlyOne,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+impl ErCardinality {
+    fn from_left(token: &str) -> Option<Self> {
+        match token {
+            "|o" => Some(Self::ZeroOrOne),
+            "||" => Some(Self::ExactlyOne),
+            "}o" => Some(Self::ZeroOrMore),
+            "}|" => Some(Self::OneOrMore),
+            _ => None,
+        }
+    }
+
+    fn from_right(token: &str) -> Option<Self> {
+        match token {
+            "o|" => Some(Self::ZeroOrOne),
+            "||" => Some(Self::ExactlyOne),
+            "o{" => Some(Self::ZeroOrMore),
+            "|{" => Some(Self::OneOrMore),
+            _ => None,
+        }
+    }
+
+    fn marker_id(self) -> &'static str {
+        match self {
+            Self::ZeroOrOne => "er-zero-or-one",
+            Self::ExactlyOne => "er-exactly-one",
+            Self::ZeroOrMore => "er-zero-or-more",
+            Self::OneOrMore => "er-one-or-more",
+        }
+    }
+}
+
+impl ErRelationship {
+    pub(crate) fn marker_attrs(&self) -> (String, String) {
+        (
+            format!(" marker-start=\"url(#{})\"", self.from.marker_id()),
+            format!(" marker-end=\"url(#{})\"", self.to.marker_id()),
+        )
+    }
+}
+
+impl ErAttribute {
+    fn cells(&self) -> [String; 4] {
+        [
+            self.data_type.clone(),
+            self.name.clone(),
+            self.keys.join(","),
+            self.comment.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl ErEntity {
+    /// Widths of the type, name, key and comment columns; empty columns collapse to zero.
+    fn column_widths(&self) -> [f32; 4] {
+        let mut widths = [0.0_f32; 4];
+        for attribute in &self.attributes {
+            for (width, cell) in widths.iter_mut().zip(attribute.cells()) {
+                let chars = display_width(&cell);
+                if chars > 0 {
+                    *width = width.max(NODE_TEXT_CHAR_WIDTH * chars as f32 + ER_CELL_PADDING * 2.0);
+                }
+            }
+        }
+        widths
+    }
+
+    fn dimensions(&self, name: &str) -> (f32, f32) {
+        let header_width =
+            NODE_TEXT_CHAR_WIDTH * display_width(name) as f32 + ER_CELL_PADDING * 4.0;
+        let columns: f32 = self.column_widths().iter().sum();
+        let width = header_width.max(columns).max(NODE_WIDTH);
+        let height = ER_HEADER_HEIGHT + ER_ROW_HEIGHT * self.attributes.len() as f32;
+        (width, height)
+    }
+}
+
+impl ErDiagramData {
+    /// Draws an entity as a table: a name header followed by one row per attribute.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn render_entity(
+        &self,
+        svg: &mut String,
+        id: &str,
+        label: &str,
+        position: Point,
+        width: f32,
+        height: f32,
+        fill: &str,
+        stroke: &str,
+        text: &str,
+    ) -> Result<bool> {
+        let Some(entity) = self.entities.get(id) else {
+            return Ok(false);
+        };
+        let left = position.x - width / 2.0;
+        let top = position.y - height / 2.0;
+        writeln!(
+            svg,
+            "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />",
+            left,
+            top,
+            width,
+            height,
+            escape_xml(fill),
+            escape_xml(stroke)
+        )?;
+        writeln!(
+            svg,
+            "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" />",
+            left,
+            top,
+            width,
+            ER_HEADER_HEIGHT,
+            ER_HEADER_FILL,
+            escape_xml(stroke)
+        )?;
+        writeln!(
+            svg,
+            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+            position.x,
+            top + ER_HEADER_HEIGHT / 2.0,
+            escape_xml(text),
+            escape_xml(label)
+        )?;
+
+        // Spread any spare width over the populated columns so the table fills the box.
+        let mut widths = entity.column_widths();
+        let populated = widths.iter().filter(|width| **width > 0.0).count().max(1);
+        let spare = (width - widths.iter().sum::<f32>()) / populated as f32;
+        for column in widths.iter_mut().filter(|width| **width > 0.0) {
+            *column += spare;
+        }
+
+        for (row, attribute) in entity.attributes.iter().enumerate() {
+            let row_top = top + ER_HEADER_HEIGHT + ER_ROW_HEIGHT * row as f32;
+            if row > 0 {
+                writeln!(
+                    svg,
+                    "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"1\" stroke-opacity=\"0.4\" />",
+                    left,
+                    row_top,
+                    left + width,
+                    row_top,
+                    escape_xml(stroke)
+                )?;
+            }
+            let mut x = left;
+            for (cell, column_width) in attribute.cells().iter().zip(widths) {
+                if column_width > 0.0 && !cell.is_empty() {
+                    writeln!(
+                        svg,
+                        "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"13\" text-anchor=\"start\" dominant-baseline=\"middle\">{}</text>",
+                        x + ER_CELL_PADDING,
+                        row_top + ER_ROW_HEIGHT / 2.0,
+                        escape_xml(text),
+                        escape_xml(cell)
+                    )?;
+                }
+                x += column_width;
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn relationship_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"^([\w-]+)\s*(\|o|\|\||\}o|\}\|)(--|\.\.)(o\||\|\||o\{|\|\{)\s*([\w-]+)\s*:\s*(.+)$",
+        )
+        .expect("valid ER relationship regex")
+    })
+}
+
+fn attribute_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r#"^([\w()\[\],.-]+)\s+([\w-]+)((?:\s*(?:PK|FK|UK)\s*,?)*)\s*(?:"([^"]*)")?$"#)
+            .expect("valid ER attribute regex")
+    })
+}
+
+fn is_entity_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '-'))
+}
+
+struct ErBuilder {
+    order: Vec<String>,
+    entities: HashMap<String, ErEntity>,
+    labels: HashMap<String, String>,
+}
+
+impl ErBuilder {
+    fn entity(&mut self, name: &str) -> Result<String> {
+        if !is_entity_name(name) {
+            bail!("invalid entity name '{name}'");
+        }
+        if !self.entities.contains_key(name) {
+            self.entities.insert(name.to_string(), ErEntity::default());
+            self.labels.insert(name.to_string(), name.to_string());
+            self.order.push(name.to_string());
+        }
+        Ok(name.to_string())
+    }
+}
+
+pub(crate) fn parse_er_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut builder = ErBuilder {
+        order: Vec::new(),
+        entities: HashMap::new(),
+        labels: HashMap::new(),
+    };
+    let mut edges = Vec::new();
+    let mut relationships = HashMap::new();
+    let mut direction = Direction::TopDown;
+    let mut open_entity: Option<String> = None;
+
+    for line in lines {
+        let line = line.trim();
+        if let Some(id) = &open_entity {
+            if line == "}" {
+                open_entity = None;
+                continue;
+            }
+            let Some(captures) = attribute_regex().captures(line) else {
+                bail!("invalid attribute '{line}' in entity '{id}'");
+            };
+            let attribute = ErAttribute {
+                data_type: captures[1].to_string(),
+                name: captures[2].to_string(),
+                keys: captures[3]
+                    .split(|ch: char| ch == ',' || ch.is_whitespace())
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                comment: captures.get(4).map(|comment| comment.as_str().to_string()),
+            };
+            if let Some(entity) = builder.entities.get_mut(id) {
+                entity.attributes.push(attribute);
+            }
+            continue;
+        }
+
+        if let Some(rest) = line.to_ascii_lowercase().strip_prefix("direction ") {
+            direction = parse_graph_header(&format!("graph {}", rest.trim()))?;
+            continue;
+        }
+        if let Some(captures) = relationship_regex().captures(line) {
+            let from = builder.entity(&captures[1])?;
+            let to = builder.entity(&captures[5])?;
+            let edge = Edge {
+                from,
+                to,
+                label: Some(decode_label(captures[6].trim())).filter(|label| !label.is_empty()),
+                kind: if &captures[3] == ".." {
+                    EdgeKind::Dashed
+                } else {
+                    EdgeKind::Solid
+                },
+                arrow: EdgeArrowDirection::None,
+                head: EdgeHead::Arrow,
+                occurrence: 0,
+            };
+            let relationship = ErRelationship {
+                from: ErCardinality::from_left(&captures[2])
+                    .expect("regex only matches known tokens"),
+                to: ErCardinality::from_right(&captures[4])
+                    .expect("regex only matches known tokens"),
+            };
+            relationships.insert(push_edge(&mut edges, edge), relationship);
+            continue;
+        }
+
+        let statement = line.split('[').next().unwrap_or_default();
+        if statement.contains("--") || statement.contains("..") {
+            bail!(
+                "invalid ER relationship '{line}'; expected `FROM ||--o{{ TO : label` with |o, ||, }}o or }}| before the line and o|, ||, o{{ or |{{ after it"
+            );
+        }
+
+        let (head, opens_block) = match line.strip_suffix('{') {
+            Some(head) => (head.trim(), true),
+            None => (line, false),
+        };
+        let (name, alias) = match head.split_once('[') {
+            Some((name, alias)) => (
+                name.trim(),
+                Some(decode_label(alias.trim_end_matches(']').trim())),
+            ),
+            None => (head, None),
+        };
+        if !is_entity_name(name) {
+            bail!("unrecognized ER diagram statement '{line}'");
+        }
+        let id = builder.entity(name)?;
+        if let Some(alias) = alias.filter(|alias| !alias.is_empty()) {
+            builder.labels.insert(id.clone(), alias);
+        }
+        if opens_block {
+            open_entity = Some(id);
+        }
+    }
+
+    if let Some(id) = open_entity {
+        bail!("entity '{id}' is missing its closing '}}'");
+    }
+    if builder.order.is_empty() {
+        bail!("ER diagram does not declare any entities");
+    }
+
+    let nodes = builder
+        .order
+        .iter()
+        .map(|id| {
+            let label = builder.labels[id].clone();
+            let (width, height) = builder.entities[id].dimensions(&label);
+            (
+                id.clone(),
+                Node {
+                    label,
+                    shape: NodeShape::Rectangle,
+                    image: None,
+                    width,
+                    height,
+                },
+            )
+        })
+        .collect();
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Er(ErDiagramData {
+            entities: builder.entities,
+            relationships,
+            original_source: original_source.to_string(),
+        }),
+        direction,
+        nodes,
+        order: builder.order,
+        edges,
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
@@ -0,0 +1,314 @@
+use anyhow::{Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use crate::*;
+
+fn require_flowchart(diagram: &Diagram, format: &str) -> Result<()> {
+    if !matches!(diagram.kind, DiagramKind::Flowchart) {
+        bail!("{format} export supports flowcharts only");
+    }
+    Ok(())
+}
+
+/// Maps every node and subgraph id to the chain of subgraph ids it is nested in, outermost
+/// first. A node listed in several subgraphs belongs to the first one, as in the definition.
+fn containers(diagram: &Diagram) -> HashMap<String, Vec<String>> {
+    fn walk(
+        subgraph: &Subgraph,
+        parents: &mut Vec<String>,
+        map: &mut HashMap<String, Vec<String>>,
+    ) {
+        map.entry(subgraph.id.clone())
+            .or_insert_with(|| parents.clone());
+        parents.push(subgraph.id.clone());
+        for id in &subgraph.nodes {
+            map.entry(id.clone()).or_insert_with(|| parents.clone());
+        }
+        for child in &subgraph.children {
+            walk(child, parents, map);
+        }
+        parents.pop();
+    }
+    let mut map = HashMap::new();
+    for subgraph in &diagram.subgraphs {
+        walk(subgraph, &mut Vec::new(), &mut map);
+    }
+    map
+}
+
+/// The edge's ends in the direction its arrowhead points, for formats whose only arrow is
+/// forward.
+fn forward_ends(edge: &Edge) -> (&str, &str) {
+    match edge.arrow {
+        EdgeArrowDirection::Backward => (&edge.to, &edge.from),
+        _ => (&edge.from, &edge.to),
+    }
+}
+
+fn plantuml_text(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "'").replace('\n', "\\n"))
+}
+
+/// Translates a flowchart to PlantUML's activity syntax. Subgraphs become partitions holding
+/// the edges that leave their nodes, and nodes become activities declared with their label
+/// where an edge first names them, so a node first reached from another partition is drawn
+/// there. Junctions become synchronization bars; edges keep their label and line style, and
+/// always point forward.
+pub fn to_plantuml(diagram: &Diagram) -> Result<String> {
+    require_flowchart(diagram, "PlantUML")?;
+    let containers = containers(diagram);
+
+    let mut uml = String::from("@startuml\n");
+    if let Some(title) = &diagram.config.title {
+        let _ = writeln!(uml, "title {title}");
+    }
+    if matches!(
+        diagram.direction,
+        Direction::LeftRight | Direction::RightLeft
+    ) {
+        uml.push_str("left to right direction\n");
+    }
+
+    struct Writer<'a> {
+        diagram: &'a Diagram,
+        containers: HashMap<String, Vec<String>>,
+        declared: HashSet<&'a str>,
+        uml: String,
+    }
+    impl<'a> Writer<'a> {
+        fn innermost(&self, id: &str) -> Option<&String> {
+            self.containers.get(id).and_then(|chain| chain.last())
+        }
+
+        /// An activity's label and alias the first time it is named, its alias afterwards.
+        fn reference(&mut self, id: &'a str) -> String {
+            match self.diagram.nodes.get(id) {
+                Some(node) if node.shape == NodeShape::Junction => format!("==={id}==="),
+                Some(node) if self.declared.insert(id) => {
+                    format!("{} as {id}", plantuml_text(&node.label))
+                }
+                _ => id.to_string(),
+            }
+        }
+
+        /// Edges leaving nodes directly in `container`, then nodes in it that no edge names.
+        fn write_members(&mut self, container: Option<&String>, depth: usize) {
+            let indent = "  ".repeat(depth);
+            let diagram = self.diagram;
+            for edge in &diagram.edges {
+                let (from, to) = forward_ends(edge);
+                if self.innermost(from) != container {
+                    continue;
+                }
+                let arrow = match edge.kind {
+                    EdgeKind::Solid => "-->",
+                    EdgeKind::Dashed => "-[dashed]->",
+                    EdgeKind::Thick => "-[bold]->",
+                    EdgeKind::Invisible => "-[hidden]->",
+                };
+                let label = edge
+                    .label
+                    .as_deref()
+                    .map(|label| format!("[{}]", label.replace('\n', "\\n")))
+                    .unwrap_or_default();
+                let from = self.reference(from);
+                let to = self.reference(to);
+                let _ = writeln!(self.uml, "{indent}{from} {arrow}{label} {to}");
+            }
+            for id in &diagram.order {
+                if self.innermost(id) == container && !self.declared.contains(id.as_str()) {
+                    let node = self.reference(id);
+                    let _ = writeln!(self.uml, "{indent}{node}");
+                }
+            }
+        }
+
+        fn write_partition(&mut self, subgraph: &Subgraph, depth: usize) {
+            let indent = "  ".repeat(depth);
+            let _ = writeln!(
+                self.uml,
+                "{indent}partition {} {{",
+                plantuml_text(&subgraph.label)
+            );
+            self.write_members(Some(&subgraph.id), depth + 1);
+            for child in &subgraph.children {
+                self.write_partition(child, depth + 1);
+            }
+            let _ = writeln!(self.uml, "{indent}}}");
+        }
+    }
+
+    let mut writer = Writer {
+        diagram,
+        containers,
+        declared: HashSet::new(),
+        uml,
+    };
+    for subgraph in &diagram.subgraphs {
+        writer.write_partition(subgraph, 0);
+    }
+    writer.write_members(None, 0);
+    writer.uml.push_str("@enduml\n");
+    Ok(writer.uml)
+}
+
+/// D2 keys that aren't plain words are quoted, since `.`, `-` and `:` mean paths, arrows
+/// and values.
+fn d2_key(id: &str) -> String {
+    if !id.is_empty() && id.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        id.to_string()
+    } else {
+        d2_string(id)
+    }
+}
+
+fn d2_string(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// The D2 shape closest to each node shape; `None` keeps D2's default rectangle.
+fn d2_shape(shape: NodeShape) -> Option<&'static str> {
+    match shape {
+        NodeShape::Rectangle
+        | NodeShape::Subroutine
+        | NodeShape::Trapezoid
+        | NodeShape::TrapezoidAlt => None,
+        NodeShape::Stadium => Some("oval"),
+        NodeShape::Circle | NodeShape::DoubleCircle | NodeShape::Junction => Some("circle"),
+        NodeShape::Diamond => Some("diamond"),
+        NodeShape::Cylinder => Some("cylinder"),
+        NodeShape::Hexagon => Some("hexagon"),
+        NodeShape::Parallelogram | NodeShape::ParallelogramAlt => Some("parallelogram"),
+        NodeShape::Asymmetric => Some("step"),
+    }
+}
+
+/// Translates a flowchart to D2. Subgraphs become containers, so edges name nodes by their
+/// container path; shapes map to the nearest D2 shape and dashed, thick and invisible edges
+/// to stroke styles.
+pub fn to_d2(diagram: &Diagram) -> Result<String> {
+    require_flowchart(diagram, "D2")?;
+    let containers = containers(diagram);
+    let path = |id: &str| {
+        containers
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .chain([id])
+            .map(d2_key)
+            .collect::<Vec<_>>()
+            .join(".")
+    };
+
+    let direction = match diagram.direction {
+        Direction::TopDown => "down",
+        Direction::BottomTop => "up",
+        Direction::LeftRight => "right",
+        Direction::RightLeft => "left",
+    };
+    let mut d2 = format!("direction: {direction}\n");
+    if let Some(title) = &diagram.config.title {
+        let _ = writeln!(
+            d2,
+            "title: {} {{\n  shape: text\n  near: top-center\n  style.font-size: 24\n}}",
+            d2_string(title)
+        );
+    }
+
+    fn write_node(d2: &mut String, id: &str, node: &Node, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let label = if node.shape == NodeShape::Junction {
+            String::new()
+        } else {
+            node.label.clone()
+        };
+        let mut styles = Vec::new();
+        if let Some(shape) = d2_shape(node.shape) {
+            styles.push(format!("shape: {shape}"));
+        }
+        match node.shape {
+            NodeShape::DoubleCircle => styles.push("style.double-border: true".to_string()),
+            NodeShape::Junction => styles.push("width: 12".to_string()),
+            _ => {}
+        }
+        let _ = write!(d2, "{indent}{}: {}", d2_key(id), d2_string(&label));
+        if styles.is_empty() {
+            d2.push('\n');
+        } else {
+            d2.push_str(" {\n");
+            for style in styles {
+                let _ = writeln!(d2, "{indent}  {style}");
+            }
+            let _ = writeln!(d2, "{indent}}}");
+        }
+    }
+    fn write_container(
+        d2: &mut String,
+        diagram: &Diagram,
+        subgraph: &Subgraph,
+        containers: &HashMap<String, Vec<String>>,
+        depth: usize,
+    ) {
+        let indent = "  ".repeat(depth);
+        let _ = writeln!(
+            d2,
+            "{indent}{}: {} {{",
+            d2_key(&subgraph.id),
+            d2_string(&subgraph.label)
+        );
+        for id in &diagram.order {
+            if containers.get(id).and_then(|chain| chain.last()) == Some(&subgraph.id)
+                && let Some(node) = diagram.nodes.get(id)
+            {
+                write_node(d2, id, node, depth + 1);
+            }
+        }
+        for child in &subgraph.children {
+            write_container(d2, diagram, child, containers, depth + 1);
+        }
+        let _ = writeln!(d2, "{indent}}}");
+    }
+    for subgraph in &diagram.subgraphs {
+        write_container(&mut d2, diagram, subgraph, &containers, 0);
+    }
+    for id in &diagram.order {
+        if !containers.contains_key(id)
+            && let Some(node) = diagram.nodes.get(id)
+        {
+            write_node(&mut d2, id, node, 0);
+        }
+    }
+
+    for edge in &diagram.edges {
+        let arrow = match edge.arrow {
+            EdgeArrowDirection::Forward => "->",
+            EdgeArrowDirection::Backward => "<-",
+            EdgeArrowDirection::Both => "<->",
+            EdgeArrowDirection::None => "--",
+        };
+        let _ = write!(d2, "{} {arrow} {}", path(&edge.from), path(&edge.to));
+        if let Some(label) = &edge.label {
+            let _ = write!(d2, ": {}", d2_string(label));
+        }
+        let style = match edge.kind {
+            EdgeKind::Solid => None,
+            EdgeKind::Dashed => Some("style.stroke-dash: 5"),
+            EdgeKind::Thick => Some("style.stroke-width: 4"),
+            EdgeKind::Invisible => Some("style.opacity: 0"),
+        };
+        match style {
+            Some(style) => {
+                let _ = writeln!(d2, " {{ {style} }}");
+            }
+            None => d2.push('\n'),
+        }
+    }
+    Ok(d2)
+}
@@ -0,0 +1,568 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+const GIT_BRANCH_PALETTE: [&str; 8] = [
+    "#4f46e5", "#10b981", "#f59e0b", "#ef4444", "#0ea5e9", "#8b5cf6", "#ec4899", "#14b8a6",
+];
+const GIT_MARGIN: f32 = 40.0;
+const GIT_COMMIT_STEP: f32 = 70.0;
+const GIT_LANE_GAP: f32 = 70.0;
+const GIT_COMMIT_RADIUS: f32 = 10.0;
+const GIT_LABEL_SPACE: f32 = 120.0;
+const GIT_MAIN_BRANCH: &str = "main";
+/// Approximate advance of the 11px commit and tag labels.
+const GIT_SMALL_CHAR_WIDTH: f32 = 6.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitCommitKind {
+    Normal,
+    Reverse,
+    Highlight,
+    Merge,
+    CherryPick,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitCommit {
+    pub id: String,
+    pub message: Option<String>,
+    pub tag: Option<String>,
+    pub kind: GitCommitKind,
+    /// Index into [`GitGraph::branches`].
+    pub branch: usize,
+    /// Indices into [`GitGraph::commits`]; merges list the merged branch head second.
+    pub parents: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitBranch {
+    pub name: String,
+    pub order: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GitGraph {
+    pub title: Option<String>,
+    pub direction: Direction,
+    pub branches: Vec<GitBranch>,
+    pub commits: Vec<GitCommit>,
+    pub original_source: String,
+}
+
+fn attribute_regex() -> &'static Regex {
+    static ATTRIBUTES: OnceLock<Regex> = OnceLock::new();
+    ATTRIBUTES.get_or_init(|| {
+        Regex::new(r#"(\w+)\s*:\s*(?:"([^"]*)"|(\S+))"#).expect("valid git attribute regex")
+    })
+}
+
+/// Splits `name id: "x" tag: "v1"` into the leading argument and its `key: value` attributes.
+fn split_attributes(rest: &str) -> (String, HashMap<String, String>) {
+    let regex = attribute_regex();
+    let argument_end = regex.find(rest).map_or(rest.len(), |found| found.start());
+    let attributes = regex
+        .captures_iter(rest)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), value.to_string())
+        })
+        .collect();
+    (rest[..argument_end].trim().to_string(), attributes)
+}
+
+fn commit_kind(value: Option<&String>) -> Result<GitCommitKind> {
+    match value.map(|value| value.to_ascii_uppercase()).as_deref() {
+        None | Some("NORMAL") => Ok(GitCommitKind::Normal),
+        Some("REVERSE") => Ok(GitCommitKind::Reverse),
+        Some("HIGHLIGHT") => Ok(GitCommitKind::Highlight),
+        Some(other) => {
+            bail!("unknown commit type '{other}'; expected NORMAL, REVERSE or HIGHLIGHT")
+        }
+    }
+}
+
+/// Parses `commit`, `branch`, `checkout`/`switch`, `merge` and `cherry-pick` statements.
+pub(crate) fn parse_git_graph(
+    header: &str,
+    lines: Vec<String>,
+    original_source: &str,
+) -> Result<Diagram> {
+    let direction = match header
+        .trim()
+        .trim_end_matches(':')
+        .split_whitespace()
+        .nth(1)
+        .map(|token| token.trim_end_matches(':'))
+    {
+        None | Some("LR") => Direction::LeftRight,
+        Some("TB") | Some("TD") => Direction::TopDown,
+        Some("BT") => Direction::BottomTop,
+        Some(other) => bail!("unsupported gitGraph orientation '{other}'; expected LR, TB or BT"),
+    };
+
+    let mut graph = GitGraph {
+        title: None,
+        direction,
+        branches: vec![GitBranch {
+            name: GIT_MAIN_BRANCH.to_string(),
+            order: None,
+        }],
+        commits: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+    let mut heads: Vec<Option<usize>> = vec![None];
+    let mut current = 0;
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("acctitle") || lower.starts_with("accdescr") {
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title ") {
+            graph.title = Some(decode_label(title.trim()));
+            continue;
+        }
+
+        let (statement, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (argument, attributes) = split_attributes(rest);
+        let branch_index = |graph: &GitGraph, name: &str| {
+            graph
+                .branches
+                .iter()
+                .position(|branch| branch.name == name)
+                .ok_or_else(|| anyhow::anyhow!("branch '{name}' does not exist"))
+        };
+        let ensure_unique = |graph: &GitGraph, id: &str| {
+            if graph.commits.iter().any(|commit| commit.id == id) {
+                bail!("commit id '{id}' is used more than once");
+            }
+            Ok(())
+        };
+
+        match statement {
+            "commit" => {
+                let id = attributes
+                    .get("id")
+                    .cloned()
+                    .unwrap_or_else(|| format!("c{}", graph.commits.len()));
+                ensure_unique(&graph, &id)?;
+                graph.commits.push(GitCommit {
+                    id,
+                    message: attributes.get("msg").cloned(),
+                    tag: attributes.get("tag").cloned(),
+                    kind: commit_kind(attributes.get("type"))?,
+                    branch: current,
+                    parents: heads[current].into_iter().collect(),
+                });
+                heads[current] = Some(graph.commits.len() - 1);
+            }
+            "branch" => {
+                if argument.is_empty() {
+                    bail!("'branch' needs a branch name");
+                }
+                if graph.branches.iter().any(|branch| branch.name == argument) {
+                    bail!("branch '{argument}' already exists");
+                }
+                let order = match attributes.get("order") {
+                    Some(order) => Some(order.parse().map_err(|_| {
+                        anyhow::anyhow!("branch '{argument}' has invalid order '{order}'")
+                    })?),
+                    None => None,
+                };
+                graph.branches.push(GitBranch {
+                    name: argument,
+                    order,
+                });
+                heads.push(heads[current]);
+                current = graph.branches.len() - 1;
+            }
+            "checkout" | "switch" => {
+                current = branch_index(&graph, &argument)?;
+            }
+            "merge" => {
+                let source = branch_index(&graph, &argument)?;
+                if source == current {
+                    bail!("cannot merge branch '{argument}' into itself");
+                }
+                let (Some(target_head), Some(source_head)) = (heads[current], heads[source]) else {
+                    bail!(
+                        "cannot merge '{argument}' into '{}' before both have commits",
+                        graph.branches[current].name
+                    );
+                };
+                if target_head == source_head {
+                    bail!(
+                        "branch '{argument}' has no new commits to merge into '{}'",
+                        graph.branches[current].name
+                    );
+                }
+                let id = attributes
+                    .get("id")
+                    .cloned()
+                    .unwrap_or_else(|| format!("c{}", graph.commits.len()));
+                ensure_unique(&graph, &id)?;
+                let kind = match attributes.get("type") {
+                    Some(_) => commit_kind(attributes.get("type"))?,
+                    None => GitCommitKind::Merge,
+                };
+                graph.commits.push(GitCommit {
+                    id,
+                    message: None,
+                    tag: attributes.get("tag").cloned(),
+                    kind,
+                    branch: current,
+                    parents: vec![target_head, source_head],
+                });
+                heads[current] = Some(graph.commits.len() - 1);
+            }
+            "cherry-pick" => {
+                let Some(source_id) = attributes.get("id") else {
+                    bail!("'cherry-pick' needs the id of the commit to copy");
+                };
+                let Some(source) = graph.commits.iter().position(|c| &c.id == source_id) else {
+                    bail!("cherry-picked commit '{source_id}' does not exist");
+                };
+                if graph.commits[source].branch == current {
+                    bail!("commit '{source_id}' is already on the current branch");
+                }
+                let id = format!("{source_id}-cherry-pick");
+                ensure_unique(&graph, &id)?;
+                graph.commits.push(GitCommit {
+                    id,
+                    message: None,
+                    tag: attributes.get("tag").cloned(),
+                    kind: GitCommitKind::CherryPick,
+                    branch: current,
+                    parents: heads[current].into_iter().chain([source]).collect(),
+                });
+                heads[current] = Some(graph.commits.len() - 1);
+            }
+            other => bail!("unsupported gitGraph statement '{other}'"),
+        }
+    }
+
+    if graph.commits.is_empty() {
+        bail!("gitGraph does not contain any commits");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    let mut edges = Vec::new();
+    for commit in &graph.commits {
+        nodes.insert(
+            commit.id.clone(),
+            Node {
+                label: commit.message.clone().unwrap_or_else(|| commit.id.clone()),
+                shape: NodeShape::Circle,
+                image: None,
+                width: GIT_COMMIT_RADIUS * 2.0,
+                height: GIT_COMMIT_RADIUS * 2.0,
+            },
+        );
+        order.push(commit.id.clone());
+        for &parent in &commit.parents {
+            edges.push(Edge {
+                from: graph.commits[parent].id.clone(),
+                to: commit.id.clone(),
+                label: None,
+                kind: EdgeKind::Solid,
+                arrow: EdgeArrowDirection::None,
+                head: EdgeHead::Arrow,
+                occurrence: 0,
+            });
+        }
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        direction: graph.direction,
+        kind: DiagramKind::GitGraph(graph),
+        nodes,
+        order,
+        edges,
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+fn tag_width(tag: &str) -> f32 {
+    display_width(tag) as f32 * GIT_SMALL_CHAR_WIDTH + 14.0
+}
+
+impl GitGraph {
+    /// Lane of each branch by `order:`, defaulting to the declaration index.
+    pub fn lanes(&self) -> Vec<usize> {
+        let mut ranked: Vec<usize> = (0..self.branches.len()).collect();
+        ranked.sort_by_key(|&idx| (self.branches[idx].order.unwrap_or(idx as i64), idx));
+        let mut lanes = vec![0; self.branches.len()];
+        for (lane, idx) in ranked.into_iter().enumerate() {
+            lanes[idx] = lane;
+        }
+        lanes
+    }
+
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&GIT_BRANCH_PALETTE);
+        let lanes = self.lanes();
+        let vertical = !matches!(self.direction, Direction::LeftRight);
+        let title_height = if self.title.is_some() { 48.0 } else { 0.0 };
+        let lane_span = GIT_LANE_GAP * (self.branches.len() as f32 - 1.0);
+        let commit_span = GIT_COMMIT_STEP * (self.commits.len() as f32 - 1.0);
+
+        let annotation_width = self
+            .commits
+            .iter()
+            .map(|commit| {
+                let label = commit.message.as_deref().unwrap_or(&commit.id);
+                let tag = commit.tag.as_ref().map_or(0.0, |tag| tag_width(tag) + 8.0);
+                display_width(label) as f32 * GIT_SMALL_CHAR_WIDTH + tag
+            })
+            .fold(GIT_LABEL_SPACE, f32::max);
+        let (width, height) = if vertical {
+            (
+                GIT_MARGIN * 2.0 + 30.0 + lane_span + GIT_COMMIT_RADIUS + 8.0 + annotation_width,
+                GIT_MARGIN * 3.0 + title_height + commit_span + 30.0,
+            )
+        } else {
+            (
+                GIT_MARGIN * 2.0 + GIT_LABEL_SPACE + commit_span,
+                GIT_MARGIN * 3.0 + title_height + lane_span + 30.0,
+            )
+        };
+        let origin_x = GIT_MARGIN + if vertical { 30.0 } else { GIT_LABEL_SPACE };
+        let origin_y = GIT_MARGIN + title_height + 30.0;
+        let position = |commit: usize| {
+            let lane = GIT_LANE_GAP * lanes[self.commits[commit].branch] as f32;
+            let step = GIT_COMMIT_STEP * commit as f32;
+            match self.direction {
+                Direction::LeftRight | Direction::RightLeft => Point {
+                    x: origin_x + step,
+                    y: origin_y + lane,
+                },
+                Direction::TopDown => Point {
+                    x: origin_x + lane,
+                    y: origin_y + step,
+                },
+                Direction::BottomTop => Point {
+                    x: origin_x + lane,
+                    y: origin_y + commit_span - step,
+                },
+            }
+        };
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                GIT_MARGIN + 20.0,
+                escape_xml(title)
+            )?;
+        }
+
+        for (idx, branch) in self.branches.iter().enumerate() {
+            let color = colors[idx % colors.len()];
+            let lane = GIT_LANE_GAP * lanes[idx] as f32;
+            let (x1, y1, x2, y2) = if vertical {
+                (
+                    origin_x + lane,
+                    origin_y - 20.0,
+                    origin_x + lane,
+                    origin_y + commit_span + 20.0,
+                )
+            } else {
+                (
+                    origin_x - 20.0,
+                    origin_y + lane,
+                    origin_x + commit_span + 20.0,
+                    origin_y + lane,
+                )
+            };
+            writeln!(
+                svg,
+                "  <line class=\"git-lane\" x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-opacity=\"0.35\" stroke-width=\"2\" stroke-dasharray=\"2 6\" />",
+                x1, y1, x2, y2, color
+            )?;
+            let label_width = display_width(&branch.name) as f32 * NODE_TEXT_CHAR_WIDTH + 20.0;
+            let (label_x, label_y) = if vertical {
+                (origin_x + lane, GIT_MARGIN + title_height + 4.0)
+            } else {
+                (GIT_MARGIN + label_width / 2.0, origin_y + lane)
+            };
+            writeln!(
+                svg,
+                "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"24\" rx=\"6\" ry=\"6\" fill=\"{}\" />\n  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"13\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                label_x - label_width / 2.0,
+                label_y - 12.0,
+                label_width,
+                color,
+                label_x,
+                label_y,
+                readable_text_color(color),
+                escape_xml(&branch.name)
+            )?;
+        }
+
+        for (idx, commit) in self.commits.iter().enumerate() {
+            let to = position(idx);
+            for (parent_slot, &parent) in commit.parents.iter().enumerate() {
+                let from = position(parent);
+                // Branch-offs take the child's color; merges and cherry-picks the source's.
+                let color_branch = if parent_slot > 0 {
+                    self.commits[parent].branch
+                } else {
+                    commit.branch
+                };
+                let color = colors[color_branch % colors.len()];
+                let dash = if commit.kind == GitCommitKind::CherryPick && parent_slot > 0 {
+                    " stroke-dasharray=\"6 4\""
+                } else {
+                    ""
+                };
+                let path = if (from.x - to.x).abs() < 0.5 || (from.y - to.y).abs() < 0.5 {
+                    format!("M{:.1},{:.1} L{:.1},{:.1}", from.x, from.y, to.x, to.y)
+                } else if vertical {
+                    let mid = (from.y + to.y) / 2.0;
+                    format!(
+                        "M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+                        from.x, from.y, from.x, mid, to.x, mid, to.x, to.y
+                    )
+                } else {
+                    let mid = (from.x + to.x) / 2.0;
+                    format!(
+                        "M{:.1},{:.1} C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+                        from.x, from.y, mid, from.y, mid, to.y, to.x, to.y
+                    )
+                };
+                writeln!(
+                    svg,
+                    "  <path class=\"git-edge\" d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"3\"{} />",
+                    path, color, dash
+                )?;
+            }
+        }
+
+        for (idx, commit) in self.commits.iter().enumerate() {
+            let center = position(idx);
+            let color = colors[commit.branch % colors.len()];
+            writeln!(
+                svg,
+                "  <g class=\"git-commit\" data-id=\"{}\">",
+                escape_xml(&commit.id)
+            )?;
+            match commit.kind {
+                GitCommitKind::Highlight => writeln!(
+                    svg,
+                    "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"#1a202c\" stroke-width=\"2\" />",
+                    center.x - GIT_COMMIT_RADIUS,
+                    center.y - GIT_COMMIT_RADIUS,
+                    GIT_COMMIT_RADIUS * 2.0,
+                    GIT_COMMIT_RADIUS * 2.0,
+                    color
+                )?,
+                GitCommitKind::Merge => writeln!(
+                    svg,
+                    "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" />\n    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"#ffffff\" />",
+                    center.x,
+                    center.y,
+                    GIT_COMMIT_RADIUS,
+                    color,
+                    center.x,
+                    center.y,
+                    GIT_COMMIT_RADIUS / 2.0
+                )?,
+                GitCommitKind::Reverse => {
+                    let arm = GIT_COMMIT_RADIUS / 2.0;
+                    writeln!(
+                        svg,
+                        "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" />\n    <path d=\"M{:.1},{:.1} L{:.1},{:.1} M{:.1},{:.1} L{:.1},{:.1}\" stroke=\"#ffffff\" stroke-width=\"2.5\" />",
+                        center.x,
+                        center.y,
+                        GIT_COMMIT_RADIUS,
+                        color,
+                        center.x - arm,
+                        center.y - arm,
+                        center.x + arm,
+                        center.y + arm,
+                        center.x - arm,
+                        center.y + arm,
+                        center.x + arm,
+                        center.y - arm
+                    )?
+                }
+                GitCommitKind::Normal | GitCommitKind::CherryPick => writeln!(
+                    svg,
+                    "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"2\" />",
+                    center.x, center.y, GIT_COMMIT_RADIUS, color
+                )?,
+            }
+
+            let label = commit.message.as_deref().unwrap_or(&commit.id);
+            let (label_x, label_y, anchor) = if vertical {
+                (center.x + GIT_COMMIT_RADIUS + 8.0, center.y, "start")
+            } else {
+                (center.x, center.y + GIT_COMMIT_RADIUS + 16.0, "middle")
+            };
+            writeln!(
+                svg,
+                "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#4a5568\" font-size=\"11\" text-anchor=\"{}\" dominant-baseline=\"middle\">{}</text>",
+                label_x,
+                label_y,
+                anchor,
+                escape_xml(label)
+            )?;
+
+            if let Some(tag) = &commit.tag {
+                let tag_width = tag_width(tag);
+                let (tag_x, tag_y) = if vertical {
+                    (
+                        label_x
+                            + display_width(label) as f32 * GIT_SMALL_CHAR_WIDTH
+                            + 8.0
+                            + tag_width / 2.0,
+                        center.y,
+                    )
+                } else {
+                    (center.x, center.y - GIT_COMMIT_RADIUS - 16.0)
+                };
+                writeln!(
+                    svg,
+                    "    <rect class=\"git-tag\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"18\" rx=\"4\" ry=\"4\" fill=\"#fefcbf\" stroke=\"#b7791f\" stroke-width=\"1\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#744210\" font-size=\"11\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                    tag_x - tag_width / 2.0,
+                    tag_y - 9.0,
+                    tag_width,
+                    tag_x,
+                    tag_y,
+                    escape_xml(tag)
+                )?;
+            }
+            svg.push_str("  </g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result, anyhow, bail};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::process::{Command, Stdio};
+
+use crate::*;
+
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// Program run by the `dot` layout engine; `OXDRAW_DOT` points at a different binary.
+fn dot_program() -> String {
+    std::env::var("OXDRAW_DOT").unwrap_or_else(|_| "dot".to_string())
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Translates the diagram's graph to DOT. Nodes keep their oxdraw size, subgraphs become
+/// clusters, and spacing follows `nodeSpacing`/`rankSpacing` from the frontmatter.
+pub fn to_dot(diagram: &Diagram) -> String {
+    let rankdir = match diagram.direction {
+        Direction::TopDown => "TB",
+        Direction::BottomTop => "BT",
+        Direction::LeftRight => "LR",
+        Direction::RightLeft => "RL",
+    };
+    let inches = |points: f32| points / POINTS_PER_INCH;
+    let nodesep = diagram
+        .config
+        .node_spacing
+        .unwrap_or(NODE_SPACING - NODE_WIDTH);
+    let ranksep = diagram
+        .config
+        .rank_spacing
+        .unwrap_or(NODE_SPACING - NODE_HEIGHT);
+
+    let mut dot = String::from("digraph {\n");
+    let _ = writeln!(
+        dot,
+        "  graph [rankdir={rankdir}, nodesep={:.3}, ranksep={:.3}];",
+        inches(nodesep),
+        inches(ranksep)
+    );
+    dot.push_str("  node [shape=box, fixedsize=true, label=\"\"];\n");
+    for id in &diagram.order {
+        let Some(node) = diagram.nodes.get(id) else {
+            continue;
+        };
+        let _ = writeln!(
+            dot,
+            "  {} [width={:.3}, height={:.3}];",
+            quote(id),
+            inches(node.width),
+            inches(node.height)
+        );
+    }
+    for subgraph in &diagram.subgraphs {
+        write_cluster(&mut dot, subgraph, 1);
+    }
+    for edge in &diagram.edges {
+        let _ = writeln!(dot, "  {} -> {};", quote(&edge.from), quote(&edge.to));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn write_cluster(dot: &mut String, subgraph: &Subgraph, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let _ = writeln!(
+        dot,
+        "{indent}subgraph {} {{",
+        quote(&format!("cluster_{}", subgraph.id))
+    );
+    for id in &subgraph.nodes {
+        let _ = writeln!(dot, "{indent}  {};", quote(id));
+    }
+    for child in &subgraph.children {
+        write_cluster(dot, child, depth + 1);
+    }
+    let _ = writeln!(dot, "{indent}}}");
+}
+
+/// Splits a `-Tplain` line into words, unquoting `"..."` names.
+fn plain_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut word = String::new();
+        if ch == '"' {
+            chars.next();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => word.extend(chars.next()),
+                    '"' => break,
+                    _ => word.push(ch),
+                }
+            }
+        } else {
+            while let Some(&ch) = chars.peek() {
+                if ch.is_whitespace() {
+                    break;
+                }
+                word.push(ch);
+                chars.next();
+            }
+        }
+        words.push(word);
+    }
+    words
+}
+
+/// Reads node centres out of Graphviz `-Tplain` output, flipping its y-up inches into
+/// oxdraw's y-down points offset by [`START_OFFSET`].
+pub fn parse_plain_positions(plain: &str) -> Result<HashMap<String, Point>> {
+    let mut graph_height = None;
+    let mut positions = HashMap::new();
+    for line in plain.lines() {
+        let words = plain_words(line);
+        let malformed = || anyhow!("malformed Graphviz output line '{line}'");
+        let number = |idx: usize| -> Result<f32> {
+            words
+                .get(idx)
+                .and_then(|word| word.parse::<f32>().ok())
+                .ok_or_else(malformed)
+        };
+        match words.first().map(String::as_str) {
+            Some("graph") => graph_height = Some(number(3)?),
+            Some("node") => {
+                let height = graph_height
+                    .ok_or_else(|| anyhow!("Graphviz output is missing its graph line"))?;
+                positions.insert(
+                    words.get(1).ok_or_else(malformed)?.clone(),
+                    Point {
+                        x: START_OFFSET + number(2)? * POINTS_PER_INCH,
+                        y: START_OFFSET + (height - number(3)?) * POINTS_PER_INCH,
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(positions)
+}
+
+/// Places the diagram's nodes by running Graphviz `dot` on [`to_dot`]'s output.
+pub fn dot_positions(diagram: &Diagram) -> Result<HashMap<String, Point>> {
+    let program = dot_program();
+    let mut child = Command::new(&program)
+        .arg("-Tplain")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run Graphviz '{program}'; is it installed?"))?;
+    child
+        .stdin
+        .take()
+        .context("Graphviz stdin unavailable")?
+        .write_all(to_dot(diagram).as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "Graphviz '{program}' failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let positions = parse_plain_positions(&String::from_utf8_lossy(&output.stdout))?;
+    if let Some(missing) = diagram.order.iter().find(|id| !positions.contains_key(*id)) {
+        bail!("Graphviz did not place node '{missing}'");
+    }
+    Ok(positions)
+}
@@ -0,0 +1,436 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::{ContrastWarning, PhaseTiming};
+
+/// Languages the CLI and server can report status in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Zh,
+}
+
+impl Locale {
+    /// Parses tags such as `zh`, `zh_CN.UTF-8` or `en-US`.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::En),
+            "zh" => Some(Locale::Zh),
+            _ => None,
+        }
+    }
+
+    /// `OXDRAW_LANG` wins over the usual `LC_ALL`, `LC_MESSAGES` and `LANG` variables.
+    pub fn from_env() -> Self {
+        ["OXDRAW_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::from_tag(&value))
+            .unwrap_or_default()
+    }
+}
+
+pub fn locale() -> Locale {
+    static LOCALE: OnceLock<Locale> = OnceLock::new();
+    *LOCALE.get_or_init(Locale::from_env)
+}
+
+/// User-facing status lines printed by the CLI and the editor server.
+#[derive(Debug, Clone, Copy)]
+pub enum UiMessage<'a> {
+    Error,
+    Warning(&'a str),
+    LaunchingEditor(&'a Path),
+    LoadedWebUi(&'a Path),
+    VisitToEdit {
+        host: &'a str,
+        port: u16,
+    },
+    Visit {
+        host: &'a str,
+        port: u16,
+    },
+    CreatingGraph(&'a str),
+    Location(&'a Path),
+    GraphType(&'a str),
+    LaunchingCodeMapViewer {
+        existing: bool,
+    },
+    CodeMapSaved(&'a Path),
+    RenderedDiagramSaved(&'a Path),
+    LaunchingCodedownViewer {
+        existing: bool,
+    },
+    CodedownSaved(&'a Path),
+    AugmentedMarkdownSaved(&'a Path),
+    ExportsUpToDate,
+    ExportStale {
+        path: &'a Path,
+        reason: &'a str,
+    },
+    ExportRegenerated {
+        path: &'a Path,
+        reason: &'a str,
+    },
+    GeneratedDiagram(&'a Path),
+    ServerListening(&'a str),
+    PressCtrlC,
+    AmbiguousFile {
+        name: &'a str,
+        matches: usize,
+        selected: &'a Path,
+    },
+    GitCommitFailed(&'a str),
+    WebhookFailed(&'a str),
+    LowContrast(&'a ContrastWarning),
+    PhaseTiming(&'a PhaseTiming),
+    Watching(&'a Path),
+    WatchRendered {
+        path: &'a Path,
+        millis: f64,
+    },
+    BatchRendered {
+        rendered: usize,
+        total: usize,
+    },
+    ImportedDot {
+        from: &'a Path,
+        to: &'a Path,
+    },
+}
+
+impl UiMessage<'_> {
+    pub fn localize(&self, locale: Locale) -> String {
+        use UiMessage::*;
+        match (locale, *self) {
+            (Locale::En, Error) => "error:".to_string(),
+            (Locale::Zh, Error) => "错误：".to_string(),
+            (Locale::En, Warning(message)) => format!("Warning: {message}"),
+            (Locale::Zh, Warning(message)) => format!("警告：{message}"),
+            (Locale::En, LaunchingEditor(path)) => {
+                format!("Launching editor for {}", path.display())
+            }
+            (Locale::Zh, LaunchingEditor(path)) => format!("正在为 {} 启动编辑器", path.display()),
+            (Locale::En, LoadedWebUi(path)) => format!("Loaded web UI from {}", path.display()),
+            (Locale::Zh, LoadedWebUi(path)) => format!("已从 {} 加载网页界面", path.display()),
+            (Locale::En, VisitToEdit { host, port }) => {
+                format!("Visit http://{host}:{port} in your browser to begin editing")
+            }
+            (Locale::Zh, VisitToEdit { host, port }) => {
+                format!("在浏览器中打开 http://{host}:{port} 开始编辑")
+            }
+            (Locale::En, Visit { host, port }) => {
+                format!("Visit http://{host}:{port} in your browser")
+            }
+            (Locale::Zh, Visit { host, port }) => format!("在浏览器中打开 http://{host}:{port}"),
+            (Locale::En, CreatingGraph(name)) => format!("Creating Mermaid graph '{name}'."),
+            (Locale::Zh, CreatingGraph(name)) => format!("正在创建 Mermaid 图“{name}”。"),
+            (Locale::En, Location(path)) => format!("Location: {}", path.display()),
+            (Locale::Zh, Location(path)) => format!("位置：{}", path.display()),
+            (Locale::En, GraphType(label)) => format!("Graph type: {label}"),
+            (Locale::Zh, GraphType(label)) => format!("图类型：{label}"),
+            (Locale::En, LaunchingCodeMapViewer { existing: true }) => {
+                "Launching code map viewer for existing map...".to_string()
+            }
+            (Locale::En, LaunchingCodeMapViewer { existing: false }) => {
+                "Launching code map viewer...".to_string()
+            }
+            (Locale::Zh, LaunchingCodeMapViewer { existing: true }) => {
+                "正在为已有代码地图启动查看器...".to_string()
+            }
+            (Locale::Zh, LaunchingCodeMapViewer { existing: false }) => {
+                "正在启动代码地图查看器...".to_string()
+            }
+            (Locale::En, CodeMapSaved(path)) => format!("Code map saved to {}", path.display()),
+            (Locale::Zh, CodeMapSaved(path)) => format!("代码地图已保存到 {}", path.display()),
+            (Locale::En, RenderedDiagramSaved(path)) => {
+                format!("Rendered diagram saved to {}", path.display())
+            }
+            (Locale::Zh, RenderedDiagramSaved(path)) => {
+                format!("渲染后的图已保存到 {}", path.display())
+            }
+            (Locale::En, LaunchingCodedownViewer { existing: true }) => {
+                "Launching codedown viewer for existing file...".to_string()
+            }
+            (Locale::En, LaunchingCodedownViewer { existing: false }) => {
+                "Launching codedown viewer...".to_string()
+            }
+            (Locale::Zh, LaunchingCodedownViewer { existing: true }) => {
+                "正在为已有文件启动 codedown 查看器...".to_string()
+            }
+            (Locale::Zh, LaunchingCodedownViewer { existing: false }) => {
+                "正在启动 codedown 查看器...".to_string()
+            }
+            (Locale::En, CodedownSaved(path)) => format!("Codedown saved to {}", path.display()),
+            (Locale::Zh, CodedownSaved(path)) => format!("Codedown 已保存到 {}", path.display()),
+            (Locale::En, AugmentedMarkdownSaved(path)) => {
+                format!("Augmented markdown saved to {}", path.display())
+            }
+            (Locale::Zh, AugmentedMarkdownSaved(path)) => {
+                format!("增强后的 Markdown 已保存到 {}", path.display())
+            }
+            (Locale::En, ExportsUpToDate) => "All exports are up to date.".to_string(),
+            (Locale::Zh, ExportsUpToDate) => "所有导出均已是最新。".to_string(),
+            (Locale::En, ExportStale { path, reason }) => {
+                format!("Stale {} ({reason})", path.display())
+            }
+            (Locale::Zh, ExportStale { path, reason }) => {
+                format!("已过期 {}（{reason}）", path.display())
+            }
+            (Locale::En, ExportRegenerated { path, reason }) => {
+                format!("Regenerated {} ({reason})", path.display())
+            }
+            (Locale::Zh, ExportRegenerated { path, reason }) => {
+                format!("已重新生成 {}（{reason}）", path.display())
+            }
+            (Locale::En, GeneratedDiagram(path)) => {
+                format!("Generated diagram -> {}", path.display())
+            }
+            (Locale::Zh, GeneratedDiagram(path)) => format!("已生成图 -> {}", path.display()),
+            (Locale::En, ServerListening(addr)) => {
+                format!("oxdraw server listening on http://{addr}")
+            }
+            (Locale::Zh, ServerListening(addr)) => format!("oxdraw 服务器正在监听 http://{addr}"),
+            (Locale::En, PressCtrlC) => "Press Ctrl+C to stop.".to_string(),
+            (Locale::Zh, PressCtrlC) => "按 Ctrl+C 停止。".to_string(),
+            (
+                Locale::En,
+                AmbiguousFile {
+                    name,
+                    matches,
+                    selected,
+                },
+            ) => format!(
+                "Ambiguous file request '{name}'. Found {matches} matches. Selecting '{}'.",
+                selected.display()
+            ),
+            (
+                Locale::Zh,
+                AmbiguousFile {
+                    name,
+                    matches,
+                    selected,
+                },
+            ) => format!(
+                "文件请求“{name}”有歧义，找到 {matches} 个匹配，已选择“{}”。",
+                selected.display()
+            ),
+            (Locale::En, GitCommitFailed(err)) => format!("git commit failed: {err}"),
+            (Locale::Zh, GitCommitFailed(err)) => format!("git 提交失败：{err}"),
+            (Locale::En, WebhookFailed(err)) => format!("webhook delivery failed: {err}"),
+            (Locale::Zh, WebhookFailed(err)) => format!("webhook 投递失败：{err}"),
+            (Locale::En, LowContrast(warning)) => format!(
+                "Warning: node '{}' text {} on {} has contrast {:.2}:1 (below 4.5:1); try text color {}",
+                warning.node_id,
+                warning.text,
+                warning.background,
+                warning.ratio,
+                warning.suggested_text
+            ),
+            (Locale::Zh, LowContrast(warning)) => format!(
+                "警告：节点“{}”的文字颜色 {} 在 {} 上的对比度为 {:.2}:1（低于 4.5:1），建议改用 {}",
+                warning.node_id,
+                warning.text,
+                warning.background,
+                warning.ratio,
+                warning.suggested_text
+            ),
+            (Locale::En, PhaseTiming(timing)) => {
+                format!("{:<10} {:>9.2} ms", timing.phase, timing.millis)
+            }
+            (Locale::Zh, PhaseTiming(timing)) => {
+                format!("{:<10} {:>9.2} 毫秒", timing.phase, timing.millis)
+            }
+            (Locale::En, Watching(path)) => {
+                format!(
+                    "Watching {} for changes. Press Ctrl+C to stop.",
+                    path.display()
+                )
+            }
+            (Locale::Zh, Watching(path)) => {
+                format!("正在监视 {} 的更改。按 Ctrl+C 停止。", path.display())
+            }
+            (Locale::En, WatchRendered { path, millis }) => {
+                format!("Rendered {} in {millis:.1} ms", path.display())
+            }
+            (Locale::Zh, WatchRendered { path, millis }) => {
+                format!("已渲染 {}，用时 {millis:.1} 毫秒", path.display())
+            }
+            (Locale::En, BatchRendered { rendered, total }) => {
+                format!("Rendered {rendered} of {total} diagrams")
+            }
+            (Locale::Zh, BatchRendered { rendered, total }) => {
+                format!("已渲染 {total} 个图中的 {rendered} 个")
+            }
+            (Locale::En, ImportedDot { from, to }) => {
+                format!(
+                    "Imported {} as {}; the editor saves to the Mermaid file",
+                    from.display(),
+                    to.display()
+                )
+            }
+            (Locale::Zh, ImportedDot { from, to }) => {
+                format!(
+                    "已将 {} 导入为 {}；编辑器会保存到该 Mermaid 文件",
+                    from.display(),
+                    to.display()
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for UiMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(locale()))
+    }
+}
+
+/// Prints a status line on stderr so it never mixes with diagram data written to stdout.
+pub fn status(message: UiMessage<'_>) {
+    eprintln!("{message}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_locale_from_tags() {
+        assert_eq!(Locale::from_tag("zh_CN.UTF-8"), Some(Locale::Zh));
+        assert_eq!(Locale::from_tag("zh-TW"), Some(Locale::Zh));
+        assert_eq!(Locale::from_tag("en_US.UTF-8"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr_FR"), None);
+
+        let message = UiMessage::ExportsUpToDate;
+        assert_eq!(message.localize(Locale::En), "All exports are up to date.");
+        assert_eq!(message.localize(Locale::Zh), "所有导出均已是最新。");
+    }
+
+    #[test]
+    fn every_message_has_a_chinese_translation() {
+        let path = Path::new("diagram.mmd");
+        let contrast = ContrastWarning {
+            node_id: "A".to_string(),
+            text: "#777777".to_string(),
+            background: "#888888".to_string(),
+            ratio: 1.1,
+            suggested_text: "#000000".to_string(),
+        };
+        let timing = PhaseTiming {
+            phase: "parse",
+            millis: 1.0,
+        };
+        let address = "127.0.0.1:5151";
+        let messages = [
+            UiMessage::Error,
+            UiMessage::Warning("careful"),
+            UiMessage::LaunchingEditor(path),
+            UiMessage::LoadedWebUi(path),
+            UiMessage::VisitToEdit {
+                host: "127.0.0.1",
+                port: 5151,
+            },
+            UiMessage::Visit {
+                host: "127.0.0.1",
+                port: 5151,
+            },
+            UiMessage::CreatingGraph("diagram"),
+            UiMessage::Location(path),
+            UiMessage::GraphType("flowchart"),
+            UiMessage::LaunchingCodeMapViewer { existing: true },
+            UiMessage::LaunchingCodeMapViewer { existing: false },
+            UiMessage::CodeMapSaved(path),
+            UiMessage::RenderedDiagramSaved(path),
+            UiMessage::LaunchingCodedownViewer { existing: true },
+            UiMessage::LaunchingCodedownViewer { existing: false },
+            UiMessage::CodedownSaved(path),
+            UiMessage::AugmentedMarkdownSaved(path),
+            UiMessage::ExportsUpToDate,
+            UiMessage::ExportStale {
+                path,
+                reason: "source changed",
+            },
+            UiMessage::ExportRegenerated {
+                path,
+                reason: "source changed",
+            },
+            UiMessage::GeneratedDiagram(path),
+            UiMessage::ServerListening(address),
+            UiMessage::PressCtrlC,
+            UiMessage::AmbiguousFile {
+                name: "main.rs",
+                matches: 2,
+                selected: path,
+            },
+            UiMessage::GitCommitFailed("exit status 1"),
+            UiMessage::WebhookFailed("timed out"),
+            UiMessage::LowContrast(&contrast),
+            UiMessage::PhaseTiming(&timing),
+            UiMessage::Watching(path),
+            UiMessage::WatchRendered { path, millis: 1.0 },
+            UiMessage::BatchRendered {
+                rendered: 1,
+                total: 2,
+            },
+            UiMessage::ImportedDot {
+                from: path,
+                to: path,
+            },
+        ];
+
+        let mut seen = std::collections::HashSet::new();
+        for message in &messages {
+            // Listing every variant here means a new message does not compile until it is
+            // added to `messages` as well.
+            let name = match message {
+                UiMessage::Error => "Error",
+                UiMessage::Warning(_) => "Warning",
+                UiMessage::LaunchingEditor(_) => "LaunchingEditor",
+                UiMessage::LoadedWebUi(_) => "LoadedWebUi",
+                UiMessage::VisitToEdit { .. } => "VisitToEdit",
+                UiMessage::Visit { .. } => "Visit",
+                UiMessage::CreatingGraph(_) => "CreatingGraph",
+                UiMessage::Location(_) => "Location",
+                UiMessage::GraphType(_) => "GraphType",
+                UiMessage::LaunchingCodeMapViewer { .. } => "LaunchingCodeMapViewer",
+                UiMessage::CodeMapSaved(_) => "CodeMapSaved",
+                UiMessage::RenderedDiagramSaved(_) => "RenderedDiagramSaved",
+                UiMessage::LaunchingCodedownViewer { .. } => "LaunchingCodedownViewer",
+                UiMessage::CodedownSaved(_) => "CodedownSaved",
+                UiMessage::AugmentedMarkdownSaved(_) => "AugmentedMarkdownSaved",
+                UiMessage::ExportsUpToDate => "ExportsUpToDate",
+                UiMessage::ExportStale { .. } => "ExportStale",
+                UiMessage::ExportRegenerated { .. } => "ExportRegenerated",
+                UiMessage::GeneratedDiagram(_) => "GeneratedDiagram",
+                UiMessage::ServerListening(_) => "ServerListening",
+                UiMessage::PressCtrlC => "PressCtrlC",
+                UiMessage::AmbiguousFile { .. } => "AmbiguousFile",
+                UiMessage::GitCommitFailed(_) => "GitCommitFailed",
+                UiMessage::WebhookFailed(_) => "WebhookFailed",
+                UiMessage::LowContrast(_) => "LowContrast",
+                UiMessage::PhaseTiming(_) => "PhaseTiming",
+                UiMessage::Watching(_) => "Watching",
+                UiMessage::WatchRendered { .. } => "WatchRendered",
+                UiMessage::BatchRendered { .. } => "BatchRendered",
+                UiMessage::ImportedDot { .. } => "ImportedDot",
+            };
+            seen.insert(name);
+
+            let english = message.localize(Locale::En);
+            let chinese = message.localize(Locale::Zh);
+            assert!(!chinese.trim().is_empty(), "{name} has no Chinese text");
+            assert_ne!(chinese, english, "{name} is not translated");
+        }
+        assert_eq!(seen.len(), 30, "every variant needs a sample message");
+    }
+}
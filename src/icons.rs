@@ -0,0 +1,217 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::escape_xml;
+
+/// Side of the square an icon is drawn in, beside the first line of its label.
+pub const NODE_ICON_SIZE: f32 = 16.0;
+/// Space between an icon and the label text.
+pub const NODE_ICON_GAP: f32 = 6.0;
+
+/// Built-in icons as stroked outlines in a 16×16 box, keyed by Font Awesome name.
+const BUILTIN_ICONS: &[(&str, &str)] = &[
+    (
+        "bell",
+        "M4 11.5V7a4 4 0 0 1 8 0v4.5l1.5 1.5h-11zM6.5 14.5h3",
+    ),
+    ("bolt", "M9 1 3 9h4.5L7 15l6-8H8.5z"),
+    (
+        "box",
+        "M8 1.5 14.5 5v6L8 14.5 1.5 11V5zM1.5 5 8 8.5 14.5 5M8 8.5v6",
+    ),
+    (
+        "calendar",
+        "M1.5 3h13v11.5h-13zM1.5 6.5h13M5 1.5v3M11 1.5v3",
+    ),
+    (
+        "chart-bar",
+        "M1.5 14.5h13M3.5 14.5V8M7 14.5V4M10.5 14.5V6.5M13.5 14.5V9",
+    ),
+    ("check", "M2.5 8.5 6 12l7.5-8"),
+    (
+        "clock",
+        "M8 14.5a6.5 6.5 0 1 0 0-13 6.5 6.5 0 0 0 0 13zM8 4v4l3 2",
+    ),
+    (
+        "cloud",
+        "M4.5 13h7.5a3 3 0 0 0 .4-6A4.5 4.5 0 0 0 3.8 7.6 2.8 2.8 0 0 0 4.5 13z",
+    ),
+    ("code", "M5 4 1 8l4 4M11 4l4 4-4 4M9.5 2.5l-3 11"),
+    (
+        "cog",
+        "M8 10.5a2.5 2.5 0 1 0 0-5 2.5 2.5 0 0 0 0 5zM8 1v2.5M8 12.5V15M1 8h2.5M12.5 8H15M3 3l1.8 1.8M11.2 11.2 13 13M3 13l1.8-1.8M11.2 4.8 13 3",
+    ),
+    ("comment", "M1.5 2.5h13v9H7l-3.5 3v-3h-2z"),
+    (
+        "database",
+        "M2 4c0-1.1 2.7-2 6-2s6 .9 6 2-2.7 2-6 2-6-.9-6-2zM2 4v8c0 1.1 2.7 2 6 2s6-.9 6-2V4M2 8c0 1.1 2.7 2 6 2s6-.9 6-2",
+    ),
+    ("desktop", "M1.5 2.5h13v8h-13zM8 10.5v3M5 13.5h6"),
+    ("envelope", "M1.5 3.5h13v9h-13zM1.5 3.5 8 9l6.5-5.5"),
+    ("exclamation-triangle", "M8 1.5 15 14H1zM8 6v3.5M8 11.8v.01"),
+    ("file", "M3.5 1.5h6l3 3v10h-9zM9.5 1.5v3h3"),
+    ("folder", "M1.5 3.5h5l1.5 1.5h6.5v8h-13z"),
+    (
+        "globe",
+        "M8 14.5a6.5 6.5 0 1 0 0-13 6.5 6.5 0 0 0 0 13zM1.5 8h13M8 1.5c-3.3 3.6-3.3 9.4 0 13M8 1.5c3.3 3.6 3.3 9.4 0 13",
+    ),
+    (
+        "heart",
+        "M8 14S1.5 10 1.5 5.5A3.3 3.3 0 0 1 8 4a3.3 3.3 0 0 1 6.5 1.5C14.5 10 8 14 8 14z",
+    ),
+    ("home", "M1.5 8 8 2l6.5 6M3.5 6.5v8h9v-8M6.5 14.5v-4h3v4"),
+    (
+        "key",
+        "M5.5 13a3.5 3.5 0 1 0 0-7 3.5 3.5 0 0 0 0 7zM8 7l6-6M11.5 3.5l2 2M10 5l1.5 1.5",
+    ),
+    ("laptop", "M3 3.5h10v7H3zM1 12.5h14"),
+    ("lock", "M3 7.5h10v7H3zM5 7.5V5a3 3 0 0 1 6 0v2.5"),
+    ("mobile", "M4.5 1.5h7v13h-7zM7.5 12.5h1"),
+    ("play", "M4 2.5v11l9-5.5z"),
+    (
+        "search",
+        "M7 12a5 5 0 1 0 0-10 5 5 0 0 0 0 10zM10.5 10.5l4 4",
+    ),
+    (
+        "server",
+        "M2 2h12v5H2zM2 9h12v5H2zM4.5 4.5h.01M4.5 11.5h.01",
+    ),
+    (
+        "shield",
+        "M8 1.5 14 4v4c0 3.5-2.7 5.8-6 6.5C4.7 13.8 2 11.5 2 8V4z",
+    ),
+    (
+        "shopping-cart",
+        "M1 1.5h2.5l2 9h8l1.5-6H4.2M6.5 14a.5.5 0 1 0 0-1 .5.5 0 0 0 0 1zM12.5 14a.5.5 0 1 0 0-1 .5.5 0 0 0 0 1z",
+    ),
+    (
+        "sitemap",
+        "M6 1.5h4v3H6zM1.5 11.5h4v3h-4zM10.5 11.5h4v3h-4zM8 4.5V8M3.5 11.5V8h9v3.5",
+    ),
+    (
+        "star",
+        "M8 1.5l2 4.2 4.5.6-3.3 3.1.8 4.5L8 11.7l-4 2.2.8-4.5L1.5 6.3 6 5.7z",
+    ),
+    ("terminal", "M1.5 2.5h13v11h-13zM4 6l2.5 2L4 10M8 10.5h4"),
+    ("times", "M3.5 3.5l9 9M12.5 3.5l-9 9"),
+    (
+        "user",
+        "M8 8a3 3 0 1 0 0-6 3 3 0 0 0 0 6zM2.5 14.5c0-3 2.5-5 5.5-5s5.5 2 5.5 5",
+    ),
+    (
+        "users",
+        "M6 7.5a2.5 2.5 0 1 0 0-5 2.5 2.5 0 0 0 0 5zM1 14c0-2.8 2.2-4.5 5-4.5s5 1.7 5 4.5M11 2.7a2.5 2.5 0 0 1 0 4.6M12.5 9.8c1.5.6 2.5 2 2.5 4.2",
+    ),
+];
+
+/// Newer Font Awesome names for the built-in icons.
+const ICON_ALIASES: &[(&str, &str)] = &[
+    ("cart-shopping", "shopping-cart"),
+    ("close", "times"),
+    ("gear", "cog"),
+    ("house", "home"),
+    ("magnifying-glass", "search"),
+    ("triangle-exclamation", "exclamation-triangle"),
+    ("user-group", "users"),
+    ("warning", "exclamation-triangle"),
+    ("xmark", "times"),
+];
+
+/// Path data of the built-in icon called `name`, with or without its `fa-` prefix.
+pub fn builtin_icon(name: &str) -> Option<&'static str> {
+    let name = name.strip_prefix("fa-").unwrap_or(name);
+    let name = ICON_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, target)| target);
+    BUILTIN_ICONS
+        .iter()
+        .find(|(icon, _)| *icon == name)
+        .map(|(_, path)| *path)
+}
+
+/// Splits the first Mermaid icon token (`fa:fa-server`, `fab:fa-github`, ...) out of a label,
+/// returning the icon name without its `fa-` prefix and the label without the token.
+pub fn split_label_icon(label: &str) -> Option<(String, String)> {
+    static ICON_TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = ICON_TOKEN.get_or_init(|| {
+        Regex::new(r"(?:^|\s)fa[bklrs]?:fa-([a-z0-9-]+)(?:\s|$)").expect("valid icon regex")
+    });
+    let found = token.captures(label)?;
+    let whole = found.get(0)?;
+    let name = found[1].to_string();
+    let before = label[..whole.start()].trim_end();
+    let after = label[whole.end()..].trim_start();
+    let rest = match (before.is_empty(), after.is_empty()) {
+        (false, false) => format!("{before} {after}"),
+        _ => format!("{before}{after}"),
+    };
+    Some((name, rest))
+}
+
+/// Reads every `*.svg` file in `dir` into a map keyed by file stem, for icons beyond the
+/// built-in set.
+pub fn load_icon_dir(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to read '{}'", dir.display()))?;
+    let mut icons = BTreeMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("svg") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let svg = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        icons.insert(name.to_string(), svg);
+    }
+    if icons.is_empty() {
+        bail!("icon directory '{}' has no .svg files", dir.display());
+    }
+    Ok(icons)
+}
+
+/// Markup drawing icon `name` in the square whose top-left corner is `(x, y)`. Icons from
+/// `custom` are embedded as images; built-in ones are stroked in `color`, and unknown names
+/// get an empty rounded box so the gap in the label is visible.
+pub fn icon_markup(
+    name: &str,
+    custom: &BTreeMap<String, String>,
+    x: f32,
+    y: f32,
+    color: &str,
+) -> String {
+    let size = NODE_ICON_SIZE;
+    if let Some(svg) = custom
+        .get(name)
+        .or_else(|| custom.get(&format!("fa-{name}")))
+    {
+        let data_uri = format!(
+            "data:image/svg+xml;base64,{}",
+            BASE64_STANDARD.encode(svg.as_bytes())
+        );
+        return format!(
+            "  <image class=\"icon\" x=\"{x:.1}\" y=\"{y:.1}\" width=\"{size:.1}\" height=\"{size:.1}\" href=\"{data_uri}\" />\n"
+        );
+    }
+    let color = escape_xml(color);
+    match builtin_icon(name) {
+        Some(path) => format!(
+            "  <path class=\"icon\" transform=\"translate({x:.1} {y:.1})\" d=\"{path}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\" stroke-linecap=\"round\" stroke-linejoin=\"round\" />\n"
+        ),
+        None => format!(
+            "  <rect class=\"icon\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"3\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\" />\n",
+            x + 1.0,
+            y + 1.0,
+            size - 2.0,
+            size - 2.0
+        ),
+    }
+}
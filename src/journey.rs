@@ -0,0 +1,311 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::*;
+
+const JOURNEY_SECTION_PALETTE: [&str; 6] = [
+    "#6366f1", "#0ea5e9", "#10b981", "#f59e0b", "#ec4899", "#8b5cf6",
+];
+const JOURNEY_SCORE_COLORS: [&str; 5] = ["#ef4444", "#f97316", "#eab308", "#84cc16", "#22c55e"];
+const JOURNEY_MARGIN: f32 = 40.0;
+const JOURNEY_LEGEND_WIDTH: f32 = 150.0;
+const JOURNEY_TASK_WIDTH: f32 = 150.0;
+const JOURNEY_TASK_GAP: f32 = 20.0;
+const JOURNEY_SECTION_HEIGHT: f32 = 40.0;
+const JOURNEY_TASK_HEIGHT: f32 = 64.0;
+const JOURNEY_SCORE_STEP: f32 = 36.0;
+const JOURNEY_SCORE_RADIUS: f32 = 14.0;
+
+#[derive(Debug, Clone)]
+pub struct JourneyDiagram {
+    pub title: Option<String>,
+    pub sections: Vec<String>,
+    /// Actors in order of first appearance; their index picks the legend color.
+    pub actors: Vec<String>,
+    pub tasks: Vec<JourneyTask>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JourneyTask {
+    pub label: String,
+    /// 1 (bad) to 5 (great).
+    pub score: u8,
+    pub actors: Vec<String>,
+    pub section_index: Option<usize>,
+}
+
+/// Parses `title`, `section` and `Task name: score: Actor, Actor` lines.
+pub(crate) fn parse_journey_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut journey = JourneyDiagram {
+        title: None,
+        sections: Vec::new(),
+        actors: Vec::new(),
+        tasks: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("acctitle") || lower.starts_with("accdescr") {
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title ") {
+            journey.title = Some(decode_label(title.trim()));
+            continue;
+        }
+        if let Some(section) = line.strip_prefix("section ") {
+            journey.sections.push(decode_label(section.trim()));
+            continue;
+        }
+
+        let mut parts = line.splitn(3, ':');
+        let (Some(label), Some(score)) = (parts.next(), parts.next()) else {
+            bail!("expected 'Task: score: actors' but found '{line}'");
+        };
+        let label = decode_label(label.trim());
+        if label.is_empty() {
+            bail!("journey task '{line}' is missing a name");
+        }
+        let score: u8 = match score.trim().parse() {
+            Ok(score @ 1..=5) => score,
+            _ => bail!(
+                "journey task '{label}' has score '{}'; expected a whole number from 1 to 5",
+                score.trim()
+            ),
+        };
+        let mut actors: Vec<String> = Vec::new();
+        for actor in parts.next().unwrap_or_default().split(',').map(str::trim) {
+            let actor = decode_label(actor);
+            if !actor.is_empty() && !actors.contains(&actor) {
+                actors.push(actor);
+            }
+        }
+        for actor in &actors {
+            if !journey.actors.contains(actor) {
+                journey.actors.push(actor.clone());
+            }
+        }
+
+        journey.tasks.push(JourneyTask {
+            label,
+            score,
+            actors,
+            section_index: journey.sections.len().checked_sub(1),
+        });
+    }
+
+    if journey.tasks.is_empty() {
+        bail!("journey does not declare any tasks");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    for (idx, task) in journey.tasks.iter().enumerate() {
+        let id = format!("task{idx}");
+        nodes.insert(
+            id.clone(),
+            Node {
+                label: task.label.clone(),
+                shape: NodeShape::Rectangle,
+                image: None,
+                width: JOURNEY_TASK_WIDTH,
+                height: JOURNEY_TASK_HEIGHT,
+            },
+        );
+        order.push(id);
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Journey(journey),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+impl JourneyDiagram {
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&JOURNEY_SECTION_PALETTE);
+        let title_height = if self.title.is_some() { 48.0 } else { 0.0 };
+        let column = JOURNEY_TASK_WIDTH + JOURNEY_TASK_GAP;
+        let lanes_left = JOURNEY_MARGIN + JOURNEY_LEGEND_WIDTH;
+        let width =
+            lanes_left + column * self.tasks.len() as f32 - JOURNEY_TASK_GAP + JOURNEY_MARGIN;
+
+        let section_top = JOURNEY_MARGIN + title_height;
+        let task_top = section_top + JOURNEY_SECTION_HEIGHT + 8.0;
+        // Score 5 sits just below the task boxes and score 1 at the bottom of the lane.
+        let score_top = task_top + JOURNEY_TASK_HEIGHT + 40.0;
+        let score_y = |score: u8| score_top + JOURNEY_SCORE_STEP * (5 - score) as f32;
+        let legend_height = 24.0 * self.actors.len() as f32;
+        let height =
+            (score_y(1) + JOURNEY_SCORE_RADIUS).max(task_top + legend_height) + JOURNEY_MARGIN;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                JOURNEY_MARGIN + 20.0,
+                escape_xml(title)
+            )?;
+        }
+
+        for (idx, actor) in self.actors.iter().enumerate() {
+            let y = task_top + 12.0 + 24.0 * idx as f32;
+            writeln!(
+                svg,
+                "  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"7\" fill=\"{}\" />\n  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"14\" dominant-baseline=\"middle\">{}</text>",
+                JOURNEY_MARGIN + 7.0,
+                y,
+                colors[idx % colors.len()],
+                JOURNEY_MARGIN + 22.0,
+                y,
+                escape_xml(actor)
+            )?;
+        }
+
+        // Faint guides for each score level behind the journey line.
+        for score in 1..=5 {
+            writeln!(
+                svg,
+                "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#e2e8f0\" stroke-width=\"1\" />",
+                lanes_left,
+                score_y(score),
+                width - JOURNEY_MARGIN,
+                score_y(score)
+            )?;
+        }
+
+        let column_x = |idx: usize| lanes_left + column * idx as f32;
+        let mut idx = 0;
+        while idx < self.tasks.len() {
+            let section = self.tasks[idx].section_index;
+            let run = self.tasks[idx..]
+                .iter()
+                .take_while(|task| task.section_index == section)
+                .count();
+            if let Some(section) = section {
+                let color = colors[section % colors.len()];
+                let x = column_x(idx);
+                let span = column * run as f32 - JOURNEY_TASK_GAP;
+                writeln!(
+                    svg,
+                    "  <rect class=\"journey-section\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"8\" ry=\"8\" fill=\"{}\" />\n  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                    x,
+                    section_top,
+                    span,
+                    JOURNEY_SECTION_HEIGHT,
+                    color,
+                    x + span / 2.0,
+                    section_top + JOURNEY_SECTION_HEIGHT / 2.0,
+                    readable_text_color(color),
+                    escape_xml(&self.sections[section])
+                )?;
+            }
+            idx += run;
+        }
+
+        let score_points: Vec<String> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(idx, task)| {
+                format!(
+                    "{:.1},{:.1}",
+                    column_x(idx) + JOURNEY_TASK_WIDTH / 2.0,
+                    score_y(task.score)
+                )
+            })
+            .collect();
+        writeln!(
+            svg,
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"#94a3b8\" stroke-width=\"2\" />",
+            score_points.join(" ")
+        )?;
+
+        for (idx, task) in self.tasks.iter().enumerate() {
+            let x = column_x(idx);
+            let center_x = x + JOURNEY_TASK_WIDTH / 2.0;
+            let stroke = task
+                .section_index
+                .map(|section| colors[section % colors.len()])
+                .unwrap_or("#2d3748");
+            writeln!(svg, "  <g class=\"journey-task\" data-id=\"task{idx}\">")?;
+            writeln!(
+                svg,
+                "    <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#cbd5e0\" stroke-width=\"1.5\" stroke-dasharray=\"4 4\" />",
+                center_x,
+                task_top + JOURNEY_TASK_HEIGHT,
+                center_x,
+                score_y(task.score)
+            )?;
+            writeln!(
+                svg,
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"8\" ry=\"8\" fill=\"#ffffff\" stroke=\"{}\" stroke-width=\"2\" />",
+                x, task_top, JOURNEY_TASK_WIDTH, JOURNEY_TASK_HEIGHT, stroke
+            )?;
+            writeln!(
+                svg,
+                "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                center_x,
+                task_top + JOURNEY_TASK_HEIGHT / 2.0 - 6.0,
+                escape_xml(&task.label)
+            )?;
+            let dots_width = 14.0 * task.actors.len() as f32;
+            for (dot, actor) in task.actors.iter().enumerate() {
+                let actor_index = self
+                    .actors
+                    .iter()
+                    .position(|known| known == actor)
+                    .unwrap_or_default();
+                writeln!(
+                    svg,
+                    "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"5\" fill=\"{}\"><title>{}</title></circle>",
+                    center_x - dots_width / 2.0 + 7.0 + 14.0 * dot as f32,
+                    task_top + JOURNEY_TASK_HEIGHT - 14.0,
+                    colors[actor_index % colors.len()],
+                    escape_xml(actor)
+                )?;
+            }
+            let score_color = JOURNEY_SCORE_COLORS[task.score as usize - 1];
+            writeln!(
+                svg,
+                "    <circle class=\"journey-score\" cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"2\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"13\" font-weight=\"700\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                center_x,
+                score_y(task.score),
+                JOURNEY_SCORE_RADIUS,
+                score_color,
+                center_x,
+                score_y(task.score),
+                readable_text_color(score_color),
+                task.score
+            )?;
+            svg.push_str("  </g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
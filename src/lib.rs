@@ -1,39 +1,95 @@
 use anyhow::{Result, anyhow, bail};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
+pub mod block;
+pub mod c4;
+pub mod class;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod codedown;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod codemap;
+pub mod color;
 pub mod diagram;
+pub mod dot;
 pub mod editor_core;
+pub mod er;
+pub mod export;
+pub mod gitgraph;
+#[cfg(feature = "graphviz")]
+pub mod graphviz;
+pub mod i18n;
+pub mod icons;
+pub mod journey;
+pub mod lint;
+pub mod mindmap;
+pub mod pie;
+pub mod quadrant;
+pub mod sequence;
 #[cfg(feature = "server")]
 pub mod serve;
+pub mod state;
+#[cfg(feature = "server")]
+pub mod store;
+pub mod text;
+pub mod theme;
+pub mod timeline;
 pub mod utils;
+#[cfg(feature = "server")]
+pub mod webhook;
 
+pub use block::*;
+pub use c4::*;
+pub use class::*;
+pub use color::*;
 pub use diagram::*;
+pub use dot::*;
 pub use editor_core::*;
+pub use er::*;
+pub use export::*;
+pub use gitgraph::*;
+pub use i18n::*;
+pub use icons::*;
+pub use journey::*;
+pub use lint::*;
+pub use mindmap::*;
+pub use pie::*;
+pub use quadrant::*;
+pub use sequence::*;
 #[cfg(feature = "server")]
 pub use serve::*;
+pub use state::*;
+#[cfg(feature = "server")]
+pub use store::*;
+pub use text::*;
+pub use theme::*;
+pub use timeline::*;
 pub use utils::*;
+#[cfg(feature = "server")]
+pub use webhook::*;
 
 pub const NODE_WIDTH: f32 = 140.0;
 pub const NODE_HEIGHT: f32 = 50.0;
+/// Narrowest a node shrinks to around a short label.
+pub const NODE_MIN_WIDTH: f32 = 80.0;
 pub const NODE_SPACING: f32 = 160.0;
 pub const START_OFFSET: f32 = 120.0;
 pub const LAYOUT_MARGIN: f32 = 80.0;
+/// Gap between the levels of the `tree` and `radial` layouts.
+pub const TREE_LEVEL_GAP: f32 = 60.0;
 pub const NODE_TEXT_CHAR_WIDTH: f32 = 7.4;
 pub const NODE_TEXT_HORIZONTAL_PADDING: f32 = 60.0;
 pub const NODE_TEXT_VERTICAL_PADDING: f32 = 22.0;
 pub const EDGE_LABEL_MIN_WIDTH: f32 = 36.0;
 pub const EDGE_LABEL_MIN_HEIGHT: f32 = 28.0;
 pub const EDGE_LABEL_LINE_HEIGHT: f32 = 16.0;
+pub const EDGE_LABEL_FONT_SIZE: f32 = 13.0;
 pub const EDGE_LABEL_HORIZONTAL_PADDING: f32 = 16.0;
 pub const EDGE_LABEL_VERTICAL_PADDING: f32 = 12.0;
 pub const EDGE_LABEL_CHAR_WIDTH: f32 = 7.4;
 pub const EDGE_LABEL_VERTICAL_OFFSET: f32 = 10.0;
+pub const EDGE_LABEL_NUDGE_STEP: f32 = 8.0;
 pub const EDGE_BIDIRECTIONAL_OFFSET: f32 = 28.0;
 pub const EDGE_BIDIRECTIONAL_STUB: f32 = 48.0;
 pub const EDGE_BIDIRECTIONAL_OFFSET_STEP: f32 = 12.0;
@@ -45,16 +101,36 @@ pub const EDGE_SINGLE_STUB: f32 = 56.0;
 pub const EDGE_SINGLE_OFFSET_STEP: f32 = 14.0;
 pub const EDGE_SINGLE_STUB_STEP: f32 = 20.0;
 pub const EDGE_ORTHO_MIN_STUB: f32 = 28.0;
+pub const EDGE_ROUTER_BEND_PENALTY: f32 = 40.0;
+/// Grid search states above which the edge router gives up rather than search, which bounds
+/// both its memory and, since the grid grows with the obstacles, its time.
+pub const EDGE_ROUTER_MAX_STATES: usize = 200_000;
 pub const EDGE_ARROW_EXTENSION: f32 = 1.0;
+pub const EDGE_SELF_LOOP_SIZE: f32 = 28.0;
+/// Room kept in each layer an edge passes through on its way to a later layer.
+pub const EDGE_LANE_WIDTH: f32 = 12.0;
+/// Incoming edges a node needs before `edgeBundling` merges them into one trunk.
+pub const EDGE_BUNDLE_MIN_FAN_IN: usize = 4;
+/// How far before its target a bundle's trunk splits back into separate arrows.
+pub const EDGE_BUNDLE_SPLIT: f32 = 40.0;
 pub const LAYOUT_BLOCK_START: &str = "%% OXDRAW LAYOUT START";
 pub const LAYOUT_BLOCK_END: &str = "%% OXDRAW LAYOUT END";
 pub const SUBGRAPH_PADDING: f32 = 48.0;
 pub const SUBGRAPH_LABEL_AREA: f32 = 36.0;
+/// Band added above the diagram for its `title`.
+pub const TITLE_AREA: f32 = 36.0;
+pub const TITLE_FONT_SIZE: f32 = 18.0;
 pub const SUBGRAPH_LABEL_TEXT_BASELINE: f32 = 20.0;
 pub const SUBGRAPH_LABEL_INSET_X: f32 = 20.0;
 pub const NODE_LABEL_HEIGHT: f32 = 28.0;
 pub const NODE_TEXT_LINE_HEIGHT: f32 = 16.0;
+/// Size of node and subgraph label text.
+pub const NODE_TEXT_FONT_SIZE: f32 = 14.0;
+pub const JUNCTION_SIZE: f32 = 12.0;
+pub const PINNED_NODE_CLEARANCE: f32 = 20.0;
 pub const IMAGE_COMMENT_PREFIX: &str = "%% OXDRAW IMAGE";
+pub const RANK_COMMENT_PREFIX: &str = "%% OXDRAW RANK";
+pub const TITLE_COMMENT_PREFIX: &str = "%% oxdraw-title";
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EdgeOverride {
@@ -74,6 +150,10 @@ pub struct NodeStyleOverride {
     pub label_fill: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_fill: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_width: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border_style: Option<BorderStyle>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -84,6 +164,14 @@ pub struct EdgeStyleOverride {
     pub color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arrow: Option<EdgeArrowDirection>,
+    /// Label box fill; `none` or `transparent` drops the box and draws a text halo instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_fill: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_stroke: Option<String>,
+    /// Marches the dashes along the edge in SVG output; replaces the diagram-wide setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animated: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -110,10 +198,27 @@ pub struct GanttStyleOverride {
     pub task_text: Option<String>,
 }
 
+/// A named rendering of the same file with some elements hidden, stored in the layout block.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutView {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hidden_nodes: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hidden_edges: BTreeSet<String>,
+    /// Hiding a subgraph also hides every node inside it.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub hidden_subgraphs: BTreeSet<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    /// Multiplies the rendered size; the PNG scale for raster output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<f32>,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GanttOverrides {
-    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub tasks: HashMap<String, GanttTaskOverride>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tasks: BTreeMap<String, GanttTaskOverride>,
     #[serde(default, skip_serializing_if = "GanttStyleOverride::is_empty")]
     pub style: GanttStyleOverride,
 }
@@ -144,6 +249,23 @@ pub struct NodeImage {
     pub padding: f32,
 }
 
+/// Image named by path or URL in `%% OXDRAW IMAGE <id> <src>`, loaded at render time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeImageSource {
+    /// File path, relative to the diagram's directory, or an `http(s)` URL.
+    pub src: String,
+    pub padding: f32,
+}
+
+/// Target of a flowchart `click <id> href "<url>"` statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeLink {
+    pub href: String,
+    pub tooltip: Option<String>,
+    /// Browsing context such as `_blank`.
+    pub target: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NodeShape {
     Rectangle,
@@ -159,6 +281,7 @@ pub enum NodeShape {
     Trapezoid,
     TrapezoidAlt,
     Asymmetric,
+    Junction,
 }
 
 #[derive(Debug, Clone)]
@@ -168,6 +291,10 @@ pub struct Edge {
     pub label: Option<String>,
     pub kind: EdgeKind,
     pub arrow: EdgeArrowDirection,
+    /// Marker drawn at the ends `arrow` points to.
+    pub head: EdgeHead,
+    /// How many identical edges precede this one; keeps their identifiers distinct.
+    pub occurrence: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -188,6 +315,26 @@ pub enum EdgeArrowDirection {
     None,
 }
 
+/// Dash pattern of a node's outline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BorderStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+/// Marker at the pointed ends of an edge: `-->`, `--o` or `--x`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeHead {
+    #[default]
+    Arrow,
+    Circle,
+    Cross,
+}
+
 #[derive(Debug, Clone, Copy, Serialize)]
 pub struct CanvasSize {
     pub width: f32,
@@ -198,6 +345,8 @@ pub struct CanvasSize {
 pub struct AutoLayout {
     pub positions: HashMap<String, Point>,
     pub size: CanvasSize,
+    /// Bend points, by edge id, for edges that span several layers of the automatic layout.
+    pub waypoints: HashMap<String, Vec<Point>>,
 }
 
 #[derive(Debug, Clone)]
@@ -209,16 +358,230 @@ pub struct LayoutComputation {
     pub final_routes: HashMap<String, Vec<Point>>,
 }
 
+/// Custom adjustment run on the automatic node positions before edges are routed, so
+/// downstream crates can nudge nodes without reimplementing routing. Positions from layout
+/// overrides are applied afterwards and still win.
+///
+/// Any `Fn(&Diagram, &mut HashMap<String, Point>) -> Result<()>` closure is a pass.
+pub trait LayoutPass {
+    fn adjust(&self, diagram: &Diagram, positions: &mut HashMap<String, Point>) -> Result<()>;
+}
+
+impl<F> LayoutPass for F
+where
+    F: Fn(&Diagram, &mut HashMap<String, Point>) -> Result<()>,
+{
+    fn adjust(&self, diagram: &Diagram, positions: &mut HashMap<String, Point>) -> Result<()> {
+        self(diagram, positions)
+    }
+}
+
+/// [`LayoutPass`] that keeps nodes where an earlier layout put them, so an edit only places
+/// the nodes it adds instead of reshuffling the whole diagram.
+#[derive(Debug, Clone, Default)]
+pub struct IncrementalLayout {
+    /// Automatic positions from the earlier layout, by node id.
+    pub previous: HashMap<String, Point>,
+}
+
+/// Wall-clock time spent in one rendering phase, reported by `--verbose` and the editor's
+/// debug payload.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTiming {
+    /// `parse`, `rank`, `separate`, `passes`, `route`, `render` or `rasterize`.
+    pub phase: &'static str,
+    pub millis: f64,
+}
+
+/// A collision left in the final layout, reported so the editor can highlight it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutDiagnostic {
+    pub edge_id: String,
+    pub kind: CollisionKind,
+    /// Nodes the edge or its label runs through; for crossings, the endpoints of both edges.
+    pub nodes: Vec<String>,
+    /// The other edge of an [`CollisionKind::EdgeCrossing`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other_edge_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollisionKind {
+    NodeOverlap,
+    LabelOverlap,
+    EdgeCrossing,
+}
+
+/// Node text whose contrast against its background is below [`WCAG_AA_CONTRAST`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContrastWarning {
+    pub node_id: String,
+    pub text: String,
+    pub background: String,
+    pub ratio: f32,
+    /// The nearest shade of `text` that meets the target ratio.
+    pub suggested_text: String,
+}
+
+/// Ids that appeared, disappeared or changed between two versions of a diagram.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeList {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+impl ChangeList {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Layout overrides dropped because their node or edge no longer exists.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrunedOverrides {
+    pub nodes: Vec<String>,
+    pub edges: Vec<String>,
+}
+
+impl PrunedOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.edges.is_empty()
+    }
+}
+
+/// Structural difference produced by replacing a diagram's source.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceChangeSet {
+    pub nodes: ChangeList,
+    pub edges: ChangeList,
+    pub subgraphs: ChangeList,
+    pub pruned_overrides: PrunedOverrides,
+    /// True when anything was removed or any override was pruned.
+    pub destructive: bool,
+}
+
+/// A node or edge whose id changed while the rest of it stayed the same.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+/// Semantic difference between two versions of a diagram, from [`Diagram::diff`]. Renamed
+/// nodes and edges are listed once under `renamed_*` rather than as a removal and an addition.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagramDiff {
+    pub nodes: ChangeList,
+    pub renamed_nodes: Vec<Rename>,
+    pub edges: ChangeList,
+    pub renamed_edges: Vec<Rename>,
+    pub subgraphs: ChangeList,
+}
+
+impl DiagramDiff {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+            && self.renamed_nodes.is_empty()
+            && self.edges.is_empty()
+            && self.renamed_edges.is_empty()
+            && self.subgraphs.is_empty()
+    }
+}
+
+impl SourceChangeSet {
+    pub fn record_pruned(&mut self, pruned: PrunedOverrides) {
+        self.destructive |= !pruned.is_empty();
+        self.pruned_overrides = pruned;
+    }
+}
+
+/// The editor's current view of the canvas, used to export exactly what is on screen.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ViewportRender {
+    /// Visible region in diagram coordinates.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default = "default_viewport_zoom")]
+    pub zoom: f32,
+    #[serde(default)]
+    pub selected_nodes: Vec<String>,
+    #[serde(default)]
+    pub selected_edges: Vec<String>,
+    /// Stroke used for selected nodes and edges; defaults to the editor highlight.
+    #[serde(default)]
+    pub selection_color: Option<String>,
+}
+
+fn default_viewport_zoom() -> f32 {
+    1.0
+}
+
+/// Largest width or height, in output pixels, of a viewport render.
+pub const MAX_VIEWPORT_SIDE: f32 = 16_384.0;
+/// Largest output area of a viewport render, about 256 MB once rasterized to RGBA.
+pub const MAX_VIEWPORT_PIXELS: f32 = 64_000_000.0;
+
+/// A text-free thumbnail of the whole diagram, used to navigate large canvases.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MinimapRender {
+    /// Length of the thumbnail's longer side, in pixels.
+    #[serde(default = "default_minimap_size")]
+    pub size: f32,
+    /// Visible region in diagram coordinates, outlined when all four are given.
+    #[serde(default)]
+    pub x: Option<f32>,
+    #[serde(default)]
+    pub y: Option<f32>,
+    #[serde(default)]
+    pub width: Option<f32>,
+    #[serde(default)]
+    pub height: Option<f32>,
+}
+
+impl Default for MinimapRender {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_MINIMAP_SIZE,
+            x: None,
+            y: None,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+pub const DEFAULT_MINIMAP_SIZE: f32 = 240.0;
+
+fn default_minimap_size() -> f32 {
+    DEFAULT_MINIMAP_SIZE
+}
+
 #[derive(Debug, Clone)]
 pub struct Geometry {
     pub positions: HashMap<String, Point>,
     pub edges: HashMap<String, Vec<Point>>,
+    /// Edge label centres by edge id, moved apart where they would overlap.
+    pub labels: HashMap<String, Point>,
     pub subgraphs: Vec<SubgraphVisual>,
     pub width: f32,
     pub height: f32,
+    /// Offset added to layout (diagram-space) coordinates to get render-space coordinates.
+    pub shift: Point,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SubgraphVisual {
     pub id: String,
     pub label: String,
@@ -230,9 +593,47 @@ pub struct SubgraphVisual {
     pub label_y: f32,
     pub depth: usize,
     pub order: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub parent_id: Option<String>,
 }
 
+/// Where a laid-out diagram puts everything, in the rendered SVG's coordinates; from
+/// [`Diagram::layout_geometry`].
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutGeometry {
+    pub canvas: CanvasSize,
+    pub nodes: Vec<NodeGeometry>,
+    pub edges: Vec<EdgeGeometry>,
+    pub subgraphs: Vec<SubgraphVisual>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeGeometry {
+    pub id: String,
+    pub label: String,
+    pub shape: &'static str,
+    /// Centre of the node.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EdgeGeometry {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The route from the `from` node's border to the `to` node's border.
+    pub points: Vec<Point>,
+    /// Centre of the label, when the edge has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label_position: Option<Point>,
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct NodeStylePatch {
     #[serde(default)]
@@ -245,6 +646,10 @@ pub struct NodeStylePatch {
     pub label_fill: Option<Option<String>>,
     #[serde(default)]
     pub image_fill: Option<Option<String>>,
+    #[serde(default)]
+    pub stroke_width: Option<Option<f32>>,
+    #[serde(default)]
+    pub border_style: Option<Option<BorderStyle>>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -270,12 +675,19 @@ impl NodeStyleOverride {
             && self.text.is_none()
             && self.label_fill.is_none()
             && self.image_fill.is_none()
+            && self.stroke_width.is_none()
+            && self.border_style.is_none()
     }
 }
 
 impl EdgeStyleOverride {
     pub fn is_empty(&self) -> bool {
-        self.line.is_none() && self.color.is_none() && self.arrow.is_none()
+        self.line.is_none()
+            && self.color.is_none()
+            && self.arrow.is_none()
+            && self.label_fill.is_none()
+            && self.label_stroke.is_none()
+            && self.animated.is_none()
     }
 }
 
@@ -329,3 +741,32 @@ impl EdgeArrowDirection {
         }
     }
 }
+
+impl BorderStyle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BorderStyle::Solid => "solid",
+            BorderStyle::Dashed => "dashed",
+            BorderStyle::Dotted => "dotted",
+        }
+    }
+
+    /// SVG `stroke-dasharray` for the style, if it has one.
+    pub fn dash_pattern(self) -> Option<&'static str> {
+        match self {
+            BorderStyle::Solid => None,
+            BorderStyle::Dashed => Some("8 6"),
+            BorderStyle::Dotted => Some("2 4"),
+        }
+    }
+}
+
+impl EdgeHead {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EdgeHead::Arrow => "arrow",
+            EdgeHead::Circle => "circle",
+            EdgeHead::Cross => "cross",
+        }
+    }
+}
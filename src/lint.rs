@@ -0,0 +1,285 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::diagram::{
+    diagram_header, extract_mermaid_diagram_source, label_lines, same_connection,
+};
+use crate::{
+    DiagnosticCode, Diagram, DiagramKind, Edge, EdgeArrowDirection, NODE_TEXT_CHAR_WIDTH,
+    NODE_TEXT_FONT_SIZE, ParseError, edge_identifier, text_width,
+};
+
+/// Width past which a label line that nothing wraps or truncates is reported.
+pub const LINT_LABEL_WIDTH: f32 = 320.0;
+
+/// How serious a [`LintFinding`] is; ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// The severity's lowercase name, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// The check behind a [`LintFinding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    /// A statement the parser had to skip.
+    Syntax,
+    UnclosedSubgraph,
+    /// A flowchart header without `TD`, `LR`, ...
+    ImplicitDirection,
+    IsolatedNode,
+    UnreachableNode,
+    DuplicateEdge,
+    LabelOverflow,
+}
+
+impl LintRule {
+    /// The rule's kebab-case name, as serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            LintRule::Syntax => "syntax",
+            LintRule::UnclosedSubgraph => "unclosed-subgraph",
+            LintRule::ImplicitDirection => "implicit-direction",
+            LintRule::IsolatedNode => "isolated-node",
+            LintRule::UnreachableNode => "unreachable-node",
+            LintRule::DuplicateEdge => "duplicate-edge",
+            LintRule::LabelOverflow => "label-overflow",
+        }
+    }
+}
+
+/// One problem reported by [`lint`]. Findings about the graph as a whole have no line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LintFinding {
+    pub rule: LintRule,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// The node or edge the finding is about.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl LintFinding {
+    fn new(rule: LintRule, severity: Severity, message: String) -> Self {
+        Self {
+            rule,
+            severity,
+            message,
+            line: None,
+            column: None,
+            id: None,
+        }
+    }
+
+    fn about(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    fn from_parse_error(error: &ParseError) -> Self {
+        let rule = match error.code {
+            DiagnosticCode::UnclosedSubgraph => LintRule::UnclosedSubgraph,
+            _ => LintRule::Syntax,
+        };
+        Self {
+            line: Some(error.line),
+            column: Some(error.column),
+            ..Self::new(rule, Severity::Error, error.message.clone())
+        }
+    }
+}
+
+/// Parses `definition` leniently and reports syntax errors, unclosed subgraphs and, for
+/// flowcharts, a missing direction, isolated and unreachable nodes, repeated edges and labels
+/// that overflow. Findings are sorted by line, with graph-wide ones last.
+pub fn lint(definition: &str) -> Result<Vec<LintFinding>> {
+    let (diagram, diagnostics) = match Diagram::parse_lenient(definition) {
+        Ok(parsed) => parsed,
+        Err(err) => match err.downcast_ref::<ParseError>() {
+            Some(parse_error) => return Ok(vec![LintFinding::from_parse_error(parse_error)]),
+            None => return Err(err),
+        },
+    };
+    let mut findings: Vec<LintFinding> = diagnostics
+        .iter()
+        .map(LintFinding::from_parse_error)
+        .collect();
+    if matches!(diagram.kind, DiagramKind::Flowchart) {
+        findings.extend(implicit_direction(definition));
+        findings.extend(isolated_and_unreachable_nodes(&diagram));
+        findings.extend(duplicate_edges(&diagram));
+        findings.extend(label_overflows(&diagram));
+    }
+    findings.sort_by_key(|finding| (finding.line.unwrap_or(usize::MAX), finding.column));
+    Ok(findings)
+}
+
+fn implicit_direction(definition: &str) -> Option<LintFinding> {
+    let source = extract_mermaid_diagram_source(definition);
+    let (line, header) = diagram_header(&source)?;
+    if header.split_whitespace().nth(1).is_some() {
+        return None;
+    }
+    Some(LintFinding {
+        line: Some(line),
+        column: Some(1),
+        ..LintFinding::new(
+            LintRule::ImplicitDirection,
+            Severity::Info,
+            format!("'{header}' has no direction and defaults to TD; add TD, LR, BT or RL"),
+        )
+    })
+}
+
+/// Nodes without any edge, when the diagram has edges at all, and connected nodes that no path
+/// from a node without incoming edges reaches.
+fn isolated_and_unreachable_nodes(diagram: &Diagram) -> Vec<LintFinding> {
+    if diagram.edges.is_empty() {
+        return Vec::new();
+    }
+    let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut has_incoming: HashSet<&str> = HashSet::new();
+    let mut connected: HashSet<&str> = HashSet::new();
+    for edge in &diagram.edges {
+        let (from, to) = (edge.from.as_str(), edge.to.as_str());
+        connected.extend([from, to]);
+        let directed = match edge.arrow {
+            EdgeArrowDirection::Forward => vec![(from, to)],
+            EdgeArrowDirection::Backward => vec![(to, from)],
+            EdgeArrowDirection::Both | EdgeArrowDirection::None => vec![(from, to), (to, from)],
+        };
+        for (source, target) in directed {
+            successors.entry(source).or_default().push(target);
+            if source != target {
+                has_incoming.insert(target);
+            }
+        }
+    }
+
+    let mut reached: HashSet<&str> = HashSet::new();
+    let mut queue: VecDeque<&str> = diagram
+        .order
+        .iter()
+        .map(String::as_str)
+        .filter(|id| connected.contains(id) && !has_incoming.contains(id))
+        .collect();
+    let has_start = !queue.is_empty();
+    while let Some(id) = queue.pop_front() {
+        if reached.insert(id) {
+            queue.extend(successors.get(id).into_iter().flatten());
+        }
+    }
+
+    let mut findings = Vec::new();
+    for id in &diagram.order {
+        if !connected.contains(id.as_str()) {
+            findings.push(
+                LintFinding::new(
+                    LintRule::IsolatedNode,
+                    Severity::Warning,
+                    format!("node '{id}' has no edges"),
+                )
+                .about(id),
+            );
+        } else if has_start && !reached.contains(id.as_str()) {
+            findings.push(
+                LintFinding::new(
+                    LintRule::UnreachableNode,
+                    Severity::Warning,
+                    format!("node '{id}' can't be reached from any start node"),
+                )
+                .about(id),
+            );
+        }
+    }
+    findings
+}
+
+/// Edges declared more than once with the same ends, style and label.
+fn duplicate_edges(diagram: &Diagram) -> Vec<LintFinding> {
+    let mut groups: Vec<(&Edge, usize)> = Vec::new();
+    for edge in &diagram.edges {
+        match groups
+            .iter_mut()
+            .find(|(first, _)| same_connection(first, edge) && first.label == edge.label)
+        {
+            Some((_, count)) => *count += 1,
+            None => groups.push((edge, 1)),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(edge, count)| {
+            let id = edge_identifier(edge);
+            let described = match &edge.label {
+                Some(label) => format!("edge '{id}' labeled '{label}'"),
+                None => format!("edge '{id}'"),
+            };
+            LintFinding::new(
+                LintRule::DuplicateEdge,
+                Severity::Warning,
+                format!("{described} is declared {count} times"),
+            )
+            .about(id)
+        })
+        .collect()
+}
+
+/// Labels wider than the image node they sit in, and label lines past [`LINT_LABEL_WIDTH`]
+/// when neither `wrappingWidth` nor `maxLabelWidth` keeps them in check.
+fn label_overflows(diagram: &Diagram) -> Vec<LintFinding> {
+    let unbounded = diagram.config.wrap_width.is_none() && diagram.config.max_label_width.is_none();
+    let mut findings = Vec::new();
+    for id in &diagram.order {
+        let Some(node) = diagram.nodes.get(id) else {
+            continue;
+        };
+        let widest = label_lines(&diagram.fitted_label(&node.label))
+            .iter()
+            .map(|line| text_width(line, NODE_TEXT_FONT_SIZE, NODE_TEXT_CHAR_WIDTH))
+            .fold(0.0, f32::max);
+        let finding = if node.image.is_some() && widest > node.width {
+            LintFinding::new(
+                LintRule::LabelOverflow,
+                Severity::Warning,
+                format!(
+                    "label of image node '{id}' is {widest:.0}px wide and overflows the {:.0}px image",
+                    node.width
+                ),
+            )
+        } else if node.image.is_none() && unbounded && widest > LINT_LABEL_WIDTH {
+            LintFinding::new(
+                LintRule::LabelOverflow,
+                Severity::Info,
+                format!(
+                    "label of node '{id}' is {widest:.0}px wide; set wrappingWidth or maxLabelWidth to keep it narrower"
+                ),
+            )
+        } else {
+            continue;
+        };
+        findings.push(finding.about(id));
+    }
+    findings
+}
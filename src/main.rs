@@ -1,24 +1,33 @@
 mod cli;
 
+use cli::AlreadyReported;
+use oxdraw::UiMessage;
+
 #[cfg(feature = "server")]
 use cli::dispatch;
 
 #[cfg(not(feature = "server"))]
 use cli::dispatch_sync;
 
+/// Prints `err` unless it was already reported, then exits with a failure status.
+fn exit_with(err: anyhow::Error) -> ! {
+    if !err.is::<AlreadyReported>() {
+        eprintln!("\u{001b}[31m{}\u{001b}[0m {err:?}", UiMessage::Error);
+    }
+    std::process::exit(1);
+}
+
 #[cfg(feature = "server")]
 #[tokio::main]
 async fn main() {
     if let Err(err) = dispatch().await {
-        eprintln!("\u{001b}[31merror:\u{001b}[0m {err:?}");
-        std::process::exit(1);
+        exit_with(err);
     }
 }
 
 #[cfg(not(feature = "server"))]
 fn main() {
     if let Err(err) = dispatch_sync() {
-        eprintln!("\u{001b}[31merror:\u{001b}[0m {err:?}");
-        std::process::exit(1);
+        exit_with(err);
     }
 }
@@ -0,0 +1,491 @@
+use anyhow::{Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fmt::Write;
+
+use crate::diagram::{compute_node_dimensions, normalize_label_lines};
+use crate::*;
+
+const MINDMAP_BRANCH_PALETTE: [&str; 8] = [
+    "#6366f1", "#f59e0b", "#10b981", "#ef4444", "#06b6d4", "#8b5cf6", "#ec4899", "#84cc16",
+];
+const MINDMAP_ROOT_FILL: &str = "#312e81";
+const MINDMAP_LEVEL_GAP: f32 = 48.0;
+const MINDMAP_SIBLING_GAP: f32 = 16.0;
+const MINDMAP_MARGIN: f32 = 40.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MindmapShape {
+    Default,
+    Square,
+    Rounded,
+    Circle,
+    Cloud,
+    Bang,
+    Hexagon,
+}
+
+/// `mindmap` sources keep their own tree, laid out radially around the root.
+#[derive(Debug, Clone)]
+pub struct MindmapData {
+    /// Nodes in source order; the root is always first and parents precede their children.
+    pub nodes: Vec<MindmapNode>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MindmapNode {
+    pub id: String,
+    pub label: String,
+    pub shape: MindmapShape,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Splits `id((label))`, `id)cloud(`, `[label]` or a bare label into its parts.
+fn parse_mindmap_node(text: &str) -> Result<(Option<&str>, &str, MindmapShape)> {
+    const DELIMITERS: [(&str, &str, MindmapShape); 6] = [
+        ("((", "))", MindmapShape::Circle),
+        ("))", "((", MindmapShape::Bang),
+        ("{{", "}}", MindmapShape::Hexagon),
+        ("(", ")", MindmapShape::Rounded),
+        (")", "(", MindmapShape::Cloud),
+        ("[", "]", MindmapShape::Square),
+    ];
+
+    let Some(start) = text.find(['(', ')', '[', '{']) else {
+        return Ok((None, text, MindmapShape::Default));
+    };
+    let (id, rest) = text.split_at(start);
+    // A bare label may contain brackets of its own, e.g. `Long history (since 1970)`.
+    if id.contains(char::is_whitespace) {
+        return Ok((None, text, MindmapShape::Default));
+    }
+    for (open, close, shape) in DELIMITERS {
+        if let Some(inner) = rest
+            .strip_prefix(open)
+            .and_then(|inner| inner.strip_suffix(close))
+        {
+            return Ok(((!id.is_empty()).then_some(id), inner.trim(), shape));
+        }
+    }
+    bail!("unterminated shape in mindmap node '{text}'");
+}
+
+/// Parses indented `mindmap` lines; `lines` carries each line's indentation in characters.
+pub(crate) fn parse_mindmap(lines: Vec<(usize, String)>, original_source: &str) -> Result<Diagram> {
+    let mut mindmap = MindmapData {
+        nodes: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut used_ids: HashSet<String> = HashSet::new();
+
+    for (indent, line) in lines {
+        let line = line.trim();
+        // Icons and classes decorate the previous node and do not affect the layout.
+        if line.starts_with("::icon(") || line.starts_with(":::") {
+            continue;
+        }
+
+        let (id, label, shape) = parse_mindmap_node(line)?;
+        let label = decode_label(label);
+        if label.is_empty() {
+            bail!("mindmap node '{line}' has an empty label");
+        }
+
+        while stack.last().is_some_and(|&(level, _)| level >= indent) {
+            stack.pop();
+        }
+        let parent = stack.last().map(|&(_, idx)| idx);
+        if parent.is_none() && !mindmap.nodes.is_empty() {
+            bail!("mindmap can only have one root, but '{line}' is not indented under it");
+        }
+
+        let index = mindmap.nodes.len();
+        let id = match id {
+            Some(id) if used_ids.insert(id.to_string()) => id.to_string(),
+            _ => {
+                let mut generated = format!("node{index}");
+                while !used_ids.insert(generated.clone()) {
+                    generated.push('_');
+                }
+                generated
+            }
+        };
+
+        let (mut width, mut height) = compute_node_dimensions(NodeShape::Rectangle, &label);
+        match shape {
+            MindmapShape::Circle => {
+                width = width.max(height);
+                height = width;
+            }
+            MindmapShape::Hexagon => width += 30.0,
+            MindmapShape::Cloud | MindmapShape::Bang => {
+                width *= 1.4;
+                height *= 1.6;
+            }
+            MindmapShape::Default | MindmapShape::Square | MindmapShape::Rounded => {}
+        }
+
+        let depth = parent.map_or(0, |parent| mindmap.nodes[parent].depth + 1);
+        if let Some(parent) = parent {
+            mindmap.nodes[parent].children.push(index);
+        }
+        mindmap.nodes.push(MindmapNode {
+            id,
+            label,
+            shape,
+            parent,
+            children: Vec::new(),
+            depth,
+            width,
+            height,
+        });
+        stack.push((indent, index));
+    }
+
+    if mindmap.nodes.is_empty() {
+        bail!("mindmap does not declare a root node");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    let mut edges = Vec::new();
+    for node in &mindmap.nodes {
+        nodes.insert(
+            node.id.clone(),
+            Node {
+                label: node.label.clone(),
+                shape: NodeShape::Rectangle,
+                image: None,
+                width: node.width,
+                height: node.height,
+            },
+        );
+        order.push(node.id.clone());
+        if let Some(parent) = node.parent {
+            edges.push(Edge {
+                from: mindmap.nodes[parent].id.clone(),
+                to: node.id.clone(),
+                label: None,
+                kind: EdgeKind::Solid,
+                arrow: EdgeArrowDirection::None,
+                head: EdgeHead::Arrow,
+                occurrence: 0,
+            });
+        }
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Mindmap(mindmap),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges,
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+impl MindmapData {
+    /// Places every node on a ring for its depth, giving each subtree an angular sector
+    /// proportional to its number of leaves. Positions are relative to the root.
+    pub fn radial_layout(&self) -> Vec<Point> {
+        let count = self.nodes.len();
+        let mut leaves = vec![0_usize; count];
+        for idx in (0..count).rev() {
+            let node = &self.nodes[idx];
+            leaves[idx] = node
+                .children
+                .iter()
+                .map(|&child| leaves[child])
+                .sum::<usize>()
+                .max(1);
+        }
+
+        // (start angle, sweep) for each node's sector.
+        let mut sectors = vec![(0.0_f32, 0.0_f32); count];
+        sectors[0] = (-FRAC_PI_2, TAU);
+        for idx in 0..count {
+            let (mut start, sweep) = sectors[idx];
+            let node = &self.nodes[idx];
+            for &child in &node.children {
+                let share = sweep * leaves[child] as f32 / leaves[idx] as f32;
+                sectors[child] = (start, share);
+                start += share;
+            }
+        }
+
+        let max_depth = self.nodes.iter().map(|node| node.depth).max().unwrap_or(0);
+        let mut extents = vec![0.0_f32; max_depth + 1];
+        for node in &self.nodes {
+            let extent = node.width.max(node.height) / 2.0;
+            extents[node.depth] = extents[node.depth].max(extent);
+        }
+
+        let mut radii = vec![0.0_f32; max_depth + 1];
+        for depth in 1..=max_depth {
+            let mut radius =
+                radii[depth - 1] + extents[depth - 1] + extents[depth] + MINDMAP_LEVEL_GAP;
+            for (idx, node) in self.nodes.iter().enumerate() {
+                if node.depth != depth {
+                    continue;
+                }
+                let (start, sweep) = sectors[idx];
+                let angle = start + sweep / 2.0;
+                // Size of the box along the ring, which neighbouring sectors must clear.
+                let tangential = node.width * angle.sin().abs() + node.height * angle.cos().abs();
+                radius = radius.max((tangential + MINDMAP_SIBLING_GAP) / sweep.min(TAU / 2.0));
+            }
+            radii[depth] = radius;
+        }
+
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| {
+                let (start, sweep) = sectors[idx];
+                let angle = start + sweep / 2.0;
+                let radius = radii[node.depth];
+                Point {
+                    x: radius * angle.cos(),
+                    y: radius * angle.sin(),
+                }
+            })
+            .collect()
+    }
+
+    fn branch_of(&self, mut idx: usize) -> Option<usize> {
+        while let Some(parent) = self.nodes[idx].parent {
+            if parent == 0 {
+                return self.nodes[0]
+                    .children
+                    .iter()
+                    .position(|&child| child == idx);
+            }
+            idx = parent;
+        }
+        None
+    }
+
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&MINDMAP_BRANCH_PALETTE);
+        let mut positions = self.radial_layout();
+        let min_x = self
+            .nodes
+            .iter()
+            .zip(&positions)
+            .map(|(node, point)| point.x - node.width / 2.0)
+            .fold(f32::INFINITY, f32::min);
+        let min_y = self
+            .nodes
+            .iter()
+            .zip(&positions)
+            .map(|(node, point)| point.y - node.height / 2.0)
+            .fold(f32::INFINITY, f32::min);
+        for point in &mut positions {
+            point.x += MINDMAP_MARGIN - min_x;
+            point.y += MINDMAP_MARGIN - min_y;
+        }
+        let width = self
+            .nodes
+            .iter()
+            .zip(&positions)
+            .map(|(node, point)| point.x + node.width / 2.0)
+            .fold(0.0_f32, f32::max)
+            + MINDMAP_MARGIN;
+        let height = self
+            .nodes
+            .iter()
+            .zip(&positions)
+            .map(|(node, point)| point.y + node.height / 2.0)
+            .fold(0.0_f32, f32::max)
+            + MINDMAP_MARGIN;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        let root = positions[0];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let Some(parent) = node.parent else {
+                continue;
+            };
+            let color = self.branch_color(idx, colors);
+            let from = positions[parent];
+            let to = positions[idx];
+            // Bend the branch so it leaves the parent along the child's direction from the root.
+            let parent_radius = ((from.x - root.x).powi(2) + (from.y - root.y).powi(2)).sqrt();
+            let angle = (to.y - root.y).atan2(to.x - root.x);
+            writeln!(
+                svg,
+                "  <path class=\"mindmap-branch\" d=\"M{:.1},{:.1} Q{:.1},{:.1} {:.1},{:.1}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.1}\" stroke-linecap=\"round\" />",
+                from.x,
+                from.y,
+                root.x + parent_radius * angle.cos(),
+                root.y + parent_radius * angle.sin(),
+                to.x,
+                to.y,
+                color,
+                (8.0 - 2.0 * node.depth as f32).max(2.0)
+            )?;
+        }
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let center = positions[idx];
+            let (fill, stroke, text) = match node.depth {
+                0 => (MINDMAP_ROOT_FILL, MINDMAP_ROOT_FILL, "#ffffff"),
+                1 => {
+                    let color = self.branch_color(idx, colors);
+                    (color, color, readable_text_color(color))
+                }
+                _ => ("#ffffff", self.branch_color(idx, colors), "#1a202c"),
+            };
+            writeln!(
+                svg,
+                "  <g class=\"mindmap-node\" data-id=\"{}\">",
+                escape_xml(&node.id)
+            )?;
+            render_mindmap_shape(&mut svg, node, center, fill, stroke)?;
+
+            let lines = normalize_label_lines(&node.label);
+            let first_line = center.y - NODE_TEXT_LINE_HEIGHT * (lines.len() - 1) as f32 / 2.0;
+            for (line_idx, line) in lines.iter().enumerate() {
+                writeln!(
+                    svg,
+                    "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                    center.x,
+                    first_line + NODE_TEXT_LINE_HEIGHT * line_idx as f32,
+                    text,
+                    if node.depth == 0 { 700 } else { 500 },
+                    escape_xml(line)
+                )?;
+            }
+            svg.push_str("  </g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    fn branch_color(&self, idx: usize, colors: &[&'static str]) -> &'static str {
+        match self.branch_of(idx) {
+            Some(branch) => colors[branch % colors.len()],
+            None => MINDMAP_ROOT_FILL,
+        }
+    }
+}
+
+fn render_mindmap_shape(
+    svg: &mut String,
+    node: &MindmapNode,
+    center: Point,
+    fill: &str,
+    stroke: &str,
+) -> std::fmt::Result {
+    let half_w = node.width / 2.0;
+    let half_h = node.height / 2.0;
+    let paint = format!("fill=\"{fill}\" stroke=\"{stroke}\" stroke-width=\"2\"");
+    match node.shape {
+        MindmapShape::Default | MindmapShape::Square | MindmapShape::Rounded => {
+            let radius = match node.shape {
+                MindmapShape::Square => 0.0,
+                MindmapShape::Rounded => half_h,
+                _ => 8.0,
+            };
+            writeln!(
+                svg,
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{:.1}\" ry=\"{:.1}\" {paint} />",
+                center.x - half_w,
+                center.y - half_h,
+                node.width,
+                node.height,
+                radius,
+                radius
+            )
+        }
+        MindmapShape::Circle => writeln!(
+            svg,
+            "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" {paint} />",
+            center.x, center.y, half_w
+        ),
+        MindmapShape::Hexagon => {
+            let inset = 15.0_f32.min(half_w / 2.0);
+            writeln!(
+                svg,
+                "    <polygon points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" {paint} />",
+                center.x - half_w,
+                center.y,
+                center.x - half_w + inset,
+                center.y - half_h,
+                center.x + half_w - inset,
+                center.y - half_h,
+                center.x + half_w,
+                center.y,
+                center.x + half_w - inset,
+                center.y + half_h,
+                center.x - half_w + inset,
+                center.y + half_h
+            )
+        }
+        MindmapShape::Cloud => {
+            // Puffs drawn as outward arcs between points on the inscribed ellipse.
+            let points: Vec<Point> = (0..10)
+                .map(|step| {
+                    let angle = TAU * step as f32 / 10.0;
+                    Point {
+                        x: center.x + (half_w - 8.0) * angle.cos(),
+                        y: center.y + (half_h - 8.0) * angle.sin(),
+                    }
+                })
+                .collect();
+            write!(svg, "    <path d=\"M{:.1},{:.1}", points[0].x, points[0].y)?;
+            for (idx, point) in points.iter().enumerate() {
+                let next = points[(idx + 1) % points.len()];
+                let chord = ((next.x - point.x).powi(2) + (next.y - point.y).powi(2)).sqrt();
+                write!(
+                    svg,
+                    " A{:.1},{:.1} 0 0 1 {:.1},{:.1}",
+                    chord * 0.6,
+                    chord * 0.6,
+                    next.x,
+                    next.y
+                )?;
+            }
+            writeln!(svg, " Z\" {paint} />")
+        }
+        MindmapShape::Bang => {
+            let points: Vec<String> = (0..24)
+                .map(|step| {
+                    let angle = TAU * step as f32 / 24.0;
+                    let scale = if step % 2 == 0 { 1.0 } else { 0.82 };
+                    format!(
+                        "{:.1},{:.1}",
+                        center.x + half_w * scale * angle.cos(),
+                        center.y + half_h * scale * angle.sin()
+                    )
+                })
+                .collect();
+            writeln!(
+                svg,
+                "    <polygon points=\"{}\" {paint} />",
+                points.join(" ")
+            )
+        }
+    }
+}
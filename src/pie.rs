@@ -0,0 +1,274 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fmt::Write;
+
+use crate::*;
+
+const PIE_PALETTE: [&str; 12] = [
+    "#4f46e5", "#f59e0b", "#10b981", "#ef4444", "#06b6d4", "#8b5cf6", "#84cc16", "#ec4899",
+    "#f97316", "#14b8a6", "#6366f1", "#eab308",
+];
+
+#[derive(Debug, Clone)]
+pub struct PieChart {
+    pub title: Option<String>,
+    /// `pie showData` appends the raw values to the legend entries.
+    pub show_data: bool,
+    pub slices: Vec<PieSlice>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PieSlice {
+    pub label: String,
+    pub value: f64,
+}
+
+/// Parses `pie [showData] [title ...]` followed by `title ...` and `"Label" : value` lines.
+pub(crate) fn parse_pie_chart(
+    header: &str,
+    lines: Vec<String>,
+    original_source: &str,
+) -> Result<Diagram> {
+    let mut chart = PieChart {
+        title: None,
+        show_data: false,
+        slices: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+
+    let mut header_rest = header.trim()[3..].trim();
+    if let Some(rest) = strip_keyword(header_rest, "showData") {
+        chart.show_data = true;
+        header_rest = rest.trim();
+    }
+    if let Some(title) = strip_keyword(header_rest, "title") {
+        chart.title = Some(decode_label(title.trim())).filter(|title| !title.is_empty());
+    } else if !header_rest.is_empty() {
+        bail!("unexpected '{header_rest}' after 'pie'");
+    }
+
+    for line in lines {
+        let line = line.trim();
+        if line == "showData" {
+            chart.show_data = true;
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title ") {
+            chart.title = Some(decode_label(title.trim()));
+            continue;
+        }
+        let Some((label, value)) = line.rsplit_once(':') else {
+            bail!("expected '\"Label\" : value' but found '{line}'");
+        };
+        let label = label.trim();
+        if !(label.len() >= 2 && label.starts_with('"') && label.ends_with('"')) {
+            bail!("pie slice label {label} must be wrapped in double quotes");
+        }
+        let value: f64 = value
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid value '{}' for slice {label}", value.trim()))?;
+        if !value.is_finite() || value < 0.0 {
+            bail!("slice {label} must have a non-negative value");
+        }
+        chart.slices.push(PieSlice {
+            label: decode_label(label),
+            value,
+        });
+    }
+
+    if chart.slices.is_empty() {
+        bail!("pie chart does not declare any slices");
+    }
+    if chart.total() <= 0.0 {
+        bail!("pie chart values must not all be zero");
+    }
+    if !chart.total().is_finite() {
+        bail!("pie chart values are too large to add up");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    for (idx, slice) in chart.slices.iter().enumerate() {
+        let id = format!("slice{idx}");
+        nodes.insert(
+            id.clone(),
+            Node {
+                label: slice.label.clone(),
+                shape: NodeShape::Rectangle,
+                image: None,
+                width: NODE_WIDTH,
+                height: NODE_HEIGHT,
+            },
+        );
+        order.push(id);
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Pie(chart),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+/// `text` after a leading `keyword` that stands as a whole word.
+fn strip_keyword<'a>(text: &'a str, keyword: &str) -> Option<&'a str> {
+    let rest = text.strip_prefix(keyword)?;
+    (rest.is_empty() || rest.starts_with(char::is_whitespace)).then_some(rest)
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value:.0}")
+    } else {
+        let formatted = format!("{value:.2}");
+        formatted.trim_end_matches('0').to_string()
+    }
+}
+
+impl PieChart {
+    pub fn total(&self) -> f64 {
+        self.slices.iter().map(|slice| slice.value).sum()
+    }
+
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&PIE_PALETTE);
+        let margin = 40.0_f32;
+        let radius = 160.0_f32;
+        let legend_gap = 48.0_f32;
+        let legend_row = 26.0_f32;
+        let swatch = 16.0_f32;
+        let title_height = if self.title.is_some() { 48.0 } else { 0.0 };
+
+        let legend_entries: Vec<String> = self
+            .slices
+            .iter()
+            .map(|slice| {
+                if self.show_data {
+                    format!("{} [{}]", slice.label, format_value(slice.value))
+                } else {
+                    slice.label.clone()
+                }
+            })
+            .collect();
+        let legend_width = legend_entries
+            .iter()
+            .map(|entry| display_width(entry) as f32 * NODE_TEXT_CHAR_WIDTH)
+            .fold(0.0_f32, f32::max)
+            + swatch
+            + 10.0;
+        let legend_height = legend_row * self.slices.len() as f32;
+
+        let chart_height = (radius * 2.0).max(legend_height);
+        let width = margin * 2.0 + radius * 2.0 + legend_gap + legend_width;
+        let height = margin * 2.0 + title_height + chart_height;
+        let cx = margin + radius;
+        let cy = margin + title_height + chart_height / 2.0;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                margin + 20.0,
+                escape_xml(title)
+            )?;
+        }
+
+        let total = self.total() as f32;
+        let mut angle = -FRAC_PI_2;
+        for (idx, slice) in self.slices.iter().enumerate() {
+            let fraction = slice.value as f32 / total;
+            if fraction <= 0.0 {
+                continue;
+            }
+            let color = colors[idx % colors.len()];
+            let sweep = fraction * TAU;
+            if fraction >= 1.0 - f32::EPSILON {
+                writeln!(
+                    svg,
+                    "  <circle class=\"pie-slice\" cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"2\" />",
+                    cx, cy, radius, color
+                )?;
+            } else {
+                let end = angle + sweep;
+                writeln!(
+                    svg,
+                    "  <path class=\"pie-slice\" d=\"M{:.1},{:.1} L{:.1},{:.1} A{:.1},{:.1} 0 {} 1 {:.1},{:.1} Z\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"2\" />",
+                    cx,
+                    cy,
+                    cx + radius * angle.cos(),
+                    cy + radius * angle.sin(),
+                    radius,
+                    radius,
+                    u8::from(sweep > std::f32::consts::PI),
+                    cx + radius * end.cos(),
+                    cy + radius * end.sin(),
+                    color
+                )?;
+            }
+
+            let middle = angle + sweep / 2.0;
+            let label_radius = if fraction >= 1.0 - f32::EPSILON {
+                0.0
+            } else {
+                radius * 0.65
+            };
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{:.0}%</text>",
+                cx + label_radius * middle.cos(),
+                cy + label_radius * middle.sin(),
+                readable_text_color(color),
+                fraction * 100.0
+            )?;
+            angle += sweep;
+        }
+
+        let legend_x = cx + radius + legend_gap;
+        let legend_top = cy - legend_height / 2.0;
+        for (idx, entry) in legend_entries.iter().enumerate() {
+            let row_center = legend_top + legend_row * idx as f32 + legend_row / 2.0;
+            writeln!(
+                svg,
+                "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"3\" ry=\"3\" fill=\"{}\" />",
+                legend_x,
+                row_center - swatch / 2.0,
+                swatch,
+                swatch,
+                colors[idx % colors.len()]
+            )?;
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"14\" text-anchor=\"start\" dominant-baseline=\"middle\">{}</text>",
+                legend_x + swatch + 10.0,
+                row_center,
+                escape_xml(entry)
+            )?;
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
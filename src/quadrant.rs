@@ -0,0 +1,278 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+/// Quadrant fills in Mermaid numbering: top right, top left, bottom left, bottom right.
+const QUADRANT_PALETTE: [&str; 4] = ["#6366f1", "#0ea5e9", "#10b981", "#f59e0b"];
+const QUADRANT_POINT_COLOR: &str = "#1e293b";
+const QUADRANT_MARGIN: f32 = 40.0;
+const QUADRANT_SIZE: f32 = 480.0;
+const QUADRANT_AXIS_SPACE: f32 = 36.0;
+const QUADRANT_POINT_RADIUS: f32 = 6.0;
+
+#[derive(Debug, Clone)]
+pub struct QuadrantChart {
+    pub title: Option<String>,
+    pub x_axis: (Option<String>, Option<String>),
+    pub y_axis: (Option<String>, Option<String>),
+    /// `quadrant-1` through `quadrant-4` titles.
+    pub quadrants: [Option<String>; 4],
+    pub points: Vec<QuadrantPoint>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuadrantPoint {
+    pub label: String,
+    /// 0.0 (left) to 1.0 (right).
+    pub x: f32,
+    /// 0.0 (bottom) to 1.0 (top).
+    pub y: f32,
+}
+
+fn point_regex() -> &'static Regex {
+    static POINT: OnceLock<Regex> = OnceLock::new();
+    POINT.get_or_init(|| {
+        Regex::new(r"^(.+?)\s*:\s*\[\s*([^,\]]+?)\s*,\s*([^,\]]+?)\s*\]$")
+            .expect("valid quadrant point regex")
+    })
+}
+
+/// Splits `Low --> High` axis text into its two ends; the high end is optional.
+fn parse_axis(text: &str) -> (Option<String>, Option<String>) {
+    let (low, high) = match text.split_once("-->") {
+        Some((low, high)) => (low, Some(high)),
+        None => (text, None),
+    };
+    let label = |part: &str| Some(decode_label(part.trim())).filter(|label| !label.is_empty());
+    (label(low), high.and_then(label))
+}
+
+/// Parses `title`, `x-axis`, `y-axis`, `quadrant-N` and `Point: [x, y]` lines.
+pub(crate) fn parse_quadrant_chart(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut chart = QuadrantChart {
+        title: None,
+        x_axis: (None, None),
+        y_axis: (None, None),
+        quadrants: Default::default(),
+        points: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("acctitle") || lower.starts_with("accdescr") {
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title ") {
+            chart.title = Some(decode_label(title.trim()));
+            continue;
+        }
+        if let Some(axis) = line.strip_prefix("x-axis ") {
+            chart.x_axis = parse_axis(axis);
+            continue;
+        }
+        if let Some(axis) = line.strip_prefix("y-axis ") {
+            chart.y_axis = parse_axis(axis);
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("quadrant-") {
+            let (number, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let index = match number {
+                "1" => 0,
+                "2" => 1,
+                "3" => 2,
+                "4" => 3,
+                _ => {
+                    bail!("unknown quadrant 'quadrant-{number}'; expected quadrant-1 to quadrant-4")
+                }
+            };
+            chart.quadrants[index] = Some(decode_label(title.trim()));
+            continue;
+        }
+
+        let Some(caps) = point_regex().captures(line) else {
+            bail!("expected a quadrant point such as 'Name: [0.3, 0.6]' but found '{line}'");
+        };
+        let label = decode_label(caps[1].trim());
+        let coordinate = |value: &str, axis: char| -> Result<f32> {
+            match value.parse::<f32>() {
+                Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+                _ => bail!("point '{label}' has {axis} '{value}'; expected a number from 0 to 1"),
+            }
+        };
+        let x = coordinate(&caps[2], 'x')?;
+        let y = coordinate(&caps[3], 'y')?;
+        chart.points.push(QuadrantPoint { label, x, y });
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    for (idx, point) in chart.points.iter().enumerate() {
+        let id = format!("point{idx}");
+        nodes.insert(
+            id.clone(),
+            Node {
+                label: point.label.clone(),
+                shape: NodeShape::Circle,
+                image: None,
+                width: QUADRANT_POINT_RADIUS * 2.0,
+                height: QUADRANT_POINT_RADIUS * 2.0,
+            },
+        );
+        order.push(id);
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Quadrant(chart),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+impl QuadrantChart {
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&QUADRANT_PALETTE);
+        let title_height = if self.title.is_some() { 48.0 } else { 0.0 };
+        let grid_left = QUADRANT_MARGIN + QUADRANT_AXIS_SPACE;
+        let grid_top = QUADRANT_MARGIN + title_height;
+        let half = QUADRANT_SIZE / 2.0;
+        let width = grid_left + QUADRANT_SIZE + QUADRANT_MARGIN;
+        let height = grid_top + QUADRANT_SIZE + QUADRANT_AXIS_SPACE + QUADRANT_MARGIN;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                QUADRANT_MARGIN + 20.0,
+                escape_xml(title)
+            )?;
+        }
+
+        // Top-left corner of each quadrant, in Mermaid's numbering.
+        let corners = [
+            (grid_left + half, grid_top),
+            (grid_left, grid_top),
+            (grid_left, grid_top + half),
+            (grid_left + half, grid_top + half),
+        ];
+        for (idx, (x, y)) in corners.iter().enumerate() {
+            let color = colors[idx % colors.len()];
+            writeln!(
+                svg,
+                "  <rect class=\"quadrant\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" fill-opacity=\"0.16\" />",
+                x, y, half, half, color
+            )?;
+            if let Some(title) = &self.quadrants[idx] {
+                writeln!(
+                    svg,
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#2d3748\" font-size=\"15\" font-weight=\"600\" text-anchor=\"middle\">{}</text>",
+                    x + half / 2.0,
+                    y + 28.0,
+                    escape_xml(title)
+                )?;
+            }
+        }
+
+        writeln!(
+            svg,
+            "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#a0aec0\" stroke-width=\"2\" />\n  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#a0aec0\" stroke-width=\"1.5\" />\n  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#a0aec0\" stroke-width=\"1.5\" />",
+            grid_left,
+            grid_top,
+            QUADRANT_SIZE,
+            QUADRANT_SIZE,
+            grid_left + half,
+            grid_top,
+            grid_left + half,
+            grid_top + QUADRANT_SIZE,
+            grid_left,
+            grid_top + half,
+            grid_left + QUADRANT_SIZE,
+            grid_top + half
+        )?;
+
+        // Axis ends sit under (or beside) the half of the grid they describe.
+        let x_label_y = grid_top + QUADRANT_SIZE + QUADRANT_AXIS_SPACE / 2.0 + 4.0;
+        for (label, center) in [
+            (&self.x_axis.0, grid_left + half / 2.0),
+            (&self.x_axis.1, grid_left + half * 1.5),
+        ] {
+            if let Some(label) = label {
+                writeln!(
+                    svg,
+                    "  <text class=\"quadrant-axis\" x=\"{:.1}\" y=\"{:.1}\" fill=\"#4a5568\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                    center,
+                    x_label_y,
+                    escape_xml(label)
+                )?;
+            }
+        }
+        let y_label_x = QUADRANT_MARGIN + QUADRANT_AXIS_SPACE / 2.0;
+        for (label, center) in [
+            (&self.y_axis.0, grid_top + half * 1.5),
+            (&self.y_axis.1, grid_top + half / 2.0),
+        ] {
+            if let Some(label) = label {
+                writeln!(
+                    svg,
+                    "  <text class=\"quadrant-axis\" x=\"{:.1}\" y=\"{:.1}\" fill=\"#4a5568\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\" transform=\"rotate(-90 {:.1} {:.1})\">{}</text>",
+                    y_label_x,
+                    center,
+                    y_label_x,
+                    center,
+                    escape_xml(label)
+                )?;
+            }
+        }
+
+        for (idx, point) in self.points.iter().enumerate() {
+            let cx = grid_left + point.x * QUADRANT_SIZE;
+            let cy = grid_top + (1.0 - point.y) * QUADRANT_SIZE;
+            // Points near the bottom edge put their label above so it stays inside the grid.
+            let label_y = if point.y < 0.08 {
+                cy - QUADRANT_POINT_RADIUS - 8.0
+            } else {
+                cy + QUADRANT_POINT_RADIUS + 14.0
+            };
+            writeln!(
+                svg,
+                "  <g class=\"quadrant-point\" data-id=\"point{idx}\">\n    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\" stroke=\"#ffffff\" stroke-width=\"2\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"13\" text-anchor=\"middle\">{}</text>\n  </g>",
+                cx,
+                cy,
+                QUADRANT_POINT_RADIUS,
+                QUADRANT_POINT_COLOR,
+                cx,
+                label_y,
+                escape_xml(&point.label)
+            )?;
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
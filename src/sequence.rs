@@ -0,0 +1,726 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use crate::*;
+
+#[derive(Debug, Clone)]
+pub struct SequenceDiagram {
+    pub title: Option<String>,
+    pub participants: Vec<SequenceParticipant>,
+    pub events: Vec<SequenceEvent>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceParticipant {
+    pub id: String,
+    pub label: String,
+    pub actor: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum SequenceEvent {
+    Message(SequenceMessage),
+    Activate(String),
+    Deactivate(String),
+    BlockStart {
+        kind: SequenceBlockKind,
+        label: String,
+    },
+    /// `else`, `and` or `option` separating the sections of the innermost block.
+    BlockSection {
+        label: String,
+    },
+    BlockEnd,
+}
+
+#[derive(Debug, Clone)]
+pub struct SequenceMessage {
+    pub from: String,
+    pub to: String,
+    pub text: String,
+    pub line: SequenceLine,
+    pub arrow: SequenceArrow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceLine {
+    Solid,
+    Dotted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceArrow {
+    None,
+    Filled,
+    Cross,
+    Open,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceBlockKind {
+    Loop,
+    Alt,
+    Opt,
+    Par,
+    Critical,
+    Break,
+}
+
+impl SequenceBlockKind {
+    fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "loop" => Some(Self::Loop),
+            "alt" => Some(Self::Alt),
+            "opt" => Some(Self::Opt),
+            "par" => Some(Self::Par),
+            "critical" => Some(Self::Critical),
+            "break" => Some(Self::Break),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Loop => "loop",
+            Self::Alt => "alt",
+            Self::Opt => "opt",
+            Self::Par => "par",
+            Self::Critical => "critical",
+            Self::Break => "break",
+        }
+    }
+
+    fn section_keyword(self) -> Option<&'static str> {
+        match self {
+            Self::Alt => Some("else"),
+            Self::Par => Some("and"),
+            Self::Critical => Some("option"),
+            _ => None,
+        }
+    }
+}
+
+fn message_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"^([\w.]+)\s*(-->>|->>|--x|-x|--\)|-\)|-->|->)([+-]?)\s*([\w.]+)\s*(?::\s*(.*))?$",
+        )
+        .expect("valid sequence message regex")
+    })
+}
+
+impl SequenceDiagram {
+    fn ensure_participant(&mut self, id: &str) {
+        if !self.participants.iter().any(|p| p.id == id) {
+            self.participants.push(SequenceParticipant {
+                id: id.to_string(),
+                label: id.to_string(),
+                actor: false,
+            });
+        }
+    }
+
+    fn index_of(&self, id: &str) -> Option<usize> {
+        self.participants.iter().position(|p| p.id == id)
+    }
+}
+
+pub(crate) fn parse_sequence_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut sequence = SequenceDiagram {
+        title: None,
+        participants: Vec::new(),
+        events: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+    let mut open_blocks: Vec<SequenceBlockKind> = Vec::new();
+
+    for line in lines {
+        let line = line.trim();
+        let (keyword, rest) = match line.split_once(char::is_whitespace) {
+            Some((keyword, rest)) => (keyword, rest.trim()),
+            None => (line, ""),
+        };
+        let keyword_lower = keyword.to_ascii_lowercase();
+
+        match keyword_lower.as_str() {
+            "title" => {
+                sequence.title = Some(decode_label(rest.trim_start_matches(':').trim()));
+            }
+            "participant" | "actor" => {
+                if rest.is_empty() {
+                    bail!("'{keyword}' requires a name");
+                }
+                let (id, label) = match rest.split_once(" as ") {
+                    Some((id, label)) => (id.trim(), decode_label(label.trim())),
+                    None => (rest, decode_label(rest)),
+                };
+                if sequence.index_of(id).is_some() {
+                    bail!("participant '{id}' is declared more than once");
+                }
+                sequence.participants.push(SequenceParticipant {
+                    id: id.to_string(),
+                    label,
+                    actor: keyword_lower == "actor",
+                });
+            }
+            "activate" | "deactivate" => {
+                if rest.is_empty() {
+                    bail!("'{keyword}' requires a participant");
+                }
+                sequence.ensure_participant(rest);
+                sequence.events.push(if keyword_lower == "activate" {
+                    SequenceEvent::Activate(rest.to_string())
+                } else {
+                    SequenceEvent::Deactivate(rest.to_string())
+                });
+            }
+            "end" if rest.is_empty() => {
+                if open_blocks.pop().is_none() {
+                    bail!("'end' without a matching block");
+                }
+                sequence.events.push(SequenceEvent::BlockEnd);
+            }
+            "autonumber" => {}
+            _ => {
+                if let Some(kind) = SequenceBlockKind::from_keyword(&keyword_lower) {
+                    open_blocks.push(kind);
+                    sequence.events.push(SequenceEvent::BlockStart {
+                        kind,
+                        label: decode_label(rest),
+                    });
+                    continue;
+                }
+                if let Some(kind) = open_blocks.last()
+                    && kind.section_keyword() == Some(keyword_lower.as_str())
+                {
+                    sequence.events.push(SequenceEvent::BlockSection {
+                        label: decode_label(rest),
+                    });
+                    continue;
+                }
+                let Some(captures) = message_regex().captures(line) else {
+                    bail!("unrecognized sequence diagram statement '{line}'");
+                };
+                let from = captures[1].to_string();
+                let to = captures[4].to_string();
+                let (line_style, arrow) = match &captures[2] {
+                    "->" => (SequenceLine::Solid, SequenceArrow::None),
+                    "-->" => (SequenceLine::Dotted, SequenceArrow::None),
+                    "->>" => (SequenceLine::Solid, SequenceArrow::Filled),
+                    "-->>" => (SequenceLine::Dotted, SequenceArrow::Filled),
+                    "-x" => (SequenceLine::Solid, SequenceArrow::Cross),
+                    "--x" => (SequenceLine::Dotted, SequenceArrow::Cross),
+                    "-)" => (SequenceLine::Solid, SequenceArrow::Open),
+                    _ => (SequenceLine::Dotted, SequenceArrow::Open),
+                };
+                sequence.ensure_participant(&from);
+                sequence.ensure_participant(&to);
+                sequence
+                    .events
+                    .push(SequenceEvent::Message(SequenceMessage {
+                        from: from.clone(),
+                        to: to.clone(),
+                        text: captures
+                            .get(5)
+                            .map(|text| decode_label(text.as_str().trim()))
+                            .unwrap_or_default(),
+                        line: line_style,
+                        arrow,
+                    }));
+                match &captures[3] {
+                    "+" => sequence.events.push(SequenceEvent::Activate(to)),
+                    "-" => sequence.events.push(SequenceEvent::Deactivate(from)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if let Some(kind) = open_blocks.last() {
+        bail!("'{}' block is missing its 'end'", kind.as_str());
+    }
+    if sequence.participants.is_empty() {
+        bail!("sequence diagram does not declare any participants");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    for participant in &sequence.participants {
+        nodes.insert(
+            participant.id.clone(),
+            Node {
+                label: participant.label.clone(),
+                shape: NodeShape::Rectangle,
+                image: None,
+                width: NODE_WIDTH,
+                height: NODE_HEIGHT,
+            },
+        );
+        order.push(participant.id.clone());
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Sequence(sequence),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+struct OpenFrame {
+    kind: SequenceBlockKind,
+    label: String,
+    top: f32,
+    sections: Vec<(f32, String)>,
+    extent: Option<(f32, f32)>,
+}
+
+impl OpenFrame {
+    fn include(&mut self, left: f32, right: f32) {
+        self.extent = Some(match self.extent {
+            Some((l, r)) => (l.min(left), r.max(right)),
+            None => (left, right),
+        });
+    }
+}
+
+fn text_width(text: &str) -> f32 {
+    display_width(text) as f32 * NODE_TEXT_CHAR_WIDTH
+}
+
+impl SequenceDiagram {
+    pub fn render_svg(&self, background: &str) -> Result<String> {
+        let margin = 40.0_f32;
+        let box_height = 44.0_f32;
+        let min_box_width = 110.0_f32;
+        let min_gap = 50.0_f32;
+        let message_text_gap = 20.0_f32;
+        let message_spacing = 24.0_f32;
+        let self_loop_width = 40.0_f32;
+        let self_loop_height = 24.0_f32;
+        let activation_width = 10.0_f32;
+        let frame_padding = 50.0_f32;
+        let frame_header = 26.0_f32;
+
+        let widths: Vec<f32> = self
+            .participants
+            .iter()
+            .map(|p| (text_width(&p.label) + 30.0).max(min_box_width))
+            .collect();
+        let count = self.participants.len();
+
+        // Widen the gap after a participant until every message label between neighbours fits.
+        let mut required_gap = vec![0.0_f32; count];
+        for event in &self.events {
+            let SequenceEvent::Message(message) = event else {
+                continue;
+            };
+            let (Some(from), Some(to)) = (self.index_of(&message.from), self.index_of(&message.to))
+            else {
+                continue;
+            };
+            let needed = text_width(&message.text) + 24.0;
+            if from == to {
+                required_gap[from] = required_gap[from].max(needed + self_loop_width);
+            } else {
+                let (left, right) = (from.min(to), from.max(to));
+                let per_gap = needed / (right - left) as f32;
+                for gap in &mut required_gap[left..right] {
+                    *gap = gap.max(per_gap);
+                }
+            }
+        }
+
+        let mut centers = Vec::with_capacity(count);
+        let mut cursor = margin;
+        for idx in 0..count {
+            let half = widths[idx] / 2.0;
+            if idx > 0 {
+                let spacing = (widths[idx - 1] / 2.0 + half + min_gap).max(required_gap[idx - 1]);
+                cursor = centers[idx - 1] + spacing;
+            } else {
+                cursor += half;
+            }
+            centers.push(cursor);
+        }
+        let mut width = centers[count - 1] + widths[count - 1] / 2.0 + margin;
+        if let Some(gap) = required_gap.last() {
+            width = width.max(centers[count - 1] + gap + margin);
+        }
+
+        let header_top = if self.title.is_some() { 72.0 } else { margin };
+        let mut y = header_top + box_height + 24.0;
+
+        let mut frames = String::new();
+        let mut bars = String::new();
+        let mut messages = String::new();
+        let mut open_frames: Vec<OpenFrame> = Vec::new();
+        let mut active: Vec<Vec<f32>> = vec![Vec::new(); count];
+
+        let anchor_x = |active: &[Vec<f32>], idx: usize, toward_right: bool| -> f32 {
+            let depth = active[idx].len() as f32;
+            if depth == 0.0 {
+                centers[idx]
+            } else {
+                let offset = activation_width / 2.0 + (depth - 1.0) * activation_width / 2.0;
+                if toward_right {
+                    centers[idx] + offset
+                } else {
+                    centers[idx] - offset
+                }
+            }
+        };
+
+        for event in &self.events {
+            match event {
+                SequenceEvent::Message(message) => {
+                    let (Some(from), Some(to)) =
+                        (self.index_of(&message.from), self.index_of(&message.to))
+                    else {
+                        continue;
+                    };
+                    y += message_text_gap;
+                    let dash = if message.line == SequenceLine::Dotted {
+                        " stroke-dasharray=\"5 4\""
+                    } else {
+                        ""
+                    };
+                    let marker = match message.arrow {
+                        SequenceArrow::None => String::new(),
+                        SequenceArrow::Filled => " marker-end=\"url(#seq-arrowhead)\"".to_string(),
+                        SequenceArrow::Cross => " marker-end=\"url(#seq-cross)\"".to_string(),
+                        SequenceArrow::Open => " marker-end=\"url(#seq-open)\"".to_string(),
+                    };
+                    let (left, right);
+                    if from == to {
+                        let x = anchor_x(&active, from, true);
+                        writeln!(
+                            messages,
+                            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1f2937\" font-size=\"13\">{}</text>",
+                            x + 8.0,
+                            y - 6.0,
+                            escape_xml(&message.text)
+                        )?;
+                        writeln!(
+                            messages,
+                            "  <path d=\"M {:.1} {:.1} H {:.1} V {:.1} H {:.1}\" fill=\"none\" stroke=\"#1f2937\" stroke-width=\"1.5\"{}{} />",
+                            x,
+                            y,
+                            x + self_loop_width,
+                            y + self_loop_height,
+                            x,
+                            dash,
+                            marker
+                        )?;
+                        y += self_loop_height;
+                        left = centers[from] - widths[from] / 2.0;
+                        right = x + self_loop_width + text_width(&message.text) + 16.0;
+                    } else {
+                        let start = anchor_x(&active, from, to > from);
+                        let end = anchor_x(&active, to, from > to);
+                        writeln!(
+                            messages,
+                            "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1f2937\" font-size=\"13\" text-anchor=\"middle\">{}</text>",
+                            (start + end) / 2.0,
+                            y - 6.0,
+                            escape_xml(&message.text)
+                        )?;
+                        writeln!(
+                            messages,
+                            "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#1f2937\" stroke-width=\"1.5\"{}{} />",
+                            start, y, end, y, dash, marker
+                        )?;
+                        left = centers[from.min(to)] - frame_padding;
+                        right = centers[from.max(to)] + frame_padding;
+                    }
+                    for frame in &mut open_frames {
+                        frame.include(left, right);
+                    }
+                    y += message_spacing;
+                }
+                SequenceEvent::Activate(id) => {
+                    if let Some(idx) = self.index_of(id) {
+                        active[idx].push(y - message_spacing);
+                    }
+                }
+                SequenceEvent::Deactivate(id) => {
+                    let Some(idx) = self.index_of(id) else {
+                        continue;
+                    };
+                    let depth = active[idx].len();
+                    if let Some(start) = active[idx].pop() {
+                        let end = (y - message_spacing).max(start + 8.0);
+                        write_activation(&mut bars, centers[idx], depth, start, end)?;
+                    }
+                }
+                SequenceEvent::BlockStart { kind, label } => {
+                    y += 8.0;
+                    open_frames.push(OpenFrame {
+                        kind: *kind,
+                        label: label.clone(),
+                        top: y,
+                        sections: Vec::new(),
+                        extent: None,
+                    });
+                    y += frame_header;
+                }
+                SequenceEvent::BlockSection { label } => {
+                    if let Some(frame) = open_frames.last_mut() {
+                        y += 4.0;
+                        frame.sections.push((y, label.clone()));
+                        y += frame_header - 4.0;
+                    }
+                }
+                SequenceEvent::BlockEnd => {
+                    let Some(frame) = open_frames.pop() else {
+                        continue;
+                    };
+                    y += 4.0;
+                    let (left, right) = frame.extent.unwrap_or((
+                        centers[0] - frame_padding,
+                        centers[count - 1] + frame_padding,
+                    ));
+                    if let Some(parent) = open_frames.last_mut() {
+                        parent.include(left - 10.0, right + 10.0);
+                    }
+                    width = width.max(right + margin);
+                    write_frame(&mut frames, &frame, left, right, y)?;
+                    y += 12.0;
+                }
+            }
+        }
+
+        y += 16.0;
+        for (idx, stack) in active.iter().enumerate() {
+            for (depth, start) in stack.iter().enumerate() {
+                write_activation(&mut bars, centers[idx], depth + 1, *start, y - 8.0)?;
+            }
+        }
+
+        let footer_top = y;
+        let height = footer_top + box_height + margin;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <defs>
+    <marker id="seq-arrowhead" markerWidth="10" markerHeight="10" refX="9" refY="5" orient="auto" markerUnits="userSpaceOnUse">
+      <path d="M0,0 L10,5 L0,10 z" fill="#1f2937" />
+    </marker>
+    <marker id="seq-open" markerWidth="10" markerHeight="10" refX="9" refY="5" orient="auto" markerUnits="userSpaceOnUse">
+      <path d="M0,0 L10,5 L0,10" fill="none" stroke="#1f2937" stroke-width="1.5" />
+    </marker>
+    <marker id="seq-cross" markerWidth="10" markerHeight="10" refX="5" refY="5" orient="auto" markerUnits="userSpaceOnUse">
+      <path d="M1,1 L9,9 M9,1 L1,9" stroke="#1f2937" stroke-width="1.5" />
+    </marker>
+  </defs>
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"40\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                escape_xml(title)
+            )?;
+        }
+
+        svg.push_str(&frames);
+        for center in &centers {
+            writeln!(
+                svg,
+                "  <line class=\"sequence-lifeline\" x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#94a3b8\" stroke-width=\"1\" stroke-dasharray=\"4 4\" />",
+                center,
+                header_top + box_height,
+                center,
+                footer_top
+            )?;
+        }
+        svg.push_str(&bars);
+        svg.push_str(&messages);
+        for (idx, participant) in self.participants.iter().enumerate() {
+            for top in [header_top, footer_top] {
+                write_participant(
+                    &mut svg,
+                    participant,
+                    centers[idx],
+                    widths[idx],
+                    top,
+                    box_height,
+                )?;
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
+
+fn write_activation(
+    svg: &mut String,
+    center: f32,
+    depth: usize,
+    start: f32,
+    end: f32,
+) -> Result<()> {
+    let offset = (depth.saturating_sub(1)) as f32 * 5.0;
+    writeln!(
+        svg,
+        "  <rect class=\"sequence-activation\" x=\"{:.1}\" y=\"{:.1}\" width=\"10\" height=\"{:.1}\" fill=\"#e0e7ff\" stroke=\"#4f46e5\" stroke-width=\"1\" />",
+        center - 5.0 + offset,
+        start,
+        end - start
+    )?;
+    Ok(())
+}
+
+fn write_frame(
+    svg: &mut String,
+    frame: &OpenFrame,
+    left: f32,
+    right: f32,
+    bottom: f32,
+) -> Result<()> {
+    let keyword = frame.kind.as_str();
+    let tab_width = text_width(keyword) + 20.0;
+    writeln!(
+        svg,
+        "  <g class=\"sequence-block\" data-block-kind=\"{}\">",
+        keyword
+    )?;
+    writeln!(
+        svg,
+        "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#64748b\" stroke-width=\"1.2\" />",
+        left,
+        frame.top,
+        right - left,
+        bottom - frame.top
+    )?;
+    writeln!(
+        svg,
+        "    <path d=\"M {:.1} {:.1} H {:.1} V {:.1} L {:.1} {:.1} H {:.1} Z\" fill=\"#e2e8f0\" stroke=\"#64748b\" stroke-width=\"1.2\" />",
+        left,
+        frame.top,
+        left + tab_width,
+        frame.top + 12.0,
+        left + tab_width - 6.0,
+        frame.top + 20.0,
+        left
+    )?;
+    writeln!(
+        svg,
+        "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#0f172a\" font-size=\"12\" font-weight=\"600\">{}</text>",
+        left + 6.0,
+        frame.top + 14.0,
+        keyword
+    )?;
+    if !frame.label.is_empty() {
+        writeln!(
+            svg,
+            "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#334155\" font-size=\"12\">[{}]</text>",
+            left + tab_width + 8.0,
+            frame.top + 14.0,
+            escape_xml(&frame.label)
+        )?;
+    }
+    for (section_y, label) in &frame.sections {
+        writeln!(
+            svg,
+            "    <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#64748b\" stroke-width=\"1\" stroke-dasharray=\"6 4\" />",
+            left, section_y, right, section_y
+        )?;
+        if !label.is_empty() {
+            writeln!(
+                svg,
+                "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#334155\" font-size=\"12\" text-anchor=\"middle\">[{}]</text>",
+                (left + right) / 2.0,
+                section_y + 14.0,
+                escape_xml(label)
+            )?;
+        }
+    }
+    svg.push_str("  </g>\n");
+    Ok(())
+}
+
+fn write_participant(
+    svg: &mut String,
+    participant: &SequenceParticipant,
+    center: f32,
+    width: f32,
+    top: f32,
+    height: f32,
+) -> Result<()> {
+    writeln!(
+        svg,
+        "  <g class=\"sequence-participant\" data-id=\"{}\">",
+        escape_xml(&participant.id)
+    )?;
+    if participant.actor {
+        writeln!(
+            svg,
+            "    <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"6\" fill=\"#ffffff\" stroke=\"#1f2937\" stroke-width=\"1.5\" />",
+            center,
+            top + 6.0
+        )?;
+        writeln!(
+            svg,
+            "    <path d=\"M {:.1} {:.1} V {:.1} M {:.1} {:.1} H {:.1} M {:.1} {:.1} L {:.1} {:.1} L {:.1} {:.1}\" fill=\"none\" stroke=\"#1f2937\" stroke-width=\"1.5\" />",
+            center,
+            top + 12.0,
+            top + 22.0,
+            center - 9.0,
+            top + 16.0,
+            center + 9.0,
+            center - 8.0,
+            top + 30.0,
+            center,
+            top + 22.0,
+            center + 8.0,
+            top + 30.0
+        )?;
+        writeln!(
+            svg,
+            "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1f2937\" font-size=\"13\" text-anchor=\"middle\">{}</text>",
+            center,
+            top + height - 2.0,
+            escape_xml(&participant.label)
+        )?;
+    } else {
+        writeln!(
+            svg,
+            "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" ry=\"6\" fill=\"#eef2ff\" stroke=\"#4f46e5\" stroke-width=\"1.5\" />",
+            center - width / 2.0,
+            top,
+            width,
+            height
+        )?;
+        writeln!(
+            svg,
+            "    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1f2937\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+            center,
+            top + height / 2.0,
+            escape_xml(&participant.label)
+        )?;
+    }
+    svg.push_str("  </g>\n");
+    Ok(())
+}
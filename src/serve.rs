@@ -1,8 +1,8 @@
 use std::collections::HashSet;
-use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{Context, Result, anyhow, bail};
 use axum::extract::{DefaultBodyLimit, Path as AxumPath, State};
@@ -10,7 +10,7 @@ use axum::http::StatusCode;
 use axum::http::{HeaderValue, header};
 use axum::response::IntoResponse;
 use axum::response::Response;
-use axum::routing::{delete, get, put};
+use axum::routing::{delete, get, post, put};
 use axum::{Json, Router};
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
@@ -30,14 +30,20 @@ use crate::*;
 
 const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
 const MAX_IMAGE_REQUEST_BYTES: usize = (MAX_IMAGE_BYTES * 4) / 3 + 1 * 1024 * 1024;
+pub const DEFAULT_GIT_COMMIT_MESSAGE: &str = "oxdraw: {summary} in {file}";
 
 /// Arguments for running the oxdraw web server
 #[derive(Debug, Clone, Parser)]
 #[command(name = "oxdraw serve", about = "Start the oxdraw web sync API server.")]
 pub struct ServeArgs {
     /// Path to the diagram definition that should be served.
-    #[arg(short = 'i', long = "input")]
-    pub input: PathBuf,
+    #[arg(short = 'i', long = "input", required_unless_present = "store")]
+    pub input: Option<PathBuf>,
+
+    /// JSON or YAML file selecting where the diagram is stored instead of `--input`, e.g.
+    /// `{"kind": "sqlite", "path": "diagrams.db", "name": "flow.mmd"}`.
+    #[arg(long = "store", conflicts_with = "input")]
+    pub store: Option<PathBuf>,
 
     /// Address to bind the HTTP server to.
     #[arg(long, default_value = "127.0.0.1")]
@@ -47,9 +53,44 @@ pub struct ServeArgs {
     #[arg(long, default_value_t = 5151)]
     pub port: u16,
 
-    /// Background color for rendered SVG previews.
-    #[arg(long = "background-color", default_value = "white")]
-    pub background_color: String,
+    /// Background color for rendered SVG previews; defaults to the theme's background.
+    #[arg(long = "background-color")]
+    pub background_color: Option<String>,
+
+    /// Built-in theme or .toml/.json theme file for every render; overrides the frontmatter
+    /// `config.theme`.
+    #[arg(long = "theme")]
+    pub theme: Option<String>,
+
+    /// URL to POST to whenever the diagram source or layout changes. May be repeated.
+    #[arg(long = "webhook")]
+    pub webhooks: Vec<String>,
+
+    /// Secret used to sign webhook payloads (sent as `X-Oxdraw-Signature`).
+    #[arg(long = "webhook-secret", requires = "webhooks")]
+    pub webhook_secret: Option<String>,
+
+    /// Stage and commit the diagram file with git after every saved change.
+    #[arg(long = "git-commit", conflicts_with = "store")]
+    pub git_commit: bool,
+
+    /// Commit message template; `{summary}`, `{change}` and `{file}` are substituted.
+    #[arg(long = "git-commit-message", requires = "git_commit")]
+    pub git_commit_message: Option<String>,
+
+    /// How edges are grouped when the editor rewrites the definition.
+    #[arg(long = "group-edges", value_enum, default_value_t)]
+    pub group_edges: EdgeGrouping,
+
+    /// Keep nodes where the previous layout put them when the source changes, placing only
+    /// new nodes.
+    #[arg(long = "stable-layout")]
+    pub stable_layout: bool,
+
+    /// Reject edits, dry runs included, whose saved source would exceed the limits for
+    /// untrusted input: source size, node and edge counts, label length and nesting depth.
+    #[arg(long = "untrusted-limits")]
+    pub untrusted_limits: bool,
 
     /// Path to the codebase for code map mode.
     #[clap(skip)]
@@ -65,13 +106,21 @@ pub struct ServeArgs {
 }
 
 struct ServeState {
-    source_path: PathBuf,
-    background: String,
+    store: Arc<dyn DiagramStore>,
+    webhooks: WebhookDispatcher,
+    git: Option<GitCommitter>,
+    format: DefinitionFormat,
+    background: Option<String>,
+    theme: Option<Theme>,
     overrides: RwLock<LayoutOverrides>,
     source_lock: Mutex<()>,
     code_map_root: Option<PathBuf>,
     code_map_mapping: Option<CodeMapMapping>,
     code_map_warning: Option<String>,
+    /// Automatic positions last served, by node id, when `--stable-layout` is on.
+    stable_layout: Option<RwLock<HashMap<String, Point>>>,
+    /// Quota every saved source must fit, checked before it is written.
+    limits: ParseLimits,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -79,9 +128,13 @@ struct ServeState {
 struct DiagramPayload {
     source_path: String,
     kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
     background: String,
     auto_size: CanvasSize,
     render_size: CanvasSize,
+    // Render space = diagram space + shift; see `DiagramViewModel::coordinate_shift`.
+    coordinate_shift: Point,
     nodes: Vec<NodePayload>,
     edges: Vec<EdgePayload>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -89,6 +142,18 @@ struct DiagramPayload {
     #[serde(skip_serializing_if = "Option::is_none")]
     gantt: Option<GanttPayload>,
     source: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<ParseError>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    layout_diagnostics: Vec<LayoutDiagnostic>,
+    debug: DebugPayload,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DebugPayload {
+    /// Reading and parsing the source, then each layout phase.
+    timings: Vec<PhaseTiming>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -153,8 +218,15 @@ struct NodePayload {
     label_fill_color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     image_fill_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stroke_width: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    border_style: Option<BorderStyle>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     membership: Vec<String>,
+    pinned: bool,
+    /// Stands in for a collapsed subgraph of the same id.
+    collapsed: bool,
     width: f32,
     height: f32,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -204,6 +276,12 @@ struct EdgePayload {
     color: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     arrow_direction: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label_fill_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label_stroke_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    animated: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -214,6 +292,8 @@ struct LayoutUpdate {
     edges: HashMap<String, Option<EdgeOverride>>,
     #[serde(default)]
     gantt_tasks: HashMap<String, Option<GanttTaskLayoutUpdate>>,
+    #[serde(default)]
+    pinned: HashMap<String, bool>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -229,6 +309,14 @@ struct SourceUpdateRequest {
     source: String,
 }
 
+/// What a `?dry_run=true` request would have saved.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DryRunPayload {
+    source: String,
+    changes: SourceChangeSet,
+}
+
 #[derive(Debug, Deserialize, Default)]
 struct StyleUpdate {
     #[serde(default)]
@@ -249,21 +337,44 @@ struct NodeImageUpdateRequest {
     padding: Option<f32>,
 }
 
+#[derive(Debug, Deserialize)]
+struct QuickAddRequest {
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    from: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct QuickAddPayload {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_id: Option<String>,
+}
+
 impl ServeState {
+    /// The pass seeding layouts from the last served positions, if `--stable-layout` is on.
+    async fn incremental_layout(&self) -> Option<IncrementalLayout> {
+        let previous = self.stable_layout.as_ref()?.read().await.clone();
+        Some(IncrementalLayout { previous })
+    }
+
     async fn read_diagram(&self) -> Result<(String, Diagram)> {
-        let contents = tokio::fs::read_to_string(&self.source_path)
-            .await
-            .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+        let (contents, diagram, _) = self.read_diagram_with_diagnostics().await?;
+        Ok((contents, diagram))
+    }
+
+    /// Reads leniently so a file that is mid-edit still renders; skipped lines are reported.
+    async fn read_diagram_with_diagnostics(&self) -> Result<(String, Diagram, Vec<ParseError>)> {
+        let contents = self.store.read().await?;
         let (definition, _) = split_source_and_overrides(&contents)?;
-        let diagram = match Diagram::parse(&definition) {
-            Ok(d) => d,
+        let (mut diagram, diagnostics) = match Diagram::parse_lenient(&definition) {
+            Ok(parsed) => parsed,
             Err(e) => {
                 // If this is a markdown file and we failed to parse as a diagram,
                 // return a dummy diagram so the UI can load and switch to codedown mode.
-                let is_md = self
-                    .source_path
-                    .extension()
-                    .map_or(false, |ext| ext == "md");
+                let is_md = self.store.location().ends_with(".md");
                 if is_md {
                     let mut nodes = HashMap::new();
                     nodes.insert(
@@ -276,41 +387,102 @@ impl ServeState {
                             height: NODE_HEIGHT,
                         },
                     );
-                    Diagram {
+                    let diagram = Diagram {
                         kind: DiagramKind::Flowchart,
+                        config: DiagramConfig::default(),
                         direction: Direction::TopDown,
                         nodes,
                         order: vec!["dummy".to_string()],
                         edges: Vec::new(),
                         subgraphs: Vec::new(),
                         node_membership: HashMap::new(),
-                    }
+                    };
+                    (diagram, Vec::new())
                 } else {
                     return Err(e);
                 }
             }
         };
-        Ok((contents, diagram))
+        if let Some(theme) = &self.theme {
+            diagram.config.custom_theme = Some(theme.clone());
+        }
+        Ok((contents, diagram, diagnostics))
+    }
+
+    /// `--background-color` when given, else the page color of the diagram's theme.
+    fn page_background(&self, diagram: &Diagram) -> String {
+        diagram.config.page_background(self.background.as_deref())
     }
 
     async fn current_overrides(&self) -> LayoutOverrides {
         self.overrides.read().await.clone()
     }
 
+    /// Copy of this state over an in-memory store, so a dry run can go through the real
+    /// mutation without touching the file, git or webhooks.
+    async fn scratch(&self) -> Result<ServeState> {
+        let contents = self.store.read().await?;
+        Ok(ServeState {
+            store: Arc::new(MemoryStore::new(self.store.location(), contents)),
+            webhooks: WebhookDispatcher::new(Vec::new()),
+            git: None,
+            format: self.format,
+            background: self.background.clone(),
+            theme: self.theme.clone(),
+            overrides: RwLock::new(self.current_overrides().await),
+            source_lock: Mutex::new(()),
+            code_map_root: None,
+            code_map_mapping: None,
+            code_map_warning: None,
+            stable_layout: None,
+            limits: self.limits,
+        })
+    }
+
+    /// Compares a scratch state after a dry run against the saved diagram.
+    async fn preview(&self, scratch: &ServeState) -> Result<DryRunPayload> {
+        let (_, before) = self.read_diagram().await?;
+        let (source, after) = scratch.read_diagram().await?;
+        let mut changes = before.change_set(&after);
+        let node_ids: HashSet<String> = after.nodes.keys().cloned().collect();
+        let edge_ids: HashSet<String> = after.edges.iter().map(edge_identifier).collect();
+        changes.record_pruned(self.current_overrides().await.prune(&node_ids, &edge_ids));
+        Ok(DryRunPayload { source, changes })
+    }
+
     async fn apply_update(&self, update: LayoutUpdate) -> Result<()> {
         let LayoutUpdate {
             nodes,
             edges,
             gantt_tasks,
+            pinned,
         } = update;
 
         if !gantt_tasks.is_empty() {
             self.apply_gantt_task_updates(&gantt_tasks).await?;
         }
 
-        if nodes.is_empty() && edges.is_empty() {
+        if nodes.is_empty() && edges.is_empty() && pinned.is_empty() {
             return Ok(());
         }
+        let summary = describe_change(
+            "update layout of",
+            nodes.keys().chain(edges.keys()).chain(pinned.keys()),
+        );
+
+        let needs_positions = {
+            let overrides = self.overrides.read().await;
+            pinned.iter().any(|(id, pin)| {
+                *pin && !overrides.nodes.contains_key(id) && !nodes.contains_key(id)
+            })
+        };
+        let current_positions = if needs_positions {
+            let (_, diagram) = self.read_diagram().await?;
+            let overrides = self.current_overrides().await;
+            diagram.layout(Some(&overrides))?.final_positions
+        } else {
+            HashMap::new()
+        };
 
         let snapshot = {
             let mut overrides = self.overrides.write().await;
@@ -323,13 +495,28 @@ impl ServeState {
                         changed = true;
                     }
                     None => {
-                        if overrides.nodes.remove(&id).is_some() {
+                        if !overrides.pinned.contains(&id) && overrides.nodes.remove(&id).is_some()
+                        {
                             changed = true;
                         }
                     }
                 }
             }
 
+            for (id, pin) in pinned {
+                if pin {
+                    let position = overrides
+                        .nodes
+                        .get(&id)
+                        .or_else(|| current_positions.get(&id))
+                        .copied()
+                        .ok_or_else(|| anyhow!("cannot pin unknown node '{id}'"))?;
+                    changed |= overrides.pin(&id, position);
+                } else {
+                    changed |= overrides.unpin(&id);
+                }
+            }
+
             for (id, value) in edges {
                 match value {
                     Some(edge_override) if !edge_override.points.is_empty() => {
@@ -352,7 +539,8 @@ impl ServeState {
         };
 
         if let Some(snapshot) = snapshot {
-            self.rewrite_file_with_overrides(&snapshot).await
+            self.rewrite_file_with_overrides(&snapshot, ChangeKind::Layout, &summary)
+                .await
         } else {
             Ok(())
         }
@@ -363,9 +551,7 @@ impl ServeState {
         gantt_tasks: &HashMap<String, Option<GanttTaskLayoutUpdate>>,
     ) -> Result<()> {
         let _guard = self.source_lock.lock().await;
-        let contents = tokio::fs::read_to_string(&self.source_path)
-            .await
-            .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+        let contents = self.store.read().await?;
         let (definition, _) = split_source_and_overrides(&contents)?;
         let diagram = Diagram::parse(&definition)?;
         let DiagramKind::Gantt(gantt) = &diagram.kind else {
@@ -384,13 +570,16 @@ impl ServeState {
         };
 
         let merged = merge_source_and_overrides(&rewritten, &snapshot)?;
-        tokio::fs::write(&self.source_path, merged.as_bytes())
-            .await
-            .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+        let summary = describe_change("reschedule", gantt_tasks.keys());
+        self.persist(&merged, ChangeKind::Layout, &summary).await?;
         Ok(())
     }
 
     async fn apply_style_update(&self, update: StyleUpdate) -> Result<()> {
+        let summary = describe_change(
+            "restyle",
+            update.node_styles.keys().chain(update.edge_styles.keys()),
+        );
         let snapshot = {
             let mut overrides = self.overrides.write().await;
 
@@ -414,6 +603,12 @@ impl ServeState {
                         if let Some(image_fill) = patch.image_fill {
                             current.image_fill = image_fill;
                         }
+                        if let Some(stroke_width) = patch.stroke_width {
+                            current.stroke_width = stroke_width;
+                        }
+                        if let Some(border_style) = patch.border_style {
+                            current.border_style = border_style;
+                        }
 
                         if current.is_empty() {
                             overrides.node_styles.remove(&id);
@@ -441,6 +636,15 @@ impl ServeState {
                         if let Some(arrow) = patch.arrow {
                             current.arrow = arrow;
                         }
+                        if let Some(label_fill) = patch.label_fill {
+                            current.label_fill = label_fill;
+                        }
+                        if let Some(label_stroke) = patch.label_stroke {
+                            current.label_stroke = label_stroke;
+                        }
+                        if let Some(animated) = patch.animated {
+                            current.animated = animated;
+                        }
 
                         if current.is_empty() {
                             overrides.edge_styles.remove(&id);
@@ -478,10 +682,23 @@ impl ServeState {
             overrides.clone()
         };
 
-        self.rewrite_file_with_overrides(&snapshot).await
+        self.rewrite_file_with_overrides(&snapshot, ChangeKind::Style, &summary)
+            .await
+    }
+
+    async fn relayout(&self) -> Result<()> {
+        let snapshot = {
+            let mut overrides = self.overrides.write().await;
+            if !overrides.clear_unpinned_positions() {
+                return Ok(());
+            }
+            overrides.clone()
+        };
+        self.rewrite_file_with_overrides(&snapshot, ChangeKind::Layout, "reset automatic layout")
+            .await
     }
 
-    async fn prune_overrides_for(&self, diagram: &Diagram) -> Result<()> {
+    async fn prune_overrides_for(&self, diagram: &Diagram, summary: &str) -> Result<()> {
         let node_ids: HashSet<String> = diagram.nodes.keys().cloned().collect();
         let edge_ids: HashSet<String> = diagram
             .edges
@@ -495,17 +712,22 @@ impl ServeState {
             overrides.clone()
         };
 
-        let definition = diagram.to_definition();
-        self.write_definition_with_overrides(&definition, &snapshot)
+        let definition = diagram.to_definition_with(&self.format);
+        self.write_definition_with_overrides(&definition, &snapshot, ChangeKind::Source, summary)
             .await
     }
 
-    async fn replace_source(&self, contents: &str) -> Result<()> {
+    async fn replace_source(&self, contents: &str) -> Result<SourceChangeSet> {
         let has_block = contents
             .lines()
             .any(|line| line.trim().eq_ignore_ascii_case(LAYOUT_BLOCK_START));
         let (definition, parsed_overrides) = split_source_and_overrides(contents)?;
         let diagram = Diagram::parse(&definition)?;
+        // An unreadable previous file has nothing meaningful to diff against.
+        let mut changes = match self.read_diagram().await {
+            Ok((_, previous)) => previous.change_set(&diagram),
+            Err(_) => SourceChangeSet::default(),
+        };
 
         let node_ids: HashSet<String> = diagram.nodes.keys().cloned().collect();
         let edge_ids: HashSet<String> = diagram
@@ -519,24 +741,52 @@ impl ServeState {
             if has_block {
                 *overrides = parsed_overrides;
             }
-            overrides.prune(&node_ids, &edge_ids);
+            changes.record_pruned(overrides.prune(&node_ids, &edge_ids));
             overrides.clone()
         };
 
-        self.write_definition_with_overrides(&definition, &snapshot)
-            .await
+        self.write_definition_with_overrides(
+            &definition,
+            &snapshot,
+            ChangeKind::Source,
+            "edit source",
+        )
+        .await?;
+        Ok(changes)
     }
 
-    async fn rewrite_file_with_overrides(&self, overrides: &LayoutOverrides) -> Result<()> {
+    /// Writes a finished change to the store and announces it; callers hold `source_lock`.
+    async fn persist(&self, contents: &str, change: ChangeKind, summary: &str) -> Result<()> {
+        if self.limits != ParseLimits::default() {
+            let (definition, _) = split_source_and_overrides(contents)?;
+            Diagram::parse_with_limits(&definition, &self.limits)?;
+        }
+        self.store.write(contents).await?;
+        if let Some(git) = &self.git
+            && let Err(err) = git.commit(change, summary).await
+        {
+            status(UiMessage::GitCommitFailed(&format!("{err:#}")));
+        }
+        self.webhooks.notify(WebhookPayload {
+            file_id: self.store.location(),
+            change,
+            summary: summary.to_string(),
+            source: contents.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn rewrite_file_with_overrides(
+        &self,
+        overrides: &LayoutOverrides,
+        change: ChangeKind,
+        summary: &str,
+    ) -> Result<()> {
         let _guard = self.source_lock.lock().await;
-        let contents = tokio::fs::read_to_string(&self.source_path)
-            .await
-            .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+        let contents = self.store.read().await?;
         let (definition, _) = split_source_and_overrides(&contents)?;
         let merged = merge_source_and_overrides(&definition, overrides)?;
-        tokio::fs::write(&self.source_path, merged.as_bytes())
-            .await
-            .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+        self.persist(&merged, change, summary).await?;
         Ok(())
     }
 
@@ -544,21 +794,39 @@ impl ServeState {
         &self,
         definition: &str,
         overrides: &LayoutOverrides,
+        change: ChangeKind,
+        summary: &str,
     ) -> Result<()> {
         let merged = merge_source_and_overrides(definition, overrides)?;
         let _guard = self.source_lock.lock().await;
-        tokio::fs::write(&self.source_path, merged.as_bytes())
-            .await
-            .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+        self.persist(&merged, change, summary).await?;
         Ok(())
     }
 
+    async fn quick_add_node(
+        &self,
+        label: &str,
+        from: Option<&str>,
+    ) -> Result<(String, Option<String>)> {
+        let overrides_snapshot = self.overrides.read().await.clone();
+        let _guard = self.source_lock.lock().await;
+        let contents = self.store.read().await?;
+        let (definition, _) = split_source_and_overrides(&contents)?;
+        let mut diagram = Diagram::parse(&definition)?;
+        let (id, edge_id) = diagram.quick_add_node(label, from)?;
+        let merged = merge_source_and_overrides(
+            &diagram.to_definition_with(&self.format),
+            &overrides_snapshot,
+        )?;
+        self.persist(&merged, ChangeKind::Source, &format!("add node {id}"))
+            .await?;
+        Ok((id, edge_id))
+    }
+
     async fn remove_node(&self, node_id: &str) -> Result<bool> {
         let diagram = {
             let _guard = self.source_lock.lock().await;
-            let source = tokio::fs::read_to_string(&self.source_path)
-                .await
-                .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+            let source = self.store.read().await?;
             let mut diagram = Diagram::parse(&source)?;
             if diagram.nodes.len() == 1 && diagram.nodes.contains_key(node_id) {
                 bail!("diagram must contain at least one node");
@@ -566,82 +834,200 @@ impl ServeState {
             if !diagram.remove_node(node_id) {
                 return Ok(false);
             }
-            let rewritten = diagram.to_definition();
-            tokio::fs::write(&self.source_path, rewritten.as_bytes())
-                .await
-                .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+            let rewritten = diagram.to_definition_with(&self.format);
+            self.store.write(&rewritten).await?;
             diagram
         };
 
-        self.prune_overrides_for(&diagram).await?;
+        self.prune_overrides_for(&diagram, &format!("remove node {node_id}"))
+            .await?;
         Ok(true)
     }
 
     async fn remove_edge(&self, edge_id: &str) -> Result<bool> {
         let diagram = {
             let _guard = self.source_lock.lock().await;
-            let source = tokio::fs::read_to_string(&self.source_path)
-                .await
-                .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+            let source = self.store.read().await?;
             let mut diagram = Diagram::parse(&source)?;
             if !diagram.remove_edge_by_identifier(edge_id) {
                 return Ok(false);
             }
-            let rewritten = diagram.to_definition();
-            tokio::fs::write(&self.source_path, rewritten.as_bytes())
-                .await
-                .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+            let rewritten = diagram.to_definition_with(&self.format);
+            self.store.write(&rewritten).await?;
             diagram
         };
 
-        self.prune_overrides_for(&diagram).await?;
+        self.prune_overrides_for(&diagram, &format!("remove edge {edge_id}"))
+            .await?;
+        Ok(true)
+    }
+
+    async fn set_subgraph_collapsed(&self, subgraph_id: &str, collapsed: bool) -> Result<bool> {
+        let (_, diagram) = self.read_diagram().await?;
+        if diagram.subgraph(subgraph_id).is_none() {
+            return Ok(false);
+        }
+        let snapshot = {
+            let mut overrides = self.overrides.write().await;
+            if !overrides.set_collapsed(subgraph_id, collapsed) {
+                return Ok(true);
+            }
+            overrides.clone()
+        };
+        let summary = if collapsed {
+            format!("collapse subgraph {subgraph_id}")
+        } else {
+            format!("expand subgraph {subgraph_id}")
+        };
+        self.rewrite_file_with_overrides(&snapshot, ChangeKind::Layout, &summary)
+            .await?;
         Ok(true)
     }
 
     async fn set_node_image(&self, node_id: &str, image: Option<NodeImage>) -> Result<()> {
         let overrides_snapshot = self.overrides.read().await.clone();
         let _guard = self.source_lock.lock().await;
-        let contents = tokio::fs::read_to_string(&self.source_path)
-            .await
-            .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+        let contents = self.store.read().await?;
         let (definition, _) = split_source_and_overrides(&contents)?;
         let mut diagram = Diagram::parse(&definition)?;
         let Some(node) = diagram.nodes.get_mut(node_id) else {
             bail!("node '{node_id}' not found");
         };
+        let summary = if image.is_some() {
+            format!("set image on node {node_id}")
+        } else {
+            format!("remove image from node {node_id}")
+        };
         node.image = image;
-        let rewritten = diagram.to_definition();
+        diagram.config.image_sources.remove(node_id);
+        let rewritten = diagram.to_definition_with(&self.format);
         let merged = merge_source_and_overrides(&rewritten, &overrides_snapshot)?;
-        tokio::fs::write(&self.source_path, merged.as_bytes())
-            .await
-            .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+        self.persist(&merged, ChangeKind::Source, &summary).await?;
         Ok(())
     }
 
     async fn update_node_image_padding(&self, node_id: &str, padding: f32) -> Result<()> {
         let overrides_snapshot = self.overrides.read().await.clone();
         let _guard = self.source_lock.lock().await;
-        let contents = tokio::fs::read_to_string(&self.source_path)
-            .await
-            .with_context(|| format!("failed to read '{}'", self.source_path.display()))?;
+        let contents = self.store.read().await?;
         let (definition, _) = split_source_and_overrides(&contents)?;
         let mut diagram = Diagram::parse(&definition)?;
         let Some(node) = diagram.nodes.get_mut(node_id) else {
             bail!("node '{node_id}' not found");
         };
-        let Some(image) = node.image.as_mut() else {
-            bail!("node '{node_id}' does not have an image to update");
-        };
-        image.padding = padding;
-        let rewritten = diagram.to_definition();
+        if let Some(source) = diagram.config.image_sources.get_mut(node_id) {
+            source.padding = padding;
+        } else {
+            let Some(image) = node.image.as_mut() else {
+                bail!("node '{node_id}' does not have an image to update");
+            };
+            image.padding = padding;
+        }
+        let rewritten = diagram.to_definition_with(&self.format);
         let merged = merge_source_and_overrides(&rewritten, &overrides_snapshot)?;
-        tokio::fs::write(&self.source_path, merged.as_bytes())
+        let summary = format!("adjust image padding on node {node_id}");
+        self.persist(&merged, ChangeKind::Source, &summary).await?;
+        Ok(())
+    }
+}
+
+/// Commits the served file after every saved change (`--git-commit`).
+struct GitCommitter {
+    path: PathBuf,
+    message_template: String,
+}
+
+impl GitCommitter {
+    fn new(path: PathBuf, message_template: String) -> Result<Self> {
+        if !path.is_file() {
+            bail!("--git-commit requires a diagram stored in a local file");
+        }
+        let output = std::process::Command::new("git")
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .current_dir(path.parent().unwrap_or(Path::new(".")))
+            .output()
+            .context("failed to run git")?;
+        if !output.status.success() {
+            bail!(
+                "--git-commit requires '{}' to be inside a git repository",
+                path.display()
+            );
+        }
+        Ok(Self {
+            path,
+            message_template,
+        })
+    }
+
+    fn message(&self, change: ChangeKind, summary: &str) -> String {
+        let file = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.message_template
+            .replace("{summary}", summary)
+            .replace("{change}", change.as_str())
+            .replace("{file}", &file)
+    }
+
+    async fn commit(&self, change: ChangeKind, summary: &str) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let file = self.path.file_name().unwrap_or_default();
+        let add = tokio::process::Command::new("git")
+            .arg("add")
+            .arg("--")
+            .arg(file)
+            .current_dir(dir)
+            .output()
+            .await
+            .context("failed to run git add")?;
+        if !add.status.success() {
+            bail!(
+                "git add failed: {}",
+                String::from_utf8_lossy(&add.stderr).trim()
+            );
+        }
+
+        let commit = tokio::process::Command::new("git")
+            .args(["commit", "--quiet", "-m"])
+            .arg(self.message(change, summary))
+            .arg("--")
+            .arg(file)
+            .current_dir(dir)
+            .output()
             .await
-            .with_context(|| format!("failed to write '{}'", self.source_path.display()))?;
+            .context("failed to run git commit")?;
+        if !commit.status.success() {
+            let stdout = String::from_utf8_lossy(&commit.stdout);
+            // Saving an unchanged file is not an error.
+            if stdout.contains("nothing to commit") || stdout.contains("no changes added") {
+                return Ok(());
+            }
+            bail!(
+                "git commit failed: {}",
+                String::from_utf8_lossy(&commit.stderr).trim()
+            );
+        }
         Ok(())
     }
 }
 
+fn describe_change<'a>(verb: &str, ids: impl Iterator<Item = &'a String>) -> String {
+    const LISTED: usize = 3;
+    let ids: std::collections::BTreeSet<&String> = ids.collect();
+    let mut listed = ids
+        .iter()
+        .take(LISTED)
+        .map(|id| id.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if ids.len() > LISTED {
+        listed.push_str(&format!(" and {} more", ids.len() - LISTED));
+    }
+    format!("{verb} {listed}")
+}
+
 #[derive(Debug, Deserialize)]
 struct OpenRequest {
     path: String,
@@ -709,29 +1095,90 @@ async fn open_in_editor(
 }
 
 pub async fn run_serve(args: ServeArgs, ui_root: Option<PathBuf>) -> Result<()> {
-    let initial_source = fs::read_to_string(&args.input)
-        .with_context(|| format!("failed to read '{}'", args.input.display()))?;
+    let store = open_store(&args)?;
+    run_serve_with_store(args, store, ui_root).await
+}
+
+/// The store `--store` configures, or a [`FileStore`] over `--input`.
+fn open_store(args: &ServeArgs) -> Result<Arc<dyn DiagramStore>> {
+    match (&args.store, &args.input) {
+        (Some(config), _) => StoreConfig::load(config)?.open(),
+        (None, Some(input)) => Ok(Arc::new(FileStore::new(input.clone()))),
+        (None, None) => bail!("pass --input <FILE> or --store <CONFIG>"),
+    }
+}
+
+/// Runs the server against an arbitrary [`DiagramStore`]; `args.input` is ignored.
+pub async fn run_serve_with_store(
+    args: ServeArgs,
+    store: Arc<dyn DiagramStore>,
+    ui_root: Option<PathBuf>,
+) -> Result<()> {
+    let initial_source = store.read().await?;
     let (_, overrides) = split_source_and_overrides(&initial_source)?;
+    let theme = args.theme.as_deref().map(Theme::resolve).transpose()?;
+
+    let webhooks = args
+        .webhooks
+        .iter()
+        .map(|url| Webhook {
+            url: url.clone(),
+            secret: args.webhook_secret.clone(),
+        })
+        .collect();
+
+    let git = if args.git_commit {
+        let template = args
+            .git_commit_message
+            .clone()
+            .unwrap_or_else(|| DEFAULT_GIT_COMMIT_MESSAGE.to_string());
+        Some(GitCommitter::new(
+            PathBuf::from(store.location()),
+            template,
+        )?)
+    } else {
+        None
+    };
 
     let state = Arc::new(ServeState {
-        source_path: args.input.clone(),
+        store,
+        webhooks: WebhookDispatcher::new(webhooks),
+        git,
+        format: DefinitionFormat {
+            edge_grouping: args.group_edges,
+        },
         background: args.background_color.clone(),
+        theme,
         overrides: RwLock::new(overrides),
         source_lock: Mutex::new(()),
         code_map_root: args.code_map_root,
         code_map_mapping: args.code_map_mapping,
         code_map_warning: args.code_map_warning,
+        stable_layout: args.stable_layout.then(|| RwLock::new(HashMap::new())),
+        limits: if args.untrusted_limits {
+            ParseLimits::untrusted()
+        } else {
+            ParseLimits::default()
+        },
     });
 
     let mut app = Router::new()
         .route("/api/diagram", get(get_diagram))
         .route("/api/diagram/svg", get(get_svg))
+        .route("/api/diagram/png", post(post_viewport_png))
+        .route("/api/diagram/minimap", get(get_minimap))
         .route("/api/diagram/layout", put(put_layout))
+        .route("/api/diagram/relayout", post(post_relayout))
         .route("/api/diagram/style", put(put_style))
         .route("/api/diagram/source", get(get_source).put(put_source))
+        .route("/api/diagram/nodes", post(post_node))
         .route("/api/diagram/nodes/:id/image", put(put_node_image))
         .route("/api/diagram/nodes/:id", delete(delete_node))
         .route("/api/diagram/edges/:id", delete(delete_edge))
+        .route(
+            "/api/diagram/subgraphs/:id/collapsed",
+            put(put_subgraph_collapsed),
+        )
         .route("/api/codemap/mapping", get(get_codemap_mapping))
         .route("/api/codemap/status", get(get_codemap_status))
         .route("/api/codemap/file", get(get_codemap_file))
@@ -769,8 +1216,8 @@ pub async fn run_serve(args: ServeArgs, ui_root: Option<PathBuf>) -> Result<()>
         .await
         .with_context(|| format!("failed to bind HTTP server to {addr}"))?;
 
-    println!("oxdraw server listening on http://{addr}");
-    println!("Press Ctrl+C to stop.");
+    status(UiMessage::ServerListening(&addr.to_string()));
+    status(UiMessage::PressCtrlC);
 
     axum::serve(listener, app)
         .with_graceful_shutdown(async {
@@ -784,19 +1231,36 @@ pub async fn run_serve(args: ServeArgs, ui_root: Option<PathBuf>) -> Result<()>
 
 async fn get_diagram(
     State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<ViewQuery>,
 ) -> Result<Json<DiagramPayload>, (StatusCode, String)> {
-    let (source, diagram) = state.read_diagram().await.map_err(internal_error)?;
+    let parse_started = Instant::now();
+    let (source, mut diagram, diagnostics) = state
+        .read_diagram_with_diagnostics()
+        .await
+        .map_err(internal_error)?;
+    let mut timings = vec![PhaseTiming {
+        phase: "parse",
+        millis: parse_started.elapsed().as_secs_f64() * 1000.0,
+    }];
     let overrides = state.current_overrides().await;
+    apply_view_query(&mut diagram, &overrides, &query)?;
 
-    let layout = diagram.layout(Some(&overrides)).map_err(internal_error)?;
-    let geometry = align_geometry(
-        &layout.final_positions,
-        &layout.final_routes,
-        &diagram.edges,
-        &diagram.subgraphs,
-        &diagram.nodes,
-    )
+    let incremental = state.incremental_layout().await;
+    let (layout, layout_timings) = match &incremental {
+        Some(pass) => diagram.layout_timed(Some(&overrides), &[pass]),
+        None => diagram.layout_timed(Some(&overrides), &[]),
+    }
     .map_err(internal_error)?;
+    if let Some(previous) = &state.stable_layout {
+        previous.write().await.extend(
+            layout
+                .auto_positions
+                .iter()
+                .map(|(id, point)| (id.clone(), *point)),
+        );
+    }
+    timings.extend(layout_timings);
+    let geometry = diagram.geometry(&layout).map_err(internal_error)?;
 
     let mut nodes = Vec::new();
     for id in &diagram.order {
@@ -821,6 +1285,8 @@ async fn get_diagram(
         let text_color = style.and_then(|s| s.text.clone());
         let label_fill_color = style.and_then(|s| s.label_fill.clone());
         let image_fill_color = style.and_then(|s| s.image_fill.clone());
+        let stroke_width = style.and_then(|s| s.stroke_width);
+        let border_style = style.and_then(|s| s.border_style);
         let image_payload = node.image.as_ref().map(|image| NodeImagePayload {
             mime_type: image.mime_type.clone(),
             data: BASE64_STANDARD.encode(&image.data),
@@ -840,7 +1306,11 @@ async fn get_diagram(
             text_color,
             label_fill_color,
             image_fill_color,
+            stroke_width,
+            border_style,
             membership: diagram.node_membership.get(id).cloned().unwrap_or_default(),
+            pinned: overrides.pinned.contains(id),
+            collapsed: overrides.collapsed.contains(id),
             width: node.width,
             height: node.height,
             image: image_payload,
@@ -874,6 +1344,9 @@ async fn get_diagram(
         let arrow_direction = style
             .and_then(|s| s.arrow)
             .map(|direction| direction.as_str().to_string());
+        let label_fill_color = style.and_then(|s| s.label_fill.clone());
+        let label_stroke_color = style.and_then(|s| s.label_stroke.clone());
+        let animated = style.and_then(|s| s.animated);
 
         edges.push(EdgePayload {
             id: identifier,
@@ -886,6 +1359,9 @@ async fn get_diagram(
             override_points: manual_points,
             color,
             arrow_direction,
+            label_fill_color,
+            label_stroke_color,
+            animated,
         });
     }
 
@@ -908,6 +1384,18 @@ async fn get_diagram(
 
     let (kind, gantt_payload) = match &diagram.kind {
         DiagramKind::Flowchart => ("flowchart".to_string(), None),
+        DiagramKind::Sequence(_) => ("sequence".to_string(), None),
+        DiagramKind::State(_) => ("state".to_string(), None),
+        DiagramKind::Class(_) => ("class".to_string(), None),
+        DiagramKind::Er(_) => ("er".to_string(), None),
+        DiagramKind::Pie(_) => ("pie".to_string(), None),
+        DiagramKind::Mindmap(_) => ("mindmap".to_string(), None),
+        DiagramKind::Journey(_) => ("journey".to_string(), None),
+        DiagramKind::Timeline(_) => ("timeline".to_string(), None),
+        DiagramKind::GitGraph(_) => ("gitGraph".to_string(), None),
+        DiagramKind::Quadrant(_) => ("quadrantChart".to_string(), None),
+        DiagramKind::C4(_) => ("c4".to_string(), None),
+        DiagramKind::Block(_) => ("block".to_string(), None),
         DiagramKind::Gantt(gantt) => {
             let gantt_overrides = &overrides.gantt;
             let row_fill_even = gantt_overrides
@@ -1006,27 +1494,98 @@ async fn get_diagram(
     };
 
     let payload = DiagramPayload {
-        source_path: state.source_path.display().to_string(),
+        source_path: state.store.location(),
         kind,
-        background: state.background.clone(),
+        title: diagram.config.title.clone(),
+        background: state.page_background(&diagram),
         auto_size: layout.auto_size,
         render_size: CanvasSize {
             width: geometry.width,
             height: geometry.height,
         },
+        coordinate_shift: geometry.shift,
         nodes,
         edges,
         subgraphs,
         gantt: gantt_payload,
         source,
+        diagnostics,
+        layout_diagnostics: diagram.layout_diagnostics(&layout),
+        debug: DebugPayload { timings },
     };
 
     Ok(Json(payload))
 }
 
-async fn get_svg(State(state): State<Arc<ServeState>>) -> Result<Response, (StatusCode, String)> {
-    let (_, diagram) = state.read_diagram().await.map_err(internal_error)?;
+async fn get_svg(
+    State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<ViewQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let (contents, mut diagram) = state.read_diagram().await.map_err(internal_error)?;
+    let overrides = state.current_overrides().await;
+    let zoom = apply_view_query(&mut diagram, &overrides, &query)?;
+    let override_ref = if overrides.is_empty() {
+        None
+    } else {
+        Some(&overrides)
+    };
+
+    let incremental = state.incremental_layout().await;
+    let mut svg = match &incremental {
+        Some(pass) => {
+            diagram.render_svg_with(&state.page_background(&diagram), override_ref, &[pass])
+        }
+        None => diagram.render_svg(&state.page_background(&diagram), override_ref),
+    }
+    .map_err(internal_error)?;
+    if zoom != 1.0 {
+        svg = zoom_svg(&svg, zoom).map_err(internal_error)?;
+    }
+    let (definition, _) = split_source_and_overrides(&contents).map_err(internal_error)?;
+    let svg = embed_source_metadata(&svg, &definition, &overrides).map_err(internal_error)?;
+
+    let mut response = Response::new(svg.into());
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("image/svg+xml"),
+    );
+    Ok(response)
+}
+
+async fn post_viewport_png(
+    State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<ViewQuery>,
+    Json(viewport): Json<ViewportRender>,
+) -> Result<Response, (StatusCode, String)> {
+    let (_, mut diagram) = state.read_diagram().await.map_err(internal_error)?;
+    let overrides = state.current_overrides().await;
+    // The viewport carries its own zoom, so only the view's hidden elements and theme apply.
+    apply_view_query(&mut diagram, &overrides, &query)?;
+    let override_ref = if overrides.is_empty() {
+        None
+    } else {
+        Some(&overrides)
+    };
+
+    let png = diagram
+        .render_viewport_png(&state.page_background(&diagram), override_ref, &viewport)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    let mut response = Response::new(png.into());
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("image/png"));
+    Ok(response)
+}
+
+async fn get_minimap(
+    State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<ViewQuery>,
+    axum::extract::Query(minimap): axum::extract::Query<MinimapRender>,
+) -> Result<Response, (StatusCode, String)> {
+    let (_, mut diagram) = state.read_diagram().await.map_err(internal_error)?;
     let overrides = state.current_overrides().await;
+    apply_view_query(&mut diagram, &overrides, &query)?;
     let override_ref = if overrides.is_empty() {
         None
     } else {
@@ -1034,8 +1593,8 @@ async fn get_svg(State(state): State<Arc<ServeState>>) -> Result<Response, (Stat
     };
 
     let svg = diagram
-        .render_svg(&state.background, override_ref)
-        .map_err(internal_error)?;
+        .render_minimap_svg(&state.page_background(&diagram), override_ref, &minimap)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
 
     let mut response = Response::new(svg.into());
     response.headers_mut().insert(
@@ -1047,21 +1606,37 @@ async fn get_svg(State(state): State<Arc<ServeState>>) -> Result<Response, (Stat
 
 async fn put_layout(
     State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
     Json(update): Json<LayoutUpdate>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    state.apply_update(update).await.map_err(internal_error)?;
-    Ok(StatusCode::NO_CONTENT)
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    target.apply_update(update).await.map_err(save_error)?;
+    dry_run_response(&state, scratch.as_ref()).await
+}
+
+async fn post_relayout(
+    State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    target.relayout().await.map_err(save_error)?;
+    dry_run_response(&state, scratch.as_ref()).await
 }
 
 async fn put_style(
     State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
     Json(update): Json<StyleUpdate>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    state
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    target
         .apply_style_update(update)
         .await
-        .map_err(internal_error)?;
-    Ok(StatusCode::NO_CONTENT)
+        .map_err(save_error)?;
+    dry_run_response(&state, scratch.as_ref()).await
 }
 
 async fn get_source(
@@ -1073,28 +1648,73 @@ async fn get_source(
 
 async fn put_source(
     State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
     Json(payload): Json<SourceUpdateRequest>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    state
+) -> Result<Response, Response> {
+    if query.dry_run {
+        let scratch = state
+            .scratch()
+            .await
+            .map_err(|err| internal_error(err).into_response())?;
+        // Source edits already report their change set, so only the text is added.
+        let changes = scratch
+            .replace_source(&payload.source)
+            .await
+            .map_err(source_error)?;
+        let source = scratch
+            .store
+            .read()
+            .await
+            .map_err(|err| internal_error(err).into_response())?;
+        return Ok(Json(DryRunPayload { source, changes }).into_response());
+    }
+    let changes = state
         .replace_source(&payload.source)
         .await
-        .map_err(internal_error)?;
-    Ok(StatusCode::NO_CONTENT)
+        .map_err(source_error)?;
+    Ok(Json(changes).into_response())
+}
+
+async fn post_node(
+    State(state): State<Arc<ServeState>>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
+    Json(payload): Json<QuickAddRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let from = payload.from.as_deref().filter(|from| !from.is_empty());
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    match target.quick_add_node(&payload.label, from).await {
+        Ok(_) if scratch.is_some() => dry_run_response(&state, scratch.as_ref()).await,
+        Ok((id, edge_id)) => {
+            Ok((StatusCode::CREATED, Json(QuickAddPayload { id, edge_id })).into_response())
+        }
+        Err(err) => {
+            let message = err.to_string();
+            if message.contains("not found") || message.contains("only be added") {
+                Err((StatusCode::BAD_REQUEST, message))
+            } else {
+                Err(save_error(err))
+            }
+        }
+    }
 }
 
 async fn delete_node(
     State(state): State<Arc<ServeState>>,
     AxumPath(node_id): AxumPath<String>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    match state.remove_node(&node_id).await {
-        Ok(true) => Ok(StatusCode::NO_CONTENT),
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    match target.remove_node(&node_id).await {
+        Ok(true) => dry_run_response(&state, scratch.as_ref()).await,
         Ok(false) => Err((StatusCode::NOT_FOUND, format!("node '{node_id}' not found"))),
         Err(err) => {
             let message = err.to_string();
             if message.contains("at least one node") {
                 Err((StatusCode::BAD_REQUEST, message))
             } else {
-                Err(internal_error(err))
+                Err(save_error(err))
             }
         }
     }
@@ -1103,18 +1723,125 @@ async fn delete_node(
 async fn delete_edge(
     State(state): State<Arc<ServeState>>,
     AxumPath(edge_id): AxumPath<String>,
-) -> Result<impl IntoResponse, (StatusCode, String)> {
-    match state.remove_edge(&edge_id).await {
-        Ok(true) => Ok(StatusCode::NO_CONTENT),
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    match target.remove_edge(&edge_id).await {
+        Ok(true) => dry_run_response(&state, scratch.as_ref()).await,
         Ok(false) => Err((StatusCode::NOT_FOUND, format!("edge '{edge_id}' not found"))),
-        Err(err) => Err(internal_error(err)),
+        Err(err) => Err(save_error(err)),
+    }
+}
+
+async fn put_subgraph_collapsed(
+    State(state): State<Arc<ServeState>>,
+    AxumPath(subgraph_id): AxumPath<String>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
+    Json(request): Json<CollapseRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
+    match target
+        .set_subgraph_collapsed(&subgraph_id, request.collapsed)
+        .await
+    {
+        Ok(true) => dry_run_response(&state, scratch.as_ref()).await,
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            format!("subgraph '{subgraph_id}' not found"),
+        )),
+        Err(err) => Err(save_error(err)),
+    }
+}
+
+/// Scratch state for a `?dry_run=true` request, which mutates it instead of `state`.
+async fn dry_run_scratch(
+    state: &ServeState,
+    query: &DryRunQuery,
+) -> Result<Option<ServeState>, (StatusCode, String)> {
+    if !query.dry_run {
+        return Ok(None);
     }
+    state.scratch().await.map(Some).map_err(internal_error)
+}
+
+/// The preview of a dry run, or `204 No Content` once a real change has been saved.
+async fn dry_run_response(
+    state: &ServeState,
+    scratch: Option<&ServeState>,
+) -> Result<Response, (StatusCode, String)> {
+    match scratch {
+        Some(scratch) => {
+            let preview = state.preview(scratch).await.map_err(internal_error)?;
+            Ok(Json(preview).into_response())
+        }
+        None => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+/// Collapses the subgraphs marked in `overrides`, then applies `?view=`, `?layout=` and the
+/// spacing parameters to a freshly read diagram and returns the view's zoom.
+fn apply_view_query(
+    diagram: &mut Diagram,
+    overrides: &LayoutOverrides,
+    query: &ViewQuery,
+) -> Result<f32, (StatusCode, String)> {
+    diagram.apply_layout_options(&LayoutOptions {
+        node_spacing: query.node_spacing,
+        rank_spacing: query.rank_spacing,
+        margin: query.margin,
+        layout: query.layout,
+        wrap_width: query.wrap_width,
+        max_label_width: query.max_label_width,
+    });
+    diagram.apply_collapsed(&overrides.collapsed);
+    let mut zoom = 1.0;
+    if let Some(name) = &query.view {
+        let view = overrides
+            .view(name)
+            .map_err(|err| (StatusCode::NOT_FOUND, err.to_string()))?;
+        diagram.apply_view(view);
+        zoom = view.zoom.unwrap_or(1.0);
+    }
+    if let Some(name) = &query.theme {
+        let theme = Theme::built_in(name).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "unknown theme '{name}'; expected {}",
+                    BUILT_IN_THEMES.join(", ")
+                ),
+            )
+        })?;
+        diagram.config.custom_theme = Some(theme);
+    }
+    Ok(zoom)
 }
 
 fn internal_error(err: anyhow::Error) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
 
+/// An edit the diagram can't take, such as one past `--untrusted-limits`, is the client's
+/// error; anything else failing while saving is the server's.
+fn save_error(err: anyhow::Error) -> (StatusCode, String) {
+    match err.downcast_ref::<ParseError>() {
+        Some(parse_error) => (StatusCode::UNPROCESSABLE_ENTITY, parse_error.to_string()),
+        None => internal_error(err),
+    }
+}
+
+/// Parse failures are reported as a structured [`ParseError`] so editors can highlight the line.
+fn source_error(err: anyhow::Error) -> Response {
+    match err.downcast_ref::<ParseError>() {
+        Some(parse_error) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(parse_error.clone())).into_response()
+        }
+        None => internal_error(err).into_response(),
+    }
+}
+
 fn rewrite_gantt_task_lines(
     definition: &str,
     gantt: &GanttData,
@@ -1291,8 +2018,9 @@ fn rewrite_gantt_task_lines(
 async fn put_node_image(
     State(state): State<Arc<ServeState>>,
     AxumPath(node_id): AxumPath<String>,
+    axum::extract::Query(query): axum::extract::Query<DryRunQuery>,
     Json(payload): Json<NodeImageUpdateRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     let NodeImageUpdateRequest {
         mime_type,
         data,
@@ -1307,6 +2035,8 @@ async fn put_node_image(
         }
     });
 
+    let scratch = dry_run_scratch(&state, &query).await?;
+    let target = scratch.as_ref().unwrap_or(&state);
     let data_str = match data
         .as_deref()
         .map(str::trim)
@@ -1315,17 +2045,17 @@ async fn put_node_image(
         Some(value) => value,
         None => {
             if let Some(padding_value) = sanitized_padding {
-                state
+                target
                     .update_node_image_padding(&node_id, padding_value)
                     .await
-                    .map_err(internal_error)?;
+                    .map_err(save_error)?;
             } else {
-                state
+                target
                     .set_node_image(&node_id, None)
                     .await
-                    .map_err(internal_error)?;
+                    .map_err(save_error)?;
             }
-            return Ok(StatusCode::NO_CONTENT);
+            return dry_run_response(&state, scratch.as_ref()).await;
         }
     };
 
@@ -1370,12 +2100,12 @@ async fn put_node_image(
         padding: sanitized_padding.unwrap_or(0.0),
     };
 
-    state
+    target
         .set_node_image(&node_id, Some(image))
         .await
-        .map_err(internal_error)?;
+        .map_err(save_error)?;
 
-    Ok(StatusCode::NO_CONTENT)
+    dry_run_response(&state, scratch.as_ref()).await
 }
 
 fn merge_source_and_overrides(definition: &str, overrides: &LayoutOverrides) -> Result<String> {
@@ -1426,6 +2156,44 @@ struct EdgeStylePatch {
     color: Option<Option<String>>,
     #[serde(default)]
     arrow: Option<Option<EdgeArrowDirection>>,
+    #[serde(default)]
+    label_fill: Option<Option<String>>,
+    #[serde(default)]
+    label_stroke: Option<Option<String>>,
+    #[serde(default)]
+    animated: Option<Option<bool>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ViewQuery {
+    #[serde(default)]
+    view: Option<String>,
+    #[serde(default)]
+    node_spacing: Option<f32>,
+    #[serde(default)]
+    rank_spacing: Option<f32>,
+    #[serde(default)]
+    margin: Option<f32>,
+    #[serde(default)]
+    layout: Option<LayoutEngine>,
+    #[serde(default)]
+    wrap_width: Option<f32>,
+    #[serde(default)]
+    max_label_width: Option<f32>,
+    /// Built-in theme name; theme files are only read from `--theme`.
+    #[serde(default)]
+    theme: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollapseRequest {
+    collapsed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DryRunQuery {
+    #[serde(default)]
+    dry_run: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1531,12 +2299,11 @@ async fn get_codemap_file(
                         a.cmp(b)
                     }
                 });
-                println!(
-                    "Ambiguous file request '{}'. Found {} matches. Selecting '{}'.",
-                    name_string,
-                    matches.len(),
-                    matches[0].display()
-                );
+                status(UiMessage::AmbiguousFile {
+                    name: &name_string,
+                    matches: matches.len(),
+                    selected: &matches[0],
+                });
                 Some(matches[0].clone())
             }
         })
@@ -1664,3 +2431,365 @@ async fn get_codemap_search(
 
     Ok(Json(results))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn memory_state(source: &str) -> ServeState {
+        state_with(Arc::new(MemoryStore::new("flow.mmd", source)))
+    }
+
+    fn state_with(store: Arc<dyn DiagramStore>) -> ServeState {
+        ServeState {
+            store,
+            webhooks: WebhookDispatcher::new(Vec::new()),
+            git: None,
+            format: DefinitionFormat::default(),
+            background: None,
+            theme: None,
+            overrides: RwLock::new(LayoutOverrides::default()),
+            source_lock: Mutex::new(()),
+            code_map_root: None,
+            code_map_mapping: None,
+            code_map_warning: None,
+            stable_layout: None,
+            limits: ParseLimits::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn git_committer_commits_each_change() {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["config", "user.name", "oxdraw"]);
+        git(dir.path(), &["config", "user.email", "oxdraw@example.com"]);
+        let path = dir.path().join("flow.mmd");
+        std::fs::write(&path, "graph TD\n  A --> B\n").unwrap();
+
+        let committer =
+            GitCommitter::new(path.clone(), DEFAULT_GIT_COMMIT_MESSAGE.to_string()).unwrap();
+        committer
+            .commit(ChangeKind::Source, "edit source")
+            .await
+            .unwrap();
+        std::fs::write(&path, "graph TD\n  A --> C\n").unwrap();
+        let summary = describe_change(
+            "update layout of",
+            ["D", "A", "C", "B", "A"].map(String::from).iter(),
+        );
+        committer
+            .commit(ChangeKind::Layout, &summary)
+            .await
+            .unwrap();
+        // Nothing changed since the last commit.
+        committer
+            .commit(ChangeKind::Layout, &summary)
+            .await
+            .unwrap();
+
+        let log = git(dir.path(), &["log", "--format=%s"]);
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            [
+                "oxdraw: update layout of A, B, C and 1 more in flow.mmd",
+                "oxdraw: edit source in flow.mmd",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn saves_are_committed_with_git_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flow.mmd");
+        std::fs::write(&path, "graph TD\n  A --> B\n").unwrap();
+        assert!(GitCommitter::new(path.clone(), DEFAULT_GIT_COMMIT_MESSAGE.to_string()).is_err());
+
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["config", "user.name", "oxdraw"]);
+        git(dir.path(), &["config", "user.email", "oxdraw@example.com"]);
+        let mut state = state_with(Arc::new(FileStore::new(&path)));
+        state.git = Some(
+            GitCommitter::new(path.clone(), "{change}: {summary} ({file})".to_string()).unwrap(),
+        );
+
+        state
+            .replace_source("graph TD\n  A --> B\n  B --> C\n")
+            .await
+            .unwrap();
+        assert!(state.remove_node("A").await.unwrap());
+
+        let log = git(dir.path(), &["log", "--format=%s"]);
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            [
+                "source: remove node A (flow.mmd)",
+                "source: edit source (flow.mmd)",
+            ]
+        );
+        assert_eq!(
+            git(dir.path(), &["show", "HEAD:flow.mmd"]),
+            std::fs::read_to_string(&path).unwrap()
+        );
+        assert!(git(dir.path(), &["status", "--porcelain"]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn edits_save_through_every_store_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        #[cfg_attr(not(feature = "sqlite"), allow(unused_mut))]
+        let mut stores: Vec<Arc<dyn DiagramStore>> = vec![
+            Arc::new(FileStore::new(dir.path().join("flow.mmd"))),
+            Arc::new(MemoryStore::new("flow.mmd", "")),
+        ];
+        #[cfg(feature = "sqlite")]
+        stores.push(Arc::new(
+            SqliteStore::open(dir.path().join("diagrams.db"), "flow.mmd").unwrap(),
+        ));
+
+        for store in stores {
+            let state = state_with(store.clone());
+            // Nothing saved yet: a missing file or row, or an empty memory store.
+            assert!(state.read_diagram().await.is_err());
+            state.replace_source("graph TD\n  A --> B\n").await.unwrap();
+            assert_eq!(store.read().await.unwrap(), "graph TD\n  A --> B\n");
+            let (_, diagram) = state.read_diagram().await.unwrap();
+            assert_eq!(diagram.edges.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn store_flag_opens_the_configured_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("store.yaml");
+        std::fs::write(
+            &config,
+            "kind: memory\nname: scratch.mmd\nsource: \"graph TD\\n  A --> B\\n\"\n",
+        )
+        .unwrap();
+        let args = ServeArgs::parse_from(["oxdraw", "--store", config.to_str().unwrap()]);
+        let store = open_store(&args).unwrap();
+        assert_eq!(store.location(), "scratch.mmd");
+        assert_eq!(store.read().await.unwrap(), "graph TD\n  A --> B\n");
+
+        #[cfg(feature = "sqlite")]
+        {
+            let config = dir.path().join("sqlite.json");
+            let database = dir.path().join("diagrams.db");
+            std::fs::write(
+                &config,
+                serde_json::json!({"kind": "sqlite", "path": database, "name": "flow.mmd"})
+                    .to_string(),
+            )
+            .unwrap();
+            let args = ServeArgs::parse_from(["oxdraw", "--store", config.to_str().unwrap()]);
+            let store = open_store(&args).unwrap();
+            store.write("graph LR\n  X --> Y\n").await.unwrap();
+            assert_eq!(store.read().await.unwrap(), "graph LR\n  X --> Y\n");
+        }
+
+        assert!(ServeArgs::try_parse_from(["oxdraw"]).is_err());
+        assert!(
+            ServeArgs::try_parse_from(["oxdraw", "-i", "flow.mmd", "--store", "store.yaml"])
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_previews_without_saving() {
+        let source = "graph TD\n  A --> B\n  B --> C\n";
+        let mut overrides = LayoutOverrides::default();
+        overrides.pin("C", Point { x: 10.0, y: 20.0 });
+        let state = memory_state(source);
+        *state.overrides.write().await = overrides;
+
+        let scratch = state.scratch().await.unwrap();
+        assert!(scratch.remove_node("C").await.unwrap());
+        let preview = state.preview(&scratch).await.unwrap();
+        assert!(!preview.source.contains('C'));
+        assert_eq!(preview.changes.nodes.removed, vec!["C"]);
+        assert_eq!(preview.changes.edges.removed, vec!["B --> C"]);
+        assert_eq!(preview.changes.pruned_overrides.nodes, vec!["C"]);
+        assert!(preview.changes.destructive);
+
+        assert_eq!(state.store.read().await.unwrap(), source);
+        assert!(state.current_overrides().await.pinned.contains("C"));
+    }
+
+    #[tokio::test]
+    async fn dry_runs_leave_the_saved_file_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flow.mmd");
+        let source = "graph TD\n  subgraph Team\n    A --> B\n  end\n  B --> C\n";
+        std::fs::write(&path, source).unwrap();
+        let state = Arc::new(state_with(Arc::new(FileStore::new(&path))));
+        let dry_run = || axum::extract::Query(DryRunQuery { dry_run: true });
+        let unchanged = |what: &str| {
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), source, "{what}");
+        };
+
+        let response = put_source(
+            State(state.clone()),
+            dry_run(),
+            Json(SourceUpdateRequest {
+                source: "graph TD\n  X --> Y\n".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        unchanged("PUT source");
+
+        let mut layout = LayoutUpdate::default();
+        layout
+            .nodes
+            .insert("A".to_string(), Some(Point { x: 5.0, y: 5.0 }));
+        put_layout(State(state.clone()), dry_run(), Json(layout))
+            .await
+            .unwrap();
+        put_style(
+            State(state.clone()),
+            dry_run(),
+            Json(StyleUpdate::default()),
+        )
+        .await
+        .unwrap();
+        put_subgraph_collapsed(
+            State(state.clone()),
+            AxumPath("Team".to_string()),
+            dry_run(),
+            Json(CollapseRequest { collapsed: true }),
+        )
+        .await
+        .unwrap();
+        put_node_image(
+            State(state.clone()),
+            AxumPath("A".to_string()),
+            dry_run(),
+            Json(NodeImageUpdateRequest {
+                mime_type: None,
+                data: None,
+                padding: None,
+            }),
+        )
+        .await
+        .unwrap();
+        unchanged("PUT layout, style, collapsed and image");
+
+        delete_node(State(state.clone()), AxumPath("C".to_string()), dry_run())
+            .await
+            .unwrap();
+        delete_edge(
+            State(state.clone()),
+            AxumPath("A --> B".to_string()),
+            dry_run(),
+        )
+        .await
+        .unwrap();
+        unchanged("DELETE node and edge");
+
+        let response = post_node(
+            State(state.clone()),
+            dry_run(),
+            Json(QuickAddRequest {
+                label: "New".to_string(),
+                from: Some("C".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        post_relayout(State(state.clone()), dry_run())
+            .await
+            .unwrap();
+        unchanged("POST node and relayout");
+        assert!(state.current_overrides().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_runs_report_edits_past_the_limits() {
+        let mut state = memory_state("graph TD\n  A --> B\n");
+        state.limits = ParseLimits {
+            max_nodes: Some(2),
+            ..ParseLimits::default()
+        };
+        let state = Arc::new(state);
+
+        let (status, message) = post_node(
+            State(state.clone()),
+            axum::extract::Query(DryRunQuery { dry_run: true }),
+            Json(QuickAddRequest {
+                label: "C".to_string(),
+                from: Some("B".to_string()),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(message.contains("node count exceeds the limit of 2"));
+
+        let response = put_source(
+            State(state.clone()),
+            axum::extract::Query(DryRunQuery { dry_run: false }),
+            Json(SourceUpdateRequest {
+                source: "graph TD\n  A --> B\n  B --> C\n".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(state.store.read().await.unwrap(), "graph TD\n  A --> B\n");
+    }
+
+    #[tokio::test]
+    async fn collapsing_a_subgraph_is_saved_in_the_layout_block() {
+        let source = "graph TD\n  subgraph Team\n    A --> B\n  end\n  B --> C\n";
+        let state = memory_state(source);
+
+        assert!(!state.set_subgraph_collapsed("Nope", true).await.unwrap());
+        assert!(state.set_subgraph_collapsed("Team", true).await.unwrap());
+        let saved = state.store.read().await.unwrap();
+        let (_, overrides) = split_source_and_overrides(&saved).unwrap();
+        assert!(overrides.collapsed.contains("Team"));
+
+        assert!(state.set_subgraph_collapsed("Team", false).await.unwrap());
+        assert!(state.current_overrides().await.collapsed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn viewport_png_rejects_oversized_images() {
+        let state = Arc::new(memory_state("graph TD\n  A --> B\n"));
+        let viewport = |width: f32, height: f32| ViewportRender {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+            zoom: 1.0,
+            selected_nodes: Vec::new(),
+            selected_edges: Vec::new(),
+            selection_color: None,
+        };
+        let render = |viewport| {
+            post_viewport_png(
+                State(state.clone()),
+                axum::extract::Query(ViewQuery::default()),
+                Json(viewport),
+            )
+        };
+
+        assert!(render(viewport(200.0, 100.0)).await.is_ok());
+        let (status, message) = render(viewport(50_000.0, 50_000.0)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.contains("exceeds the limit"));
+    }
+}
@@ -0,0 +1,375 @@
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::diagram::compute_node_dimensions;
+use crate::*;
+
+const STATE_START_SIZE: f32 = 20.0;
+const STATE_END_SIZE: f32 = 26.0;
+const STATE_CHOICE_SIZE: f32 = 36.0;
+
+/// `stateDiagram-v2` sources are translated into the flowchart model, so states, transitions and
+/// composite states reuse the regular layered layout, renderer and editor overrides.
+#[derive(Debug, Clone)]
+pub struct StateDiagramData {
+    pub original_source: String,
+}
+
+fn transition_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(r"^(\[\*\]|[\w.]+)\s*-->\s*(\[\*\]|[\w.]+)\s*(?::\s*(.*))?$")
+            .expect("valid state transition regex")
+    })
+}
+
+struct Composite {
+    subgraph: Subgraph,
+    parent: Option<usize>,
+}
+
+struct StateBuilder {
+    nodes: HashMap<String, Node>,
+    order: Vec<String>,
+    edges: Vec<Edge>,
+    composites: Vec<Composite>,
+    composite_ids: HashMap<String, usize>,
+    stack: Vec<usize>,
+    node_membership: HashMap<String, Vec<String>>,
+}
+
+impl StateBuilder {
+    fn scope_prefix(&self) -> String {
+        match self.stack.last() {
+            Some(&idx) => format!("{}__", self.composites[idx].subgraph.id),
+            None => "__".to_string(),
+        }
+    }
+
+    fn add_node(&mut self, id: &str, label: String, shape: NodeShape, size: Option<f32>) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            if shape != NodeShape::Rectangle {
+                let (width, height) = match size {
+                    Some(size) => (size, size),
+                    None => compute_node_dimensions(shape, &node.label),
+                };
+                node.shape = shape;
+                node.label = label;
+                node.width = width;
+                node.height = height;
+            }
+            return;
+        }
+        let (width, height) = match size {
+            Some(size) => (size, size),
+            None => compute_node_dimensions(shape, &label),
+        };
+        self.nodes.insert(
+            id.to_string(),
+            Node {
+                label,
+                shape,
+                image: None,
+                width,
+                height,
+            },
+        );
+        self.order.push(id.to_string());
+        if let Some(&innermost) = self.stack.last() {
+            self.composites[innermost]
+                .subgraph
+                .nodes
+                .push(id.to_string());
+            self.node_membership.insert(
+                id.to_string(),
+                self.stack
+                    .iter()
+                    .map(|&idx| self.composites[idx].subgraph.id.clone())
+                    .collect(),
+            );
+        }
+    }
+
+    /// Resolves an endpoint, creating `[*]` markers per composite scope and plain states on
+    /// first use.
+    fn endpoint(&mut self, token: &str, is_source: bool) -> String {
+        if token == "[*]" {
+            let (suffix, shape, size) = if is_source {
+                ("start", NodeShape::Junction, STATE_START_SIZE)
+            } else {
+                ("end", NodeShape::DoubleCircle, STATE_END_SIZE)
+            };
+            let id = format!("{}{suffix}", self.scope_prefix());
+            self.add_node(&id, String::new(), shape, Some(size));
+            return id;
+        }
+        if !self.composite_ids.contains_key(token) {
+            self.add_node(token, token.to_string(), NodeShape::Rectangle, None);
+        }
+        token.to_string()
+    }
+
+    fn set_label(&mut self, id: &str, label: String) {
+        if let Some(&idx) = self.composite_ids.get(id) {
+            self.composites[idx].subgraph.label = label;
+            return;
+        }
+        self.add_node(id, label.clone(), NodeShape::Rectangle, None);
+        if let Some(node) = self.nodes.get_mut(id) {
+            let (width, height) = compute_node_dimensions(node.shape, &label);
+            node.label = label;
+            node.width = width;
+            node.height = height;
+        }
+    }
+
+    fn open_composite(&mut self, id: &str, label: String) -> Result<()> {
+        // Transitions may mention the state before its block; drop that placeholder node.
+        if self.nodes.remove(id).is_some() {
+            self.order.retain(|existing| existing != id);
+            self.node_membership.remove(id);
+            for composite in &mut self.composites {
+                composite.subgraph.nodes.retain(|existing| existing != id);
+            }
+        }
+        if self.composite_ids.contains_key(id) {
+            bail!("composite state '{id}' is declared more than once");
+        }
+        let idx = self.composites.len();
+        self.composites.push(Composite {
+            subgraph: Subgraph {
+                id: id.to_string(),
+                label,
+                nodes: Vec::new(),
+                children: Vec::new(),
+                order: idx,
+            },
+            parent: self.stack.last().copied(),
+        });
+        self.composite_ids.insert(id.to_string(), idx);
+        self.stack.push(idx);
+        Ok(())
+    }
+
+    /// Transitions into or out of a composite state attach to its inner `[*]` marker, falling back
+    /// to its first member.
+    fn redirect_composite_endpoint(&self, id: &str, is_source: bool) -> Option<String> {
+        let &idx = self.composite_ids.get(id)?;
+        let marker = format!("{id}__{}", if is_source { "end" } else { "start" });
+        if self.nodes.contains_key(&marker) {
+            return Some(marker);
+        }
+        let mut current = idx;
+        loop {
+            let composite = &self.composites[current];
+            if let Some(first) = composite.subgraph.nodes.first() {
+                return Some(first.clone());
+            }
+            current = self
+                .composites
+                .iter()
+                .position(|child| child.parent == Some(current))?;
+        }
+    }
+
+    fn into_subgraphs(self) -> Vec<Subgraph> {
+        let mut composites: Vec<Option<Composite>> =
+            self.composites.into_iter().map(Some).collect();
+        // Children always come after their parent, so attach from the back.
+        for idx in (0..composites.len()).rev() {
+            let Some(parent) = composites[idx].as_ref().and_then(|c| c.parent) else {
+                continue;
+            };
+            let child = composites[idx].take().expect("child visited once");
+            if let Some(parent) = composites[parent].as_mut() {
+                parent.subgraph.children.insert(0, child.subgraph);
+            }
+        }
+        composites
+            .into_iter()
+            .flatten()
+            .map(|composite| composite.subgraph)
+            .filter(|subgraph| !subgraph.nodes.is_empty() || !subgraph.children.is_empty())
+            .collect()
+    }
+}
+
+pub(crate) fn parse_state_diagram(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut builder = StateBuilder {
+        nodes: HashMap::new(),
+        order: Vec::new(),
+        edges: Vec::new(),
+        composites: Vec::new(),
+        composite_ids: HashMap::new(),
+        stack: Vec::new(),
+        node_membership: HashMap::new(),
+    };
+    let mut direction = Direction::TopDown;
+    let mut in_note = false;
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if in_note {
+            in_note = lower != "end note";
+            continue;
+        }
+        if line == "--" || lower.starts_with("classdef ") || lower.starts_with("class ") {
+            continue;
+        }
+        if lower.starts_with("note ") {
+            in_note = !line.contains(':');
+            continue;
+        }
+        if let Some(rest) = lower.strip_prefix("direction ") {
+            if builder.stack.is_empty() {
+                direction = parse_graph_header(&format!("graph {}", rest.trim()))?;
+            }
+            continue;
+        }
+        if line == "}" {
+            if builder.stack.pop().is_none() {
+                bail!("'}}' without a matching composite state");
+            }
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("state ") {
+            parse_state_declaration(&mut builder, rest.trim())?;
+            continue;
+        }
+        if let Some(captures) = transition_regex().captures(line) {
+            let from = builder.endpoint(&captures[1], true);
+            let to = builder.endpoint(&captures[2], false);
+            push_edge(
+                &mut builder.edges,
+                Edge {
+                    from,
+                    to,
+                    label: captures
+                        .get(3)
+                        .map(|label| decode_label(label.as_str().trim()))
+                        .filter(|label| !label.is_empty()),
+                    kind: EdgeKind::Solid,
+                    arrow: EdgeArrowDirection::Forward,
+                    head: EdgeHead::Arrow,
+                    occurrence: 0,
+                },
+            );
+            continue;
+        }
+        if let Some((id, description)) = line.split_once(':') {
+            let id = id.trim();
+            if !id.is_empty() && id.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+                builder.set_label(id, decode_label(description.trim()));
+                continue;
+            }
+        }
+        if line.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+            builder.endpoint(line, false);
+            continue;
+        }
+        bail!("unrecognized state diagram statement '{line}'");
+    }
+
+    if let Some(&idx) = builder.stack.last() {
+        bail!(
+            "composite state '{}' is missing its closing '}}'",
+            builder.composites[idx].subgraph.id
+        );
+    }
+
+    let edges = std::mem::take(&mut builder.edges)
+        .into_iter()
+        .filter_map(|mut edge| {
+            if let Some(from) = builder.redirect_composite_endpoint(&edge.from, true) {
+                edge.from = from;
+            }
+            if let Some(to) = builder.redirect_composite_endpoint(&edge.to, false) {
+                edge.to = to;
+            }
+            (builder.nodes.contains_key(&edge.from) && builder.nodes.contains_key(&edge.to))
+                .then_some(edge)
+        })
+        .collect();
+
+    if builder.nodes.is_empty() {
+        bail!("state diagram does not declare any states");
+    }
+
+    let nodes = std::mem::take(&mut builder.nodes);
+    let order = std::mem::take(&mut builder.order);
+    let node_membership = std::mem::take(&mut builder.node_membership);
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::State(StateDiagramData {
+            original_source: original_source.to_string(),
+        }),
+        direction,
+        nodes,
+        order,
+        edges,
+        subgraphs: builder.into_subgraphs(),
+        node_membership,
+    })
+}
+
+/// Handles `state "Label" as Id`, `state Id <<choice>>` and `state Id {`.
+fn parse_state_declaration(builder: &mut StateBuilder, rest: &str) -> Result<()> {
+    let (rest, opens_block) = match rest.strip_suffix('{') {
+        Some(rest) => (rest.trim(), true),
+        None => (rest, false),
+    };
+
+    let (id, label) = if let Some(quoted) = rest.strip_prefix('"') {
+        let Some((label, tail)) = quoted.split_once('"') else {
+            bail!("state label is missing its closing quote");
+        };
+        let Some(id) = tail.trim().strip_prefix("as ") else {
+            bail!("expected 'as <id>' after quoted state label");
+        };
+        (id.trim().to_string(), Some(decode_label(label)))
+    } else {
+        (rest.to_string(), None)
+    };
+
+    let (id, annotation) = match id.split_once("<<") {
+        Some((id, annotation)) => (
+            id.trim().to_string(),
+            Some(
+                annotation
+                    .trim_end_matches(">>")
+                    .trim()
+                    .to_ascii_lowercase(),
+            ),
+        ),
+        None => (id, None),
+    };
+    if id.is_empty() || !id.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        bail!("invalid state id '{id}'");
+    }
+
+    if opens_block {
+        return builder.open_composite(&id, label.unwrap_or_else(|| id.clone()));
+    }
+
+    let shape = match annotation.as_deref() {
+        None => NodeShape::Rectangle,
+        Some("choice") => NodeShape::Diamond,
+        Some("fork" | "join") => NodeShape::Junction,
+        Some(other) => bail!("unsupported state annotation '<<{other}>>'"),
+    };
+    let label = label.unwrap_or_else(|| id.clone());
+    if shape == NodeShape::Rectangle {
+        builder.set_label(&id, label);
+    } else {
+        let size = if shape == NodeShape::Junction {
+            STATE_START_SIZE
+        } else {
+            STATE_CHOICE_SIZE
+        };
+        builder.add_node(&id, String::new(), shape, Some(size));
+    }
+    Ok(())
+}
@@ -0,0 +1,774 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::{RwLock, watch};
+
+const FILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Backing storage for the diagram source served by `oxdraw serve`.
+///
+/// The stored text is the full source, including the trailing layout override block.
+#[async_trait]
+pub trait DiagramStore: Send + Sync {
+    /// Human readable location of the diagram, reported to the editor as its source path.
+    fn location(&self) -> String;
+
+    async fn read(&self) -> Result<String>;
+
+    async fn write(&self, contents: &str) -> Result<()>;
+
+    /// Receiver whose value is bumped every time the stored source changes.
+    fn watch(&self) -> watch::Receiver<u64>;
+}
+
+fn bump(revision: &watch::Sender<u64>) {
+    revision.send_modify(|value| *value = value.wrapping_add(1));
+}
+
+/// Diagram stored in a file on disk; used by the single-file editor.
+pub struct FileStore {
+    path: PathBuf,
+    revision: Arc<watch::Sender<u64>>,
+    poller: std::sync::Once,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            revision: Arc::new(watch::Sender::new(0)),
+            poller: std::sync::Once::new(),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    // Edits made outside the server (e.g. in a text editor) are picked up by polling the mtime.
+    fn spawn_poller(&self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let path = self.path.clone();
+        let revision: Weak<watch::Sender<u64>> = Arc::downgrade(&self.revision);
+        let mut last_seen = self.modified();
+        handle.spawn(async move {
+            loop {
+                tokio::time::sleep(FILE_POLL_INTERVAL).await;
+                let Some(revision) = revision.upgrade() else {
+                    break;
+                };
+                let modified = tokio::fs::metadata(&path)
+                    .await
+                    .and_then(|m| m.modified())
+                    .ok();
+                if modified != last_seen {
+                    last_seen = modified;
+                    bump(&revision);
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl DiagramStore for FileStore {
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn read(&self) -> Result<String> {
+        tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("failed to read '{}'", self.path.display()))
+    }
+
+    async fn write(&self, contents: &str) -> Result<()> {
+        tokio::fs::write(&self.path, contents.as_bytes())
+            .await
+            .with_context(|| format!("failed to write '{}'", self.path.display()))?;
+        bump(&self.revision);
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.poller.call_once(|| self.spawn_poller());
+        self.revision.subscribe()
+    }
+}
+
+/// Diagram held only in memory; used by the stateless mode where nothing is written to disk.
+pub struct MemoryStore {
+    name: String,
+    contents: RwLock<String>,
+    revision: watch::Sender<u64>,
+}
+
+impl MemoryStore {
+    pub fn new(name: impl Into<String>, contents: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            contents: RwLock::new(contents.into()),
+            revision: watch::Sender::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl DiagramStore for MemoryStore {
+    fn location(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn read(&self) -> Result<String> {
+        Ok(self.contents.read().await.clone())
+    }
+
+    async fn write(&self, contents: &str) -> Result<()> {
+        *self.contents.write().await = contents.to_string();
+        bump(&self.revision);
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+}
+
+/// Diagram stored as a row in a SQLite database; used by the hosted multi-file mode.
+///
+/// Several stores may share one database file, each addressing its own diagram by name.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    name: String,
+    connection: Arc<std::sync::Mutex<rusqlite::Connection>>,
+    revision: watch::Sender<u64>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open(path: impl AsRef<std::path::Path>, name: impl Into<String>) -> Result<Self> {
+        let path = path.as_ref();
+        let connection = rusqlite::Connection::open(path)
+            .with_context(|| format!("failed to open database '{}'", path.display()))?;
+        Self::with_connection(Arc::new(std::sync::Mutex::new(connection)), name)
+    }
+
+    pub fn with_connection(
+        connection: Arc<std::sync::Mutex<rusqlite::Connection>>,
+        name: impl Into<String>,
+    ) -> Result<Self> {
+        connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("database connection poisoned"))?
+            .execute(
+                "CREATE TABLE IF NOT EXISTS diagrams (name TEXT PRIMARY KEY, source TEXT NOT NULL)",
+                [],
+            )
+            .context("failed to create diagrams table")?;
+        Ok(Self {
+            name: name.into(),
+            connection,
+            revision: watch::Sender::new(0),
+        })
+    }
+
+    fn query<T>(&self, f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>) -> Result<T> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("database connection poisoned"))?;
+        f(&connection).with_context(|| format!("database error for diagram '{}'", self.name))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl DiagramStore for SqliteStore {
+    fn location(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn read(&self) -> Result<String> {
+        use rusqlite::OptionalExtension;
+
+        self.query(|conn| {
+            conn.query_row(
+                "SELECT source FROM diagrams WHERE name = ?1",
+                [&self.name],
+                |row| row.get(0),
+            )
+            .optional()
+        })?
+        .with_context(|| format!("diagram '{}' not found in database", self.name))
+    }
+
+    async fn write(&self, contents: &str) -> Result<()> {
+        self.query(|conn| {
+            conn.execute(
+                "INSERT INTO diagrams (name, source) VALUES (?1, ?2) \
+                 ON CONFLICT(name) DO UPDATE SET source = excluded.source",
+                [&self.name, contents],
+            )
+        })?;
+        bump(&self.revision);
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.revision.subscribe()
+    }
+}
+
+/// Selects and opens a [`DiagramStore`] from configuration, e.g. a deployment's JSON/YAML config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum StoreConfig {
+    File {
+        path: PathBuf,
+    },
+    Memory {
+        name: String,
+        #[serde(default)]
+        source: String,
+    },
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        path: PathBuf,
+        name: String,
+    },
+    #[cfg(feature = "s3")]
+    S3(S3Config),
+}
+
+impl StoreConfig {
+    /// Reads a JSON or YAML store configuration, such as the file `oxdraw serve --store` names.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("invalid store configuration in '{}'", path.display()))
+    }
+
+    pub fn open(self) -> Result<Arc<dyn DiagramStore>> {
+        Ok(match self {
+            StoreConfig::File { path } => Arc::new(FileStore::new(path)),
+            StoreConfig::Memory { name, source } => Arc::new(MemoryStore::new(name, source)),
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite { path, name } => Arc::new(SqliteStore::open(path, name)?),
+            #[cfg(feature = "s3")]
+            StoreConfig::S3(config) => Arc::new(S3Store::new(config)?),
+        })
+    }
+}
+
+/// Connection settings for [`S3Store`]. Credentials fall back to the standard
+/// `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` environment variables.
+#[cfg(feature = "s3")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct S3Config {
+    /// Base URL of the S3-compatible endpoint, e.g. `https://s3.us-east-1.amazonaws.com`.
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    /// Owner of the diagram; every object key is prefixed with it so owners cannot see each other's diagrams.
+    pub owner: String,
+    pub name: String,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+}
+
+#[cfg(feature = "s3")]
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+/// Diagram stored in an S3-compatible bucket; used by hosted deployments with several replicas.
+///
+/// Every version is written once under `<owner>/objects/<sha256>`, and `<owner>/diagrams/<name>`
+/// holds the hash of the current version. Replicas always read through to the bucket, and
+/// `watch` polls the head object so edits made by other replicas are noticed.
+#[cfg(feature = "s3")]
+pub struct S3Store {
+    config: S3Config,
+    access_key_id: String,
+    secret_access_key: String,
+    client: reqwest::Client,
+    revision: Arc<watch::Sender<u64>>,
+    poller: std::sync::Once,
+}
+
+#[cfg(feature = "s3")]
+const S3_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[cfg(feature = "s3")]
+impl S3Store {
+    pub fn new(config: S3Config) -> Result<Self> {
+        let access_key_id = match &config.access_key_id {
+            Some(key) => key.clone(),
+            None => std::env::var("AWS_ACCESS_KEY_ID").context("missing S3 access key id")?,
+        };
+        let secret_access_key = match &config.secret_access_key {
+            Some(key) => key.clone(),
+            None => {
+                std::env::var("AWS_SECRET_ACCESS_KEY").context("missing S3 secret access key")?
+            }
+        };
+        Ok(Self {
+            config,
+            access_key_id,
+            secret_access_key,
+            client: reqwest::Client::new(),
+            revision: Arc::new(watch::Sender::new(0)),
+            poller: std::sync::Once::new(),
+        })
+    }
+
+    fn head_key(&self) -> String {
+        format!("{}/diagrams/{}", self.config.owner, self.config.name)
+    }
+
+    fn object_key(&self, hash: &str) -> String {
+        format!("{}/objects/{hash}", self.config.owner)
+    }
+
+    /// Hash of the current version, or `None` if the diagram has never been written.
+    pub async fn head(&self) -> Result<Option<String>> {
+        let head = self.get_object(&self.head_key()).await?;
+        Ok(head.map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string()))
+    }
+
+    /// Reads a specific content-addressed version.
+    pub async fn read_version(&self, hash: &str) -> Result<String> {
+        let bytes = self
+            .get_object(&self.object_key(hash))
+            .await?
+            .with_context(|| format!("version '{hash}' of '{}' not found", self.config.name))?;
+        String::from_utf8(bytes).context("stored diagram is not valid UTF-8")
+    }
+
+    async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let response = self
+            .request(reqwest::Method::GET, key, Vec::new())
+            .send()
+            .await
+            .with_context(|| format!("failed to fetch '{key}' from S3"))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response
+            .error_for_status()
+            .with_context(|| format!("failed to fetch '{key}' from S3"))?;
+        Ok(Some(response.bytes().await?.to_vec()))
+    }
+
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.request(reqwest::Method::PUT, key, body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to upload '{key}' to S3"))?;
+        Ok(())
+    }
+
+    fn request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: Vec<u8>,
+    ) -> reqwest::RequestBuilder {
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let path = format!(
+            "/{}/{}",
+            s3_uri_encode(&self.config.bucket),
+            key.split('/')
+                .map(s3_uri_encode)
+                .collect::<Vec<_>>()
+                .join("/")
+        );
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint, |(_, rest)| rest)
+            .to_string();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let headers = sign_v4(&SigningRequest {
+            method: method.as_str(),
+            host: &host,
+            path: &path,
+            body: &body,
+            region: &self.config.region,
+            access_key_id: &self.access_key_id,
+            secret_access_key: &self.secret_access_key,
+            unix_time: now,
+        });
+        let mut builder = self.client.request(method, format!("{endpoint}{path}"));
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        builder.body(body)
+    }
+
+    fn spawn_poller(&self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let store = Self {
+            config: self.config.clone(),
+            access_key_id: self.access_key_id.clone(),
+            secret_access_key: self.secret_access_key.clone(),
+            client: self.client.clone(),
+            revision: Arc::new(watch::Sender::new(0)),
+            poller: std::sync::Once::new(),
+        };
+        let revision: Weak<watch::Sender<u64>> = Arc::downgrade(&self.revision);
+        handle.spawn(async move {
+            let mut last_seen = store.head().await.ok().flatten();
+            loop {
+                tokio::time::sleep(S3_POLL_INTERVAL).await;
+                let Some(revision) = revision.upgrade() else {
+                    break;
+                };
+                let Ok(head) = store.head().await else {
+                    continue;
+                };
+                if head != last_seen {
+                    last_seen = head;
+                    bump(&revision);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl DiagramStore for S3Store {
+    fn location(&self) -> String {
+        format!("s3://{}/{}", self.config.bucket, self.head_key())
+    }
+
+    async fn read(&self) -> Result<String> {
+        let hash = self
+            .head()
+            .await?
+            .with_context(|| format!("diagram '{}' not found in bucket", self.config.name))?;
+        self.read_version(&hash).await
+    }
+
+    async fn write(&self, contents: &str) -> Result<()> {
+        let hash = hex_digest(contents.as_bytes());
+        // Objects are immutable, so the version only needs uploading once.
+        if self.get_object(&self.object_key(&hash)).await?.is_none() {
+            self.put_object(&self.object_key(&hash), contents.as_bytes().to_vec())
+                .await?;
+        }
+        self.put_object(&self.head_key(), hash.into_bytes()).await?;
+        bump(&self.revision);
+        Ok(())
+    }
+
+    fn watch(&self) -> watch::Receiver<u64> {
+        self.poller.call_once(|| self.spawn_poller());
+        self.revision.subscribe()
+    }
+}
+
+#[cfg(feature = "s3")]
+struct SigningRequest<'a> {
+    method: &'a str,
+    host: &'a str,
+    path: &'a str,
+    body: &'a [u8],
+    region: &'a str,
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    unix_time: u64,
+}
+
+/// AWS Signature Version 4 headers for a request without query parameters.
+#[cfg(feature = "s3")]
+fn sign_v4(request: &SigningRequest) -> Vec<(&'static str, String)> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    let days = (request.unix_time / 86_400) as i64;
+    let seconds = request.unix_time % 86_400;
+    let (year, month, day) = crate::diagram::civil_from_days(days);
+    let date = format!("{year:04}{month:02}{day:02}");
+    let timestamp = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        seconds / 3_600,
+        (seconds / 60) % 60,
+        seconds % 60
+    );
+    let payload_hash = hex_digest(request.body);
+
+    let canonical_request = format!(
+        "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{timestamp}\n\nhost;x-amz-content-sha256;x-amz-date\n{payload_hash}",
+        request.method, request.path, request.host
+    );
+    let scope = format!("{date}/{}/s3/aws4_request", request.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+        hex_digest(canonical_request.as_bytes())
+    );
+
+    let key = hmac(
+        format!("AWS4{}", request.secret_access_key).as_bytes(),
+        &date,
+    );
+    let key = hmac(&key, request.region);
+    let key = hmac(&key, "s3");
+    let key = hmac(&key, "aws4_request");
+    let signature = to_hex(&hmac(&key, &string_to_sign));
+
+    vec![
+        (
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+                request.access_key_id
+            ),
+        ),
+        ("x-amz-content-sha256", payload_hash),
+        ("x-amz-date", timestamp),
+    ]
+}
+
+#[cfg(feature = "s3")]
+fn hex_digest(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    to_hex(&Sha256::digest(bytes))
+}
+
+#[cfg(feature = "s3")]
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(feature = "s3")]
+fn s3_uri_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_store_round_trips_and_notifies() {
+        let store = MemoryStore::new("scratch.mmd", "graph TD\n  A --> B\n");
+        let mut changes = store.watch();
+        assert_eq!(store.read().await.unwrap(), "graph TD\n  A --> B\n");
+
+        store.write("graph LR\n  A --> C\n").await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        changes.mark_unchanged();
+        assert_eq!(store.read().await.unwrap(), "graph LR\n  A --> C\n");
+        assert_eq!(store.location(), "scratch.mmd");
+    }
+
+    #[tokio::test]
+    async fn file_store_writes_through_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("diagram.mmd");
+        std::fs::write(&path, "graph TD\n  A --> B\n").unwrap();
+
+        let store = FileStore::new(&path);
+        let changes = store.watch();
+        store.write("graph TD\n  B --> C\n").await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "graph TD\n  B --> C\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_store_starts_empty_without_a_source() {
+        let store = StoreConfig::Memory {
+            name: "scratch.mmd".to_string(),
+            source: String::new(),
+        }
+        .open()
+        .unwrap();
+        assert_eq!(store.read().await.unwrap(), "");
+        store.write("graph TD\n  A\n").await.unwrap();
+        assert_eq!(store.read().await.unwrap(), "graph TD\n  A\n");
+    }
+
+    #[tokio::test]
+    async fn file_store_reports_a_missing_file_until_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.mmd");
+        let store = FileStore::new(&path);
+
+        let err = store.read().await.unwrap_err();
+        assert!(format!("{err:#}").contains("failed to read"));
+        store.write("graph TD\n  A --> B\n").await.unwrap();
+        assert_eq!(store.read().await.unwrap(), "graph TD\n  A --> B\n");
+
+        let unwritable = FileStore::new(dir.path().join("no-such-dir/flow.mmd"));
+        assert!(unwritable.write("graph TD\n  A\n").await.is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_persists_across_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("diagrams.db");
+
+        let store = SqliteStore::open(&path, "flow.mmd").unwrap();
+        let err = store.read().await.unwrap_err();
+        assert!(format!("{err:#}").contains("diagram 'flow.mmd' not found"));
+        store.write("graph TD\n  A --> B\n").await.unwrap();
+        drop(store);
+
+        let reopened = SqliteStore::open(&path, "flow.mmd").unwrap();
+        assert_eq!(reopened.read().await.unwrap(), "graph TD\n  A --> B\n");
+        assert!(
+            SqliteStore::open(&path, "other.mmd")
+                .unwrap()
+                .read()
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_keeps_diagrams_apart() {
+        let connection = Arc::new(std::sync::Mutex::new(
+            rusqlite::Connection::open_in_memory().unwrap(),
+        ));
+        let first = SqliteStore::with_connection(connection.clone(), "first.mmd").unwrap();
+        let second = SqliteStore::with_connection(connection, "second.mmd").unwrap();
+
+        assert!(first.read().await.is_err());
+        first.write("graph TD\n  A --> B\n").await.unwrap();
+        second.write("graph LR\n  X --> Y\n").await.unwrap();
+        first.write("graph TD\n  A --> C\n").await.unwrap();
+
+        assert_eq!(first.read().await.unwrap(), "graph TD\n  A --> C\n");
+        assert_eq!(second.read().await.unwrap(), "graph LR\n  X --> Y\n");
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn signs_requests_with_sigv4() {
+        let headers = sign_v4(&SigningRequest {
+            method: "PUT",
+            host: "localhost:9000",
+            path: "/diagrams/team-a/objects/abc",
+            body: b"graph TD\n  A --> B\n",
+            region: "us-east-1",
+            access_key_id: "AKIDEXAMPLE",
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            unix_time: 1_704_164_645,
+        });
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.as_str())
+                .unwrap()
+        };
+        assert_eq!(header("x-amz-date"), "20240102T030405Z");
+        assert_eq!(
+            header("authorization"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240102/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+             Signature=3fbc946c0e37b8a87f54cadcdc60a763379083c5d2e108fab98008bc4f94e149"
+        );
+    }
+
+    #[cfg(feature = "s3")]
+    #[tokio::test]
+    async fn s3_store_writes_content_addressed_versions() {
+        use axum::body::Bytes;
+        use axum::extract::{Path, State};
+        use axum::http::StatusCode;
+        use std::collections::HashMap;
+
+        type Bucket = Arc<std::sync::Mutex<HashMap<String, Bytes>>>;
+        let bucket: Bucket = Arc::default();
+        let app = axum::Router::new()
+            .route(
+                "/*key",
+                axum::routing::get(
+                    |State(bucket): State<Bucket>, Path(key): Path<String>| async move {
+                        match bucket.lock().unwrap().get(&key) {
+                            Some(body) => Ok(body.clone()),
+                            None => Err(StatusCode::NOT_FOUND),
+                        }
+                    },
+                )
+                .put(
+                    |State(bucket): State<Bucket>, Path(key): Path<String>, body: Bytes| async move {
+                        bucket.lock().unwrap().insert(key, body);
+                        StatusCode::OK
+                    },
+                ),
+            )
+            .with_state(bucket.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let store = S3Store::new(S3Config {
+            endpoint: format!("http://{addr}"),
+            bucket: "diagrams".to_string(),
+            region: default_s3_region(),
+            owner: "team-a".to_string(),
+            name: "flow.mmd".to_string(),
+            access_key_id: Some("key".to_string()),
+            secret_access_key: Some("secret".to_string()),
+        })
+        .unwrap();
+
+        assert!(store.read().await.is_err());
+        store.write("graph TD\n  A --> B\n").await.unwrap();
+        let first = store.head().await.unwrap().unwrap();
+        store.write("graph TD\n  A --> C\n").await.unwrap();
+
+        assert_eq!(store.read().await.unwrap(), "graph TD\n  A --> C\n");
+        assert_eq!(
+            store.read_version(&first).await.unwrap(),
+            "graph TD\n  A --> B\n"
+        );
+        assert!(
+            bucket
+                .lock()
+                .unwrap()
+                .contains_key(&format!("diagrams/team-a/objects/{first}"))
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Context, Result, bail};
+use resvg::usvg::fontdb::{Database, Family, Query};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Font stack written into exported SVGs when the theme doesn't name one.
+pub const DEFAULT_FONT_FAMILY: &str = "Inter, system-ui, sans-serif";
+
+/// Installed families tried, in order, when the generic `sans-serif` one is missing.
+const SANS_SERIF_FALLBACKS: [&str; 5] = [
+    "Helvetica",
+    "Liberation Sans",
+    "DejaVu Sans",
+    "Noto Sans",
+    "Segoe UI",
+];
+
+/// System fonts shared by label measurement and PNG export, so both agree on the face that
+/// draws the default font stack.
+pub fn font_database() -> Arc<Database> {
+    static DATABASE: OnceLock<Arc<Database>> = OnceLock::new();
+    DATABASE
+        .get_or_init(|| {
+            let mut database = Database::new();
+            database.load_system_fonts();
+            let configured = database.family_name(&Family::SansSerif).to_string();
+            if !has_family(&database, &configured)
+                && let Some(fallback) = SANS_SERIF_FALLBACKS
+                    .iter()
+                    .find(|family| has_family(&database, family))
+            {
+                database.set_sans_serif_family(*fallback);
+            }
+            Arc::new(database)
+        })
+        .clone()
+}
+
+/// [`font_database`] plus the faces in `files`, for fonts that aren't installed.
+pub fn font_database_with(files: &[PathBuf]) -> Result<Arc<Database>> {
+    if files.is_empty() {
+        return Ok(font_database());
+    }
+    let mut database = (*font_database()).clone();
+    for path in files {
+        load_font_file(&mut database, path)?;
+    }
+    Ok(Arc::new(database))
+}
+
+/// Family name of the first face in the font file at `path`.
+pub fn font_file_family(path: &Path) -> Result<String> {
+    let mut database = Database::new();
+    load_font_file(&mut database, path)?;
+    database
+        .faces()
+        .find_map(|face| face.families.first().map(|(family, _)| family.clone()))
+        .with_context(|| format!("font file '{}' has no family name", path.display()))
+}
+
+fn load_font_file(database: &mut Database, path: &Path) -> Result<()> {
+    let before = database.len();
+    database
+        .load_font_file(path)
+        .with_context(|| format!("failed to read font file '{}'", path.display()))?;
+    if database.len() == before {
+        bail!("'{}' is not a TrueType or OpenType font", path.display());
+    }
+    Ok(())
+}
+
+/// Whether `database` can draw the first family of the CSS font stack `stack`, rather than
+/// falling back to a later one. Generic families always resolve.
+pub fn has_font_family(database: &Database, stack: &str) -> bool {
+    let Some(first) = stack.split(',').next() else {
+        return true;
+    };
+    let name = first.trim().trim_matches(['"', '\'']);
+    matches!(
+        name,
+        "" | "serif" | "sans-serif" | "monospace" | "cursive" | "fantasy" | "system-ui"
+    ) || has_family(database, name)
+}
+
+fn has_family(database: &Database, name: &str) -> bool {
+    database
+        .faces()
+        .any(|face| face.families.iter().any(|(family, _)| family == name))
+}
+
+/// Regular face resolved from [`DEFAULT_FONT_FAMILY`].
+struct MeasureFont {
+    data: Vec<u8>,
+    index: u32,
+}
+
+fn measure_font() -> Option<&'static MeasureFont> {
+    static FONT: OnceLock<Option<MeasureFont>> = OnceLock::new();
+    FONT.get_or_init(|| {
+        let database = font_database();
+        let id = database.query(&Query {
+            families: &[Family::Name("Inter"), Family::SansSerif],
+            ..Query::default()
+        })?;
+        database.with_face_data(id, |data, index| MeasureFont {
+            data: data.to_vec(),
+            index,
+        })
+    })
+    .as_ref()
+}
+
+/// Terminal-style column count of `text`: CJK ideographs, fullwidth forms and emoji take two
+/// columns, combining marks none. Emoji joined with a zero-width joiner count once.
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Advance width of `text` set at `font_size` in the default font stack. Without a usable
+/// system font (wasm builds, bare containers) every column of [`display_width`] counts as
+/// `fallback_char_width`, the estimate labels were sized with before.
+pub fn text_width(text: &str, font_size: f32, fallback_char_width: f32) -> f32 {
+    let fallback = || display_width(text) as f32 * fallback_char_width;
+    let Some(font) = measure_font() else {
+        return fallback();
+    };
+    let Ok(face) = ttf_parser::Face::parse(&font.data, font.index) else {
+        return fallback();
+    };
+    let scale = font_size / f32::from(face.units_per_em());
+    let missing = face
+        .glyph_hor_advance(ttf_parser::GlyphId(0))
+        .map_or(fallback_char_width, |advance| f32::from(advance) * scale);
+    let mut joined = false;
+    text.chars()
+        .map(|ch| {
+            let after_joiner = std::mem::replace(&mut joined, ch == ZERO_WIDTH_JOINER);
+            if after_joiner {
+                return 0.0;
+            }
+            let columns = UnicodeWidthChar::width(ch).unwrap_or(0);
+            match face
+                .glyph_index(ch)
+                .and_then(|glyph| face.glyph_hor_advance(glyph))
+            {
+                Some(advance) => f32::from(advance) * scale,
+                // Another font draws it: wide glyphs take a full em, marks take no room.
+                None if columns >= 2 => missing.max(font_size),
+                None if columns == 0 => 0.0,
+                None => missing,
+            }
+        })
+        .sum()
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Family name the `@font-face` from [`embedded_font_face`] registers.
+pub const EMBEDDED_FONT_FAMILY: &str = "oxdraw-embedded";
+
+/// `@font-face` rule carrying the measuring face as a base64 TrueType font, cut down to the
+/// glyphs `chars` need. `None` when there is no system font to embed or it can't be subset.
+pub fn embedded_font_face(chars: impl IntoIterator<Item = char>) -> Option<String> {
+    use base64::Engine;
+
+    let font = measure_font()?;
+    let face = ttf_parser::Face::parse(&font.data, font.index).ok()?;
+    let mut glyphs: Vec<u16> = chars
+        .into_iter()
+        .filter_map(|ch| face.glyph_index(ch))
+        .map(|glyph| glyph.0)
+        .collect();
+    glyphs.push(0);
+    glyphs.sort_unstable();
+    glyphs.dedup();
+    let subset =
+        subsetter::subset(&font.data, font.index, subsetter::Profile::pdf(&glyphs)).ok()?;
+    Some(format!(
+        "@font-face {{ font-family: \"{EMBEDDED_FONT_FAMILY}\"; src: url(data:font/ttf;base64,{}) format(\"truetype\"); }}",
+        base64::engine::general_purpose::STANDARD.encode(subset)
+    ))
+}
@@ -0,0 +1,248 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::{DEFAULT_FONT_FAMILY, NodeShape, Palette, Rgb};
+
+/// Names accepted by [`Theme::built_in`].
+pub const BUILT_IN_THEMES: [&str; 4] = ["default", "dark", "neutral", "forest"];
+
+/// Colors and fonts for flowchart-style diagrams. Custom themes are JSON or TOML files with
+/// the same camelCase keys; `extends` names the built-in theme that fills in the rest.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Theme {
+    pub name: String,
+    /// Page color used when no `--background-color` is given.
+    pub background: String,
+    /// Fills by shape name (`rectangle`, `diamond`, ...). Shapes left out use `nodeFill`,
+    /// then the palette.
+    #[serde(default)]
+    pub node_fills: BTreeMap<String, String>,
+    #[serde(default)]
+    pub node_fill: Option<String>,
+    pub node_stroke: String,
+    pub node_text: String,
+    /// Edge strokes and junction dots.
+    pub edge: String,
+    /// Edge label boxes; the page background when unset.
+    #[serde(default)]
+    pub edge_label_background: Option<String>,
+    pub subgraph_fill: String,
+    /// Tints handed out to top-level subgraphs in order; nested subgraphs get paler shades of
+    /// their ancestor's. Empty gives every subgraph `subgraph_fill`.
+    #[serde(default)]
+    pub subgraph_palette: Vec<String>,
+    pub subgraph_stroke: String,
+    pub subgraph_text: String,
+    /// Diagram title.
+    pub text: String,
+    pub font_family: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: "default".to_string(),
+            background: "white".to_string(),
+            node_fills: BTreeMap::new(),
+            node_fill: None,
+            node_stroke: "#2d3748".to_string(),
+            node_text: "#1a202c".to_string(),
+            edge: "#2d3748".to_string(),
+            edge_label_background: None,
+            subgraph_fill: "#edf2f7".to_string(),
+            subgraph_palette: colors(&[
+                "#bee3f8", "#c6f6d5", "#feebc8", "#e9d8fd", "#fed7e2", "#b2f5ea",
+            ]),
+            subgraph_stroke: "#a0aec0".to_string(),
+            subgraph_text: "#2d3748".to_string(),
+            text: "#1a202c".to_string(),
+            font_family: DEFAULT_FONT_FAMILY.to_string(),
+        }
+    }
+}
+
+fn colors(values: &[&str]) -> Vec<String> {
+    values.iter().map(|color| color.to_string()).collect()
+}
+
+fn fills(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(shape, fill)| (shape.to_string(), fill.to_string()))
+        .collect()
+}
+
+impl Theme {
+    /// One of [`BUILT_IN_THEMES`]; Mermaid's `base` is the default theme.
+    pub fn built_in(name: &str) -> Option<Theme> {
+        let theme = match name.trim().to_ascii_lowercase().as_str() {
+            "default" | "base" => Theme::default(),
+            "dark" => Theme {
+                name: "dark".to_string(),
+                background: "#0f172a".to_string(),
+                node_fills: fills(&[
+                    ("stadium", "#164e63"),
+                    ("circle", "#4c1d95"),
+                    ("double-circle", "#1e3a8a"),
+                    ("diamond", "#831843"),
+                    ("subroutine", "#7c2d12"),
+                    ("cylinder", "#14532d"),
+                    ("hexagon", "#7f1d1d"),
+                ]),
+                node_fill: Some("#334155".to_string()),
+                node_stroke: "#94a3b8".to_string(),
+                node_text: "#f1f5f9".to_string(),
+                edge: "#cbd5e1".to_string(),
+                edge_label_background: Some("#1e293b".to_string()),
+                subgraph_fill: "#1e293b".to_string(),
+                subgraph_palette: colors(&[
+                    "#1e3a5f", "#1c3d2e", "#3f2e1a", "#312a52", "#4a1d32", "#134e4a",
+                ]),
+                subgraph_stroke: "#475569".to_string(),
+                subgraph_text: "#e2e8f0".to_string(),
+                text: "#f1f5f9".to_string(),
+                ..Theme::default()
+            },
+            "neutral" => Theme {
+                name: "neutral".to_string(),
+                node_fills: fills(&[("diamond", "#e4e4e7"), ("circle", "#e4e4e7")]),
+                node_fill: Some("#f4f4f5".to_string()),
+                node_stroke: "#52525b".to_string(),
+                node_text: "#18181b".to_string(),
+                edge: "#52525b".to_string(),
+                subgraph_fill: "#fafafa".to_string(),
+                subgraph_palette: Vec::new(),
+                subgraph_stroke: "#a1a1aa".to_string(),
+                subgraph_text: "#3f3f46".to_string(),
+                text: "#18181b".to_string(),
+                ..Theme::default()
+            },
+            "forest" => Theme {
+                name: "forest".to_string(),
+                node_fills: fills(&[
+                    ("stadium", "#d9f0c2"),
+                    ("circle", "#b7dfa0"),
+                    ("diamond", "#f4f7c5"),
+                    ("cylinder", "#a7d7a0"),
+                ]),
+                node_fill: Some("#cde498".to_string()),
+                node_stroke: "#13540c".to_string(),
+                node_text: "#1a2e05".to_string(),
+                edge: "#13540c".to_string(),
+                subgraph_fill: "#f0f8e8".to_string(),
+                subgraph_palette: colors(&["#e3f2d3", "#f4f7c5", "#d4ecd9", "#f3e8c8"]),
+                subgraph_stroke: "#6eaa49".to_string(),
+                subgraph_text: "#13540c".to_string(),
+                text: "#13540c".to_string(),
+                ..Theme::default()
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Reads a custom theme from a `.toml` or `.json` file.
+    pub fn load(path: &Path) -> Result<Theme> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read theme file '{}'", path.display()))?;
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        let value: serde_json::Value = if is_toml {
+            toml::from_str(&text)
+                .with_context(|| format!("invalid theme file '{}'", path.display()))?
+        } else {
+            serde_json::from_str(&text)
+                .with_context(|| format!("invalid theme file '{}'", path.display()))?
+        };
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "custom".to_string());
+        Theme::from_value(value, &name)
+            .with_context(|| format!("invalid theme file '{}'", path.display()))
+    }
+
+    /// Lays the keys of `value` over the theme its `extends` key names (the default theme
+    /// when missing); `nodeFills` entries are merged one by one.
+    fn from_value(value: serde_json::Value, name: &str) -> Result<Theme> {
+        let serde_json::Value::Object(mut fields) = value else {
+            bail!("expected a table of theme colors");
+        };
+        let base_name = match fields.remove("extends") {
+            Some(serde_json::Value::String(base)) => base,
+            Some(_) => bail!("'extends' must name a built-in theme"),
+            None => "default".to_string(),
+        };
+        let base = Theme::built_in(&base_name).ok_or_else(|| unknown_theme(&base_name))?;
+        let serde_json::Value::Object(mut merged) = serde_json::to_value(&base)? else {
+            unreachable!("themes serialize to objects");
+        };
+        merged.insert("name".to_string(), name.into());
+        // A file that sets one subgraph fill expects every subgraph to use it.
+        if fields.contains_key("subgraphFill") && !fields.contains_key("subgraphPalette") {
+            merged.insert("subgraphPalette".to_string(), serde_json::json!([]));
+        }
+        if let Some(serde_json::Value::Object(fills)) = fields.remove("nodeFills")
+            && let Some(serde_json::Value::Object(base_fills)) = merged.get_mut("nodeFills")
+        {
+            base_fills.extend(fills);
+        }
+        merged.extend(fields);
+        Ok(serde_json::from_value(serde_json::Value::Object(merged))?)
+    }
+
+    /// A built-in theme by name, or else a theme file at that path.
+    pub fn resolve(name_or_path: &str) -> Result<Theme> {
+        if let Some(theme) = Theme::built_in(name_or_path) {
+            return Ok(theme);
+        }
+        let path = Path::new(name_or_path);
+        if path.is_file() {
+            return Theme::load(path);
+        }
+        Err(unknown_theme(name_or_path))
+    }
+
+    /// Fill for `shape`. A non-default palette wins over the theme's fills, and junction dots
+    /// take the edge color.
+    pub fn node_fill(&self, shape: NodeShape, palette: Palette) -> String {
+        if shape == NodeShape::Junction {
+            return self.edge.clone();
+        }
+        if palette != Palette::Default {
+            return palette.node_fill(shape).to_string();
+        }
+        self.node_fills
+            .get(shape.as_str())
+            .or(self.node_fill.as_ref())
+            .cloned()
+            .unwrap_or_else(|| palette.node_fill(shape).to_string())
+    }
+
+    /// Fill for a subgraph `depth` levels below the `index`th top-level subgraph: its palette
+    /// tint, faded a quarter of the way towards the page background per level.
+    pub fn subgraph_fill(&self, index: usize, depth: usize) -> String {
+        if self.subgraph_palette.is_empty() {
+            return self.subgraph_fill.clone();
+        }
+        let tint = &self.subgraph_palette[index % self.subgraph_palette.len()];
+        if depth == 0 {
+            return tint.clone();
+        }
+        match (Rgb::parse(tint), Rgb::parse(&self.background)) {
+            (Some(tint), Some(page)) => tint.mix(page, (depth as f32 * 0.25).min(0.75)).to_hex(),
+            _ => tint.clone(),
+        }
+    }
+}
+
+fn unknown_theme(name: &str) -> anyhow::Error {
+    anyhow!(
+        "unknown theme '{name}'; expected {} or a .toml/.json theme file",
+        BUILT_IN_THEMES.join(", ")
+    )
+}
@@ -0,0 +1,350 @@
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::*;
+
+const TIMELINE_PALETTE: [&str; 8] = [
+    "#6366f1", "#0ea5e9", "#10b981", "#f59e0b", "#ef4444", "#8b5cf6", "#14b8a6", "#ec4899",
+];
+const TIMELINE_MARGIN: f32 = 40.0;
+const TIMELINE_COLUMN_WIDTH: f32 = 160.0;
+const TIMELINE_COLUMN_GAP: f32 = 24.0;
+const TIMELINE_PERIOD_HEIGHT: f32 = 44.0;
+const TIMELINE_SECTION_HEIGHT: f32 = 36.0;
+const TIMELINE_EVENT_GAP: f32 = 12.0;
+const TIMELINE_EVENT_PADDING: f32 = 10.0;
+const TIMELINE_STEM: f32 = 24.0;
+
+#[derive(Debug, Clone)]
+pub struct TimelineDiagram {
+    pub title: Option<String>,
+    pub sections: Vec<String>,
+    pub periods: Vec<TimelinePeriod>,
+    pub original_source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelinePeriod {
+    pub label: String,
+    pub events: Vec<String>,
+    pub section_index: Option<usize>,
+}
+
+/// Splits `line` at the colons that separate a period from its events. A colon must be followed
+/// by whitespace to count, so `10:30 : Standup` keeps `10:30` together.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for (idx, ch) in line.char_indices() {
+        let separates = ch == ':'
+            && line[idx + 1..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace);
+        if separates {
+            fields.push(&line[start..idx]);
+            start = idx + 1;
+        }
+    }
+    fields.push(&line[start..]);
+    fields
+}
+
+/// Parses `title`, `section`, `Period : Event : Event` and continuation `: Event` lines.
+pub(crate) fn parse_timeline(lines: Vec<String>, original_source: &str) -> Result<Diagram> {
+    let mut timeline = TimelineDiagram {
+        title: None,
+        sections: Vec::new(),
+        periods: Vec::new(),
+        original_source: original_source.to_string(),
+    };
+
+    for line in lines {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("acctitle") || lower.starts_with("accdescr") {
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title ") {
+            timeline.title = Some(decode_label(title.trim()));
+            continue;
+        }
+        if let Some(section) = line.strip_prefix("section ") {
+            timeline.sections.push(decode_label(section.trim()));
+            continue;
+        }
+
+        let mut parts = split_fields(line).into_iter();
+        let period = parts.next().unwrap_or_default().trim();
+        let events = parts
+            .map(str::trim)
+            .filter(|event| !event.is_empty())
+            .map(decode_label);
+
+        if period.is_empty() {
+            let Some(last) = timeline.periods.last_mut() else {
+                bail!("event '{line}' appears before any time period");
+            };
+            last.events.extend(events);
+            continue;
+        }
+
+        timeline.periods.push(TimelinePeriod {
+            label: decode_label(period),
+            events: events.collect(),
+            section_index: timeline.sections.len().checked_sub(1),
+        });
+    }
+
+    if timeline.periods.is_empty() {
+        bail!("timeline does not declare any time periods");
+    }
+
+    let mut nodes = HashMap::new();
+    let mut order = Vec::new();
+    for (idx, period) in timeline.periods.iter().enumerate() {
+        let id = format!("period{idx}");
+        nodes.insert(
+            id.clone(),
+            Node {
+                label: period.label.clone(),
+                shape: NodeShape::Rectangle,
+                image: None,
+                width: TIMELINE_COLUMN_WIDTH,
+                height: TIMELINE_PERIOD_HEIGHT,
+            },
+        );
+        order.push(id);
+    }
+
+    Ok(Diagram {
+        config: DiagramConfig::default(),
+        kind: DiagramKind::Timeline(timeline),
+        direction: Direction::LeftRight,
+        nodes,
+        order,
+        edges: Vec::new(),
+        subgraphs: Vec::new(),
+        node_membership: HashMap::new(),
+    })
+}
+
+/// Greedy word wrap to the text width of a timeline column.
+fn wrap_event(text: &str) -> Vec<String> {
+    let max_chars = ((TIMELINE_COLUMN_WIDTH - TIMELINE_EVENT_PADDING * 2.0) / NODE_TEXT_CHAR_WIDTH)
+        .floor() as usize;
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn event_height(lines: &[String]) -> f32 {
+    lines.len() as f32 * NODE_TEXT_LINE_HEIGHT + TIMELINE_EVENT_PADDING * 2.0
+}
+
+impl TimelineDiagram {
+    /// Height of the event stack under (or over) each period, including the stem.
+    fn stack_height(period: &TimelinePeriod) -> f32 {
+        if period.events.is_empty() {
+            return 0.0;
+        }
+        TIMELINE_STEM
+            + period
+                .events
+                .iter()
+                .map(|event| event_height(&wrap_event(event)) + TIMELINE_EVENT_GAP)
+                .sum::<f32>()
+            - TIMELINE_EVENT_GAP
+    }
+
+    pub fn render_svg(&self, background: &str, palette: Palette) -> Result<String> {
+        let colors = palette.categorical().unwrap_or(&TIMELINE_PALETTE);
+        let title_height = if self.title.is_some() { 48.0 } else { 0.0 };
+        let section_height = if self.sections.is_empty() {
+            0.0
+        } else {
+            TIMELINE_SECTION_HEIGHT + 12.0
+        };
+        let column = TIMELINE_COLUMN_WIDTH + TIMELINE_COLUMN_GAP;
+        let width =
+            TIMELINE_MARGIN * 2.0 + column * self.periods.len() as f32 - TIMELINE_COLUMN_GAP;
+
+        // Even periods stack their events above the axis and odd ones below.
+        let above = self
+            .periods
+            .iter()
+            .step_by(2)
+            .map(Self::stack_height)
+            .fold(0.0_f32, f32::max);
+        let below = self
+            .periods
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(Self::stack_height)
+            .fold(0.0_f32, f32::max);
+        let section_top = TIMELINE_MARGIN + title_height;
+        let axis_y = section_top + section_height + above + TIMELINE_PERIOD_HEIGHT / 2.0;
+        let height = axis_y + TIMELINE_PERIOD_HEIGHT / 2.0 + below + TIMELINE_MARGIN;
+
+        let mut svg = String::new();
+        write!(
+            svg,
+            r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{:.0}" height="{:.0}" viewBox="0 0 {:.0} {:.0}" font-family="Inter, system-ui, sans-serif">
+  <rect width="100%" height="100%" fill="{}" />
+"##,
+            width,
+            height,
+            width,
+            height,
+            escape_xml(page_fill(background)),
+        )?;
+
+        if let Some(title) = &self.title {
+            writeln!(
+                svg,
+                "  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"#1a202c\" font-size=\"20\" font-weight=\"700\" text-anchor=\"middle\">{}</text>",
+                width / 2.0,
+                TIMELINE_MARGIN + 20.0,
+                escape_xml(title)
+            )?;
+        }
+
+        let column_x = |idx: usize| TIMELINE_MARGIN + column * idx as f32;
+        let color_for = |idx: usize| {
+            let period = &self.periods[idx];
+            let slot = if self.sections.is_empty() {
+                idx
+            } else {
+                period.section_index.unwrap_or(0)
+            };
+            colors[slot % colors.len()]
+        };
+
+        let mut idx = 0;
+        while idx < self.periods.len() {
+            let section = self.periods[idx].section_index;
+            let run = self.periods[idx..]
+                .iter()
+                .take_while(|period| period.section_index == section)
+                .count();
+            if let Some(section) = section {
+                let color = color_for(idx);
+                let x = column_x(idx);
+                let span = column * run as f32 - TIMELINE_COLUMN_GAP;
+                writeln!(
+                    svg,
+                    "  <rect class=\"timeline-section\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"8\" ry=\"8\" fill=\"{}\" />\n  <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"14\" font-weight=\"600\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                    x,
+                    section_top,
+                    span,
+                    TIMELINE_SECTION_HEIGHT,
+                    color,
+                    x + span / 2.0,
+                    section_top + TIMELINE_SECTION_HEIGHT / 2.0,
+                    readable_text_color(color),
+                    escape_xml(&self.sections[section])
+                )?;
+            }
+            idx += run;
+        }
+
+        writeln!(
+            svg,
+            "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#a0aec0\" stroke-width=\"3\" stroke-linecap=\"round\" />",
+            TIMELINE_MARGIN / 2.0,
+            axis_y,
+            width - TIMELINE_MARGIN / 2.0,
+            axis_y
+        )?;
+
+        for (idx, period) in self.periods.iter().enumerate() {
+            let x = column_x(idx);
+            let center_x = x + TIMELINE_COLUMN_WIDTH / 2.0;
+            let color = color_for(idx);
+            let upward = idx % 2 == 0;
+            writeln!(
+                svg,
+                "  <g class=\"timeline-period\" data-id=\"period{idx}\">"
+            )?;
+
+            if !period.events.is_empty() {
+                let stem_end = if upward {
+                    axis_y - TIMELINE_PERIOD_HEIGHT / 2.0 - TIMELINE_STEM
+                } else {
+                    axis_y + TIMELINE_PERIOD_HEIGHT / 2.0 + TIMELINE_STEM
+                };
+                writeln!(
+                    svg,
+                    "    <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"2\" stroke-dasharray=\"4 3\" />",
+                    center_x, axis_y, center_x, stem_end, color
+                )?;
+
+                let mut cursor = stem_end;
+                for event in &period.events {
+                    let lines = wrap_event(event);
+                    let box_height = event_height(&lines);
+                    let top = if upward { cursor - box_height } else { cursor };
+                    writeln!(
+                        svg,
+                        "    <rect class=\"timeline-event\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"6\" ry=\"6\" fill=\"#ffffff\" stroke=\"{}\" stroke-width=\"1.5\" />",
+                        x, top, TIMELINE_COLUMN_WIDTH, box_height, color
+                    )?;
+                    writeln!(
+                        svg,
+                        "    <text x=\"{:.1}\" fill=\"#2d3748\" font-size=\"13\" text-anchor=\"middle\">",
+                        center_x
+                    )?;
+                    for (line_idx, line) in lines.iter().enumerate() {
+                        writeln!(
+                            svg,
+                            "      <tspan x=\"{:.1}\" y=\"{:.1}\" dominant-baseline=\"middle\">{}</tspan>",
+                            center_x,
+                            top + TIMELINE_EVENT_PADDING
+                                + NODE_TEXT_LINE_HEIGHT * (line_idx as f32 + 0.5),
+                            escape_xml(line)
+                        )?;
+                    }
+                    svg.push_str("    </text>\n");
+                    cursor = if upward {
+                        top - TIMELINE_EVENT_GAP
+                    } else {
+                        top + box_height + TIMELINE_EVENT_GAP
+                    };
+                }
+            }
+
+            writeln!(
+                svg,
+                "    <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"10\" ry=\"10\" fill=\"{}\" />\n    <text x=\"{:.1}\" y=\"{:.1}\" fill=\"{}\" font-size=\"15\" font-weight=\"700\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>",
+                x,
+                axis_y - TIMELINE_PERIOD_HEIGHT / 2.0,
+                TIMELINE_COLUMN_WIDTH,
+                TIMELINE_PERIOD_HEIGHT,
+                color,
+                center_x,
+                axis_y,
+                readable_text_color(color),
+                escape_xml(&period.label)
+            )?;
+            svg.push_str("  </g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
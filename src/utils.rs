@@ -85,3 +85,69 @@ pub fn split_source_and_overrides(
 
     Ok((definition, overrides))
 }
+
+/// Namespace of the `<metadata>` children written by [`embed_source_metadata`].
+pub const SOURCE_METADATA_NAMESPACE: &str = "https://github.com/RohanAdwankar/oxdraw";
+
+/// Stores `definition` and the `overrides` layout JSON in a `<metadata>` block at the end of
+/// `svg`, so the diagram can be recovered with [`extract_source_metadata`] and edited again.
+pub fn embed_source_metadata(
+    svg: &str,
+    definition: &str,
+    overrides: &crate::LayoutOverrides,
+) -> anyhow::Result<String> {
+    let Some(end) = svg.rfind("</svg>") else {
+        anyhow::bail!("rendered SVG has no closing </svg> tag");
+    };
+    let mut metadata = format!(
+        "  <metadata xmlns:oxdraw=\"{SOURCE_METADATA_NAMESPACE}\">\n    <oxdraw:source>{}</oxdraw:source>\n",
+        escape_xml(definition)
+    );
+    if !overrides.is_empty() {
+        let json = serde_json::to_string(overrides)?;
+        metadata.push_str(&format!(
+            "    <oxdraw:layout>{}</oxdraw:layout>\n",
+            escape_xml(&json)
+        ));
+    }
+    metadata.push_str("  </metadata>\n");
+
+    let mut embedded = String::with_capacity(svg.len() + metadata.len());
+    embedded.push_str(&svg[..end]);
+    embedded.push_str(&metadata);
+    embedded.push_str(&svg[end..]);
+    Ok(embedded)
+}
+
+/// Reads back the definition and layout overrides [`embed_source_metadata`] stored in `svg`;
+/// `None` when the SVG carries no oxdraw source.
+pub fn extract_source_metadata(
+    svg: &str,
+) -> anyhow::Result<Option<(String, crate::LayoutOverrides)>> {
+    fn element<'a>(svg: &'a str, name: &str) -> Option<&'a str> {
+        let open = format!("<oxdraw:{name}>");
+        let start = svg.find(&open)? + open.len();
+        let end = start + svg[start..].find(&format!("</oxdraw:{name}>"))?;
+        Some(&svg[start..end])
+    }
+
+    let Some(source) = element(svg, "source") else {
+        return Ok(None);
+    };
+    let overrides = match element(svg, "layout") {
+        Some(json) => serde_json::from_str(&unescape_xml(json))
+            .with_context(|| "failed to parse the layout stored in the SVG metadata")?,
+        None => crate::LayoutOverrides::default(),
+    };
+    Ok(Some((unescape_xml(source), overrides)))
+}
+
+/// Reverses [`escape_xml`].
+pub fn unescape_xml(input: &str) -> String {
+    input
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{UiMessage, status};
+
+/// Header carrying the `sha256=<hex>` HMAC of the request body when a secret is configured.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-Oxdraw-Signature";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub url: String,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Source,
+    Layout,
+    Style,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Source => "source",
+            ChangeKind::Layout => "layout",
+            ChangeKind::Style => "style",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub file_id: String,
+    pub change: ChangeKind,
+    /// Short human readable description, e.g. `remove node A`.
+    pub summary: String,
+    pub source: String,
+}
+
+/// Posts [`WebhookPayload`]s to every configured hook after the diagram changes.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookDispatcher {
+    hooks: Vec<Webhook>,
+    client: reqwest::Client,
+}
+
+impl WebhookDispatcher {
+    pub fn new(hooks: Vec<Webhook>) -> Self {
+        Self {
+            hooks,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Delivers in the background so slow receivers never hold up the editor.
+    pub fn notify(&self, payload: WebhookPayload) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = dispatcher.deliver(&payload).await {
+                status(UiMessage::WebhookFailed(&format!("{err:#}")));
+            }
+        });
+    }
+
+    pub async fn deliver(&self, payload: &WebhookPayload) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        let mut failures = Vec::new();
+        for hook in &self.hooks {
+            let mut request = self
+                .client
+                .post(&hook.url)
+                .header("content-type", "application/json")
+                .body(body.clone());
+            if let Some(secret) = &hook.secret {
+                request = request.header(WEBHOOK_SIGNATURE_HEADER, sign_payload(secret, &body));
+            }
+            let result = request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .with_context(|| format!("failed to notify '{}'", hook.url));
+            if let Err(err) = result {
+                failures.push(format!("{err:#}"));
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!(failures.join("; "))
+        }
+    }
+}
+
+/// Signature sent in [`WEBHOOK_SIGNATURE_HEADER`], in the same `sha256=<hex>` form GitHub uses.
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("sha256={hex}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn delivers_signed_payloads() {
+        type Received = Arc<Mutex<Vec<(Option<String>, Bytes)>>>;
+        let received: Received = Arc::default();
+        let app = axum::Router::new()
+            .route(
+                "/hook",
+                axum::routing::post(
+                    |axum::extract::State(received): axum::extract::State<Received>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        let signature = headers
+                            .get(WEBHOOK_SIGNATURE_HEADER)
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string);
+                        received.lock().unwrap().push((signature, body));
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let dispatcher = WebhookDispatcher::new(vec![Webhook {
+            url: format!("http://{addr}/hook"),
+            secret: Some("s3cret".to_string()),
+        }]);
+        dispatcher
+            .deliver(&WebhookPayload {
+                file_id: "flow.mmd".to_string(),
+                change: ChangeKind::Layout,
+                summary: "update layout of A".to_string(),
+                source: "graph TD\n  A --> B\n".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        let (signature, body) = &received[0];
+        let json: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(json["fileId"], "flow.mmd");
+        assert_eq!(json["change"], "layout");
+        assert_eq!(json["summary"], "update layout of A");
+        assert_eq!(json["source"], "graph TD\n  A --> B\n");
+        assert_eq!(
+            signature.as_deref(),
+            Some(sign_payload("s3cret", body).as_str())
+        );
+    }
+}
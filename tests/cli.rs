@@ -38,7 +38,7 @@ fn main() {
         "expected files in tests/input/ to have the .mmd extension"
     );
 
-    let tests: Vec<_> = in_paths
+    let mut tests: Vec<_> = in_paths
         .into_iter()
         .flat_map(|in_path| {
             let stem = in_path.file_stem().unwrap().to_str().unwrap();
@@ -57,6 +57,18 @@ fn main() {
             ]
         })
         .collect();
+    tests.push(libtest_mimic::Trial::test(
+        "batch_render",
+        test_batch_render,
+    ));
+    tests.push(libtest_mimic::Trial::test(
+        "json_parse_error",
+        test_json_parse_error,
+    ));
+    tests.push(libtest_mimic::Trial::test(
+        "new_rejects_render_flags",
+        test_new_rejects_render_flags,
+    ));
 
     let args = libtest_mimic::Arguments::from_args();
     libtest_mimic::run(&args, tests).exit();
@@ -71,7 +83,7 @@ fn test_svg(in_path: PathBuf, expected_path: PathBuf) -> Result<(), Failed> {
 
     let mut cmd = cargo_bin_cmd!("oxdraw");
     cmd.arg("--input")
-        .arg(in_path)
+        .arg(&in_path)
         .arg("--output")
         .arg(&out_path)
         .arg("--output-format")
@@ -96,6 +108,19 @@ fn test_svg(in_path: PathBuf, expected_path: PathBuf) -> Result<(), Failed> {
         return Err(diff.into());
     }
 
+    // The exported SVG carries its source, so rendering it again reproduces it.
+    let rerendered_path = temp_dir.path().join(format!("{stem}.rerendered.svg"));
+    cargo_bin_cmd!("oxdraw")
+        .arg("--input")
+        .arg(&out_path)
+        .arg("--output")
+        .arg(&rerendered_path)
+        .assert()
+        .success();
+    if fs::read_to_string(&rerendered_path)? != actual {
+        return Err(format!("re-rendering {} changed the output", out_path.display()).into());
+    }
+
     Ok(())
 }
 
@@ -128,3 +153,102 @@ fn smoke_test_png(in_path: PathBuf) -> Result<(), Failed> {
     }
     Ok(())
 }
+
+/// Directory and `**` glob inputs render every diagram below them into `-o`, keeping relative
+/// paths, a file whose name looks like a glob still renders alone, and one broken diagram
+/// fails the command without stopping the others.
+#[cfg(not(target_arch = "wasm32"))]
+fn test_batch_render() -> Result<(), Failed> {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("src/nested"))?;
+    fs::write(root.join("src/a.mmd"), "graph TD\n  A --> B\n")?;
+    fs::write(root.join("src/nested/b.mmd"), "graph LR\n  C --> D\n")?;
+    fs::write(root.join("src/nested/notes.txt"), "not a diagram\n")?;
+
+    let render = |input: &str, output: &str| {
+        let mut cmd = cargo_bin_cmd!("oxdraw");
+        cmd.current_dir(root)
+            .arg("--input")
+            .arg(input)
+            .arg("--output")
+            .arg(output);
+        cmd
+    };
+    for (input, output) in [("src", "out-dir"), ("src/**/*.mmd", "out-glob")] {
+        render(input, output).assert().success();
+        for rendered in ["a.svg", "nested/b.svg"] {
+            if !root.join(output).join(rendered).is_file() {
+                return Err(format!("'{input}' didn't render {output}/{rendered}").into());
+            }
+        }
+        if root.join(output).join("nested/notes.svg").exists() {
+            return Err(format!("'{input}' rendered a file that isn't a diagram").into());
+        }
+    }
+
+    fs::write(root.join("diagram[1].mmd"), "graph TD\n  A --> B\n")?;
+    render("diagram[1].mmd", "single.svg").assert().success();
+    if !root.join("single.svg").is_file() {
+        return Err("an existing file named like a glob didn't render on its own".into());
+    }
+
+    fs::create_dir_all(root.join("mixed"))?;
+    fs::write(root.join("mixed/good.mmd"), "graph TD\n  A --> B\n")?;
+    fs::write(root.join("mixed/broken.mmd"), "this is not a diagram\n")?;
+    render("mixed", "out-mixed").assert().failure();
+    if !root.join("out-mixed/good.svg").is_file() {
+        return Err("a failing diagram stopped the others from rendering".into());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn test_json_parse_error() -> Result<(), Failed> {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let input = temp_dir.path().join("broken.mmd");
+    fs::write(&input, "graph TD\n  A -->|x\n")?;
+
+    let output = cargo_bin_cmd!("oxdraw")
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(temp_dir.path().join("broken.svg"))
+        .arg("--error-format")
+        .arg("json")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr)?;
+    if stderr.lines().count() != 1 {
+        return Err(format!("expected only the JSON error on stderr, got:\n{stderr}").into());
+    }
+    serde_json::from_str::<serde_json::Value>(&stderr)?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn test_new_rejects_render_flags() -> Result<(), Failed> {
+    let temp_dir = TempDir::new().expect("create temp dir");
+    let target = temp_dir.path().join("fresh.mmd");
+
+    let output = cargo_bin_cmd!("oxdraw")
+        .arg("--new")
+        .arg("--input")
+        .arg(&target)
+        .arg("--palette")
+        .arg("tol")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr)?;
+    if !stderr.contains("--palette") {
+        return Err(format!("expected the error to name --palette, got:\n{stderr}").into());
+    }
+    if target.exists() {
+        return Err("a rejected --new still created the diagram".into());
+    }
+    Ok(())
+}
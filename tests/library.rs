@@ -1,5 +1,10 @@
 use anyhow::Result;
-use oxdraw::Diagram;
+use oxdraw::{
+    Diagram, EditorCore, IncrementalLayout, LayoutOptions, LayoutOverrides, LayoutPass, LintRule,
+    Point, RasterFormat, RasterSize, Severity, dot_to_mermaid, is_dot_source, lint, parse_dot,
+    to_d2, to_plantuml,
+};
+use std::collections::HashMap;
 
 #[test]
 fn diagram_parse_and_render_svg() -> Result<()> {
@@ -39,6 +44,103 @@ fn diagram_render_png_has_png_header() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn diagram_render_raster_encodes_jpeg_and_webp() -> Result<()> {
+    let diagram = Diagram::parse("graph LR\n    Start --> Finish\n")?;
+
+    let jpeg =
+        diagram.render_raster("transparent", None, 1.0, RasterFormat::Jpeg { quality: 80 })?;
+    assert!(
+        jpeg.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "jpeg should start with SOI marker"
+    );
+    let low = diagram.render_raster("white", None, 2.0, RasterFormat::Jpeg { quality: 10 })?;
+    let high = diagram.render_raster("white", None, 2.0, RasterFormat::Jpeg { quality: 95 })?;
+    assert!(
+        low.len() < high.len(),
+        "lower quality should give a smaller file"
+    );
+    assert!(
+        diagram
+            .render_raster("white", None, 1.0, RasterFormat::Jpeg { quality: 0 })
+            .is_err()
+    );
+
+    let webp = diagram.render_raster("white", None, 1.0, RasterFormat::Webp)?;
+    assert!(webp.starts_with(b"RIFF") && &webp[8..12] == b"WEBP");
+
+    Ok(())
+}
+
+#[test]
+fn diagram_render_raster_sized_hits_exact_dimensions() -> Result<()> {
+    let diagram = Diagram::parse("graph LR\n    Start --> Finish\n")?;
+    let png_size = |png: &[u8]| {
+        let pixmap = tiny_skia::Pixmap::decode_png(png).unwrap();
+        (pixmap.width(), pixmap.height())
+    };
+
+    let wide = RasterSize {
+        width: Some(1920),
+        ..RasterSize::default()
+    };
+    let png = diagram.render_raster_sized("white", None, &wide, RasterFormat::Png)?;
+    let (width, height) = png_size(&png);
+    assert_eq!(width, 1920);
+    let (one_x_width, one_x_height) = png_size(&diagram.render_png("white", None, 1.0)?);
+    let expected = one_x_height as f32 * 1920.0 / one_x_width as f32;
+    assert!((height as f32 - expected).abs() <= 1.0);
+
+    let tall = RasterSize {
+        height: Some(300),
+        ..RasterSize::default()
+    };
+    let png = diagram.render_raster_sized("white", None, &tall, RasterFormat::Png)?;
+    assert_eq!(png_size(&png).1, 300);
+
+    let print = RasterSize {
+        dpi: Some(192.0),
+        ..RasterSize::default()
+    };
+    let png = diagram.render_raster_sized("white", None, &print, RasterFormat::Png)?;
+    assert_eq!(png_size(&png), (one_x_width * 2, one_x_height * 2));
+    assert_eq!(&png[37..41], b"pHYs");
+    assert_eq!(u32::from_be_bytes(png[41..45].try_into()?), 7559);
+
+    let empty = RasterSize {
+        width: Some(0),
+        ..RasterSize::default()
+    };
+    assert!(
+        diagram
+            .render_raster_sized("white", None, &empty, RasterFormat::Png)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn svg_metadata_round_trips_source_and_overrides() -> Result<()> {
+    let definition = "graph LR\n    A[\"Fish & <Chips>\"] --> B\n";
+    let mut overrides = LayoutOverrides::default();
+    overrides
+        .nodes
+        .insert("A".into(), Point { x: 12.0, y: 34.0 });
+
+    let diagram = Diagram::parse(definition)?;
+    let svg = diagram.render_svg("white", Some(&overrides))?;
+    let embedded = oxdraw::embed_source_metadata(&svg, definition, &overrides)?;
+    assert!(embedded.ends_with("  </metadata>\n</svg>\n"));
+
+    let (source, layout) = oxdraw::extract_source_metadata(&embedded)?.unwrap();
+    assert_eq!(source, definition);
+    assert_eq!(layout.nodes["A"].x, 12.0);
+    assert!(oxdraw::extract_source_metadata(&svg)?.is_none());
+
+    Ok(())
+}
+
 #[test]
 fn diagram_parses_image_comments() -> Result<()> {
     let definition = include_str!("input/image_node.mmd");
@@ -69,3 +171,354 @@ fn diagram_parses_image_comments() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn view_model_exposes_coordinate_shift() -> Result<()> {
+    let core = EditorCore::from_source(
+        "graph TD\n    A --> B\n    subgraph G\n    B\n    end",
+        "white",
+    )?;
+    let view = core.view_model()?;
+
+    let node = view.nodes.iter().find(|node| node.id == "B").unwrap();
+    let rendered_x = node.rendered_position.x + view.coordinate_shift.x;
+    let rendered_y = node.rendered_position.y + view.coordinate_shift.y;
+
+    let svg = core.render_svg()?;
+    let frame = &view.subgraphs[0];
+    assert!(rendered_x > frame.x && rendered_x < frame.x + frame.width);
+    assert!(rendered_y > frame.y && rendered_y < frame.y + frame.height);
+    assert!(svg.contains(&format!("x=\"{:.1}\"", rendered_x - node.width / 2.0)));
+
+    Ok(())
+}
+
+#[test]
+fn layout_passes_adjust_positions_before_routing() -> Result<()> {
+    let diagram = Diagram::parse("graph LR\n    A --> B\n    A --> C")?;
+    // Line every node up on the first node's row, like a timeline.
+    let align_rows = |_: &Diagram, positions: &mut HashMap<String, Point>| -> Result<()> {
+        let y = positions["A"].y;
+        for point in positions.values_mut() {
+            point.y = y;
+        }
+        Ok(())
+    };
+    let passes: [&dyn LayoutPass; 1] = [&align_rows];
+
+    let layout = diagram.layout_with(None, &passes)?;
+    let y = layout.final_positions["A"].y;
+    assert!(layout.final_positions.values().all(|point| point.y == y));
+    let route = &layout.final_routes["A --> B"];
+    assert!(route.iter().all(|point| (point.y - y).abs() < 1.0));
+
+    let svg = diagram.render_svg_with("white", None, &passes)?;
+    assert_ne!(svg, diagram.render_svg("white", None)?);
+
+    Ok(())
+}
+
+#[test]
+fn incremental_layout_keeps_existing_nodes_in_place() -> Result<()> {
+    let before = Diagram::parse("graph TD\n    A --> B\n    A --> C\n    B --> D")?;
+    let previous = before.layout(None)?.auto_positions;
+
+    // A new root above A would push every rank down in a fresh layout.
+    let after = Diagram::parse(
+        "graph TD\n    R --> A\n    A --> B\n    A --> C\n    B --> D\n    C --> E",
+    )?;
+    let fresh = after.layout(None)?.auto_positions;
+    assert_ne!(fresh["A"].y, previous["A"].y);
+
+    let pass = IncrementalLayout {
+        previous: previous.clone(),
+    };
+    let layout = after.layout_with(None, &[&pass])?;
+    let positions = &layout.final_positions;
+    for id in ["A", "B", "C", "D"] {
+        assert_eq!(positions[id].x, previous[id].x);
+        assert_eq!(positions[id].y, previous[id].y);
+    }
+    // New nodes keep their place relative to the nodes they hang off.
+    assert!(positions["R"].y < positions["A"].y);
+    assert!(positions["E"].y > positions["C"].y);
+    let overlaps = |a: &str, b: &str| {
+        let (a_node, b_node) = (&after.nodes[a], &after.nodes[b]);
+        (positions[a].x - positions[b].x).abs() < (a_node.width + b_node.width) / 2.0
+            && (positions[a].y - positions[b].y).abs() < (a_node.height + b_node.height) / 2.0
+    };
+    assert!(!overlaps("E", "D"));
+
+    Ok(())
+}
+
+#[test]
+fn incremental_layout_handles_empty_renamed_and_removed_nodes() -> Result<()> {
+    let diagram = Diagram::parse("graph LR\n    A --> B\n    B --> C\n    C --> A")?;
+    let fresh = diagram.layout(None)?.final_positions;
+
+    // Nothing to keep: an empty or unrelated previous layout changes nothing.
+    for previous in [
+        HashMap::new(),
+        HashMap::from([("Gone".to_string(), Point { x: 9.0, y: 9.0 })]),
+    ] {
+        let pass = IncrementalLayout { previous };
+        let positions = diagram.layout_with(None, &[&pass])?.final_positions;
+        assert_eq!(positions.len(), fresh.len());
+        for (id, point) in &fresh {
+            assert_eq!(positions[id].x, point.x);
+            assert_eq!(positions[id].y, point.y);
+        }
+    }
+
+    // Removed nodes stay out of the layout, and a new node joining a cycle lands clear of
+    // the nodes that kept their place.
+    let before = Diagram::parse("graph LR\n    A --> B\n    B --> C\n    C --> A\n    C --> X")?;
+    let pass = IncrementalLayout {
+        previous: before.layout(None)?.auto_positions,
+    };
+    let after = Diagram::parse(
+        "graph LR\n    A --> B\n    B --> C\n    C --> A\n    B --> N\n    N --> A",
+    )?;
+    let positions = after.layout_with(None, &[&pass])?.final_positions;
+    assert!(!positions.contains_key("X"));
+    for id in ["A", "B", "C"] {
+        assert_eq!(positions[id].x, pass.previous[id].x);
+        assert_eq!(positions[id].y, pass.previous[id].y);
+        let (kept, new) = (&after.nodes[id], &after.nodes["N"]);
+        let apart_x = (positions[id].x - positions["N"].x).abs() >= (kept.width + new.width) / 2.0;
+        let apart_y =
+            (positions[id].y - positions["N"].y).abs() >= (kept.height + new.height) / 2.0;
+        assert!(apart_x || apart_y, "N overlaps {id}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn timed_rendering_reports_each_phase() -> Result<()> {
+    let diagram = Diagram::parse("graph TD\n    A --> B\n    A --> C")?;
+
+    let (svg, timings) = diagram.render_svg_timed("white", None)?;
+    assert_eq!(svg, diagram.render_svg("white", None)?);
+    let phases: Vec<_> = timings.iter().map(|timing| timing.phase).collect();
+    assert_eq!(phases, ["rank", "separate", "route", "render"]);
+    assert!(timings.iter().all(|timing| timing.millis >= 0.0));
+
+    let pie = Diagram::parse("pie\n    \"A\" : 1")?;
+    let (_, timings) = pie.render_svg_timed("white", None)?;
+    assert_eq!(timings.len(), 1);
+    assert_eq!(timings[0].phase, "render");
+
+    Ok(())
+}
+
+#[cfg(feature = "graphviz")]
+#[test]
+fn graphviz_translates_diagrams_and_reads_positions() -> Result<()> {
+    let diagram = Diagram::parse("graph LR\n  A --> B\n  subgraph Group\n    B\n  end")?;
+    let dot = oxdraw::graphviz::to_dot(&diagram);
+    assert!(dot.contains("rankdir=LR"));
+    assert!(dot.contains("subgraph \"cluster_Group\""));
+    assert!(dot.contains("\"A\" -> \"B\";"));
+
+    let plain = "graph 1 3 1\nnode A 0.5 0.5 1 0.5 \"\" solid box black lightgrey\nnode \"B\" 2.5 0.5 1 0.5 \"\" solid box black lightgrey\nstop\n";
+    let positions = oxdraw::graphviz::parse_plain_positions(plain)?;
+    assert_eq!(positions["B"].x - positions["A"].x, 144.0);
+    assert_eq!(positions["A"].y, positions["B"].y);
+
+    for truncated in [
+        "graph 1 3 1\nnode\n",
+        "graph 1 3 1\nnode A 0.5\n",
+        "graph 1\n",
+    ] {
+        let err = oxdraw::graphviz::parse_plain_positions(truncated).unwrap_err();
+        assert!(
+            err.to_string().contains("malformed Graphviz output"),
+            "{err}"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "graphviz"))]
+#[test]
+fn dot_layout_requires_graphviz_feature() -> Result<()> {
+    let diagram = Diagram::parse("---\nconfig:\n  layout: dot\n---\ngraph TD\n  A --> B")?;
+    let err = diagram.layout(None).unwrap_err();
+    assert!(err.to_string().contains("graphviz feature"));
+    Ok(())
+}
+
+#[test]
+fn layout_options_override_spacing_and_margin() -> Result<()> {
+    let definition = "graph TD\n  A --> B\n  A --> C";
+    let svg_width = |svg: &str| -> f32 {
+        let start = svg.find(" width=\"").unwrap() + 8;
+        let end = start + svg[start..].find('"').unwrap();
+        svg[start..end].parse().unwrap()
+    };
+
+    let default = Diagram::parse(definition)?;
+    let mut spaced = Diagram::parse(definition)?;
+    spaced.apply_layout_options(&LayoutOptions {
+        node_spacing: Some(200.0),
+        rank_spacing: Some(150.0),
+        margin: Some(10.0),
+        ..LayoutOptions::default()
+    });
+
+    let before = default.layout(None)?.final_positions;
+    let after = spaced.layout(None)?.final_positions;
+    assert!(after["B"].y - after["A"].y > before["B"].y - before["A"].y);
+    assert!(after["C"].x - after["B"].x > before["C"].x - before["B"].x);
+
+    let narrow = svg_width(&spaced.render_svg("white", None)?);
+    let mut padded = spaced.clone();
+    padded.apply_layout_options(&LayoutOptions {
+        margin: Some(80.0),
+        ..LayoutOptions::default()
+    });
+    assert_eq!(
+        svg_width(&padded.render_svg("white", None)?),
+        narrow + 140.0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layout_options_clamp_negative_and_ignore_non_finite_spacing() -> Result<()> {
+    let definition = "---\nconfig:\n  nodeSpacing: .inf\n  diagramPadding: -40\n---\ngraph TD\n  A --> B\n  A --> C";
+    let mut diagram = Diagram::parse(definition)?;
+    assert_eq!(diagram.config.node_spacing, None);
+    assert_eq!(diagram.config.margin, Some(0.0));
+
+    diagram.apply_layout_options(&LayoutOptions {
+        node_spacing: Some(-10.0),
+        rank_spacing: Some(f32::INFINITY),
+        margin: Some(f32::NAN),
+        ..LayoutOptions::default()
+    });
+    assert_eq!(diagram.config.node_spacing, Some(0.0));
+    assert_eq!(diagram.config.rank_spacing, None);
+    assert_eq!(diagram.config.margin, Some(0.0));
+
+    let svg = diagram.render_svg("white", None)?;
+    assert!(!svg.contains("NaN") && !svg.contains("inf"));
+    Ok(())
+}
+
+#[test]
+fn lint_reports_graph_problems() -> Result<()> {
+    let findings = lint(
+        "graph\n  A --> B\n  A --> B\n  A -->|x| B\n  C --> D\n  D --> C\n  E\n  subgraph S\n  F --> A\n",
+    )?;
+    let found: Vec<_> = findings
+        .iter()
+        .map(|finding| (finding.rule, finding.severity, finding.id.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (LintRule::ImplicitDirection, Severity::Info, None),
+            (LintRule::UnclosedSubgraph, Severity::Error, None),
+            (LintRule::UnreachableNode, Severity::Warning, Some("C")),
+            (LintRule::UnreachableNode, Severity::Warning, Some("D")),
+            (LintRule::IsolatedNode, Severity::Warning, Some("E")),
+            (LintRule::DuplicateEdge, Severity::Warning, Some("A --> B")),
+        ]
+    );
+    assert_eq!(findings[1].line, Some(8));
+
+    assert!(lint("graph LR\n  A --> B\n  B --> C\n")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn dot_graphs_convert_to_flowcharts() -> Result<()> {
+    let source = "digraph G {\n  rankdir=LR;\n  node [shape=box];\n  subgraph cluster_a { label=\"Stage A\"; a1 [label=\"Load data\"]; a2; }\n  a1 -> a2 -> b [label=\"ok\"];\n  b [shape=diamond];\n  c -> b [dir=back];\n}\n";
+    assert!(is_dot_source(source));
+    assert!(!is_dot_source("graph TD\n  A --> B\n"));
+    let converted = dot_to_mermaid(source)?;
+    assert!(converted.starts_with("graph LR\n"));
+    assert!(converted.contains("subgraph a[Stage A]"));
+    assert!(converted.contains("b --> c"));
+
+    let diagram = parse_dot(source)?;
+    assert_eq!(diagram.nodes["a1"].label, "Load data");
+    assert_eq!(diagram.subgraphs.len(), 1);
+    assert_eq!(diagram.subgraphs[0].label, "Stage A");
+    assert_eq!(diagram.edges.len(), 3);
+    assert_eq!(diagram.edges[1].label.as_deref(), Some("ok"));
+
+    let undirected = parse_dot("graph { a -- b; b -- c }")?;
+    assert_eq!(undirected.edges.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn flowcharts_export_to_plantuml_and_d2() -> Result<()> {
+    let diagram = Diagram::parse(
+        "graph LR\n  subgraph stage[Stage A]\n    a1[Load data]\n    a2(Clean)\n  end\n  b{Valid?}\n  a1 -->|ok| a2\n  a2 -.-> b\n  c <--> b\n",
+    )?;
+
+    let uml = to_plantuml(&diagram)?;
+    assert!(uml.starts_with("@startuml\nleft to right direction\npartition \"Stage A\" {\n"));
+    assert!(uml.contains("  \"Load data\" as a1 -->[ok] \"Clean\" as a2\n"));
+    assert!(uml.contains("  a2 -[dashed]-> \"Valid?\" as b\n"));
+    assert!(uml.ends_with("\"c\" as c --> b\n@enduml\n"));
+
+    let d2 = to_d2(&diagram)?;
+    assert!(d2.starts_with("direction: right\nstage: \"Stage A\" {\n  a1: \"Load data\"\n"));
+    assert!(d2.contains("b: \"Valid?\" {\n  shape: diamond\n}\n"));
+    assert!(d2.contains("stage.a1 -> stage.a2: \"ok\"\n"));
+    assert!(d2.contains("stage.a2 -> b { style.stroke-dash: 5 }\n"));
+    assert!(d2.ends_with("c <-> b\n"));
+
+    let sequence = Diagram::parse("sequenceDiagram\n  A->>B: hi\n")?;
+    assert!(to_d2(&sequence).is_err());
+    Ok(())
+}
+
+#[test]
+fn layout_geometry_matches_the_rendered_svg() -> Result<()> {
+    let diagram =
+        Diagram::parse("graph TD\n  subgraph S[Group]\n    A[Start]\n  end\n  A -->|go| B\n")?;
+    let geometry = diagram.layout_geometry(None)?;
+    let svg = diagram.render_svg("white", None)?;
+    assert!(svg.contains(&format!(
+        "width=\"{:.0}\" height=\"{:.0}\"",
+        geometry.canvas.width, geometry.canvas.height
+    )));
+
+    let ids: Vec<_> = geometry.nodes.iter().map(|node| node.id.as_str()).collect();
+    assert_eq!(ids, ["A", "B"]);
+    let start = &geometry.nodes[0];
+    assert_eq!((start.label.as_str(), start.shape), ("Start", "rectangle"));
+    assert!(svg.contains(&format!(
+        "<rect x=\"{:.1}\" y=\"{:.1}\"",
+        start.x - start.width / 2.0,
+        start.y - start.height / 2.0
+    )));
+
+    let edge = &geometry.edges[0];
+    assert_eq!((edge.from.as_str(), edge.to.as_str()), ("A", "B"));
+    assert!(edge.points.len() >= 2);
+    assert!(edge.label_position.is_some());
+
+    let group = &geometry.subgraphs[0];
+    assert_eq!(group.id, "S");
+    assert!(
+        group.x <= start.x - start.width / 2.0
+            && start.x + start.width / 2.0 <= group.x + group.width
+    );
+
+    assert!(
+        Diagram::parse("pie\n  \"a\": 1\n")?
+            .layout_geometry(None)
+            .is_err()
+    );
+    Ok(())
+}
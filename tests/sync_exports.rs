@@ -0,0 +1,42 @@
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::fs;
+
+use assert_cmd::cargo::cargo_bin_cmd;
+use tempfile::TempDir;
+
+#[test]
+fn sync_exports_regenerates_only_stale_outputs() {
+    let dir = TempDir::new().expect("create temp dir");
+    fs::write(dir.path().join("flow.mmd"), "graph TD\n  A --> B\n").unwrap();
+    fs::write(
+        dir.path().join("oxdraw-exports.yaml"),
+        "exports:\n  - source: flow.mmd\n    outputs: [out/flow.svg]\n",
+    )
+    .unwrap();
+    let config = dir.path().join("oxdraw-exports.yaml");
+
+    let run = || {
+        let mut cmd = cargo_bin_cmd!("oxdraw");
+        cmd.arg("sync-exports").arg("--config").arg(&config);
+        cmd
+    };
+
+    // First run produces the export and fails so a pre-commit hook stops the commit.
+    run().assert().failure();
+    let svg = fs::read_to_string(dir.path().join("out/flow.svg")).unwrap();
+    assert!(svg.contains("<svg"));
+    assert!(dir.path().join("oxdraw-exports.lock.json").is_file());
+
+    run().assert().success();
+
+    fs::write(dir.path().join("flow.mmd"), "graph TD\n  A --> C\n").unwrap();
+    run().arg("--check").assert().failure();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out/flow.svg")).unwrap(),
+        svg,
+        "--check must not rewrite exports"
+    );
+    run().assert().failure();
+    run().assert().success();
+}